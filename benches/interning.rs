@@ -0,0 +1,90 @@
+use std::collections::hash_map::RandomState as SipHash;
+use std::hash::BuildHasher;
+use std::time::Duration;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use ff::Field;
+use pasta_curves::pallas::Scalar as Fr;
+use rand::{rngs::StdRng, SeedableRng};
+
+use lurk::{field::FWrap, store::Store};
+
+const SIZES: [usize; 2] = [1_000, 100_000];
+
+fn field_keys(n: usize) -> Vec<FWrap<Fr>> {
+    let mut rng = StdRng::seed_from_u64(0);
+    (0..n).map(|_| FWrap(Fr::random(&mut rng))).collect()
+}
+
+/// Inserts every key and looks each one up again, as interning does
+fn intern_all<K: Clone + Eq + std::hash::Hash, S: BuildHasher + Default>(keys: &[K]) -> usize {
+    let mut set = indexmap::IndexSet::<K, S>::default();
+    for key in keys {
+        set.insert_full(key.clone());
+    }
+    keys.iter().filter_map(|key| set.get_index_of(key)).sum()
+}
+
+fn field_element_interning(c: &mut Criterion) {
+    let mut group = c.benchmark_group("field-element-interning");
+    for size in SIZES {
+        let keys = field_keys(size);
+        group.bench_with_input(BenchmarkId::new("siphash", size), &keys, |b, keys| {
+            b.iter(|| intern_all::<_, SipHash>(black_box(keys)))
+        });
+        group.bench_with_input(BenchmarkId::new("ahash", size), &keys, |b, keys| {
+            b.iter(|| intern_all::<_, ahash::RandomState>(black_box(keys)))
+        });
+    }
+    group.finish();
+}
+
+fn string_interning(c: &mut Criterion) {
+    let mut group = c.benchmark_group("string-interning");
+    for size in SIZES {
+        let keys: Vec<String> = (0..size).map(|i| format!("symbol-{i}")).collect();
+        group.bench_with_input(BenchmarkId::new("siphash", size), &keys, |b, keys| {
+            b.iter(|| intern_all::<_, SipHash>(black_box(keys)))
+        });
+        group.bench_with_input(BenchmarkId::new("ahash", size), &keys, |b, keys| {
+            b.iter(|| intern_all::<_, ahash::RandomState>(black_box(keys)))
+        });
+    }
+    group.finish();
+}
+
+/// Interning into a `Store`, with the hasher selected by the `siphash` feature
+fn store_interning(c: &mut Criterion) {
+    let mut group = c.benchmark_group("store-interning");
+    let hasher = if cfg!(feature = "siphash") {
+        "siphash"
+    } else {
+        "ahash"
+    };
+    for size in SIZES {
+        group.bench_with_input(BenchmarkId::new(hasher, size), &size, |b, &size| {
+            b.iter(|| {
+                let store = &mut Store::<Fr>::default();
+                let pairs: Vec<_> = (0..size)
+                    .map(|i| {
+                        let num = store.num(i as u64);
+                        let sym = store.sym(format!("sym-{}", i % 100));
+                        store.cons(num, sym)
+                    })
+                    .collect();
+                let list = store.list(&pairs);
+                store.hydrate_scalar_cache();
+                black_box(list)
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().measurement_time(Duration::from_secs(10));
+    targets = field_element_interning, string_interning, store_interning
+}
+
+criterion_main!(benches);
@@ -24,6 +24,7 @@ use lurk::field::LurkField;
 use lurk::proof::{nova::NovaProver, Prover};
 use lurk::ptr::Ptr;
 use lurk::repl::{ReplState, ReplTrait};
+use lurk::secret::Secret;
 use lurk::store::Store;
 use lurk::symbol::Symbol;
 use lurk::tag::ExprTag;
@@ -304,19 +305,19 @@ impl ClutchState<F, Coproc<F>> {
 
         let (expr, secret) = if rest.is_nil() {
             // TODO: also support Commitment::from_ptr_with_hiding (randomized secret at runtime).
-            (first, <F as ff::Field>::ZERO)
+            (first, Secret::new(<F as ff::Field>::ZERO))
         } else if let Expression::Num(n) = store
             .fetch(&second)
             .ok_or_else(|| anyhow!("second arg to !:COMMIT must be a number."))?
         {
-            (first, n.into_scalar())
+            (first, Secret::new(n.into_scalar()))
         } else {
             bail!("Secret must be a Num")
         };
 
         let (evaled, _, _, _) = self.repl_state.eval_expr(expr, store)?;
 
-        let commitment = Commitment::from_ptr_and_secret(store, &evaled, secret)?;
+        let commitment = Commitment::from_ptr_and_secret(store, &evaled, &secret)?;
 
         let committed_expression = CommittedExpression {
             expr: LurkPtr::from_ptr(store, &evaled),
@@ -366,17 +367,7 @@ impl ClutchState<F, Coproc<F>> {
                 )
             })?;
 
-        let new_secret = store
-            .secret(new_comm)
-            .ok_or_else(|| anyhow!("secret missing"))
-            .and_then(|hash| {
-                store
-                    .hash_expr(&hash)
-                    .ok_or_else(|| anyhow!("hash missing"))
-            })
-            .map(|hash| *hash.value())?;
-
-        let (_, new_fun) = store
+        let (new_secret, new_fun) = store
             .open(new_comm)
             .ok_or_else(|| anyhow!("opening missing"))?;
 
@@ -470,8 +461,8 @@ impl ClutchState<F, Coproc<F>> {
         let commitment_ptr = commitment_expr.map(|c| {
             let ptr = c.expr.ptr(store, self.repl_state.limit, &self.lang());
 
-            if let Some(secret) = c.secret {
-                store.intern_comm(secret, ptr);
+            if let Some(secret) = &c.secret {
+                store.hide_secret(secret, ptr);
             };
 
             ptr
@@ -569,7 +560,9 @@ impl ClutchState<F, Coproc<F>> {
                 .map_err(|_| Error::msg("Failed to generate proof key"))?
                 .to_base32();
             match proof.claim {
-                Claim::Evaluation(_) | Claim::Opening(_) => println!("{0:#?}", proof.claim),
+                Claim::Evaluation(_) | Claim::Opening(_) | Claim::Creation(_) => {
+                    println!("{0:#?}", proof.claim)
+                }
                 Claim::PtrEvaluation(_) => println!("Claim::PtrEvaluation elided."),
             }
 
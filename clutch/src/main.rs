@@ -2,6 +2,7 @@ use anyhow::Result;
 
 use clutch::ClutchState;
 
+use lurk::config::Settings;
 use lurk::eval::lang::{Coproc, Lang};
 use lurk::field::LanguageField;
 use lurk::repl::repl_cli;
@@ -16,7 +17,7 @@ fn main() -> Result<()> {
     tracing::subscriber::set_global_default(subscriber).unwrap();
 
     let default_field = LanguageField::Pallas;
-    let field = if let Ok(lurk_field) = std::env::var("LURK_FIELD") {
+    let field = if let Some(lurk_field) = Settings::load(None)?.field {
         match lurk_field.as_str() {
             "BLS12-381" => LanguageField::BLS12_381,
             "PALLAS" => LanguageField::Pallas,
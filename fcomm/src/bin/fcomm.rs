@@ -12,6 +12,7 @@ use tracing::info;
 use tracing_subscriber::{fmt, prelude::*, EnvFilter, Registry};
 
 use hex::FromHex;
+use rand::rngs::OsRng;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
@@ -28,10 +29,18 @@ use lurk::store::Store;
 use clap::{Args, Parser, Subcommand};
 use clap_verbosity_flag::{Verbosity, WarnLevel};
 
+use fcomm::creation::Creation;
+use fcomm::escrow::{EscrowRecord, Share, TrusteeKey, TrusteePublicKey};
+use fcomm::package::Package;
+use fcomm::pinning::{PinPolicy, Pins};
+use fcomm::seal::Seal;
+use fcomm::vault::{self, KeySource};
+use fcomm::witness::{substitute_witnesses, Witnesses};
 use fcomm::{
-    committed_expression_store, error::Error, evaluate, file_map::FileStore, public_param_dir,
-    Claim, Commitment, CommittedExpression, Evaluation, Expression, LurkPtr, Opening,
-    OpeningRequest, Proof, ReductionCount, S1,
+    committed_expression_store, compare::compare_proofs, error::Error, evaluate,
+    file_map::FileStore, load_committed_expression, public_param_dir, server, Claim, Commitment,
+    CommittedExpression, Evaluation, Expression, LurkPtr, Opening, OpeningRequest, Proof,
+    ReductionCount, VerificationResult, S1,
 };
 
 use lurk::public_parameters::public_params;
@@ -53,6 +62,10 @@ struct Cli {
     #[clap(short, long, value_parser)]
     error: bool,
 
+    /// What to do when a proof needs another verifier key than the pinned one: off, warn or fail
+    #[clap(long, default_value = "warn", value_parser)]
+    pin_policy: PinPolicy,
+
     /// Be verbose
     #[clap(flatten)]
     verbose: Verbosity<WarnLevel>,
@@ -77,6 +90,33 @@ enum Command {
 
     /// Verifies a proof
     Verify(Verify),
+
+    /// Reports how two proofs of the same claim differ
+    CompareProofs(CompareProofs),
+
+    /// Bundles a committed function into a package
+    Package(MakePackage),
+
+    /// Checks a package and stores its function, so it can be opened
+    Unpack(Unpack),
+
+    /// Checks a package and verifies its creation proof
+    VerifyPackage(VerifyPackage),
+
+    /// Generates a trustee key for escrowed secrets and prints its public key
+    EscrowKeygen(EscrowKeygen),
+
+    /// Decrypts a trustee's share of an escrowed secret
+    EscrowShare(EscrowShare),
+
+    /// Recovers an escrowed secret from enough trustees' shares
+    EscrowRecover(EscrowRecover),
+
+    /// Encrypts the committed expressions of the data directory, which hold their secrets
+    Lock(VaultKey),
+
+    /// Decrypts the committed expressions `lock` encrypted
+    Unlock(VaultKey),
 }
 
 #[derive(Args, Debug)]
@@ -92,6 +132,35 @@ struct Commit {
     // Function is lurk source.
     #[clap(long, value_parser)]
     lurk: bool,
+
+    /// Symbols or packages the function must not reference, separated by commas
+    #[clap(long, value_parser, value_delimiter = ',')]
+    seal: Vec<String>,
+
+    /// Path to proof output: also prove the commitment was computed from the function's source
+    #[clap(long, value_parser, conflicts_with = "seal")]
+    prove_creation: Option<PathBuf>,
+
+    /// Number of circuit reductions per step when proving creation
+    #[clap(short = 'r', long, default_value = "10", value_parser)]
+    reduction_count: usize,
+
+    /// Public keys of the trustees to escrow the secret with, separated by commas
+    #[clap(
+        long,
+        value_parser,
+        value_delimiter = ',',
+        requires_all = ["escrow", "escrow_threshold"]
+    )]
+    escrow_trustees: Vec<String>,
+
+    /// Number of trustees needed to recover the escrowed secret
+    #[clap(long, value_parser)]
+    escrow_threshold: Option<usize>,
+
+    /// Path to escrow record output
+    #[clap(long, value_parser, requires = "escrow_trustees")]
+    escrow: Option<PathBuf>,
 }
 
 #[derive(Args, Debug)]
@@ -143,6 +212,10 @@ struct Eval {
     #[clap(long, value_parser)]
     claim: Option<PathBuf>,
 
+    /// Path to the values of the expression's `(witness "name")` inputs
+    #[clap(long, value_parser)]
+    witness: Option<PathBuf>,
+
     // Expression is lurk source.
     #[clap(long, value_parser)]
     lurk: bool,
@@ -166,16 +239,120 @@ struct Prove {
     #[clap(long, value_parser)]
     claim: Option<PathBuf>,
 
+    /// Path to the values of the expression's `(witness "name")` inputs
+    #[clap(long, value_parser, conflicts_with = "claim")]
+    witness: Option<PathBuf>,
+
     // Expression is lurk source.
     #[clap(long, value_parser)]
     lurk: bool,
+
+    /// Print the witness size, constraints and non-zero entries of each folding step
+    #[clap(long, value_parser)]
+    report: bool,
 }
 
 #[derive(Args, Debug)]
 struct Verify {
     /// Path to proof input
+    #[clap(short, long, value_parser, required_unless_present = "server")]
+    proof: Option<PathBuf>,
+
+    /// Verify length-prefixed proofs from stdin, writing a framed verdict to stdout for each
+    #[clap(long, value_parser, conflicts_with = "proof")]
+    server: bool,
+}
+
+#[derive(Args, Debug)]
+struct CompareProofs {
+    /// Path to the first proof
+    #[clap(value_parser)]
+    left: PathBuf,
+
+    /// Path to the second proof
+    #[clap(value_parser)]
+    right: PathBuf,
+
+    /// Write the differences as JSON
+    #[clap(long, value_parser)]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct MakePackage {
+    /// Path to the function, as written by `commit --lurk`
     #[clap(short, long, value_parser)]
-    proof: PathBuf,
+    function: PathBuf,
+
+    /// Path to the proof of the function's creation, as written by `commit --prove-creation`
+    #[clap(long, value_parser)]
+    creation_proof: Option<PathBuf>,
+
+    /// Include the secret, so whoever has the package can open the commitment
+    #[clap(long, value_parser)]
+    include_secret: bool,
+
+    /// Path to package output
+    #[clap(short, long, value_parser)]
+    package: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct Unpack {
+    /// Path to package
+    #[clap(short, long, value_parser)]
+    package: PathBuf,
+
+    /// Path to the function's source output
+    #[clap(short, long, value_parser)]
+    source: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct VerifyPackage {
+    /// Path to package
+    #[clap(short, long, value_parser)]
+    package: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct EscrowKeygen {
+    /// Path to trustee key output
+    #[clap(short, long, value_parser)]
+    key: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct EscrowShare {
+    /// Path to escrow record
+    #[clap(short, long, value_parser)]
+    escrow: PathBuf,
+
+    /// Path to trustee key
+    #[clap(short, long, value_parser)]
+    key: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct VaultKey {
+    /// Path to a keyfile, used instead of `FCOMM_KEYFILE` or `FCOMM_PASSPHRASE`
+    #[clap(long, value_parser)]
+    keyfile: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct EscrowRecover {
+    /// Path to escrow record
+    #[clap(short, long, value_parser)]
+    escrow: PathBuf,
+
+    /// Paths to trustees' shares
+    #[clap(short, long, value_parser)]
+    share: Vec<PathBuf>,
+
+    /// Path to function: check the secret opens its commitment and write it to the function's file
+    #[clap(short, long, value_parser)]
+    function: Option<PathBuf>,
 }
 
 impl Commit {
@@ -192,23 +369,44 @@ impl Commit {
                 expr: LurkPtr::Source(src),
                 secret: None,
                 commitment: None,
+                seal: None,
             }
         } else {
             CommittedExpression::read_from_json_path(&self.function)
                 .expect("committed expression read_from_path")
         };
-        let fun_ptr = function.expr_ptr(s, limit, lang).expect("fun_ptr");
+        if !self.seal.is_empty() {
+            function.seal = Some(Seal::new(&self.seal));
+        }
+        let fun_ptr = function
+            .committed_ptr(s, limit, lang)
+            .unwrap_or_else(|e| panic!("can't commit to the function: {e}"));
         let function_map = committed_expression_store();
 
-        let commitment = if let Some(secret) = function.secret {
+        let commitment = if let Some(secret) = &function.secret {
             Commitment::from_ptr_and_secret(s, &fun_ptr, secret).unwrap()
         } else {
             let (commitment, secret) = Commitment::from_ptr_with_hiding(s, &fun_ptr).unwrap();
+            // the secret is written to the function's file, to open it later
             function.secret = Some(secret);
             commitment
         };
         function.commitment = Some(commitment);
 
+        if let Some(escrow_path) = &self.escrow {
+            let trustees = self
+                .escrow_trustees
+                .iter()
+                .map(|key| key.parse::<TrusteePublicKey>())
+                .collect::<Result<Vec<_>, _>>()
+                .expect("trustee public keys");
+            let threshold = self.escrow_threshold.expect("escrow threshold");
+            let secret = function.secret.as_ref().expect("secret");
+            EscrowRecord::new(commitment, secret, &trustees, threshold, OsRng)
+                .expect("escrow record")
+                .write_to_json_path(escrow_path);
+        }
+
         function_map
             .set(&commitment, &function)
             .expect("function_map set");
@@ -219,6 +417,24 @@ impl Commit {
         } else {
             serde_json::to_writer(io::stdout(), &commitment).expect("serde_json to_writer");
         }
+
+        if let Some(proof_path) = &self.prove_creation {
+            let rc = ReductionCount::try_from(self.reduction_count).expect("reduction count");
+            let prover = NovaProver::<S1, Coproc<S1>>::new(rc.count(), lang.clone());
+            let lang_rc = Arc::new(lang.clone());
+            let pp = public_params(rc.count(), true, lang_rc.clone(), &public_param_dir())
+                .expect("public params");
+
+            let proof =
+                Creation::create_and_prove(s, &function, limit, false, &prover, &pp, lang_rc)
+                    .expect("proving creation");
+
+            // Write first, so prover can debug if proof doesn't verify (it should).
+            proof.write_to_json_path(proof_path);
+            proof
+                .verify(&pp, lang)
+                .expect("created proof doesn't verify");
+        }
     }
 }
 
@@ -235,7 +451,6 @@ impl Open {
         let lang_rc = Arc::new(lang.clone());
         let pp =
             public_params(rc.count(), true, lang_rc, &public_param_dir()).expect("public params");
-        let function_map = committed_expression_store();
 
         let handle_proof = |out_path, proof: Proof<'_, S1>| {
             proof.write_to_json_path(out_path);
@@ -258,9 +473,8 @@ impl Open {
 
                 handle_proof(out_path, proof);
             } else {
-                let function = function_map
-                    .get(&request.commitment)
-                    .expect("committed function not found");
+                let function = load_committed_expression(&request.commitment, s, limit, lang)
+                    .expect("committed function");
                 let input = request.input.eval(s, limit, lang).unwrap();
 
                 let claim = Opening::apply(s, input, function, limit, self.chain, lang)
@@ -273,9 +487,7 @@ impl Open {
                     .map_err(Error::CommitmentParseError)
                     .unwrap();
 
-                function_map
-                    .get(&commitment)
-                    .expect("committed function not found")
+                load_committed_expression(&commitment, s, limit, lang).expect("committed function")
             } else {
                 let function_path = self.function.as_ref().expect("function missing");
                 if self.lurk {
@@ -285,6 +497,7 @@ impl Open {
                         expr: LurkPtr::Source(src),
                         secret: None,
                         commitment: None,
+                        seal: None,
                     }
                 } else {
                     CommittedExpression::read_from_json_path(function_path).unwrap()
@@ -317,6 +530,7 @@ impl Eval {
         let s = &mut Store::<S1>::default();
 
         let expr = expression(s, &self.expression, self.lurk, limit, lang).unwrap();
+        let expr = with_witnesses(s, expr, self.witness.as_ref(), limit, lang).unwrap();
 
         let evaluation = Evaluation::eval(s, expr, limit).unwrap();
 
@@ -367,6 +581,7 @@ impl Prove {
                     lang,
                 )
                 .unwrap();
+                let expr = with_witnesses(s, expr, self.witness.as_ref(), limit, lang).unwrap();
 
                 Proof::eval_and_prove(s, expr, None, limit, false, &prover, &pp, lang_rc).unwrap()
             }
@@ -377,12 +592,36 @@ impl Prove {
         proof
             .verify(&pp, lang)
             .expect("created proof doesn't verify");
+
+        if self.report {
+            let step = prover.step_report(&pp).expect("step report");
+            println!("{step}");
+            println!(
+                "{} steps, {} folded constraints in all",
+                proof.num_steps,
+                proof.num_steps * step.folded_constraints
+            );
+        }
     }
 }
 
 impl Verify {
-    fn verify(&self, cli_error: bool, lang: &Lang<S1, Coproc<S1>>) {
-        let proof = proof(Some(&self.proof)).unwrap();
+    fn verify(&self, cli_error: bool, pin_policy: PinPolicy, lang: &Lang<S1, Coproc<S1>>) {
+        if self.server {
+            let mut pins = Pins::load_default().expect("can't load the pinned keys");
+            let served = server::serve(
+                &mut io::stdin().lock(),
+                &mut io::stdout().lock(),
+                &mut pins,
+                &public_param_dir(),
+                pin_policy,
+                lang,
+            )
+            .expect("verification server failed");
+            info!("Served {served} verification request(s).");
+            return;
+        }
+        let proof = proof(self.proof.as_ref()).unwrap();
         let lang_rc = Arc::new(lang.clone());
         let pp = public_params(
             proof.reduction_count.count(),
@@ -391,7 +630,129 @@ impl Verify {
             &public_param_dir(),
         )
         .unwrap();
-        let result = proof.verify(&pp, lang).unwrap();
+        let verified = Pins::load_default()
+            .and_then(|mut pins| pins.verify_proof(&proof, &pp, lang, pin_policy));
+        let result = match verified {
+            Ok(verified) => VerificationResult { verified },
+            Err(e) => {
+                info!("{e}");
+                VerificationResult { verified: false }
+            }
+        };
+
+        serde_json::to_writer(io::stdout(), &result).unwrap();
+
+        if result.verified {
+            info!("Verification succeeded.");
+        } else if cli_error {
+            serde_json::to_writer(io::stderr(), &result).unwrap();
+            std::process::exit(1);
+        };
+    }
+}
+
+impl CompareProofs {
+    fn compare(&self, cli_error: bool, lang: &Lang<S1, Coproc<S1>>) {
+        let left = proof(Some(&self.left)).unwrap();
+        let right = proof(Some(&self.right)).unwrap();
+        let comparison = compare_proofs(&left, &right, lang).unwrap();
+
+        if self.json {
+            serde_json::to_writer(io::stdout(), &comparison).unwrap();
+        } else {
+            print!("{comparison}");
+        }
+
+        if !comparison.is_identical() && cli_error {
+            std::process::exit(1);
+        }
+    }
+}
+
+impl MakePackage {
+    fn package(&self, limit: usize, lang: &Lang<S1, Coproc<S1>>) {
+        let s = &mut Store::<S1>::default();
+        let function = CommittedExpression::read_from_json_path(&self.function)
+            .expect("committed expression read_from_path");
+        let creation_proof = self
+            .creation_proof
+            .as_ref()
+            .map(|path| Proof::read_from_json_path(path).expect("creation proof read_from_path"));
+        let package = Package::new(
+            s,
+            &function,
+            creation_proof,
+            self.include_secret,
+            limit,
+            lang,
+        )
+        .expect("packaging the function");
+        package.write_to_json_path(&self.package);
+    }
+}
+
+impl Unpack {
+    fn unpack(&self, limit: usize, lang: &Lang<S1, Coproc<S1>>) {
+        let s = &mut Store::<S1>::default();
+        let package = Package::read_from_json_path(&self.package).expect("package read_from_path");
+        package.check(s, limit, lang).expect("invalid package");
+
+        if package.secret.is_some() {
+            committed_expression_store()
+                .set(&package.commitment, &package.committed_expression())
+                .expect("function_map set");
+        } else {
+            info!("The package doesn't hold the secret, so its function can't be opened.");
+        }
+        if let Some(source_path) = &self.source {
+            std::fs::write(source_path, &package.source).expect("source write");
+        }
+        serde_json::to_writer(io::stdout(), &package.commitment).expect("serde_json to_writer");
+    }
+}
+
+impl VerifyPackage {
+    fn verify(
+        &self,
+        cli_error: bool,
+        pin_policy: PinPolicy,
+        limit: usize,
+        lang: &Lang<S1, Coproc<S1>>,
+    ) {
+        let s = &mut Store::<S1>::default();
+        let package = Package::read_from_json_path(&self.package).expect("package read_from_path");
+        let verified = match package.check(s, limit, lang) {
+            Ok(()) => match &package.creation_proof {
+                Some(proof) => {
+                    let lang_rc = Arc::new(lang.clone());
+                    let pp = public_params(
+                        proof.reduction_count.count(),
+                        true,
+                        lang_rc,
+                        &public_param_dir(),
+                    )
+                    .unwrap();
+                    let verified = Pins::load_default()
+                        .and_then(|mut pins| pins.verify_proof(proof, &pp, lang, pin_policy));
+                    match verified {
+                        Ok(verified) => verified,
+                        Err(e) => {
+                            info!("{e}");
+                            false
+                        }
+                    }
+                }
+                None => {
+                    info!("The package has no creation proof to verify.");
+                    true
+                }
+            },
+            Err(e) => {
+                info!("{e}");
+                false
+            }
+        };
+        let result = VerificationResult { verified };
 
         serde_json::to_writer(io::stdout(), &result).unwrap();
 
@@ -404,6 +765,84 @@ impl Verify {
     }
 }
 
+impl EscrowKeygen {
+    fn keygen(&self) {
+        let key = TrusteeKey::random(OsRng);
+        key.write_to_json_path(&self.key);
+        println!("{}", key.public_key());
+    }
+}
+
+impl EscrowShare {
+    fn share(&self) {
+        let record =
+            EscrowRecord::read_from_json_path(&self.escrow).expect("escrow record read_from_path");
+        let key = TrusteeKey::read_from_json_path(&self.key).expect("trustee key read_from_path");
+        let share = record.decrypt_share(&key).expect("decrypting share");
+        serde_json::to_writer(io::stdout(), &share).expect("serde_json to_writer");
+    }
+}
+
+impl VaultKey {
+    fn key(&self) -> KeySource {
+        match &self.keyfile {
+            Some(path) => KeySource::Keyfile(path.clone()),
+            None => KeySource::from_env()
+                .expect("set FCOMM_PASSPHRASE or FCOMM_KEYFILE, or pass --keyfile"),
+        }
+    }
+
+    fn lock(&self) {
+        let dir = committed_expression_store().dir().to_path_buf();
+        let locked = vault::lock_dir(&dir, &self.key()).expect("locking the data directory");
+        println!("locked {locked} committed expressions in {}", dir.display());
+    }
+
+    fn unlock(&self) {
+        let dir = committed_expression_store().dir().to_path_buf();
+        let unlocked = vault::unlock_dir(&dir, &self.key()).expect("unlocking the data directory");
+        println!(
+            "unlocked {unlocked} committed expressions in {}",
+            dir.display()
+        );
+    }
+}
+
+impl EscrowRecover {
+    fn recover(&self, limit: usize, lang: &Lang<S1, Coproc<S1>>) {
+        let record =
+            EscrowRecord::read_from_json_path(&self.escrow).expect("escrow record read_from_path");
+        let shares = self
+            .share
+            .iter()
+            .map(|path| Share::read_from_json_path(path).expect("share read_from_path"))
+            .collect::<Vec<_>>();
+        let secret = record.recover(&shares).expect("recovering secret");
+
+        if let Some(function_path) = &self.function {
+            let s = &mut Store::<S1>::default();
+            let mut function = CommittedExpression::read_from_json_path(function_path)
+                .expect("committed expression read_from_path");
+            let fun_ptr = function
+                .committed_ptr(s, limit, lang)
+                .unwrap_or_else(|e| panic!("can't commit to the function: {e}"));
+            let commitment = Commitment::from_ptr_and_secret(s, &fun_ptr, &secret).unwrap();
+            assert_eq!(
+                commitment, record.commitment,
+                "the recovered secret doesn't open the escrowed commitment"
+            );
+            function.secret = Some(secret.clone());
+            function.commitment = Some(commitment);
+            committed_expression_store()
+                .set(&commitment, &function)
+                .expect("function_map set");
+            function.write_to_json_path(function_path);
+        }
+        // Printing the recovered secret is the point of the command
+        serde_json::to_writer(io::stdout(), secret.expose()).expect("serde_json to_writer");
+    }
+}
+
 fn read_from_path<P: AsRef<Path>, F: LurkField + Serialize>(
     store: &mut Store<F>,
     path: P,
@@ -497,6 +936,26 @@ fn expression<P: AsRef<Path>, F: LurkField + Serialize + DeserializeOwned>(
     }
 }
 
+/// Replaces the `(witness "name")` inputs of `expr` with commitments to their values in the
+/// witness file, if there is one
+fn with_witnesses(
+    store: &mut Store<S1>,
+    expr: Ptr<S1>,
+    witness_path: Option<&PathBuf>,
+    limit: usize,
+    lang: &Lang<S1, Coproc<S1>>,
+) -> Result<Ptr<S1>, Error> {
+    let Some(witness_path) = witness_path else {
+        return Ok(expr);
+    };
+    let witnesses = Witnesses::<S1>::read_from_json_path(witness_path)?;
+    let commitments = witnesses.commit(store, limit, lang)?;
+    for (name, commitment) in &commitments {
+        info!("Witness {name:?} committed as {}", commitment.to_string());
+    }
+    substitute_witnesses(store, expr, &commitments)
+}
+
 fn opening_request<P: AsRef<Path>, F: LurkField + Serialize + DeserializeOwned>(
     request_path: P,
 ) -> Result<OpeningRequest<F>, error::Error> {
@@ -535,6 +994,15 @@ fn main() {
         Command::Open(o) => o.open(cli.limit, cli.eval_input, &lang),
         Command::Eval(e) => e.eval(cli.limit, &lang),
         Command::Prove(p) => p.prove(cli.limit, &lang),
-        Command::Verify(v) => v.verify(cli.error, &lang),
+        Command::Verify(v) => v.verify(cli.error, cli.pin_policy, &lang),
+        Command::CompareProofs(c) => c.compare(cli.error, &lang),
+        Command::Package(p) => p.package(cli.limit, &lang),
+        Command::Unpack(u) => u.unpack(cli.limit, &lang),
+        Command::VerifyPackage(v) => v.verify(cli.error, cli.pin_policy, cli.limit, &lang),
+        Command::EscrowKeygen(k) => k.keygen(),
+        Command::EscrowShare(s) => s.share(),
+        Command::EscrowRecover(r) => r.recover(cli.limit, &lang),
+        Command::Lock(k) => k.lock(),
+        Command::Unlock(k) => k.unlock(),
     }
 }
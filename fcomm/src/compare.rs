@@ -0,0 +1,260 @@
+//! Differences between two proofs of what should be the same claim, such as
+//! proofs of one evaluation made by different versions of this crate.
+//!
+//! The proofs are compared part by part: the claim, field by field; the public
+//! input and output the verifier derives from the claim; what identifies the
+//! circuit the proof is for; and the serialized Nova proof itself. Reporting
+//! the first part that differs usually points at the cause: a changed claim
+//! encoding shows up in the claim and public IO, a changed circuit in the
+//! circuit part, and a changed prover only in the proof bytes.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use lurk::eval::lang::{Coproc, Lang};
+
+use crate::{error::Error, Claim, Proof, S1};
+
+/// The part of a proof a difference was found in
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Part {
+    /// The claim, compared through its serialization
+    Claim,
+    /// The public input derived from the claim
+    PublicInput,
+    /// The public output derived from the claim
+    PublicOutput,
+    /// The reduction count, number of steps and evaluator configuration
+    Circuit,
+    /// The serialized Nova proof
+    ProofBytes,
+}
+
+impl fmt::Display for Part {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Part::Claim => "claim",
+            Part::PublicInput => "public input",
+            Part::PublicOutput => "public output",
+            Part::Circuit => "circuit",
+            Part::ProofBytes => "proof bytes",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Difference {
+    pub part: Part,
+    /// Where in the part the values differ, e.g. `Evaluation.expr_out`
+    pub path: String,
+    pub left: String,
+    pub right: String,
+}
+
+#[derive(Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProofComparison {
+    pub differences: Vec<Difference>,
+}
+
+impl ProofComparison {
+    pub fn is_identical(&self) -> bool {
+        self.differences.is_empty()
+    }
+
+    pub fn differences_in(&self, part: Part) -> impl Iterator<Item = &Difference> {
+        self.differences.iter().filter(move |d| d.part == part)
+    }
+
+    fn push(&mut self, part: Part, path: String, left: String, right: String) {
+        self.differences.push(Difference {
+            part,
+            path,
+            left,
+            right,
+        });
+    }
+
+    fn compare_json(&mut self, part: Part, path: String, left: &Value, right: &Value) {
+        let child = |key: &dyn fmt::Display| {
+            if path.is_empty() {
+                key.to_string()
+            } else {
+                format!("{path}.{key}")
+            }
+        };
+        match (left, right) {
+            (Value::Object(l), Value::Object(r)) => {
+                let keys: BTreeSet<_> = l.keys().chain(r.keys()).collect();
+                for key in keys {
+                    match (l.get(key), r.get(key)) {
+                        (Some(l), Some(r)) => self.compare_json(part, child(key), l, r),
+                        (l, r) => {
+                            let show = |v: Option<&Value>| {
+                                v.map_or_else(|| "<missing>".into(), Value::to_string)
+                            };
+                            self.push(part, child(key), show(l), show(r))
+                        }
+                    }
+                }
+            }
+            (Value::Array(l), Value::Array(r)) if l.len() == r.len() => {
+                for (i, (l, r)) in l.iter().zip(r).enumerate() {
+                    self.compare_json(part, format!("{path}[{i}]"), l, r);
+                }
+            }
+            (l, r) if l != r => self.push(part, path, l.to_string(), r.to_string()),
+            _ => (),
+        }
+    }
+
+    pub(crate) fn compare_claims(
+        &mut self,
+        left: &Claim<S1>,
+        right: &Claim<S1>,
+    ) -> Result<(), Error> {
+        let left = serde_json::to_value(left).map_err(anyhow::Error::from)?;
+        let right = serde_json::to_value(right).map_err(anyhow::Error::from)?;
+        self.compare_json(Part::Claim, String::new(), &left, &right);
+        Ok(())
+    }
+
+    fn compare_field_vecs(&mut self, part: Part, left: &[S1], right: &[S1]) {
+        let hex = |f: Option<&S1>| f.map_or_else(|| "<missing>".into(), |f| format!("{f:?}"));
+        for i in 0..left.len().max(right.len()) {
+            let (l, r) = (left.get(i), right.get(i));
+            if l != r {
+                self.push(part, format!("[{i}]"), hex(l), hex(r));
+            }
+        }
+    }
+}
+
+impl fmt::Display for ProofComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_identical() {
+            return writeln!(f, "The proofs are identical.");
+        }
+        for d in &self.differences {
+            writeln!(f, "{} {}:", d.part, d.path)?;
+            writeln!(f, "  left:  {}", d.left)?;
+            writeln!(f, "  right: {}", d.right)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares two proofs part by part. Nothing is verified: the proofs can be
+/// compared even if neither verifies.
+pub fn compare_proofs(
+    left: &Proof<'_, S1>,
+    right: &Proof<'_, S1>,
+    lang: &Lang<S1, Coproc<S1>>,
+) -> Result<ProofComparison, Error> {
+    let mut comparison = ProofComparison::default();
+
+    comparison.compare_claims(&left.claim, &right.claim)?;
+
+    // A claim the verifier can't make sense of is a difference, not a failure
+    // to compare
+    match (left.io_vecs(lang), right.io_vecs(lang)) {
+        (Ok((left_in, left_out)), Ok((right_in, right_out))) => {
+            comparison.compare_field_vecs(Part::PublicInput, &left_in, &right_in);
+            comparison.compare_field_vecs(Part::PublicOutput, &left_out, &right_out);
+        }
+        (left_io, right_io) => {
+            let show =
+                |io: Result<_, Error>| io.map_or_else(|e| format!("error: {e}"), |_| "ok".into());
+            comparison.push(
+                Part::PublicInput,
+                String::new(),
+                show(left_io),
+                show(right_io),
+            );
+        }
+    }
+
+    let circuit = [
+        (
+            "reduction_count",
+            left.reduction_count.count().to_string(),
+            right.reduction_count.count().to_string(),
+        ),
+        (
+            "num_steps",
+            left.num_steps.to_string(),
+            right.num_steps.to_string(),
+        ),
+        (
+            "eval_config",
+            left.eval_config.to_string(),
+            right.eval_config.to_string(),
+        ),
+    ];
+    for (path, l, r) in circuit {
+        if l != r {
+            comparison.push(Part::Circuit, path.into(), l, r);
+        }
+    }
+
+    let left_bytes = bincode::serialize(&left.proof).map_err(anyhow::Error::from)?;
+    let right_bytes = bincode::serialize(&right.proof).map_err(anyhow::Error::from)?;
+    if left_bytes != right_bytes {
+        let first = left_bytes
+            .iter()
+            .zip(&right_bytes)
+            .position(|(l, r)| l != r)
+            .unwrap_or_else(|| left_bytes.len().min(right_bytes.len()));
+        comparison.push(
+            Part::ProofBytes,
+            format!("from byte {first}"),
+            format!("{} bytes", left_bytes.len()),
+            format!("{} bytes", right_bytes.len()),
+        );
+    }
+
+    Ok(comparison)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Evaluation;
+    use lurk::eval::Status;
+
+    #[test]
+    fn claim_differences_are_located() {
+        let left = Evaluation {
+            expr: "(+ 1 2)".into(),
+            expr_out: "3".into(),
+            status: Status::Terminal,
+            ..Default::default()
+        };
+        let right = Evaluation {
+            expr_out: "4".into(),
+            iterations: Some(3),
+            ..left.clone()
+        };
+        let mut comparison = ProofComparison::default();
+        comparison
+            .compare_claims(
+                &Claim::Evaluation(left.clone()),
+                &Claim::Evaluation(left.clone()),
+            )
+            .unwrap();
+        assert!(comparison.is_identical());
+
+        comparison
+            .compare_claims(&Claim::Evaluation(left), &Claim::Evaluation(right))
+            .unwrap();
+        let paths: Vec<_> = comparison
+            .differences_in(Part::Claim)
+            .map(|d| d.path.as_str())
+            .collect();
+        assert_eq!(paths, ["Evaluation.expr_out", "Evaluation.iterations"]);
+        assert_eq!(comparison.differences[0].left, "\"3\"");
+    }
+}
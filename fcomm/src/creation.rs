@@ -0,0 +1,217 @@
+//! Proofs that a commitment was made correctly.
+//!
+//! `fcomm commit --prove-creation proof.json` proves, along with creating the
+//! commitment, that
+//!
+//! `(hide (open <secret commitment>) <source>)`
+//!
+//! evaluates to it, where the secret commitment is `(commit <secret>)`. The
+//! claim only holds the hash of the function's source and the commitment to
+//! the secret, so whoever has the source can check the commitment hides the
+//! function it evaluates to, without learning the secret or committing to the
+//! function themselves. Sealed commitments can't be proved this way yet.
+
+use std::sync::Arc;
+
+#[cfg(not(target_arch = "wasm32"))]
+use proptest_derive::Arbitrary;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use lurk::{
+    eval::{
+        empty_sym_env,
+        lang::{Coproc, Lang},
+        IO,
+    },
+    field::LurkField,
+    lurk_sym_ptr,
+    proof::nova::{NovaProver, PublicParams},
+    ptr::Ptr,
+    store::Store,
+    tag::ExprTag,
+    z_ptr::ZExprPtr,
+    Num,
+};
+
+use crate::{error::Error, evaluate, Claim, Commitment, CommittedExpression, LurkPtr, Proof, S1};
+
+/// The claim that `commitment` hides the function evaluated from `source`
+#[cfg_attr(not(target_arch = "wasm32"), derive(Arbitrary))]
+#[cfg_attr(not(target_arch = "wasm32"), proptest(no_bound))]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Creation<F: LurkField> {
+    pub commitment: Commitment<F>,
+    /// `(commit <secret>)`, which binds the secret without revealing it
+    pub secret_commitment: Commitment<F>,
+    /// The hash of the function's source
+    pub source: ZExprPtr<F>,
+}
+
+/// `(hide (open <secret commitment>) <source>)`
+fn creation_expression<F: LurkField>(
+    s: &mut Store<F>,
+    secret_commitment: Ptr<F>,
+    source: Ptr<F>,
+) -> Ptr<F> {
+    let hide = lurk_sym_ptr!(s, hide);
+    let open = lurk_sym_ptr!(s, open);
+    let secret = s.list(&[open, secret_commitment]);
+    s.list(&[hide, secret, source])
+}
+
+impl<F: LurkField + Serialize + DeserializeOwned> Creation<F> {
+    /// The expression whose evaluation proves `function` was committed to
+    /// correctly, along with the corresponding claim. The secret is added to
+    /// `s`, so the expression can be evaluated with it.
+    pub(crate) fn construct(
+        s: &mut Store<F>,
+        function: &CommittedExpression<F>,
+        lang: &Lang<F, Coproc<F>>,
+    ) -> Result<(Ptr<F>, Self), Error> {
+        if function.seal.is_some() {
+            return Err(Error::CreationFailure(
+                "proving the creation of sealed commitments is unsupported".into(),
+            ));
+        }
+        let missing = |what: &str| Error::CreationFailure(format!("the {what} is missing"));
+        let commitment = function.commitment.ok_or_else(|| missing("commitment"))?;
+        let secret = function.secret.as_ref().ok_or_else(|| missing("secret"))?;
+
+        // A function given as a pointer is its own source, since functions
+        // evaluate to themselves
+        let source = match &function.expr {
+            LurkPtr::Source(source) => s.read(source).map_err(|e| {
+                Error::CreationFailure(format!("can't read the function's source: {e}"))
+            })?,
+            expr @ LurkPtr::ZStorePtr(_) => expr.ptr(s, 0, lang),
+        };
+        let source_hash = s
+            .hash_expr(&source)
+            .ok_or_else(|| missing("source's hash"))?;
+
+        let secret = s.num(Num::Scalar(*secret.expose()));
+        let secret_comm = s.commit(secret);
+        let secret_commitment = Commitment::from_comm(s, &secret_comm)?;
+
+        let expression = creation_expression(s, secret_comm, source);
+        let creation = Self {
+            commitment,
+            secret_commitment,
+            source: source_hash,
+        };
+        Ok((expression, creation))
+    }
+
+    /// The claim that `function` was committed to correctly, once it's checked
+    /// to be true
+    pub fn new(
+        s: &mut Store<F>,
+        function: &CommittedExpression<F>,
+        limit: usize,
+        lang: &Lang<F, Coproc<F>>,
+    ) -> Result<Self, Error> {
+        let (expression, creation) = Self::construct(s, function, lang)?;
+        let (output, _iterations) = evaluate(s, expression, None, limit, lang)?;
+        if output.expr.tag != ExprTag::Comm
+            || Commitment::from_comm(s, &output.expr)? != creation.commitment
+        {
+            return Err(Error::CreationFailure(
+                "the commitment doesn't hide the function's source".into(),
+            ));
+        }
+        Ok(creation)
+    }
+
+    /// The public input and output of the evaluation, as the verifier
+    /// reconstructs them from the claim
+    pub fn io(&self, s: &mut Store<F>) -> (IO<F>, IO<F>) {
+        let secret_commitment = self.secret_commitment.ptr(s);
+        let source = s.intern_maybe_opaque(self.source.tag(), *self.source.value());
+
+        let input = IO {
+            expr: creation_expression(s, secret_commitment, source),
+            env: empty_sym_env(s),
+            cont: s.intern_cont_outermost(),
+        };
+        let output = IO {
+            expr: self.commitment.ptr(s),
+            env: empty_sym_env(s),
+            cont: s.intern_cont_terminal(),
+        };
+        (input, output)
+    }
+}
+
+impl<'a> Creation<S1> {
+    pub fn create_and_prove(
+        s: &'a mut Store<S1>,
+        function: &CommittedExpression<S1>,
+        limit: usize,
+        only_use_cached_proofs: bool,
+        nova_prover: &'a NovaProver<S1, Coproc<S1>>,
+        pp: &'a PublicParams<'_, S1, Coproc<S1>>,
+        lang: Arc<Lang<S1, Coproc<S1>>>,
+    ) -> Result<Proof<'a, S1>, Error> {
+        let claim = Claim::Creation(Self::new(s, function, limit, &lang)?);
+        Proof::prove_claim(
+            s,
+            &claim,
+            limit,
+            only_use_cached_proofs,
+            nova_prover,
+            pp,
+            &lang,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::seal::Seal;
+    use lurk::secret::Secret;
+
+    #[test]
+    fn creation_io_matches_the_proven_expression() {
+        let s = &mut Store::<S1>::default();
+        let lang = Lang::new();
+        let mut function = CommittedExpression::<S1> {
+            expr: LurkPtr::Source("(lambda (x) (+ x 1))".into()),
+            secret: Some(Secret::new(S1::from(42))),
+            commitment: None,
+            seal: None,
+        };
+        assert!(Creation::new(s, &function, 1000, &lang).is_err());
+
+        let fun_ptr = function.committed_ptr(s, 1000, &lang).unwrap();
+        let secret = Secret::new(S1::from(42));
+        let commitment = Commitment::from_ptr_and_secret(s, &fun_ptr, &secret).unwrap();
+        function.commitment = Some(commitment);
+        let creation = Creation::new(s, &function, 1000, &lang).unwrap();
+        assert_eq!(creation.commitment, commitment);
+
+        // The verifier only has the claim, which doesn't reveal the secret
+        let (expression, _) = Creation::construct(s, &function, &lang).unwrap();
+        let verifier_store = &mut Store::<S1>::default();
+        let (input, output) = creation.io(verifier_store);
+        assert_eq!(
+            s.hash_expr(&expression),
+            verifier_store.hash_expr(&input.expr)
+        );
+        assert_eq!(
+            Commitment::from_comm(verifier_store, &output.expr).unwrap(),
+            commitment
+        );
+
+        let mut other_secret = function.clone();
+        other_secret.secret = Some(Secret::new(S1::from(43)));
+        assert!(matches!(
+            Creation::new(s, &other_secret, 1000, &lang),
+            Err(Error::CreationFailure(_))
+        ));
+        let mut sealed = function;
+        sealed.seal = Some(Seal::new(["emit"]));
+        assert!(Creation::new(s, &sealed, 1000, &lang).is_err());
+    }
+}
@@ -22,14 +22,34 @@ pub enum Error {
     UnknownCommitment,
     #[error("Opening Failure: {0}")]
     OpeningFailure(String),
+    #[error("Witness error: {0}")]
+    WitnessError(String),
+    #[error("Seal error: {0}")]
+    SealError(String),
+    #[error("Creation failure: {0}")]
+    CreationFailure(String),
+    #[error("Package error: {0}")]
+    PackageError(String),
+    #[error("Pinning error: {0}")]
+    PinningError(String),
+    #[error("Policy error: {0}")]
+    PolicyError(String),
+    #[error("Escrow error: {0}")]
+    EscrowError(String),
+    #[error("Vault error: {0}")]
+    VaultError(String),
     #[error("Evaluation Failure")]
     EvaluationFailure(ReductionError),
+    #[error("Unexpected value: {0}")]
+    MatchError(#[from] lurk::pattern::MatchError),
     #[error("Store error: {0}")]
     StoreError(#[from] store::Error),
     #[error("Serde error: {0}")]
     SerdeError(#[from] lurk::z_data::serde::SerdeError),
     #[error("Anyhow error: {0}")]
     AnyhowError(#[from] anyhow::Error),
+    #[error("Cancelled")]
+    Cancelled,
     #[error("Cache error: {0}")]
     CacheError(#[from] error::Error),
 }
@@ -0,0 +1,373 @@
+//! Escrow of commitment secrets with trustees.
+//!
+//! Whoever loses the secret of a hiding commitment can never open it again. To
+//! keep an organization's commitments from being lost along with one
+//! operator, `fcomm commit --escrow-trustees` splits the secret with Shamir's
+//! scheme, so that any `threshold` of the trustees' shares, and no fewer,
+//! recover it. Each share is encrypted to its trustee's X25519 public key: the
+//! Diffie-Hellman secret of the trustee's key and a fresh ephemeral key is
+//! expanded with HKDF-SHA256 into a ChaCha20-Poly1305 key, which seals the
+//! share along with the commitment and the share's index, so shares can't be
+//! tampered with or moved to another record. The resulting `EscrowRecord` is
+//! stored alongside the commitment. Trustees decrypt their shares with
+//! `fcomm escrow-share`, and `fcomm escrow-recover` combines them, checking the
+//! secret against the commitment if the function is at hand.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use ff::{Field, PrimeField};
+use hkdf::Hkdf;
+use rand::{CryptoRng, RngCore};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use sha2::Sha256;
+use x25519_dalek::{EphemeralSecret, PublicKey, SharedSecret, StaticSecret};
+use zeroize::Zeroizing;
+
+use lurk::secret::Secret;
+
+use crate::{error::Error, Commitment, S1};
+
+/// Separates the keys of escrowed shares from other uses of HKDF
+const KEY_DOMAIN: &[u8] = b"fcomm-escrow-share-key";
+
+/// A trustee's decryption key, which is zeroized when dropped. Its `Debug`
+/// output is redacted, and it's only serialized to be written to the trustee's
+/// key file.
+#[derive(Clone)]
+pub struct TrusteeKey {
+    secret: StaticSecret,
+}
+
+impl TrusteeKey {
+    pub fn random(rng: impl RngCore + CryptoRng) -> Self {
+        Self {
+            secret: StaticSecret::random_from_rng(rng),
+        }
+    }
+
+    pub fn public_key(&self) -> TrusteePublicKey {
+        TrusteePublicKey(PublicKey::from(&self.secret).to_bytes())
+    }
+}
+
+impl fmt::Debug for TrusteeKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TrusteeKey({}, <redacted>)", self.public_key())
+    }
+}
+
+impl Serialize for TrusteeKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&Zeroizing::new(hex::encode(self.secret.as_bytes())))
+    }
+}
+
+impl<'de> Deserialize<'de> for TrusteeKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let encoded = Zeroizing::new(String::deserialize(deserializer)?);
+        let mut bytes = Zeroizing::new([0; 32]);
+        hex::decode_to_slice(encoded.as_str(), bytes.as_mut_slice()).map_err(D::Error::custom)?;
+        Ok(Self {
+            secret: StaticSecret::from(*bytes),
+        })
+    }
+}
+
+/// A trustee's public key, an X25519 public key, which reads and prints as hex
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TrusteePublicKey(#[serde(with = "hex")] [u8; 32]);
+
+impl TrusteePublicKey {
+    /// The key, which mustn't be a point of small order, like the identity,
+    /// since the Diffie-Hellman secrets of those don't depend on the other key
+    fn point(&self) -> Result<PublicKey, Error> {
+        let key = PublicKey::from(self.0);
+        // Clamped scalars are multiples of the cofactor, so any of them only
+        // sends the points of small order to the identity
+        let probe = StaticSecret::from([1; 32]);
+        if !probe.diffie_hellman(&key).was_contributory() {
+            return Err(Error::EscrowError(format!(
+                "{self} is a point of small order, not a public key"
+            )));
+        }
+        Ok(key)
+    }
+}
+
+impl FromStr for TrusteePublicKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let key = Self(hex::FromHex::from_hex(s)?);
+        key.point()?;
+        Ok(key)
+    }
+}
+
+impl fmt::Display for TrusteePublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+/// A share of an escrowed secret: the value at `index` of the polynomial whose
+/// value at zero is the secret
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Share {
+    pub index: u64,
+    pub value: S1,
+}
+
+/// A share encrypted to its trustee
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct EncryptedShare {
+    pub trustee: TrusteePublicKey,
+    pub index: u64,
+    /// The ephemeral public key the share was encrypted with
+    #[serde(with = "hex")]
+    ephemeral: [u8; 32],
+    /// The share, sealed with ChaCha20-Poly1305
+    #[serde(with = "hex")]
+    ciphertext: Vec<u8>,
+}
+
+/// The shares of a commitment's secret, any `threshold` of which recover it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct EscrowRecord {
+    pub commitment: Commitment<S1>,
+    pub threshold: usize,
+    pub shares: Vec<EncryptedShare>,
+}
+
+impl EncryptedShare {
+    /// The cipher of the share encrypted with `ephemeral` to `trustee`, given
+    /// their Diffie-Hellman secret `shared`. Each key seals a single share, so
+    /// the nonce is always zero.
+    fn cipher(
+        shared: &SharedSecret,
+        ephemeral: &[u8; 32],
+        trustee: &TrusteePublicKey,
+    ) -> Result<ChaCha20Poly1305, Error> {
+        if !shared.was_contributory() {
+            return Err(Error::EscrowError(format!(
+                "{trustee} is a point of small order, not a public key"
+            )));
+        }
+        let salt = [ephemeral.as_slice(), &trustee.0].concat();
+        let mut key = Zeroizing::new([0; 32]);
+        Hkdf::<Sha256>::new(Some(&salt), shared.as_bytes())
+            .expand(KEY_DOMAIN, key.as_mut_slice())
+            .expect("32 bytes is a valid length for HKDF-SHA256");
+        Ok(ChaCha20Poly1305::new(Key::from_slice(key.as_slice())))
+    }
+
+    /// The data the share is bound to: the commitment and its index
+    fn aad(commitment: &Commitment<S1>, index: u64) -> Vec<u8> {
+        let mut aad = commitment.comm.to_repr().as_ref().to_vec();
+        aad.extend(index.to_le_bytes());
+        aad
+    }
+}
+
+impl EscrowRecord {
+    /// Splits `secret`, the secret of `commitment`, among `trustees`, so that
+    /// any `threshold` of them can recover it
+    pub fn new(
+        commitment: Commitment<S1>,
+        secret: &Secret<S1>,
+        trustees: &[TrusteePublicKey],
+        threshold: usize,
+        mut rng: impl RngCore + CryptoRng,
+    ) -> Result<Self, Error> {
+        if threshold == 0 || threshold > trustees.len() {
+            return Err(Error::EscrowError(format!(
+                "threshold must be between 1 and the {} trustees, not {threshold}",
+                trustees.len()
+            )));
+        }
+        // the coefficients of a polynomial of degree `threshold - 1`
+        let mut coefficients = vec![*secret.expose()];
+        coefficients.extend((1..threshold).map(|_| S1::random(&mut rng)));
+
+        let shares = trustees
+            .iter()
+            .zip(1..)
+            .map(|(trustee, index)| {
+                let x = S1::from(index);
+                let value = coefficients
+                    .iter()
+                    .rev()
+                    .fold(S1::ZERO, |acc, coefficient| acc * x + coefficient);
+                let ephemeral_secret = EphemeralSecret::random_from_rng(&mut rng);
+                let ephemeral = PublicKey::from(&ephemeral_secret).to_bytes();
+                let shared = ephemeral_secret.diffie_hellman(&trustee.point()?);
+                let plaintext = Zeroizing::new(value.to_repr());
+                let payload = Payload {
+                    msg: plaintext.as_slice(),
+                    aad: &EncryptedShare::aad(&commitment, index),
+                };
+                let ciphertext = EncryptedShare::cipher(&shared, &ephemeral, trustee)?
+                    .encrypt(&Nonce::default(), payload)
+                    .map_err(|_| Error::EscrowError("encryption failed".into()))?;
+                Ok(EncryptedShare {
+                    trustee: *trustee,
+                    index,
+                    ephemeral,
+                    ciphertext,
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(Self {
+            commitment,
+            threshold,
+            shares,
+        })
+    }
+
+    /// Decrypts the share of the trustee whose key is `key`
+    pub fn decrypt_share(&self, key: &TrusteeKey) -> Result<Share, Error> {
+        let public_key = key.public_key();
+        let Some(share) = self.shares.iter().find(|share| share.trustee == public_key) else {
+            return Err(Error::EscrowError(format!("{public_key} isn't a trustee")));
+        };
+        let ephemeral = TrusteePublicKey(share.ephemeral).point()?;
+        let shared = key.secret.diffie_hellman(&ephemeral);
+        let payload = Payload {
+            msg: &share.ciphertext,
+            aad: &EncryptedShare::aad(&self.commitment, share.index),
+        };
+        let plaintext = Zeroizing::new(
+            EncryptedShare::cipher(&shared, &share.ephemeral, &public_key)?
+                .decrypt(&Nonce::default(), payload)
+                .map_err(|_| Error::EscrowError("the share was tampered with".into()))?,
+        );
+        let mut repr = <S1 as PrimeField>::Repr::default();
+        if plaintext.len() != repr.as_ref().len() {
+            return Err(Error::EscrowError("the share isn't a scalar".into()));
+        }
+        repr.as_mut().copy_from_slice(&plaintext);
+        let value = Option::from(S1::from_repr(repr))
+            .ok_or_else(|| Error::EscrowError("the share isn't a scalar".into()))?;
+        Ok(Share {
+            index: share.index,
+            value,
+        })
+    }
+
+    /// Recovers the secret from at least `threshold` distinct shares
+    pub fn recover(&self, shares: &[Share]) -> Result<Secret<S1>, Error> {
+        let mut indices = shares.iter().map(|share| share.index).collect::<Vec<_>>();
+        indices.sort_unstable();
+        indices.dedup();
+        if indices.len() != shares.len() {
+            return Err(Error::EscrowError("shares must be distinct".into()));
+        }
+        if let Some(index) = indices
+            .iter()
+            .find(|index| !self.shares.iter().any(|share| share.index == **index))
+        {
+            return Err(Error::EscrowError(format!("no share has index {index}")));
+        }
+        if shares.len() < self.threshold {
+            return Err(Error::EscrowError(format!(
+                "{} shares are needed, only {} were given",
+                self.threshold,
+                shares.len()
+            )));
+        }
+        // Lagrange interpolation at zero
+        let secret = shares.iter().fold(S1::ZERO, |acc, share| {
+            let x = S1::from(share.index);
+            let (num, den) = shares
+                .iter()
+                .filter(|other| other.index != share.index)
+                .map(|other| S1::from(other.index))
+                .fold((S1::ONE, S1::ONE), |(num, den), other_x| {
+                    (num * other_x, den * (other_x - x))
+                });
+            // indices are distinct, so `den` isn't zero
+            acc + share.value * num * den.invert().unwrap()
+        });
+        Ok(Secret::new(secret))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn secrets_are_recovered_by_enough_trustees() {
+        let keys = (0..3)
+            .map(|_| TrusteeKey::random(OsRng))
+            .collect::<Vec<_>>();
+        let trustees = keys.iter().map(TrusteeKey::public_key).collect::<Vec<_>>();
+        let commitment = Commitment { comm: S1::from(42) };
+        let secret = Secret::random(OsRng);
+        let record = EscrowRecord::new(commitment, &secret, &trustees, 2, OsRng).unwrap();
+
+        let shares = keys
+            .iter()
+            .map(|key| record.decrypt_share(key).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(record.recover(&shares[..2]).unwrap(), secret);
+        assert_eq!(record.recover(&shares[1..]).unwrap(), secret);
+        assert_eq!(record.recover(&shares).unwrap(), secret);
+        assert!(record.recover(&shares[..1]).is_err());
+        assert!(record.recover(&[shares[0], shares[0]]).is_err());
+
+        let outsider = TrusteeKey::random(OsRng);
+        assert!(record.decrypt_share(&outsider).is_err());
+        let key = trustees[0].to_string().parse::<TrusteePublicKey>().unwrap();
+        assert_eq!(key, trustees[0]);
+    }
+
+    #[test]
+    fn shares_are_authenticated() {
+        let key = TrusteeKey::random(OsRng);
+        let trustees = [key.public_key()];
+        let commitment = Commitment { comm: S1::from(42) };
+        let record =
+            EscrowRecord::new(commitment, &Secret::random(OsRng), &trustees, 1, OsRng).unwrap();
+        assert!(record.decrypt_share(&key).is_ok());
+
+        let mut tampered = record.clone();
+        tampered.shares[0].ciphertext[0] ^= 1;
+        assert!(tampered.decrypt_share(&key).is_err());
+        let mut moved = record;
+        moved.commitment = Commitment { comm: S1::from(43) };
+        assert!(moved.decrypt_share(&key).is_err());
+    }
+
+    #[test]
+    fn small_order_keys_are_rejected() {
+        let identity = TrusteePublicKey([0; 32]);
+        let mut one = [0; 32];
+        one[0] = 1;
+        for key in [identity, TrusteePublicKey(one)] {
+            assert!(key.to_string().parse::<TrusteePublicKey>().is_err());
+            let commitment = Commitment { comm: S1::from(42) };
+            let secret = Secret::random(OsRng);
+            assert!(EscrowRecord::new(commitment, &secret, &[key], 1, OsRng).is_err());
+        }
+    }
+
+    #[test]
+    fn trustee_keys_are_redacted() {
+        let key = TrusteeKey::random(OsRng);
+        let encoded = hex::encode(key.secret.as_bytes());
+        assert!(!format!("{key:?}").contains(&encoded));
+
+        // The key file holds the key itself
+        let json = serde_json::to_string(&key).unwrap();
+        assert_eq!(json, format!("\"{encoded}\""));
+        let read: TrusteeKey = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.public_key(), key.public_key());
+    }
+}
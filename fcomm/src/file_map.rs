@@ -1,27 +1,51 @@
 use std::fs::create_dir_all;
 use std::fs::File;
-use std::io::{self, BufReader, BufWriter};
+use std::io::{self, BufRead, BufReader, BufWriter, Read};
 use std::marker::PhantomData;
 use std::path::Path;
 
+use lurk::artifacts::{ArtifactKind, ArtifactName, Artifacts};
+use lurk::config::Settings;
+use lurk::field::LanguageField;
 use lurk::public_parameters::error::Error;
 
+use bincode::Options;
 use camino::Utf8PathBuf;
 use serde::{Deserialize, Serialize};
+use tracing::error;
 
+use crate::vault::{self, KeySource};
+
+/// The `fcomm_data` setting, which is read anew on each call so changes to
+/// `FCOMM_DATA_PATH` are seen. `FCOMM_DATA_PATH` is honored even when
+/// `lurk.toml` can't be read, and the error is logged before falling back to
+/// the default dir.
 pub fn data_dir() -> Utf8PathBuf {
-    match std::env::var("FCOMM_DATA_PATH") {
-        Ok(name) => name.into(),
-        Err(_) => Utf8PathBuf::from("/var/tmp/fcomm_data/"),
+    if let Ok(dir) = std::env::var("FCOMM_DATA_PATH") {
+        return Utf8PathBuf::from(dir);
+    }
+    match Settings::load(None) {
+        Ok(Settings {
+            fcomm_data: Some(dir),
+            ..
+        }) => dir,
+        Ok(_) => Utf8PathBuf::from(DEFAULT_DATA_DIR),
+        Err(e) => {
+            error!("ignoring unreadable lurk settings, using {DEFAULT_DATA_DIR}: {e}");
+            Utf8PathBuf::from(DEFAULT_DATA_DIR)
+        }
     }
 }
 
+const DEFAULT_DATA_DIR: &str = "/var/tmp/fcomm_data/";
+
 pub trait FileStore
 where
     Self: Sized,
 {
     fn write_to_path<P: AsRef<Path>>(&self, path: P);
     fn write_to_json_path<P: AsRef<Path>>(&self, path: P);
+    fn write_to_locked_path<P: AsRef<Path>>(&self, path: P, key: &KeySource) -> Result<(), Error>;
     fn read_from_path<P: AsRef<Path>>(path: P) -> Result<Self, Error>;
     fn read_from_json_path<P: AsRef<Path>>(path: P) -> Result<Self, Error>;
     fn read_from_stdin() -> Result<Self, Error>;
@@ -43,10 +67,36 @@ where
         serde_json::to_writer(writer, &self).expect("failed to write file");
     }
 
+    /// Writes the value as `write_to_path` does, encrypted with `key`
+    fn write_to_locked_path<P: AsRef<Path>>(&self, path: P, key: &KeySource) -> Result<(), Error> {
+        let bytes = bincode::serialize(&self)
+            .map_err(|e| Error::CacheError(format!("Cache serialization error: {}", e)))?;
+        let locked = vault::lock(&bytes, key).map_err(|e| Error::CacheError(e.to_string()))?;
+        std::fs::write(path, locked)?;
+        Ok(())
+    }
+
+    /// Reads a value `write_to_path` wrote, decrypting it with the key of
+    /// `KeySource::from_env` if it was written locked
     fn read_from_path<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        bincode::deserialize_from(reader)
+        let file = File::open(&path)?;
+        let limit = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+        if vault::is_locked(reader.fill_buf()?) {
+            let key = KeySource::from_env().ok_or_else(|| {
+                Error::CacheError(format!(
+                    "{} is locked: set FCOMM_PASSPHRASE or FCOMM_KEYFILE",
+                    path.as_ref().display()
+                ))
+            })?;
+            let mut locked = vec![];
+            reader.read_to_end(&mut locked)?;
+            let bytes =
+                vault::unlock(&locked, &key).map_err(|e| Error::CacheError(e.to_string()))?;
+            return from_untrusted_bytes(&bytes);
+        }
+        bincode_options(limit)
+            .deserialize_from(reader)
             .map_err(|e| Error::CacheError(format!("Cache deserialization error: {}", e)))
     }
 
@@ -62,35 +112,82 @@ where
     }
 }
 
+/// The options of `bincode::serialize`, with a limit of `limit` bytes read, so
+/// a length prefix can't make the deserializer allocate more memory than the
+/// input could fill
+fn bincode_options(limit: u64) -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(limit)
+}
+
+/// Deserializes bytes as `FileStore::read_from_path` reads a file, for bytes
+/// received from untrusted parties
+pub fn from_untrusted_bytes<T: for<'de> Deserialize<'de>>(bytes: &[u8]) -> Result<T, Error> {
+    bincode_options(bytes.len() as u64)
+        .deserialize(bytes)
+        .map_err(|e| Error::CacheError(format!("Cache deserialization error: {}", e)))
+}
+
 #[derive(Debug)]
 pub struct FileMap<K: ToString, V: FileStore> {
-    dir: Utf8PathBuf,
+    artifacts: Artifacts,
+    kind: ArtifactKind,
+    field: LanguageField,
+    locked: bool,
     _t: PhantomData<(K, V)>,
 }
 
 impl<K: ToString, V: FileStore> FileMap<K, V> {
-    pub fn new<P: AsRef<Path>>(name: P) -> Result<Self, Error> {
+    /// A map whose values are stored as artifacts of `kind` over `field`,
+    /// named after their keys
+    pub fn new<P: AsRef<Path>>(
+        name: P,
+        kind: ArtifactKind,
+        field: LanguageField,
+    ) -> Result<Self, Error> {
         let data_dir = data_dir().as_std_path().join(name);
         let dir = Utf8PathBuf::from_path_buf(data_dir).expect("path contains invalid Unicode");
         create_dir_all(&dir)?;
 
         Ok(Self {
-            dir,
+            artifacts: Artifacts::new(dir).with_legacy_names(|name| name.hash.clone()),
+            kind,
+            field,
+            locked: false,
             _t: Default::default(),
         })
     }
 
+    /// Writes the values encrypted while `KeySource::from_env` gives a key,
+    /// for maps whose values hold secrets
+    pub fn locked(mut self) -> Self {
+        self.locked = true;
+        self
+    }
+
+    /// The directory the values are stored in
+    pub fn dir(&self) -> &Path {
+        self.artifacts.dir().as_std_path()
+    }
+
     fn key_path(&self, key: &K) -> Utf8PathBuf {
-        self.dir.join(Utf8PathBuf::from(key.to_string()))
+        let name = ArtifactName::new(self.kind, self.field, key.to_string());
+        self.artifacts.resolve(&name)
     }
 
     pub fn get(&self, key: &K) -> Option<V> {
-        self.key_path(key);
         V::read_from_path(self.key_path(key)).ok()
     }
 
     pub fn set(&self, key: &K, data: &V) -> Result<(), Error> {
-        data.write_to_path(self.key_path(key));
-        Ok(())
+        match KeySource::from_env().filter(|_| self.locked) {
+            Some(vault_key) => data.write_to_locked_path(self.key_path(key), &vault_key),
+            None => {
+                data.write_to_path(self.key_path(key));
+                Ok(())
+            }
+        }
     }
 }
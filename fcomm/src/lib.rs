@@ -1,5 +1,6 @@
 use ::nova::traits::Group;
 use abomonation::Abomonation;
+use anyhow::anyhow;
 use std::convert::TryFrom;
 use std::sync::Arc;
 use tracing::info;
@@ -14,21 +15,27 @@ use hex::FromHex;
 #[cfg(not(target_arch = "wasm32"))]
 use lurk::field::FWrap;
 use lurk::{
+    artifacts::ArtifactKind,
+    builder,
     circuit::ToInputs,
     eval::{
-        empty_sym_env,
+        empty_emit_log, empty_sym_env,
         lang::{Coproc, Lang},
         Evaluable, Evaluator, Status, Witness, IO,
     },
     field::LurkField,
     hash::PoseidonCache,
+    lem::EvalConfig,
     lurk_sym_ptr,
     proof::nova::{self, NovaProver, PublicParams, G1, G2},
     proof::Prover,
     ptr::{ContPtr, Ptr},
+    ptr_match,
+    secret::Secret,
     state::initial_lurk_state,
     store::Store,
-    tag::ExprTag,
+    syntax::Syntax,
+    tag::{ExprTag, Tag},
     writer::Write,
     z_expr::ZExpr,
     z_ptr::ZExprPtr,
@@ -51,10 +58,24 @@ use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::file_map::{data_dir, FileMap};
 
+pub mod compare;
+pub mod creation;
 pub mod error;
+pub mod escrow;
 pub mod file_map;
-
+pub mod package;
+pub mod pinning;
+pub mod policy;
+pub mod seal;
+pub mod server;
+pub mod session;
+pub mod streaming;
+pub mod vault;
+pub mod witness;
+
+use creation::Creation;
 use error::Error;
+use seal::Seal;
 
 pub const DEFAULT_REDUCTION_COUNT: ReductionCount = ReductionCount::Ten;
 pub static VERBOSE: OnceCell<bool> = OnceCell::new();
@@ -78,12 +99,39 @@ mod base64 {
 
 pub type NovaProofCache = FileMap<String, Proof<'static, S1>>;
 pub fn nova_proof_cache(reduction_count: usize) -> NovaProofCache {
-    FileMap::<String, Proof<'_, S1>>::new(format!("nova_proofs.{}", reduction_count)).unwrap()
+    FileMap::<String, Proof<'_, S1>>::new(
+        format!("nova_proofs.{}", reduction_count),
+        ArtifactKind::Proof,
+        S1::FIELD,
+    )
+    .unwrap()
 }
 
 pub type CommittedExpressionMap = FileMap<Commitment<S1>, CommittedExpression<S1>>;
 pub fn committed_expression_store() -> CommittedExpressionMap {
-    FileMap::<Commitment<S1>, CommittedExpression<S1>>::new("committed_expressions").unwrap()
+    FileMap::<Commitment<S1>, CommittedExpression<S1>>::new(
+        "committed_expressions",
+        ArtifactKind::Function,
+        S1::FIELD,
+    )
+    .unwrap()
+    .locked()
+}
+
+/// Reads the function committed to by `commitment` from the committed
+/// expression store, checking it again, as anything read from disk, with
+/// `CommittedExpression::check`
+pub fn load_committed_expression(
+    commitment: &Commitment<S1>,
+    s: &mut Store<S1>,
+    limit: usize,
+    lang: &Lang<S1, Coproc<S1>>,
+) -> Result<CommittedExpression<S1>, Error> {
+    let function = committed_expression_store()
+        .get(commitment)
+        .ok_or(Error::UnknownCommitment)?;
+    function.check(s, limit, lang)?;
+    Ok(function)
 }
 
 pub fn public_param_dir() -> Utf8PathBuf {
@@ -142,6 +190,34 @@ pub struct OpeningRequest<F: LurkField> {
     pub commitment: Commitment<F>,
     pub input: Expression<F>,
     pub chain: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_predicate: Option<InputPredicate>,
+}
+
+/// Tags an opening's input can be required to have
+#[cfg_attr(not(target_arch = "wasm32"), derive(Arbitrary))]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputTag {
+    Num,
+    U64,
+    Char,
+    Comm,
+    Cons,
+}
+
+/// A condition on the input of an opening. It's checked by the proven
+/// evaluation itself before the committed function is applied, so an input
+/// that fails it can't yield a proof of the opening, and a verifier checking a
+/// claim that declares it knows the input satisfied it.
+#[cfg_attr(not(target_arch = "wasm32"), derive(Arbitrary))]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputPredicate {
+    /// The input is a `u64` between `min` and `max`, inclusive
+    U64Range {
+        min: u64,
+        max: u64,
+    },
+    Tag(InputTag),
 }
 
 impl<F: LurkField> ToString for Commitment<F> {
@@ -213,6 +289,10 @@ pub struct Opening<F: LurkField> {
     pub status: Status,
     pub commitment: Commitment<F>,
     pub new_commitment: Option<Commitment<F>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_predicate: Option<InputPredicate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seal: Option<Seal>,
 }
 
 #[cfg_attr(not(target_arch = "wasm32"), derive(Arbitrary))]
@@ -268,10 +348,13 @@ pub struct CommittedExpression<F: LurkField + Serialize> {
     pub expr: LurkPtr<F>,
     #[cfg_attr(
         not(target_arch = "wasm32"),
-        proptest(strategy = "any::<FWrap<F>>().prop_map(|x| Some(x.0))")
+        proptest(strategy = "any::<FWrap<F>>().prop_map(|x| Some(Secret::new(x.0)))")
     )]
-    pub secret: Option<F>,
+    #[serde(with = "lurk::secret::exposed")]
+    pub secret: Option<Secret<F>>,
     pub commitment: Option<Commitment<F>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seal: Option<Seal>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -279,6 +362,15 @@ pub struct VerificationResult {
     pub verified: bool,
 }
 
+/// The outcome of `Proof::verify_with_predicate`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClaimVerification {
+    pub verified: bool,
+    /// The predicate's verdict on the claim, which is only consulted once the
+    /// proof has verified
+    pub claim_check: Option<Result<(), String>>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Proof<'a, F: CurveCycleEquipped>
 where
@@ -289,6 +381,22 @@ where
     pub proof: nova::Proof<'a, F, Coproc<F>>,
     pub num_steps: usize,
     pub reduction_count: ReductionCount,
+    /// The configuration of the evaluator the proof was made with. Proofs that
+    /// predate it were all made with the standard configuration. Proofs are
+    /// made with the `MultiFrame` circuit of a `Lang`, which only evaluates
+    /// `EvalConfig::for_lang` of it, so that's the only configuration proofs
+    /// are made with, and verifiers reject any other.
+    #[serde(default)]
+    pub eval_config: EvalConfig,
+    /// The values the computation emitted, in order. The proof commits to
+    /// them in its public IO.
+    #[serde(default)]
+    pub emitted: Vec<String>,
+    /// The digest of the verifier key of the parameters the proof was made
+    /// with, as computed by `PublicParams::vk_digest`. Proofs that predate it
+    /// don't say.
+    #[serde(default)]
+    pub vk_digest: Option<String>,
 }
 
 #[cfg_attr(not(target_arch = "wasm32"), derive(Arbitrary))]
@@ -300,6 +408,7 @@ pub enum Claim<F: LurkField> {
     // TODO: Add Expression type
     PtrEvaluation(PtrEvaluation<F>),
     Opening(Opening<F>),
+    Creation(Creation<F>),
 }
 
 impl<F: LurkField + Serialize + for<'de> Deserialize<'de>> Claim<F> {
@@ -330,7 +439,24 @@ impl<F: LurkField + Serialize + for<'de> Deserialize<'de>> Claim<F> {
                 let expr_in = ZExprPtr::<F>::from_lurk_str(&open.input)?;
                 let expr_out = ZExprPtr::<F>::from_lurk_str(&open.output)?;
                 let expr = ZExpr::Cons(expr_in, expr_out);
-                Ok(expr.z_ptr(&PoseidonCache::default()))
+                let cache = PoseidonCache::default();
+                match &open.input_predicate {
+                    // the same opening with and without a predicate are
+                    // different claims, so they mustn't share a proof
+                    Some(predicate) => {
+                        let predicate = ZExprPtr::<F>::from_lurk_str(&format!("\"{predicate}\""))?;
+                        Ok(ZExpr::Cons(expr.z_ptr(&cache), predicate).z_ptr(&cache))
+                    }
+                    None => Ok(expr.z_ptr(&cache)),
+                }
+            }
+            Claim::Creation(creation) => {
+                let commitment = ZExprPtr::from_parts(ExprTag::Comm, creation.commitment.comm);
+                let secret_commitment =
+                    ZExprPtr::from_parts(ExprTag::Comm, creation.secret_commitment.comm);
+                let cache = PoseidonCache::default();
+                let commitments = ZExpr::Cons(secret_commitment, commitment).z_ptr(&cache);
+                Ok(ZExpr::Cons(creation.source, commitments).z_ptr(&cache))
             }
         }
     }
@@ -358,6 +484,9 @@ impl<F: LurkField> Claim<F> {
     pub fn is_opening(&self) -> bool {
         self.opening().is_some()
     }
+    pub fn is_creation(&self) -> bool {
+        self.creation().is_some()
+    }
     pub fn evaluation(&self) -> Option<Evaluation> {
         match self {
             Self::Evaluation(e) => Some(e.clone()),
@@ -376,6 +505,89 @@ impl<F: LurkField> Claim<F> {
             _ => None,
         }
     }
+    pub fn creation(&self) -> Option<Creation<F>> {
+        match self {
+            Self::Creation(c) => Some(c.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl InputPredicate {
+    /// The Lurk expression that checks the predicate on the value of `x`
+    fn check<F: LurkField>(&self, x: Syntax<F>) -> Syntax<F> {
+        use builder::{op, uint};
+        let same_after = |conversion: &str| op("eq", [op(conversion, [x.clone()]), x.clone()]);
+        match self {
+            Self::U64Range { min, max } => builder::if_(
+                same_after("u64"),
+                builder::if_(
+                    op("<=", [uint(*min), x.clone()]),
+                    op("<=", [x.clone(), uint(*max)]),
+                    builder::nil(),
+                ),
+                builder::nil(),
+            ),
+            Self::Tag(InputTag::Num) => same_after("num"),
+            Self::Tag(InputTag::U64) => same_after("u64"),
+            Self::Tag(InputTag::Char) => same_after("char"),
+            Self::Tag(InputTag::Comm) => same_after("comm"),
+            Self::Tag(InputTag::Cons) => op("eq", [op("atom", [x.clone()]), builder::nil()]),
+        }
+    }
+}
+
+impl std::fmt::Display for InputPredicate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::U64Range { min, max } => write!(f, "u64-range-{min}-{max}"),
+            Self::Tag(tag) => write!(f, "tag-{tag:?}"),
+        }
+    }
+}
+
+/// Builds `(open <commitment>)`, or the expression checking the seal of a
+/// sealed commitment before extracting its function (see `seal`)
+fn opened_function<F: LurkField>(
+    s: &mut Store<F>,
+    comm_ptr: Ptr<F>,
+    seal: Option<&Seal>,
+) -> Result<Ptr<F>, Error> {
+    match seal {
+        Some(seal) => seal.opened_function(s, comm_ptr),
+        None => {
+            let open = lurk_sym_ptr!(s, open);
+            Ok(s.list(&[open, comm_ptr]))
+        }
+    }
+}
+
+/// Builds `((open <commitment>) input)`, the expression an opening evaluates.
+/// With a predicate, the function is only applied to inputs that satisfy it:
+///
+/// `(let ((x input)) (if <check x> ((open <commitment>) x) input-rejected))`
+///
+/// where the unbound `input-rejected` ends evaluation in an error otherwise.
+fn opening_application<F: LurkField>(
+    s: &mut Store<F>,
+    fun_expr: Ptr<F>,
+    input: Ptr<F>,
+    predicate: Option<&InputPredicate>,
+) -> Ptr<F> {
+    let Some(predicate) = predicate else {
+        return s.list(&[fun_expr, input]);
+    };
+    let x = s.intern_syntax(builder::sym("x"));
+    let check = s.intern_syntax(predicate.check(builder::sym("x")));
+    let rejected = s.intern_syntax(builder::sym("input-rejected"));
+    let let_ = s.intern_syntax(builder::lurk("let"));
+    let if_ = s.intern_syntax(builder::lurk("if"));
+
+    let application = s.list(&[fun_expr, x]);
+    let body = s.list(&[if_, check, application, rejected]);
+    let binding = s.list(&[x, input]);
+    let bindings = s.list(&[binding]);
+    s.list(&[let_, bindings, body])
 }
 
 type E = Error;
@@ -506,16 +718,24 @@ impl<F: LurkField + Serialize + DeserializeOwned> Commitment<F> {
         s.intern_opaque_comm(self.comm)
     }
 
-    pub fn from_ptr_with_hiding(s: &mut Store<F>, ptr: &Ptr<F>) -> Result<(Self, F), Error> {
-        let secret = F::random(OsRng);
-
-        let commitment = Self::from_ptr_and_secret(s, ptr, secret)?;
+    /// Commits to `ptr` with a fresh random secret, which is zeroized when
+    /// the returned `Secret` is dropped
+    pub fn from_ptr_with_hiding(
+        s: &mut Store<F>,
+        ptr: &Ptr<F>,
+    ) -> Result<(Self, Secret<F>), Error> {
+        let secret = Secret::random(OsRng);
+        let hidden = s.hide_secret(&secret, *ptr);
 
-        Ok((commitment, secret))
+        Ok((Self::from_comm(s, &hidden)?, secret))
     }
 
-    pub fn from_ptr_and_secret(s: &mut Store<F>, ptr: &Ptr<F>, secret: F) -> Result<Self, Error> {
-        let hidden = s.hide(secret, *ptr);
+    pub fn from_ptr_and_secret(
+        s: &mut Store<F>,
+        ptr: &Ptr<F>,
+        secret: &Secret<F>,
+    ) -> Result<Self, Error> {
+        let hidden = s.hide_secret(secret, *ptr);
 
         Self::from_comm(s, &hidden)
     }
@@ -525,35 +745,48 @@ impl<F: LurkField + Serialize + DeserializeOwned> Commitment<F> {
         s: &mut Store<F>,
         function: &CommittedExpression<F>,
         input: Ptr<F>,
+        predicate: Option<&InputPredicate>,
         limit: usize,
         lang: &Lang<F, Coproc<F>>,
     ) -> Result<(Self, Ptr<F>), Error> {
-        let fun_ptr = function.expr_ptr(s, limit, lang)?;
-        let secret = function.secret.expect("CommittedExpression secret missing");
+        let fun_ptr = function.committed_ptr(s, limit, lang)?;
+        let secret = function
+            .secret
+            .as_ref()
+            .expect("CommittedExpression secret missing");
 
         let commitment = Self::from_ptr_and_secret(s, &fun_ptr, secret)?;
+        if function.commitment.map_or(false, |c| c != commitment) {
+            return Err(Error::OpeningFailure(
+                "the function doesn't match its commitment".into(),
+            ));
+        }
 
-        let open = lurk_sym_ptr!(s, open);
-        let comm_ptr = s.hide(secret, fun_ptr);
+        let comm_ptr = s.hide_secret(secret, fun_ptr);
 
         // (open <commitment>)
-        let fun_expr = s.list(&[open, comm_ptr]);
+        let fun_expr = opened_function(s, comm_ptr, function.seal.as_ref())?;
 
         // ((open <commitment>) input)
-        let expression = s.list(&[fun_expr, input]);
+        let expression = opening_application(s, fun_expr, input, predicate);
 
         Ok((commitment, expression))
     }
 
-    fn fun_application(&self, s: &mut Store<F>, input: Ptr<F>) -> Ptr<F> {
-        let open = lurk_sym_ptr!(s, open);
+    fn fun_application(
+        &self,
+        s: &mut Store<F>,
+        input: Ptr<F>,
+        predicate: Option<&InputPredicate>,
+        seal: Option<&Seal>,
+    ) -> Result<Ptr<F>, Error> {
         let comm_ptr = self.ptr(s);
 
         // (open <commitment>)
-        let fun_expr = s.list(&[open, comm_ptr]);
+        let fun_expr = opened_function(s, comm_ptr, seal)?;
 
         // ((open commitment) input)
-        s.list(&[fun_expr, input])
+        Ok(opening_application(s, fun_expr, input, predicate))
     }
 }
 
@@ -564,27 +797,81 @@ impl<F: LurkField + Serialize + DeserializeOwned> CommittedExpression<F> {
         limit: usize,
         lang: &Lang<F, Coproc<F>>,
     ) -> Result<Ptr<F>, Error> {
-        let source_ptr = self.expr.ptr(s, limit, lang);
+        self.expr.try_ptr(s, limit, lang)
+    }
 
-        Ok(source_ptr)
+    /// Checks a committed function read back from storage, which may have
+    /// changed since it was committed to: that it still respects its seal, if
+    /// it has one, and that it still hides to its commitment
+    pub fn check(
+        &self,
+        s: &mut Store<F>,
+        limit: usize,
+        lang: &Lang<F, Coproc<F>>,
+    ) -> Result<(), Error> {
+        let fun_ptr = self.committed_ptr(s, limit, lang)?;
+        if let (Some(secret), Some(commitment)) = (&self.secret, self.commitment) {
+            if Commitment::from_ptr_and_secret(s, &fun_ptr, secret)? != commitment {
+                return Err(Error::OpeningFailure(
+                    "the function doesn't match its commitment".into(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The value the commitment hides: the function itself or, with a seal,
+    /// the function along with the seal's digest once it's checked that the
+    /// function respects it
+    pub fn committed_ptr(
+        &self,
+        s: &mut Store<F>,
+        limit: usize,
+        lang: &Lang<F, Coproc<F>>,
+    ) -> Result<Ptr<F>, Error> {
+        let fun_ptr = self.expr_ptr(s, limit, lang)?;
+        match &self.seal {
+            Some(seal) => {
+                seal.check(s, fun_ptr)?;
+                seal.wrap(s, fun_ptr)
+            }
+            None => Ok(fun_ptr),
+        }
     }
 }
 
 impl<F: LurkField + Serialize + DeserializeOwned> LurkPtr<F> {
+    /// Like `try_ptr`, but panics if the source can't be read or evaluated, or
+    /// the `ZStore` doesn't hold the expression
     pub fn ptr(&self, s: &mut Store<F>, limit: usize, lang: &Lang<F, Coproc<F>>) -> Ptr<F> {
+        self.try_ptr(s, limit, lang)
+            .expect("could not get the expression")
+    }
+
+    /// The expression: the value of the source, or the one in the `ZStore`
+    pub fn try_ptr(
+        &self,
+        s: &mut Store<F>,
+        limit: usize,
+        lang: &Lang<F, Coproc<F>>,
+    ) -> Result<Ptr<F>, Error> {
         match self {
             LurkPtr::Source(source) => {
-                let ptr = s.read(source).expect("could not read source");
-                assert!(!ptr.raw.is_opaque());
-                let (out, _) = evaluate(s, ptr, None, limit, lang).unwrap();
+                let ptr = s
+                    .read(source)
+                    .map_err(|e| anyhow!("could not read source: {e}"))?;
+                if ptr.raw.is_opaque() {
+                    return Err(anyhow!("the source is opaque").into());
+                }
+                let (out, _) = evaluate(s, ptr, None, limit, lang)?;
 
-                out.expr
+                Ok(out.expr)
             }
             LurkPtr::ZStorePtr(z_store_ptr) => {
                 let z_store = &z_store_ptr.z_store;
                 let z_ptr = z_store_ptr.z_ptr;
                 s.intern_z_expr_ptr(&z_ptr, z_store)
-                    .expect("failed to intern z_ptr")
+                    .ok_or_else(|| anyhow!("failed to intern z_ptr").into())
             }
         }
     }
@@ -671,23 +958,25 @@ impl<'a> Opening<S1> {
         lang: Arc<Lang<S1, Coproc<S1>>>,
     ) -> Result<Proof<'a, S1>, Error> {
         let input = request.input.expr.ptr(s, limit, &lang);
-        let commitment = request.commitment;
-
-        let function_map = committed_expression_store();
-        let function = function_map
-            .get(&commitment)
-            .ok_or(Error::UnknownCommitment)?;
+        let function = load_committed_expression(&request.commitment, s, limit, &lang)?;
 
-        Self::apply_and_prove(
+        let claim = Self::apply_with_predicate(
             s,
             input,
             function,
             limit,
             request.chain,
+            request.input_predicate,
+            &lang,
+        )?;
+        Proof::prove_claim(
+            s,
+            &claim,
+            limit,
             only_use_cached_proofs,
             nova_prover,
             pp,
-            lang,
+            &lang,
         )
     }
 
@@ -699,14 +988,17 @@ impl<'a> Opening<S1> {
         lang: &Lang<S1, Coproc<S1>>,
     ) -> Result<Claim<S1>, Error> {
         let input = request.input.expr.ptr(s, limit, lang);
-        let commitment = request.commitment;
+        let function = load_committed_expression(&request.commitment, s, limit, lang)?;
 
-        let function_map = committed_expression_store();
-        let function = function_map
-            .get(&commitment)
-            .ok_or(Error::UnknownCommitment)?;
-
-        Self::apply(s, input, function, limit, chain, lang)
+        Self::apply_with_predicate(
+            s,
+            input,
+            function,
+            limit,
+            chain,
+            request.input_predicate,
+            lang,
+        )
     }
 
     fn _is_chained(&self) -> bool {
@@ -733,29 +1025,47 @@ impl<'a> Opening<S1> {
         chain: bool,
         lang: &Lang<S1, Coproc<S1>>,
     ) -> Result<Claim<S1>, Error> {
-        let (commitment, expression) =
-            Commitment::construct_with_fun_application(s, &function, input, limit, lang)?;
+        Self::apply_with_predicate(s, input, function, limit, chain, None, lang)
+    }
+
+    /// Like `apply`, but the function is only applied if `input` satisfies
+    /// `predicate`, which is recorded in the claim
+    pub fn apply_with_predicate(
+        s: &mut Store<S1>,
+        input: Ptr<S1>,
+        function: CommittedExpression<S1>,
+        limit: usize,
+        chain: bool,
+        predicate: Option<InputPredicate>,
+        lang: &Lang<S1, Coproc<S1>>,
+    ) -> Result<Claim<S1>, Error> {
+        let (commitment, expression) = Commitment::construct_with_fun_application(
+            s,
+            &function,
+            input,
+            predicate.as_ref(),
+            limit,
+            lang,
+        )?;
         let (public_output, _iterations) = evaluate(s, expression, None, limit, lang)?;
 
         let (new_commitment, output_expr) = if chain {
-            let cons = public_output.expr;
-            let result_expr = s.car(&cons)?;
-            let new_comm = s.cdr(&cons)?;
+            let (result_expr, new_comm) = ptr_match!(s, &public_output.expr, [Ptr<S1>; ..])?;
 
-            let new_secret0 = s.secret(new_comm).expect("secret missing");
-            let new_secret = *s.hash_expr(&new_secret0).expect("hash missing").value();
-
-            let (_, new_fun) = s.open(new_comm).expect("opening missing");
+            let (new_secret, new_fun) = s.open(new_comm).expect("opening missing");
             let new_commitment = Commitment::from_comm(s, &new_comm)?;
 
             s.hydrate_scalar_cache();
 
             let expr = LurkPtr::from_ptr(s, &new_fun);
 
+            // The next function is committed to by the function itself, so
+            // it's never sealed
             let new_function = CommittedExpression::<S1> {
                 expr,
                 secret: Some(new_secret),
                 commitment: Some(new_commitment),
+                seal: None,
             };
 
             let function_map = committed_expression_store();
@@ -786,6 +1096,8 @@ impl<'a> Opening<S1> {
             input: input_string,
             output: output_string,
             status,
+            input_predicate: predicate,
+            seal: function.seal,
         });
 
         Ok(claim)
@@ -844,7 +1156,6 @@ impl<'a> Proof<'a, S1> {
         let reduction_count = nova_prover.reduction_count();
 
         let proof_map = nova_proof_cache(reduction_count);
-        let function_map = committed_expression_store();
 
         let key = claim.proof_key()?.to_base32();
 
@@ -867,21 +1178,42 @@ impl<'a> Proof<'a, S1> {
                 let commitment = o.commitment;
 
                 // In order to prove the opening, we need access to the original function.
-                let function = function_map
-                    .get(&commitment)
-                    .expect("function for commitment missing");
+                let function = load_committed_expression(&commitment, s, limit, lang)?;
 
                 let input = s.read(&o.input).expect("bad expression");
-                let (c, expression) =
-                    Commitment::construct_with_fun_application(s, &function, input, limit, lang)?;
+                let (c, expression) = Commitment::construct_with_fun_application(
+                    s,
+                    &function,
+                    input,
+                    o.input_predicate.as_ref(),
+                    limit,
+                    lang,
+                )?;
 
                 assert_eq!(commitment, c);
                 (expression, empty_sym_env(s))
             }
+            Claim::Creation(c) => {
+                // The secret is only known to the owner of the function
+                let function = load_committed_expression(&c.commitment, s, limit, lang)?;
+
+                let (expression, creation) = Creation::construct(s, &function, lang)?;
+
+                assert_eq!(c, &creation);
+                (expression, empty_sym_env(s))
+            }
         };
 
+        let frames = nova_prover
+            .get_evaluation_frames(expr, env, s, limit, lang)
+            .expect("Nova proof failed");
+        let emitted = frames
+            .iter()
+            .filter_map(|frame| frame.output.maybe_emitted_expression(s))
+            .map(|value| value.fmt_to_string(s, initial_lurk_state()))
+            .collect();
         let (proof, _public_input, _public_output, num_steps) = nova_prover
-            .evaluate_and_prove(pp, expr, env, s, limit, lang.clone())
+            .prove(pp, &frames, s, lang.clone())
             .expect("Nova proof failed");
 
         let proof = Self {
@@ -889,6 +1221,9 @@ impl<'a> Proof<'a, S1> {
             proof,
             num_steps,
             reduction_count: ReductionCount::try_from(reduction_count)?,
+            eval_config: EvalConfig::for_lang(lang.as_ref()),
+            emitted,
+            vk_digest: Some(pp.vk_digest()),
         };
 
         match &claim {
@@ -911,6 +1246,8 @@ impl<'a> Proof<'a, S1> {
                     )));
                 }
             }
+            // Creation claims are only made once the evaluation is checked
+            Claim::Creation(_) => (),
         };
 
         proof.verify(pp, lang).expect("Nova verification failed");
@@ -920,11 +1257,38 @@ impl<'a> Proof<'a, S1> {
         Ok(proof)
     }
 
+    /// The circuit the proof was made for, as far as the proof tells: its
+    /// reduction count and the capabilities of its evaluator. The digest of
+    /// the LEM step function of the configuration is left out, since it's the
+    /// `MultiFrame` circuit that's proven.
+    pub fn circuit_fingerprint(&self) -> String {
+        format!(
+            "rc{}-{}",
+            self.reduction_count.count(),
+            self.eval_config.flags()
+        )
+    }
+
     pub fn verify(
         &self,
         pp: &PublicParams<'_, S1, Coproc<S1>>,
         lang: &Lang<S1, Coproc<S1>>,
     ) -> Result<VerificationResult, Error> {
+        let eval_config = EvalConfig::for_lang(lang);
+        if self.eval_config != eval_config {
+            return Err(Error::VerificationError(format!(
+                "proof was made with evaluation config {}, but the verifier uses {eval_config}",
+                self.eval_config
+            )));
+        }
+        if let Some(vk_digest) = &self.vk_digest {
+            let verifier_digest = pp.vk_digest();
+            if *vk_digest != verifier_digest {
+                return Err(Error::VerificationError(format!(
+                    "proof was made for verifier key {vk_digest}, but the verifier uses {verifier_digest}"
+                )));
+            }
+        }
         let (public_inputs, public_outputs) = self.io_vecs(lang)?;
 
         let claim_iterations_and_num_steps_are_consistent = if let Claim::Evaluation(Evaluation {
@@ -952,17 +1316,38 @@ impl<'a> Proof<'a, S1> {
             true
         };
 
+        // Nova rejects well-formed but invalid proofs with an `Err`
         let verified = claim_iterations_and_num_steps_are_consistent
             && self
                 .proof
                 .verify(pp, self.num_steps, &public_inputs, &public_outputs)
-                .expect("error verifying");
+                .unwrap_or(false);
 
         let result = VerificationResult::new(verified);
 
         Ok(result)
     }
 
+    /// Verifies the proof and then, if it holds, runs `predicate` on its claim,
+    /// e.g. to check the claim is about an expected commitment or has output of
+    /// an expected shape. The predicate explains a rejection with an `Err`.
+    pub fn verify_with_predicate<P>(
+        &self,
+        pp: &PublicParams<'_, S1, Coproc<S1>>,
+        lang: &Lang<S1, Coproc<S1>>,
+        predicate: P,
+    ) -> Result<ClaimVerification, Error>
+    where
+        P: FnOnce(&Claim<S1>) -> Result<(), String>,
+    {
+        let verified = self.verify(pp, lang)?.verified;
+        let claim_check = verified.then(|| predicate(&self.claim));
+        Ok(ClaimVerification {
+            verified,
+            claim_check,
+        })
+    }
+
     pub fn evaluation_io(&self, s: &mut Store<S1>) -> Result<(IO<S1>, IO<S1>), Error> {
         let evaluation = &self.claim.evaluation().expect("expected evaluation claim");
 
@@ -1036,7 +1421,12 @@ impl<'a> Proof<'a, S1> {
         let output = opening.public_output_expression(s);
         let input = s.read(&opening.input).expect("could not read input");
 
-        let expression = opening.commitment.fun_application(s, input);
+        let expression = opening.commitment.fun_application(
+            s,
+            input,
+            opening.input_predicate.as_ref(),
+            opening.seal.as_ref(),
+        )?;
         let outermost = s.intern_cont_outermost();
 
         let input_io = IO::<S1> {
@@ -1063,14 +1453,30 @@ impl<'a> Proof<'a, S1> {
             Claim::Evaluation(_) => self.evaluation_io(s),
             Claim::PtrEvaluation(_) => self.ptr_evaluation_io(s, lang),
             Claim::Opening(_) => self.opening_io(s),
+            Claim::Creation(ref creation) => Ok(creation.io(s)),
         }
     }
 
-    fn io_vecs(&self, lang: &Lang<S1, Coproc<S1>>) -> Result<(Vec<S1>, Vec<S1>), Error> {
+    /// The public inputs and outputs the proof is verified against, as
+    /// derived from its claim and what it emitted
+    pub fn io_vecs(&self, lang: &Lang<S1, Coproc<S1>>) -> Result<(Vec<S1>, Vec<S1>), Error> {
         let s = &mut Store::<S1>::default();
 
-        self.io(s, lang)
-            .map(|(i, o)| (i.to_inputs(s), o.to_inputs(s)))
+        let (i, o) = self.io(s, lang)?;
+        let (mut inputs, mut outputs) = (i.to_inputs(s), o.to_inputs(s));
+        let mut log = empty_emit_log(s);
+        inputs.extend([log.tag().to_field(), *log.value()]);
+        for value in &self.emitted {
+            let value = s
+                .read(value)
+                .map_err(|_| Error::VerificationError("failed to read emitted value".into()))?;
+            let value = s
+                .hash_expr(&value)
+                .ok_or_else(|| Error::VerificationError("failed to hash emitted value".into()))?;
+            log = ZExpr::Cons(value, log).z_ptr(&s.poseidon_cache);
+        }
+        outputs.extend([log.tag().to_field(), *log.value()]);
+        Ok((inputs, outputs))
     }
 }
 
@@ -1080,6 +1486,13 @@ impl VerificationResult {
     }
 }
 
+impl ClaimVerification {
+    /// Whether the proof verified and the predicate accepted its claim
+    pub fn accepted(&self) -> bool {
+        matches!(self.claim_check, Some(Ok(())))
+    }
+}
+
 pub fn evaluate<F: LurkField>(
     store: &mut Store<F>,
     expr: Ptr<F>,
@@ -1092,7 +1505,12 @@ pub fn evaluate<F: LurkField>(
 
     let (io, iterations, _) = evaluator.eval().map_err(Error::EvaluationFailure)?;
 
-    assert!(<lurk::eval::IO<F> as Evaluable<F, Witness<F>, Coproc<F>>>::is_terminal(&io));
+    let status = <lurk::eval::IO<F> as Evaluable<F, Witness<F>, Coproc<F>>>::status(&io);
+    if !status.is_terminal() {
+        return Err(Error::EvaluationFailure(ReductionError::Misc(format!(
+            "evaluation ended with status {status:?}"
+        ))));
+    }
     Ok((io, iterations))
 }
 
@@ -1131,6 +1549,7 @@ mod test {
             expr: LurkPtr::Source(function_source.into()),
             secret: None,
             commitment: None,
+            seal: None,
         };
         assert_json_snapshot!(committed_expression);
 
@@ -1148,6 +1567,7 @@ mod test {
             input,
             commitment: c,
             chain: true,
+            input_predicate: None,
         };
         assert_json_snapshot!(req);
 
@@ -1157,6 +1577,8 @@ mod test {
             status: Status::Error,
             commitment: c,
             new_commitment: None,
+            input_predicate: None,
+            seal: None,
         };
         assert_json_snapshot!(opening);
     }
@@ -1205,6 +1627,7 @@ mod test {
             expr: LurkPtr::Source(function_source.into()),
             secret: None,
             commitment: None,
+            seal: None,
         };
 
         let limit = 1000;
@@ -1255,6 +1678,18 @@ mod test {
 
             proof.verify(&pp, &lang_rc).expect("Failed to verify");
 
+            let check = proof
+                .verify_with_predicate(&pp, &lang_rc, |claim| match claim.opening() {
+                    Some(o) if o.input == *function_input => Ok(()),
+                    _ => Err(format!("expected an opening on {function_input}")),
+                })
+                .expect("Failed to verify");
+            assert!(check.accepted());
+            let check = proof
+                .verify_with_predicate(&pp, &lang_rc, |_| Err("rejected".into()))
+                .expect("Failed to verify");
+            assert!(check.verified && !check.accepted());
+
             let opening = proof.claim.opening().expect("expected opening claim");
 
             match opening.new_commitment {
@@ -1265,6 +1700,115 @@ mod test {
         }
     }
 
+    #[test]
+    fn secrets_are_redacted_but_written_to_function_files() {
+        let value = S1::from(123456789);
+        let function = CommittedExpression::<S1> {
+            expr: LurkPtr::Source("(lambda (x) x)".into()),
+            secret: Some(Secret::new(value)),
+            commitment: None,
+            seal: None,
+        };
+        let debug = format!("{function:?}");
+        assert!(debug.contains("Secret(<redacted>)"));
+        assert!(!debug.contains(&format!("{value:?}")));
+
+        let json = serde_json::to_string(&function).unwrap();
+        assert!(json.contains(&serde_json::to_string(&value).unwrap()));
+        let read: CommittedExpression<S1> = serde_json::from_str(&json).unwrap();
+        assert_eq!(read, function);
+    }
+
+    #[test]
+    fn opening_input_predicate() {
+        let s = &mut Store::<S1>::default();
+        let lang = Lang::new();
+        let function = CommittedExpression::<S1> {
+            expr: LurkPtr::Source("(lambda (x) (cons x x))".into()),
+            secret: Some(Secret::new(S1::from(42))),
+            commitment: None,
+            seal: None,
+        };
+        let mut apply = |input: &str, predicate| {
+            let input = s.read(input).unwrap();
+            Opening::apply_with_predicate(s, input, function.clone(), 1000, false, predicate, &lang)
+        };
+
+        let in_range = Some(InputPredicate::U64Range { min: 1, max: 10 });
+        let claim = apply("5u64", in_range).unwrap();
+        let opening = claim.opening().unwrap();
+        assert_eq!(opening.status, Status::Terminal);
+        assert_eq!(opening.input_predicate, in_range);
+        assert!(apply("20u64", in_range).is_err());
+        assert!(apply("5", in_range).is_err());
+
+        let num = Some(InputPredicate::Tag(InputTag::Num));
+        assert!(apply("5", num).is_ok());
+        assert!(apply("5u64", num).is_err());
+        let cons = Some(InputPredicate::Tag(InputTag::Cons));
+        assert!(apply("'(1 2)", cons).is_ok());
+        assert!(apply("'a", cons).is_err());
+        assert!(apply("'a", None).is_ok());
+    }
+
+    #[test]
+    fn sealed_opening() {
+        let s = &mut Store::<S1>::default();
+        let lang = Lang::new();
+        let seal = Seal::new(["emit"]);
+        let function = |source: &str| CommittedExpression::<S1> {
+            expr: LurkPtr::Source(source.into()),
+            secret: Some(Secret::new(S1::from(42))),
+            commitment: None,
+            seal: Some(seal.clone()),
+        };
+
+        let input = s.num(3);
+        let claim = Opening::apply(
+            s,
+            input,
+            function("(lambda (x) (+ x 1))"),
+            1000,
+            false,
+            &lang,
+        )
+        .unwrap();
+        let opening = claim.opening().unwrap();
+        assert_eq!(opening.status, Status::Terminal);
+        assert_eq!(opening.output, "4");
+        assert_eq!(opening.seal, Some(seal.clone()));
+
+        // The seal changes the commitment
+        let mut unsealed = function("(lambda (x) (+ x 1))");
+        unsealed.seal = None;
+        let claim = Opening::apply(s, input, unsealed, 1000, false, &lang).unwrap();
+        assert_ne!(claim.opening().unwrap().commitment, opening.commitment);
+
+        let leaky = function("(lambda (x) (emit x))");
+        assert!(matches!(
+            Opening::apply(s, input, leaky, 1000, false, &lang),
+            Err(Error::SealError(_))
+        ));
+        let closed_over = function("(let ((f (lambda (y) (emit y)))) (lambda (x) (+ x 1)))");
+        assert!(Opening::apply(s, input, closed_over, 1000, false, &lang).is_err());
+
+        // A stored function is checked again when it's loaded, so one edited
+        // after it was committed to is rejected, whether it breaks its seal or
+        // no longer matches its commitment
+        let mut stored = function("(lambda (x) (+ x 1))");
+        stored.commitment = Some(opening.commitment);
+        stored.check(s, 1000, &lang).unwrap();
+        let mut edited = stored.clone();
+        edited.expr = LurkPtr::Source("(lambda (x) (emit x))".into());
+        assert!(matches!(
+            edited.check(s, 1000, &lang),
+            Err(Error::SealError(_))
+        ));
+        edited.expr = LurkPtr::Source("(lambda (x) (+ x 2))".into());
+        assert!(edited.check(s, 1000, &lang).is_err());
+        assert!(Opening::apply(s, input, edited, 1000, false, &lang).is_err());
+    }
+
     proptest! {
       #[test]
       fn prop_z_bytes(x in any::<ZBytes>()) {
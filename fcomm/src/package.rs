@@ -0,0 +1,204 @@
+//! Program packages, which bundle everything needed to share a committed
+//! function in a single `.lurkpkg` file.
+//!
+//! A package holds the function's source, its expansion (the source as read,
+//! with reader syntax such as `'x` expanded, printed back), its commitment,
+//! the function itself as a `ZStore` payload and, optionally, the proof that
+//! the commitment was created from the source (see `creation`). The secret is
+//! only included on request, since whoever has it can open the commitment.
+//!
+//! `fcomm package` writes a package, `fcomm unpack` stores its function so it
+//! can be opened by commitment and `fcomm verify-package` checks its parts
+//! agree with each other before verifying its creation proof.
+
+use serde::{Deserialize, Serialize};
+
+use lurk::{
+    eval::lang::{Coproc, Lang},
+    secret::Secret,
+    state::initial_lurk_state,
+    store::Store,
+    writer::Write,
+};
+
+use crate::{error::Error, seal::Seal, Claim, Commitment, CommittedExpression, LurkPtr, Proof, S1};
+
+/// The version of the package format written by this release
+pub const PACKAGE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub struct Package {
+    pub version: u32,
+    pub source: String,
+    /// The source as read, printed back
+    pub expansion: String,
+    pub commitment: Commitment<S1>,
+    /// The function the source evaluates to, along with the store data needed
+    /// to intern it
+    pub function: LurkPtr<S1>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seal: Option<Seal>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "lurk::secret::exposed"
+    )]
+    pub secret: Option<Secret<S1>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub creation_proof: Option<Proof<'static, S1>>,
+}
+
+fn package_error<T>(msg: impl Into<String>) -> Result<T, Error> {
+    Err(Error::PackageError(msg.into()))
+}
+
+fn expansion(s: &mut Store<S1>, source: &str) -> Result<String, Error> {
+    let ptr = s
+        .read(source)
+        .map_err(|e| Error::PackageError(format!("can't read the source: {e}")))?;
+    Ok(ptr.fmt_to_string(s, initial_lurk_state()))
+}
+
+impl Package {
+    /// Packages `function`, which must have been committed to from source, as
+    /// `fcomm commit --lurk` does
+    pub fn new(
+        s: &mut Store<S1>,
+        function: &CommittedExpression<S1>,
+        creation_proof: Option<Proof<'static, S1>>,
+        include_secret: bool,
+        limit: usize,
+        lang: &Lang<S1, Coproc<S1>>,
+    ) -> Result<Self, Error> {
+        let LurkPtr::Source(source) = &function.expr else {
+            return package_error("only functions committed from source can be packaged");
+        };
+        let Some(commitment) = function.commitment else {
+            return package_error("the function isn't committed");
+        };
+        let fun_ptr = function.expr_ptr(s, limit, lang)?;
+        let package = Self {
+            version: PACKAGE_VERSION,
+            source: source.clone(),
+            expansion: expansion(s, source)?,
+            commitment,
+            function: LurkPtr::from_ptr(s, &fun_ptr),
+            seal: function.seal.clone(),
+            secret: function.secret.clone().filter(|_| include_secret),
+            creation_proof,
+        };
+        package.check(s, limit, lang)?;
+        Ok(package)
+    }
+
+    /// The packaged function, as `fcomm commit` stores it. It can only be
+    /// opened if the package holds the secret.
+    pub fn committed_expression(&self) -> CommittedExpression<S1> {
+        CommittedExpression {
+            expr: self.function.clone(),
+            secret: self.secret.clone(),
+            commitment: Some(self.commitment),
+            seal: self.seal.clone(),
+        }
+    }
+
+    /// Checks the parts of the package agree with each other: the expansion
+    /// and the function are those of the source, the function respects the
+    /// seal, if there's one, and, when the package holds them, the secret and
+    /// the creation proof's claim are those of the commitment. The creation
+    /// proof itself isn't verified.
+    pub fn check(
+        &self,
+        s: &mut Store<S1>,
+        limit: usize,
+        lang: &Lang<S1, Coproc<S1>>,
+    ) -> Result<(), Error> {
+        if self.version != PACKAGE_VERSION {
+            return package_error(format!("unsupported package version {}", self.version));
+        }
+        if expansion(s, &self.source)? != self.expansion {
+            return package_error("the expansion isn't that of the source");
+        }
+        let source_fun = LurkPtr::Source(self.source.clone()).try_ptr(s, limit, lang)?;
+        let fun = self.committed_expression().expr_ptr(s, limit, lang)?;
+        if s.hash_expr(&source_fun) != s.hash_expr(&fun) {
+            return package_error("the function isn't the one the source evaluates to");
+        }
+        if let Some(seal) = &self.seal {
+            seal.check(s, fun)?;
+        }
+        if let Some(secret) = &self.secret {
+            let committed = self.committed_expression().committed_ptr(s, limit, lang)?;
+            if Commitment::from_ptr_and_secret(s, &committed, secret)? != self.commitment {
+                return package_error("the secret doesn't open the commitment to the function");
+            }
+        }
+        if let Some(proof) = &self.creation_proof {
+            let Claim::Creation(creation) = &proof.claim else {
+                return package_error("the creation proof doesn't prove a creation");
+            };
+            let source = s.read(&self.source).expect("the source was read already");
+            if creation.commitment != self.commitment
+                || s.hash_expr(&source) != Some(creation.source)
+            {
+                return package_error("the creation proof is about another function");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn packages_are_checked() {
+        let s = &mut Store::<S1>::default();
+        let lang = Lang::new();
+        let mut function = CommittedExpression::<S1> {
+            expr: LurkPtr::Source("(lambda (x) (cons x 'x))".into()),
+            secret: Some(Secret::new(S1::from(42))),
+            commitment: None,
+            seal: None,
+        };
+        assert!(Package::new(s, &function, None, true, 1000, &lang).is_err());
+
+        let fun_ptr = function.committed_ptr(s, 1000, &lang).unwrap();
+        let secret = Secret::new(S1::from(42));
+        let commitment = Commitment::from_ptr_and_secret(s, &fun_ptr, &secret).unwrap();
+        function.commitment = Some(commitment);
+        let package = Package::new(s, &function, None, true, 1000, &lang).unwrap();
+        assert!(package.expansion.contains("quote"));
+        assert_eq!(package.committed_expression().secret, Some(secret));
+        let package = Package::new(s, &function, None, false, 1000, &lang).unwrap();
+        assert_eq!(package.committed_expression().secret, None);
+
+        let json = serde_json::to_string(&package).unwrap();
+        let mut package: Package = serde_json::from_str(&json).unwrap();
+        let s = &mut Store::<S1>::default();
+        package.check(s, 1000, &lang).unwrap();
+
+        package.secret = Some(Secret::new(S1::from(43)));
+        assert!(matches!(
+            package.check(s, 1000, &lang),
+            Err(Error::PackageError(_))
+        ));
+        package.secret = None;
+        package.seal = Some(Seal::new(["cons"]));
+        assert!(matches!(
+            package.check(s, 1000, &lang),
+            Err(Error::SealError(_))
+        ));
+        package.seal = None;
+        // a function missing from its `ZStore` is an error rather than a panic
+        let LurkPtr::ZStorePtr(mut function) = package.function.clone() else {
+            panic!("packaged functions are ZStore pointers")
+        };
+        function.z_store = Default::default();
+        package.function = LurkPtr::ZStorePtr(function);
+        assert!(package.check(&mut Store::default(), 1000, &lang).is_err());
+        package.source = "(lambda (x) (cons x 'y))".into();
+        assert!(package.check(s, 1000, &lang).is_err());
+    }
+}
@@ -0,0 +1,195 @@
+//! Trust-on-first-use pinning of verifier keys.
+//!
+//! The first time fcomm verifies a proof of some circuit, it records the digest
+//! of the verifier key it used under the circuit's fingerprint: the reduction
+//! count and evaluation config the proof was made with, along with the
+//! coprocessors of the verifier's `Lang`. Keys are only pinned by proofs that
+//! verify, so a bogus proof can't pin the key it was made for. Later proofs of
+//! the same circuit must be verified with the same key, so public parameters
+//! swapped in the meantime, in the cache for instance, don't go unnoticed.
+//! Whether a different key only warns or fails verification is up to the
+//! `PinPolicy`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use lurk::{
+    eval::lang::{Coproc, Lang},
+    proof::nova::PublicParams,
+};
+
+use crate::{error::Error, file_map::data_dir, Proof, S1};
+
+/// What to do when a proof needs another verifier key than the pinned one
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PinPolicy {
+    /// Neither check nor record keys
+    Off,
+    /// Log a warning and go on verifying
+    #[default]
+    Warn,
+    /// Fail verification
+    Fail,
+}
+
+impl FromStr for PinPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "warn" => Ok(Self::Warn),
+            "fail" => Ok(Self::Fail),
+            _ => Err(format!(
+                "unknown pin policy {s:?}, expected off, warn or fail"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PinPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Off => write!(f, "off"),
+            Self::Warn => write!(f, "warn"),
+            Self::Fail => write!(f, "fail"),
+        }
+    }
+}
+
+/// The verifier keys accepted so far, by circuit fingerprint
+#[derive(Debug)]
+pub struct Pins {
+    path: Utf8PathBuf,
+    keys: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PinsFile {
+    keys: BTreeMap<String, String>,
+}
+
+impl Pins {
+    /// The pins in the `pinned_keys.json` file of the fcomm data directory
+    pub fn load_default() -> Result<Self, Error> {
+        Self::load(&data_dir().join("pinned_keys.json"))
+    }
+
+    /// The pins in the file at `path`, which are none if it doesn't exist yet
+    pub fn load(path: &Utf8Path) -> Result<Self, Error> {
+        let keys = if path.exists() {
+            let json = std::fs::read_to_string(path)?;
+            serde_json::from_str::<PinsFile>(&json)
+                .map_err(|e| Error::PinningError(format!("can't read {path}: {e}")))?
+                .keys
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Self {
+            path: path.to_owned(),
+            keys,
+        })
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = PinsFile {
+            keys: self.keys.clone(),
+        };
+        let json =
+            serde_json::to_string_pretty(&file).map_err(|e| Error::PinningError(e.to_string()))?;
+        std::fs::write(&self.path, json)?;
+        Ok(())
+    }
+
+    /// Applies `policy` if the key pinned for `circuit` isn't `vk_digest`
+    pub fn check(&self, circuit: &str, vk_digest: &str, policy: PinPolicy) -> Result<(), Error> {
+        if policy == PinPolicy::Off {
+            return Ok(());
+        }
+        match self.keys.get(circuit) {
+            Some(pinned) if pinned != vk_digest => {
+                let msg =
+                    format!("{circuit} needs verifier key {vk_digest}, not the pinned {pinned}");
+                match policy {
+                    PinPolicy::Fail => Err(Error::PinningError(msg)),
+                    _ => {
+                        warn!("{msg}");
+                        Ok(())
+                    }
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Pins `vk_digest` for `circuit` if it has no key yet
+    pub fn pin(&mut self, circuit: &str, vk_digest: &str, policy: PinPolicy) -> Result<(), Error> {
+        if policy == PinPolicy::Off || self.keys.contains_key(circuit) {
+            return Ok(());
+        }
+        info!("Pinning verifier key {vk_digest} for circuit {circuit}");
+        self.keys.insert(circuit.to_owned(), vk_digest.to_owned());
+        self.save()
+    }
+
+    /// Verifies `proof` if the key of `pp` passes the check against the one
+    /// pinned for its circuit, and pins the key if there's none yet and the
+    /// proof verified
+    pub fn verify_proof(
+        &mut self,
+        proof: &Proof<'_, S1>,
+        pp: &PublicParams<'_, S1, Coproc<S1>>,
+        lang: &Lang<S1, Coproc<S1>>,
+        policy: PinPolicy,
+    ) -> Result<bool, Error> {
+        let circuit = format!("{}-{}", proof.circuit_fingerprint(), lang.key());
+        let vk_digest = pp.vk_digest();
+        self.check(&circuit, &vk_digest, policy)?;
+        let verified = proof.verify(pp, lang)?.verified;
+        if verified {
+            self.pin(&circuit, &vk_digest, policy)?;
+        }
+        Ok(verified)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::Builder;
+
+    #[test]
+    fn keys_are_pinned_on_first_use() {
+        let tmp_dir = Builder::new().prefix("tmp").tempdir().expect("tmp dir");
+        let path = Utf8PathBuf::from_path_buf(tmp_dir.path().join("pins.json")).unwrap();
+
+        let mut pins = Pins::load(&path).unwrap();
+        pins.pin("rc10", "aaaa", PinPolicy::Off).unwrap();
+        assert!(!path.exists());
+        // checking alone doesn't pin
+        pins.check("rc10", "aaaa", PinPolicy::Fail).unwrap();
+        assert!(!path.exists());
+        pins.pin("rc10", "aaaa", PinPolicy::Fail).unwrap();
+
+        let mut pins = Pins::load(&path).unwrap();
+        pins.check("rc10", "aaaa", PinPolicy::Fail).unwrap();
+        pins.check("rc10", "bbbb", PinPolicy::Warn).unwrap();
+        assert!(matches!(
+            pins.check("rc10", "bbbb", PinPolicy::Fail),
+            Err(Error::PinningError(_))
+        ));
+        // the first key stays pinned
+        pins.pin("rc10", "bbbb", PinPolicy::Fail).unwrap();
+        assert!(pins.check("rc10", "bbbb", PinPolicy::Fail).is_err());
+        pins.check("rc100", "bbbb", PinPolicy::Fail).unwrap();
+        assert_eq!("warn".parse::<PinPolicy>().unwrap(), PinPolicy::Warn);
+    }
+}
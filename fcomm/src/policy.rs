@@ -0,0 +1,202 @@
+//! Verifying proofs of several versions of a circuit.
+//!
+//! A service moving to a new circuit, with another reduction count or
+//! evaluation configuration, has to accept proofs of both the old and the new
+//! one while provers upgrade. A `VerifierPolicy` lists the circuits it accepts,
+//! each with the `Lang` and public parameters to verify its proofs with, and
+//! verifies a proof with the circuit matching the reduction count, evaluation
+//! configuration and verifier key the proof says it was made with. Proofs of
+//! other circuits are rejected with an error.
+//!
+//! Each verification increments the `fcomm_verifications` counter, labeled
+//! with the version of the circuit, or `unknown`, and whether it verified, so
+//! operators can tell when proofs of an old version stop coming in.
+
+use std::sync::Arc;
+
+use tracing::info;
+
+use lurk::{
+    eval::lang::{Coproc, Lang},
+    lem::EvalConfig,
+    proof::nova::PublicParams,
+};
+
+use crate::{error::Error, Proof, ReductionCount, VerificationResult, S1};
+
+/// A circuit accepted by a `VerifierPolicy`, under the name `version`
+pub struct AcceptedCircuit<'a> {
+    pub version: String,
+    pub reduction_count: ReductionCount,
+    pub lang: Arc<Lang<S1, Coproc<S1>>>,
+    pub pp: Arc<PublicParams<'a, S1, Coproc<S1>>>,
+}
+
+impl AcceptedCircuit<'_> {
+    /// The fingerprint of the circuit: its reduction count, the key of its
+    /// `Lang` and the digest of its verifier key, so that circuits built with
+    /// different parameters have different fingerprints
+    pub fn fingerprint(&self) -> String {
+        self.fingerprint_with(&self.pp.vk_digest())
+    }
+
+    fn fingerprint_with(&self, vk_digest: &str) -> String {
+        let rc = self.reduction_count.count();
+        format!("rc{rc}-{}-{vk_digest}", self.lang.key())
+    }
+
+    /// Whether `proof` says it was made for the circuit, whose verifier key
+    /// has digest `vk_digest`. Proofs that don't tell their verifier key are
+    /// matched on their reduction count and evaluation configuration alone.
+    fn made_for(&self, proof: &Proof<'_, S1>, vk_digest: &str) -> bool {
+        proof.reduction_count == self.reduction_count
+            && proof.eval_config == EvalConfig::for_lang(&self.lang)
+            && proof
+                .vk_digest
+                .as_ref()
+                .map_or(true, |digest| digest == vk_digest)
+    }
+}
+
+/// An accepted circuit, along with the digest of its verifier key, which is
+/// only computed once
+struct Accepted<'a> {
+    circuit: AcceptedCircuit<'a>,
+    vk_digest: String,
+    fingerprint: String,
+}
+
+/// The circuits a verifier accepts proofs of
+#[derive(Default)]
+pub struct VerifierPolicy<'a> {
+    circuits: Vec<Accepted<'a>>,
+}
+
+impl<'a> VerifierPolicy<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accepts the proofs of `circuit`, which fails if the policy already
+    /// accepts a circuit with the same fingerprint or version
+    pub fn accept(mut self, circuit: AcceptedCircuit<'a>) -> Result<Self, Error> {
+        let vk_digest = circuit.pp.vk_digest();
+        let fingerprint = circuit.fingerprint_with(&vk_digest);
+        for accepted in &self.circuits {
+            if accepted.fingerprint == fingerprint || accepted.circuit.version == circuit.version {
+                return Err(Error::PolicyError(format!(
+                    "version {} ({fingerprint}) clashes with the accepted version {} ({})",
+                    circuit.version, accepted.circuit.version, accepted.fingerprint
+                )));
+            }
+        }
+        self.circuits.push(Accepted {
+            circuit,
+            vk_digest,
+            fingerprint,
+        });
+        Ok(self)
+    }
+
+    /// The versions of the accepted circuits, in the order they were accepted
+    pub fn versions(&self) -> impl Iterator<Item = &str> {
+        self.circuits
+            .iter()
+            .map(|accepted| accepted.circuit.version.as_str())
+    }
+
+    /// The accepted circuit with fingerprint `fingerprint`, if any
+    pub fn circuit(&self, fingerprint: &str) -> Option<&AcceptedCircuit<'a>> {
+        self.circuits
+            .iter()
+            .find(|accepted| accepted.fingerprint == fingerprint)
+            .map(|accepted| &accepted.circuit)
+    }
+
+    /// Verifies `proof` with the first accepted circuit it was made for,
+    /// returning the version of that circuit along with the result
+    pub fn verify(&self, proof: &Proof<'_, S1>) -> Result<(&str, VerificationResult), Error> {
+        let circuit = self
+            .circuits
+            .iter()
+            .find(|accepted| accepted.circuit.made_for(proof, &accepted.vk_digest))
+            .map(|accepted| &accepted.circuit);
+        let Some(circuit) = circuit else {
+            let fingerprint = proof.circuit_fingerprint();
+            metrics::counter!(
+                "fcomm_verifications",
+                1,
+                "version" => "unknown",
+                "verified" => "false"
+            );
+            let versions = self.versions().collect::<Vec<_>>().join(", ");
+            return Err(Error::PolicyError(format!(
+                "proof of circuit {fingerprint}, none of the accepted versions {versions}"
+            )));
+        };
+        let result = proof.verify(&circuit.pp, &circuit.lang)?;
+        info!(
+            "Proof of version {} verified = {}",
+            circuit.version, result.verified
+        );
+        metrics::counter!(
+            "fcomm_verifications",
+            1,
+            "version" => circuit.version.clone(),
+            "verified" => result.verified.to_string()
+        );
+        Ok((circuit.version.as_str(), result))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::public_param_dir;
+    use lurk::proof::nova::NovaProver;
+    use lurk::public_parameters::public_params;
+    use lurk::store::Store;
+
+    #[test]
+    fn proofs_are_verified_by_their_version() {
+        let lang = Arc::new(Lang::new());
+        let rc = ReductionCount::One;
+        let pp = public_params(rc.count(), true, lang.clone(), &public_param_dir()).unwrap();
+        let circuit = |version: &str, reduction_count| AcceptedCircuit {
+            version: version.into(),
+            reduction_count,
+            lang: lang.clone(),
+            pp: pp.clone(),
+        };
+        let policy = VerifierPolicy::new().accept(circuit("v1", rc)).unwrap();
+        let clash = VerifierPolicy::new().accept(circuit("v1", rc)).unwrap();
+        assert!(matches!(
+            clash.accept(circuit("v3", rc)),
+            Err(Error::PolicyError(_))
+        ));
+        let policy = policy.accept(circuit("v2", ReductionCount::Ten)).unwrap();
+        assert_eq!(policy.versions().collect::<Vec<_>>(), ["v1", "v2"]);
+
+        let s = &mut Store::<S1>::default();
+        let expr = s.read("(+ 1 2)").unwrap();
+        let prover = NovaProver::<S1, Coproc<S1>>::new(rc.count(), (*lang).clone());
+        let mut proof =
+            Proof::eval_and_prove(s, expr, None, 100, false, &prover, &pp, lang.clone()).unwrap();
+        let (version, result) = policy.verify(&proof).unwrap();
+        assert_eq!(version, "v1");
+        assert!(result.verified);
+        let fingerprint = policy.circuits[0].fingerprint.clone();
+        assert!(fingerprint.ends_with(&pp.vk_digest()));
+        assert_eq!(policy.circuit(&fingerprint).unwrap().version, "v1");
+
+        // Proofs that predate the verifier key digest are still accepted
+        let vk_digest = proof.vk_digest.take();
+        assert!(policy.verify(&proof).unwrap().1.verified);
+        proof.vk_digest = Some("other parameters".into());
+        assert!(matches!(policy.verify(&proof), Err(Error::PolicyError(_))));
+        proof.vk_digest = vk_digest;
+
+        proof.reduction_count = ReductionCount::Five;
+        assert!(matches!(policy.verify(&proof), Err(Error::PolicyError(_))));
+    }
+}
@@ -0,0 +1,159 @@
+//! Sealed symbols: builtins or whole packages a committed function is known
+//! never to reference.
+//!
+//! When committing with a seal, as in `fcomm commit --seal emit,eval`, the
+//! function is first walked statically, through its body, its closed
+//! environment and any commitment it can open, and rejected if any sealed
+//! symbol appears. A seal naming a package, like `.lurk.user`, seals every
+//! symbol in it. The committed value is then `(cons <digest> <function>)`,
+//! where the digest is the hash of the list of sealed symbols, and openings
+//! apply the function through
+//!
+//! `(let ((sealed (open <commitment>))) (if (eq (car sealed) <digest>) (cdr sealed) seal-mismatch))`
+//!
+//! Since the opening claim states the seal, its proof fails unless the
+//! commitment was made with that very seal. Note that sealing `eval` is
+//! worthwhile whenever `emit` is sealed, since evaluated data isn't analyzed.
+
+use std::collections::{BTreeSet, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(target_arch = "wasm32"))]
+use proptest_derive::Arbitrary;
+
+use lurk::{
+    field::LurkField,
+    lurk_sym_ptr,
+    ptr::Ptr,
+    state::{initial_lurk_state, user_sym},
+    store::Store,
+    tag::ExprTag,
+    writer::Write,
+    Num,
+};
+
+use crate::error::Error;
+
+/// The symbols a committed function doesn't reference, as they are read
+#[cfg_attr(not(target_arch = "wasm32"), derive(Arbitrary))]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Seal {
+    pub symbols: BTreeSet<String>,
+}
+
+impl Seal {
+    pub fn new<S: Into<String>>(symbols: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            symbols: symbols.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    fn sealed_symbols<F: LurkField>(&self, s: &mut Store<F>) -> Result<Vec<Ptr<F>>, Error> {
+        self.symbols
+            .iter()
+            .map(|name| match s.read(name) {
+                Ok(ptr) if ptr.tag == ExprTag::Sym => Ok(ptr),
+                _ => Err(Error::SealError(format!("{name:?} is not a symbol"))),
+            })
+            .collect()
+    }
+
+    /// The hash of the list of sealed symbols, which is bound into sealed
+    /// commitments
+    pub fn digest<F: LurkField>(&self, s: &mut Store<F>) -> Result<F, Error> {
+        let symbols = self.sealed_symbols(s)?;
+        let list = s.list(&symbols);
+        Ok(*s
+            .hash_expr(&list)
+            .ok_or_else(|| Error::SealError("seal can't be hashed".into()))?
+            .value())
+    }
+
+    /// Fails if `fun` references a sealed symbol, or refers to data that
+    /// can't be analyzed, like an opaque function
+    pub fn check<F: LurkField>(&self, s: &mut Store<F>, fun: Ptr<F>) -> Result<(), Error> {
+        let sealed = self
+            .sealed_symbols(s)?
+            .iter()
+            .map(|ptr| s.fetch_symbol(ptr).expect("symbol was just read"))
+            .collect::<Vec<_>>();
+        let s: &Store<F> = s;
+        let mut visited = HashSet::new();
+        let mut stack = vec![fun];
+        while let Some(ptr) = stack.pop() {
+            if !visited.insert(ptr) {
+                continue;
+            }
+            let unanalyzable = || {
+                Error::SealError(format!(
+                    "can't analyze {}",
+                    ptr.fmt_to_string(s, initial_lurk_state())
+                ))
+            };
+            match ptr.tag {
+                ExprTag::Sym => {
+                    let symbol = s.fetch_symbol(&ptr).ok_or_else(unanalyzable)?;
+                    if let Some(seal) = sealed.iter().find(|seal| symbol.has_parent(seal)) {
+                        return Err(Error::SealError(format!(
+                            "the function references {symbol}, sealed by {seal}"
+                        )));
+                    }
+                }
+                ExprTag::Cons => {
+                    let (car, cdr) = s.car_cdr(&ptr)?;
+                    stack.extend([car, cdr]);
+                }
+                ExprTag::Fun => {
+                    let (arg, body, env) = *s.fetch_fun(&ptr).ok_or_else(unanalyzable)?;
+                    stack.extend([arg, body, env]);
+                }
+                ExprTag::Thunk => {
+                    let thunk = s.fetch_thunk(&ptr).ok_or_else(unanalyzable)?;
+                    stack.push(thunk.value);
+                }
+                ExprTag::Comm => {
+                    let (_, value) = s.open(ptr).ok_or_else(unanalyzable)?;
+                    stack.push(value);
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// `(cons <digest> fun)`, the value a sealed commitment hides
+    pub fn wrap<F: LurkField>(&self, s: &mut Store<F>, fun: Ptr<F>) -> Result<Ptr<F>, Error> {
+        let digest = self.digest(s)?;
+        let digest = s.num(Num::Scalar(digest));
+        Ok(s.cons(digest, fun))
+    }
+
+    /// The expression that opens the sealed commitment `comm` to its function
+    pub fn opened_function<F: LurkField>(
+        &self,
+        s: &mut Store<F>,
+        comm: Ptr<F>,
+    ) -> Result<Ptr<F>, Error> {
+        let digest = self.digest(s)?;
+        let digest = s.num(Num::Scalar(digest));
+        let sealed = s.intern_symbol(&user_sym("sealed"));
+        let mismatch = s.intern_symbol(&user_sym("seal-mismatch"));
+        let open = lurk_sym_ptr!(s, open);
+        let let_ = lurk_sym_ptr!(s, let_);
+        let if_ = lurk_sym_ptr!(s, if_);
+        let eq = lurk_sym_ptr!(s, equal);
+        let car = lurk_sym_ptr!(s, car);
+        let cdr = lurk_sym_ptr!(s, cdr);
+
+        let opened = s.list(&[open, comm]);
+        let binding = s.list(&[sealed, opened]);
+        let bindings = s.list(&[binding]);
+        let committed_digest = s.list(&[car, sealed]);
+        let check = s.list(&[eq, committed_digest, digest]);
+        let fun = s.list(&[cdr, sealed]);
+        let body = s.list(&[if_, check, fun, mismatch]);
+        Ok(s.list(&[let_, bindings, body]))
+    }
+}
@@ -0,0 +1,250 @@
+//! A framed protocol for verifying a stream of proofs within one process.
+//!
+//! Every frame is a 4-byte big-endian length followed by that many bytes of
+//! payload. Requests carry a JSON-encoded `Proof`, the same format written by
+//! `fcomm prove`. Each request is answered, in order, by a frame holding a
+//! JSON-encoded `Verdict`. The session ends when the input is closed or an
+//! empty frame is received.
+
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use camino::Utf8Path;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use lurk::eval::lang::{Coproc, Lang};
+use lurk::public_parameters::public_params;
+
+use crate::{
+    error::Error,
+    pinning::{PinPolicy, Pins},
+    Proof, S1,
+};
+
+/// Frames larger than this are rejected, since there's no way to skip them
+/// without trusting the announced length. Compressed proofs are well below it.
+pub const MAX_FRAME_LEN: usize = 1 << 26;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Verdict {
+    /// Position of the request in the session, starting at zero
+    pub seq: u64,
+    pub verified: bool,
+    /// Set when the request couldn't be verified at all, e.g. because it
+    /// didn't hold a well-formed proof
+    pub error: Option<String>,
+}
+
+/// Reads one frame. Returns `None` on a clean end of input or an empty frame.
+pub fn read_frame<R: Read>(reader: &mut R) -> Result<Option<Vec<u8>>, Error> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => (),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len == 0 {
+        return Ok(None);
+    }
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame of {len} bytes exceeds the limit of {MAX_FRAME_LEN}"),
+        )
+        .into());
+    }
+    // the buffer grows with the bytes actually received, so a bogus length
+    // doesn't allocate up front
+    let mut payload = vec![];
+    reader.take(len as u64).read_to_end(&mut payload)?;
+    if payload.len() < len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated frame").into());
+    }
+    Ok(Some(payload))
+}
+
+pub fn write_frame<W: Write>(writer: &mut W, payload: &[u8]) -> Result<(), Error> {
+    let len = u32::try_from(payload.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "frame too large"))?;
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(payload)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn verify_frame(
+    payload: &[u8],
+    pins: &mut Pins,
+    params_dir: &Utf8Path,
+    pin_policy: PinPolicy,
+    lang: &Lang<S1, Coproc<S1>>,
+) -> Result<bool, String> {
+    let proof: Proof<'_, S1> = serde_json::from_slice(payload).map_err(|e| e.to_string())?;
+    // parameters are cached in memory after the first request for each
+    // reduction count
+    let pp = public_params(
+        proof.reduction_count.count(),
+        true,
+        Arc::new(lang.clone()),
+        params_dir,
+    )
+    .map_err(|e| e.to_string())?;
+    pins.verify_proof(&proof, &pp, lang, pin_policy)
+        .map_err(|e| e.to_string())
+}
+
+/// Answers verification requests from `reader` on `writer` until the session
+/// ends, returning the number of requests served. Verifier keys are checked
+/// against `pins` and pinned once a proof verifies, as with `fcomm verify`,
+/// and public parameters are taken from the cache in `params_dir`. Malformed
+/// and invalid proofs are reported in their verdict; only I/O and framing
+/// errors end the session early.
+pub fn serve<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    pins: &mut Pins,
+    params_dir: &Utf8Path,
+    pin_policy: PinPolicy,
+    lang: &Lang<S1, Coproc<S1>>,
+) -> Result<u64, Error> {
+    let mut seq = 0;
+    while let Some(payload) = read_frame(reader)? {
+        let verdict = match verify_frame(&payload, pins, params_dir, pin_policy, lang) {
+            Ok(verified) => Verdict {
+                seq,
+                verified,
+                error: None,
+            },
+            Err(e) => Verdict {
+                seq,
+                verified: false,
+                error: Some(e),
+            },
+        };
+        info!("Request {seq}: verified = {}", verdict.verified);
+        let response = serde_json::to_vec(&verdict).expect("verdicts are serializable");
+        write_frame(writer, &response)?;
+        seq += 1;
+    }
+    Ok(seq)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Claim, ReductionCount};
+    use lurk::{proof::nova::NovaProver, store::Store};
+    use tempfile::Builder;
+
+    fn frames(payloads: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = vec![];
+        for payload in payloads {
+            write_frame(&mut bytes, payload).unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn frame_roundtrip() {
+        let bytes = frames(&[b"abc", b"de"]);
+        let mut reader = bytes.as_slice();
+        assert_eq!(read_frame(&mut reader).unwrap(), Some(b"abc".to_vec()));
+        assert_eq!(read_frame(&mut reader).unwrap(), Some(b"de".to_vec()));
+        assert_eq!(read_frame(&mut reader).unwrap(), None);
+
+        // an empty frame closes the session even if more input follows
+        let bytes = frames(&[b"", b"abc"]);
+        assert_eq!(read_frame(&mut bytes.as_slice()).unwrap(), None);
+
+        // a truncated payload is an error rather than the end of the session
+        let bytes = frames(&[b"abc"]);
+        assert!(read_frame(&mut &bytes[..5]).is_err());
+
+        // and so is a frame announced larger than the limit
+        let mut bytes = (MAX_FRAME_LEN as u32 + 1).to_be_bytes().to_vec();
+        bytes.extend(b"abc");
+        assert!(read_frame(&mut bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn malformed_proofs_get_a_verdict() {
+        let tmp_dir = Builder::new().prefix("tmp").tempdir().expect("tmp dir");
+        let tmp_dir = Utf8Path::from_path(tmp_dir.path()).unwrap();
+        let mut pins = Pins::load(&tmp_dir.join("pins.json")).unwrap();
+        let input = frames(&[b"not a proof", b"{}"]);
+        let mut output = vec![];
+        let lang = Lang::new();
+        let served = serve(
+            &mut input.as_slice(),
+            &mut output,
+            &mut pins,
+            &tmp_dir.join("public_params"),
+            PinPolicy::Off,
+            &lang,
+        )
+        .unwrap();
+        assert_eq!(served, 2);
+
+        let mut reader = output.as_slice();
+        for seq in 0..2 {
+            let payload = read_frame(&mut reader).unwrap().unwrap();
+            let verdict: Verdict = serde_json::from_slice(&payload).unwrap();
+            assert_eq!(verdict.seq, seq);
+            assert!(!verdict.verified);
+            assert!(verdict.error.is_some());
+        }
+        assert_eq!(read_frame(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn tampered_proofs_get_a_verdict() {
+        let tmp_dir = Builder::new().prefix("tmp").tempdir().expect("tmp dir");
+        let tmp_dir = Utf8Path::from_path(tmp_dir.path()).unwrap();
+        // proofs are cached in the data directory
+        std::env::set_var("FCOMM_DATA_PATH", tmp_dir.join("fcomm_data"));
+        let params_dir = tmp_dir.join("public_params");
+        let lang = Lang::new();
+        let lang_rc = Arc::new(lang.clone());
+        let rc = ReductionCount::One;
+        let pp = public_params(rc.count(), true, lang_rc.clone(), &params_dir).unwrap();
+        let prover = NovaProver::new(rc.count(), lang.clone());
+        let s = &mut Store::<S1>::default();
+        let expr = s.read("(+ 1 2)").unwrap();
+        let proof =
+            Proof::eval_and_prove(s, expr, None, 100, false, &prover, &pp, lang_rc).unwrap();
+        let valid = serde_json::to_vec(&proof).unwrap();
+        // a well-formed proof of another output
+        let mut tampered: serde_json::Value = serde_json::from_slice(&valid).unwrap();
+        let Claim::Evaluation(evaluation) = &proof.claim else {
+            panic!("expected an evaluation claim")
+        };
+        assert_eq!(evaluation.expr_out, "3");
+        tampered["claim"]["Evaluation"]["expr_out"] = "4".into();
+        let tampered = serde_json::to_vec(&tampered).unwrap();
+
+        let input = frames(&[&valid, &tampered]);
+        let mut output = vec![];
+        let mut pins = Pins::load(&tmp_dir.join("pins.json")).unwrap();
+        let policy = PinPolicy::Fail;
+        let served = serve(
+            &mut input.as_slice(),
+            &mut output,
+            &mut pins,
+            &params_dir,
+            policy,
+            &lang,
+        )
+        .unwrap();
+        assert_eq!(served, 2);
+
+        let mut reader = output.as_slice();
+        let verdicts: Vec<Verdict> = (0..2)
+            .map(|_| serde_json::from_slice(&read_frame(&mut reader).unwrap().unwrap()).unwrap())
+            .collect();
+        assert!(verdicts[0].verified);
+        assert!(!verdicts[1].verified);
+        assert_eq!(verdicts[1].error, None);
+    }
+}
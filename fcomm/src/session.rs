@@ -0,0 +1,245 @@
+//! Claims over a session: a sequence of evaluations where each one starts in
+//! the environment the previous ones left, as when a user interacts with a
+//! REPL.
+//!
+//! A session is proven as a single evaluation, of a program running its steps
+//! in order and returning the list of their values. A step named `x` runs as
+//! `(let ((x <step>)) (cons x <rest>))`, binding its value for the steps after
+//! it as `!(def x <step>)` would, and an unnamed step as `(cons <step> <rest>)`.
+//! The whole session thus gets one folded proof, whose claim is checked to be
+//! the evaluation of that program.
+//!
+//! Sessions are proven whole: a claim is made once all its steps are known,
+//! and a session extended with more steps is another claim, proven from
+//! scratch. Proofs aren't extended with the steps of a session that goes on.
+
+use std::sync::Arc;
+
+use abomonation::Abomonation;
+use nova::traits::Group;
+#[cfg(not(target_arch = "wasm32"))]
+use proptest_derive::Arbitrary;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use lurk::{
+    eval::{
+        lang::{Coproc, Lang},
+        Status,
+    },
+    field::LurkField,
+    lurk_sym_ptr,
+    proof::nova::{CurveCycleEquipped, NovaProver, PublicParams, G1, G2},
+    ptr::Ptr,
+    store::Store,
+    tag::ExprTag,
+    z_ptr::ZExprPtr,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use lurk_macros::serde_test;
+
+#[allow(unused_imports)] // this is used in the serde_test macro
+#[cfg(not(target_arch = "wasm32"))]
+use lurk::z_data;
+
+use crate::{error::Error, Claim, LurkPtr, Proof, VerificationResult, S1};
+
+/// A step of a session
+#[cfg_attr(not(target_arch = "wasm32"), derive(Arbitrary))]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SessionStep {
+    /// The source of the expression the step evaluates
+    pub expr: String,
+    /// The symbol the step's value is bound to in the steps after it
+    pub name: Option<String>,
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), derive(Arbitrary))]
+#[cfg_attr(not(target_arch = "wasm32"), proptest(no_bound))]
+#[cfg_attr(not(target_arch = "wasm32"), serde_test(types(S1), zdata(true)))]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SessionClaim<F: LurkField> {
+    /// The environment the session starts in
+    pub env: LurkPtr<F>,
+    /// The steps of the session, in order
+    pub steps: Vec<SessionStep>,
+}
+
+fn z_ptr<F: LurkField>(ptr: &LurkPtr<F>) -> Result<ZExprPtr<F>, Error> {
+    match ptr {
+        LurkPtr::Source(source) => Ok(ZExprPtr::<F>::from_lurk_str(source)?),
+        LurkPtr::ZStorePtr(zsp) => Ok(zsp.z_ptr),
+    }
+}
+
+/// Reads the expression of `step` and the symbol it's bound to
+fn read_step<F: LurkField>(
+    s: &mut Store<F>,
+    step: &SessionStep,
+) -> Result<(Ptr<F>, Option<Ptr<F>>), Error> {
+    let bad_step = |what| Error::VerificationError(format!("bad session step {what}"));
+    let expr = s
+        .read(&step.expr)
+        .map_err(|e| bad_step(format!("`{}`: {e}", step.expr)))?;
+    let name = match &step.name {
+        None => None,
+        Some(name) => match s.read(name) {
+            Ok(sym) if sym.tag == ExprTag::Sym => Some(sym),
+            _ => return Err(bad_step(format!("name `{name}`: not a symbol"))),
+        },
+    };
+    Ok((expr, name))
+}
+
+impl<F: LurkField> SessionClaim<F> {
+    /// The claim over the session of `steps`, run in `env`. Each step's
+    /// expression must be readable and its name, if any, a symbol.
+    pub fn new(env: LurkPtr<F>, steps: Vec<SessionStep>) -> Result<Self, Error> {
+        let s = &mut Store::<F>::default();
+        for step in &steps {
+            read_step(s, step)?;
+        }
+        Ok(Self { env, steps })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// The program the session is proven as, returning the list of the values
+    /// of its steps
+    pub fn program(&self, s: &mut Store<F>) -> Result<Ptr<F>, Error> {
+        let mut program = lurk_sym_ptr!(s, nil);
+        for step in self.steps.iter().rev() {
+            let (expr, name) = read_step(s, step)?;
+            let cons = lurk_sym_ptr!(s, cons);
+            program = match name {
+                None => s.list(&[cons, expr, program]),
+                Some(name) => {
+                    let binding = s.list(&[name, expr]);
+                    let bindings = s.list(&[binding]);
+                    let body = s.list(&[cons, name, program]);
+                    let let_ = lurk_sym_ptr!(s, let_);
+                    s.list(&[let_, bindings, body])
+                }
+            };
+        }
+        Ok(program)
+    }
+}
+
+impl<F: LurkField + Serialize + DeserializeOwned> SessionClaim<F> {
+    /// The environment the session starts in
+    pub fn start_env(&self, s: &mut Store<F>, lang: &Lang<F, Coproc<F>>) -> Ptr<F> {
+        // no limit is needed, since the environment is never evaluated
+        self.env.ptr(s, 0, lang)
+    }
+}
+
+/// A proof of a `SessionClaim`: a single proof of the evaluation of its program
+#[derive(Serialize, Deserialize)]
+pub struct SessionProof<'a, F: CurveCycleEquipped>
+where
+    <<G1<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+    <<G2<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+{
+    pub claim: SessionClaim<F>,
+    pub proof: Proof<'a, F>,
+}
+
+impl<'a> SessionProof<'a, S1> {
+    /// Evaluates the steps of `claim` and proves them with a single folded
+    /// proof
+    #[allow(clippy::too_many_arguments)]
+    pub fn prove(
+        claim: SessionClaim<S1>,
+        s: &'a mut Store<S1>,
+        limit: usize,
+        only_use_cached_proofs: bool,
+        nova_prover: &'a NovaProver<S1, Coproc<S1>>,
+        pp: &'a PublicParams<'_, S1, Coproc<S1>>,
+        lang: Arc<Lang<S1, Coproc<S1>>>,
+    ) -> Result<Self, Error> {
+        let program = claim.program(s)?;
+        let env = claim.start_env(s, &lang);
+        let proof = Proof::eval_and_prove(
+            s,
+            program,
+            Some(env),
+            limit,
+            only_use_cached_proofs,
+            nova_prover,
+            pp,
+            lang,
+        )?;
+        Ok(Self { claim, proof })
+    }
+
+    /// Verifies the proof and that it's of the complete evaluation of the
+    /// session's program in the session's environment
+    pub fn verify(
+        &self,
+        pp: &PublicParams<'_, S1, Coproc<S1>>,
+        lang: &Lang<S1, Coproc<S1>>,
+    ) -> Result<VerificationResult, Error> {
+        let Claim::PtrEvaluation(evaluation) = &self.proof.claim else {
+            return Ok(VerificationResult::new(false));
+        };
+        let s = &mut Store::default();
+        let program = self.claim.program(s)?;
+        let env = self.claim.start_env(s, lang);
+        let missing = || Error::VerificationError("session program can't be hashed".into());
+        let program = s.hash_expr(&program).ok_or_else(missing)?;
+        let env = s.hash_expr(&env).ok_or_else(missing)?;
+        if evaluation.status != Status::Terminal
+            || z_ptr(&evaluation.expr)? != program
+            || z_ptr(&evaluation.env)? != env
+        {
+            return Ok(VerificationResult::new(false));
+        }
+        self.proof.verify(pp, lang)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::evaluate;
+
+    fn step(expr: &str, name: Option<&str>) -> SessionStep {
+        SessionStep {
+            expr: expr.into(),
+            name: name.map(Into::into),
+        }
+    }
+
+    #[test]
+    fn session_steps_see_earlier_bindings() {
+        let steps = vec![
+            step("1", Some("x")),
+            step("(+ x 1)", None),
+            step("(* x 10)", Some("y")),
+            step("(+ x y)", None),
+        ];
+        let session = SessionClaim::<S1>::new(LurkPtr::default(), steps.clone()).unwrap();
+        assert_eq!(session.len(), 4);
+
+        // names must be symbols
+        for bad in [step("2", Some("3")), step("(+ 1", None)] {
+            let mut steps = steps.clone();
+            steps.push(bad);
+            assert!(SessionClaim::<S1>::new(LurkPtr::default(), steps).is_err());
+        }
+
+        let s = &mut Store::<S1>::default();
+        let lang = Lang::new();
+        let program = session.program(s).unwrap();
+        let env = session.start_env(s, &lang);
+        let (output, _) = evaluate(s, program, Some(env), 1000, &lang).unwrap();
+        let values = s.read("(1 2 10 11)").unwrap();
+        assert_eq!(s.hash_expr(&output.expr), s.hash_expr(&values));
+    }
+}
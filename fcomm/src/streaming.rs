@@ -0,0 +1,227 @@
+//! Commitments to payloads too large to hold as a single Lurk string.
+//!
+//! A `CommitmentBuilder` takes its payload a piece at a time and interns and
+//! hashes it in chunks as the pieces arrive, so the work is spread over the
+//! input instead of happening all at once when the commitment is made. The
+//! committed payload is the list of chunk strings, in order: opening the
+//! commitment and concatenating the chunks gives back the original text.
+
+use std::io::{self, Read};
+use std::ops::ControlFlow;
+
+use rand::rngs::OsRng;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use lurk::{field::LurkField, ptr::Ptr, secret::Secret, store::Store};
+
+use crate::{error::Error, Commitment};
+
+/// The default number of characters per chunk
+pub const DEFAULT_CHUNK_SIZE: usize = 1 << 16;
+
+/// How far a `CommitmentBuilder` has got, reported after each chunk
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Progress {
+    /// The number of chunks interned and hashed
+    pub chunks: usize,
+    /// The number of bytes of payload consumed, including those still
+    /// waiting to fill a chunk
+    pub bytes: usize,
+}
+
+type ProgressFn<'a> = Box<dyn FnMut(Progress) -> ControlFlow<()> + 'a>;
+
+pub struct CommitmentBuilder<'a, F: LurkField> {
+    store: &'a mut Store<F>,
+    chunk_size: usize,
+    chunks: Vec<Ptr<F>>,
+    pending: String,
+    pending_chars: usize,
+    bytes: usize,
+    on_progress: Option<ProgressFn<'a>>,
+}
+
+impl<'a, F: LurkField + Serialize + DeserializeOwned> CommitmentBuilder<'a, F> {
+    pub fn new(store: &'a mut Store<F>) -> Self {
+        Self {
+            store,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            chunks: vec![],
+            pending: String::new(),
+            pending_chars: 0,
+            bytes: 0,
+            on_progress: None,
+        }
+    }
+
+    /// Sets the number of characters per chunk. Since the chunks are part of
+    /// the committed payload, the same text committed with different chunk
+    /// sizes gives different commitments.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunks can't be empty");
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Calls `f` after each chunk. Returning `ControlFlow::Break` cancels the
+    /// commitment, making the call that fed the chunk fail with
+    /// `Error::Cancelled`.
+    pub fn on_progress<P>(mut self, f: P) -> Self
+    where
+        P: FnMut(Progress) -> ControlFlow<()> + 'a,
+    {
+        self.on_progress = Some(Box::new(f));
+        self
+    }
+
+    pub fn progress(&self) -> Progress {
+        Progress {
+            chunks: self.chunks.len(),
+            bytes: self.bytes,
+        }
+    }
+
+    fn flush_chunk(&mut self) -> Result<(), Error> {
+        let chunk = std::mem::take(&mut self.pending);
+        self.pending_chars = 0;
+        let ptr = self.store.intern_string(&chunk);
+        // hashing now fills the store's cache, leaving little to do at the end
+        self.store.hash_expr(&ptr);
+        self.chunks.push(ptr);
+
+        let progress = self.progress();
+        if let Some(f) = self.on_progress.as_mut() {
+            if f(progress).is_break() {
+                return Err(Error::Cancelled);
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends `text` to the payload
+    pub fn push_str(&mut self, text: &str) -> Result<(), Error> {
+        for c in text.chars() {
+            self.pending.push(c);
+            self.pending_chars += 1;
+            self.bytes += c.len_utf8();
+            if self.pending_chars == self.chunk_size {
+                self.flush_chunk()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends everything `reader` yields to the payload, which must be UTF-8
+    pub fn read_from<R: Read>(&mut self, reader: &mut R) -> Result<(), Error> {
+        let mut buf = vec![0u8; self.chunk_size.max(4)];
+        // bytes of a character split across reads
+        let mut partial = vec![];
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            partial.extend_from_slice(&buf[..n]);
+            let valid = match std::str::from_utf8(&partial) {
+                Ok(text) => text.len(),
+                Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e).into()),
+            };
+            let rest = partial.split_off(valid);
+            let text = std::str::from_utf8(&partial).expect("checked above");
+            self.push_str(text)?;
+            partial = rest;
+        }
+        if !partial.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "payload ends within a character",
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Interns the last chunk and the list of chunks, returning the payload
+    fn payload(mut self) -> Result<(Ptr<F>, &'a mut Store<F>), Error> {
+        if !self.pending.is_empty() {
+            self.flush_chunk()?;
+        }
+        let payload = self.store.list(&self.chunks);
+        Ok((payload, self.store))
+    }
+
+    /// Commits to the payload with `secret` as its nonce, returning the
+    /// commitment along with the payload
+    pub fn finish_with_nonce(self, secret: &Secret<F>) -> Result<(Commitment<F>, Ptr<F>), Error> {
+        let (payload, store) = self.payload()?;
+        let commitment = Commitment::from_ptr_and_secret(store, &payload, secret)?;
+        Ok((commitment, payload))
+    }
+
+    /// Commits to the payload with a random nonce, returning the commitment,
+    /// the payload and the nonce, which is zeroized when dropped
+    pub fn finish_with_hiding(self) -> Result<(Commitment<F>, Ptr<F>, Secret<F>), Error> {
+        let secret = Secret::random(OsRng);
+        let (commitment, payload) = self.finish_with_nonce(&secret)?;
+        Ok((commitment, payload, secret))
+    }
+}
+
+impl<F: LurkField + Serialize + DeserializeOwned> Commitment<F> {
+    /// Starts a commitment whose payload is fed incrementally
+    pub fn streaming(s: &mut Store<F>) -> CommitmentBuilder<'_, F> {
+        CommitmentBuilder::new(s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::S1;
+
+    #[test]
+    fn streamed_payload_is_list_of_chunks() {
+        let s = &mut Store::<S1>::default();
+        let mut progress = vec![];
+        let mut builder = Commitment::streaming(s).chunk_size(4).on_progress(|p| {
+            progress.push(p);
+            ControlFlow::Continue(())
+        });
+        builder.push_str("hello ").unwrap();
+        builder.read_from(&mut "wörld!".as_bytes()).unwrap();
+        let secret = Secret::new(S1::from(7));
+        let (commitment, payload) = builder.finish_with_nonce(&secret).unwrap();
+        assert_eq!(progress.len(), 3);
+        assert_eq!(progress[2].bytes, "hello wörld!".len());
+
+        let chunks = ["hell", "o wö", "rld!"].map(|c| s.str(c));
+        assert_eq!(payload, s.list(&chunks));
+        let expected = Commitment::from_ptr_and_secret(s, &payload, &secret).unwrap();
+        assert_eq!(commitment, expected);
+    }
+
+    #[test]
+    fn streaming_can_be_cancelled() {
+        let s = &mut Store::<S1>::default();
+        let mut builder = Commitment::streaming(s).chunk_size(2).on_progress(|p| {
+            if p.chunks < 2 {
+                ControlFlow::Continue(())
+            } else {
+                ControlFlow::Break(())
+            }
+        });
+        assert!(builder.push_str("ab").is_ok());
+        assert!(matches!(builder.push_str("cd"), Err(Error::Cancelled)));
+    }
+
+    #[test]
+    fn invalid_utf8_is_rejected() {
+        let s = &mut Store::<S1>::default();
+        let mut builder = Commitment::streaming(s);
+        assert!(builder.read_from(&mut &[0x61, 0xff][..]).is_err());
+        let mut builder = Commitment::streaming(s);
+        assert!(builder.read_from(&mut &[0x61, 0xc3][..]).is_err());
+    }
+}
@@ -0,0 +1,202 @@
+//! Encryption at rest of the data directory's secret-bearing files.
+//!
+//! The committed expressions `fcomm` keeps in order to open its commitments
+//! hold the commitments' secrets, which shouldn't lie around in plaintext on a
+//! shared proving machine. `fcomm lock` encrypts them in place with
+//! XChaCha20-Poly1305, under a key derived with Argon2id from a passphrase or
+//! from the contents of a keyfile, and `fcomm unlock` decrypts them back.
+//!
+//! The key is taken from `FCOMM_KEYFILE`, the path of a keyfile, or else from
+//! `FCOMM_PASSPHRASE`. While one of them is set, committed expressions are
+//! written encrypted, and locked files are decrypted whenever they're read, so
+//! the other commands work the same on a locked data directory.
+//!
+//! A locked file is `MAGIC`, the salt of the key derivation, the nonce and the
+//! ciphertext, whose tag authenticates the header as well.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use argon2::Argon2;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    Key, XChaCha20Poly1305, XNonce,
+};
+use rand::{rngs::OsRng, RngCore};
+use zeroize::Zeroizing;
+
+use crate::error::Error;
+
+/// The start of every locked file
+pub const MAGIC: &[u8] = b"fcomm-locked-v1\n";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = MAGIC.len() + SALT_LEN + NONCE_LEN;
+
+/// Where the key of locked files comes from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeySource {
+    Passphrase(String),
+    Keyfile(PathBuf),
+}
+
+impl KeySource {
+    /// The key configured by `FCOMM_KEYFILE` or `FCOMM_PASSPHRASE`, if any
+    pub fn from_env() -> Option<Self> {
+        if let Some(path) = std::env::var_os("FCOMM_KEYFILE") {
+            return Some(Self::Keyfile(path.into()));
+        }
+        std::env::var("FCOMM_PASSPHRASE").ok().map(Self::Passphrase)
+    }
+
+    /// Derives the key for `salt`, wiping the key material and the raw key
+    /// once the cipher holds it
+    fn cipher(&self, salt: &[u8]) -> Result<XChaCha20Poly1305, Error> {
+        let material = Zeroizing::new(match self {
+            Self::Passphrase(passphrase) => passphrase.as_bytes().to_vec(),
+            Self::Keyfile(path) => fs::read(path)?,
+        });
+        if material.is_empty() {
+            return Err(Error::VaultError(
+                "the passphrase or keyfile is empty".into(),
+            ));
+        }
+        let mut key = Zeroizing::new([0; 32]);
+        Argon2::default()
+            .hash_password_into(&material, salt, key.as_mut())
+            .map_err(|e| Error::VaultError(format!("can't derive the key: {e}")))?;
+        Ok(XChaCha20Poly1305::new(Key::from_slice(key.as_ref())))
+    }
+}
+
+/// Whether `bytes` are the contents of a locked file
+pub fn is_locked(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Encrypts `plaintext` under a fresh salt and nonce
+pub fn lock(plaintext: &[u8], source: &KeySource) -> Result<Vec<u8>, Error> {
+    let mut header = MAGIC.to_vec();
+    header.resize(HEADER_LEN, 0);
+    OsRng.fill_bytes(&mut header[MAGIC.len()..]);
+    let (salt, nonce) = header[MAGIC.len()..].split_at(SALT_LEN);
+    let payload = Payload {
+        msg: plaintext,
+        aad: &header,
+    };
+    let ciphertext = source
+        .cipher(salt)?
+        .encrypt(XNonce::from_slice(nonce), payload)
+        .map_err(|_| Error::VaultError("encryption failed".into()))?;
+    header.extend(ciphertext);
+    Ok(header)
+}
+
+/// Decrypts the contents of a locked file
+pub fn unlock(bytes: &[u8], source: &KeySource) -> Result<Vec<u8>, Error> {
+    if !is_locked(bytes) || bytes.len() < HEADER_LEN {
+        return Err(Error::VaultError("not a locked file".into()));
+    }
+    let (header, ciphertext) = bytes.split_at(HEADER_LEN);
+    let (salt, nonce) = header[MAGIC.len()..].split_at(SALT_LEN);
+    let payload = Payload {
+        msg: ciphertext,
+        aad: header,
+    };
+    source
+        .cipher(salt)?
+        .decrypt(XNonce::from_slice(nonce), payload)
+        .map_err(|_| Error::VaultError("wrong key, or the file was tampered with".into()))
+}
+
+/// Locks the files of `dir` that aren't locked yet, returning how many it
+/// locked. Every file gets its own salt, so Argon2id runs once per file.
+pub fn lock_dir(dir: &Path, source: &KeySource) -> Result<usize, Error> {
+    rewrite_dir(dir, |bytes| {
+        (!is_locked(bytes)).then(|| lock(bytes, source))
+    })
+}
+
+/// Unlocks the locked files of `dir`, returning how many it unlocked. Nothing
+/// is written unless all of them can be decrypted. Every file has its own
+/// salt, so Argon2id runs once per file.
+pub fn unlock_dir(dir: &Path, source: &KeySource) -> Result<usize, Error> {
+    rewrite_dir(dir, |bytes| is_locked(bytes).then(|| unlock(bytes, source)))
+}
+
+/// Replaces the files of `dir` for which `rewrite` returns new contents, after
+/// computing all of them. Each file is replaced by renaming a temporary file
+/// over it, so an interruption leaves it either old or new.
+fn rewrite_dir(
+    dir: &Path,
+    rewrite: impl Fn(&[u8]) -> Option<Result<Vec<u8>, Error>>,
+) -> Result<usize, Error> {
+    if !dir.exists() {
+        return Ok(0);
+    }
+    let mut rewritten = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        if let Some(contents) = rewrite(&fs::read(&path)?) {
+            rewritten.push((path, contents?));
+        }
+    }
+    for (path, contents) in &rewritten {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, contents)?;
+        fs::rename(&tmp, path)?;
+    }
+    Ok(rewritten.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locked_files_are_unlocked_with_their_key_only() {
+        let key = KeySource::Passphrase("correct horse".into());
+        let locked = lock(b"secret", &key).unwrap();
+        assert!(is_locked(&locked));
+        assert!(!locked.windows(6).any(|window| window == b"secret"));
+        assert_eq!(unlock(&locked, &key).unwrap(), b"secret");
+        // fresh salts and nonces
+        assert_ne!(lock(b"secret", &key).unwrap(), locked);
+
+        let wrong = KeySource::Passphrase("battery staple".into());
+        assert!(unlock(&locked, &wrong).is_err());
+        let mut tampered = locked.clone();
+        tampered[MAGIC.len()] ^= 1;
+        assert!(unlock(&tampered, &key).is_err());
+        assert!(unlock(b"secret", &key).is_err());
+    }
+
+    #[test]
+    fn directories_are_locked_and_unlocked() {
+        let dir = tempfile::tempdir().unwrap();
+        let keyfile = dir.path().join("key");
+        fs::write(&keyfile, [7; 32]).unwrap();
+        let key = KeySource::Keyfile(keyfile);
+        let data = dir.path().join("data");
+        fs::create_dir(&data).unwrap();
+        fs::write(data.join("a"), b"first").unwrap();
+        fs::write(data.join("b"), b"second").unwrap();
+
+        assert_eq!(lock_dir(&data, &key).unwrap(), 2);
+        assert!(is_locked(&fs::read(data.join("a")).unwrap()));
+        // already locked
+        assert_eq!(lock_dir(&data, &key).unwrap(), 0);
+
+        let wrong = KeySource::Passphrase("not the keyfile".into());
+        assert!(unlock_dir(&data, &wrong).is_err());
+        assert!(is_locked(&fs::read(data.join("b")).unwrap()));
+
+        assert_eq!(unlock_dir(&data, &key).unwrap(), 2);
+        assert_eq!(fs::read(data.join("a")).unwrap(), b"first");
+        assert_eq!(fs::read(data.join("b")).unwrap(), b"second");
+        assert_eq!(fs::read_dir(&data).unwrap().count(), 2);
+    }
+}
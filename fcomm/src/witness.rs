@@ -0,0 +1,155 @@
+//! Named external inputs, supplied when an expression is evaluated or proved.
+//!
+//! A program refers to an input by name, as in `(+ (witness "price") 1)`, and
+//! its value comes from a witness file mapping names to `LurkPtr`s, given
+//! either as Lurk source or as a `ZStore` pointer:
+//!
+//! ```json
+//! { "price": { "Source": "100" } }
+//! ```
+//!
+//! Before evaluation each value is hidden behind a fresh commitment, and every
+//! `(witness "name")` is replaced by `(open (comm <commitment>))`. The claim
+//! therefore carries the commitments and not the data: the proof can be
+//! verified by anyone, only the prover knows what was committed to, and the
+//! same program can be proved against another dataset by supplying another
+//! witness file.
+
+use std::collections::BTreeMap;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use lurk::{
+    eval::lang::{Coproc, Lang},
+    field::LurkField,
+    lurk_sym_ptr,
+    ptr::Ptr,
+    ptr_match,
+    state::{initial_lurk_state, user_sym},
+    store::Store,
+    tag::ExprTag,
+    writer::Write,
+    Num,
+};
+
+use crate::{error::Error, Commitment, LurkPtr};
+
+/// The contents of a witness file: the value of each named input
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Witnesses<F: LurkField> {
+    pub values: BTreeMap<String, LurkPtr<F>>,
+}
+
+/// The commitment each witness was hidden behind, by name
+pub type WitnessCommitments<F> = BTreeMap<String, Commitment<F>>;
+
+impl<F: LurkField + Serialize + DeserializeOwned> Witnesses<F> {
+    /// Hides every value in `s` with a random secret. The store keeps the
+    /// secrets, so the commitments can be opened while evaluating with it.
+    pub fn commit(
+        &self,
+        s: &mut Store<F>,
+        limit: usize,
+        lang: &Lang<F, Coproc<F>>,
+    ) -> Result<WitnessCommitments<F>, Error> {
+        self.values
+            .iter()
+            .map(|(name, value)| {
+                let ptr = value.ptr(s, limit, lang);
+                let (commitment, _secret) = Commitment::from_ptr_with_hiding(s, &ptr)?;
+                Ok((name.clone(), commitment))
+            })
+            .collect()
+    }
+}
+
+/// Replaces every `(witness "name")` in `expr` by the opening of the
+/// commitment named `name`.
+///
+/// The commitment is spelled out as `(comm <hash>)`. A comm pointer would
+/// print as that same text, but wouldn't read back as itself, so the claim
+/// wouldn't reproduce the public input.
+pub fn substitute_witnesses<F: LurkField>(
+    s: &mut Store<F>,
+    expr: Ptr<F>,
+    commitments: &WitnessCommitments<F>,
+) -> Result<Ptr<F>, Error> {
+    let witness = s.intern_symbol(&user_sym("witness"));
+    substitute(s, expr, witness, commitments)
+}
+
+fn substitute<F: LurkField>(
+    s: &mut Store<F>,
+    expr: Ptr<F>,
+    witness: Ptr<F>,
+    commitments: &WitnessCommitments<F>,
+) -> Result<Ptr<F>, Error> {
+    if expr.tag != ExprTag::Cons {
+        return Ok(expr);
+    }
+    let (car, cdr) = s.car_cdr(&expr)?;
+    if car != witness {
+        let car = substitute(s, car, witness, commitments)?;
+        let cdr = substitute(s, cdr, witness, commitments)?;
+        return Ok(s.cons(car, cdr));
+    }
+
+    let (name,) = ptr_match!(s, &cdr, [String]).map_err(|e| {
+        Error::WitnessError(format!(
+            "expected (witness \"<name>\"), got {}: {e}",
+            expr.fmt_to_string(s, initial_lurk_state())
+        ))
+    })?;
+    let commitment = commitments
+        .get(&name)
+        .ok_or_else(|| Error::WitnessError(format!("no witness named {name:?}")))?;
+
+    // (open (comm <commitment>))
+    let hash = s.num(Num::Scalar(commitment.comm));
+    let comm = lurk_sym_ptr!(s, comm);
+    let comm = s.list(&[comm, hash]);
+    let open = lurk_sym_ptr!(s, open);
+    Ok(s.list(&[open, comm]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{evaluate, S1};
+
+    #[test]
+    fn witnesses_are_opened_from_commitments() {
+        let s = &mut Store::<S1>::default();
+        let lang = Lang::new();
+        let witnesses = Witnesses::<S1> {
+            values: BTreeMap::from([
+                ("price".into(), LurkPtr::Source("100".into())),
+                ("count".into(), LurkPtr::Source("3".into())),
+            ]),
+        };
+        let commitments = witnesses.commit(s, 1000, &lang).unwrap();
+
+        let expr = s
+            .read(r#"(* (+ (witness "price") 1) (witness "count"))"#)
+            .unwrap();
+        let expr = substitute_witnesses(s, expr, &commitments).unwrap();
+
+        // The claim holds the printed expression, which mentions the
+        // commitments but not the values
+        let printed = expr.fmt_to_string(s, initial_lurk_state());
+        assert!(!printed.contains("witness"));
+        let read_back = s.read(&printed).unwrap();
+        assert_eq!(s.hash_expr(&expr), s.hash_expr(&read_back));
+
+        let (output, _) = evaluate(s, expr, None, 1000, &lang).unwrap();
+        let expected = s.num(303);
+        assert!(s.ptr_eq(&output.expr, &expected).unwrap());
+
+        let unknown = s.read(r#"(witness "volume")"#).unwrap();
+        assert!(substitute_witnesses(s, unknown, &commitments).is_err());
+        let malformed = s.read("(witness price)").unwrap();
+        assert!(substitute_witnesses(s, malformed, &commitments).is_err());
+    }
+}
@@ -0,0 +1,13 @@
+//! Proofs and claims, as a verifier service receives them from anyone, must be
+//! rejected with an error when malformed, never with a panic or by running out
+//! of memory.
+#![no_main]
+
+use fcomm::{file_map::from_untrusted_bytes, Claim, Proof, S1};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = from_untrusted_bytes::<Proof<'static, S1>>(data);
+    let _ = serde_json::from_slice::<Proof<'static, S1>>(data);
+    let _ = serde_json::from_slice::<Claim<S1>>(data);
+});
@@ -0,0 +1,23 @@
+//! `ZStore`s, as found in witness files and `LurkPtr`s, must be rejected with
+//! an error when malformed, and interning whatever they hold must not panic.
+#![no_main]
+
+use fcomm::S1;
+use libfuzzer_sys::fuzz_target;
+use lurk::{
+    store::Store,
+    z_data::{from_z_data, ZData},
+    z_ptr::ZExprPtr,
+    z_store::ZStore,
+};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(z_data) = ZData::from_bytes(data) else {
+        return;
+    };
+    let Ok((z_store, z_ptr)) = from_z_data::<(ZStore<S1>, ZExprPtr<S1>)>(&z_data) else {
+        return;
+    };
+    let store = &mut Store::<S1>::default();
+    let _ = store.intern_z_expr_ptr(&z_ptr, &z_store);
+});
@@ -0,0 +1,82 @@
+//! A valid proof with some of its bytes flipped must not verify, unless the
+//! flips were lost in deserialization and it still proves the same claim with
+//! the same SNARK.
+#![no_main]
+
+use std::sync::Arc;
+
+use arbitrary::Arbitrary;
+use fcomm::{file_map::from_untrusted_bytes, public_param_dir, Proof, ReductionCount, S1};
+use libfuzzer_sys::fuzz_target;
+use lurk::{
+    eval::lang::{Coproc, Lang},
+    proof::nova::{NovaProver, PublicParams},
+    public_parameters::public_params,
+    store::Store,
+};
+use once_cell::sync::Lazy;
+
+struct Verifier {
+    lang: Arc<Lang<S1, Coproc<S1>>>,
+    pp: Arc<PublicParams<'static, S1, Coproc<S1>>>,
+    proof: Vec<u8>,
+    snark: Vec<u8>,
+    io: (Vec<S1>, Vec<S1>),
+}
+
+/// A valid proof, made once for all runs, and what's needed to verify it
+static VERIFIER: Lazy<Verifier> = Lazy::new(|| {
+    let lang = Arc::new(Lang::new());
+    let rc = ReductionCount::One;
+    let pp = public_params(rc.count(), true, lang.clone(), &public_param_dir()).unwrap();
+    let (proof, snark, io) = {
+        let store = &mut Store::<S1>::default();
+        let expr = store.read("(+ 1 2)").unwrap();
+        let prover = NovaProver::<S1, Coproc<S1>>::new(rc.count(), (*lang).clone());
+        let proof =
+            Proof::eval_and_prove(store, expr, None, 100, false, &prover, &pp, lang.clone())
+                .unwrap();
+        assert!(proof.verify(&pp, &lang).unwrap().verified);
+        let snark = bincode::serialize(&proof.proof).unwrap();
+        (
+            bincode::serialize(&proof).unwrap(),
+            snark,
+            proof.io_vecs(&lang).unwrap(),
+        )
+    };
+    Verifier {
+        lang,
+        pp,
+        proof,
+        snark,
+        io,
+    }
+});
+
+/// The bytes of the proof to flip, as offsets wrapping around its length and
+/// masks to xor them with
+#[derive(Arbitrary, Debug)]
+struct Mutation {
+    flips: Vec<(u32, u8)>,
+}
+
+fuzz_target!(|mutation: Mutation| {
+    let verifier = &*VERIFIER;
+    let mut bytes = verifier.proof.clone();
+    for (offset, mask) in mutation.flips {
+        let i = offset as usize % bytes.len();
+        bytes[i] ^= mask;
+    }
+    let Ok(proof) = from_untrusted_bytes::<Proof<'static, S1>>(&bytes) else {
+        return;
+    };
+    let Ok(result) = proof.verify(&verifier.pp, &verifier.lang) else {
+        return;
+    };
+    if result.verified {
+        // the claim may be written differently, but it must be the same
+        let same_claim = proof.io_vecs(&verifier.lang).ok().as_ref() == Some(&verifier.io);
+        let same_snark = bincode::serialize(&proof.proof).unwrap() == verifier.snark;
+        assert!(same_claim && same_snark, "a mutated proof verified");
+    }
+});
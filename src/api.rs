@@ -0,0 +1,392 @@
+//! The supported public API of Lurk.
+//!
+//! Everything else in the crate is an implementation detail as far as semver
+//! goes: LEM, the circuits and the reduction machinery are expected to keep
+//! changing. The items in this module, on the other hand, follow these rules:
+//!
+//! * A release can only break them if it bumps the version accordingly, that
+//!   is, the minor version while Lurk is on `0.x` and the major one after that
+//! * Items that are renamed or replaced stay around for at least one more
+//!   release as `#[deprecated]` shims, whose notes point at their successors
+//! * Internal refactors that move the re-exported items only change the paths
+//!   under `lurk::`, never the ones under `lurk::api::`
+//!
+//! `tests/api-tests.rs` pins the signatures of the facade, so changing them by
+//! accident fails to compile.
+//!
+//! A typical session reads an expression, evaluates it, proves the evaluation
+//! and verifies the proof against the resulting `Claim`:
+//!
+//! ```ignore
+//! use lurk::api::{self, Coproc, Lang, NovaProver, Prover, Store};
+//!
+//! let store = &mut Store::default();
+//! let expr = store.read("(+ 1 2)")?;
+//! let lang = Arc::new(Lang::<Fr, Coproc<Fr>>::new());
+//! let pp = api::public_params(1, lang.clone());
+//! let prover = NovaProver::new(1, (*lang).clone());
+//! let (proof, claim) = api::prove(&prover, &pp, store, expr, 100, lang)?;
+//! assert!(api::verify(&proof, &pp, &claim)?);
+//! ```
+
+use ::nova::traits::Group;
+use abomonation::Abomonation;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+pub use crate::{
+    batch::{BatchRoot, InclusionProof},
+    coprocessor::Coprocessor,
+    error::{ProofError, ReductionError},
+    eval::{
+        lang::{Coproc, Lang},
+        IO,
+    },
+    field::LurkField,
+    proof::{
+        nova::{public_params, CurveCycleEquipped, NovaProver, Proof, PublicParams},
+        Prover,
+    },
+    ptr::Ptr,
+    secret::Secret,
+    store::Store,
+    tag::ExprTag,
+    z_ptr::ZExprPtr,
+    z_store::ZStore,
+};
+
+use crate::{
+    batch,
+    eval::{empty_sym_env, Evaluable, Evaluator, Witness},
+    proof::nova::{G1, G2},
+    tag::{ContTag, Tag},
+};
+
+/// The result of evaluating an expression
+#[derive(Clone, Debug)]
+pub struct Evaluation<F: LurkField> {
+    /// The expression, environment and continuation evaluation stopped at
+    pub output: IO<F>,
+    /// The number of reduction steps taken
+    pub iterations: usize,
+    /// The expressions emitted along the way
+    pub emitted: Vec<Ptr<F>>,
+}
+
+/// What a proof attests: that the reduction starting at `input` arrives at
+/// `output` after `num_steps` folding steps. Inputs and outputs are the
+/// hashes of the expression, environment and continuation, as the circuit
+/// sees them. If the prover was asked for an `output_predicate`, verifying
+/// the claim also checks that its output satisfies it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Claim<F: LurkField> {
+    pub input: Vec<F>,
+    pub output: Vec<F>,
+    pub num_steps: usize,
+    pub output_predicate: Option<OutputPredicate>,
+}
+
+/// A requirement on the tags of the output of a proven evaluation. The tags
+/// are part of the public output of the circuit, which the proof binds, so
+/// a verifier checking the predicate on a verified claim doesn't need to trust
+/// whoever post-processed it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputPredicate {
+    /// The evaluation ended with the terminal continuation
+    Terminal,
+    /// The evaluation ended with the terminal continuation and a result with
+    /// this tag
+    Returns(ExprTag),
+}
+
+impl OutputPredicate {
+    /// Whether `output`, the public output of a proof, satisfies the predicate
+    pub fn holds<F: LurkField>(&self, output: &[F]) -> bool {
+        // the tags and hashes of the expression, environment and continuation
+        let [expr_tag, _, _, _, cont_tag, _] = output else {
+            return false;
+        };
+        let terminal = *cont_tag == ContTag::Terminal.to_field::<F>();
+        match self {
+            Self::Terminal => terminal,
+            Self::Returns(tag) => terminal && *expr_tag == tag.to_field::<F>(),
+        }
+    }
+}
+
+impl std::fmt::Display for OutputPredicate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Terminal => write!(f, "terminal"),
+            Self::Returns(tag) => write!(f, "terminal returning {tag}"),
+        }
+    }
+}
+
+/// The options of `prove_with`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ProveOptions {
+    /// The predicate the output must satisfy, which is recorded in the claim
+    pub output_predicate: Option<OutputPredicate>,
+}
+
+/// Evaluates `expr` in the empty environment, taking at most `limit` steps
+pub fn evaluate<F: LurkField, C: Coprocessor<F>>(
+    store: &mut Store<F>,
+    expr: Ptr<F>,
+    limit: usize,
+    lang: &Lang<F, C>,
+) -> Result<Evaluation<F>, ReductionError> {
+    let env = empty_sym_env(store);
+    let (output, iterations, emitted) = Evaluator::new(expr, env, store, limit, lang).eval()?;
+    Ok(Evaluation {
+        output,
+        iterations,
+        emitted,
+    })
+}
+
+/// Cancels the evaluations it's given to. Clones share the cancellation, so
+/// one can be kept to cancel an evaluation that another was handed to.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes the evaluations using this token stop before their next step
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The future returned by `evaluate_async`
+#[derive(Debug)]
+pub struct EvaluateAsync<'a, F: LurkField, C: Coprocessor<F>> {
+    store: &'a mut Store<F>,
+    lang: &'a Lang<F, C>,
+    io: IO<F>,
+    limit: usize,
+    frames_per_poll: usize,
+    cancellation: CancellationToken,
+    iterations: usize,
+    emitted: Vec<Ptr<F>>,
+}
+
+// The future is never pinned structurally, so it can be moved while pinned
+impl<F: LurkField, C: Coprocessor<F>> Unpin for EvaluateAsync<'_, F, C> {}
+
+impl<F: LurkField, C: Coprocessor<F>> Future for EvaluateAsync<'_, F, C> {
+    type Output = Result<Evaluation<F>, ReductionError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        for _ in 0..this.frames_per_poll {
+            if this.cancellation.is_cancelled() {
+                return Poll::Ready(Err(ReductionError::Cancelled(this.iterations)));
+            }
+            if this.iterations == this.limit || Evaluable::<F, Witness<F>, C>::is_complete(&this.io)
+            {
+                return Poll::Ready(Ok(Evaluation {
+                    output: this.io,
+                    iterations: this.iterations,
+                    emitted: std::mem::take(&mut this.emitted),
+                }));
+            }
+            this.io = match this.io.reduce(this.store, this.lang) {
+                Ok((io, _)) => io,
+                Err(e) => return Poll::Ready(Err(e)),
+            };
+            if let Some(emitted) = this.io.maybe_emitted_expression(this.store) {
+                this.emitted.push(emitted);
+            }
+            this.iterations += 1;
+            Evaluable::<F, Witness<F>, C>::log(&this.io, this.store, this.iterations);
+        }
+        // There's more to evaluate, but the executor gets to run other tasks
+        // first
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// Evaluates like `evaluate`, but as a future that yields to the executor
+/// every `frames_per_poll` steps. Once `cancellation` is cancelled, the future
+/// resolves to `ReductionError::Cancelled` the next time it's polled.
+///
+/// # Panics
+///
+/// If `frames_per_poll` is zero
+pub fn evaluate_async<'a, F: LurkField, C: Coprocessor<F>>(
+    store: &'a mut Store<F>,
+    expr: Ptr<F>,
+    limit: usize,
+    lang: &'a Lang<F, C>,
+    frames_per_poll: usize,
+    cancellation: CancellationToken,
+) -> EvaluateAsync<'a, F, C> {
+    assert!(frames_per_poll > 0, "evaluations must make progress");
+    let env = empty_sym_env(store);
+    let io = IO {
+        expr,
+        env,
+        cont: store.intern_cont_outermost(),
+    };
+    Evaluable::<F, Witness<F>, C>::log(&io, store, 0);
+    EvaluateAsync {
+        store,
+        lang,
+        io,
+        limit,
+        frames_per_poll,
+        cancellation,
+        iterations: 0,
+        emitted: vec![],
+    }
+}
+
+/// Evaluates `expr` in the empty environment, taking at most `limit` steps,
+/// and proves the evaluation
+pub fn prove<'a, F: CurveCycleEquipped, C: Coprocessor<F> + 'a>(
+    prover: &'a NovaProver<F, C>,
+    pp: &'a PublicParams<'_, F, C>,
+    store: &'a mut Store<F>,
+    expr: Ptr<F>,
+    limit: usize,
+    lang: Arc<Lang<F, C>>,
+) -> Result<(Proof<'a, F, C>, Claim<F>), ProofError>
+where
+    <<G1<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+    <<G2<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+{
+    prove_with(
+        prover,
+        pp,
+        store,
+        expr,
+        limit,
+        lang,
+        ProveOptions::default(),
+    )
+}
+
+/// Like `prove`, with `options`. Fails if the output doesn't satisfy the
+/// requested predicate, rather than returning a claim that doesn't verify.
+pub fn prove_with<'a, F: CurveCycleEquipped, C: Coprocessor<F> + 'a>(
+    prover: &'a NovaProver<F, C>,
+    pp: &'a PublicParams<'_, F, C>,
+    store: &'a mut Store<F>,
+    expr: Ptr<F>,
+    limit: usize,
+    lang: Arc<Lang<F, C>>,
+    options: ProveOptions,
+) -> Result<(Proof<'a, F, C>, Claim<F>), ProofError>
+where
+    <<G1<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+    <<G2<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+{
+    let env = empty_sym_env(store);
+    let (proof, input, output, num_steps) =
+        prover.evaluate_and_prove(pp, expr, env, store, limit, lang)?;
+    if let Some(predicate) = options.output_predicate {
+        if !predicate.holds(&output) {
+            return Err(ProofError::OutputPredicate(predicate.to_string()));
+        }
+    }
+    let claim = Claim {
+        input,
+        output,
+        num_steps,
+        output_predicate: options.output_predicate,
+    };
+    Ok((proof, claim))
+}
+
+/// Checks that `proof` proves `claim`, and that the output satisfies the
+/// claim's predicate, if it has one
+pub fn verify<F: CurveCycleEquipped, C: Coprocessor<F>>(
+    proof: &Proof<'_, F, C>,
+    pp: &PublicParams<'_, F, C>,
+    claim: &Claim<F>,
+) -> Result<bool, ProofError>
+where
+    <<G1<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+    <<G2<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+{
+    if let Some(predicate) = claim.output_predicate {
+        if !predicate.holds(&claim.output) {
+            return Ok(false);
+        }
+    }
+    Ok(proof.verify(pp, claim.num_steps, &claim.input, &claim.output)?)
+}
+
+/// Commits to `payload`, hiding it with `secret` if there's one
+pub fn commit<F: LurkField>(
+    store: &mut Store<F>,
+    payload: Ptr<F>,
+    secret: Option<&Secret<F>>,
+) -> Ptr<F> {
+    match secret {
+        Some(secret) => store.hide_secret(secret, payload),
+        None => store.commit(payload),
+    }
+}
+
+/// Hides each of `payloads` with the secret at the same position of `secrets`,
+/// returning the commitments along with the root of a Merkle tree over their
+/// hashes and a proof that each of them belongs to it. Publishing the root
+/// commits to the whole batch.
+///
+/// Every payload needs a fresh secret, such as one from `Secret::random`:
+/// sharing a secret would let anyone who learns it open the whole batch, and
+/// equal payloads would have equal commitments.
+///
+/// # Panics
+///
+/// If `secrets` and `payloads` have different lengths
+pub fn commit_batch<F: LurkField>(
+    store: &mut Store<F>,
+    payloads: &[Ptr<F>],
+    secrets: &[Secret<F>],
+) -> (Vec<Ptr<F>>, BatchRoot<F>, Vec<InclusionProof<F>>) {
+    assert_eq!(
+        payloads.len(),
+        secrets.len(),
+        "every payload needs its own secret"
+    );
+    let comms: Vec<_> = payloads
+        .iter()
+        .zip(secrets)
+        .map(|(payload, secret)| store.hide_secret(secret, *payload))
+        .collect();
+    let hashes: Vec<_> = comms
+        .iter()
+        .map(|comm| store.hash_expr(comm).expect("commitments can be hashed"))
+        .map(|z_ptr| *z_ptr.value())
+        .collect();
+    let (root, proofs) = batch::merkle_batch(&store.poseidon_cache, &hashes);
+    (comms, root, proofs)
+}
+
+/// The secret and the payload of the commitment `comm`, if it's a commitment
+/// made with `store`
+pub fn open<F: LurkField>(store: &Store<F>, comm: Ptr<F>) -> Option<(Secret<F>, Ptr<F>)> {
+    if comm.tag != ExprTag::Comm {
+        return None;
+    }
+    store.open(comm)
+}
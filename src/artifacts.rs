@@ -0,0 +1,262 @@
+//! Canonical names for the artifacts Lurk keeps on disk.
+//!
+//! Commitments, proofs, public parameters and committed functions are stored
+//! under names of the form
+//!
+//! `<kind>_<field>_<hash>_v<version>`
+//!
+//! where `kind` is the prefix of the `ArtifactKind`, `field` is the
+//! `LanguageField` the artifact is defined over, `hash` identifies its content
+//! (a commitment's hash, a claim's hash...) and `version` is the version of
+//! the artifact's format. The REPL, `fcomm` and its server mode all resolve
+//! artifacts with `Artifacts`, which also finds the ones stored under the
+//! names used before this scheme.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use std::{fmt, fs, io, str::FromStr};
+use thiserror::Error;
+
+use crate::field::LanguageField;
+
+/// The version of the artifact formats written by this release
+pub const ARTIFACT_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ArtifactKind {
+    Commitment,
+    Proof,
+    ProofMeta,
+    PublicParams,
+    Function,
+}
+
+impl ArtifactKind {
+    const ALL: [Self; 5] = [
+        Self::Commitment,
+        Self::Proof,
+        Self::ProofMeta,
+        Self::PublicParams,
+        Self::Function,
+    ];
+
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Self::Commitment => "commit",
+            Self::Proof => "proof",
+            Self::ProofMeta => "proofmeta",
+            Self::PublicParams => "pp",
+            Self::Function => "fn",
+        }
+    }
+}
+
+impl fmt::Display for ArtifactKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.prefix())
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum Error {
+    #[error("Malformed artifact name `{0}`")]
+    Malformed(String),
+    #[error("Unknown artifact kind `{0}`")]
+    UnknownKind(String),
+    #[error("Unknown field `{0}`")]
+    UnknownField(String),
+}
+
+fn parse_field(field: &str) -> Result<LanguageField, Error> {
+    match field {
+        "Pallas" => Ok(LanguageField::Pallas),
+        "Vesta" => Ok(LanguageField::Vesta),
+        "BLS12-381" => Ok(LanguageField::BLS12_381),
+        "BN256" => Ok(LanguageField::BN256),
+        "Grumpkin" => Ok(LanguageField::Grumpkin),
+        _ => Err(Error::UnknownField(field.into())),
+    }
+}
+
+/// The canonical name of an artifact
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ArtifactName {
+    pub kind: ArtifactKind,
+    pub field: LanguageField,
+    /// Identifies the artifact's content. It can't be empty, but can hold `_`s
+    pub hash: String,
+    pub version: u32,
+}
+
+impl ArtifactName {
+    /// The name of an artifact written in the current format
+    pub fn new<H: Into<String>>(kind: ArtifactKind, field: LanguageField, hash: H) -> Self {
+        Self {
+            kind,
+            field,
+            hash: hash.into(),
+            version: ARTIFACT_VERSION,
+        }
+    }
+}
+
+impl fmt::Display for ArtifactName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            kind,
+            field,
+            hash,
+            version,
+        } = self;
+        write!(f, "{kind}_{field}_{hash}_v{version}")
+    }
+}
+
+impl FromStr for ArtifactName {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let malformed = || Error::Malformed(s.into());
+        let mut parts = s.splitn(3, '_');
+        let (Some(kind), Some(field), Some(rest)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(malformed());
+        };
+        let (hash, version) = rest.rsplit_once("_v").ok_or_else(malformed)?;
+        if hash.is_empty() {
+            return Err(malformed());
+        }
+        let kind = ArtifactKind::ALL
+            .into_iter()
+            .find(|k| k.prefix() == kind)
+            .ok_or_else(|| Error::UnknownKind(kind.into()))?;
+        Ok(Self {
+            kind,
+            field: parse_field(field)?,
+            hash: hash.into(),
+            version: version.parse().map_err(|_| malformed())?,
+        })
+    }
+}
+
+/// A directory of artifacts
+#[derive(Clone, Debug)]
+pub struct Artifacts {
+    dir: Utf8PathBuf,
+    legacy_name: Option<fn(&ArtifactName) -> String>,
+}
+
+impl Artifacts {
+    pub fn new<P: Into<Utf8PathBuf>>(dir: P) -> Self {
+        Self {
+            dir: dir.into(),
+            legacy_name: None,
+        }
+    }
+
+    /// Also looks artifacts up under the file names `legacy_name` gives them,
+    /// which is where the directory's artifacts were stored before their names
+    /// were canonical
+    pub fn with_legacy_names(mut self, legacy_name: fn(&ArtifactName) -> String) -> Self {
+        self.legacy_name = Some(legacy_name);
+        self
+    }
+
+    #[inline]
+    pub fn dir(&self) -> &Utf8Path {
+        &self.dir
+    }
+
+    /// Where the artifact named `name` is to be stored
+    pub fn path(&self, name: &ArtifactName) -> Utf8PathBuf {
+        self.dir.join(name.to_string())
+    }
+
+    /// Where the artifact named `name` is stored, if it's stored at all
+    pub fn lookup(&self, name: &ArtifactName) -> Option<Utf8PathBuf> {
+        let path = self.path(name);
+        if path.exists() {
+            return Some(path);
+        }
+        let legacy_name = self.legacy_name?;
+        let legacy_path = self.dir.join(legacy_name(name));
+        legacy_path.exists().then_some(legacy_path)
+    }
+
+    /// Where to read the artifact named `name` from, or to write it to. That's
+    /// where it's already stored, if it is, and its canonical path otherwise
+    pub fn resolve(&self, name: &ArtifactName) -> Utf8PathBuf {
+        self.lookup(name).unwrap_or_else(|| self.path(name))
+    }
+
+    /// The names of the artifacts of `kind` stored under their canonical names
+    pub fn list(&self, kind: ArtifactKind) -> io::Result<Vec<ArtifactName>> {
+        let mut names = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let file_name = entry?.file_name();
+            let Some(file_name) = file_name.to_str() else {
+                continue;
+            };
+            match file_name.parse::<ArtifactName>() {
+                Ok(name) if name.kind == kind => names.push(name),
+                _ => (),
+            }
+        }
+        names.sort_by(|a, b| (&a.hash, a.version).cmp(&(&b.hash, b.version)));
+        Ok(names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_roundtrip() {
+        for kind in ArtifactKind::ALL {
+            for field in [LanguageField::Pallas, LanguageField::BLS12_381] {
+                let name = ArtifactName::new(kind, field, "nova_10_abc");
+                assert_eq!(name.to_string().parse::<ArtifactName>(), Ok(name));
+            }
+        }
+        let name = ArtifactName::new(ArtifactKind::Commitment, LanguageField::BLS12_381, "0a1b");
+        assert_eq!(name.to_string(), "commit_BLS12-381_0a1b_v1");
+        for malformed in [
+            "commit_Pallas_0a1b",
+            "commit_Pallas__v1",
+            "commit_Pallas_0a1b_vx",
+        ] {
+            assert!(matches!(
+                malformed.parse::<ArtifactName>(),
+                Err(Error::Malformed(_))
+            ));
+        }
+        assert_eq!(
+            "tx_Pallas_0a1b_v1".parse::<ArtifactName>(),
+            Err(Error::UnknownKind("tx".into()))
+        );
+    }
+
+    #[test]
+    fn lookups_fall_back_to_legacy_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+        let artifacts =
+            Artifacts::new(dir).with_legacy_names(|name| format!("{}.commit", name.hash));
+        let name = ArtifactName::new(ArtifactKind::Commitment, LanguageField::Pallas, "0a1b");
+        assert_eq!(artifacts.lookup(&name), None);
+        assert_eq!(artifacts.resolve(&name), dir.join("commit_Pallas_0a1b_v1"));
+
+        let legacy = dir.join("0a1b.commit");
+        fs::write(&legacy, "").unwrap();
+        assert_eq!(artifacts.resolve(&name), legacy);
+        assert!(artifacts.list(ArtifactKind::Commitment).unwrap().is_empty());
+
+        fs::write(artifacts.path(&name), "").unwrap();
+        assert_eq!(artifacts.resolve(&name), artifacts.path(&name));
+        assert_eq!(
+            artifacts.list(ArtifactKind::Commitment).unwrap(),
+            vec![name]
+        );
+        assert!(artifacts.list(ArtifactKind::Proof).unwrap().is_empty());
+    }
+}
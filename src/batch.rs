@@ -0,0 +1,153 @@
+//! Merkle roots over batches of commitments.
+//!
+//! An application committing to many expressions can publish a single root
+//! instead of every commitment, and later prove that any of them belongs to
+//! the published batch with an `InclusionProof`.
+//!
+//! The tree is binary, with the commitment hashes as leaves in the order they
+//! were committed. A node that's left without a sibling, at the end of a level
+//! of odd width, moves up unchanged. Inner nodes hash their children after
+//! `NODE_DOMAIN`, and the root hashes the number of leaves and the top of the
+//! tree after `ROOT_DOMAIN`, so neither can be mistaken for a commitment, and a
+//! proof can't claim a leaf of another batch size. The shape of the path to a
+//! leaf follows from its index and the batch size, so proofs only hold the
+//! hashes of the siblings along the way.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{field::LurkField, hash::PoseidonCache};
+
+const NODE_DOMAIN: u64 = 1;
+const ROOT_DOMAIN: u64 = 2;
+
+/// The root of a batch of commitments, which is what gets published
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchRoot<F: LurkField> {
+    /// The hash binding the commitments of the batch and their number
+    pub hash: F,
+    /// The number of commitments in the batch
+    pub len: usize,
+}
+
+/// Proves that a commitment is the leaf at `index` of a batch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InclusionProof<F: LurkField> {
+    pub index: usize,
+    /// The siblings on the path from the leaf to the top of the tree
+    pub siblings: Vec<F>,
+}
+
+fn hash_node<F: LurkField>(cache: &PoseidonCache<F>, left: F, right: F) -> F {
+    cache.hash3(&[F::from_u64(NODE_DOMAIN), left, right])
+}
+
+fn hash_root<F: LurkField>(cache: &PoseidonCache<F>, len: usize, top: F) -> F {
+    cache.hash3(&[F::from_u64(ROOT_DOMAIN), F::from_u64(len as u64), top])
+}
+
+/// Builds the tree over the commitment hashes `leaves`, returning its root
+/// and an inclusion proof for each leaf
+pub fn merkle_batch<F: LurkField>(
+    cache: &PoseidonCache<F>,
+    leaves: &[F],
+) -> (BatchRoot<F>, Vec<InclusionProof<F>>) {
+    let mut proofs: Vec<_> = (0..leaves.len())
+        .map(|index| InclusionProof {
+            index,
+            siblings: vec![],
+        })
+        .collect();
+    let mut level = leaves.to_vec();
+    // the position of each leaf in the current level
+    let mut positions: Vec<_> = (0..leaves.len()).collect();
+    while level.len() > 1 {
+        for (proof, position) in proofs.iter_mut().zip(&mut positions) {
+            if let Some(sibling) = level.get(*position ^ 1) {
+                proof.siblings.push(*sibling);
+            }
+            *position /= 2;
+        }
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => hash_node(cache, *left, *right),
+                [single] => *single,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    let top = level.first().copied().unwrap_or(F::ZERO);
+    let root = BatchRoot {
+        hash: hash_root(cache, leaves.len(), top),
+        len: leaves.len(),
+    };
+    (root, proofs)
+}
+
+impl<F: LurkField> BatchRoot<F> {
+    /// Whether `proof` proves that the commitment hashing to `comm` belongs to
+    /// the batch
+    pub fn verify(&self, cache: &PoseidonCache<F>, comm: F, proof: &InclusionProof<F>) -> bool {
+        if proof.index >= self.len {
+            return false;
+        }
+        let mut siblings = proof.siblings.iter();
+        let (mut node, mut position, mut width) = (comm, proof.index, self.len);
+        while width > 1 {
+            if position ^ 1 < width {
+                let Some(sibling) = siblings.next() else {
+                    return false;
+                };
+                node = if position % 2 == 0 {
+                    hash_node(cache, node, *sibling)
+                } else {
+                    hash_node(cache, *sibling, node)
+                };
+            }
+            position /= 2;
+            width = (width + 1) / 2;
+        }
+        siblings.next().is_none() && hash_root(cache, self.len, node) == self.hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blstrs::Scalar as Fr;
+
+    #[test]
+    fn every_leaf_of_a_batch_is_proven() {
+        let cache = PoseidonCache::<Fr>::default();
+        for len in 0..10 {
+            let leaves: Vec<_> = (0..len).map(|i| Fr::from(100 + i)).collect();
+            let (root, proofs) = merkle_batch(&cache, &leaves);
+            assert_eq!(root.len, leaves.len());
+            assert_eq!(proofs.len(), leaves.len());
+            for (leaf, proof) in leaves.iter().zip(&proofs) {
+                assert!(root.verify(&cache, *leaf, proof));
+                // neither another commitment nor another position is accepted
+                assert!(!root.verify(&cache, *leaf + Fr::from(1), proof));
+                let moved = InclusionProof {
+                    index: (proof.index + 1) % len as usize,
+                    ..proof.clone()
+                };
+                assert!(len == 1 || !root.verify(&cache, *leaf, &moved));
+            }
+        }
+    }
+
+    #[test]
+    fn proofs_are_bound_to_the_batch_size() {
+        let cache = PoseidonCache::<Fr>::default();
+        let leaves: Vec<_> = (0..5).map(Fr::from).collect();
+        let (root, proofs) = merkle_batch(&cache, &leaves);
+        let (shorter, _) = merkle_batch(&cache, &leaves[..4]);
+        assert_ne!(root.hash, shorter.hash);
+        let resized = BatchRoot { len: 4, ..root };
+        assert!(!resized.verify(&cache, leaves[0], &proofs[0]));
+        let mut padded = proofs[0].clone();
+        padded.siblings.push(Fr::from(0));
+        assert!(!root.verify(&cache, leaves[0], &padded));
+    }
+}
@@ -0,0 +1,199 @@
+//! Constructors for Lurk expressions, for programs that generate Lurk code
+//! from Rust data instead of formatting and reading source text.
+//!
+//! Each function returns a `Syntax` node, so expressions compose like values:
+//!
+//! ```
+//! use lurk::builder::{call, lambda, let_, num, op, sym};
+//! use lurk::store::Store;
+//! use pasta_curves::pallas::Scalar as Fr;
+//!
+//! let store = &mut Store::<Fr>::default();
+//! let double = lambda(&["x"], op("+", [sym("x"), sym("x")]));
+//! let expr = let_([("double", double)], call(sym("double"), [num(21)]));
+//! let ptr = store.intern_syntax(expr);
+//! assert_eq!(ptr, store.read("(let ((double (lambda (x) (+ x x)))) (double 21))").unwrap());
+//! ```
+//!
+//! Symbols are resolved the way the reader resolves them in the `lurk.user`
+//! package, so built and read expressions intern to the same pointers.
+
+use crate::field::LurkField;
+use crate::num::Num;
+use crate::package::SymbolRef;
+use crate::parser::position::Pos;
+use crate::state::{initial_lurk_state, lurk_sym, user_sym};
+use crate::syntax::Syntax;
+use crate::uint::UInt;
+use crate::Symbol;
+
+#[inline]
+pub fn num<F: LurkField, T: Into<Num<F>>>(n: T) -> Syntax<F> {
+    Syntax::Num(Pos::No, n.into())
+}
+
+#[inline]
+pub fn uint<F: LurkField>(n: u64) -> Syntax<F> {
+    Syntax::UInt(Pos::No, UInt::U64(n))
+}
+
+#[inline]
+pub fn string<F: LurkField, S: Into<String>>(s: S) -> Syntax<F> {
+    Syntax::String(Pos::No, s.into())
+}
+
+#[inline]
+pub fn character<F: LurkField>(c: char) -> Syntax<F> {
+    Syntax::Char(Pos::No, c)
+}
+
+/// A symbol as the reader would read `name` in the `lurk.user` package:
+/// builtins like `lambda` or `+` resolve to the `lurk` package and any other
+/// name to `lurk.user`
+pub fn sym<F: LurkField>(name: &str) -> Syntax<F> {
+    let symbol = match initial_lurk_state().resolve(name) {
+        Some(symbol) => symbol.clone(),
+        None => user_sym(name).into(),
+    };
+    Syntax::Symbol(Pos::No, symbol)
+}
+
+/// A symbol in the `lurk` package
+#[inline]
+pub fn lurk<F: LurkField>(name: &str) -> Syntax<F> {
+    Syntax::Symbol(Pos::No, lurk_sym(name).into())
+}
+
+/// A symbol given by its full path, e.g. `["my-package", "foo"]` for
+/// `.my-package.foo`
+#[inline]
+pub fn path<F: LurkField>(path: &[&str]) -> Syntax<F> {
+    Syntax::Symbol(Pos::No, SymbolRef::new(Symbol::sym(path)))
+}
+
+/// A keyword given by its path, e.g. `["foo"]` for `:foo`
+#[inline]
+pub fn key<F: LurkField>(path: &[&str]) -> Syntax<F> {
+    Syntax::Symbol(Pos::No, SymbolRef::new(Symbol::key(path)))
+}
+
+#[inline]
+pub fn nil<F: LurkField>() -> Syntax<F> {
+    lurk("nil")
+}
+
+#[inline]
+pub fn t<F: LurkField>() -> Syntax<F> {
+    lurk("t")
+}
+
+#[inline]
+pub fn list<F: LurkField, I: IntoIterator<Item = Syntax<F>>>(xs: I) -> Syntax<F> {
+    Syntax::List(Pos::No, xs.into_iter().collect())
+}
+
+/// A list ending in `tail` instead of `nil`, like `(a b . tail)`
+#[inline]
+pub fn improper<F: LurkField, I>(xs: I, tail: Syntax<F>) -> Syntax<F>
+where
+    I: IntoIterator<Item = Syntax<F>>,
+{
+    Syntax::Improper(Pos::No, xs.into_iter().collect(), Box::new(tail))
+}
+
+#[inline]
+pub fn quote<F: LurkField>(x: Syntax<F>) -> Syntax<F> {
+    Syntax::Quote(Pos::No, Box::new(x))
+}
+
+/// Applies the builtin `name` to `args`, as in `(+ 1 2)`
+pub fn op<F: LurkField, I: IntoIterator<Item = Syntax<F>>>(name: &str, args: I) -> Syntax<F> {
+    list(std::iter::once(lurk(name)).chain(args))
+}
+
+/// Applies `f` to `args`
+pub fn call<F: LurkField, I: IntoIterator<Item = Syntax<F>>>(f: Syntax<F>, args: I) -> Syntax<F> {
+    list(std::iter::once(f).chain(args))
+}
+
+pub fn lambda<F: LurkField>(params: &[&str], body: Syntax<F>) -> Syntax<F> {
+    list([lurk("lambda"), list(params.iter().map(|p| sym(p))), body])
+}
+
+fn binding_form<'a, F: LurkField, I>(keyword: &str, bindings: I, body: Syntax<F>) -> Syntax<F>
+where
+    I: IntoIterator<Item = (&'a str, Syntax<F>)>,
+{
+    let bindings = bindings
+        .into_iter()
+        .map(|(name, val)| list([sym(name), val]));
+    list([lurk(keyword), list(bindings), body])
+}
+
+pub fn let_<'a, F: LurkField, I>(bindings: I, body: Syntax<F>) -> Syntax<F>
+where
+    I: IntoIterator<Item = (&'a str, Syntax<F>)>,
+{
+    binding_form("let", bindings, body)
+}
+
+pub fn letrec<'a, F: LurkField, I>(bindings: I, body: Syntax<F>) -> Syntax<F>
+where
+    I: IntoIterator<Item = (&'a str, Syntax<F>)>,
+{
+    binding_form("letrec", bindings, body)
+}
+
+pub fn if_<F: LurkField>(cond: Syntax<F>, then: Syntax<F>, otherwise: Syntax<F>) -> Syntax<F> {
+    list([lurk("if"), cond, then, otherwise])
+}
+
+pub fn begin<F: LurkField, I: IntoIterator<Item = Syntax<F>>>(xs: I) -> Syntax<F> {
+    op("begin", xs)
+}
+
+#[cfg(test)]
+mod tests {
+    use pasta_curves::pallas::Scalar as Fr;
+
+    use super::*;
+    use crate::store::Store;
+
+    fn assert_reads_as(syntax: Syntax<Fr>, src: &str) {
+        let store = &mut Store::<Fr>::default();
+        let built = store.intern_syntax(syntax);
+        let read = store.read(src).unwrap();
+        assert_eq!(built, read, "{src}");
+    }
+
+    #[test]
+    fn built_matches_read() {
+        assert_reads_as(num(42), "42");
+        assert_reads_as(uint(7), "7u64");
+        assert_reads_as(string("hi"), "\"hi\"");
+        assert_reads_as(character('a'), "#\\a");
+        assert_reads_as(sym("foo"), "foo");
+        assert_reads_as(sym("cons"), "cons");
+        assert_reads_as(path(&["lurk", "user", "foo"]), ".lurk.user.foo");
+        assert_reads_as(key(&["foo"]), ":foo");
+        assert_reads_as(list([]), "()");
+        assert_reads_as(nil(), "nil");
+        assert_reads_as(t(), "t");
+        assert_reads_as(improper([num(1), num(2)], num(3)), "(1 2 . 3)");
+        assert_reads_as(quote(list([sym("a"), sym("b")])), "'(a b)");
+        let n = || sym("n");
+        let recur = call(sym("fact"), [op("-", [n(), num(1)])]);
+        let body = if_(op("=", [n(), num(0)]), num(1), op("*", [n(), recur]));
+        assert_reads_as(
+            letrec(
+                [("fact", lambda(&["n"], body))],
+                call(sym("fact"), [num(5)]),
+            ),
+            "(letrec ((fact (lambda (n) (if (= n 0) 1 (* n (fact (- n 1))))))) (fact 5))",
+        );
+        assert_reads_as(
+            begin([op("emit", [string("x")]), num(1)]),
+            "(begin (emit \"x\") 1)",
+        );
+    }
+}
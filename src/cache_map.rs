@@ -1,15 +1,16 @@
 use stable_deref_trait::StableDeref;
 use std::borrow::Borrow;
-use std::collections::HashMap;
 use std::hash::Hash;
 
 use std::sync::RwLock;
 
+use crate::hasher::HashMap;
+
 #[derive(Debug)]
 /// `CacheMap` is an adaptation of `FrozenMap`:
 /// `<https://docs.rs/elsa/latest/elsa/map/struct.FrozenMap.html>`
 pub struct CacheMap<K, V> {
-    map: RwLock<HashMap<K, V, ahash::RandomState>>,
+    map: RwLock<HashMap<K, V>>,
 }
 
 impl<K, V> Default for CacheMap<K, V> {
@@ -329,12 +330,12 @@ impl<K: Eq + Hash, V: Copy> CacheMap<K, V> {
     }
 }
 
-impl<K, V> std::convert::AsMut<HashMap<K, V, ahash::RandomState>> for CacheMap<K, V> {
+impl<K, V> std::convert::AsMut<HashMap<K, V>> for CacheMap<K, V> {
     /// Get mutable access to the underlying [`HashMap`].
     ///
     /// This is safe, as it requires a `&mut self`, ensuring nothing is using
     /// the 'frozen' contents.
-    fn as_mut(&mut self) -> &mut HashMap<K, V, ahash::RandomState> {
+    fn as_mut(&mut self) -> &mut HashMap<K, V> {
         self.map.get_mut().unwrap()
     }
 }
@@ -34,7 +34,7 @@ use crate::circuit::circuit_frame::constraints::{
 use crate::circuit::gadgets::hashes::{AllocatedConsWitness, AllocatedContWitness};
 use crate::circuit::ToInputs;
 use crate::coprocessor::Coprocessor;
-use crate::eval::{lang::Lang, Frame, Witness, IO};
+use crate::eval::{empty_emit_log, lang::Lang, Frame, Witness, IO};
 use crate::expr::Thunk;
 use crate::hash_witness::HashWitness;
 use crate::lurk_sym_ptr;
@@ -42,6 +42,7 @@ use crate::proof::Provable;
 use crate::ptr::Ptr;
 use crate::store::Store;
 use crate::tag::{ContTag, ExprTag, Op1, Op2};
+use crate::z_ptr::ZExprPtr;
 use num_bigint::BigUint;
 use num_integer::Integer;
 use num_traits::FromPrimitive;
@@ -63,6 +64,8 @@ pub struct MultiFrame<'a, F: LurkField, C: Coprocessor<F>> {
     pub input: Option<IO<F>>,
     pub output: Option<IO<F>>,
     pub frames: Option<Vec<CircuitFrame<'a, F, C>>>,
+    /// The emit log before the frames, for Nova proofs
+    pub emit_log: Option<ZExprPtr<F>>,
     pub cached_witness: Option<WitnessCS<F>>,
     pub count: usize,
 }
@@ -97,6 +100,7 @@ impl<'a, F: LurkField, C: Coprocessor<F>> MultiFrame<'a, F, C> {
             input: None,
             output: None,
             frames: None,
+            emit_log: None,
             cached_witness: None,
             count,
         }
@@ -111,6 +115,17 @@ impl<'a, F: LurkField, C: Coprocessor<F>> MultiFrame<'a, F, C> {
         frames: &[Frame<IO<F>, Witness<F>, C>],
         store: &'a Store<F>,
         lang: Arc<Lang<F, C>>,
+    ) -> Vec<Self> {
+        Self::from_frames_with_log(count, frames, store, lang, empty_emit_log(store))
+    }
+
+    /// Like `from_frames`, for frames that start with the emit log `log`
+    pub fn from_frames_with_log(
+        count: usize,
+        frames: &[Frame<IO<F>, Witness<F>, C>],
+        store: &'a Store<F>,
+        lang: Arc<Lang<F, C>>,
+        mut log: ZExprPtr<F>,
     ) -> Vec<Self> {
         // `count` is the number of `Frames` to include per `MultiFrame`.
         let total_frames = frames.len();
@@ -118,6 +133,14 @@ impl<'a, F: LurkField, C: Coprocessor<F>> MultiFrame<'a, F, C> {
         let mut multi_frames = Vec::with_capacity(n);
 
         for chunk in frames.chunks(count) {
+            let emit_log = log;
+            for frame in chunk {
+                log = frame
+                    .output
+                    .extend_emit_log(log, store)
+                    .expect("emitted expr hash missing");
+            }
+
             let mut inner_frames = Vec::with_capacity(count);
 
             for x in chunk {
@@ -143,6 +166,7 @@ impl<'a, F: LurkField, C: Coprocessor<F>> MultiFrame<'a, F, C> {
                 input: Some(chunk[0].input),
                 output: Some(output),
                 frames: Some(inner_frames),
+                emit_log: Some(emit_log),
                 cached_witness: None,
                 count,
             };
@@ -175,11 +199,15 @@ impl<'a, F: LurkField, C: Coprocessor<F>> MultiFrame<'a, F, C> {
             input,
             output,
             frames,
+            emit_log: None,
             cached_witness: None,
             count,
         }
     }
 
+    /// Synthesizes `frames` from the allocated input. When given the emit log
+    /// the frames start with, it also returns the log after them, to which
+    /// each frame adds the value it emitted, if any.
     pub fn synthesize_frames<CS: ConstraintSystem<F>>(
         &self,
         cs: &mut CS,
@@ -187,14 +215,22 @@ impl<'a, F: LurkField, C: Coprocessor<F>> MultiFrame<'a, F, C> {
         input_expr: AllocatedPtr<F>,
         input_env: AllocatedPtr<F>,
         input_cont: AllocatedContPtr<F>,
+        input_log: Option<AllocatedPtr<F>>,
         frames: &[CircuitFrame<'_, F, C>],
         g: &GlobalAllocations<F>,
-    ) -> (AllocatedPtr<F>, AllocatedPtr<F>, AllocatedContPtr<F>) {
+    ) -> (
+        AllocatedPtr<F>,
+        AllocatedPtr<F>,
+        AllocatedContPtr<F>,
+        Option<AllocatedPtr<F>>,
+    ) {
         if cs.is_witness_generator() && CONFIG.parallelism.synthesis.is_parallel() {
-            self.synthesize_frames_parallel(cs, store, input_expr, input_env, input_cont, frames, g)
+            self.synthesize_frames_parallel(
+                cs, store, input_expr, input_env, input_cont, input_log, frames, g,
+            )
         } else {
             self.synthesize_frames_sequential(
-                cs, store, input_expr, input_env, input_cont, frames, None, g,
+                cs, store, input_expr, input_env, input_cont, input_log, frames, None, g,
             )
         }
     }
@@ -206,13 +242,20 @@ impl<'a, F: LurkField, C: Coprocessor<F>> MultiFrame<'a, F, C> {
         input_expr: AllocatedPtr<F>,
         input_env: AllocatedPtr<F>,
         input_cont: AllocatedContPtr<F>,
+        input_log: Option<AllocatedPtr<F>>,
         frames: &[CircuitFrame<'_, F, C>],
         cons_and_cont_witnesses: Option<Vec<(ConsCircuitWitness<F>, ContCircuitWitness<F>)>>,
         g: &GlobalAllocations<F>,
-    ) -> (AllocatedPtr<F>, AllocatedPtr<F>, AllocatedContPtr<F>) {
+    ) -> (
+        AllocatedPtr<F>,
+        AllocatedPtr<F>,
+        AllocatedContPtr<F>,
+        Option<AllocatedPtr<F>>,
+    ) {
         let mut hash_circuit_witness_cache = HashMap::new();
 
         let acc = (input_expr, input_env, input_cont);
+        let mut log = input_log;
 
         let (_, (new_expr, new_env, new_cont)) =
             frames.iter().fold((0, acc), |(i, allocated_io), frame| {
@@ -277,10 +320,22 @@ impl<'a, F: LurkField, C: Coprocessor<F>> MultiFrame<'a, F, C> {
                     )
                     .unwrap();
 
+                log = log.take().map(|log| {
+                    extend_emit_log(
+                        &mut cs.namespace(|| format!("emit log {i}")),
+                        g,
+                        store,
+                        &new_allocated_io.0,
+                        &new_allocated_io.2,
+                        &log,
+                    )
+                    .unwrap()
+                });
+
                 (i + 1, new_allocated_io)
             });
 
-        (new_expr, new_env, new_cont)
+        (new_expr, new_env, new_cont, log)
     }
 
     pub fn synthesize_frames_parallel<CS: ConstraintSystem<F>>(
@@ -290,9 +345,15 @@ impl<'a, F: LurkField, C: Coprocessor<F>> MultiFrame<'a, F, C> {
         input_expr: AllocatedPtr<F>,
         input_env: AllocatedPtr<F>,
         input_cont: AllocatedContPtr<F>,
+        input_log: Option<AllocatedPtr<F>>,
         frames: &[CircuitFrame<'_, F, C>],
         g: &GlobalAllocations<F>,
-    ) -> (AllocatedPtr<F>, AllocatedPtr<F>, AllocatedContPtr<F>) {
+    ) -> (
+        AllocatedPtr<F>,
+        AllocatedPtr<F>,
+        AllocatedContPtr<F>,
+        Option<AllocatedPtr<F>>,
+    ) {
         assert!(cs.is_witness_generator());
         assert!(CONFIG.parallelism.synthesis.is_parallel());
 
@@ -306,12 +367,34 @@ impl<'a, F: LurkField, C: Coprocessor<F>> MultiFrame<'a, F, C> {
             .synthesis
             .chunk_size(num_frames, MIN_CHUNK_SIZE);
 
+        // the emit logs the chunks start with, computed out of the circuit
+        let chunk_logs = {
+            let mut log = self.emit_log;
+            frames
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    let start = log;
+                    log = log.and_then(|log| {
+                        chunk.iter().try_fold(log, |log, frame| {
+                            frame.output?.extend_emit_log(log, store).ok()
+                        })
+                    });
+                    start
+                })
+                .collect::<Vec<_>>()
+        };
+
         let css = frames
             .par_chunks(chunk_size)
             .enumerate()
             .map(|(i, chunk)| {
-                let (input_expr, input_env, input_cont) = if i == 0 {
-                    (input_expr.clone(), input_env.clone(), input_cont.clone())
+                let (input_expr, input_env, input_cont, input_log) = if i == 0 {
+                    (
+                        input_expr.clone(),
+                        input_env.clone(),
+                        input_cont.clone(),
+                        input_log.clone(),
+                    )
                 } else {
                     let previous_frame = &frames[i * chunk_size];
                     let mut bogus_cs = WitnessCS::new();
@@ -324,7 +407,13 @@ impl<'a, F: LurkField, C: Coprocessor<F>> MultiFrame<'a, F, C> {
                     let z = previous_frame.input.unwrap().cont;
                     let input_cont =
                         AllocatedContPtr::alloc_cont_ptr(&mut bogus_cs, store, || Ok(&z)).unwrap();
-                    (input_expr, input_env, input_cont)
+                    let input_log = input_log.as_ref().map(|_| {
+                        AllocatedPtr::alloc(&mut bogus_cs, || {
+                            chunk_logs[i].ok_or(SynthesisError::AssignmentMissing)
+                        })
+                        .unwrap()
+                    });
+                    (input_expr, input_env, input_cont, input_log)
                 };
 
                 let cons_and_cont_witnesses = {
@@ -375,6 +464,7 @@ impl<'a, F: LurkField, C: Coprocessor<F>> MultiFrame<'a, F, C> {
                     input_expr,
                     input_env,
                     input_cont,
+                    input_log,
                     chunk,
                     Some(cons_and_cont_witnesses),
                     g,
@@ -397,6 +487,46 @@ impl<'a, F: LurkField, C: Coprocessor<F>> MultiFrame<'a, F, C> {
     }
 }
 
+/// Adds the value emitted by the frame whose output has `expr` and `cont`, if
+/// any, to the emit log `log`. As in `IO::maybe_emitted_expression`, a frame
+/// emits a value when it outputs the dummy continuation and a thunk of the
+/// value whose continuation is `Emit`.
+fn extend_emit_log<F: LurkField, CS: ConstraintSystem<F>>(
+    cs: &mut CS,
+    g: &GlobalAllocations<F>,
+    store: &Store<F>,
+    expr: &AllocatedPtr<F>,
+    cont: &AllocatedContPtr<F>,
+    log: &AllocatedPtr<F>,
+) -> Result<AllocatedPtr<F>, SynthesisError> {
+    let (thunk_hash, value, thunk_cont) =
+        expr.allocate_thunk_components_unconstrained(cs.namespace(|| "thunk"), store)?;
+    let is_thunk = expr.is_thunk(&mut cs.namespace(|| "is_thunk"))?;
+    let cont_is_dummy = cont.alloc_tag_equal(
+        &mut cs.namespace(|| "cont_is_dummy"),
+        ContTag::Dummy.to_field(),
+    )?;
+    let is_dummy_thunk = Boolean::and(
+        &mut cs.namespace(|| "is_dummy_thunk"),
+        &is_thunk,
+        &cont_is_dummy,
+    )?;
+    constraints::implies_equal(
+        &mut cs.namespace(|| "thunk components are correct"),
+        &is_dummy_thunk,
+        expr.hash(),
+        &thunk_hash,
+    )?;
+    let thunk_emits = thunk_cont.alloc_tag_equal(
+        &mut cs.namespace(|| "thunk_emits"),
+        ContTag::Emit.to_field(),
+    )?;
+    let emits = Boolean::and(&mut cs.namespace(|| "emits"), &is_dummy_thunk, &thunk_emits)?;
+    let extended_log =
+        AllocatedPtr::construct_cons(cs.namespace(|| "extended_log"), g, store, &value, log)?;
+    AllocatedPtr::pick(cs.namespace(|| "new_log"), &emits, &extended_log, log)
+}
+
 impl<F: LurkField, C: Coprocessor<F>> CircuitFrame<'_, F, C> {
     pub fn precedes(&self, maybe_next: &Self) -> bool {
         self.output == maybe_next.input
@@ -566,8 +696,9 @@ impl<F: LurkField, C: Coprocessor<F>> Circuit<F> for MultiFrame<'_, F, C> {
             ////////////////////////////////////////////////////////////////////////////////
             let g = GlobalAllocations::new(&mut cs.namespace(|| "global_allocations"), store)?;
 
-            let (new_expr, new_env, new_cont) =
-                self.synthesize_frames(cs, store, input_expr, input_env, input_cont, frames, &g);
+            let (new_expr, new_env, new_cont, _) = self.synthesize_frames(
+                cs, store, input_expr, input_env, input_cont, None, frames, &g,
+            );
 
             output_expr.enforce_equal(
                 &mut cs.namespace(|| "outer output expr is correct"),
@@ -3281,6 +3412,7 @@ fn apply_continuation<F: LurkField, CS: ConstraintSystem<F>>(
         let (open_secret_scalar, open_expr_ptr) = store
             .get_maybe_opaque(ExprTag::Comm, digest.get_value().unwrap_or(F::ZERO))
             .and_then(|commit| store.open(commit))
+            .map(|(secret, payload)| (*secret.expose(), payload))
             .unwrap_or_else(|| {
                 // nil is dummy
                 (F::ZERO, lurk_sym_ptr!(store, nil))
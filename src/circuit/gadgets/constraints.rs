@@ -153,14 +153,46 @@ pub(crate) fn add_to_lc<F: PrimeField, CS: ConstraintSystem<F>>(
 /// If premise is true, enforce `a` fits into 64 bits. It shows a non-deterministic
 /// partial bit decomposition in order to constraint correct behavior.
 pub(crate) fn implies_u64<F: LurkField, CS: ConstraintSystem<F>>(
+    cs: CS,
+    premise: &Boolean,
+    a: &AllocatedNum<F>,
+) -> Result<(), SynthesisError> {
+    implies_fits_in_bits(cs, premise, a, 64, "u64")
+}
+
+/// If premise is true, enforce `a` fits into 32 bits, like `implies_u64`
+pub(crate) fn implies_u32<F: LurkField, CS: ConstraintSystem<F>>(
+    cs: CS,
+    premise: &Boolean,
+    a: &AllocatedNum<F>,
+) -> Result<(), SynthesisError> {
+    implies_fits_in_bits(cs, premise, a, 32, "u32")
+}
+
+/// If premise is true, enforce `a` fits into the 21 bits of a char, like
+/// `implies_u64`. Values in that range that aren't chars, such as surrogates,
+/// aren't ruled out.
+pub(crate) fn implies_char<F: LurkField, CS: ConstraintSystem<F>>(
+    cs: CS,
+    premise: &Boolean,
+    a: &AllocatedNum<F>,
+) -> Result<(), SynthesisError> {
+    implies_fits_in_bits(cs, premise, a, 21, "char")
+}
+
+/// If premise is true, enforce `a` fits into `n` bits, with `n` allocated bits
+/// and one packing constraint
+fn implies_fits_in_bits<F: LurkField, CS: ConstraintSystem<F>>(
     mut cs: CS,
     premise: &Boolean,
     a: &AllocatedNum<F>,
+    n: usize,
+    name: &str,
 ) -> Result<(), SynthesisError> {
     let mut a_u64 = a.get_value().and_then(|a| a.to_u64()).unwrap_or(0);
 
-    let mut bits: Vec<Boolean> = Vec::with_capacity(64);
-    for i in 0..64 {
+    let mut bits: Vec<Boolean> = Vec::with_capacity(n);
+    for i in 0..n {
         let b = a_u64 & 1;
         let b_bool = Boolean::Is(AllocatedBit::alloc(
             &mut cs.namespace(|| format!("b.{i}")),
@@ -173,7 +205,7 @@ pub(crate) fn implies_u64<F: LurkField, CS: ConstraintSystem<F>>(
 
     // premise -> a = sum(bits)
     implies_pack(
-        &mut cs.namespace(|| "u64 bit decomposition check"),
+        &mut cs.namespace(|| format!("{name} bit decomposition check")),
         premise,
         &bits,
         a,
@@ -1476,6 +1508,34 @@ mod tests {
         assert!(!cs.is_satisfied());
     }
 
+    #[test]
+    fn test_implies_u32_and_char() {
+        for (n, fits_u32, fits_char) in [
+            (0x10FFFF, true, true),
+            (0x200000, true, false),
+            (u32::MAX as u64, true, false),
+            (1 << 32, false, false),
+        ] {
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            let num = AllocatedNum::alloc(cs.namespace(|| "num"), || Ok(Fr::from(n))).unwrap();
+            let t = Boolean::Constant(true);
+            implies_u32(&mut cs.namespace(|| "enforce u32"), &t, &num).unwrap();
+            assert_eq!(fits_u32, cs.is_satisfied());
+
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            let num = AllocatedNum::alloc(cs.namespace(|| "num"), || Ok(Fr::from(n))).unwrap();
+            implies_char(&mut cs.namespace(|| "enforce char"), &t, &num).unwrap();
+            assert_eq!(fits_char, cs.is_satisfied());
+
+            // nothing is enforced without the premise
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            let num = AllocatedNum::alloc(cs.namespace(|| "num"), || Ok(Fr::from(n))).unwrap();
+            let f = Boolean::Constant(false);
+            implies_char(&mut cs.namespace(|| "enforce char"), &f, &num).unwrap();
+            assert!(cs.is_satisfied());
+        }
+    }
+
     proptest! {
         #[test]
         fn test_implies_u64(f in any::<FWrap<Fr>>()) {
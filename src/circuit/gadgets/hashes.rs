@@ -192,6 +192,8 @@ impl<'a, F: LurkField> HashConst<'a, F> {
             HashConst::A4(c) => hash!(c),
             HashConst::A6(c) => hash!(c),
             HashConst::A8(c) => hash!(c),
+            HashConst::A12(c) => hash!(c),
+            HashConst::A16(c) => hash!(c),
         }
     }
 }
@@ -208,6 +210,8 @@ impl<'a, F: LurkField> HashConst<'a, F> {
             HashConst::A4(c) => hash!(c),
             HashConst::A6(c) => hash!(c),
             HashConst::A8(c) => hash!(c),
+            HashConst::A12(c) => hash!(c),
+            HashConst::A16(c) => hash!(c),
         }
     }
 }
@@ -264,6 +268,8 @@ impl<'a, F: LurkField> HashConst<'a, F> {
             HashConst::A4(c) => hash!(c),
             HashConst::A6(c) => hash!(c),
             HashConst::A8(c) => hash!(c),
+            HashConst::A12(c) => hash!(c),
+            HashConst::A16(c) => hash!(c),
         }
     }
 }
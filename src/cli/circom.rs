@@ -75,17 +75,21 @@ fn get_circom_binary() -> Result<Command> {
     }
 }
 
-pub(crate) fn create_circom_gadget(circom_folder: Utf8PathBuf, name: String) -> Result<()> {
+pub(crate) fn create_circom_gadget(
+    circom_folder: Utf8PathBuf,
+    name: String,
+    field: Option<&str>,
+) -> Result<()> {
     let circom_gadget = circom_dir().join(&name);
     let circom_file = circom_folder.join(&name).with_extension("circom");
 
     // TODO: support for other fields
     let default_field = "vesta";
-    let field = if let Ok(lurk_field) = std::env::var("LURK_FIELD") {
-        // FG: The prime is actually the reverse of the field in $LURK_FIELD,
+    let field = if let Some(lurk_field) = field {
+        // FG: The prime is actually the reverse of the field in the `field` setting,
         // because circom and lurk have different semantics about which field should be specified
         // (circom wants the base field and lurk the scalar field).
-        match lurk_field.as_str() {
+        match lurk_field {
             "BLS12-381" => "bn128",
             "PALLAS" => "vesta",
             "VESTA" => "pallas",
@@ -2,6 +2,7 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
 use crate::field::LurkField;
+use crate::parser::dialect::ReaderDialect;
 use crate::z_store::ZStore;
 use crate::{ptr::Ptr, store::Store};
 
@@ -18,6 +19,10 @@ use super::{
 pub(crate) struct Commitment<F: LurkField> {
     pub(crate) hash: F,
     pub(crate) zstore: ZStore<F>,
+    /// The dialect the committed data was read with. Commitments made before
+    /// dialects existed were read with the default one.
+    #[serde(default)]
+    pub(crate) dialect: ReaderDialect,
 }
 
 impl<F: LurkField> HasFieldModulus for Commitment<F> {
@@ -27,7 +32,12 @@ impl<F: LurkField> HasFieldModulus for Commitment<F> {
 }
 
 impl<F: LurkField> Commitment<F> {
-    pub(crate) fn new(secret: Option<F>, payload: Ptr<F>, store: &mut Store<F>) -> Result<Self> {
+    pub(crate) fn new(
+        secret: Option<F>,
+        payload: Ptr<F>,
+        dialect: ReaderDialect,
+        store: &mut Store<F>,
+    ) -> Result<Self> {
         let comm_ptr = match secret {
             Some(secret) => store.hide(secret, payload),
             None => store.commit(payload),
@@ -35,7 +45,11 @@ impl<F: LurkField> Commitment<F> {
         let mut zstore = Some(ZStore::<F>::default());
         let hash = *store.get_z_expr(&comm_ptr, &mut zstore)?.0.value();
         let zstore = zstore.unwrap();
-        Ok(Self { hash, zstore })
+        Ok(Self {
+            hash,
+            zstore,
+            dialect,
+        })
     }
 }
 
@@ -43,6 +57,6 @@ impl<F: LurkField + Serialize> Commitment<F> {
     #[inline]
     pub(crate) fn persist(self) -> Result<()> {
         let hash_str = &self.hash.hex_digits();
-        dump(self, commitment_path(hash_str))
+        dump(self, commitment_path::<F>(hash_str))
     }
 }
@@ -0,0 +1,119 @@
+//! Inspection of serialized `ZStore`s, for `lurk inspect-zstore`, so that the
+//! data received along with a commitment can be examined without writing Rust.
+//! Z-pointers are shown in base58, as `ZPtr::to_base58` encodes them, and read
+//! in base58 or in base32.
+
+use anyhow::{anyhow, Result};
+use camino::Utf8Path;
+use serde::de::DeserializeOwned;
+use std::fs;
+
+use crate::{
+    field::LurkField,
+    hash::PoseidonCache,
+    state::initial_lurk_state,
+    store::Store,
+    writer::Write,
+    z_data::{from_z_data, ZData},
+    z_ptr::ZExprPtr,
+    z_store::ZStore,
+};
+
+/// Reads a `ZStore` serialized as versioned `ZData`, or as a chunked store
+pub(crate) fn read_z_store<F: LurkField + DeserializeOwned>(path: &Utf8Path) -> Result<ZStore<F>> {
+    let bytes = fs::read(path)?;
+    #[cfg(not(target_arch = "wasm32"))]
+    if bytes.starts_with(&crate::z_data::chunked::CHUNKED_MAGIC) {
+        return ZStore::read_chunked(std::io::Cursor::new(bytes));
+    }
+    let z_data = ZData::from_versioned_bytes(&bytes)?;
+    Ok(from_z_data(&z_data)?)
+}
+
+/// A line for each expression and continuation of `z_store`: its z-pointer
+/// followed by its contents, or by `opaque` if the `ZStore` doesn't have them
+pub(crate) fn list<F: LurkField>(z_store: &ZStore<F>) -> Vec<String> {
+    let exprs = z_store.expr_map.iter().map(|(z_ptr, z_expr)| match z_expr {
+        Some(z_expr) => format!("{z_ptr} {} {z_expr}", z_ptr.tag()),
+        None => format!("{z_ptr} {} opaque", z_ptr.tag()),
+    });
+    let conts = z_store.cont_map.iter().map(|(z_ptr, z_cont)| match z_cont {
+        Some(z_cont) => format!("{z_ptr} {} {z_cont:?}", z_ptr.tag()),
+        None => format!("{z_ptr} {} opaque", z_ptr.tag()),
+    });
+    exprs.chain(conts).collect()
+}
+
+/// A line for each expression and continuation of `z_store` whose contents
+/// don't hash to its z-pointer. The data is sound if there are none.
+pub(crate) fn verify<F: LurkField>(z_store: &ZStore<F>) -> Vec<String> {
+    let cache = PoseidonCache::default();
+    let exprs = z_store.expr_map.iter().filter_map(|(z_ptr, z_expr)| {
+        let hashed = z_expr.as_ref()?.z_ptr(&cache);
+        (hashed != *z_ptr).then(|| format!("{z_ptr} hashes to {hashed}"))
+    });
+    let conts = z_store.cont_map.iter().filter_map(|(z_ptr, z_cont)| {
+        let hashed = z_cont.as_ref()?.z_ptr(&cache);
+        (hashed != *z_ptr).then(|| format!("{z_ptr} hashes to {hashed}"))
+    });
+    exprs.chain(conts).collect()
+}
+
+/// The Lurk source of the expression `z_store` has at `z_ptr`, given in
+/// base58 or base32. The parts of it the `ZStore` doesn't have are printed as
+/// opaque.
+pub(crate) fn to_lurk_source<F: LurkField>(z_store: &ZStore<F>, z_ptr: &str) -> Result<String> {
+    let z_ptr = ZExprPtr::from_base58(z_ptr).or_else(|e| {
+        ZExprPtr::from_base32(z_ptr).map_err(|_| e.context(format!("invalid z-pointer {z_ptr}")))
+    })?;
+    let mut store = Store::default();
+    let ptr = store
+        .import_reachable(z_store, &[z_ptr])
+        .and_then(|ptrs| ptrs.first().copied())
+        .ok_or_else(|| anyhow!("{z_ptr} isn't in the ZStore"))?;
+    Ok(ptr.fmt_to_string(&store, initial_lurk_state()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::z_data::to_z_data;
+    use pasta_curves::pallas::Scalar as Fr;
+
+    #[test]
+    fn z_stores_are_inspected() {
+        let store = &mut Store::<Fr>::default();
+        let expr = store.read("(let ((x \"hi\")) (cons x 42))").unwrap();
+        let (z_store, z_ptrs) = store.export_reachable(&[expr]).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8Path::from_path(dir.path()).unwrap().join("data.zstore");
+        fs::write(&path, to_z_data(&z_store).unwrap().to_versioned_bytes()).unwrap();
+        let z_store = read_z_store::<Fr>(&path).unwrap();
+
+        assert_eq!(
+            list(&z_store).len(),
+            z_store.expr_map.len() + z_store.cont_map.len()
+        );
+        assert!(verify(&z_store).is_empty());
+        let source = to_lurk_source(&z_store, &z_ptrs[0].to_string()).unwrap();
+        assert_eq!(source, "(let ((x \"hi\")) (cons x 42))");
+        let source = to_lurk_source(&z_store, &z_ptrs[0].to_base32()).unwrap();
+        assert_eq!(source, "(let ((x \"hi\")) (cons x 42))");
+
+        let chunked = z_store
+            .write_chunked(std::io::Cursor::new(vec![]), 4)
+            .unwrap();
+        fs::write(&path, chunked.into_inner()).unwrap();
+        assert_eq!(read_z_store::<Fr>(&path).unwrap(), z_store);
+
+        let mut tampered = z_store.clone();
+        let z_expr = tampered
+            .expr_map
+            .values_mut()
+            .find_map(Option::as_mut)
+            .unwrap();
+        *z_expr = crate::z_expr::ZExpr::Num(Fr::from(7));
+        assert_eq!(verify(&tampered).len(), 1);
+    }
+}
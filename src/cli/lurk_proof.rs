@@ -1,22 +1,26 @@
 use ::nova::traits::Group;
 use abomonation::Abomonation;
-use anyhow::Result;
+use anyhow::{bail, Result};
 use pasta_curves::pallas::Scalar;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 use crate::{
     coprocessor::Coprocessor,
     eval::lang::{Coproc, Lang},
     field::LurkField,
-    proof::nova::{self, CurveCycleEquipped, G1, G2},
-    public_parameters::public_params,
+    proof::nova::{self, CurveCycleEquipped, PublicParams, G1, G2},
+    public_parameters::{
+        backend::{ImportedParams, Provenance},
+        public_params, public_params_with_backend,
+    },
     z_ptr::{ZContPtr, ZExprPtr},
     z_store::ZStore,
 };
 
 use crate::cli::{
     field_data::{dump, load},
-    paths::{proof_meta_path, proof_path, public_params_dir},
+    paths::{params_import, proof_meta_path, proof_path, public_params_dir},
 };
 
 use super::field_data::HasFieldModulus;
@@ -60,9 +64,34 @@ where
         num_steps: usize,
         rc: usize,
         lang: Lang<F, Coproc<F>>,
+        /// Where the parameters the proof was made with came from
+        provenance: Provenance,
     },
 }
 
+/// The public parameters the CLI proves and verifies with, for `rc` and
+/// `lang`, along with their provenance: imported from the material the
+/// settings name, if any, or produced by Nova's setup
+pub(crate) fn cli_public_params(
+    rc: usize,
+    lang: Arc<Lang<Scalar, Coproc<Scalar>>>,
+) -> Result<(
+    Arc<PublicParams<'static, Scalar, Coproc<Scalar>>>,
+    Provenance,
+)> {
+    match params_import() {
+        Some(path) => {
+            let backend = ImportedParams::new(&path)?;
+            let pp = public_params_with_backend(rc, true, lang, &public_params_dir(), &backend)?;
+            Ok((pp, backend.provenance()))
+        }
+        None => Ok((
+            public_params(rc, true, lang, &public_params_dir())?,
+            Provenance::Generated,
+        )),
+    }
+}
+
 impl<'a, F: CurveCycleEquipped> HasFieldModulus for LurkProof<'a, F>
 where
     Coproc<F>: Coprocessor<F>,
@@ -103,9 +132,15 @@ impl<'a> LurkProof<'a, Scalar> {
                 num_steps,
                 rc,
                 lang,
+                provenance,
             } => {
                 tracing::info!("Loading public parameters");
-                let pp = public_params(rc, true, std::sync::Arc::new(lang), &public_params_dir())?;
+                let (pp, verifier_provenance) = cli_public_params(rc, Arc::new(lang))?;
+                if provenance != verifier_provenance {
+                    bail!(
+                        "the proof was made with parameters {provenance}, but the verifier's are {verifier_provenance}"
+                    )
+                }
                 Ok(proof.verify(&pp, num_steps, &public_inputs, &public_outputs)?)
             }
         }
@@ -1,26 +1,27 @@
 mod circom;
 mod commitment;
 mod field_data;
+mod inspect;
 mod lurk_proof;
 pub mod paths;
 mod repl;
+mod scaffold;
 
 use anyhow::{bail, Context, Result};
 use camino::Utf8PathBuf;
 use clap::{Args, Parser, Subcommand};
-use config::{Config, Environment, File};
 use pasta_curves::pallas;
 
-use std::{collections::HashMap, fs};
+use std::fs;
 
 use crate::{
+    config::Settings,
     field::{LanguageField, LurkField},
     store::Store,
-    z_data::{from_z_data, ZData},
-    z_store::ZStore,
 };
 
 use crate::cli::{
+    inspect::read_z_store,
     paths::set_lurk_dirs,
     repl::{validate_non_zero, Backend, Repl},
 };
@@ -51,6 +52,10 @@ enum Command {
     /// See `lurk circom --help` for more details
     #[command(verbatim_doc_comment)]
     Circom(CircomArgs),
+    /// Lists, prints and verifies the contents of a ZStore file
+    InspectZstore(InspectZStoreArgs),
+    /// Generates a downstream crate serving commitments and proofs over HTTP
+    ScaffoldService(ScaffoldServiceArgs),
 }
 
 #[derive(Args, Debug)]
@@ -67,6 +72,10 @@ struct LoadArgs {
     #[arg(long)]
     prove: bool,
 
+    /// Flag to check that LEM agrees with the evaluation before proving it
+    #[arg(long)]
+    cross_check: bool,
+
     /// Config file, containing the lowest precedence parameters
     #[clap(long, value_parser)]
     config: Option<Utf8PathBuf>,
@@ -91,6 +100,11 @@ struct LoadArgs {
     #[clap(long, value_parser)]
     public_params_dir: Option<Utf8PathBuf>,
 
+    /// Parameter material to import instead of running Nova's setup, as
+    /// exported by `ImportedParams::export`
+    #[clap(long, value_parser)]
+    params_import: Option<Utf8PathBuf>,
+
     /// Path to proofs directory
     #[clap(long, value_parser)]
     proofs_dir: Option<Utf8PathBuf>,
@@ -115,6 +129,9 @@ struct LoadCli {
     #[arg(long)]
     prove: bool,
 
+    #[arg(long)]
+    cross_check: bool,
+
     #[clap(long, value_parser)]
     config: Option<Utf8PathBuf>,
 
@@ -133,6 +150,9 @@ struct LoadCli {
     #[clap(long, value_parser)]
     public_params_dir: Option<Utf8PathBuf>,
 
+    #[clap(long, value_parser)]
+    params_import: Option<Utf8PathBuf>,
+
     #[clap(long, value_parser)]
     proofs_dir: Option<Utf8PathBuf>,
 
@@ -149,12 +169,14 @@ impl LoadArgs {
             lurk_file: self.lurk_file,
             zstore: self.zstore,
             prove: self.prove,
+            cross_check: self.cross_check,
             config: self.config,
             rc: self.rc,
             limit: self.limit,
             backend: self.backend,
             field: self.field,
             public_params_dir: self.public_params_dir,
+            params_import: self.params_import,
             proofs_dir: self.proofs_dir,
             commits_dir: self.commits_dir,
             circom_dir: self.circom_dir,
@@ -172,6 +194,15 @@ struct ReplArgs {
     #[clap(long, value_parser)]
     load: Option<Utf8PathBuf>,
 
+    /// Script to be run instead of entering the REPL. Failed assertions don't stop it, but make
+    /// the process exit with a non-zero code once it's done
+    #[clap(long, value_parser)]
+    script: Option<Utf8PathBuf>,
+
+    /// Flag to check that LEM agrees with evaluations before proving them
+    #[arg(long)]
+    cross_check: bool,
+
     /// Config file, containing the lowest precedence parameters
     #[clap(long, value_parser)]
     config: Option<Utf8PathBuf>,
@@ -196,6 +227,11 @@ struct ReplArgs {
     #[clap(long, value_parser)]
     public_params_dir: Option<Utf8PathBuf>,
 
+    /// Parameter material to import instead of running Nova's setup, as
+    /// exported by `ImportedParams::export`
+    #[clap(long, value_parser)]
+    params_import: Option<Utf8PathBuf>,
+
     /// Path to proofs directory
     #[clap(long, value_parser)]
     proofs_dir: Option<Utf8PathBuf>,
@@ -214,6 +250,12 @@ struct ReplCli {
     #[clap(long, value_parser)]
     load: Option<Utf8PathBuf>,
 
+    #[clap(long, value_parser)]
+    script: Option<Utf8PathBuf>,
+
+    #[arg(long)]
+    cross_check: bool,
+
     #[clap(long, value_parser)]
     zstore: Option<Utf8PathBuf>,
 
@@ -235,6 +277,9 @@ struct ReplCli {
     #[clap(long, value_parser)]
     public_params_dir: Option<Utf8PathBuf>,
 
+    #[clap(long, value_parser)]
+    params_import: Option<Utf8PathBuf>,
+
     #[clap(long, value_parser)]
     proofs_dir: Option<Utf8PathBuf>,
 
@@ -249,6 +294,8 @@ impl ReplArgs {
     fn into_cli(self) -> ReplCli {
         ReplCli {
             load: self.load,
+            script: self.script,
+            cross_check: self.cross_check,
             zstore: self.zstore,
             config: self.config,
             rc: self.rc,
@@ -256,6 +303,7 @@ impl ReplArgs {
             backend: self.backend,
             field: self.field,
             public_params_dir: self.public_params_dir,
+            params_import: self.params_import,
             proofs_dir: self.proofs_dir,
             commits_dir: self.commits_dir,
             circom_dir: self.circom_dir,
@@ -288,48 +336,10 @@ fn parse_filename(file: &str) -> Result<Utf8PathBuf> {
     Ok(path)
 }
 
-fn get_parsed_usize(
-    param_name: &str,
-    arg: &Option<usize>,
-    config: &HashMap<String, String>,
-    default: usize,
-) -> Result<usize> {
-    match arg {
-        Some(arg) => Ok(*arg),
-        None => match config.get(param_name) {
-            None => Ok(default),
-            Some(arg_str) => Ok(arg_str.parse::<usize>()?),
-        },
-    }
-}
-
-fn get_parsed<T>(
-    param_name: &str,
-    arg: &Option<String>,
-    config: &HashMap<String, String>,
-    parse_fn: fn(&String) -> Result<T>,
-    default: T,
-) -> Result<T> {
-    match arg {
-        Some(arg) => parse_fn(arg),
-        None => match config.get(param_name) {
-            None => Ok(default),
-            Some(arg) => parse_fn(arg),
-        },
-    }
-}
-
-pub fn get_config(config_path: &Option<Utf8PathBuf>) -> Result<HashMap<String, String>> {
-    // First load from the config file
-    let builder = match config_path {
-        Some(config_path) if config_path.exists() => {
-            Config::builder().add_source(File::with_name(config_path.as_str()))
-        }
-        _ => Config::builder(),
-    };
-    // Then potentially overwrite with environment variables
-    let builder = builder.add_source(Environment::with_prefix("LURK"));
-    Ok(builder.build()?.try_deserialize()?)
+/// Reads the settings from `config_path`, or from the config file in the data
+/// dir, and from the environment
+pub fn get_config(config_path: &Option<Utf8PathBuf>) -> Result<Settings> {
+    Settings::load(config_path.as_deref())
 }
 
 fn get_store<F: LurkField + for<'a> serde::de::Deserialize<'a>>(
@@ -337,12 +347,7 @@ fn get_store<F: LurkField + for<'a> serde::de::Deserialize<'a>>(
 ) -> Result<Store<F>> {
     match zstore_path {
         None => Ok(Store::default()),
-        Some(zstore_path) => {
-            let bytes = fs::read(zstore_path)?;
-            let zdata = ZData::from_bytes(&bytes)?;
-            let zstore: ZStore<F> = from_z_data(&zdata)?;
-            Ok(zstore.to_store())
-        }
+        Some(zstore_path) => Ok(read_z_store::<F>(zstore_path)?.to_store()),
     }
 }
 
@@ -359,37 +364,40 @@ impl ReplCli {
         macro_rules! repl {
             ( $rc: expr, $limit: expr, $field: path, $backend: expr ) => {{
                 let mut repl = new_repl!(self, $rc, $limit, $field, $backend);
+                repl.set_cross_check(self.cross_check);
                 if let Some(lurk_file) = &self.load {
                     repl.load_file(lurk_file)?;
                 }
-                repl.start()
+                match &self.script {
+                    Some(script) => repl.run_script(script),
+                    None => repl.start(),
+                }
             }};
         }
-        let config = get_config(&self.config)?;
-        tracing::info!("Configured variables: {:?}", config);
-        set_lurk_dirs(
-            &config,
-            &self.public_params_dir,
-            &self.proofs_dir,
-            &self.commits_dir,
-            &self.circom_dir,
-        );
-        let rc = get_parsed_usize("rc", &self.rc, &config, DEFAULT_RC)?;
-        let limit = get_parsed_usize("limit", &self.limit, &config, DEFAULT_LIMIT)?;
-        let backend = get_parsed(
-            "backend",
-            &self.backend,
-            &config,
-            parse_backend,
-            DEFAULT_BACKEND,
-        )?;
-        let field = get_parsed(
-            "field",
-            &self.field,
-            &config,
-            parse_field,
-            backend.default_field(),
-        )?;
+        let settings = get_config(&self.config)?.with_flags(Settings {
+            field: self.field.clone(),
+            backend: self.backend.clone(),
+            rc: self.rc,
+            limit: self.limit,
+            public_params: self.public_params_dir.clone(),
+            params_import: self.params_import.clone(),
+            proofs: self.proofs_dir.clone(),
+            commits: self.commits_dir.clone(),
+            circom: self.circom_dir.clone(),
+            ..Default::default()
+        });
+        tracing::info!("Configured variables: {:?}", settings);
+        set_lurk_dirs(&settings);
+        let rc = settings.rc.unwrap_or(DEFAULT_RC);
+        let limit = settings.limit.unwrap_or(DEFAULT_LIMIT);
+        let backend = settings
+            .backend
+            .as_ref()
+            .map_or(Ok(DEFAULT_BACKEND), parse_backend)?;
+        let field = settings
+            .field
+            .as_ref()
+            .map_or_else(|| Ok(backend.default_field()), parse_field)?;
         validate_non_zero("rc", rc)?;
         backend.validate_field(&field)?;
         match field {
@@ -409,6 +417,7 @@ impl LoadCli {
         macro_rules! load {
             ( $rc: expr, $limit: expr, $field: path, $backend: expr ) => {{
                 let mut repl = new_repl!(self, $rc, $limit, $field, $backend);
+                repl.set_cross_check(self.cross_check);
                 repl.load_file(&self.lurk_file)?;
                 if self.prove {
                     repl.prove_last_frames()?;
@@ -416,31 +425,30 @@ impl LoadCli {
                 Ok(())
             }};
         }
-        let config = get_config(&self.config)?;
-        tracing::info!("Configured variables: {:?}", config);
-        set_lurk_dirs(
-            &config,
-            &self.public_params_dir,
-            &self.proofs_dir,
-            &self.commits_dir,
-            &self.circom_dir,
-        );
-        let rc = get_parsed_usize("rc", &self.rc, &config, DEFAULT_RC)?;
-        let limit = get_parsed_usize("limit", &self.limit, &config, DEFAULT_LIMIT)?;
-        let backend = get_parsed(
-            "backend",
-            &self.backend,
-            &config,
-            parse_backend,
-            DEFAULT_BACKEND,
-        )?;
-        let field = get_parsed(
-            "field",
-            &self.field,
-            &config,
-            parse_field,
-            backend.default_field(),
-        )?;
+        let settings = get_config(&self.config)?.with_flags(Settings {
+            field: self.field.clone(),
+            backend: self.backend.clone(),
+            rc: self.rc,
+            limit: self.limit,
+            public_params: self.public_params_dir.clone(),
+            params_import: self.params_import.clone(),
+            proofs: self.proofs_dir.clone(),
+            commits: self.commits_dir.clone(),
+            circom: self.circom_dir.clone(),
+            ..Default::default()
+        });
+        tracing::info!("Configured variables: {:?}", settings);
+        set_lurk_dirs(&settings);
+        let rc = settings.rc.unwrap_or(DEFAULT_RC);
+        let limit = settings.limit.unwrap_or(DEFAULT_LIMIT);
+        let backend = settings
+            .backend
+            .as_ref()
+            .map_or(Ok(DEFAULT_BACKEND), parse_backend)?;
+        let field = settings
+            .field
+            .as_ref()
+            .map_or_else(|| Ok(backend.default_field()), parse_field)?;
         validate_non_zero("rc", rc)?;
         backend.validate_field(&field)?;
         match field {
@@ -469,6 +477,11 @@ struct VerifyArgs {
     #[clap(long, value_parser)]
     public_params_dir: Option<Utf8PathBuf>,
 
+    /// Parameter material to import instead of running Nova's setup, as
+    /// exported by `ImportedParams::export`
+    #[clap(long, value_parser)]
+    params_import: Option<Utf8PathBuf>,
+
     /// Path to proofs directory
     #[clap(long, value_parser)]
     proofs_dir: Option<Utf8PathBuf>,
@@ -502,6 +515,85 @@ struct CircomArgs {
     circom_dir: Option<Utf8PathBuf>,
 }
 
+/// With neither `--print` nor `--verify`, lists the z-pointers of the ZStore,
+/// in base58, along with the data they point to
+#[derive(Args, Debug)]
+struct InspectZStoreArgs {
+    /// The ZStore file to be inspected
+    #[clap(value_parser)]
+    zstore: Utf8PathBuf,
+
+    /// Z-pointers, in base58 or base32, whose expressions are printed as Lurk source
+    #[clap(long, value_parser)]
+    print: Vec<String>,
+
+    /// Writes the expressions of `--print` to this file instead of printing them
+    #[clap(long, value_parser)]
+    export: Option<Utf8PathBuf>,
+
+    /// Flag to check that the data of every z-pointer hashes to it
+    #[arg(long)]
+    verify: bool,
+}
+
+#[derive(Args, Debug)]
+struct ScaffoldServiceArgs {
+    /// The name of the service's crate
+    #[clap(value_parser)]
+    name: String,
+
+    /// The directory of the crate (defaults to `./<NAME>`)
+    #[clap(long, value_parser)]
+    dir: Option<Utf8PathBuf>,
+
+    /// A local Lurk checkout for the crate to depend on, instead of the git
+    /// repository
+    #[clap(long, value_parser)]
+    lurk_path: Option<Utf8PathBuf>,
+
+    /// Reduction count used for proofs (defaults to 10)
+    #[clap(long, value_parser)]
+    rc: Option<usize>,
+}
+
+impl InspectZStoreArgs {
+    fn run(&self) -> Result<()> {
+        use crate::cli::inspect::{list, to_lurk_source, verify};
+        let z_store = read_z_store::<pallas::Scalar>(&self.zstore)
+            .with_context(|| "reading store from file")?;
+        if self.print.is_empty() && !self.verify {
+            for line in list(&z_store) {
+                println!("{line}");
+            }
+            return Ok(());
+        }
+        if self.verify {
+            let mismatches = verify(&z_store);
+            for line in &mismatches {
+                println!("{line}");
+            }
+            if !mismatches.is_empty() {
+                bail!("{} z-pointers don't match their data", mismatches.len())
+            }
+            println!("All z-pointers match their data");
+        }
+        let sources = self
+            .print
+            .iter()
+            .map(|z_ptr| to_lurk_source(&z_store, z_ptr))
+            .collect::<Result<Vec<_>>>()?;
+        match &self.export {
+            Some(path) if !sources.is_empty() => {
+                let mut out = sources.join("\n");
+                out.push('\n');
+                fs::write(path, out)?
+            }
+            _ => sources.iter().for_each(|source| println!("{source}")),
+        }
+        Ok(())
+    }
+}
+
 impl Cli {
     fn run(self) -> Result<()> {
         match self.command {
@@ -510,15 +602,14 @@ impl Cli {
             #[allow(unused_variables)]
             Command::Verify(verify_args) => {
                 use crate::cli::lurk_proof::LurkProof;
-                let config = get_config(&verify_args.config)?;
-                tracing::info!("Configured variables: {:?}", config);
-                set_lurk_dirs(
-                    &config,
-                    &verify_args.public_params_dir,
-                    &verify_args.proofs_dir,
-                    &None,
-                    &None,
-                );
+                let settings = get_config(&verify_args.config)?.with_flags(Settings {
+                    public_params: verify_args.public_params_dir,
+                    params_import: verify_args.params_import,
+                    proofs: verify_args.proofs_dir,
+                    ..Default::default()
+                });
+                tracing::info!("Configured variables: {:?}", settings);
+                set_lurk_dirs(&settings);
                 LurkProof::verify_proof(&verify_args.proof_id)?;
                 Ok(())
             }
@@ -528,11 +619,26 @@ impl Cli {
                     bail!("Circom gadget name cannot be `main`, see circom documentation")
                 }
 
-                let config = get_config(&circom_args.config)?;
-                tracing::info!("Configured variables: {:?}", config);
-                set_lurk_dirs(&config, &None, &None, &None, &circom_args.circom_dir);
-
-                create_circom_gadget(circom_args.circom_folder, circom_args.name)?;
+                let settings = get_config(&circom_args.config)?.with_flags(Settings {
+                    circom: circom_args.circom_dir,
+                    ..Default::default()
+                });
+                tracing::info!("Configured variables: {:?}", settings);
+                set_lurk_dirs(&settings);
+
+                create_circom_gadget(
+                    circom_args.circom_folder,
+                    circom_args.name,
+                    settings.field.as_deref(),
+                )?;
+                Ok(())
+            }
+            Command::InspectZstore(inspect_args) => inspect_args.run(),
+            Command::ScaffoldService(args) => {
+                let dir = args.dir.unwrap_or_else(|| args.name.clone().into());
+                let rc = args.rc.unwrap_or(DEFAULT_RC);
+                scaffold::scaffold_service(&dir, &args.name, args.lurk_path.as_deref(), rc)?;
+                println!("Scaffolded {} in {dir}", args.name);
                 Ok(())
             }
         }
@@ -2,9 +2,11 @@ use anyhow::Result;
 use camino::{Utf8Path, Utf8PathBuf};
 use once_cell::sync::OnceCell;
 
-use std::collections::HashMap;
 use std::fs;
 
+use crate::artifacts::{ArtifactKind, ArtifactName, Artifacts};
+use crate::config::{lurk_data_dir, Settings};
+use crate::field::LurkField;
 use crate::public_parameters::public_params_default_dir;
 
 pub(crate) static LURK_DIRS: OnceCell<LurkDirs> = OnceCell::new();
@@ -14,55 +16,19 @@ pub(crate) struct LurkDirs {
     proofs: Utf8PathBuf,
     commits: Utf8PathBuf,
     circom: Utf8PathBuf,
+    params_import: Option<Utf8PathBuf>,
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-fn home_dir() -> Utf8PathBuf {
-    Utf8PathBuf::from_path_buf(home::home_dir().expect("missing home directory"))
-        .expect("path contains invalid Unicode")
-}
-
-#[cfg(not(target_arch = "wasm32"))]
-fn lurk_dir() -> Utf8PathBuf {
-    home_dir().join(Utf8Path::new(".lurk"))
-}
-
-#[cfg(target_arch = "wasm32")]
-pub(crate) fn lurk_dir() -> Utf8PathBuf {
-    Utf8PathBuf::from(".lurk")
-}
-
-#[cfg(not(target_arch = "wasm32"))]
 pub(crate) fn proofs_default_dir() -> Utf8PathBuf {
-    let home = home::home_dir().unwrap();
-    Utf8PathBuf::from_path_buf(home.join(".lurk/proofs")).expect("path contains invalid Unicode")
-}
-
-#[cfg(target_arch = "wasm32")]
-pub(crate) fn proofs_default_dir() -> Utf8PathBuf {
-    Utf8PathBuf::from(".lurk/public_params")
-}
-
-#[cfg(not(target_arch = "wasm32"))]
-pub(crate) fn commits_default_dir() -> Utf8PathBuf {
-    let home = home::home_dir().unwrap();
-    Utf8PathBuf::from_path_buf(home.join(".lurk/commits")).expect("path contains invalid Unicode")
+    lurk_data_dir().join("proofs")
 }
 
-#[cfg(target_arch = "wasm32")]
 pub(crate) fn commits_default_dir() -> Utf8PathBuf {
-    Utf8PathBuf::from(".lurk/commits")
+    lurk_data_dir().join("commits")
 }
 
-#[cfg(not(target_arch = "wasm32"))]
 pub(crate) fn circom_default_dir() -> Utf8PathBuf {
-    let home = home::home_dir().unwrap();
-    Utf8PathBuf::from_path_buf(home.join(".lurk/circom")).expect("path contains invalid Unicode")
-}
-
-#[cfg(target_arch = "wasm32")]
-pub(crate) fn circom_default_dir() -> Utf8PathBuf {
-    Utf8PathBuf::from(".lurk/circom")
+    lurk_data_dir().join("circom")
 }
 
 pub(crate) fn public_params_dir() -> Utf8PathBuf {
@@ -73,6 +39,15 @@ pub(crate) fn public_params_dir() -> Utf8PathBuf {
         .to_owned()
 }
 
+/// The parameter material to import, if the settings name one
+pub(crate) fn params_import() -> Option<Utf8PathBuf> {
+    LURK_DIRS
+        .get()
+        .expect("failed to initialize beforehand with `set_lurk_dirs()`")
+        .params_import
+        .to_owned()
+}
+
 pub(crate) fn proofs_dir() -> Utf8PathBuf {
     LURK_DIRS
         .get()
@@ -106,35 +81,22 @@ fn lurk_leaf_dirs() -> [Utf8PathBuf; 4] {
     ]
 }
 
-pub(crate) fn set_lurk_dirs(
-    config: &HashMap<String, String>,
-    public_params_dir: &Option<Utf8PathBuf>,
-    proofs_dir: &Option<Utf8PathBuf>,
-    commits_dir: &Option<Utf8PathBuf>,
-    circom_dir: &Option<Utf8PathBuf>,
-) {
-    let get_path = |given_path: &Option<Utf8PathBuf>, config_key: &str, default: Utf8PathBuf| {
-        given_path.clone().unwrap_or_else(|| {
-            config
-                .get(config_key)
-                .map_or_else(|| default, Utf8PathBuf::from)
-        })
+pub(crate) fn set_lurk_dirs(settings: &Settings) {
+    let get_path = |path: &Option<Utf8PathBuf>, default: fn() -> Utf8PathBuf| {
+        path.clone().unwrap_or_else(default)
     };
 
-    let public_params = get_path(
-        public_params_dir,
-        "public_params",
-        public_params_default_dir(),
-    );
-    let proofs = get_path(proofs_dir, "proofs", proofs_default_dir());
-    let commits = get_path(commits_dir, "commits", commits_default_dir());
-    let circom = get_path(circom_dir, "circom", circom_default_dir());
+    let public_params = get_path(&settings.public_params, public_params_default_dir);
+    let proofs = get_path(&settings.proofs, proofs_default_dir);
+    let commits = get_path(&settings.commits, commits_default_dir);
+    let circom = get_path(&settings.circom, circom_default_dir);
 
     LURK_DIRS.get_or_init(|| LurkDirs {
         public_params,
         proofs,
         commits,
         circom,
+        params_import: settings.params_import.clone(),
     });
 
     create_lurk_dirs().unwrap();
@@ -150,23 +112,34 @@ pub(crate) fn create_lurk_dirs() -> Result<()> {
 
 // Not currently configurable
 pub(crate) fn repl_history() -> Utf8PathBuf {
-    lurk_dir().join(Utf8Path::new("repl-history"))
+    lurk_data_dir().join(Utf8Path::new("repl-history"))
 }
 
-pub(crate) fn commitment_path(name: &str) -> Utf8PathBuf {
-    commits_dir().join(Utf8Path::new(&format!("{name}.commit")))
+pub(crate) fn commitment_path<F: LurkField>(hash: &str) -> Utf8PathBuf {
+    let name = ArtifactName::new(ArtifactKind::Commitment, F::FIELD, hash);
+    Artifacts::new(commits_dir())
+        .with_legacy_names(|name| format!("{}.commit", name.hash))
+        .resolve(&name)
+}
+
+/// The canonical name of a proof is its key. Keys that don't parse as names
+/// were given to proofs before names were canonical, which were stored as
+/// `<key>.proof` and `<key>.meta`
+fn proof_artifact_path(key: &str, kind: ArtifactKind, legacy_extension: &str) -> Utf8PathBuf {
+    match key.parse::<ArtifactName>() {
+        Ok(name) => Artifacts::new(proofs_dir()).path(&ArtifactName { kind, ..name }),
+        Err(_) => proofs_dir()
+            .join(Utf8Path::new(key))
+            .with_extension(legacy_extension),
+    }
 }
 
-pub(crate) fn proof_path(name: &str) -> Utf8PathBuf {
-    proofs_dir()
-        .join(Utf8Path::new(name))
-        .with_extension("proof")
+pub(crate) fn proof_path(key: &str) -> Utf8PathBuf {
+    proof_artifact_path(key, ArtifactKind::Proof, "proof")
 }
 
-pub(crate) fn proof_meta_path(name: &str) -> Utf8PathBuf {
-    proofs_dir()
-        .join(Utf8Path::new(name))
-        .with_extension("meta")
+pub(crate) fn proof_meta_path(key: &str) -> Utf8PathBuf {
+    proof_artifact_path(key, ArtifactKind::ProofMeta, "meta")
 }
 
 pub(crate) fn circom_binary_path() -> Utf8PathBuf {
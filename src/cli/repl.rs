@@ -18,18 +18,23 @@ use tracing::info;
 use super::{commitment::Commitment, field_data::load, paths::commitment_path};
 
 use crate::{
-    cli::paths::{proof_path, public_params_dir},
+    artifacts::{ArtifactKind, ArtifactName},
+    cli::paths::proof_path,
     eval::{
         lang::{Coproc, Lang},
+        trace::{cont_tag_from_name, TraceQuery},
         Evaluator, Frame, Witness, IO,
     },
     field::{LanguageField, LurkField},
+    lem::{compare_backends, cross_check},
     lurk_sym_ptr,
     package::{Package, SymbolRef},
-    parser,
+    parser::{
+        self,
+        dialect::{CaseFolding, ReaderDialect, StringEscapes},
+    },
     proof::{nova::NovaProver, Prover},
     ptr::Ptr,
-    public_parameters::public_params,
     state::State,
     store::Store,
     tag::{ContTag, ExprTag},
@@ -39,7 +44,7 @@ use crate::{
     Num, Symbol,
 };
 
-use super::lurk_proof::{LurkProof, LurkProofMeta};
+use super::lurk_proof::{cli_public_params, LurkProof, LurkProofMeta};
 
 #[derive(Completer, Helper, Highlighter, Hinter)]
 struct InputValidator {
@@ -114,6 +119,10 @@ pub(crate) struct Repl<F: LurkField> {
     limit: usize,
     backend: Backend,
     evaluation: Option<Evaluation<F>>,
+    /// The failed assertions of the script being run, if any
+    script_failures: Option<Vec<String>>,
+    /// Whether evaluations are cross-checked with LEM before being proved
+    cross_check: bool,
 }
 
 pub(crate) fn validate_non_zero(name: &str, x: usize) -> Result<()> {
@@ -155,9 +164,17 @@ impl Repl<F> {
             limit,
             backend,
             evaluation: None,
+            script_failures: None,
+            cross_check: false,
         }
     }
 
+    /// Makes proving first check that LEM evaluates the last expression like
+    /// the legacy evaluator did, failing with a report if it doesn't
+    pub(crate) fn set_cross_check(&mut self, cross_check: bool) {
+        self.cross_check = cross_check;
+    }
+
     #[allow(dead_code)]
     fn proof_claim(
         store: &mut Store<F>,
@@ -195,8 +212,8 @@ impl Repl<F> {
 
     #[allow(dead_code)]
     fn proof_key(backend: &Backend, rc: &usize, claim_hash: &str) -> String {
-        let field = F::FIELD;
-        format!("{backend}_{field}_{rc}_{claim_hash}")
+        let hash = format!("{backend}_{rc}_{claim_hash}");
+        ArtifactName::new(ArtifactKind::Proof, F::FIELD, hash).to_string()
     }
 
     pub(crate) fn prove_last_frames(&mut self) -> Result<()> {
@@ -227,7 +244,12 @@ impl Repl<F> {
                         (cont.parts(), cont_out.parts()),
                     );
 
-                    let claim_comm = Commitment::new(None, claim, &mut self.store)?;
+                    let claim_comm = Commitment::new(
+                        None,
+                        claim,
+                        self.state.borrow().dialect().clone(),
+                        &mut self.store,
+                    )?;
                     let claim_hash = &claim_comm.hash.hex_digits();
                     let proof_key = &Self::proof_key(&self.backend, &self.rc, claim_hash);
                     let proof_path = proof_path(proof_key);
@@ -237,6 +259,14 @@ impl Repl<F> {
                         // TODO: make sure that the proof file is not corrupted
                     } else {
                         info!("Proof not cached");
+                        if self.cross_check {
+                            info!("Cross-checking the evaluation with LEM");
+                            let divergence =
+                                cross_check(&self.store, input, output, *iterations, self.limit)?;
+                            if let Some(divergence) = divergence {
+                                bail!("{divergence}")
+                            }
+                        }
                         // padding the frames, if needed
                         let n_pad = pad(n_frames, self.rc) - n_frames;
                         if n_pad != 0 {
@@ -245,8 +275,7 @@ impl Repl<F> {
                         }
 
                         info!("Loading public parameters");
-                        let pp =
-                            public_params(self.rc, true, self.lang.clone(), &public_params_dir())?;
+                        let (pp, provenance) = cli_public_params(self.rc, self.lang.clone())?;
 
                         let prover = NovaProver::new(self.rc, (*self.lang).clone());
 
@@ -265,6 +294,7 @@ impl Repl<F> {
                             num_steps,
                             rc: self.rc,
                             lang: (*self.lang).clone(),
+                            provenance,
                         };
 
                         let lurk_proof_meta = LurkProofMeta {
@@ -292,7 +322,8 @@ impl Repl<F> {
     }
 
     fn hide(&mut self, secret: F, payload: Ptr<F>) -> Result<()> {
-        let commitment = Commitment::new(Some(secret), payload, &mut self.store)?;
+        let dialect = self.state.borrow().dialect().clone();
+        let commitment = Commitment::new(Some(secret), payload, dialect, &mut self.store)?;
         let hash_str = &commitment.hash.hex_digits();
         commitment.persist()?;
         println!(
@@ -303,11 +334,18 @@ impl Repl<F> {
     }
 
     fn fetch(&mut self, hash: &F, print_data: bool) -> Result<()> {
-        let commitment: Commitment<F> = load(commitment_path(&hash.hex_digits()))?;
+        let commitment: Commitment<F> = load(commitment_path::<F>(&hash.hex_digits()))?;
         let comm_hash = commitment.hash;
         if &comm_hash != hash {
             bail!("Hash mismatch. Corrupted commitment file.")
         } else {
+            if &commitment.dialect != self.state.borrow().dialect() {
+                println!(
+                    "Warning: commitment was read with dialect ({}), current dialect is ({})",
+                    commitment.dialect,
+                    self.state.borrow().dialect()
+                );
+            }
             // create a ZExprPtr with the intended hash
             let comm_zptr = &ZExprPtr::from_parts(ExprTag::Comm, comm_hash);
             // populate the REPL's store with the data
@@ -370,6 +408,15 @@ impl Repl<F> {
         Ok((last_output, iterations))
     }
 
+    /// Reports a failed assertion, which is fatal unless a script is being run
+    fn fail(&mut self, msg: String) {
+        eprintln!("{msg}");
+        match &mut self.script_failures {
+            Some(failures) => failures.push(msg),
+            None => process::exit(1),
+        }
+    }
+
     fn peek1(&self, cmd: &str, args: &Ptr<F>) -> Result<Ptr<F>> {
         let (first, rest) = self.store.car_cdr(args)?;
         if !rest.is_nil() {
@@ -422,6 +469,92 @@ impl Repl<F> {
         }
     }
 
+    fn get_usize(&self, ptr: &Ptr<F>) -> Result<usize> {
+        match self
+            .store
+            .fetch_num(ptr)
+            .and_then(|n| n.into_scalar().to_u64())
+        {
+            None => bail!(
+                "Expected a non-negative integer. Got {}",
+                ptr.fmt_to_string(&self.store, &self.state.borrow())
+            ),
+            Some(n) => Ok(n as usize),
+        }
+    }
+
+    /// Builds a `TraceQuery` from a property list such as
+    /// `:head if :cont call :from 10 :to 20 :limit 5`
+    fn trace_query(&self, mut args: Ptr<F>) -> Result<TraceQuery> {
+        let mut query = TraceQuery::new();
+        let (mut from, mut to) = (None, None);
+        while !args.is_nil() {
+            let (key, rest) = self.store.car_cdr(&args)?;
+            let (val, rest) = self.store.car_cdr(&rest)?;
+            let Some(key) = self.store.fetch_key(&key) else {
+                bail!(
+                    "Expected keyword. Got {}",
+                    key.fmt_to_string(&self.store, &self.state.borrow())
+                )
+            };
+            match key.name()? {
+                "head" => query = query.expr_head(self.get_symbol(&val)?),
+                "cont" => {
+                    let name = self.get_symbol(&val)?;
+                    let Some(tag) = cont_tag_from_name(name.name()?) else {
+                        bail!("Unknown continuation tag: {}", name.name()?)
+                    };
+                    query = query.cont_tag(tag);
+                }
+                "from" => from = Some(self.get_usize(&val)?),
+                "to" => to = Some(self.get_usize(&val)?),
+                "limit" => query = query.limit(self.get_usize(&val)?),
+                k => bail!("Unsupported query key: {k}"),
+            }
+            args = rest;
+        }
+        if from.is_some() || to.is_some() {
+            query = query.iterations(from.unwrap_or(0)..to.unwrap_or(usize::MAX));
+        }
+        Ok(query)
+    }
+
+    /// Updates the current reader dialect from a property list such as
+    /// `:case lower :escapes raw :package .lurk.user`
+    fn reader_dialect(&self, mut args: Ptr<F>) -> Result<ReaderDialect> {
+        let mut dialect = self.state.borrow().dialect().clone();
+        while !args.is_nil() {
+            let (key, rest) = self.store.car_cdr(&args)?;
+            let (val, rest) = self.store.car_cdr(&rest)?;
+            let Some(key) = self.store.fetch_key(&key) else {
+                bail!(
+                    "Expected keyword. Got {}",
+                    key.fmt_to_string(&self.store, &self.state.borrow())
+                )
+            };
+            match key.name()? {
+                "case" => {
+                    dialect.case_folding = match self.get_symbol(&val)?.name()? {
+                        "preserve" => CaseFolding::Preserve,
+                        "lower" => CaseFolding::Lower,
+                        c => bail!("Unknown case folding: {c}"),
+                    }
+                }
+                "escapes" => {
+                    dialect.string_escapes = match self.get_symbol(&val)?.name()? {
+                        "standard" => StringEscapes::Standard,
+                        "raw" => StringEscapes::Raw,
+                        e => bail!("Unknown string escapes: {e}"),
+                    }
+                }
+                "package" => dialect.default_package = Some(self.get_symbol(&val)?.path().to_vec()),
+                k => bail!("Unsupported dialect key: {k}"),
+            }
+            args = rest;
+        }
+        Ok(dialect)
+    }
+
     fn handle_meta_cases(&mut self, cmd: &str, args: &Ptr<F>, pwd_path: &Utf8Path) -> Result<()> {
         match cmd {
             "def" => {
@@ -494,11 +627,11 @@ impl Repl<F> {
                 let first = self.peek1(cmd, args)?;
                 let (first_io, ..) = self.eval_expr(first)?;
                 if first_io.expr.is_nil() {
-                    eprintln!(
+                    let msg = format!(
                         "`assert` failed. {} evaluates to nil",
                         first.fmt_to_string(&self.store, &self.state.borrow())
                     );
-                    process::exit(1);
+                    self.fail(msg);
                 }
             }
             "assert-eq" => {
@@ -510,7 +643,7 @@ impl Repl<F> {
                     .eval_expr(second)
                     .with_context(|| "evaluating second arg")?;
                 if !&self.store.ptr_eq(&first_io.expr, &second_io.expr)? {
-                    eprintln!(
+                    let msg = format!(
                         "`assert-eq` failed. Expected:\n  {} = {}\nGot:\n  {} ≠ {}",
                         first.fmt_to_string(&self.store, &self.state.borrow()),
                         second.fmt_to_string(&self.store, &self.state.borrow()),
@@ -521,7 +654,7 @@ impl Repl<F> {
                             .expr
                             .fmt_to_string(&self.store, &self.state.borrow())
                     );
-                    process::exit(1);
+                    self.fail(msg);
                 }
             }
             "assert-emitted" => {
@@ -535,12 +668,13 @@ impl Repl<F> {
                 let (mut first_emitted, mut rest_emitted) = self.store.car_cdr(&first_io.expr)?;
                 for (i, elem) in emitted.iter().enumerate() {
                     if elem != &first_emitted {
-                        eprintln!(
+                        let msg = format!(
                             "`assert-emitted` failed at position {i}. Expected {}, but found {}.",
                             first_emitted.fmt_to_string(&self.store, &self.state.borrow()),
                             elem.fmt_to_string(&self.store, &self.state.borrow()),
                         );
-                        process::exit(1);
+                        self.fail(msg);
+                        break;
                     }
                     (first_emitted, rest_emitted) = self.store.car_cdr(&rest_emitted)?;
                 }
@@ -548,11 +682,27 @@ impl Repl<F> {
             "assert-error" => {
                 let first = self.peek1(cmd, args)?;
                 if self.eval_expr(first).is_ok() {
-                    eprintln!(
+                    let msg = format!(
                         "`assert-error` failed. {} doesn't result on evaluation error.",
                         first.fmt_to_string(&self.store, &self.state.borrow())
                     );
-                    process::exit(1);
+                    self.fail(msg);
+                }
+            }
+            "expect-iterations" => {
+                let (first, second) = self.peek2(cmd, args)?;
+                let expected = self.get_usize(&second)?;
+                let (_, iterations, _) = self
+                    .eval_expr(first)
+                    .with_context(|| "evaluating first arg")?;
+                if iterations != expected {
+                    let msg = format!(
+                        "`expect-iterations` failed. {} took {}, not {}",
+                        first.fmt_to_string(&self.store, &self.state.borrow()),
+                        Self::pretty_iterations_display(iterations),
+                        Self::pretty_iterations_display(expected),
+                    );
+                    self.fail(msg);
                 }
             }
             "commit" => {
@@ -597,6 +747,47 @@ impl Repl<F> {
                 }
                 self.prove_last_frames()?;
             }
+            "find-frames" => {
+                let Some(Evaluation { frames, .. }) = self.evaluation.as_ref() else {
+                    bail!("No evaluation to search")
+                };
+                let query = self.trace_query(*args)?;
+                let matches = query.run(frames, &self.store);
+                for m in &matches {
+                    println!(
+                        "[{}] {} {}",
+                        m.iteration,
+                        m.cont.tag,
+                        m.expr.fmt_to_string(&self.store, &self.state.borrow())
+                    );
+                }
+                println!("{} frame(s) found", matches.len());
+            }
+            "compare-backends" => {
+                let first = self.peek1(cmd, args)?;
+                let comparison =
+                    compare_backends(&mut self.store, first, self.env, &self.lang, self.limit)?;
+                println!("{comparison}");
+            }
+            "measure" => {
+                // Only the counters are shown, so a single state is sampled
+                let first = self.peek1(cmd, args)?;
+                let profile =
+                    Evaluator::new(first, self.env, &mut self.store, self.limit, &self.lang)
+                        .profile(usize::MAX)?;
+                println!(
+                    "{}, {} tail call(s), {} stack-growing call(s), max continuation depth {}",
+                    Self::pretty_iterations_display(profile.iterations),
+                    profile.tail_calls,
+                    profile.growing_calls,
+                    profile.max_cont_depth,
+                );
+            }
+            "set-dialect" => {
+                let dialect = self.reader_dialect(*args)?;
+                self.state.borrow_mut().set_dialect(dialect)?;
+                println!("Dialect: {}", self.state.borrow().dialect());
+            }
             "verify" => {
                 let first = self.peek1(cmd, args)?;
                 let proof_id = self.get_string(&first)?;
@@ -729,6 +920,27 @@ impl Repl<F> {
         }
     }
 
+    /// Loads a script, carrying on past failed assertions, and returns them
+    fn run_script_collecting(&mut self, script_path: &Utf8Path) -> Result<Vec<String>> {
+        self.script_failures = Some(vec![]);
+        let res = self.load_file(script_path);
+        let failures = self.script_failures.take().unwrap_or_default();
+        res.map(|_| failures)
+    }
+
+    /// Runs a script of forms and meta commands, such as `!(assert-eq ...)`,
+    /// exiting with a non-zero code if any assertion fails
+    pub(crate) fn run_script(&mut self, script_path: &Utf8Path) -> Result<()> {
+        let failures = self.run_script_collecting(script_path)?;
+        if failures.is_empty() {
+            println!("All assertions of {script_path} passed");
+            Ok(())
+        } else {
+            eprintln!("{} assertion(s) of {script_path} failed", failures.len());
+            process::exit(1)
+        }
+    }
+
     pub(crate) fn start(&mut self) -> Result<()> {
         println!("Lurk REPL welcomes you.");
 
@@ -804,4 +1016,34 @@ mod test {
         assert_eq!(pad(610, 10), 610);
         assert_eq!(pad(619, 20), 620);
     }
+
+    #[test]
+    fn test_script_failures() {
+        use crate::cli::repl::{Backend, Repl};
+        use crate::{lurk_sym_ptr, store::Store};
+        use camino::Utf8Path;
+
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("test.lurkrc");
+        std::fs::write(
+            &script,
+            "!(assert-eq (+ 1 1) 2)\n\
+             !(assert-eq (+ 1 1) 3)\n\
+             !(assert-error (car 1))\n\
+             !(assert-error 1)\n\
+             !(expect-iterations (+ 1 1) 3)\n\
+             !(expect-iterations (+ 1 1) 4)\n",
+        )
+        .unwrap();
+        let store = Store::default();
+        let env = lurk_sym_ptr!(store, nil);
+        let mut repl = Repl::new(store, env, 1, 1000, Backend::Nova);
+        let failures = repl
+            .run_script_collecting(Utf8Path::from_path(&script).unwrap())
+            .unwrap();
+        assert_eq!(failures.len(), 3);
+        assert!(failures[0].starts_with("`assert-eq`"));
+        assert!(failures[1].starts_with("`assert-error`"));
+        assert!(failures[2].starts_with("`expect-iterations`"));
+    }
 }
@@ -0,0 +1,108 @@
+//! Generation of downstream proving services, for `lurk scaffold-service`.
+//!
+//! The generated crate is a small axum server committing to, opening and
+//! proving Lurk expressions with `lurk::api` and the public parameter cache
+//! alone. It doubles as an example of what downstream crates need from Lurk.
+
+use anyhow::{bail, Result};
+use camino::Utf8Path;
+use std::fs;
+
+const CARGO_TOML: &str = include_str!("scaffold/Cargo.toml.template");
+const MAIN_RS: &str = include_str!("scaffold/main.rs.template");
+const README_MD: &str = include_str!("scaffold/README.md.template");
+
+/// How the generated crate depends on Lurk when no local checkout is given
+const LURK_GIT: &str = r#"{ git = "https://github.com/lurk-lab/lurk-rs", branch = "main" }"#;
+
+/// Whether `name` can name a crate
+fn is_crate_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Fills the placeholders of a template
+fn render(template: &str, name: &str, lurk: &str, rc: usize) -> String {
+    template
+        .replace("{{name}}", name)
+        .replace("{{lurk}}", lurk)
+        .replace("{{rc}}", &rc.to_string())
+}
+
+/// Writes the crate of the service `name` to `dir`, which must not exist or be
+/// empty. The crate depends on the Lurk checkout at `lurk_path` if there's one,
+/// and proves with reduction count `rc`.
+pub(crate) fn scaffold_service(
+    dir: &Utf8Path,
+    name: &str,
+    lurk_path: Option<&Utf8Path>,
+    rc: usize,
+) -> Result<()> {
+    if !is_crate_name(name) {
+        bail!("`{name}` isn't a valid crate name")
+    }
+    if dir.exists() && fs::read_dir(dir)?.next().is_some() {
+        bail!("{dir} already exists and isn't empty")
+    }
+    let lurk = match lurk_path {
+        Some(path) => format!("{{ path = {:?} }}", path.as_str()),
+        None => LURK_GIT.to_owned(),
+    };
+    fs::create_dir_all(dir.join("src"))?;
+    fs::write(dir.join("Cargo.toml"), render(CARGO_TOML, name, &lurk, rc))?;
+    fs::write(dir.join("src/main.rs"), render(MAIN_RS, name, &lurk, rc))?;
+    fs::write(dir.join("README.md"), render(README_MD, name, &lurk, rc))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn services_are_scaffolded() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(tmp.path()).unwrap().join("my-service");
+        let lurk = Utf8Path::new("../lurk-rs");
+        scaffold_service(&dir, "my-service", Some(lurk), 10).unwrap();
+
+        let manifest = fs::read_to_string(dir.join("Cargo.toml")).unwrap();
+        assert!(manifest.contains("name = \"my-service\""));
+        assert!(manifest.contains("lurk = { path = \"../lurk-rs\" }"));
+        let main = fs::read_to_string(dir.join("src/main.rs")).unwrap();
+        assert!(main.contains("const RC: usize = 10;"));
+        for file in ["Cargo.toml", "src/main.rs", "README.md"] {
+            assert!(!fs::read_to_string(dir.join(file)).unwrap().contains("{{"));
+        }
+
+        // the service isn't overwritten
+        assert!(scaffold_service(&dir, "my-service", None, 10).is_err());
+        let other = dir.with_file_name("other");
+        assert!(scaffold_service(&other, "1st-service", None, 10).is_err());
+        assert!(scaffold_service(&other, "my service", None, 10).is_err());
+        assert!(!other.exists());
+    }
+
+    /// Generates a service depending on this checkout and checks that it
+    /// builds, which fetches its dependencies, hence `#[ignore]`
+    #[test]
+    #[ignore]
+    fn scaffolded_services_build() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(tmp.path()).unwrap().join("service");
+        let lurk = Utf8Path::new(env!("CARGO_MANIFEST_DIR"));
+        scaffold_service(&dir, "service", Some(lurk), 10).unwrap();
+
+        let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".into());
+        let status = std::process::Command::new(cargo)
+            .arg("check")
+            .current_dir(&dir)
+            // the builds of Lurk and its dependencies are kept across runs
+            .env("CARGO_TARGET_DIR", lurk.join("target/scaffold"))
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+}
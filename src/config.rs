@@ -1,18 +1,102 @@
-//! Global config for parallelism.
-use anyhow::bail;
+//! Global config for parallelism, the prover's memory use and the settings
+//! Lurk reads from its config file and the environment.
+//!
+//! Settings are layered. By increasing precedence, they are read from
+//! `lurk.toml` in the data dir (or another config file given with `--config`),
+//! from `LURK_*` environment variables and, for the CLI, from its flags. The
+//! setting `proofs`, for instance, is the `proofs = "..."` line of the config
+//! file, the `LURK_PROOFS` variable and the `--proofs-dir` flag.
+use camino::{Utf8Path, Utf8PathBuf};
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
 use once_cell::sync::Lazy;
+use serde::Deserialize;
+
+use crate::proof::watchdog::parse_size;
 
 pub static CONFIG: Lazy<Config> = Lazy::new(init_config);
 
-fn canned_config_from_env() -> Option<CannedConfig> {
-    if let Ok(x) = std::env::var("LURK_CANNED_CONFIG") {
-        let canned = CannedConfig::try_from(x.as_str()).ok();
+/// The name of the config file read from the data dir
+pub const CONFIG_FILE: &str = "lurk.toml";
+
+/// The directory Lurk keeps its data in, `$LURK_DATA_DIR` or `~/.lurk`
+#[cfg(not(target_arch = "wasm32"))]
+pub fn lurk_data_dir() -> Utf8PathBuf {
+    if let Ok(dir) = std::env::var("LURK_DATA_DIR") {
+        return dir.into();
+    }
+    let home = home::home_dir().expect("missing home directory");
+    Utf8PathBuf::from_path_buf(home.join(".lurk")).expect("path contains invalid Unicode")
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn lurk_data_dir() -> Utf8PathBuf {
+    Utf8PathBuf::from(".lurk")
+}
+
+/// The settings read from the config file and the environment. Unset settings
+/// are left to the CLI flags or to the defaults of whoever reads them.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct Settings {
+    pub field: Option<String>,
+    pub backend: Option<String>,
+    pub rc: Option<usize>,
+    pub limit: Option<usize>,
+    pub public_params: Option<Utf8PathBuf>,
+    pub proofs: Option<Utf8PathBuf>,
+    pub commits: Option<Utf8PathBuf>,
+    pub circom: Option<Utf8PathBuf>,
+    /// Parameter material, as `ImportedParams::export` writes it, to prove and
+    /// verify with instead of the parameters of Nova's setup
+    pub params_import: Option<Utf8PathBuf>,
+    /// Where `fcomm` keeps its data. `FCOMM_DATA_PATH` is still honored and
+    /// takes precedence over `LURK_FCOMM_DATA`
+    pub fcomm_data: Option<Utf8PathBuf>,
+    /// One of the canned parallelism configs, like `MAX-PARALLEL-SIMPLE`
+    pub canned_config: Option<String>,
+    pub memory_limit: Option<String>,
+    pub checkpoint_path: Option<PathBuf>,
+}
 
-        tracing::debug!("{:?}", &canned);
+impl Settings {
+    /// Reads the settings from `config_file`, or from `lurk.toml` in the data
+    /// dir if none is given, then from the environment. A missing config file
+    /// is the same as an empty one.
+    pub fn load(config_file: Option<&Utf8Path>) -> Result<Self> {
+        let config_file =
+            config_file.map_or_else(|| lurk_data_dir().join(CONFIG_FILE), Utf8Path::to_owned);
+        let mut builder = ::config::Config::builder();
+        if config_file.exists() {
+            builder = builder.add_source(::config::File::with_name(config_file.as_str()));
+        }
+        let settings = builder
+            .add_source(::config::Environment::with_prefix("LURK"))
+            .set_override_option("fcomm_data", std::env::var("FCOMM_DATA_PATH").ok())?
+            .build()?
+            .try_deserialize()?;
+        Ok(settings)
+    }
 
-        canned
-    } else {
-        None
+    /// The settings of `flags`, falling back to those of `self` for the ones
+    /// `flags` leaves unset
+    pub fn with_flags(self, flags: Settings) -> Self {
+        Self {
+            field: flags.field.or(self.field),
+            backend: flags.backend.or(self.backend),
+            rc: flags.rc.or(self.rc),
+            limit: flags.limit.or(self.limit),
+            public_params: flags.public_params.or(self.public_params),
+            proofs: flags.proofs.or(self.proofs),
+            commits: flags.commits.or(self.commits),
+            circom: flags.circom.or(self.circom),
+            params_import: flags.params_import.or(self.params_import),
+            fcomm_data: flags.fcomm_data.or(self.fcomm_data),
+            canned_config: flags.canned_config.or(self.canned_config),
+            memory_limit: flags.memory_limit.or(self.memory_limit),
+            checkpoint_path: flags.checkpoint_path.or(self.checkpoint_path),
+        }
     }
 }
 
@@ -68,10 +152,40 @@ pub struct WitnessGeneration {
     pub precompute_neptune: bool,
 }
 
+/// How much memory the prover may use, and what it does when it runs out.
+/// See `proof::watchdog`.
+#[derive(Debug, Default)]
+pub struct MemoryConfig {
+    /// Resident memory in bytes the prover stays under, from `LURK_MEMORY_LIMIT`
+    pub limit: Option<usize>,
+    /// Where the prover saves its progress when it reaches the limit, from
+    /// `LURK_CHECKPOINT_PATH`
+    pub checkpoint_path: Option<PathBuf>,
+}
+
+impl MemoryConfig {
+    fn from_settings(settings: &Settings) -> Self {
+        let limit = settings.memory_limit.as_ref().and_then(|x| {
+            let limit = parse_size(x);
+            if limit.is_none() {
+                tracing::warn!("Ignoring invalid memory limit: {x}");
+            }
+            limit
+        });
+        Self {
+            limit,
+            checkpoint_path: settings.checkpoint_path.clone(),
+        }
+    }
+}
+
 #[derive(Default, Debug)]
 pub struct Config {
     pub parallelism: ParallelConfig,
     pub witness_generation: WitnessGeneration,
+    pub memory: MemoryConfig,
+    /// The settings read when the config was first needed
+    pub settings: Settings,
 }
 
 impl Config {
@@ -85,6 +199,8 @@ impl Config {
             witness_generation: WitnessGeneration {
                 precompute_neptune: false,
             },
+            memory: MemoryConfig::default(),
+            settings: Settings::default(),
         }
     }
 
@@ -98,6 +214,8 @@ impl Config {
             witness_generation: WitnessGeneration {
                 precompute_neptune: true,
             },
+            memory: MemoryConfig::default(),
+            settings: Settings::default(),
         }
     }
 
@@ -111,6 +229,8 @@ impl Config {
             witness_generation: WitnessGeneration {
                 precompute_neptune: true,
             },
+            memory: MemoryConfig::default(),
+            settings: Settings::default(),
         }
     }
 }
@@ -146,5 +266,71 @@ impl TryFrom<&str> for CannedConfig {
 }
 
 fn init_config() -> Config {
-    canned_config_from_env().map_or_else(Config::fully_sequential, |x| x.into())
+    let settings = Settings::load(None).unwrap_or_else(|e| {
+        tracing::warn!("Ignoring invalid settings: {e}");
+        Settings::default()
+    });
+    let canned = settings
+        .canned_config
+        .as_deref()
+        .and_then(|x| CannedConfig::try_from(x).ok());
+    tracing::debug!("{:?}", &canned);
+    let mut config: Config = canned.map_or_else(Config::fully_sequential, |x| x.into());
+    config.memory = MemoryConfig::from_settings(&settings);
+    config.settings = settings;
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sets an env var until dropped, then restores its previous value
+    struct EnvGuard {
+        key: &'static str,
+        old: Option<String>,
+    }
+
+    impl EnvGuard {
+        fn set(key: &'static str, value: &str) -> Self {
+            let old = std::env::var(key).ok();
+            std::env::set_var(key, value);
+            Self { key, old }
+        }
+    }
+
+    impl Drop for EnvGuard {
+        fn drop(&mut self) {
+            match &self.old {
+                Some(old) => std::env::set_var(self.key, old),
+                None => std::env::remove_var(self.key),
+            }
+        }
+    }
+
+    #[test]
+    fn flags_override_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+        let config_file = dir.join(CONFIG_FILE);
+        let contents = "rc = 20\nlimit = 1000\nproofs = \"/tmp/proofs\"\n";
+        std::fs::write(&config_file, contents).unwrap();
+        let _limit = EnvGuard::set("LURK_LIMIT", "2000");
+
+        let settings = Settings::load(Some(&config_file)).unwrap();
+        assert_eq!(settings.rc, Some(20));
+        assert_eq!(settings.limit, Some(2000));
+        assert_eq!(settings.proofs, Some("/tmp/proofs".into()));
+        assert_eq!(settings.commits, None);
+
+        let flags = Settings {
+            rc: Some(5),
+            commits: Some("/tmp/commits".into()),
+            ..Default::default()
+        };
+        let settings = settings.with_flags(flags);
+        assert_eq!(settings.rc, Some(5));
+        assert_eq!(settings.limit, Some(2000));
+        assert_eq!(settings.commits, Some("/tmp/commits".into()));
+    }
 }
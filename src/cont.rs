@@ -232,4 +232,23 @@ impl<F: LurkField> Continuation<F> {
             _ => unreachable!("Not a simple Continuation: {:?}", self),
         }
     }
+
+    /// The continuation this one eventually returns to, if any
+    pub fn continuation(&self) -> Option<ContPtr<F>> {
+        match self {
+            Self::Outermost | Self::Dummy | Self::Error | Self::Terminal => None,
+            Self::Call0 { continuation, .. }
+            | Self::Call { continuation, .. }
+            | Self::Call2 { continuation, .. }
+            | Self::Tail { continuation, .. }
+            | Self::Lookup { continuation, .. }
+            | Self::Unop { continuation, .. }
+            | Self::Binop { continuation, .. }
+            | Self::Binop2 { continuation, .. }
+            | Self::If { continuation, .. }
+            | Self::Let { continuation, .. }
+            | Self::LetRec { continuation, .. }
+            | Self::Emit { continuation } => Some(*continuation),
+        }
+    }
 }
@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use crate::eval::IO;
 use crate::field::LurkField;
 use crate::hash_witness::ConsName;
@@ -15,6 +17,17 @@ pub enum ProofError {
     Synthesis(#[from] SynthesisError),
     #[error("Reduction error: {0}")]
     Reduction(#[from] ReductionError),
+    #[error("Invalid segment schedule: {0}")]
+    Schedule(String),
+    #[error("Memory limit reached after {steps_done} steps (checkpoint: {checkpoint:?})")]
+    MemoryLimit {
+        steps_done: usize,
+        checkpoint: Option<PathBuf>,
+    },
+    #[error("Checkpoint error: {0}")]
+    Checkpoint(String),
+    #[error("The output doesn't satisfy the requested predicate: {0}")]
+    OutputPredicate(String),
 }
 
 impl From<store::Error> for ProofError {
@@ -31,6 +44,8 @@ pub enum ReductionError {
     Misc(String),
     #[error("Lookup error: {0}")]
     Store(#[from] store::Error),
+    #[error("Evaluation cancelled after {0} iterations")]
+    Cancelled(usize),
 }
 
 #[derive(Error, Debug, Clone)]
@@ -8,6 +8,8 @@ use crate::state::{initial_lurk_state, State};
 use crate::store::Store;
 use crate::tag::ContTag;
 use crate::writer::Write;
+use crate::z_expr::ZExpr;
+use crate::z_ptr::ZExprPtr;
 use crate::{lurk_sym_ptr, store};
 use lang::Lang;
 
@@ -24,6 +26,7 @@ use tracing::info;
 pub mod lang;
 
 mod reduction;
+pub mod trace;
 
 #[cfg(test)]
 pub(crate) mod tests;
@@ -143,7 +146,68 @@ impl<F: LurkField> From<ContPtr<F>> for Status {
     }
 }
 
+/// A component of an `IO`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IOComponent {
+    Expr,
+    Env,
+    Cont,
+}
+
+impl std::fmt::Display for IOComponent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Expr => write!(f, "expr"),
+            Self::Env => write!(f, "env"),
+            Self::Cont => write!(f, "cont"),
+        }
+    }
+}
+
+/// The first place where a sequence of frames doesn't chain up: the output of
+/// frame `frame` differs from the input of frame `frame + 1` in `component`.
+/// Indices are positions in the slice that was checked.
+#[derive(thiserror::Error, Clone, Copy, Debug, PartialEq, Eq)]
+#[error("output {component} of frame {frame} differs from the input of frame {}", .frame + 1)]
+pub struct FrameMismatch {
+    pub frame: usize,
+    pub component: IOComponent,
+}
+
+impl<F: LurkField> IO<F> {
+    /// The first component in which `self` and `other` differ, if any
+    fn mismatch(&self, other: &Self) -> Option<IOComponent> {
+        if self.expr != other.expr {
+            Some(IOComponent::Expr)
+        } else if self.env != other.env {
+            Some(IOComponent::Env)
+        } else if self.cont != other.cont {
+            Some(IOComponent::Cont)
+        } else {
+            None
+        }
+    }
+}
+
 impl<F: LurkField, W: Copy, C: Coprocessor<F>> Frame<IO<F>, W, C> {
+    /// Checks that each frame's output is the next frame's input. Copies of a
+    /// frame, as added for padding, share its iteration and are skipped.
+    pub fn check_io_chain(frames: &[Self]) -> Result<(), FrameMismatch> {
+        for (i, pair) in frames.windows(2).enumerate() {
+            let (frame, next) = (&pair[0], &pair[1]);
+            if frame.i == next.i {
+                continue;
+            }
+            if let Some(component) = frame.output.mismatch(&next.input) {
+                return Err(FrameMismatch {
+                    frame: i,
+                    component,
+                });
+            }
+        }
+        Ok(())
+    }
+
     pub fn precedes(&self, maybe_next: &Self) -> bool {
         let sequential = self.i + 1 == maybe_next.i;
         let io_match = self.output == maybe_next.input;
@@ -230,6 +294,14 @@ impl<F: LurkField, C: Coprocessor<F>> Evaluable<F, Witness<F>, C> for IO<F> {
     }
 }
 
+/// The emit log of a computation that hasn't emitted anything yet. The emit
+/// log is the list of the values emitted so far, most recent first, which Nova
+/// proofs carry in their public IO, so they commit to what a computation
+/// emitted along with its result.
+pub fn empty_emit_log<F: LurkField>(store: &Store<F>) -> ZExprPtr<F> {
+    ZExpr::Nil.z_ptr(&store.poseidon_cache)
+}
+
 impl<F: LurkField> IO<F> {
     // Returns any expression that was emitted in this IO (if an output) or previous (if an input).
     // The intention is that this method will be used to extract and handle all output as needed.
@@ -248,6 +320,24 @@ impl<F: LurkField> IO<F> {
         (expr.continuation.tag == crate::tag::ContTag::Emit).then_some(expr.value)
     }
 
+    /// Adds the value emitted in this IO, if it's the output of a frame that
+    /// emitted one, to the emit log `log`, which is hashed but not interned
+    pub fn extend_emit_log(
+        &self,
+        log: ZExprPtr<F>,
+        store: &Store<F>,
+    ) -> Result<ZExprPtr<F>, store::Error> {
+        match self.maybe_emitted_expression(store) {
+            Some(value) => {
+                let value = store
+                    .hash_expr(&value)
+                    .ok_or_else(|| store::Error("emitted expr hash missing".into()))?;
+                Ok(ZExpr::Cons(value, log).z_ptr(&store.poseidon_cache))
+            }
+            None => Ok(log),
+        }
+    }
+
     pub fn to_vector(&self, store: &Store<F>) -> Result<Vec<F>, store::Error> {
         let expr_z_ptr = store
             .hash_expr(&self.expr)
@@ -90,6 +90,20 @@ fn reduce_with_witness_inner<F: LurkField, C: Coprocessor<F>>(
                     Control::ApplyContinuation(expr, env, cont)
                 }
 
+                ExprTag::Ratio => {
+                    return Err(store::Error("Ratios are only supported by LEM".into()).into());
+                }
+
+                ExprTag::Set => {
+                    return Err(store::Error("Sets are only supported by LEM".into()).into());
+                }
+
+                ExprTag::Bytes => {
+                    return Err(
+                        store::Error("Byte vectors are only supported by LEM".into()).into(),
+                    );
+                }
+
                 ExprTag::Thunk => match store
                     .fetch(&expr)
                     .ok_or_else(|| store::Error("Fetch failed".into()))?
@@ -689,6 +703,9 @@ fn apply_continuation<F: LurkField>(
     let control = match cont.tag {
         ContTag::Terminal | ContTag::Error => Control::Return(result, env, cont),
         ContTag::Dummy => unreachable!("Dummy Continuation should never be applied."),
+        ContTag::OutOfGas => {
+            return Err(store::Error("Only LEM runs out of gas".into()).into());
+        }
         ContTag::Outermost => Control::Return(result, env, store.intern_cont_terminal()),
         ContTag::Emit => match cont_witness
             .fetch_named_cont(ContName::ApplyContinuation, store, &cont)
@@ -190,6 +190,26 @@ fn evaluate_simple() {
     test_aux::<Coproc<Fr>>(s, expr, Some(expected), None, None, None, 1, None);
 }
 
+#[test]
+fn frame_io_chain() {
+    let s = &mut Store::<Fr>::default();
+    let lang = Lang::<Fr, Coproc<Fr>>::new();
+    let expr = s.read("(let ((x 1)) (+ x 2))").unwrap();
+    let env = empty_sym_env(s);
+    let mut frames = Evaluator::generate_frames(expr, env, s, 100, |n| n % 4 != 0, &lang).unwrap();
+    assert_eq!(frames.len() % 4, 0);
+    assert_eq!(Frame::check_io_chain(&frames), Ok(()));
+
+    frames[3].input.env = s.num(1);
+    assert_eq!(
+        Frame::check_io_chain(&frames),
+        Err(FrameMismatch {
+            frame: 2,
+            component: IOComponent::Env,
+        })
+    );
+}
+
 #[test]
 fn evaluate_lookup() {
     let mut store = Store::<Fr>::default();
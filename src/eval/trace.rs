@@ -0,0 +1,634 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::coprocessor::Coprocessor;
+use crate::error::ReductionError;
+use crate::field::LurkField;
+use crate::ptr::{ContPtr, Ptr};
+use crate::store::{self, Store};
+use crate::symbol::Symbol;
+use crate::tag::{ContTag, ExprTag};
+use crate::z_ptr::{ZContPtr, ZExprPtr};
+use crate::z_store::ZStore;
+
+use super::{Evaluable, Evaluator, Frame, Witness, IO};
+
+/// A condition on a single recorded frame. Conditions are checked against the
+/// frame's input, which is the state the reduction step started from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TracePredicate {
+    /// The expression is a list whose first element is the given symbol
+    ExprHead(Symbol),
+    /// The expression has the given tag
+    ExprTag(ExprTag),
+    /// The continuation has the given tag
+    ContTag(ContTag),
+    /// The frame's iteration lies in the given (half-open) range
+    Iterations(Range<usize>),
+}
+
+impl TracePredicate {
+    fn holds<F: LurkField>(&self, i: usize, io: &IO<F>, store: &Store<F>) -> bool {
+        match self {
+            Self::ExprHead(sym) => {
+                if io.expr.tag != ExprTag::Cons {
+                    return false;
+                }
+                match store.car_cdr(&io.expr) {
+                    Ok((head, _)) => store.fetch_sym(&head).as_ref() == Some(sym),
+                    Err(_) => false,
+                }
+            }
+            Self::ExprTag(tag) => &io.expr.tag == tag,
+            Self::ContTag(tag) => &io.cont.tag == tag,
+            Self::Iterations(range) => range.contains(&i),
+        }
+    }
+}
+
+/// An evaluation state picked from a trace, either by a `TraceQuery` or by
+/// sampling
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceMatch<F: LurkField> {
+    /// Position of the frame in the trace
+    pub index: usize,
+    /// Iteration recorded in the frame
+    pub iteration: usize,
+    pub expr: Ptr<F>,
+    pub env: Ptr<F>,
+    pub cont: ContPtr<F>,
+}
+
+/// A conjunction of `TracePredicate`s used to search a sequence of recorded
+/// frames. An empty query selects every frame.
+///
+/// ```ignore
+/// let query = TraceQuery::new()
+///     .expr_head(lurk_sym("if"))
+///     .cont_tag(ContTag::Call)
+///     .iterations(1000..2000);
+/// let matches = query.run(&frames, &store);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TraceQuery {
+    predicates: Vec<TracePredicate>,
+    limit: Option<usize>,
+}
+
+impl TraceQuery {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn with(mut self, predicate: TracePredicate) -> Self {
+        self.predicates.push(predicate);
+        self
+    }
+
+    #[inline]
+    pub fn expr_head(self, sym: Symbol) -> Self {
+        self.with(TracePredicate::ExprHead(sym))
+    }
+
+    #[inline]
+    pub fn expr_tag(self, tag: ExprTag) -> Self {
+        self.with(TracePredicate::ExprTag(tag))
+    }
+
+    #[inline]
+    pub fn cont_tag(self, tag: ContTag) -> Self {
+        self.with(TracePredicate::ContTag(tag))
+    }
+
+    #[inline]
+    pub fn iterations(self, range: Range<usize>) -> Self {
+        self.with(TracePredicate::Iterations(range))
+    }
+
+    /// Stops the search after `limit` matches
+    #[inline]
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    #[inline]
+    pub fn predicates(&self) -> &[TracePredicate] {
+        &self.predicates
+    }
+
+    pub fn matches<F: LurkField, W: Copy, C: Coprocessor<F>>(
+        &self,
+        frame: &Frame<IO<F>, W, C>,
+        store: &Store<F>,
+    ) -> bool {
+        self.predicates
+            .iter()
+            .all(|p| p.holds(frame.i, &frame.input, store))
+    }
+
+    /// Returns the frames that satisfy every predicate, in trace order, and at
+    /// most `limit` of them if there's a limit.
+    ///
+    /// Frames are checked until the limit is reached, against the predicates
+    /// in the order they were added, up to the first one that fails. Iteration ranges are checked
+    /// against the frames' own iteration counters, so adding them first keeps
+    /// `expr_head` from fetching symbols of frames outside of the ranges.
+    pub fn run<F: LurkField, W: Copy, C: Coprocessor<F>>(
+        &self,
+        frames: &[Frame<IO<F>, W, C>],
+        store: &Store<F>,
+    ) -> Vec<TraceMatch<F>> {
+        let matches = frames
+            .iter()
+            .enumerate()
+            .filter(|(_, frame)| self.matches(frame, store))
+            .map(|(index, frame)| TraceMatch {
+                index,
+                iteration: frame.i,
+                expr: frame.input.expr,
+                env: frame.input.env,
+                cont: frame.input.cont,
+            });
+        match self.limit {
+            Some(limit) => matches.take(limit).collect(),
+            None => matches.collect(),
+        }
+    }
+}
+
+/// Aggregate counters over an evaluation, plus every `every`-th state.
+///
+/// Tag and head counters cover all iterations since they only require
+/// inspecting pointers. Environment depths require walking the environment,
+/// so they're only measured on the sampled iterations. Continuation depths
+/// are memoized per continuation, so they're measured on every iteration.
+///
+/// Function applications are told apart by what they do to the continuation
+/// of their caller. A call in tail position finds a `Tail` continuation and
+/// reuses it, so loops written that way run in constant space. Any other call
+/// wraps the continuation in a new `Tail`, growing it until the call returns.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceProfile<F: LurkField> {
+    every: usize,
+    /// Number of reductions performed
+    pub iterations: usize,
+    /// The states at iterations `0`, `every`, `2 * every`, ...
+    pub samples: Vec<TraceMatch<F>>,
+    pub expr_tags: HashMap<ExprTag, usize>,
+    pub cont_tags: HashMap<ContTag, usize>,
+    /// Occurrences of symbols in head position, indexed by pointer to keep the
+    /// hot path free of store lookups. See `TraceProfile::op_mix`
+    pub heads: HashMap<Ptr<F>, usize>,
+    /// Sampled environment depths and how many times each was seen
+    pub env_depths: BTreeMap<usize, usize>,
+    /// Applications that reused their caller's continuation
+    pub tail_calls: usize,
+    /// Applications that grew their caller's continuation
+    pub growing_calls: usize,
+    /// The length of the longest chain of continuations seen
+    pub max_cont_depth: usize,
+    /// The depth of every continuation reached, so chains are walked once
+    cont_depths: HashMap<ContPtr<F>, usize>,
+}
+
+impl<F: LurkField> TraceProfile<F> {
+    pub fn new(every: usize) -> Self {
+        assert!(every > 0, "the sampling interval can't be zero");
+        Self {
+            every,
+            iterations: 0,
+            samples: Vec::new(),
+            expr_tags: HashMap::new(),
+            cont_tags: HashMap::new(),
+            heads: HashMap::new(),
+            env_depths: BTreeMap::new(),
+            tail_calls: 0,
+            growing_calls: 0,
+            max_cont_depth: 0,
+            cont_depths: HashMap::new(),
+        }
+    }
+
+    #[inline]
+    pub fn every(&self) -> usize {
+        self.every
+    }
+
+    fn record(&mut self, io: &IO<F>, store: &Store<F>) {
+        let i = self.iterations;
+        self.iterations += 1;
+        *self.expr_tags.entry(io.expr.tag).or_default() += 1;
+        *self.cont_tags.entry(io.cont.tag).or_default() += 1;
+        let cont_depth = self.cont_depth(io.cont, store);
+        self.max_cont_depth = self.max_cont_depth.max(cont_depth);
+        if io.expr.tag == ExprTag::Cons {
+            if let Ok((head, _)) = store.car_cdr(&io.expr) {
+                if head.tag == ExprTag::Sym {
+                    *self.heads.entry(head).or_default() += 1;
+                }
+            }
+        }
+        if i % self.every == 0 {
+            *self
+                .env_depths
+                .entry(env_depth(&io.env, store))
+                .or_default() += 1;
+            self.samples.push(TraceMatch {
+                index: self.samples.len(),
+                iteration: i,
+                expr: io.expr,
+                env: io.env,
+                cont: io.cont,
+            });
+        }
+    }
+
+    /// Counts the function application performed by the reduction from `input`
+    /// to `output`, if any
+    fn record_call(&mut self, input: &IO<F>, output: &IO<F>, store: &Store<F>) {
+        // Applications are the reductions of `Call0` and `Call2` that move on
+        // to the function's body, as opposed to returning their input, which
+        // happens when applying a function that takes arguments to none
+        let applies = matches!(input.cont.tag, ContTag::Call0 | ContTag::Call2)
+            && output.cont.tag == ContTag::Tail
+            && output.expr != input.expr;
+        if !applies {
+            return;
+        }
+        let caller = store.fetch_cont(&input.cont).and_then(|c| c.continuation());
+        if caller == Some(output.cont) {
+            self.tail_calls += 1;
+        } else {
+            self.growing_calls += 1;
+        }
+    }
+
+    /// The number of continuations `cont` goes through before reaching one
+    /// that doesn't return anywhere
+    fn cont_depth(&mut self, cont: ContPtr<F>, store: &Store<F>) -> usize {
+        let mut pending = vec![];
+        let mut cont = cont;
+        let mut depth = loop {
+            if let Some(depth) = self.cont_depths.get(&cont) {
+                break *depth;
+            }
+            match store.fetch_cont(&cont).and_then(|c| c.continuation()) {
+                Some(next) => {
+                    pending.push(cont);
+                    cont = next;
+                }
+                None => {
+                    self.cont_depths.insert(cont, 0);
+                    break 0;
+                }
+            }
+        };
+        for cont in pending.into_iter().rev() {
+            depth += 1;
+            self.cont_depths.insert(cont, depth);
+        }
+        depth
+    }
+
+    /// Resolves `heads` into symbols, most frequent first
+    pub fn op_mix(&self, store: &Store<F>) -> Vec<(Symbol, usize)> {
+        let mut mix = self
+            .heads
+            .iter()
+            .filter_map(|(ptr, count)| store.fetch_sym(ptr).map(|sym| (sym, *count)))
+            .collect::<Vec<_>>();
+        mix.sort_by(|(a_sym, a), (b_sym, b)| b.cmp(a).then_with(|| a_sym.path().cmp(b_sym.path())));
+        mix
+    }
+}
+
+/// Number of bindings in an environment
+fn env_depth<F: LurkField>(env: &Ptr<F>, store: &Store<F>) -> usize {
+    let mut depth = 0;
+    let mut env = *env;
+    while env.tag == ExprTag::Cons {
+        match store.car_cdr(&env) {
+            Ok((_, rest)) => env = rest,
+            Err(_) => break,
+        }
+        depth += 1;
+    }
+    depth
+}
+
+impl<'a, F: LurkField, C: Coprocessor<F>> Evaluator<'a, F, C> {
+    /// Evaluates like `Evaluator::eval`, but instead of the result it returns
+    /// a `TraceProfile` sampling one of every `every` iterations. No frames
+    /// nor witnesses are kept, but besides the samples, memory grows with the
+    /// distinct symbols seen in head position and with the distinct
+    /// continuations reached, whose depths are cached, which can be as many
+    /// as the iterations.
+    pub fn profile(&mut self, every: usize) -> Result<TraceProfile<F>, ReductionError> {
+        let mut profile = TraceProfile::new(every);
+        let mut io = self.initial();
+        for _ in 0..self.limit {
+            if Evaluable::<F, Witness<F>, C>::is_complete(&io) {
+                break;
+            }
+            profile.record(&io, self.store);
+            let (next, _) = io.reduce(self.store, self.lang)?;
+            profile.record_call(&io, &next, self.store);
+            io = next;
+        }
+        Ok(profile)
+    }
+}
+
+/// The parts of an evaluation state that differ from the previous state of a
+/// `CompressedTrace`. Parts that didn't change are left out.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateDelta<F: LurkField> {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expr: Option<ZExprPtr<F>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<ZExprPtr<F>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cont: Option<ZContPtr<F>>,
+}
+
+/// A serializable sequence of evaluation states, such as the inputs and
+/// outputs of a run of frames. Consecutive states mostly share their
+/// structure, so each state is stored as its difference from the previous
+/// one, and everything the states point to is stored once, in a shared
+/// `ZStore`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompressedTrace<F: LurkField> {
+    pub store: ZStore<F>,
+    /// One delta per state. The first one has every part.
+    pub deltas: Vec<StateDelta<F>>,
+}
+
+impl<F: LurkField> CompressedTrace<F> {
+    /// Compresses `states`, hashing them with `store`
+    pub fn from_states<'a, I>(states: I, store: &Store<F>) -> Result<Self, store::Error>
+    where
+        I: IntoIterator<Item = &'a IO<F>>,
+    {
+        let mut z_store = Some(ZStore::new());
+        let mut deltas = vec![];
+        let mut prev: Option<(ZExprPtr<F>, ZExprPtr<F>, ZContPtr<F>)> = None;
+        for io in states {
+            let (expr, _) = store.get_z_expr(&io.expr, &mut None)?;
+            let (env, _) = store.get_z_expr(&io.env, &mut None)?;
+            let (cont, _) = store.get_z_cont(&io.cont, &mut None)?;
+            let mut delta = StateDelta::default();
+            // only what changed needs to be added to the shared store
+            if prev.map_or(true, |(e, _, _)| e != expr) {
+                store.get_z_expr(&io.expr, &mut z_store)?;
+                delta.expr = Some(expr);
+            }
+            if prev.map_or(true, |(_, e, _)| e != env) {
+                store.get_z_expr(&io.env, &mut z_store)?;
+                delta.env = Some(env);
+            }
+            if prev.map_or(true, |(_, _, c)| c != cont) {
+                store.get_z_cont(&io.cont, &mut z_store)?;
+                delta.cont = Some(cont);
+            }
+            deltas.push(delta);
+            prev = Some((expr, env, cont));
+        }
+        Ok(Self {
+            store: z_store.unwrap(),
+            deltas,
+        })
+    }
+
+    /// Compresses the states a run of frames goes through: the input of the
+    /// first frame followed by the output of each
+    pub fn from_frames<W, C: Coprocessor<F>>(
+        frames: &[Frame<IO<F>, W, C>],
+        store: &Store<F>,
+    ) -> Result<Self, store::Error> {
+        let first = frames.first().map(|frame| &frame.input);
+        let outputs = frames.iter().map(|frame| &frame.output);
+        Self::from_states(first.into_iter().chain(outputs), store)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Interns the states back into `store`
+    pub fn states(&self, store: &mut Store<F>) -> Result<Vec<IO<F>>, store::Error> {
+        let mut states: Vec<IO<F>> = Vec::with_capacity(self.deltas.len());
+        for (i, delta) in self.deltas.iter().enumerate() {
+            let missing = |part| store::Error(format!("state {i} has no {part}"));
+            let prev = states.last();
+            let expr = match &delta.expr {
+                Some(z_ptr) => store
+                    .intern_z_expr_ptr(z_ptr, &self.store)
+                    .ok_or_else(|| missing("interned expr"))?,
+                None => prev.ok_or_else(|| missing("expr"))?.expr,
+            };
+            let env = match &delta.env {
+                Some(z_ptr) => store
+                    .intern_z_expr_ptr(z_ptr, &self.store)
+                    .ok_or_else(|| missing("interned env"))?,
+                None => prev.ok_or_else(|| missing("env"))?.env,
+            };
+            let cont = match &delta.cont {
+                Some(z_ptr) => store
+                    .intern_z_cont_ptr(z_ptr, &self.store)
+                    .ok_or_else(|| missing("interned cont"))?,
+                None => prev.ok_or_else(|| missing("cont"))?.cont,
+            };
+            states.push(IO { expr, env, cont });
+        }
+        Ok(states)
+    }
+}
+
+/// Parses a continuation tag from its name, as printed without the trailing
+/// `#` (e.g. "call" or "letrec")
+pub fn cont_tag_from_name(name: &str) -> Option<ContTag> {
+    let tag = match name.to_lowercase().trim_end_matches('#') {
+        "outermost" => ContTag::Outermost,
+        "call0" => ContTag::Call0,
+        "call" => ContTag::Call,
+        "call2" => ContTag::Call2,
+        "tail" => ContTag::Tail,
+        "error" => ContTag::Error,
+        "lookup" => ContTag::Lookup,
+        "unop" => ContTag::Unop,
+        "binop" => ContTag::Binop,
+        "binop2" => ContTag::Binop2,
+        "if" => ContTag::If,
+        "let" => ContTag::Let,
+        "letrec" => ContTag::LetRec,
+        "dummy" => ContTag::Dummy,
+        "terminal" => ContTag::Terminal,
+        "emit" => ContTag::Emit,
+        "out_of_gas" => ContTag::OutOfGas,
+        _ => return None,
+    };
+    Some(tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::{empty_sym_env, lang::Coproc, lang::Lang, Evaluator};
+    use crate::state::lurk_sym;
+    use pasta_curves::pallas::Scalar as Fr;
+
+    fn frames(
+        s: &mut Store<Fr>,
+        src: &str,
+    ) -> Vec<Frame<IO<Fr>, crate::eval::Witness<Fr>, Coproc<Fr>>> {
+        let expr = s.read(src).unwrap();
+        let env = empty_sym_env(s);
+        let lang = Lang::<Fr, Coproc<Fr>>::new();
+        Evaluator::new(expr, env, s, 100, &lang)
+            .get_frames()
+            .unwrap()
+    }
+
+    #[test]
+    fn query_by_head_and_cont() {
+        let s = &mut Store::<Fr>::default();
+        let frames = frames(s, "(if (= 1 1) (+ 1 2) 0)");
+
+        let ifs = TraceQuery::new().expr_head(lurk_sym("if")).run(&frames, s);
+        assert_eq!(ifs.len(), 1);
+        assert_eq!(ifs[0].index, 0);
+        assert_eq!(ifs[0].expr, frames[0].input.expr);
+
+        let adds = TraceQuery::new().expr_head(lurk_sym("+")).run(&frames, s);
+        assert_eq!(adds.len(), 1);
+        assert!(adds[0].index > ifs[0].index);
+
+        let in_if = TraceQuery::new().cont_tag(ContTag::If).run(&frames, s);
+        assert!(!in_if.is_empty());
+        assert!(in_if.iter().all(|m| m.cont.tag == ContTag::If));
+    }
+
+    #[test]
+    fn query_by_iterations_and_limit() {
+        let s = &mut Store::<Fr>::default();
+        let frames = frames(s, "(+ 1 (+ 2 (+ 3 4)))");
+        let n = frames.len();
+
+        assert_eq!(TraceQuery::new().run(&frames, s).len(), n);
+
+        let tail = TraceQuery::new().iterations(2..n).run(&frames, s);
+        assert_eq!(tail.len(), n - 2);
+        assert!(tail.iter().all(|m| m.iteration >= 2));
+
+        let first = TraceQuery::new().limit(1).run(&frames, s);
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].index, 0);
+    }
+
+    #[test]
+    fn sampled_profile() {
+        let s = &mut Store::<Fr>::default();
+        let expr = s.read("(let ((a 1) (b 2)) (+ a (+ b (+ a b))))").unwrap();
+        let env = empty_sym_env(s);
+        let lang = Lang::<Fr, Coproc<Fr>>::new();
+        let (_, iterations, _) = Evaluator::new(expr, env, s, 100, &lang).eval().unwrap();
+
+        let profile = Evaluator::new(expr, env, s, 100, &lang).profile(3).unwrap();
+        assert_eq!(profile.iterations, iterations);
+        assert_eq!(profile.samples.len(), (iterations + 2) / 3);
+        assert!(profile.samples.iter().all(|m| m.iteration % 3 == 0));
+        assert_eq!(profile.cont_tags.values().sum::<usize>(), iterations);
+        assert_eq!(
+            profile.env_depths.values().sum::<usize>(),
+            profile.samples.len()
+        );
+
+        let mix = profile.op_mix(s);
+        assert_eq!(mix[0], (lurk_sym("+"), 3));
+        assert!(mix.iter().any(|(sym, _)| sym == &lurk_sym("let")));
+    }
+
+    #[test]
+    fn tail_call_stats() {
+        let s = &mut Store::<Fr>::default();
+        let lang = Lang::<Fr, Coproc<Fr>>::new();
+        let env = empty_sym_env(s);
+        let mut profile = |src| {
+            let expr = s.read(src).unwrap();
+            Evaluator::new(expr, env, s, 1000, &lang)
+                .profile(10)
+                .unwrap()
+        };
+
+        // every call of a loop is in tail position, including the first one,
+        // which finds the `Tail` continuation of the `letrec`'s body
+        let count =
+            profile("(letrec ((count (lambda (n) (if (= n 0) 0 (count (- n 1)))))) (count 3))");
+        assert_eq!(count.tail_calls, 4);
+        assert_eq!(count.growing_calls, 0);
+
+        // the recursive calls of `sum` wait for `+`
+        let sum =
+            profile("(letrec ((sum (lambda (n) (if (= n 0) 0 (+ n (sum (- n 1))))))) (sum 3))");
+        assert_eq!(sum.tail_calls, 1);
+        assert_eq!(sum.growing_calls, 3);
+        assert!(sum.max_cont_depth > count.max_cont_depth);
+
+        let constant = profile("1");
+        assert_eq!(constant.tail_calls + constant.growing_calls, 0);
+        assert_eq!(constant.max_cont_depth, 0);
+    }
+
+    #[test]
+    fn compressed_trace_roundtrip() {
+        let s = &mut Store::<Fr>::default();
+        let frames = frames(
+            s,
+            "(letrec ((count (lambda (n) (if (= n 0) 0 (count (- n 1)))))) (count 3))",
+        );
+        let trace = CompressedTrace::from_frames(&frames, s).unwrap();
+        assert_eq!(trace.len(), frames.len() + 1);
+
+        let states = trace.states(s).unwrap();
+        assert_eq!(states[0], frames[0].input);
+        for (state, frame) in states[1..].iter().zip(&frames) {
+            assert_eq!(state, &frame.output);
+        }
+
+        // storing every state on its own repeats the structure they share
+        let separate: usize = states
+            .iter()
+            .map(|io| {
+                let mut z_store = Some(ZStore::new());
+                s.get_z_expr(&io.expr, &mut z_store).unwrap();
+                s.get_z_expr(&io.env, &mut z_store).unwrap();
+                s.get_z_cont(&io.cont, &mut z_store).unwrap();
+                serde_json::to_vec(&z_store).unwrap().len()
+            })
+            .sum();
+        let compressed = serde_json::to_vec(&trace).unwrap().len();
+        assert!(compressed < separate, "{compressed} >= {separate}");
+
+        let empty = CompressedTrace::<Fr>::from_states([], s).unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn cont_tag_names() {
+        assert_eq!(cont_tag_from_name("call"), Some(ContTag::Call));
+        assert_eq!(cont_tag_from_name("letrec#"), Some(ContTag::LetRec));
+        assert_eq!(cont_tag_from_name("Terminal"), Some(ContTag::Terminal));
+        assert_eq!(cont_tag_from_name("nope"), None);
+    }
+}
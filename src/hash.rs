@@ -1,11 +1,14 @@
-use std::collections::HashMap;
 use std::hash::Hash;
+use std::mem::size_of;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 use crate::cache_map::CacheMap;
 use crate::field::{FWrap, LurkField};
+use crate::hasher::HashMap;
 
-use generic_array::typenum::{U3, U4, U6, U8};
+use crossbeam::utils::CachePadded;
+use generic_array::typenum::{U12, U16, U3, U4, U6, U8};
 use neptune::{poseidon::PoseidonConstants, Poseidon};
 use once_cell::sync::OnceCell;
 
@@ -15,6 +18,8 @@ pub enum HashArity {
     A4,
     A6,
     A8,
+    A12,
+    A16,
 }
 
 impl From<usize> for HashArity {
@@ -24,6 +29,8 @@ impl From<usize> for HashArity {
             4 => Self::A4,
             6 => Self::A6,
             8 => Self::A8,
+            12 => Self::A12,
+            16 => Self::A16,
             _ => panic!("unsupported arity: {}", n),
         }
     }
@@ -35,6 +42,8 @@ pub enum HashConst<'a, F: LurkField> {
     A4(&'a PoseidonConstants<F, U4>),
     A6(&'a PoseidonConstants<F, U6>),
     A8(&'a PoseidonConstants<F, U8>),
+    A12(&'a PoseidonConstants<F, U12>),
+    A16(&'a PoseidonConstants<F, U16>),
 }
 
 /// Holds the constants needed for poseidon hashing.
@@ -44,6 +53,8 @@ pub struct HashConstants<F: LurkField> {
     c4: OnceCell<PoseidonConstants<F, U4>>,
     c6: OnceCell<PoseidonConstants<F, U6>>,
     c8: OnceCell<PoseidonConstants<F, U8>>,
+    c12: OnceCell<PoseidonConstants<F, U12>>,
+    c16: OnceCell<PoseidonConstants<F, U16>>,
 }
 
 impl<F: LurkField> Default for HashConstants<F> {
@@ -53,6 +64,8 @@ impl<F: LurkField> Default for HashConstants<F> {
             c4: OnceCell::new(),
             c6: OnceCell::new(),
             c8: OnceCell::new(),
+            c12: OnceCell::new(),
+            c16: OnceCell::new(),
         }
     }
 }
@@ -74,27 +87,116 @@ impl<F: LurkField> HashConstants<F> {
         self.c8.get_or_init(|| PoseidonConstants::new())
     }
 
+    pub fn c12(&self) -> &PoseidonConstants<F, U12> {
+        self.c12.get_or_init(|| PoseidonConstants::new())
+    }
+
+    pub fn c16(&self) -> &PoseidonConstants<F, U16> {
+        self.c16.get_or_init(|| PoseidonConstants::new())
+    }
+
     pub fn constants(&self, arity: HashArity) -> HashConst<'_, F> {
         match arity {
             HashArity::A3 => HashConst::A3(self.c3.get_or_init(|| PoseidonConstants::new())),
             HashArity::A4 => HashConst::A4(self.c4.get_or_init(|| PoseidonConstants::new())),
             HashArity::A6 => HashConst::A6(self.c6.get_or_init(|| PoseidonConstants::new())),
             HashArity::A8 => HashConst::A8(self.c8.get_or_init(|| PoseidonConstants::new())),
+            HashArity::A12 => HashConst::A12(self.c12.get_or_init(|| PoseidonConstants::new())),
+            HashArity::A16 => HashConst::A16(self.c16.get_or_init(|| PoseidonConstants::new())),
         }
     }
 }
 
+/// The number of slots a `Counter` spreads its increments over
+const COUNTER_SHARDS: usize = 16;
+
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// The slot of the counters the current thread increments
+    static SHARD: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed) % COUNTER_SHARDS;
+}
+
+/// A counter incremented from many threads, such as those of rayon's pool.
+/// Each thread increments a slot of its own, on a cache line of its own, so the
+/// threads don't contend for a single atomic, and the slots are only summed up
+/// when the counter is read.
+#[derive(Debug, Default)]
+struct Counter([CachePadded<AtomicUsize>; COUNTER_SHARDS]);
+
+impl Counter {
+    fn increment(&self) {
+        SHARD.with(|shard| self.0[*shard].fetch_add(1, Ordering::Relaxed));
+    }
+
+    fn get(&self) -> usize {
+        self.0.iter().map(|slot| slot.load(Ordering::Relaxed)).sum()
+    }
+}
+
 #[derive(Clone, Default, Debug)]
 pub struct PoseidonCache<F: LurkField> {
     a3: Arc<CacheMap<CacheKey<F, 3>, F>>,
     a4: Arc<CacheMap<CacheKey<F, 4>, F>>,
     a6: Arc<CacheMap<CacheKey<F, 6>, F>>,
     a8: Arc<CacheMap<CacheKey<F, 8>, F>>,
+    a12: Arc<CacheMap<CacheKey<F, 12>, F>>,
+    a16: Arc<CacheMap<CacheKey<F, 16>, F>>,
+    lookups: Arc<Counter>,
+    misses: Arc<Counter>,
 
     pub constants: HashConstants<F>,
 }
 
+/// How much a `PoseidonCache` has been used and how much it holds
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoseidonCacheStats {
+    /// The number of hashes asked for
+    pub lookups: usize,
+    /// The number of hashes that had to be computed
+    pub misses: usize,
+    /// The number of hashes cached
+    pub entries: usize,
+    /// An estimate of the bytes taken by the cached preimages and hashes
+    pub memory: usize,
+}
+
+impl PoseidonCacheStats {
+    /// The fraction of the lookups found in the cache, if there were any.
+    /// The counters are read while hashes may still be computed, so the misses
+    /// can outnumber the lookups they were read with.
+    pub fn hit_rate(&self) -> Option<f64> {
+        let hits = self.lookups.saturating_sub(self.misses);
+        (self.lookups > 0).then(|| hits as f64 / self.lookups as f64)
+    }
+}
+
 impl<F: LurkField> PoseidonCache<F> {
+    /// How much the cache has been used and how much it holds
+    pub fn stats(&self) -> PoseidonCacheStats {
+        let lens = [
+            (self.a3.len(), 3),
+            (self.a4.len(), 4),
+            (self.a6.len(), 6),
+            (self.a8.len(), 8),
+            (self.a12.len(), 12),
+            (self.a16.len(), 16),
+        ];
+        // a lookup is counted before its miss, so reading the misses first
+        // keeps them from outnumbering the lookups, unless hashes are computed
+        // meanwhile
+        let misses = self.misses.get();
+        PoseidonCacheStats {
+            lookups: self.lookups.get(),
+            misses,
+            entries: lens.iter().map(|(len, _)| len).sum(),
+            memory: lens
+                .iter()
+                .map(|(len, arity)| len * (arity + 1) * size_of::<F>())
+                .sum(),
+        }
+    }
+
     pub fn compute_hash<const ARITY: usize>(&self, preimage: [F; ARITY]) -> F {
         macro_rules! hash {
             ($hash_name:ident, $n:expr) => {{
@@ -109,6 +211,8 @@ impl<F: LurkField> PoseidonCache<F> {
             4 => hash!(hash4, 4),
             6 => hash!(hash6, 6),
             8 => hash!(hash8, 8),
+            12 => hash!(hash12, 12),
+            16 => hash!(hash16, 16),
             _ => unreachable!(),
         }
     }
@@ -120,6 +224,8 @@ pub struct InversePoseidonCache<F: LurkField> {
     a4: HashMap<FWrap<F>, [F; 4]>,
     a6: HashMap<FWrap<F>, [F; 6]>,
     a8: HashMap<FWrap<F>, [F; 8]>,
+    a12: HashMap<FWrap<F>, [F; 12]>,
+    a16: HashMap<FWrap<F>, [F; 16]>,
 
     pub constants: HashConstants<F>,
 }
@@ -145,6 +251,8 @@ impl<F: LurkField> InversePoseidonCache<F> {
             4 => get!(a4, 4),
             6 => get!(a6, 6),
             8 => get!(a8, 8),
+            12 => get!(a12, 12),
+            16 => get!(a16, 16),
             _ => unreachable!(),
         }
     }
@@ -165,6 +273,8 @@ impl<F: LurkField> InversePoseidonCache<F> {
             4 => insert!(a4, 4),
             6 => insert!(a6, 6),
             8 => insert!(a8, 8),
+            12 => insert!(a12, 12),
+            16 => insert!(a16, 16),
             _ => unreachable!(),
         }
     }
@@ -184,28 +294,52 @@ impl<F: LurkField, const N: usize> Hash for CacheKey<F, N> {
 
 impl<F: LurkField> PoseidonCache<F> {
     pub fn hash3(&self, preimage: &[F; 3]) -> F {
+        self.lookups.increment();
         self.a3.get_copy_or_insert_with(CacheKey(*preimage), || {
+            self.misses.increment();
             Poseidon::new_with_preimage(preimage, self.constants.c3()).hash()
         })
     }
 
     pub fn hash4(&self, preimage: &[F; 4]) -> F {
+        self.lookups.increment();
         self.a4.get_copy_or_insert_with(CacheKey(*preimage), || {
+            self.misses.increment();
             Poseidon::new_with_preimage(preimage, self.constants.c4()).hash()
         })
     }
 
     pub fn hash6(&self, preimage: &[F; 6]) -> F {
+        self.lookups.increment();
         self.a6.get_copy_or_insert_with(CacheKey(*preimage), || {
+            self.misses.increment();
             Poseidon::new_with_preimage(preimage, self.constants.c6()).hash()
         })
     }
 
     pub fn hash8(&self, preimage: &[F; 8]) -> F {
+        self.lookups.increment();
         self.a8.get_copy_or_insert_with(CacheKey(*preimage), || {
+            self.misses.increment();
             Poseidon::new_with_preimage(preimage, self.constants.c8()).hash()
         })
     }
+
+    pub fn hash12(&self, preimage: &[F; 12]) -> F {
+        self.lookups.increment();
+        self.a12.get_copy_or_insert_with(CacheKey(*preimage), || {
+            self.misses.increment();
+            Poseidon::new_with_preimage(preimage, self.constants.c12()).hash()
+        })
+    }
+
+    pub fn hash16(&self, preimage: &[F; 16]) -> F {
+        self.lookups.increment();
+        self.a16.get_copy_or_insert_with(CacheKey(*preimage), || {
+            self.misses.increment();
+            Poseidon::new_with_preimage(preimage, self.constants.c16()).hash()
+        })
+    }
 }
 
 pub trait IntoHashComponents<F: LurkField> {
@@ -0,0 +1,33 @@
+//! The hasher behind the maps that intern data in the stores and cache hashes
+//! of it.
+//!
+//! ## Threat model
+//!
+//! Those maps are keyed by pointers, field elements, symbol paths and strings,
+//! much of which comes from the programs being evaluated. Whoever picks the
+//! keys could, in principle, pick many that collide under the hasher and turn
+//! lookups linear (HashDoS). The standard library's SipHash is designed to
+//! make that infeasible, but it is slow on the small keys interning deals
+//! with, and interning is one of the hottest paths of evaluation.
+//!
+//! By default we use `ahash` with keys randomized per process. It is much
+//! faster, and the randomization means collisions can't be precomputed, but it
+//! doesn't come with SipHash's cryptographic argument. That is the right trade
+//! for the usual setting, where the person running Lurk also chose the
+//! programs. Deployments interning untrusted input, such as a service that
+//! evaluates programs or loads `ZStore`s submitted over the network, should
+//! build with the `siphash` feature, which switches every map here back to
+//! SipHash.
+//!
+//! The choice is invisible outside the process: index sets keep insertion
+//! order whatever the hasher, so pointers, Poseidon hashes and proofs are the
+//! same either way. The `interning` benchmark compares both hashers.
+
+#[cfg(not(feature = "siphash"))]
+pub type RandomState = ahash::RandomState;
+#[cfg(feature = "siphash")]
+pub type RandomState = std::collections::hash_map::RandomState;
+
+pub type HashMap<K, V> = std::collections::HashMap<K, V, RandomState>;
+pub type IndexSet<K> = indexmap::IndexSet<K, RandomState>;
+pub type DashMap<K, V> = dashmap::DashMap<K, V, RandomState>;
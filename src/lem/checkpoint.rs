@@ -0,0 +1,208 @@
+//! Checkpoints of long runs, to resume them in another process.
+//!
+//! A run of a function, such as an evaluation by Lurk's step function, only
+//! depends on the arguments of its next frame and the part of the store they
+//! refer to. An `EvalCheckpoint` carries both, the latter as the tuples
+//! reachable from the arguments, as `FrameData` carries a frame, along with the
+//! openings of the reachable commitments. `FrameStream::checkpoint` takes one
+//! between two frames, and `Func::resume_stream` goes on with the frames after
+//! it, so an evaluation proven incrementally over hours can be saved to disk
+//! and resumed after a restart.
+//!
+//! A checkpoint is only resumed by the function it was taken on, as told by
+//! its digest, and the openings it carries are checked against their
+//! commitments before they're added to a store.
+
+use anyhow::{anyhow, bail, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use crate::field::{FWrap, LurkField};
+use crate::tag::ExprTag::Comm;
+
+use super::{
+    pointers::Ptr,
+    replay::{globalize, intern_tuples, localize},
+    store::Store,
+    Func, Tag,
+};
+
+/// The state of a run between two frames
+#[derive(Serialize, Deserialize)]
+pub struct EvalCheckpoint<F: LurkField> {
+    /// The name of the function that was run
+    pub func: String,
+    /// The digest of the function that was run, as `Func::digest` computes it
+    func_digest: String,
+    /// The number of frames computed before the checkpoint
+    pub frames_done: usize,
+    /// The children of the pointers reachable from the arguments, each one
+    /// after the pointers among its children
+    tuples: Vec<Vec<Ptr<F>>>,
+    /// The hash, secret and payload of each reachable commitment
+    comms: Vec<(F, F, Ptr<F>)>,
+    /// The arguments of the next frame
+    args: Vec<Ptr<F>>,
+}
+
+impl<F: LurkField> EvalCheckpoint<F> {
+    /// The checkpoint of a run of `func` after `frames_done` frames, whose next
+    /// frame takes `args`
+    pub fn new(func: &Func, frames_done: usize, args: &[Ptr<F>], store: &Store<F>) -> Result<Self> {
+        Self::with_digest(&func.name, func.digest()?, frames_done, args, store)
+    }
+
+    /// Like `new`, for a function whose name and digest are already known
+    pub(crate) fn with_digest(
+        func: &str,
+        func_digest: String,
+        frames_done: usize,
+        args: &[Ptr<F>],
+        store: &Store<F>,
+    ) -> Result<Self> {
+        let mut tuples = vec![];
+        let mut indices = HashMap::new();
+        let mut local =
+            |ptr: &Ptr<F>, tuples: &mut Vec<_>| localize(ptr, store, tuples, &mut indices);
+        let args = args
+            .iter()
+            .map(|arg| local(arg, &mut tuples))
+            .collect::<Result<Vec<_>>>()?;
+
+        // commitments are leaves, so the payloads they open to have to be
+        // looked for among the pointers found so far, and their own children
+        let mut comms = vec![];
+        let mut seen = HashSet::new();
+        let mut pending = args.clone();
+        let mut scanned = 0;
+        loop {
+            pending.extend(tuples[scanned..].iter().flatten());
+            scanned = tuples.len();
+            let Some(ptr) = pending.pop() else {
+                break;
+            };
+            let Ptr::Leaf(Tag::Expr(Comm), hash) = ptr else {
+                continue;
+            };
+            if !seen.insert(FWrap(hash)) {
+                continue;
+            }
+            if let Some((secret, payload)) = store.comms.get(&FWrap(hash)) {
+                let payload = local(payload, &mut tuples)?;
+                comms.push((hash, *secret, payload));
+                pending.push(payload);
+            }
+        }
+        Ok(Self {
+            func: func.to_string(),
+            func_digest,
+            frames_done,
+            tuples,
+            comms,
+            args,
+        })
+    }
+
+    /// Interns the part of the store carried by the checkpoint in `store` and
+    /// returns the arguments of the next frame, pointing to it. Fails if an
+    /// opening doesn't hash to its commitment.
+    pub fn restore(&self, store: &mut Store<F>) -> Result<Vec<Ptr<F>>> {
+        let interned = intern_tuples(&self.tuples, store)?;
+        for (hash, secret, payload) in &self.comms {
+            let payload = globalize(payload, &interned)?;
+            if store.hash_comm(*secret, &payload)? != *hash {
+                bail!("Checkpoint has an opening that doesn't match its commitment");
+            }
+            store.comms.insert(FWrap(*hash), (*secret, payload));
+        }
+        self.args
+            .iter()
+            .map(|arg| globalize(arg, &interned))
+            .collect()
+    }
+
+    /// Checks that the checkpoint was taken on a run of `func`
+    pub(crate) fn check_func(&self, func: &Func) -> Result<()> {
+        if self.func != func.name || self.func_digest != func.digest()? {
+            bail!(
+                "Checkpoint of `{}` ({}) can't resume `{}`",
+                self.func,
+                self.func_digest,
+                func.name
+            )
+        }
+        Ok(())
+    }
+
+    /// Writes the checkpoint to `path`, replacing the previous one only once
+    /// it's completely written, so a crash while writing leaves it intact
+    pub fn write(&self, path: &Path) -> Result<()>
+    where
+        F: Serialize,
+    {
+        let tmp = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        bincode::serialize_into(&mut writer, self)?;
+        writer
+            .into_inner()
+            .map_err(|e| anyhow!("Couldn't write checkpoint: {e}"))?
+            .sync_all()?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// Reads a checkpoint written by `write`. The lengths the file claims are
+    /// bounded by its size, so a corrupt file fails to read rather than
+    /// exhausting memory.
+    pub fn read(path: &Path) -> Result<Self>
+    where
+        F: DeserializeOwned,
+    {
+        use bincode::Options;
+
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        // the options of `bincode::serialize_into`, which `write` uses
+        Ok(bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .allow_trailing_bytes()
+            .with_limit(len)
+            .deserialize_from(BufReader::new(file))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use blstrs::Scalar as Fr;
+
+    use super::*;
+    use crate::func;
+
+    #[test]
+    fn forged_openings_are_rejected() {
+        let func = func!(id(x): 1 => {
+            return (x)
+        });
+        let store = &mut Store::<Fr>::default();
+        let payload = Ptr::num(Fr::from(42));
+        let secret = Fr::from(7);
+        let hash = store.hash_comm(secret, &payload).unwrap();
+        store.comms.insert(FWrap(hash), (secret, payload));
+        let comm = Ptr::comm(hash);
+        let checkpoint = EvalCheckpoint::new(&func, 0, &[comm], store).unwrap();
+        assert!(checkpoint.restore(&mut Store::default()).is_ok());
+
+        let mut forged = checkpoint;
+        forged.comms[0].1 = Fr::from(8);
+        assert!(forged.restore(&mut Store::default()).is_err());
+
+        // a file that claims more data than it has
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("id.checkpoint");
+        fs::write(&path, u64::MAX.to_le_bytes()).unwrap();
+        assert!(EvalCheckpoint::<Fr>::read(&path).is_err());
+    }
+}
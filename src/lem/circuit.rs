@@ -21,28 +21,37 @@
 //! on a concrete or a virtual path and use such booleans as the premises to build
 //! the constraints we care about with implication gadgets.
 
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet, VecDeque},
+};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use bellpepper::util_cs::witness_cs::WitnessCS;
 use bellpepper_core::{
-    ConstraintSystem, SynthesisError,
+    ConstraintSystem, Index, LinearCombination, SynthesisError, Variable,
     {
         boolean::{AllocatedBit, Boolean},
         num::AllocatedNum,
     },
 };
+use indexmap::IndexMap;
+#[cfg(feature = "parallel-slots")]
+use rayon::prelude::*;
 
 use crate::circuit::gadgets::{
     constraints::{
         add, alloc_equal, alloc_is_zero, allocate_is_negative, boolean_to_num, div, enforce_pack,
-        enforce_product_and_sum, enforce_selector_with_premise, implies_equal, implies_equal_const,
-        implies_u64, implies_unequal, implies_unequal_const, mul, pick, sub,
+        enforce_product_and_sum, enforce_selector_with_premise, implies_char, implies_equal,
+        implies_equal_const, implies_u32, implies_u64, implies_unequal, implies_unequal_const, mul,
+        or, pick, sub,
     },
     data::{allocate_constant, hash_poseidon},
     pointer::AllocatedPtr,
 };
 
 use crate::{
+    cache_map::CacheMap,
     field::{FWrap, LurkField},
     tag::ExprTag::*,
 };
@@ -53,57 +62,469 @@ use super::{
     slot::*,
     store::Store,
     var_map::VarMap,
-    Block, Ctrl, Func, Op, Tag, Var,
+    Block, Ctrl, Func, Op, Tag, Var, HASH_ARITIES,
 };
 
-/// Manages global allocations for constants in a constraint system
+/// Manages global allocations for constants in a constraint system.
+///
+/// Each constant is allocated once, the first time it's needed, so an allocator
+/// that outlives a single `synthesize` call, as in `Func::synthesize_frames`,
+/// deduplicates constants across all the frames of a step circuit. It also
+/// counts how many times each constant is requested, for `report`.
+#[derive(Default)]
+pub struct GlobalAllocator<F: LurkField>(HashMap<FWrap<F>, (AllocatedNum<F>, usize)>);
+
+/// The number of preimages whose Poseidon witnesses a `SlotWitnesses` keeps,
+/// bounding the memory they take
+const SLOT_WITNESSES_LEN: usize = 1 << 16;
+
+/// The auxiliary values and the images of the Poseidon circuits of the slots
+/// of a batch of frames, such as the frames of a proof, which witness
+/// generators extend their assignment with instead of hashing. The hashes of
+/// the environments, for instance, recur across frames, so each witness is
+/// computed once, for the first `SLOT_WITNESSES_LEN` preimages, and dropped
+/// along with the batch.
 #[derive(Default)]
-pub(crate) struct GlobalAllocator<F: LurkField>(HashMap<FWrap<F>, AllocatedNum<F>>);
+pub(crate) struct SlotWitnesses<F: LurkField>(CacheMap<Vec<FWrap<F>>, Box<(Vec<F>, F)>>);
+
+impl<F: LurkField> SlotWitnesses<F> {
+    /// The witness of the Poseidon circuit hashing `preimg`
+    pub(crate) fn get(&self, store: &Store<F>, preimg: &[F]) -> Cow<'_, (Vec<F>, F)> {
+        let key: Vec<_> = preimg.iter().copied().map(FWrap).collect();
+        if let Some(witness) = self.0.get(&key) {
+            return Cow::Borrowed(witness);
+        }
+        let witness = || {
+            let constants = store
+                .poseidon_cache
+                .constants
+                .constants(preimg.len().into());
+            constants.cache_hash_witness_aux(preimg.to_vec())
+        };
+        if self.0.len() < SLOT_WITNESSES_LEN {
+            Cow::Borrowed(self.0.insert_with(key, || Box::new(witness())))
+        } else {
+            Cow::Owned(witness())
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// The constants allocated by a `GlobalAllocator`, each with the number of
+/// times it was requested, which is how many times it would have been
+/// allocated without the allocator
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConstantsReport<F: LurkField> {
+    /// The constants and their requests, the most requested first
+    pub constants: Vec<(F, usize)>,
+}
+
+impl<F: LurkField> ConstantsReport<F> {
+    /// The number of constants allocated
+    #[inline]
+    pub fn allocated(&self) -> usize {
+        self.constants.len()
+    }
+
+    /// The number of allocations that would have been made without caching
+    pub fn requested(&self) -> usize {
+        self.constants.iter().map(|(_, requests)| requests).sum()
+    }
+
+    /// The number of allocations saved by caching
+    #[inline]
+    pub fn savings(&self) -> usize {
+        self.requested() - self.allocated()
+    }
+}
+
+impl<F: LurkField> std::fmt::Display for ConstantsReport<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} constants allocated for {} requests, {} allocations saved",
+            self.allocated(),
+            self.requested(),
+            self.savings()
+        )?;
+        for (constant, requests) in &self.constants {
+            write!(f, "\n  {}: {requests}", constant.trimmed_hex_digits())?;
+        }
+        Ok(())
+    }
+}
 
+/// How an operation is referred to in the namespaces of its constraints.
+///
+/// Debug-printing operations is slow (a call prints the whole function it
+/// calls), so operations are numbered in the order they're synthesized unless
+/// the `verbose-namespaces` feature is on. The numbering is deterministic and
+/// unique within a synthesis, just like the verbose names.
+struct OpName<'a> {
+    #[cfg_attr(feature = "verbose-namespaces", allow(dead_code))]
+    idx: usize,
+    #[cfg_attr(not(feature = "verbose-namespaces"), allow(dead_code))]
+    op: &'a Op,
+}
+
+impl std::fmt::Display for OpName<'_> {
+    #[cfg(feature = "verbose-namespaces")]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.op)
+    }
+
+    #[cfg(not(feature = "verbose-namespaces"))]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.idx)
+    }
+}
+
+/// Allocates `value` in a namespace named by `namespace`, which is only called
+/// if the constraint system keeps names or the allocation fails
 #[inline]
-fn allocate_num<F: LurkField, CS: ConstraintSystem<F>>(
+fn allocate_num<F: LurkField, CS: ConstraintSystem<F>, N: Fn() -> String>(
     cs: &mut CS,
-    namespace: &str,
+    namespace: N,
     value: F,
 ) -> Result<AllocatedNum<F>> {
-    AllocatedNum::alloc(cs.namespace(|| namespace), || Ok(value))
-        .with_context(|| format!("allocation for '{namespace}' failed"))
+    AllocatedNum::alloc(cs.namespace(&namespace), || Ok(value))
+        .with_context(|| format!("allocation for '{}' failed", namespace()))
 }
 
 #[inline]
-fn allocate_const<F: LurkField, CS: ConstraintSystem<F>>(
+fn allocate_const<F: LurkField, CS: ConstraintSystem<F>, N: Fn() -> String>(
     cs: &mut CS,
-    namespace: &str,
+    namespace: N,
     value: F,
 ) -> Result<AllocatedNum<F>> {
-    allocate_constant(&mut cs.namespace(|| namespace), value)
-        .with_context(|| format!("allocation for '{namespace}' failed"))
+    allocate_constant(&mut cs.namespace(&namespace), value)
+        .with_context(|| format!("allocation for '{}' failed", namespace()))
+}
+
+/// Decomposes `num` into bits and keeps the lowest `n` of them, for `n` up to 64
+fn lowest_bits<F: LurkField, CS: ConstraintSystem<F>>(
+    cs: &mut CS,
+    num: &AllocatedNum<F>,
+    n: u32,
+) -> Result<Vec<Boolean>, SynthesisError> {
+    assert!(n <= 64);
+    let mut bits = num.to_bits_le_strict(&mut cs.namespace(|| "to_bits_le"))?;
+    bits.truncate(n as usize);
+    Ok(bits)
+}
+
+/// Enforces `lo = lhs + wrap * shift`, where `wrap` is a bit and, if `premise`
+/// is true, `lo` fits in 64 bits. With a `shift` of `2^64` or `-2^64`, `lo` is
+/// `lhs` modulo `2^64`, as long as `lhs` is less than `2^64` away from `0..2^64`
+fn enforce_wrap_u64<F: LurkField, CS: ConstraintSystem<F>>(
+    cs: &mut CS,
+    premise: &Boolean,
+    lhs: LinearCombination<F>,
+    shift: F,
+    lo: &AllocatedNum<F>,
+    wrap: &AllocatedNum<F>,
+) -> Result<(), SynthesisError> {
+    cs.enforce(
+        || "wrap is a bit",
+        |lc| lc + wrap.get_variable(),
+        |lc| lc + CS::one() - wrap.get_variable(),
+        |lc| lc,
+    );
+    cs.enforce(
+        || "lo = lhs + wrap * shift",
+        |lc| lc + wrap.get_variable(),
+        |lc| lc + (shift, CS::one()),
+        |lc| lc + lo.get_variable() - &lhs,
+    );
+    implies_u64(cs.namespace(|| "lo_u64"), premise, lo)
 }
 
 impl<F: LurkField> GlobalAllocator<F> {
     /// Checks if the allocation for a numeric variable has already been cached.
     /// If so, return the cached allocation variable. Allocate as a constant,
     /// cache and return otherwise.
-    pub(crate) fn get_or_alloc_const<CS: ConstraintSystem<F>>(
+    pub fn get_or_alloc_const<CS: ConstraintSystem<F>>(
         &mut self,
         cs: &mut CS,
         f: F,
     ) -> Result<AllocatedNum<F>> {
         let wrap = FWrap(f);
-        match self.0.get(&wrap) {
-            Some(allocated_num) => Ok(allocated_num.to_owned()),
+        match self.0.get_mut(&wrap) {
+            Some((allocated_num, requests)) => {
+                *requests += 1;
+                Ok(allocated_num.to_owned())
+            }
             None => {
                 let allocated_num =
-                    allocate_const(cs, &format!("allocate constant {}", f.hex_digits()), f)?;
-                self.0.insert(wrap, allocated_num.clone());
+                    allocate_const(cs, || format!("allocate constant {}", f.hex_digits()), f)?;
+                self.0.insert(wrap, (allocated_num.clone(), 1));
                 Ok(allocated_num)
             }
         }
     }
+
+    /// Reports the constants allocated so far and how many times each one was
+    /// requested
+    pub fn report(&self) -> ConstantsReport<F> {
+        let mut constants = self
+            .0
+            .iter()
+            .map(|(FWrap(f), (_, requests))| (*f, *requests))
+            .collect::<Vec<_>>();
+        // ties are broken by value, so reports are deterministic
+        constants
+            .sort_by(|(f1, r1), (f2, r2)| r2.cmp(r1).then_with(|| FWrap(*f1).cmp(&FWrap(*f2))));
+        ConstantsReport { constants }
+    }
+
+    /// The number of constants allocated so far
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
 }
 
 type BoundAllocations<F> = VarMap<AllocatedPtr<F>>;
 
+/// Identifies a component (0 for the tag, 1 for the hash) of a returned value,
+/// so that the returns of the same value can share constraints when returns
+/// are merged. Constants are identified by their values and everything else
+/// by the variable holding it.
+#[derive(Clone, PartialEq, Eq)]
+enum ReturnKey<F: LurkField> {
+    Const(FWrap<F>),
+    Var(Var, usize),
+}
+
+impl<F: LurkField> std::hash::Hash for ReturnKey<F> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        match self {
+            Self::Const(f) => {
+                0u8.hash(state);
+                f.hash(state);
+            }
+            Self::Var(var, component) => {
+                1u8.hash(state);
+                var.hash(state);
+                component.hash(state);
+            }
+        }
+    }
+}
+
+type ReturnKeys<F> = HashMap<Var, [ReturnKey<F>; 2]>;
+
+/// Records the keys of the variables of `block` that hold constants, without
+/// looking into the bodies of called functions, whose variables are scoped
+/// apart
+fn collect_return_keys<F: LurkField>(
+    block: &Block,
+    store: &Store<F>,
+    keys: &mut ReturnKeys<F>,
+) -> Result<()> {
+    use ReturnKey::Const;
+    for op in &block.ops {
+        match op {
+            Op::Null(tgt, tag) => {
+                keys.insert(
+                    tgt.clone(),
+                    [Const(FWrap(tag.to_field())), Const(FWrap(F::ZERO))],
+                );
+            }
+            Op::Lit(tgt, lit) => {
+                let lit_z_ptr = lit.to_z_ptr(store);
+                let lit_tag = lit_z_ptr.tag.to_field();
+                keys.insert(
+                    tgt.clone(),
+                    [Const(FWrap(lit_tag)), Const(FWrap(lit_z_ptr.hash))],
+                );
+            }
+            Op::Cast(tgt, tag, src) => {
+                let [_, hash] = return_key(keys, src);
+                keys.insert(tgt.clone(), [Const(FWrap(tag.to_field())), hash]);
+            }
+            _ => (),
+        }
+    }
+    match &block.ctrl {
+        Ctrl::MatchTag(_, cases, def) => {
+            for block in cases.values().chain(def.as_deref()) {
+                collect_return_keys(block, store, keys)?;
+            }
+        }
+        Ctrl::MatchVal(_, cases, def) => {
+            for block in cases.values().chain(def.as_deref()) {
+                collect_return_keys(block, store, keys)?;
+            }
+        }
+        Ctrl::MatchSymbol(_, cases, def) => {
+            for block in cases.values().chain(def.as_deref()) {
+                collect_return_keys(block, store, keys)?;
+            }
+        }
+        Ctrl::IfEq(_, _, eq_block, else_block) => {
+            collect_return_keys(eq_block, store, keys)?;
+            collect_return_keys(else_block, store, keys)?;
+        }
+        Ctrl::Return(_) => (),
+    }
+    Ok(())
+}
+
+fn return_key<F: LurkField>(keys: &ReturnKeys<F>, var: &Var) -> [ReturnKey<F>; 2] {
+    match keys.get(var) {
+        Some(keys) => keys.clone(),
+        None => [0, 1].map(|component| ReturnKey::Var(var.clone(), component)),
+    }
+}
+
+/// The number of constraints of the Poseidon hash a slot for the hashes of
+/// `arity` pointers computes
+fn poseidon_constraints(arity: usize) -> usize {
+    match arity {
+        2 => 289,
+        3 => 337,
+        4 => 388,
+        6 => 484,
+        8 => 586,
+        _ => unreachable!("unsupported hash arity {arity}"),
+    }
+}
+
+/// The number of constraints `MergedReturns::enforce` creates for a function
+/// with `returns` as the keys of the values of its returns
+fn merged_returns_constraints<F: LurkField>(
+    returns: &[Vec<[ReturnKey<F>; 2]>],
+    output_size: usize,
+) -> usize {
+    let mut num_constraints = 0;
+    for i in 0..output_size {
+        for component in 0..2 {
+            let distinct: HashSet<_> = returns.iter().map(|keys| &keys[i][component]).collect();
+            // one term per distinct value, plus the output constraint
+            num_constraints += distinct.len() + 1;
+        }
+    }
+    num_constraints
+}
+
+/// The values returned by the `Ctrl::Return`s of a function body, each along
+/// with the premise of the path it's returned on, to be constrained at once
+/// when the whole body has been synthesized
+struct MergedReturns<F: LurkField> {
+    keys: ReturnKeys<F>,
+    returns: Vec<(Boolean, Vec<([ReturnKey<F>; 2], AllocatedPtr<F>)>)>,
+}
+
+impl<F: LurkField> MergedReturns<F> {
+    fn new(body: &Block, store: &Store<F>) -> Result<Self> {
+        let mut keys = HashMap::default();
+        collect_return_keys(body, store, &mut keys)?;
+        Ok(Self {
+            keys,
+            returns: vec![],
+        })
+    }
+
+    fn push(
+        &mut self,
+        premise: &Boolean,
+        return_vars: &[Var],
+        bound_allocations: &BoundAllocations<F>,
+    ) -> Result<()> {
+        let values = return_vars
+            .iter()
+            .map(|var| {
+                let allocated_ptr = bound_allocations.get(var)?.clone();
+                Ok((return_key(&self.keys, var), allocated_ptr))
+            })
+            .collect::<Result<_>>()?;
+        self.returns.push((premise.clone(), values));
+        Ok(())
+    }
+
+    /// Constrains each output to the value returned on the path taken. For each
+    /// component of an output, every distinct value `v` returned gets a term
+    /// `t = (sum of the premises of the returns of v) * v`, and the output is
+    /// constrained by `(sum of all premises) * output = sum of the terms`. At
+    /// most one premise holds, and when none does, as in calls on virtual
+    /// paths, the output is left unconstrained.
+    fn enforce<CS: ConstraintSystem<F>>(
+        self,
+        cs: &mut CS,
+        outputs: &[AllocatedPtr<F>],
+    ) -> Result<()> {
+        let sum_premises = |premises: &[&Boolean], lc: LinearCombination<F>| {
+            premises
+                .iter()
+                .fold(lc, |lc, premise| lc + &premise.lc(CS::one(), F::ONE))
+        };
+        let count_premises = |premises: &[&Boolean]| {
+            premises.iter().try_fold(F::ZERO, |acc, premise| {
+                premise
+                    .get_value()
+                    .map(|holds| if holds { acc + F::ONE } else { acc })
+            })
+        };
+        let all_premises: Vec<_> = self.returns.iter().map(|(premise, _)| premise).collect();
+
+        for (i, output) in outputs.iter().enumerate() {
+            for (component, out) in [output.tag(), output.hash()].into_iter().enumerate() {
+                let cs = &mut cs.namespace(|| format!("merged return {i}.{component}"));
+
+                let mut groups: IndexMap<&ReturnKey<F>, (Vec<&Boolean>, &AllocatedNum<F>)> =
+                    IndexMap::new();
+                for (premise, values) in &self.returns {
+                    let (keys, allocated_ptr) = &values[i];
+                    let num = if component == 0 {
+                        allocated_ptr.tag()
+                    } else {
+                        allocated_ptr.hash()
+                    };
+                    groups
+                        .entry(&keys[component])
+                        .or_insert_with(|| (vec![], num))
+                        .0
+                        .push(premise);
+                }
+
+                let mut terms = Vec::with_capacity(groups.len());
+                for (j, (premises, num)) in groups.values().enumerate() {
+                    let value = count_premises(premises.as_slice())
+                        .and_then(|count| num.get_value().map(|num| count * num));
+                    let term = AllocatedNum::alloc(cs.namespace(|| format!("term {j}")), || {
+                        value.ok_or(SynthesisError::AssignmentMissing)
+                    })?;
+                    cs.enforce(
+                        || format!("term {j} constraint"),
+                        |lc| sum_premises(premises.as_slice(), lc),
+                        |lc| lc + num.get_variable(),
+                        |lc| lc + term.get_variable(),
+                    );
+                    terms.push(term);
+                }
+
+                cs.enforce(
+                    || "output constraint",
+                    |lc| sum_premises(all_premises.as_slice(), lc),
+                    |lc| lc + out.get_variable(),
+                    |lc| terms.iter().fold(lc, |lc, term| lc + term.get_variable()),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
 impl Func {
     /// Allocates an unconstrained pointer
     fn allocate_ptr<F: LurkField, CS: ConstraintSystem<F>>(
@@ -113,8 +534,8 @@ impl Func {
         bound_allocations: &mut BoundAllocations<F>,
     ) -> Result<AllocatedPtr<F>> {
         let allocated_tag =
-            allocate_num(cs, &format!("allocate {var}'s tag"), z_ptr.tag.to_field())?;
-        let allocated_hash = allocate_num(cs, &format!("allocate {var}'s hash"), z_ptr.hash)?;
+            allocate_num(cs, || format!("allocate {var}'s tag"), z_ptr.tag.to_field())?;
+        let allocated_hash = allocate_num(cs, || format!("allocate {var}'s hash"), z_ptr.hash)?;
         let allocated_ptr = AllocatedPtr::from_parts(allocated_tag, allocated_hash);
         bound_allocations.insert(var.clone(), allocated_ptr.clone());
         Ok(allocated_ptr)
@@ -124,7 +545,7 @@ impl Func {
     fn allocate_input<F: LurkField, CS: ConstraintSystem<F>>(
         &self,
         cs: &mut CS,
-        store: &mut Store<F>,
+        store: &Store<F>,
         frame: &Frame<F>,
         bound_allocations: &mut BoundAllocations<F>,
     ) -> Result<()> {
@@ -138,7 +559,7 @@ impl Func {
     /// Allocates an unconstrained pointer for each output of the frame
     fn allocate_output<F: LurkField, CS: ConstraintSystem<F>>(
         cs: &mut CS,
-        store: &mut Store<F>,
+        store: &Store<F>,
         frame: &Frame<F>,
         bound_allocations: &mut BoundAllocations<F>,
     ) -> Result<Vec<AllocatedPtr<F>>> {
@@ -166,7 +587,7 @@ impl Func {
     ) -> Result<AllocatedNum<F>> {
         allocate_num(
             cs,
-            &format!("component {component_idx} for slot {slot}"),
+            || format!("component {component_idx} for slot {slot}"),
             value,
         )
     }
@@ -175,19 +596,37 @@ impl Func {
         cs: &mut CS,
         slot: &Slot,
         preallocated_preimg: Vec<AllocatedNum<F>>,
-        store: &mut Store<F>,
+        store: &Store<F>,
+        slot_witnesses: &SlotWitnesses<F>,
     ) -> Result<AllocatedNum<F>> {
         let cs = &mut cs.namespace(|| format!("image for slot {slot}"));
+        let is_poseidon = matches!(slot.typ, SlotType::Hash(_) | SlotType::Commitment);
+        if is_poseidon && cs.is_witness_generator() {
+            // the witness is computed once for all the frames of the batch
+            // hashing the same preimage
+            let values: Option<Vec<_>> = preallocated_preimg
+                .iter()
+                .map(AllocatedNum::get_value)
+                .collect();
+            if let Some(values) = values {
+                let witness = slot_witnesses.get(store, &values);
+                let (aux, img) = &*witness;
+                cs.extend_aux(aux);
+                return Ok(AllocatedNum::alloc(cs, || Ok(*img))?);
+            }
+        }
         let preallocated_img = {
             match slot.typ {
-                SlotType::Hash2 => {
-                    hash_poseidon(cs, preallocated_preimg, store.poseidon_cache.constants.c4())?
-                }
-                SlotType::Hash3 => {
-                    hash_poseidon(cs, preallocated_preimg, store.poseidon_cache.constants.c6())?
-                }
-                SlotType::Hash4 => {
-                    hash_poseidon(cs, preallocated_preimg, store.poseidon_cache.constants.c8())?
+                SlotType::Hash(arity) => {
+                    let constants = &store.poseidon_cache.constants;
+                    match arity {
+                        2 => hash_poseidon(cs, preallocated_preimg, constants.c4())?,
+                        3 => hash_poseidon(cs, preallocated_preimg, constants.c6())?,
+                        4 => hash_poseidon(cs, preallocated_preimg, constants.c8())?,
+                        6 => hash_poseidon(cs, preallocated_preimg, constants.c12())?,
+                        8 => hash_poseidon(cs, preallocated_preimg, constants.c16())?,
+                        _ => unreachable!("unsupported hash arity {arity}"),
+                    }
                 }
                 SlotType::Commitment => {
                     hash_poseidon(cs, preallocated_preimg, store.poseidon_cache.constants.c3())?
@@ -209,115 +648,201 @@ impl Func {
                         &diff_is_negative,
                     )?
                 }
+                SlotType::BitDecomp | SlotType::Range => {
+                    unreachable!("the images of decomposition slots are allocated as bits")
+                }
             }
         };
         Ok(preallocated_img)
     }
 
+    /// Computes the values of the preimage components of every slot of a type
+    /// visited by the interpreter. The other slots are left as `None`, to be
+    /// filled with the same dummy values
+    fn slot_preimage_values<F: LurkField>(
+        preimg_data: &[Option<PreimageData<F>>],
+        store: &Store<F>,
+    ) -> Result<Vec<Option<Vec<F>>>> {
+        // Hashing the pointers of all slots at once lets the store hash them in
+        // parallel, leaving only cache hits for the slots
+        let ptrs: Vec<_> = preimg_data
+            .iter()
+            .flatten()
+            .flat_map(|preimg_data| match preimg_data {
+                PreimageData::PtrVec(ptr_vec) => ptr_vec.clone(),
+                PreimageData::FPtr(_, ptr) => vec![*ptr],
+                _ => vec![],
+            })
+            .collect();
+        store.hash_ptrs(&ptrs)?;
+        let values = |maybe_preimg_data: &Option<PreimageData<F>>| -> Result<Option<Vec<F>>> {
+            let Some(preimg_data) = maybe_preimg_data else {
+                return Ok(None);
+            };
+            let values = match preimg_data {
+                PreimageData::PtrVec(ptr_vec) => {
+                    let mut values = Vec::with_capacity(2 * ptr_vec.len());
+                    for ptr in ptr_vec {
+                        let z_ptr = store.hash_ptr(ptr)?;
+                        values.push(z_ptr.tag.to_field());
+                        values.push(z_ptr.hash);
+                    }
+                    values
+                }
+                PreimageData::FPtr(f, ptr) => {
+                    let z_ptr = store.hash_ptr(ptr)?;
+                    vec![*f, z_ptr.tag.to_field(), z_ptr.hash]
+                }
+                PreimageData::FPair(a, b) => vec![*a, *b],
+                PreimageData::F(f) => vec![*f],
+            };
+            Ok(Some(values))
+        };
+        // The slots are independent from each other, so their values can be
+        // computed concurrently
+        #[cfg(feature = "parallel-slots")]
+        let preimg_data = preimg_data.par_iter();
+        #[cfg(not(feature = "parallel-slots"))]
+        let preimg_data = preimg_data.iter();
+        preimg_data.map(values).collect()
+    }
+
     /// Allocates unconstrained slots
     fn allocate_slots<F: LurkField, CS: ConstraintSystem<F>>(
         cs: &mut CS,
         preimg_data: &[Option<PreimageData<F>>],
         slot_type: SlotType,
         num_slots: usize,
-        store: &mut Store<F>,
+        store: &Store<F>,
+        slot_witnesses: &SlotWitnesses<F>,
     ) -> Result<Vec<(Vec<AllocatedNum<F>>, AllocatedNum<F>)>> {
-        assert!(
-            preimg_data.len() == num_slots,
-            "collected preimages not equal to the number of available slots"
-        );
-
-        let mut preallocations = Vec::with_capacity(num_slots);
-
-        // We must perform the allocations for the slots containing data collected
-        // by the interpreter. The `None` cases must be filled with dummy values
-        for (slot_idx, maybe_preimg_data) in preimg_data.iter().enumerate() {
-            if let Some(preimg_data) = maybe_preimg_data {
-                let slot = Slot {
-                    idx: slot_idx,
-                    typ: slot_type,
-                };
-
-                // Allocate the preimage because the image depends on it
-                let mut preallocated_preimg = Vec::with_capacity(slot_type.preimg_size());
-
-                match preimg_data {
-                    PreimageData::PtrVec(ptr_vec) => {
-                        let mut component_idx = 0;
-                        for ptr in ptr_vec {
-                            let z_ptr = store.hash_ptr(ptr)?;
-
-                            // allocate pointer tag
-                            preallocated_preimg.push(Self::allocate_preimg_component_for_slot(
-                                cs,
-                                &slot,
-                                component_idx,
-                                z_ptr.tag.to_field(),
-                            )?);
-
-                            component_idx += 1;
+        // Allocate the image by calling the arithmetic function according
+        // to the slot type
+        Self::allocate_slots_with(
+            cs,
+            preimg_data,
+            slot_type,
+            num_slots,
+            store,
+            |cs, slot, preimg, store| {
+                Self::allocate_img_for_slot(cs, slot, preimg.to_vec(), store, slot_witnesses)
+            },
+        )
+    }
 
-                            // allocate pointer hash
-                            preallocated_preimg.push(Self::allocate_preimg_component_for_slot(
-                                cs,
-                                &slot,
-                                component_idx,
-                                z_ptr.hash,
-                            )?);
+    /// Allocates unconstrained bit decomposition slots, whose images are the
+    /// lowest 64 bits of their single-component preimages
+    fn allocate_bit_decomp_slots<F: LurkField, CS: ConstraintSystem<F>>(
+        cs: &mut CS,
+        preimg_data: &[Option<PreimageData<F>>],
+        num_slots: usize,
+        store: &Store<F>,
+    ) -> Result<Vec<(AllocatedNum<F>, Vec<Boolean>)>> {
+        let slots = Self::allocate_slots_with(
+            cs,
+            preimg_data,
+            SlotType::BitDecomp,
+            num_slots,
+            store,
+            |cs, slot, preimg, _| {
+                let cs = &mut cs.namespace(|| format!("image for slot {slot}"));
+                Ok(lowest_bits(cs, &preimg[0], 64)?)
+            },
+        )?;
+        Ok(slots
+            .into_iter()
+            .map(|(mut preimg, bits)| (preimg.remove(0), bits))
+            .collect())
+    }
 
-                            component_idx += 1;
-                        }
-                    }
-                    PreimageData::FPtr(f, ptr) => {
-                        let z_ptr = store.hash_ptr(ptr)?;
-                        // allocate first component
-                        preallocated_preimg
-                            .push(Self::allocate_preimg_component_for_slot(cs, &slot, 0, *f)?);
-                        // allocate second component
-                        preallocated_preimg.push(Self::allocate_preimg_component_for_slot(
-                            cs,
-                            &slot,
-                            1,
-                            z_ptr.tag.to_field(),
-                        )?);
-                        // allocate third component
-                        preallocated_preimg.push(Self::allocate_preimg_component_for_slot(
-                            cs, &slot, 2, z_ptr.hash,
-                        )?);
-                    }
-                    PreimageData::FPair(a, b) => {
-                        // allocate first component
-                        preallocated_preimg
-                            .push(Self::allocate_preimg_component_for_slot(cs, &slot, 0, *a)?);
-
-                        // allocate second component
-                        preallocated_preimg
-                            .push(Self::allocate_preimg_component_for_slot(cs, &slot, 1, *b)?);
-                    }
-                }
+    /// Allocates unconstrained range slots, whose images are 64 bits that are
+    /// only constrained to be bits. Unlike those of bit decomposition slots,
+    /// they aren't constrained to pack into the preimage, which is up to the
+    /// `AssertRange` using the slot, so a range check of up to 64 bits costs 64
+    /// constraints instead of a full decomposition
+    fn allocate_range_slots<F: LurkField, CS: ConstraintSystem<F>>(
+        cs: &mut CS,
+        preimg_data: &[Option<PreimageData<F>>],
+        num_slots: usize,
+        store: &Store<F>,
+    ) -> Result<Vec<(AllocatedNum<F>, Vec<Boolean>)>> {
+        let slots = Self::allocate_slots_with(
+            cs,
+            preimg_data,
+            SlotType::Range,
+            num_slots,
+            store,
+            |cs, slot, preimg, _| {
+                let cs = &mut cs.namespace(|| format!("image for slot {slot}"));
+                let value = preimg[0].get_value().map(|f| f.to_u64_unchecked());
+                (0..64)
+                    .map(|i| {
+                        let bit = value.map(|value| (value >> i) & 1 == 1);
+                        let bit = AllocatedBit::alloc(cs.namespace(|| format!("bit {i}")), bit)?;
+                        Ok(Boolean::from(bit))
+                    })
+                    .collect()
+            },
+        )?;
+        Ok(slots
+            .into_iter()
+            .map(|(mut preimg, bits)| (preimg.remove(0), bits))
+            .collect())
+    }
 
-                // Allocate the image by calling the arithmetic function according
-                // to the slot type
-                let preallocated_img =
-                    Self::allocate_img_for_slot(cs, &slot, preallocated_preimg.clone(), store)?;
+    /// Allocates unconstrained slots, with `allocate_img` allocating the image
+    /// of each slot once its preimage is allocated
+    fn allocate_slots_with<F, CS, I, A>(
+        cs: &mut CS,
+        preimg_data: &[Option<PreimageData<F>>],
+        slot_type: SlotType,
+        num_slots: usize,
+        store: &Store<F>,
+        mut allocate_img: A,
+    ) -> Result<Vec<(Vec<AllocatedNum<F>>, I)>>
+    where
+        F: LurkField,
+        CS: ConstraintSystem<F>,
+        A: FnMut(&mut CS, &Slot, &[AllocatedNum<F>], &Store<F>) -> Result<I>,
+    {
+        assert!(
+            preimg_data.len() <= num_slots,
+            "collected more preimages than the number of available slots"
+        );
 
-                preallocations.push((preallocated_preimg, preallocated_img));
-            } else {
-                let slot = Slot {
-                    idx: slot_idx,
-                    typ: slot_type,
-                };
-                let preallocated_preimg: Vec<_> = (0..slot_type.preimg_size())
-                    .map(|component_idx| {
-                        Self::allocate_preimg_component_for_slot(cs, &slot, component_idx, F::ZERO)
-                    })
-                    .collect::<Result<_, _>>()?;
+        // The witness values are computed upfront, leaving only the allocations,
+        // which must happen in order, to be performed on the constraint system
+        let preimg_values = Self::slot_preimage_values(preimg_data, store)?;
 
-                let preallocated_img =
-                    Self::allocate_img_for_slot(cs, &slot, preallocated_preimg.clone(), store)?;
+        // The unused slots, including those pruned from the end of the frame's
+        // preimages, all take their witness from the same dummy values instead
+        // of a copy each. They're still allocated, so that every frame gives
+        // rise to the same circuit
+        let dummy_values = vec![F::ZERO; slot_type.preimg_size()];
 
-                preallocations.push((preallocated_preimg, preallocated_img));
-            }
+        let mut preallocations = Vec::with_capacity(num_slots);
+        for slot_idx in 0..num_slots {
+            let slot = Slot {
+                idx: slot_idx,
+                typ: slot_type,
+            };
+            let values = preimg_values
+                .get(slot_idx)
+                .and_then(Option::as_ref)
+                .unwrap_or(&dummy_values);
+
+            // Allocate the preimage because the image depends on it
+            let preallocated_preimg = values
+                .iter()
+                .enumerate()
+                .map(|(component_idx, value)| {
+                    Self::allocate_preimg_component_for_slot(cs, &slot, component_idx, *value)
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            let preallocated_img = allocate_img(cs, &slot, &preallocated_preimg, store)?;
+
+            preallocations.push((preallocated_preimg, preallocated_img));
         }
 
         Ok(preallocations)
@@ -334,10 +859,113 @@ impl Func {
     pub fn synthesize<F: LurkField, CS: ConstraintSystem<F>>(
         &self,
         cs: &mut CS,
-        store: &mut Store<F>,
+        store: &Store<F>,
         frame: &Frame<F>,
     ) -> Result<()> {
-        let mut global_allocator = GlobalAllocator::default();
+        self.synthesize_with_allocator(cs, store, frame, &mut GlobalAllocator::default())
+    }
+
+    /// Synthesizes every frame of `frames`, each in its own namespace, taking
+    /// all constants from `global_allocator`. Constants are thus allocated once
+    /// for the whole batch instead of once per frame.
+    pub fn synthesize_frames<F: LurkField, CS: ConstraintSystem<F>>(
+        &self,
+        cs: &mut CS,
+        store: &Store<F>,
+        frames: &[Frame<F>],
+        global_allocator: &mut GlobalAllocator<F>,
+    ) -> Result<()> {
+        let slot_witnesses = SlotWitnesses::default();
+        for (i, frame) in frames.iter().enumerate() {
+            self.synthesize_with_caches(
+                &mut cs.namespace(|| format!("frame {i}")),
+                store,
+                frame,
+                global_allocator,
+                &slot_witnesses,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// A generator of the witnesses of the frames of the function. The
+    /// constraints don't depend on the frame, so `synthesize_blank` lays out
+    /// the circuit once, here, while every frame only goes through a
+    /// witness-only pass.
+    pub fn witness_generator<F: LurkField>(
+        &self,
+        store: &Store<F>,
+    ) -> Result<WitnessGenerator<'_>> {
+        let mut cs = ConstraintCounter::default();
+        self.synthesize_blank(&mut cs, store)?;
+        Ok(WitnessGenerator {
+            func: self,
+            num_inputs: cs.inputs,
+            num_aux: cs.aux,
+        })
+    }
+
+    /// Computes the witnesses of `frames`, as `WitnessGenerator::generate`
+    /// does, in parallel. Synthesis only reads the store, whose hash caches
+    /// are behind locks, so all the frames share it, along with the witnesses
+    /// of their slots.
+    pub fn generate_witnesses<F: LurkField>(
+        &self,
+        store: &Store<F>,
+        frames: &[Frame<F>],
+    ) -> Result<Vec<WitnessCS<F>>> {
+        let generator = self.witness_generator(store)?;
+        let slot_witnesses = SlotWitnesses::default();
+        frames
+            .par_iter()
+            .map(|frame| generator.generate_with(store, frame, &slot_witnesses))
+            .collect()
+    }
+
+    /// Like `synthesize`, but a constraint system that only generates witnesses
+    /// is extended with `witness`, as computed by `WitnessGenerator::generate`
+    /// for `frame`, instead of synthesizing the frame again
+    pub fn synthesize_with_witness<F: LurkField, CS: ConstraintSystem<F>>(
+        &self,
+        cs: &mut CS,
+        store: &Store<F>,
+        frame: &Frame<F>,
+        witness: Option<&WitnessCS<F>>,
+    ) -> Result<()> {
+        if cs.is_witness_generator() {
+            if let Some(witness) = witness {
+                cs.extend_aux(witness.aux_slice());
+                // the first input is the constant one, which `cs` already has
+                cs.extend_inputs(&witness.inputs_slice()[1..]);
+                return Ok(());
+            }
+        }
+        self.synthesize(cs, store, frame)
+    }
+
+    /// Like `synthesize`, but reuses the constants already allocated by
+    /// `global_allocator`
+    pub fn synthesize_with_allocator<F: LurkField, CS: ConstraintSystem<F>>(
+        &self,
+        cs: &mut CS,
+        store: &Store<F>,
+        frame: &Frame<F>,
+        global_allocator: &mut GlobalAllocator<F>,
+    ) -> Result<()> {
+        let slot_witnesses = SlotWitnesses::default();
+        self.synthesize_with_caches(cs, store, frame, global_allocator, &slot_witnesses)
+    }
+
+    /// Like `synthesize_with_allocator`, but also reuses the witnesses of the
+    /// slots in `slot_witnesses`
+    fn synthesize_with_caches<F: LurkField, CS: ConstraintSystem<F>>(
+        &self,
+        cs: &mut CS,
+        store: &Store<F>,
+        frame: &Frame<F>,
+        global_allocator: &mut GlobalAllocator<F>,
+        slot_witnesses: &SlotWitnesses<F>,
+    ) -> Result<()> {
         let mut bound_allocations = BoundAllocations::new();
 
         // Inputs are constrained by their usage inside the function body
@@ -348,29 +976,18 @@ impl Func {
         // Slots are constrained by their usage inside the function body. The ones
         // not used in throughout the concrete path are effectively unconstrained,
         // that's why they are filled with dummies
-        let preallocated_hash2_slots = Func::allocate_slots(
-            cs,
-            &frame.preimages.hash2,
-            SlotType::Hash2,
-            self.slot.hash2,
-            store,
-        )?;
-
-        let preallocated_hash3_slots = Func::allocate_slots(
-            cs,
-            &frame.preimages.hash3,
-            SlotType::Hash3,
-            self.slot.hash3,
-            store,
-        )?;
-
-        let preallocated_hash4_slots = Func::allocate_slots(
-            cs,
-            &frame.preimages.hash4,
-            SlotType::Hash4,
-            self.slot.hash4,
-            store,
-        )?;
+        let mut preallocated_hash_slots = HashMap::default();
+        for arity in HASH_ARITIES {
+            let slots = Func::allocate_slots(
+                cs,
+                frame.preimages.hash(arity),
+                SlotType::Hash(arity),
+                self.slot.hash(arity),
+                store,
+                slot_witnesses,
+            )?;
+            preallocated_hash_slots.insert(arity, slots);
+        }
 
         let preallocated_commitment_slots = Func::allocate_slots(
             cs,
@@ -378,6 +995,7 @@ impl Func {
             SlotType::Commitment,
             self.slot.commitment,
             store,
+            slot_witnesses,
         )?;
 
         let preallocated_less_than_slots = Func::allocate_slots(
@@ -386,18 +1004,34 @@ impl Func {
             SlotType::LessThan,
             self.slot.less_than,
             store,
+            slot_witnesses,
+        )?;
+
+        let preallocated_bit_decomp_slots = Func::allocate_bit_decomp_slots(
+            cs,
+            &frame.preimages.bit_decomp,
+            self.slot.bit_decomp,
+            store,
         )?;
 
+        let preallocated_range_slots =
+            Func::allocate_range_slots(cs, &frame.preimages.range, self.slot.range, store)?;
+
         struct Globals<'a, F: LurkField> {
-            store: &'a mut Store<F>,
+            store: &'a Store<F>,
             global_allocator: &'a mut GlobalAllocator<F>,
-            preallocated_hash2_slots: Vec<(Vec<AllocatedNum<F>>, AllocatedNum<F>)>,
-            preallocated_hash3_slots: Vec<(Vec<AllocatedNum<F>>, AllocatedNum<F>)>,
-            preallocated_hash4_slots: Vec<(Vec<AllocatedNum<F>>, AllocatedNum<F>)>,
+            /// The hash slots of each of the `HASH_ARITIES`
+            preallocated_hash_slots: HashMap<usize, Vec<(Vec<AllocatedNum<F>>, AllocatedNum<F>)>>,
             preallocated_commitment_slots: Vec<(Vec<AllocatedNum<F>>, AllocatedNum<F>)>,
             preallocated_less_than_slots: Vec<(Vec<AllocatedNum<F>>, AllocatedNum<F>)>,
+            preallocated_bit_decomp_slots: Vec<(AllocatedNum<F>, Vec<Boolean>)>,
+            preallocated_range_slots: Vec<(AllocatedNum<F>, Vec<Boolean>)>,
             call_outputs: VecDeque<Vec<Ptr<F>>>,
             call_count: usize,
+            op_count: usize,
+            coproc_outputs: VecDeque<Vec<Ptr<F>>>,
+            merge_returns: bool,
+            merged_returns: Vec<MergedReturns<F>>,
         }
 
         fn recurse<F: LurkField, CS: ConstraintSystem<F>>(
@@ -405,54 +1039,56 @@ impl Func {
             block: &Block,
             not_dummy: &Boolean,
             next_slot: &mut SlotsCounter,
+            mut memo: SlotMemo,
             bound_allocations: &mut BoundAllocations<F>,
             preallocated_outputs: &Vec<AllocatedPtr<F>>,
             g: &mut Globals<'_, F>,
         ) -> Result<()> {
             for op in &block.ops {
+                g.op_count += 1;
+                let op_name = OpName {
+                    idx: g.op_count,
+                    op,
+                };
                 macro_rules! hash_helper {
-                    ( $img: expr, $tag: expr, $preimg: expr, $slot: expr ) => {
+                    ( $img: expr, $tag: expr, $preimg: expr, $arity: expr ) => {
                         // Retrieve allocated preimage
                         let allocated_preimg = bound_allocations.get_many($preimg)?;
 
                         // Retrieve the preallocated preimage and image for this slot
-                        let (preallocated_preimg, preallocated_img_hash) = match $slot {
-                            SlotType::Hash2 => {
-                                &g.preallocated_hash2_slots[next_slot.consume_hash2()]
-                            }
-                            SlotType::Hash3 => {
-                                &g.preallocated_hash3_slots[next_slot.consume_hash3()]
-                            }
-                            SlotType::Hash4 => {
-                                &g.preallocated_hash4_slots[next_slot.consume_hash4()]
-                            }
-                            _ => panic!("Invalid slot type for hash_helper macro"),
-                        };
+                        let (slot, shared) =
+                            memo.hash_slot(op, || next_slot.consume_hash($arity));
+                        let (preallocated_preimg, preallocated_img_hash) =
+                            &g.preallocated_hash_slots[&$arity][slot];
 
                         // For each component of the preimage, add implication constraints
-                        // for its tag and hash
-                        for (i, allocated_ptr) in allocated_preimg.iter().enumerate() {
-                            let var = &$preimg[i];
-                            let ptr_idx = 2 * i;
-                            implies_equal(
-                                &mut cs.namespace(|| {
-                                    format!("implies equal for {var}'s tag (OP {:?}, pos {i})", &op)
-                                }),
-                                not_dummy,
-                                allocated_ptr.tag(),
-                                &preallocated_preimg[ptr_idx], // tag index
-                            )?;
-                            implies_equal(
-                                &mut cs.namespace(|| {
-                                    format!(
-                                        "implies equal for {var}'s hash (OP {:?}, pos {i})",
-                                        &op
-                                    )
-                                }),
-                                not_dummy,
-                                allocated_ptr.hash(),
-                                &preallocated_preimg[ptr_idx + 1], // hash index
-                            )?;
+                        // for its tag and hash, unless an earlier operation of the path
+                        // already did so for the shared slot
+                        if !shared {
+                            for (i, allocated_ptr) in allocated_preimg.iter().enumerate() {
+                                let var = &$preimg[i];
+                                let ptr_idx = 2 * i;
+                                implies_equal(
+                                    &mut cs.namespace(|| {
+                                        format!(
+                                            "implies equal for {var}'s tag (OP {op_name}, pos {i})"
+                                        )
+                                    }),
+                                    not_dummy,
+                                    allocated_ptr.tag(),
+                                    &preallocated_preimg[ptr_idx], // tag index
+                                )?;
+                                implies_equal(
+                                    &mut cs.namespace(|| {
+                                        format!(
+                                            "implies equal for {var}'s hash (OP {op_name}, pos {i})"
+                                        )
+                                    }),
+                                    not_dummy,
+                                    allocated_ptr.hash(),
+                                    &preallocated_preimg[ptr_idx + 1], // hash index
+                                )?;
+                            }
                         }
 
                         // Allocate the image tag if it hasn't been allocated before,
@@ -465,33 +1101,27 @@ impl Func {
                 }
 
                 macro_rules! unhash_helper {
-                    ( $preimg: expr, $img: expr, $slot: expr ) => {
+                    ( $preimg: expr, $img: expr, $arity: expr ) => {
                         // Retrieve allocated image
                         let allocated_img = bound_allocations.get($img)?;
 
                         // Retrieve the preallocated preimage and image for this slot
-                        let (preallocated_preimg, preallocated_img) = match $slot {
-                            SlotType::Hash2 => {
-                                &g.preallocated_hash2_slots[next_slot.consume_hash2()]
-                            }
-                            SlotType::Hash3 => {
-                                &g.preallocated_hash3_slots[next_slot.consume_hash3()]
-                            }
-                            SlotType::Hash4 => {
-                                &g.preallocated_hash4_slots[next_slot.consume_hash4()]
-                            }
-                            _ => panic!("Invalid slot type for unhash_helper macro"),
-                        };
+                        let (slot, shared) = memo.hash_slot(op, || next_slot.consume_hash($arity));
+                        let (preallocated_preimg, preallocated_img) =
+                            &g.preallocated_hash_slots[&$arity][slot];
 
-                        // Add the implication constraint for the image
-                        implies_equal(
-                            &mut cs.namespace(|| {
-                                format!("implies equal for {}'s hash (OP {:?})", $img, &op)
-                            }),
-                            not_dummy,
-                            allocated_img.hash(),
-                            &preallocated_img,
-                        )?;
+                        // Add the implication constraint for the image, unless an
+                        // earlier operation of the path already did so for the shared slot
+                        if !shared {
+                            implies_equal(
+                                &mut cs.namespace(|| {
+                                    format!("implies equal for {}'s hash (OP {op_name})", $img)
+                                }),
+                                not_dummy,
+                                allocated_img.hash(),
+                                &preallocated_img,
+                            )?;
+                        }
 
                         // Retrieve preimage hashes and tags create the full preimage pointers
                         // and add them to bound allocations
@@ -513,12 +1143,17 @@ impl Func {
                         // Note that, because there's currently no way of deferring giving
                         // a value to the allocated nums to be filled later, we must either
                         // add the results of the call to the witness, or recompute them.
-                        let output_vals = if let Some(true) = not_dummy.get_value() {
-                            g.call_outputs.pop_front().unwrap()
+                        // Blank frames don't have call outputs, not even for the calls
+                        // in the path their dummy inputs lead to
+                        let concrete_output_vals = if let Some(true) = not_dummy.get_value() {
+                            g.call_outputs.pop_front()
                         } else {
+                            None
+                        };
+                        let output_vals = concrete_output_vals.unwrap_or_else(|| {
                             let dummy = Ptr::Leaf(Tag::Expr(Nil), F::ZERO);
                             (0..out.len()).map(|_| dummy).collect()
-                        };
+                        });
                         assert_eq!(output_vals.len(), out.len());
                         let mut output_ptrs = Vec::with_capacity(out.len());
                         for (ptr, var) in output_vals.iter().zip(out.iter()) {
@@ -535,33 +1170,34 @@ impl Func {
                         });
                         // Finally, we synthesize the circuit for the function body
                         g.call_count += 1;
+                        let call_idx = g.call_count;
+                        if g.merge_returns {
+                            let merged_returns = MergedReturns::new(&func.body, g.store)?;
+                            g.merged_returns.push(merged_returns);
+                        }
                         recurse(
-                            &mut cs.namespace(|| format!("Call {}", g.call_count)),
+                            &mut cs.namespace(|| format!("Call {call_idx}")),
                             &func.body,
                             not_dummy,
                             next_slot,
+                            SlotMemo::new(func.share_slots),
                             bound_allocations,
                             &output_ptrs,
                             g,
                         )?;
+                        if g.merge_returns {
+                            let merged_returns = g.merged_returns.pop().unwrap();
+                            merged_returns.enforce(
+                                &mut cs.namespace(|| format!("Call {call_idx} returns")),
+                                &output_ptrs,
+                            )?;
+                        }
                     }
-                    Op::Hash2(img, tag, preimg) => {
-                        hash_helper!(img.clone(), tag, preimg, SlotType::Hash2);
-                    }
-                    Op::Hash3(img, tag, preimg) => {
-                        hash_helper!(img.clone(), tag, preimg, SlotType::Hash3);
-                    }
-                    Op::Hash4(img, tag, preimg) => {
-                        hash_helper!(img.clone(), tag, preimg, SlotType::Hash4);
-                    }
-                    Op::Unhash2(preimg, img) => {
-                        unhash_helper!(preimg, img, SlotType::Hash2);
-                    }
-                    Op::Unhash3(preimg, img) => {
-                        unhash_helper!(preimg, img, SlotType::Hash3);
+                    Op::Hash(img, tag, preimg) => {
+                        hash_helper!(img.clone(), tag, preimg, preimg.len());
                     }
-                    Op::Unhash4(preimg, img) => {
-                        unhash_helper!(preimg, img, SlotType::Hash4);
+                    Op::Unhash(preimg, img) => {
+                        unhash_helper!(preimg, img, preimg.len());
                     }
                     Op::Null(tgt, tag) => {
                         let tag = g.global_allocator.get_or_alloc_const(cs, tag.to_field())?;
@@ -570,11 +1206,11 @@ impl Func {
                         bound_allocations.insert(tgt.clone(), allocated_ptr);
                     }
                     Op::Lit(tgt, lit) => {
-                        let lit_ptr = lit.to_ptr(g.store);
-                        let lit_tag = lit_ptr.tag().to_field();
-                        let lit_hash = g.store.hash_ptr(&lit_ptr)?.hash;
+                        let lit_z_ptr = lit.to_z_ptr(g.store);
+                        let lit_tag = lit_z_ptr.tag.to_field();
                         let allocated_tag = g.global_allocator.get_or_alloc_const(cs, lit_tag)?;
-                        let allocated_hash = g.global_allocator.get_or_alloc_const(cs, lit_hash)?;
+                        let allocated_hash =
+                            g.global_allocator.get_or_alloc_const(cs, lit_z_ptr.hash)?;
                         let allocated_ptr = AllocatedPtr::from_parts(allocated_tag, allocated_hash);
                         bound_allocations.insert(tgt.clone(), allocated_ptr);
                     }
@@ -681,7 +1317,7 @@ impl Func {
                         for (i, n) in [a.hash(), b.hash()].into_iter().enumerate() {
                             implies_equal(
                                 &mut cs.namespace(|| {
-                                    format!("implies equal for component {i} (OP {:?})", &op)
+                                    format!("implies equal for component {i} (OP {op_name})")
                                 }),
                                 not_dummy,
                                 n,
@@ -694,10 +1330,14 @@ impl Func {
                     Op::Trunc(tgt, a, n) => {
                         assert!(*n <= 64);
                         let a = bound_allocations.get(a)?;
-                        let mut trunc_bits = a
-                            .hash()
-                            .to_bits_le_strict(&mut cs.namespace(|| "to_bits_le"))?;
-                        trunc_bits.truncate(*n as usize);
+                        let (preallocated_preimg, bits) =
+                            &g.preallocated_bit_decomp_slots[next_slot.consume_bit_decomp()];
+                        implies_equal(
+                            &mut cs.namespace(|| format!("implies equal (OP {op_name})")),
+                            not_dummy,
+                            a.hash(),
+                            preallocated_preimg,
+                        )?;
                         let trunc = AllocatedNum::alloc(cs.namespace(|| "trunc"), || {
                             let b = if *n < 64 { (1 << *n) - 1 } else { u64::MAX };
                             a.hash()
@@ -705,11 +1345,102 @@ impl Func {
                                 .map(|a| F::from_u64(a.to_u64_unchecked() & b))
                                 .ok_or(SynthesisError::AssignmentMissing)
                         })?;
-                        enforce_pack(&mut cs.namespace(|| "enforce_trunc"), &trunc_bits, &trunc)?;
+                        enforce_pack(
+                            &mut cs.namespace(|| "enforce_trunc"),
+                            &bits[..*n as usize],
+                            &trunc,
+                        )?;
+                        let tag = g
+                            .global_allocator
+                            .get_or_alloc_const(cs, Tag::Expr(Num).to_field())?;
+                        let c = AllocatedPtr::from_parts(tag, trunc);
+                        bound_allocations.insert(tgt.clone(), c);
+                    }
+                    Op::AssertRange(a, n) => {
+                        assert!(*n <= 64);
+                        let a = bound_allocations.get(a)?.hash();
+                        let (preallocated_preimg, bits) =
+                            &g.preallocated_range_slots[next_slot.consume_range()];
+                        implies_equal(
+                            &mut cs.namespace(|| format!("implies equal (OP {op_name})")),
+                            not_dummy,
+                            a,
+                            preallocated_preimg,
+                        )?;
+                        // `a` fits in `n` bits iff it's the packing of its lowest `n` bits
+                        let packed = AllocatedNum::alloc(
+                            cs.namespace(|| format!("packed (OP {op_name})")),
+                            || {
+                                let b = if *n < 64 { (1 << *n) - 1 } else { u64::MAX };
+                                a.get_value()
+                                    .map(|a| F::from_u64(a.to_u64_unchecked() & b))
+                                    .ok_or(SynthesisError::AssignmentMissing)
+                            },
+                        )?;
+                        enforce_pack(
+                            &mut cs.namespace(|| format!("enforce_pack (OP {op_name})")),
+                            &bits[..*n as usize],
+                            &packed,
+                        )?;
+                        implies_equal(
+                            &mut cs.namespace(|| format!("implies in range (OP {op_name})")),
+                            not_dummy,
+                            a,
+                            &packed,
+                        )?;
+                    }
+                    Op::AssertU32(a) => {
+                        let a = bound_allocations.get(a)?.hash();
+                        let cs = cs.namespace(|| format!("implies_u32 (OP {op_name})"));
+                        implies_u32(cs, not_dummy, a)?;
+                    }
+                    Op::AssertChar(a) => {
+                        let a = bound_allocations.get(a)?.hash();
+                        let cs = cs.namespace(|| format!("implies_char (OP {op_name})"));
+                        implies_char(cs, not_dummy, a)?;
+                    }
+                    Op::And(tgt, a, b) | Op::Or(tgt, a, b) | Op::Xor(tgt, a, b) => {
+                        let a = bound_allocations.get(a)?.hash();
+                        let b = bound_allocations.get(b)?.hash();
+                        let mut operand_bits = Vec::with_capacity(2);
+                        for (i, n) in [a, b].into_iter().enumerate() {
+                            let (preallocated_preimg, bits) =
+                                &g.preallocated_bit_decomp_slots[next_slot.consume_bit_decomp()];
+                            implies_equal(
+                                &mut cs.namespace(|| {
+                                    format!("implies equal for operand {i} (OP {op_name})")
+                                }),
+                                not_dummy,
+                                n,
+                                preallocated_preimg,
+                            )?;
+                            operand_bits.push(bits);
+                        }
+                        let mut c_bits = Vec::with_capacity(64);
+                        for (i, (a, b)) in operand_bits[0].iter().zip(operand_bits[1]).enumerate() {
+                            let cs = cs.namespace(|| format!("bit {i} of {tgt}"));
+                            let c = match op {
+                                Op::And(..) => Boolean::and(cs, a, b)?,
+                                Op::Or(..) => or(cs, a, b)?,
+                                _ => Boolean::xor(cs, a, b)?,
+                            };
+                            c_bits.push(c);
+                        }
+                        let c = AllocatedNum::alloc(cs.namespace(|| format!("{tgt}")), || {
+                            let a = a.get_value().ok_or(SynthesisError::AssignmentMissing)?;
+                            let b = b.get_value().ok_or(SynthesisError::AssignmentMissing)?;
+                            let c = op.bitwise_u64(a.to_u64_unchecked(), b.to_u64_unchecked());
+                            Ok(F::from_u64(c))
+                        })?;
+                        enforce_pack(
+                            &mut cs.namespace(|| format!("enforce_pack {tgt}")),
+                            &c_bits,
+                            &c,
+                        )?;
                         let tag = g
                             .global_allocator
                             .get_or_alloc_const(cs, Tag::Expr(Num).to_field())?;
-                        let c = AllocatedPtr::from_parts(tag, trunc);
+                        let c = AllocatedPtr::from_parts(tag, c);
                         bound_allocations.insert(tgt.clone(), c);
                     }
                     Op::DivRem64(tgt, a, b) => {
@@ -752,6 +1483,117 @@ impl Func {
                         bound_allocations.insert(tgt[0].clone(), div_ptr);
                         bound_allocations.insert(tgt[1].clone(), rem_ptr);
                     }
+                    Op::AddI64(tgt, a, b) | Op::SubI64(tgt, a, b) | Op::MulI64(tgt, a, b) => {
+                        let a = bound_allocations.get(a)?.hash();
+                        let b = bound_allocations.get(b)?.hash();
+                        implies_u64(cs.namespace(|| "a_u64"), not_dummy, a)?;
+                        implies_u64(cs.namespace(|| "b_u64"), not_dummy, b)?;
+                        // the result and what wraps around, which is zero on virtual paths
+                        let values = a.get_value().zip(b.get_value()).map(|(a, b)| {
+                            if not_dummy.get_value().unwrap() {
+                                let (a, b) = (a.to_u64_unchecked(), b.to_u64_unchecked());
+                                let over = match op {
+                                    Op::AddI64(..) => (a as u128 + b as u128) >> 64,
+                                    Op::SubI64(..) => u128::from(a < b),
+                                    _ => (a as u128 * b as u128) >> 64,
+                                };
+                                (F::from_u64(op.signed_i64(a, b)), F::from_u64(over as u64))
+                            } else {
+                                let c = match op {
+                                    Op::AddI64(..) => a + b,
+                                    Op::SubI64(..) => a - b,
+                                    _ => a * b,
+                                };
+                                (c, F::ZERO)
+                            }
+                        });
+                        let c = AllocatedNum::alloc(cs.namespace(|| format!("{tgt}")), || {
+                            values.map(|v| v.0).ok_or(SynthesisError::AssignmentMissing)
+                        })?;
+                        let over = AllocatedNum::alloc(cs.namespace(|| "over"), || {
+                            values.map(|v| v.1).ok_or(SynthesisError::AssignmentMissing)
+                        })?;
+                        let two_64 = F::from_u64(u64::MAX) + F::ONE;
+                        match op {
+                            Op::AddI64(..) => {
+                                let lhs =
+                                    LinearCombination::zero() + a.get_variable() + b.get_variable();
+                                enforce_wrap_u64(cs, not_dummy, lhs, -two_64, &c, &over)?;
+                            }
+                            Op::SubI64(..) => {
+                                let lhs =
+                                    LinearCombination::zero() + a.get_variable() - b.get_variable();
+                                enforce_wrap_u64(cs, not_dummy, lhs, two_64, &c, &over)?;
+                            }
+                            _ => {
+                                implies_u64(cs.namespace(|| "c_u64"), not_dummy, &c)?;
+                                implies_u64(cs.namespace(|| "over_u64"), not_dummy, &over)?;
+                                cs.enforce(
+                                    || "a * b = c + over * 2^64",
+                                    |lc| lc + a.get_variable(),
+                                    |lc| lc + b.get_variable(),
+                                    |lc| lc + c.get_variable() + (two_64, over.get_variable()),
+                                );
+                            }
+                        }
+                        let tag = g
+                            .global_allocator
+                            .get_or_alloc_const(cs, Tag::Expr(Num).to_field())?;
+                        let c = AllocatedPtr::from_parts(tag, c);
+                        bound_allocations.insert(tgt.clone(), c);
+                    }
+                    Op::LtI64(tgt, a, b) => {
+                        let a = bound_allocations.get(a)?.hash();
+                        let b = bound_allocations.get(b)?.hash();
+                        implies_u64(cs.namespace(|| "a_u64"), not_dummy, a)?;
+                        implies_u64(cs.namespace(|| "b_u64"), not_dummy, b)?;
+                        let two_63 = F::from_u64(1 << 63);
+                        let two_64 = two_63.double();
+                        // flipping the sign bits maps the i64s to u64s in the same order
+                        let mut flipped = Vec::with_capacity(2);
+                        for (i, n) in [a, b].into_iter().enumerate() {
+                            let cs = &mut cs.namespace(|| format!("operand {i}"));
+                            let values = n.get_value().map(|n| {
+                                if not_dummy.get_value().unwrap() {
+                                    let n = n.to_u64_unchecked();
+                                    (F::from_u64(n ^ (1 << 63)), F::from_u64(n >> 63))
+                                } else {
+                                    (n + two_63, F::ZERO)
+                                }
+                            });
+                            let flip = AllocatedNum::alloc(cs.namespace(|| "flip"), || {
+                                values.map(|v| v.0).ok_or(SynthesisError::AssignmentMissing)
+                            })?;
+                            let sign = AllocatedNum::alloc(cs.namespace(|| "sign"), || {
+                                values.map(|v| v.1).ok_or(SynthesisError::AssignmentMissing)
+                            })?;
+                            let lhs =
+                                LinearCombination::zero() + n.get_variable() + (two_63, CS::one());
+                            enforce_wrap_u64(cs, not_dummy, lhs, -two_64, &flip, &sign)?;
+                            flipped.push(flip);
+                        }
+                        let (a, b) = (&flipped[0], &flipped[1]);
+                        // `a < b` iff `a - b` wraps around
+                        let values = a.get_value().zip(b.get_value()).map(|(a, b)| {
+                            let lt = not_dummy.get_value().unwrap()
+                                && a.to_u64_unchecked() < b.to_u64_unchecked();
+                            let lt = if lt { F::ONE } else { F::ZERO };
+                            (a - b + lt * two_64, lt)
+                        });
+                        let diff = AllocatedNum::alloc(cs.namespace(|| "diff"), || {
+                            values.map(|v| v.0).ok_or(SynthesisError::AssignmentMissing)
+                        })?;
+                        let lt = AllocatedNum::alloc(cs.namespace(|| format!("{tgt}")), || {
+                            values.map(|v| v.1).ok_or(SynthesisError::AssignmentMissing)
+                        })?;
+                        let lhs = LinearCombination::zero() + a.get_variable() - b.get_variable();
+                        enforce_wrap_u64(cs, not_dummy, lhs, two_64, &diff, &lt)?;
+                        let tag = g
+                            .global_allocator
+                            .get_or_alloc_const(cs, Tag::Expr(Num).to_field())?;
+                        let c = AllocatedPtr::from_parts(tag, lt);
+                        bound_allocations.insert(tgt.clone(), c);
+                    }
                     Op::Emit(_) => (),
                     Op::Hide(tgt, sec, pay) => {
                         let sec = bound_allocations.get(sec)?;
@@ -763,7 +1605,7 @@ impl Func {
                             &g.preallocated_commitment_slots[next_slot.consume_commitment()];
                         implies_equal(
                             &mut cs.namespace(|| {
-                                format!("implies equal for the secret's tag (OP {:?})", &op)
+                                format!("implies equal for the secret's tag (OP {op_name})")
                             }),
                             not_dummy,
                             sec.tag(),
@@ -771,7 +1613,7 @@ impl Func {
                         )?;
                         implies_equal(
                             &mut cs.namespace(|| {
-                                format!("implies equal for the secret's hash (OP {:?})", &op)
+                                format!("implies equal for the secret's hash (OP {op_name})")
                             }),
                             not_dummy,
                             sec.hash(),
@@ -779,7 +1621,7 @@ impl Func {
                         )?;
                         implies_equal(
                             &mut cs.namespace(|| {
-                                format!("implies equal for the payload's tag (OP {:?})", &op)
+                                format!("implies equal for the payload's tag (OP {op_name})")
                             }),
                             not_dummy,
                             pay.tag(),
@@ -787,7 +1629,7 @@ impl Func {
                         )?;
                         implies_equal(
                             &mut cs.namespace(|| {
-                                format!("implies equal for the payload's hash (OP {:?})", &op)
+                                format!("implies equal for the payload's hash (OP {op_name})")
                             }),
                             not_dummy,
                             pay.hash(),
@@ -808,7 +1650,7 @@ impl Func {
                             .get_or_alloc_const(cs, Tag::Expr(Comm).to_field())?;
                         implies_equal(
                             &mut cs.namespace(|| {
-                                format!("implies equal for comm's tag (OP {:?})", &op)
+                                format!("implies equal for comm's tag (OP {op_name})")
                             }),
                             not_dummy,
                             comm.tag(),
@@ -816,7 +1658,7 @@ impl Func {
                         )?;
                         implies_equal(
                             &mut cs.namespace(|| {
-                                format!("implies equal for comm's hash (OP {:?})", &op)
+                                format!("implies equal for comm's hash (OP {op_name})")
                             }),
                             not_dummy,
                             comm.hash(),
@@ -834,11 +1676,45 @@ impl Func {
                         bound_allocations.insert(sec.clone(), allocated_sec_ptr);
                         bound_allocations.insert(pay.clone(), allocated_pay_ptr);
                     }
+                    Op::Coproc(name, out, inp) => {
+                        let coproc = g.store.coproc(name)?;
+                        // Like for calls, the outputs are allocated from the
+                        // witness, or from dummies on virtual paths, and then
+                        // constrained by the coprocessor's gadget
+                        let concrete_output_vals = if let Some(true) = not_dummy.get_value() {
+                            g.coproc_outputs.pop_front()
+                        } else {
+                            None
+                        };
+                        let output_vals = concrete_output_vals.unwrap_or_else(|| {
+                            let dummy = Ptr::Leaf(Tag::Expr(Nil), F::ZERO);
+                            (0..out.len()).map(|_| dummy).collect()
+                        });
+                        assert_eq!(output_vals.len(), out.len());
+                        let mut output_ptrs = Vec::with_capacity(out.len());
+                        for (ptr, var) in output_vals.iter().zip(out.iter()) {
+                            let zptr = &g.store.hash_ptr(ptr)?;
+                            output_ptrs.push(Func::allocate_ptr(cs, zptr, var, bound_allocations)?);
+                        }
+                        let input_ptrs = bound_allocations.get_many_cloned(inp)?;
+                        coproc
+                            .synthesize(
+                                &mut cs.namespace(|| format!("coproc {name} (OP {op_name})")),
+                                g.store,
+                                not_dummy,
+                                &input_ptrs,
+                                &output_ptrs,
+                            )
+                            .with_context(|| format!("couldn't synthesize coprocessor {name}"))?;
+                    }
                 }
             }
 
             match &block.ctrl {
                 Ctrl::Return(return_vars) => {
+                    if let Some(merged_returns) = g.merged_returns.last_mut() {
+                        return merged_returns.push(not_dummy, return_vars, bound_allocations);
+                    }
                     for (i, return_var) in return_vars.iter().enumerate() {
                         let allocated_ptr = bound_allocations.get(return_var)?;
 
@@ -904,6 +1780,7 @@ impl Func {
                         eq_block,
                         &is_eq,
                         &mut branch_slot,
+                        memo.clone(),
                         bound_allocations,
                         preallocated_outputs,
                         g,
@@ -913,6 +1790,7 @@ impl Func {
                         else_block,
                         &is_neq,
                         next_slot,
+                        memo.clone(),
                         bound_allocations,
                         preallocated_outputs,
                         g,
@@ -950,6 +1828,7 @@ impl Func {
                             block,
                             &has_match,
                             &mut branch_slot,
+                            memo.clone(),
                             bound_allocations,
                             preallocated_outputs,
                             g,
@@ -982,6 +1861,7 @@ impl Func {
                                 def,
                                 &has_match,
                                 next_slot,
+                                memo.clone(),
                                 bound_allocations,
                                 preallocated_outputs,
                                 g,
@@ -1007,134 +1887,259 @@ impl Func {
                     .with_context(|| " couldn't constrain `enforce_selector_with_premise`")
                 }
                 Ctrl::MatchVal(match_var, cases, def) => {
-                    let match_lit = bound_allocations.get(match_var)?.hash().clone();
-                    let mut selector = Vec::with_capacity(cases.len() + 2);
-                    let mut branch_slots = Vec::with_capacity(cases.len());
-                    for (i, (lit, block)) in cases.iter().enumerate() {
-                        let lit_ptr = lit.to_ptr(g.store);
-                        let lit_hash = g.store.hash_ptr(&lit_ptr)?.hash;
-                        let is_eq = not_dummy.get_value().and_then(|not_dummy| {
-                            match_lit
-                                .get_value()
-                                .map(|val| not_dummy && val == lit_hash)
-                        });
+                    // The literals are hashed once, for both the cases and the default
+                    let lit_hashes = cases
+                        .keys()
+                        .map(|lit| lit.to_z_ptr(g.store).hash)
+                        .collect::<Vec<_>>();
+                    match_hashes(
+                        cs,
+                        match_var,
+                        &lit_hashes,
+                        cases.values(),
+                        def.as_deref(),
+                        not_dummy,
+                        next_slot,
+                        memo,
+                        bound_allocations,
+                        preallocated_outputs,
+                        g,
+                    )
+                }
+                Ctrl::MatchSymbol(match_var, cases, def) => {
+                    let match_tag = bound_allocations.get(match_var)?.tag().clone();
+                    implies_equal_const(
+                        &mut cs.namespace(|| format!("implies {match_var} is a symbol")),
+                        not_dummy,
+                        &match_tag,
+                        Tag::Expr(Sym).to_field(),
+                    )?;
+                    let sym_hashes = cases
+                        .keys()
+                        .map(|sym| g.store.hash_symbol(sym).hash)
+                        .collect::<Vec<_>>();
+                    match_hashes(
+                        cs,
+                        match_var,
+                        &sym_hashes,
+                        cases.values(),
+                        def.as_deref(),
+                        not_dummy,
+                        next_slot,
+                        memo,
+                        bound_allocations,
+                        preallocated_outputs,
+                        g,
+                    )
+                }
+            }
+        }
 
-                        let has_match = Boolean::Is(AllocatedBit::alloc(
-                            &mut cs.namespace(|| format!("{i}.allocated_bit")),
-                            is_eq,
-                        )?);
-                        implies_equal_const(
-                            &mut cs.namespace(|| format!("implies equal for {match_var} ({i})")),
+        /// Selects the block whose hash in `lit_hashes` is the value of
+        /// `match_var`, or `def` if there's none
+        #[allow(clippy::too_many_arguments)]
+        fn match_hashes<'a, F: LurkField, CS: ConstraintSystem<F>>(
+            cs: &mut CS,
+            match_var: &Var,
+            lit_hashes: &[F],
+            blocks: impl IntoIterator<Item = &'a Block>,
+            def: Option<&Block>,
+            not_dummy: &Boolean,
+            next_slot: &mut SlotsCounter,
+            memo: SlotMemo,
+            bound_allocations: &mut BoundAllocations<F>,
+            preallocated_outputs: &Vec<AllocatedPtr<F>>,
+            g: &mut Globals<'_, F>,
+        ) -> Result<()> {
+            let match_lit = bound_allocations.get(match_var)?.hash().clone();
+            let mut selector = Vec::with_capacity(lit_hashes.len() + 2);
+            let mut branch_slots = Vec::with_capacity(lit_hashes.len());
+            for (i, (block, &lit_hash)) in blocks.into_iter().zip(lit_hashes).enumerate() {
+                let is_eq = not_dummy.get_value().and_then(|not_dummy| {
+                    match_lit
+                        .get_value()
+                        .map(|val| not_dummy && val == lit_hash)
+                });
+
+                let has_match = Boolean::Is(AllocatedBit::alloc(
+                    &mut cs.namespace(|| format!("{i}.allocated_bit")),
+                    is_eq,
+                )?);
+                implies_equal_const(
+                    &mut cs.namespace(|| format!("implies equal for {match_var} ({i})")),
+                    &has_match,
+                    &match_lit,
+                    lit_hash,
+                )?;
+
+                selector.push(has_match.clone());
+
+                let mut branch_slot = *next_slot;
+                recurse(
+                    &mut cs.namespace(|| format!("{i}.case")),
+                    block,
+                    &has_match,
+                    &mut branch_slot,
+                    memo.clone(),
+                    bound_allocations,
+                    preallocated_outputs,
+                    g,
+                )?;
+                branch_slots.push(branch_slot);
+            }
+
+            match def {
+                Some(def) => {
+                    let default = selector.iter().fold(not_dummy.get_value(), |acc, b| {
+                        acc.and_then(|acc| b.get_value().map(|b| acc && !b))
+                    });
+                    let has_match = Boolean::Is(AllocatedBit::alloc(
+                        &mut cs.namespace(|| "_.allocated_bit"),
+                        default,
+                    )?);
+                    for (i, &lit_hash) in lit_hashes.iter().enumerate() {
+                        implies_unequal_const(
+                            &mut cs.namespace(|| format!("{i} implies_unequal")),
                             &has_match,
                             &match_lit,
                             lit_hash,
                         )?;
-
-                        selector.push(has_match.clone());
-
-                        let mut branch_slot = *next_slot;
-                        recurse(
-                            &mut cs.namespace(|| format!("{i}.case")),
-                            block,
-                            &has_match,
-                            &mut branch_slot,
-                            bound_allocations,
-                            preallocated_outputs,
-                            g,
-                        )?;
-                        branch_slots.push(branch_slot);
-                    }
-
-                    match def {
-                        Some(def) => {
-                            let default = selector.iter().fold(not_dummy.get_value(), |acc, b| {
-                                acc.and_then(|acc| b.get_value().map(|b| acc && !b))
-                            });
-                            let has_match = Boolean::Is(AllocatedBit::alloc(
-                                &mut cs.namespace(|| "_.allocated_bit"),
-                                default,
-                            )?);
-                            for (i, (lit, _)) in cases.iter().enumerate() {
-                                let lit_ptr = lit.to_ptr(g.store);
-                                let lit_hash = g.store.hash_ptr(&lit_ptr)?.hash;
-                                implies_unequal_const(
-                                    &mut cs.namespace(|| format!("{i} implies_unequal")),
-                                    &has_match,
-                                    &match_lit,
-                                    lit_hash,
-                                )?;
-                            }
-
-                            selector.push(has_match.clone());
-
-                            recurse(
-                                &mut cs.namespace(|| "_"),
-                                def,
-                                &has_match,
-                                next_slot,
-                                bound_allocations,
-                                preallocated_outputs,
-                                g,
-                            )?;
-                        }
-                        None => (),
                     }
 
-                    // The number of slots the match used is the max number of slots of each branch
-                    *next_slot = branch_slots
-                        .into_iter()
-                        .fold(*next_slot, |acc, branch_slot| acc.max(branch_slot));
+                    selector.push(has_match.clone());
 
-                    // Now we need to enforce that at exactly one path was taken. We do that by enforcing
-                    // that the sum of the previously collected `Boolean`s is one. But, of course, this
-                    // irrelevant if we're on a virtual path and thus we use an implication gadget.
-                    selector.push(not_dummy.not());
-                    enforce_selector_with_premise(
-                        &mut cs.namespace(|| "enforce_selector_with_premise"),
-                        not_dummy,
-                        &selector,
-                    )
-                    .with_context(|| " couldn't constrain `enforce_selector_with_premise`")
+                    recurse(
+                        &mut cs.namespace(|| "_"),
+                        def,
+                        &has_match,
+                        next_slot,
+                        memo.clone(),
+                        bound_allocations,
+                        preallocated_outputs,
+                        g,
+                    )?;
                 }
+                None => (),
             }
+
+            // The number of slots the match used is the max number of slots of each branch
+            *next_slot = branch_slots
+                .into_iter()
+                .fold(*next_slot, |acc, branch_slot| acc.max(branch_slot));
+
+            // Now we need to enforce that at exactly one path was taken. We do that by enforcing
+            // that the sum of the previously collected `Boolean`s is one. But, of course, this
+            // irrelevant if we're on a virtual path and thus we use an implication gadget.
+            selector.push(not_dummy.not());
+            enforce_selector_with_premise(
+                &mut cs.namespace(|| "enforce_selector_with_premise"),
+                not_dummy,
+                &selector,
+            )
+            .with_context(|| " couldn't constrain `enforce_selector_with_premise`")
         }
 
         let call_outputs = frame.preimages.call_outputs.clone();
+        let coproc_outputs = frame.preimages.coproc_outputs.clone();
+        let mut merged_returns = vec![];
+        if self.merge_returns {
+            merged_returns.push(MergedReturns::new(&self.body, store)?);
+        }
+        let mut globals = Globals {
+            store,
+            global_allocator,
+            preallocated_hash_slots,
+            preallocated_commitment_slots,
+            preallocated_less_than_slots,
+            preallocated_bit_decomp_slots,
+            preallocated_range_slots,
+            call_outputs,
+            call_count: 0,
+            op_count: 0,
+            coproc_outputs,
+            merge_returns: self.merge_returns,
+            merged_returns,
+        };
         recurse(
             cs,
             &self.body,
             &Boolean::Constant(true),
             &mut SlotsCounter::default(),
+            SlotMemo::new(self.share_slots),
             &mut bound_allocations,
             &preallocated_outputs,
-            &mut Globals {
-                store,
-                global_allocator: &mut global_allocator,
-                preallocated_hash2_slots,
-                preallocated_hash3_slots,
-                preallocated_hash4_slots,
-                preallocated_commitment_slots,
-                preallocated_less_than_slots,
-                call_outputs,
-                call_count: 0,
-            },
-        )
+            &mut globals,
+        )?;
+        if let Some(merged_returns) = globals.merged_returns.pop() {
+            merged_returns.enforce(&mut cs.namespace(|| "returns"), &preallocated_outputs)?;
+        }
+        Ok(())
+    }
+
+    /// Creates the same constraints as `synthesize`, but without a frame to
+    /// take the witness from. This is meant for when only the shape of the
+    /// circuit matters, such as when generating public parameters, and saves
+    /// interpreting the function on some made up input to get a frame.
+    pub fn synthesize_blank<F: LurkField, CS: ConstraintSystem<F>>(
+        &self,
+        cs: &mut CS,
+        store: &Store<F>,
+    ) -> Result<()> {
+        self.synthesize(cs, store, &Frame::blank(self))
     }
 
     /// Computes the number of constraints that `synthesize` should create. It's
     /// also an explicit way to document and attest how the number of constraints
     /// grow.
-    pub fn num_constraints<F: LurkField>(&self, store: &mut Store<F>) -> usize {
+    pub fn num_constraints<F: LurkField>(&self, store: &Store<F>) -> usize {
+        let (per_frame, constants) = self.num_constraints_split(store);
+        per_frame + constants
+    }
+
+    /// Computes the number of constraints that `synthesize_frames` should
+    /// create for `num_frames` frames, starting from an empty allocator. The
+    /// constants are only paid for once.
+    pub fn num_constraints_frames<F: LurkField>(
+        &self,
+        store: &Store<F>,
+        num_frames: usize,
+    ) -> usize {
+        let (per_frame, constants) = self.num_constraints_split(store);
+        per_frame * num_frames + constants
+    }
+
+    /// The number of constraints of a frame, apart from those allocating
+    /// constants, and the number of constants
+    fn num_constraints_split<F: LurkField>(&self, store: &Store<F>) -> (usize, usize) {
+        /// The keys of the function's variables and of the values of the returns
+        /// found so far, when returns are merged
+        type Returns<F> = Option<(ReturnKeys<F>, Vec<Vec<[ReturnKey<F>; 2]>>)>;
+
         fn recurse<F: LurkField>(
             block: &Block,
             globals: &mut HashSet<FWrap<F>>,
-            store: &mut Store<F>,
+            store: &Store<F>,
+            mut memo: SlotMemo,
+            returns: &mut Returns<F>,
         ) -> usize {
             let mut num_constraints = 0;
             for op in &block.ops {
                 match op {
                     Op::Call(_, func, _) => {
-                        num_constraints += recurse(&func.body, globals, store);
+                        if returns.is_some() {
+                            let mut keys = HashMap::default();
+                            collect_return_keys(&func.body, store, &mut keys).unwrap();
+                            let mut call_returns = Some((keys, vec![]));
+                            let memo = SlotMemo::new(func.share_slots);
+                            num_constraints +=
+                                recurse(&func.body, globals, store, memo, &mut call_returns);
+                            let (_, call_returns) = call_returns.unwrap();
+                            num_constraints +=
+                                merged_returns_constraints(&call_returns, func.output_size);
+                        } else {
+                            let memo = SlotMemo::new(func.share_slots);
+                            num_constraints += recurse(&func.body, globals, store, memo, &mut None);
+                        }
                     }
                     Op::Null(_, tag) => {
                         // constrain tag and hash
@@ -1142,10 +2147,8 @@ impl Func {
                         globals.insert(FWrap(F::ZERO));
                     }
                     Op::Lit(_, lit) => {
-                        let lit_ptr = lit.to_ptr(store);
-                        let lit_hash = store.hash_ptr(&lit_ptr).unwrap().hash;
                         globals.insert(FWrap(Tag::Expr(Sym).to_field()));
-                        globals.insert(FWrap(lit_hash));
+                        globals.insert(FWrap(lit.to_z_ptr(store).hash));
                     }
                     Op::Cast(_tgt, tag, _src) => {
                         globals.insert(FWrap(tag.to_field()));
@@ -1168,36 +2171,60 @@ impl Func {
                     }
                     Op::Trunc(_, _, _) => {
                         globals.insert(FWrap(Tag::Expr(Num).to_field()));
-                        // bit decomposition + enforce_pack
-                        num_constraints += 389;
+                        // implies_equal + enforce_pack
+                        num_constraints += 2;
+                    }
+                    Op::AssertRange(_, _) => {
+                        // two implies_equal and enforce_pack
+                        num_constraints += 3;
+                    }
+                    Op::AssertU32(_) => {
+                        // one constraint per bit and the packing
+                        num_constraints += 32 + 1;
+                    }
+                    Op::AssertChar(_) => {
+                        // one constraint per bit and the packing
+                        num_constraints += 21 + 1;
+                    }
+                    Op::And(_, _, _) | Op::Or(_, _, _) | Op::Xor(_, _, _) => {
+                        globals.insert(FWrap(Tag::Expr(Num).to_field()));
+                        // two implies_equal, one gate per bit and enforce_pack
+                        num_constraints += 2 + 64 + 1;
                     }
                     Op::DivRem64(_, _, _) => {
                         globals.insert(FWrap(Tag::Expr(Num).to_field()));
                         // three implies_u64, one sub and one linear
                         num_constraints += 197;
                     }
-                    Op::Emit(_) => (),
-                    Op::Hash2(_, tag, _) => {
-                        // tag for the image
-                        globals.insert(FWrap(tag.to_field()));
-                        // tag and hash for 2 preimage pointers
-                        num_constraints += 4;
+                    Op::AddI64(_, _, _) | Op::SubI64(_, _, _) => {
+                        globals.insert(FWrap(Tag::Expr(Num).to_field()));
+                        // two implies_u64 and a wrap
+                        num_constraints += 2 * 65 + 67;
                     }
-                    Op::Hash3(_, tag, _) => {
-                        // tag for the image
-                        globals.insert(FWrap(tag.to_field()));
-                        // tag and hash for 3 preimage pointers
-                        num_constraints += 6;
+                    Op::MulI64(_, _, _) => {
+                        globals.insert(FWrap(Tag::Expr(Num).to_field()));
+                        // four implies_u64 and the product
+                        num_constraints += 4 * 65 + 1;
                     }
-                    Op::Hash4(_, tag, _) => {
+                    Op::LtI64(_, _, _) => {
+                        globals.insert(FWrap(Tag::Expr(Num).to_field()));
+                        // two implies_u64, two wraps flipping the sign bits and one comparing
+                        num_constraints += 2 * 65 + 3 * 67;
+                    }
+                    Op::Emit(_) => (),
+                    Op::Hash(_, tag, preimg) => {
                         // tag for the image
                         globals.insert(FWrap(tag.to_field()));
-                        // tag and hash for 4 preimage pointers
-                        num_constraints += 8;
+                        // tag and hash for each preimage pointer, unless the slot is shared
+                        if !memo.hash_slot(op, || 0).1 {
+                            num_constraints += 2 * preimg.len();
+                        }
                     }
-                    Op::Unhash2(..) | Op::Unhash3(..) | Op::Unhash4(..) => {
-                        // one constraint for the image's hash
-                        num_constraints += 1;
+                    Op::Unhash(..) => {
+                        // one constraint for the image's hash, unless the slot is shared
+                        if !memo.hash_slot(op, || 0).1 {
+                            num_constraints += 1;
+                        }
                     }
                     Op::Hide(..) => {
                         num_constraints += 4;
@@ -1209,15 +2236,25 @@ impl Func {
                         globals.insert(FWrap(Tag::Expr(Num).to_field()));
                         globals.insert(FWrap(Tag::Expr(Comm).to_field()));
                     }
+                    Op::Coproc(name, ..) => {
+                        let coproc = store.coproc(name).expect("coprocessor not registered");
+                        num_constraints += coproc.num_constraints();
+                    }
                 }
             }
             match &block.ctrl {
-                Ctrl::Return(vars) => num_constraints + 2 * vars.len(),
+                Ctrl::Return(vars) => match returns {
+                    Some((keys, returned)) => {
+                        returned.push(vars.iter().map(|var| return_key(keys, var)).collect());
+                        num_constraints
+                    }
+                    None => num_constraints + 2 * vars.len(),
+                },
                 Ctrl::IfEq(_, _, eq_block, else_block) => {
                     num_constraints
                         + 5
-                        + recurse(eq_block, globals, store)
-                        + recurse(else_block, globals, store)
+                        + recurse(eq_block, globals, store, memo.clone(), returns)
+                        + recurse(else_block, globals, store, memo, returns)
                 }
                 Ctrl::MatchTag(_, cases, def) => {
                     // We allocate one boolean per case and constrain it once
@@ -1226,13 +2263,13 @@ impl Func {
                     num_constraints += 2 * cases.len() + 1;
 
                     for block in cases.values() {
-                        num_constraints += recurse(block, globals, store);
+                        num_constraints += recurse(block, globals, store, memo.clone(), returns);
                     }
                     match def {
                         Some(def) => {
                             // constraints for the boolean, the unequalities and the default case
                             num_constraints += 1 + cases.len();
-                            num_constraints += recurse(def, globals, store);
+                            num_constraints += recurse(def, globals, store, memo.clone(), returns);
                         }
                         None => (),
                     };
@@ -1241,12 +2278,27 @@ impl Func {
                 Ctrl::MatchVal(_, cases, def) => {
                     num_constraints += 2 * cases.len() + 1;
                     for block in cases.values() {
-                        num_constraints += recurse(block, globals, store);
+                        num_constraints += recurse(block, globals, store, memo.clone(), returns);
+                    }
+                    match def {
+                        Some(def) => {
+                            num_constraints += 1 + cases.len();
+                            num_constraints += recurse(def, globals, store, memo.clone(), returns);
+                        }
+                        None => (),
+                    };
+                    num_constraints
+                }
+                Ctrl::MatchSymbol(_, cases, def) => {
+                    // one more constraint for the symbol tag
+                    num_constraints += 2 * cases.len() + 2;
+                    for block in cases.values() {
+                        num_constraints += recurse(block, globals, store, memo.clone(), returns);
                     }
                     match def {
                         Some(def) => {
                             num_constraints += 1 + cases.len();
-                            num_constraints += recurse(def, globals, store);
+                            num_constraints += recurse(def, globals, store, memo.clone(), returns);
                         }
                         None => (),
                     };
@@ -1255,13 +2307,167 @@ impl Func {
             }
         }
         let globals = &mut HashSet::default();
+        let memo = SlotMemo::new(self.share_slots);
         // fixed cost for each slot
-        let slot_constraints = 289 * self.slot.hash2
-            + 337 * self.slot.hash3
-            + 388 * self.slot.hash4
+        let hash_slot_constraints = HASH_ARITIES
+            .into_iter()
+            .map(|arity| poseidon_constraints(arity) * self.slot.hash(arity))
+            .sum::<usize>();
+        let slot_constraints = hash_slot_constraints
             + 265 * self.slot.commitment
-            + 391 * self.slot.less_than;
-        let num_constraints = recurse::<F>(&self.body, globals, store);
-        slot_constraints + num_constraints + globals.len()
+            + 391 * self.slot.less_than
+            + 388 * self.slot.bit_decomp
+            + 64 * self.slot.range;
+        if self.merge_returns {
+            let mut keys = HashMap::default();
+            collect_return_keys(&self.body, store, &mut keys).unwrap();
+            let mut returns = Some((keys, vec![]));
+            let num_constraints = recurse::<F>(&self.body, globals, store, memo, &mut returns);
+            let (_, returns) = returns.unwrap();
+            let returns_constraints = merged_returns_constraints(&returns, self.output_size);
+            (
+                slot_constraints + num_constraints + returns_constraints,
+                globals.len(),
+            )
+        } else {
+            let num_constraints = recurse::<F>(&self.body, globals, store, memo, &mut None);
+            (slot_constraints + num_constraints, globals.len())
+        }
+    }
+
+    /// Counts the constraints that `synthesize` actually creates, by
+    /// synthesizing the blank circuit. Unlike `num_constraints`, it can't drift
+    /// from the synthesis code, but it's much slower, so it's meant for tests.
+    pub fn measured_constraints<F: LurkField>(&self, store: &Store<F>) -> Result<usize> {
+        let mut cs = ConstraintCounter::default();
+        self.synthesize_blank(&mut cs, store)?;
+        Ok(cs.constraints)
+    }
+
+    /// Panics if `num_constraints` disagrees with the number of constraints
+    /// `synthesize` creates. Any `Func` whose constraints are counted with
+    /// `num_constraints` should have a test calling this.
+    pub fn assert_num_constraints<F: LurkField>(&self, store: &Store<F>) {
+        let computed = self.num_constraints(store);
+        let measured = self
+            .measured_constraints(store)
+            .expect("blank synthesis failed");
+        assert_eq!(
+            computed, measured,
+            "`num_constraints` of `{}` is off: {computed} computed, {measured} synthesized",
+            self.name
+        );
+    }
+}
+
+/// Generates the witnesses of the frames of a `Func`, whose circuit has been
+/// laid out by `Func::synthesize_blank`
+pub struct WitnessGenerator<'a> {
+    func: &'a Func,
+    num_inputs: usize,
+    num_aux: usize,
+}
+
+impl WitnessGenerator<'_> {
+    /// The number of auxiliary variables of every witness
+    pub fn num_aux(&self) -> usize {
+        self.num_aux
+    }
+
+    /// Computes the assignment `synthesize` gives to the variables of the
+    /// circuit for `frame`. It runs on a `WitnessCS`, which only records the
+    /// values and skips the constraints, and the witness is checked to fit the
+    /// layout of the blank circuit.
+    pub fn generate<F: LurkField>(
+        &self,
+        store: &Store<F>,
+        frame: &Frame<F>,
+    ) -> Result<WitnessCS<F>> {
+        self.generate_with(store, frame, &SlotWitnesses::default())
+    }
+
+    /// Like `generate`, but reuses the witnesses of the slots in
+    /// `slot_witnesses`
+    pub(crate) fn generate_with<F: LurkField>(
+        &self,
+        store: &Store<F>,
+        frame: &Frame<F>,
+        slot_witnesses: &SlotWitnesses<F>,
+    ) -> Result<WitnessCS<F>> {
+        let mut wcs = WitnessCS::new();
+        let global_allocator = &mut GlobalAllocator::default();
+        self.func.synthesize_with_caches(
+            &mut wcs,
+            store,
+            frame,
+            global_allocator,
+            slot_witnesses,
+        )?;
+        // the first input is the constant one
+        if wcs.aux_slice().len() != self.num_aux || wcs.inputs_slice().len() != self.num_inputs + 1
+        {
+            bail!(
+                "The witness of the frame doesn't fit the circuit of `{}`",
+                self.func.name
+            )
+        }
+        Ok(wcs)
+    }
+}
+
+/// A constraint system that only counts constraints
+#[derive(Default)]
+struct ConstraintCounter {
+    inputs: usize,
+    aux: usize,
+    constraints: usize,
+}
+
+impl<F: LurkField> ConstraintSystem<F> for ConstraintCounter {
+    type Root = Self;
+
+    fn alloc<V, A, AR>(&mut self, _annotation: A, _f: V) -> Result<Variable, SynthesisError>
+    where
+        V: FnOnce() -> Result<F, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.aux += 1;
+        Ok(Variable::new_unchecked(Index::Aux(self.aux - 1)))
+    }
+
+    fn alloc_input<V, A, AR>(&mut self, _annotation: A, _f: V) -> Result<Variable, SynthesisError>
+    where
+        V: FnOnce() -> Result<F, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        // the first input is the constant one
+        self.inputs += 1;
+        Ok(Variable::new_unchecked(Index::Input(self.inputs)))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, _annotation: A, _a: LA, _b: LB, _c: LC)
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+        LA: FnOnce(LinearCombination<F>) -> LinearCombination<F>,
+        LB: FnOnce(LinearCombination<F>) -> LinearCombination<F>,
+        LC: FnOnce(LinearCombination<F>) -> LinearCombination<F>,
+    {
+        self.constraints += 1;
+    }
+
+    fn push_namespace<NR, N>(&mut self, _name_fn: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn pop_namespace(&mut self) {}
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
     }
 }
@@ -0,0 +1,257 @@
+//! Coprocessors for LEM.
+//!
+//! `Op::Coproc(name, outputs, inputs)` applies the coprocessor registered in
+//! the `Store` under `name`, with `Store::register_coproc`. Interpretation
+//! computes the outputs with `Coproc::evaluate` and keeps them in the frame,
+//! and synthesis allocates them before handing them to `Coproc::synthesize`,
+//! which constrains them. This lets users extend a step function with
+//! operations LEM doesn't have, without changing the crate.
+
+use bellpepper_core::{
+    boolean::Boolean, num::AllocatedNum, ConstraintSystem, LinearCombination, SynthesisError,
+    Variable,
+};
+use std::fmt::Debug;
+
+use crate::{
+    circuit::gadgets::{
+        data::{allocate_constant, hash_poseidon},
+        pointer::AllocatedPtr,
+    },
+    field::LurkField,
+};
+
+use super::{pointers::Ptr, store::Store};
+
+/// The constraint system coprocessors write to. It's implemented by every
+/// `ConstraintSystem`, but can be used as a trait object.
+pub trait CoprocCS<F: LurkField> {
+    /// Allocates an auxiliary variable, whose value is unknown when only the
+    /// shape of the circuit is synthesized
+    fn alloc_aux(
+        &mut self,
+        annotation: String,
+        value: Option<F>,
+    ) -> Result<Variable, SynthesisError>;
+
+    /// Enforces `a * b = c`
+    fn enforce_lc(
+        &mut self,
+        annotation: String,
+        a: LinearCombination<F>,
+        b: LinearCombination<F>,
+        c: LinearCombination<F>,
+    );
+
+    /// The variable that's always one
+    fn one_var(&self) -> Variable;
+
+    /// Allocates the Poseidon hash of `preimage` followed by a zero, with the
+    /// constants of the `Store`, in `POSEIDON_PAD_CONSTRAINTS` constraints
+    fn poseidon_hash_pad(
+        &mut self,
+        annotation: String,
+        preimage: [AllocatedNum<F>; 2],
+        store: &Store<F>,
+    ) -> Result<AllocatedNum<F>, SynthesisError>;
+}
+
+/// The number of constraints of `CoprocCS::poseidon_hash_pad`: those of the
+/// hash and of the zero
+pub const POSEIDON_PAD_CONSTRAINTS: usize = 265 + 1;
+
+impl<F: LurkField, CS: ConstraintSystem<F>> CoprocCS<F> for CS {
+    fn alloc_aux(
+        &mut self,
+        annotation: String,
+        value: Option<F>,
+    ) -> Result<Variable, SynthesisError> {
+        self.alloc(
+            || annotation,
+            || value.ok_or(SynthesisError::AssignmentMissing),
+        )
+    }
+
+    fn enforce_lc(
+        &mut self,
+        annotation: String,
+        a: LinearCombination<F>,
+        b: LinearCombination<F>,
+        c: LinearCombination<F>,
+    ) {
+        self.enforce(|| annotation, |_| a, |_| b, |_| c)
+    }
+
+    fn one_var(&self) -> Variable {
+        CS::one()
+    }
+
+    fn poseidon_hash_pad(
+        &mut self,
+        annotation: String,
+        preimage: [AllocatedNum<F>; 2],
+        store: &Store<F>,
+    ) -> Result<AllocatedNum<F>, SynthesisError> {
+        let mut cs = self.namespace(|| annotation);
+        let zero = allocate_constant(&mut cs.namespace(|| "zero"), F::ZERO)?;
+        let [a, b] = preimage;
+        hash_poseidon(cs, vec![a, b, zero], store.poseidon_cache.constants.c3())
+    }
+}
+
+/// An operation implemented outside of LEM: a function computing its outputs
+/// together with a gadget constraining them
+pub trait Coproc<F: LurkField>: Debug + Send + Sync {
+    /// The number of inputs
+    fn arity(&self) -> usize;
+
+    /// The number of outputs
+    fn output_size(&self) -> usize;
+
+    /// Computes the outputs for `args`, which are as many as `arity`
+    fn evaluate(&self, store: &mut Store<F>, args: &[Ptr<F>]) -> anyhow::Result<Vec<Ptr<F>>>;
+
+    /// Constrains `outputs` to be the result of applying the coprocessor to
+    /// `inputs` when `not_dummy` is true. Otherwise, the inputs and outputs
+    /// are arbitrary and the constraints must be satisfied all the same, so
+    /// they should be conditioned on `not_dummy`.
+    fn synthesize(
+        &self,
+        cs: &mut dyn CoprocCS<F>,
+        store: &Store<F>,
+        not_dummy: &Boolean,
+        inputs: &[AllocatedPtr<F>],
+        outputs: &[AllocatedPtr<F>],
+    ) -> Result<(), SynthesisError>;
+
+    /// The number of constraints `synthesize` creates
+    fn num_constraints(&self) -> usize;
+}
+
+#[cfg(test)]
+mod tests {
+    use bellpepper_core::test_cs::TestConstraintSystem;
+    use blstrs::Scalar as Fr;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::func;
+    use crate::lem::{interpreter::Preimages, Tag};
+    use crate::tag::ExprTag::{Nil, Num};
+
+    /// Squares a number
+    #[derive(Debug)]
+    struct Square;
+
+    impl<F: LurkField> Coproc<F> for Square {
+        fn arity(&self) -> usize {
+            1
+        }
+
+        fn output_size(&self) -> usize {
+            1
+        }
+
+        fn evaluate(&self, _store: &mut Store<F>, args: &[Ptr<F>]) -> anyhow::Result<Vec<Ptr<F>>> {
+            let Ptr::Leaf(Tag::Expr(Num), x) = args[0] else {
+                anyhow::bail!("Can only square numbers")
+            };
+            Ok(vec![Ptr::num(x * x)])
+        }
+
+        fn synthesize(
+            &self,
+            cs: &mut dyn CoprocCS<F>,
+            _store: &Store<F>,
+            not_dummy: &Boolean,
+            inputs: &[AllocatedPtr<F>],
+            outputs: &[AllocatedPtr<F>],
+        ) -> Result<(), SynthesisError> {
+            // not_dummy * x = x', x' * x = y
+            let x = inputs[0].hash();
+            let y = outputs[0].hash();
+            let guarded_x = cs.alloc_aux(
+                "guarded x".into(),
+                not_dummy
+                    .get_value()
+                    .zip(x.get_value())
+                    .map(|(b, x)| if b { x } else { F::ZERO }),
+            )?;
+            let guarded_y = cs.alloc_aux(
+                "guarded y".into(),
+                not_dummy
+                    .get_value()
+                    .zip(y.get_value())
+                    .map(|(b, y)| if b { y } else { F::ZERO }),
+            )?;
+            let one = cs.one_var();
+            cs.enforce_lc(
+                "guard x".into(),
+                not_dummy.lc(one, F::ONE),
+                LinearCombination::zero() + x.get_variable(),
+                LinearCombination::zero() + guarded_x,
+            );
+            cs.enforce_lc(
+                "guard y".into(),
+                not_dummy.lc(one, F::ONE),
+                LinearCombination::zero() + y.get_variable(),
+                LinearCombination::zero() + guarded_y,
+            );
+            cs.enforce_lc(
+                "square".into(),
+                LinearCombination::zero() + guarded_x,
+                LinearCombination::zero() + x.get_variable(),
+                LinearCombination::zero() + guarded_y,
+            );
+            Ok(())
+        }
+
+        fn num_constraints(&self) -> usize {
+            3
+        }
+    }
+
+    #[test]
+    fn coprocessors_are_interpreted_and_synthesized() {
+        // the coprocessor is only applied to numbers, so the other inputs take
+        // the virtual path
+        let lem = func!(f(x): 1 => {
+            match x.tag {
+                Expr::Num => {
+                    let (y) = coproc square(x);
+                    return (y);
+                }
+            };
+            return (x);
+        });
+        let store = &mut Store::<Fr>::default();
+        store.register_coproc("square", Arc::new(Square));
+
+        for (input, output) in [
+            (Ptr::num(Fr::from(3u64)), Ptr::num(Fr::from(9u64))),
+            (Ptr::null(Tag::Expr(Nil)), Ptr::null(Tag::Expr(Nil))),
+        ] {
+            let (frame, _) = lem
+                .call(vec![input], store, Preimages::new_from_func(&lem))
+                .unwrap();
+            assert_eq!(frame.output, vec![output]);
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            lem.synthesize(&mut cs, store, &frame).unwrap();
+            assert!(cs.is_satisfied());
+            assert_eq!(cs.num_constraints(), lem.num_constraints(store));
+        }
+
+        // coprocessors must be registered
+        let lem = func!(f(x): 1 => {
+            let (y) = coproc cube(x);
+            return (y);
+        });
+        assert!(lem
+            .call(
+                vec![Ptr::num(Fr::from(3u64))],
+                store,
+                Preimages::new_from_func(&lem)
+            )
+            .is_err());
+    }
+}
@@ -0,0 +1,388 @@
+//! Cross-checks of LEM against the legacy evaluator.
+//!
+//! While proofs are still made from the frames of the legacy evaluator, LEM's
+//! step function is meant to compute the same results. `cross_check` runs it
+//! on the input of a legacy evaluation and compares the outcomes: whether the
+//! evaluations ended in the terminal or the error continuation or hit the
+//! limit, and the results of those that ended in the terminal one, by hash,
+//! since both stores hash expressions alike. Provers can run it before
+//! proving to abort on any divergence between the two.
+//!
+//! `compare_backends` runs both evaluators on the same expression and reports
+//! how each one ran and how long it took, side by side, whether they agree or
+//! not, to check their behavior while the evaluator is being replaced.
+//!
+//! The input is carried over to a fresh LEM store expression by expression.
+//! Thunks hold continuations, which aren't carried over, so inputs with thunks
+//! can't be checked.
+
+use anyhow::{anyhow, bail, Result};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::coprocessor::Coprocessor;
+use crate::eval::{lang::Lang, Evaluator, IO};
+use crate::expr::Expression;
+use crate::field::{FWrap, LurkField};
+use crate::ptr::Ptr as LegacyPtr;
+use crate::state::State;
+use crate::store::Store as LegacyStore;
+use crate::tag::{
+    ContTag,
+    ExprTag::{Char, Cons, Fun, U64},
+};
+use crate::uint::UInt;
+use crate::writer::Write;
+
+use super::{eval::eval_step, interpreter::Preimages, pointers::Ptr, store::Store, Tag};
+
+/// How an evaluation ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Terminal,
+    Error,
+    Limit,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Terminal => write!(f, "terminal"),
+            Self::Error => write!(f, "error"),
+            Self::Limit => write!(f, "limit reached"),
+        }
+    }
+}
+
+/// The outcomes of the two evaluators, when they differ, with the results
+/// they evaluated to and the number of iterations they took
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub input: String,
+    pub legacy_outcome: Outcome,
+    pub legacy_result: String,
+    pub legacy_iterations: usize,
+    pub lem_outcome: Outcome,
+    pub lem_result: String,
+    pub lem_iterations: usize,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "LEM and the legacy evaluator diverge on {}", self.input)?;
+        writeln!(
+            f,
+            "  legacy: {} after {} iterations, with {}",
+            self.legacy_outcome, self.legacy_iterations, self.legacy_result
+        )?;
+        write!(
+            f,
+            "  LEM:    {} after {} iterations, with {}",
+            self.lem_outcome, self.lem_iterations, self.lem_result
+        )
+    }
+}
+
+/// How an evaluator ran an expression
+#[derive(Clone, Debug)]
+pub struct Run {
+    pub outcome: Outcome,
+    pub result: String,
+    pub iterations: usize,
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for Run {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} after {} iterations in {:?}, with {}",
+            self.outcome, self.iterations, self.elapsed, self.result
+        )
+    }
+}
+
+/// The runs of the two evaluators on the same expression
+#[derive(Clone, Debug)]
+pub struct Comparison {
+    pub input: String,
+    pub legacy: Run,
+    pub lem: Run,
+    /// Whether both evaluations ended alike, with the same result if they
+    /// ended in the terminal continuation
+    pub agree: bool,
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = if self.agree { "agree" } else { "diverge" };
+        writeln!(f, "LEM and the legacy evaluator {verb} on {}", self.input)?;
+        writeln!(f, "  legacy: {}", self.legacy)?;
+        write!(f, "  LEM:    {}", self.lem)
+    }
+}
+
+/// Interns the expression `ptr` of `legacy` in `store`
+fn carry_over<F: LurkField>(
+    ptr: &LegacyPtr<F>,
+    legacy: &LegacyStore<F>,
+    store: &mut Store<F>,
+) -> Result<Ptr<F>> {
+    let missing = || anyhow!("Expression {ptr:?} isn't in the legacy store");
+    let carried = match legacy.fetch(ptr).ok_or_else(missing)? {
+        Expression::Nil
+        | Expression::RootSym
+        | Expression::RootKey
+        | Expression::Sym(..)
+        | Expression::Key(..) => {
+            let sym = legacy.fetch_symbol(ptr).ok_or_else(missing)?;
+            store.intern_symbol(&sym)
+        }
+        Expression::EmptyStr | Expression::Str(..) => {
+            let string = legacy.fetch_string(ptr).ok_or_else(missing)?;
+            store.intern_string(&string)
+        }
+        Expression::Num(num) => Ptr::num(num.into_scalar()),
+        Expression::Char(c) => Ptr::Leaf(Tag::Expr(Char), (c as u64).into()),
+        Expression::UInt(UInt::U64(x)) => Ptr::Leaf(Tag::Expr(U64), x.into()),
+        Expression::Cons(car, cdr) => {
+            let car = carry_over(&car, legacy, store)?;
+            let cdr = carry_over(&cdr, legacy, store)?;
+            store.intern_2_ptrs(Tag::Expr(Cons), car, cdr)
+        }
+        Expression::Fun(arg, body, closed_env) => {
+            let arg = carry_over(&arg, legacy, store)?;
+            let body = carry_over(&body, legacy, store)?;
+            let closed_env = carry_over(&closed_env, legacy, store)?;
+            store.intern_3_ptrs(Tag::Expr(Fun), arg, body, closed_env)
+        }
+        Expression::Comm(secret, payload) => {
+            let payload = carry_over(&payload, legacy, store)?;
+            let hash = store.hash_comm(secret, &payload)?;
+            store.comms.insert(FWrap(hash), (secret, payload));
+            Ptr::comm(hash)
+        }
+        Expression::Thunk(_) => bail!("Thunks can't be carried over to LEM"),
+    };
+    Ok(carried)
+}
+
+/// Runs LEM's step function on `input`, the input of a legacy evaluation of
+/// `iterations` iterations that ended in `output`, with the same `limit`, and
+/// returns how the two evaluations differ, if they do
+pub fn cross_check<F: LurkField>(
+    legacy: &LegacyStore<F>,
+    input: &IO<F>,
+    output: &IO<F>,
+    iterations: usize,
+    limit: usize,
+) -> Result<Option<Divergence>> {
+    let lem = run_lem(legacy, input, limit)?;
+    let legacy_outcome = outcome(output.cont.tag);
+    if lem.outcome == legacy_outcome && same_result(legacy, output, &lem)? {
+        return Ok(None);
+    }
+    let state = State::init_lurk_state();
+    Ok(Some(Divergence {
+        input: input.expr.fmt_to_string(legacy, &state),
+        legacy_outcome,
+        legacy_result: output.expr.fmt_to_string(legacy, &state),
+        legacy_iterations: iterations,
+        lem_outcome: lem.outcome,
+        lem_result: lem.result.dbg_display(&lem.store),
+        lem_iterations: lem.iterations,
+    }))
+}
+
+/// Evaluates `expr` in `env` with the legacy evaluator and with LEM's step
+/// function, with the same `limit`, and compares the two runs
+pub fn compare_backends<F: LurkField, C: Coprocessor<F>>(
+    legacy: &mut LegacyStore<F>,
+    expr: LegacyPtr<F>,
+    env: LegacyPtr<F>,
+    lang: &Lang<F, C>,
+    limit: usize,
+) -> Result<Comparison> {
+    if lang.has_coprocessors() {
+        bail!("LEM can't run coprocessors")
+    }
+    let start = Instant::now();
+    let (output, iterations, _) = Evaluator::new(expr, env, legacy, limit, lang).eval()?;
+    let legacy_elapsed = start.elapsed();
+    let input = IO {
+        expr,
+        env,
+        cont: legacy.intern_cont_outermost(),
+    };
+    let lem = run_lem(legacy, &input, limit)?;
+
+    let legacy_outcome = outcome(output.cont.tag);
+    let agree = lem.outcome == legacy_outcome && same_result(legacy, &output, &lem)?;
+    let state = State::init_lurk_state();
+    Ok(Comparison {
+        input: expr.fmt_to_string(legacy, &state),
+        legacy: Run {
+            outcome: legacy_outcome,
+            result: output.expr.fmt_to_string(legacy, &state),
+            iterations,
+            elapsed: legacy_elapsed,
+        },
+        lem: Run {
+            outcome: lem.outcome,
+            result: lem.result.dbg_display(&lem.store),
+            iterations: lem.iterations,
+            elapsed: lem.elapsed,
+        },
+        agree,
+    })
+}
+
+/// A run of LEM's step function, in the store it was carried over to
+struct LemRun<F: LurkField> {
+    store: Store<F>,
+    outcome: Outcome,
+    result: Ptr<F>,
+    iterations: usize,
+    elapsed: Duration,
+}
+
+/// Carries `input` over to a fresh LEM store and runs LEM's step function on
+/// it for at most `limit` iterations
+fn run_lem<F: LurkField>(
+    legacy: &LegacyStore<F>,
+    input: &IO<F>,
+    limit: usize,
+) -> Result<LemRun<F>> {
+    let mut store = Store::default();
+    let expr = carry_over(&input.expr, legacy, &mut store)?;
+    let env = carry_over(&input.env, legacy, &mut store)?;
+    for (ptr, carried) in [(&input.expr, &expr), (&input.env, &env)] {
+        let legacy_hash = legacy
+            .hash_expr(ptr)
+            .ok_or_else(|| anyhow!("Can't hash {ptr:?}"))?;
+        if store.hash_ptr(carried)?.hash != *legacy_hash.value() {
+            bail!("{ptr:?} changed its hash when carried over to LEM")
+        }
+    }
+
+    let start = Instant::now();
+    let step = eval_step();
+    let terminal = Ptr::null(Tag::Cont(ContTag::Terminal));
+    let error = Ptr::null(Tag::Cont(ContTag::Error));
+    let mut ptrs = vec![expr, env, Ptr::null(Tag::Cont(ContTag::Outermost))];
+    let mut iterations = 0;
+    while iterations < limit && ptrs[2] != terminal && ptrs[2] != error {
+        let (frame, _) = step.call(ptrs, &mut store, Preimages::new_from_func(&step))?;
+        ptrs = frame.output;
+        iterations += 1;
+    }
+    let outcome = match ptrs[2] {
+        cont if cont == terminal => Outcome::Terminal,
+        cont if cont == error => Outcome::Error,
+        _ => Outcome::Limit,
+    };
+    Ok(LemRun {
+        store,
+        outcome,
+        result: ptrs[0],
+        iterations,
+        elapsed: start.elapsed(),
+    })
+}
+
+/// How a legacy evaluation that ended in a continuation tagged `tag` ended
+fn outcome(tag: ContTag) -> Outcome {
+    match tag {
+        ContTag::Terminal => Outcome::Terminal,
+        ContTag::Error => Outcome::Error,
+        _ => Outcome::Limit,
+    }
+}
+
+/// Whether the result of a legacy evaluation that ended in `output` hashes
+/// like the one of `lem`, if it ended in the terminal continuation
+fn same_result<F: LurkField>(
+    legacy: &LegacyStore<F>,
+    output: &IO<F>,
+    lem: &LemRun<F>,
+) -> Result<bool> {
+    if output.cont.tag != ContTag::Terminal {
+        return Ok(true);
+    }
+    let legacy_hash = legacy
+        .hash_expr(&output.expr)
+        .ok_or_else(|| anyhow!("Can't hash {:?}", output.expr))?;
+    Ok(lem.store.hash_ptr(&lem.result)?.hash == *legacy_hash.value())
+}
+
+#[cfg(test)]
+mod tests {
+    use pasta_curves::pallas::Scalar as Fr;
+
+    use super::*;
+    use crate::eval::{
+        lang::{Coproc, Lang},
+        Evaluator,
+    };
+    use crate::lurk_sym_ptr;
+
+    #[test]
+    fn evaluators_agree_on_results() {
+        let legacy = &mut LegacyStore::<Fr>::default();
+        let lang = Lang::<Fr, Coproc<Fr>>::new();
+        let env = lurk_sym_ptr!(legacy, nil);
+        for (source, limit) in [
+            ("(+ 1 2)", 100),
+            ("(car (cons 1 2))", 100),
+            ("((lambda (x) (cons x \"a\")) 'c')", 100),
+            ("(car 1)", 100),
+            ("(letrec ((loop (lambda (x) (loop x)))) (loop 1))", 20),
+        ] {
+            let expr = legacy.read(source).unwrap();
+            let (output, iterations, _) = Evaluator::new(expr, env, legacy, limit, &lang)
+                .eval()
+                .unwrap();
+            let input = IO {
+                expr,
+                env,
+                cont: legacy.intern_cont_outermost(),
+            };
+            let divergence = cross_check(legacy, &input, &output, iterations, limit).unwrap();
+            assert_eq!(divergence, None, "{source}");
+
+            // a legacy result that LEM doesn't agree with
+            let wrong = IO {
+                expr: legacy.num(42),
+                ..output
+            };
+            if output.cont.tag == ContTag::Terminal {
+                let divergence = cross_check(legacy, &input, &wrong, iterations, limit)
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(divergence.legacy_result, "42");
+                assert!(divergence.to_string().starts_with("LEM and the legacy"));
+            }
+        }
+    }
+
+    #[test]
+    fn backends_are_compared() {
+        let legacy = &mut LegacyStore::<Fr>::default();
+        let lang = Lang::<Fr, Coproc<Fr>>::new();
+        let env = lurk_sym_ptr!(legacy, nil);
+        let expr = legacy.read("(+ 1 2)").unwrap();
+        let comparison = compare_backends(legacy, expr, env, &lang, 100).unwrap();
+        assert!(comparison.agree);
+        assert_eq!(comparison.legacy.outcome, Outcome::Terminal);
+        assert_eq!(comparison.legacy.result, "3");
+        assert_eq!(comparison.lem.outcome, Outcome::Terminal);
+        assert!(comparison
+            .to_string()
+            .starts_with("LEM and the legacy evaluator agree"));
+
+        let expr = legacy.read("(car 1)").unwrap();
+        let comparison = compare_backends(legacy, expr, env, &lang, 100).unwrap();
+        assert!(comparison.agree);
+        assert_eq!(comparison.lem.outcome, Outcome::Error);
+    }
+}
@@ -0,0 +1,214 @@
+//! Structural diffs of LEM functions.
+//!
+//! `diff` compares two functions, typically two releases of Lurk's step
+//! function, by their slots, their estimated number of constraints and their
+//! code, as printed by the parser. The unique suffixes `deconflict` gives to
+//! variables are left out of the code, so the diff of a new operation doesn't
+//! also show every variable bound after it as changed.
+
+use pasta_curves::pallas::Scalar;
+use std::fmt;
+
+use super::{slot::SlotsCounter, store::Store, Func};
+
+/// A line of the printed code of two functions
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DiffLine {
+    Same(String),
+    Removed(String),
+    Added(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FuncDiff {
+    pub old_slots: SlotsCounter,
+    pub new_slots: SlotsCounter,
+    pub old_constraints: usize,
+    pub new_constraints: usize,
+    pub lines: Vec<DiffLine>,
+}
+
+impl FuncDiff {
+    /// Whether the functions have the same code, and thus the same circuit
+    pub fn is_empty(&self) -> bool {
+        self.lines
+            .iter()
+            .all(|line| matches!(line, DiffLine::Same(..)))
+    }
+}
+
+/// The named slot counts, in the order `SlotsReport` prints them
+fn slot_counts(slots: &SlotsCounter) -> [(&'static str, usize); 9] {
+    [
+        ("hash2", slots.hash2),
+        ("hash3", slots.hash3),
+        ("hash4", slots.hash4),
+        ("hash6", slots.hash6),
+        ("hash8", slots.hash8),
+        ("commitment", slots.commitment),
+        ("less_than", slots.less_than),
+        ("bit_decomp", slots.bit_decomp),
+        ("range", slots.range),
+    ]
+}
+
+/// The printed code of `func`, without the suffixes of its variables
+fn code_lines(func: &Func) -> Vec<String> {
+    let code = func.to_string();
+    let mut stripped = String::with_capacity(code.len());
+    let mut chars = code.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '#' && chars.peek().map_or(false, char::is_ascii_digit) {
+            while chars.next_if(char::is_ascii_digit).is_some() {}
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped.lines().map(String::from).collect()
+}
+
+/// The lines of `old` and `new`, as a longest common subsequence diff
+fn diff_lines(old: &[String], new: &[String]) -> Vec<DiffLine> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let (old_rest, new_rest) = (&old[prefix..], &new[prefix..]);
+    let suffix = old_rest
+        .iter()
+        .rev()
+        .zip(new_rest.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_mid = &old_rest[..old_rest.len() - suffix];
+    let new_mid = &new_rest[..new_rest.len() - suffix];
+
+    // lcs[i][j] is the length of the longest common subsequence of
+    // old_mid[i..] and new_mid[j..]
+    let (n, m) = (old_mid.len(), new_mid.len());
+    let mut lcs = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old_mid[i] == new_mid[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = old[..prefix]
+        .iter()
+        .map(|line| DiffLine::Same(line.clone()))
+        .collect::<Vec<_>>();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old_mid[i] == new_mid[j] {
+            lines.push(DiffLine::Same(old_mid[i].clone()));
+            (i, j) = (i + 1, j + 1);
+        } else if j < m && (i == n || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            lines.push(DiffLine::Added(new_mid[j].clone()));
+            j += 1;
+        } else {
+            lines.push(DiffLine::Removed(old_mid[i].clone()));
+            i += 1;
+        }
+    }
+    lines.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|line| DiffLine::Same(line.clone())),
+    );
+    lines
+}
+
+/// Compares `old` and `new` by their slots, their constraints and their code
+pub fn diff(old: &Func, new: &Func) -> FuncDiff {
+    let store = &mut Store::<Scalar>::default();
+    FuncDiff {
+        old_slots: old.slot,
+        new_slots: new.slot,
+        old_constraints: old.num_constraints(store),
+        new_constraints: new.num_constraints(store),
+        lines: diff_lines(&code_lines(old), &code_lines(new)),
+    }
+}
+
+/// The number of unchanged lines shown around the changed ones
+const CONTEXT: usize = 2;
+
+impl fmt::Display for FuncDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let changed_slots = slot_counts(&self.old_slots)
+            .into_iter()
+            .zip(slot_counts(&self.new_slots))
+            .filter(|((_, old), (_, new))| old != new)
+            .map(|((name, old), (_, new))| format!("{name} {old} -> {new}"))
+            .collect::<Vec<_>>();
+        if changed_slots.is_empty() {
+            writeln!(f, "slots: unchanged")?;
+        } else {
+            writeln!(f, "slots: {}", changed_slots.join(", "))?;
+        }
+        let (old, new) = (self.old_constraints, self.new_constraints);
+        writeln!(
+            f,
+            "constraints: {old} -> {new} ({:+})",
+            new as i64 - old as i64
+        )?;
+
+        let changed = |line: &DiffLine| !matches!(line, DiffLine::Same(..));
+        let mut last_shown = None;
+        for (i, line) in self.lines.iter().enumerate() {
+            let start = i.saturating_sub(CONTEXT);
+            let end = (i + CONTEXT + 1).min(self.lines.len());
+            if !self.lines[start..end].iter().any(changed) {
+                continue;
+            }
+            if last_shown.map_or(i > 0, |last| last + 1 < i) {
+                writeln!(f, "...")?;
+            }
+            last_shown = Some(i);
+            match line {
+                DiffLine::Same(line) => writeln!(f, "  {line}")?,
+                DiffLine::Removed(line) => writeln!(f, "- {line}")?,
+                DiffLine::Added(line) => writeln!(f, "+ {line}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::func;
+
+    #[test]
+    fn diffs_show_new_operations() {
+        let old = func!(foo(x, y): 1 => {
+            let z = add(x, y);
+            return (z)
+        });
+        let new = func!(foo(x, y): 1 => {
+            let z = add(x, y);
+            let z: Expr::Cons = hash2(z, y);
+            return (z)
+        });
+        let same = diff(&old, &old);
+        assert!(same.is_empty());
+        assert!(same.to_string().starts_with("slots: unchanged\n"));
+
+        let diff = diff(&old, &new);
+        assert!(!diff.is_empty());
+        assert_eq!(diff.old_slots.hash2 + 1, diff.new_slots.hash2);
+        assert!(diff.new_constraints > diff.old_constraints);
+        let added = diff
+            .lines
+            .iter()
+            .filter_map(|line| match line {
+                DiffLine::Added(line) => Some(line.trim()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(added, ["let z: Expr::Cons = hash2(z, y);"]);
+        assert!(diff.to_string().contains("slots: hash2 0 -> 1\n"));
+    }
+}
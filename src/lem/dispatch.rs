@@ -0,0 +1,150 @@
+//! Dynamic dispatch for LEM.
+//!
+//! A `FuncTable` holds functions of the same signature and `Op::CallDyn`
+//! calls the one at the index a variable holds at runtime, which lets a step
+//! function be extended with handlers without growing a match of its own.
+//!
+//! `CallDyn` isn't an operation of its own, but a call to the dispatcher of the
+//! table: a function matching the index against the positions of the table's
+//! functions, each case calling one of them. Synthesis then selects among the
+//! candidates like it does among the cases of any match, and the dispatcher
+//! needs as many slots as the most demanding candidate. Calls with an index
+//! outside of the table fail.
+
+use anyhow::{bail, Result};
+
+use super::{Block, Ctrl, Func, Lit, Op, Var};
+
+/// Functions with the same numbers of inputs and outputs, indexed by their
+/// position
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuncTable {
+    funcs: Vec<Func>,
+    dispatcher: Func,
+}
+
+impl FuncTable {
+    pub fn new(name: String, funcs: Vec<Func>) -> Result<Self> {
+        let Some(first) = funcs.first() else {
+            bail!("Function table `{name}` is empty")
+        };
+        let (input_size, output_size) = (first.input_params.len(), first.output_size);
+        for func in &funcs {
+            if func.input_params.len() != input_size || func.output_size != output_size {
+                bail!(
+                    "Function `{}` doesn't have the signature of function `{}` in table `{name}`",
+                    func.name,
+                    first.name
+                )
+            }
+        }
+        let vars = |prefix: &str, n: usize| -> Vec<Var> {
+            (0..n).map(|i| Var(format!("{prefix}{i}").into())).collect()
+        };
+        let idx = Var("idx".into());
+        let (args, rets) = (vars("arg", input_size), vars("ret", output_size));
+        let cases = funcs
+            .iter()
+            .enumerate()
+            .map(|(i, func)| {
+                let call = Op::Call(rets.clone(), Box::new(func.clone()), args.clone());
+                let ctrl = Ctrl::Return(rets.clone());
+                let case = Block {
+                    ops: vec![call],
+                    ctrl,
+                };
+                (Lit::Num(i as u128), case)
+            })
+            .collect();
+        let body = Block {
+            ops: vec![],
+            ctrl: Ctrl::MatchVal(idx.clone(), cases, None),
+        };
+        let input_params = [vec![idx], args].concat();
+        let dispatcher = Func::new(name, input_params, output_size, body)?;
+        Ok(Self { funcs, dispatcher })
+    }
+
+    #[inline]
+    pub fn funcs(&self) -> &[Func] {
+        &self.funcs
+    }
+
+    /// The function `Op::CallDyn` calls with the index and the arguments
+    #[inline]
+    pub fn dispatcher(&self) -> &Func {
+        &self.dispatcher
+    }
+}
+
+/// Shim for the calls through a `FuncTable`
+#[allow(non_snake_case)]
+impl Op {
+    /// `CallDyn(ys, i, table, xs)` binds `ys` to the results of the function
+    /// at index `i` of `table` applied to `xs`
+    pub fn CallDyn(out: Vec<Var>, idx: Var, table: &FuncTable, inp: Vec<Var>) -> Op {
+        let func = Box::new(table.dispatcher.clone());
+        Op::Call(out, func, [vec![idx], inp].concat())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bellpepper_core::test_cs::TestConstraintSystem;
+    use blstrs::Scalar as Fr;
+
+    use super::*;
+    use crate::func;
+    use crate::lem::{interpreter::Preimages, pointers::Ptr, store::Store, Tag};
+    use crate::tag::ExprTag::{Cons, Num};
+
+    #[test]
+    fn calls_select_functions_by_index() {
+        let double = func!(double(x): 1 => {
+            let y = add(x, x);
+            return (y)
+        });
+        let pair = func!(pair(x): 1 => {
+            let y: Expr::Cons = hash2(x, x);
+            return (y)
+        });
+        let handlers = FuncTable::new("handlers".into(), vec![double, pair]).unwrap();
+        let apply = func!(apply(i, x): 1 => {
+            let (y) = call_dyn handlers[i](x);
+            return (y)
+        });
+        assert_eq!(apply.slot.hash2, 1);
+
+        let store = &mut Store::<Fr>::default();
+        let three = Ptr::num(Fr::from(3));
+        for (i, tag) in [(0u64, Num), (1, Cons)] {
+            let input = vec![Ptr::num(Fr::from(i)), three];
+            let (frame, _) = apply
+                .call(input, store, Preimages::new_from_func(&apply))
+                .unwrap();
+            assert_eq!(frame.output[0].tag(), &Tag::Expr(tag));
+
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            apply.synthesize(&mut cs, store, &frame).unwrap();
+            assert!(cs.is_satisfied());
+            assert_eq!(apply.num_constraints::<Fr>(store), cs.num_constraints());
+        }
+
+        let input = vec![Ptr::num(Fr::from(2)), three];
+        assert!(apply
+            .call(input, store, Preimages::new_from_func(&apply))
+            .is_err());
+    }
+
+    #[test]
+    fn tables_need_a_common_signature() {
+        let one = func!(one(x): 1 => {
+            return (x)
+        });
+        let two = func!(two(x): 2 => {
+            return (x, x)
+        });
+        assert!(FuncTable::new("empty".into(), vec![]).is_err());
+        assert!(FuncTable::new("mixed".into(), vec![one, two]).is_err());
+    }
+}
@@ -0,0 +1,209 @@
+//! Provable output logs for LEM.
+//!
+//! On its own, `Op::Emit` only prints a value when interpreting, and the
+//! circuit drops it, so what a program emitted isn't part of the claim a proof
+//! makes. `Func::with_emit_log` threads a log through the function instead: an
+//! extra input and an extra output, the last ones, holding the list of the
+//! values emitted so far as `Expr::Cons` pointers, most recent first. Each
+//! `Emit(v)` is followed by the hash of `v` onto the log, and each return
+//! returns the log, so the circuit constrains every emitted value like any
+//! other hash.
+//!
+//! Passing the log output by a frame as the log input of the next one, as the
+//! outputs of a step function are passed as inputs, carries the hash of the
+//! whole log across frames along with the rest of the frames' public inputs
+//! and outputs. The values a frame emitted are those between the two logs of
+//! the frame, which `Frame::emitted` reads back from the store.
+//!
+//! Nova proofs of `MultiFrame`s carry a log of the same shape as the last
+//! element of their public IO, starting from `eval::empty_emit_log`.
+
+use anyhow::Result;
+
+use crate::field::LurkField;
+use crate::tag::ExprTag::Cons;
+
+use super::{interpreter::Frame, pointers::Ptr, store::Store, Block, Ctrl, Func, Op, Tag, Var};
+
+/// The name of the variables holding the log, which can't be the name of a
+/// variable of a function, since `deconflict` suffixes those with `#`
+const LOG: &str = "emit_log";
+
+impl Block {
+    /// Whether the block, or any function it calls, emits
+    fn emits(&self) -> bool {
+        let emits = |op: &Op| match op {
+            Op::Emit(_) => true,
+            Op::Call(_, func, _) => func.body.emits(),
+            _ => false,
+        };
+        if self.ops.iter().any(emits) {
+            return true;
+        }
+        match &self.ctrl {
+            Ctrl::MatchTag(_, cases, def) => cases.values().chain(def.as_deref()).any(Block::emits),
+            Ctrl::MatchVal(_, cases, def) => cases.values().chain(def.as_deref()).any(Block::emits),
+            Ctrl::MatchSymbol(_, cases, def) => {
+                cases.values().chain(def.as_deref()).any(Block::emits)
+            }
+            Ctrl::IfEq(_, _, eq_block, else_block) => eq_block.emits() || else_block.emits(),
+            Ctrl::Return(..) => false,
+        }
+    }
+
+    /// Adds the emitted values to the log `log` holds before the block, and
+    /// returns the log along with the outputs
+    fn thread_log(self, mut log: Var) -> Result<Block> {
+        let next_log = Var(LOG.into());
+        let mut ops = Vec::with_capacity(self.ops.len());
+        for op in self.ops {
+            match op {
+                Op::Emit(v) => {
+                    ops.push(Op::Emit(v.clone()));
+                    ops.push(Op::Hash(next_log.clone(), Tag::Expr(Cons), vec![v, log]));
+                    log = next_log.clone();
+                }
+                Op::Call(mut out, func, mut inp) if func.body.emits() => {
+                    out.push(next_log.clone());
+                    inp.push(log);
+                    ops.push(Op::Call(out, Box::new(func.with_emit_log()?), inp));
+                    log = next_log.clone();
+                }
+                op => ops.push(op),
+            }
+        }
+        let ctrl = match self.ctrl {
+            Ctrl::MatchTag(var, cases, def) => {
+                let cases = cases
+                    .into_iter()
+                    .map(|(tag, block)| Ok((tag, block.thread_log(log.clone())?)))
+                    .collect::<Result<_>>()?;
+                let def = def.map(|def| def.thread_log(log)).transpose()?;
+                Ctrl::MatchTag(var, cases, def.map(Box::new))
+            }
+            Ctrl::MatchVal(var, cases, def) => {
+                let cases = cases
+                    .into_iter()
+                    .map(|(lit, block)| Ok((lit, block.thread_log(log.clone())?)))
+                    .collect::<Result<_>>()?;
+                let def = def.map(|def| def.thread_log(log)).transpose()?;
+                Ctrl::MatchVal(var, cases, def.map(Box::new))
+            }
+            Ctrl::MatchSymbol(var, cases, def) => {
+                let cases = cases
+                    .into_iter()
+                    .map(|(sym, block)| Ok((sym, block.thread_log(log.clone())?)))
+                    .collect::<Result<_>>()?;
+                let def = def.map(|def| def.thread_log(log)).transpose()?;
+                Ctrl::MatchSymbol(var, cases, def.map(Box::new))
+            }
+            Ctrl::IfEq(x, y, eq_block, else_block) => {
+                let eq_block = eq_block.thread_log(log.clone())?;
+                let else_block = else_block.thread_log(log)?;
+                Ctrl::IfEq(x, y, Box::new(eq_block), Box::new(else_block))
+            }
+            Ctrl::Return(mut vars) => {
+                vars.push(log);
+                Ctrl::Return(vars)
+            }
+        };
+        Ok(Block { ops, ctrl })
+    }
+}
+
+impl Func {
+    /// Returns the function with an output log as its last input and output,
+    /// to which each `Emit` adds the value it emits, as explained above. The
+    /// functions it calls that emit get a log too. It changes the signature
+    /// of the function and the shape of its circuit, so it's opt-in.
+    pub fn with_emit_log(&self) -> Result<Func> {
+        let log = Var(LOG.into());
+        let input_params = [self.input_params.clone(), vec![log.clone()]].concat();
+        let body = self.body.clone().thread_log(log)?;
+        let mut func = Func::new(self.name.clone(), input_params, self.output_size + 1, body)?;
+        func.merge_returns = self.merge_returns;
+        if self.share_slots {
+            func = func.with_shared_slots();
+        }
+        Ok(func)
+    }
+}
+
+impl<F: LurkField> Frame<F> {
+    /// The values emitted by the frame of a function made by
+    /// `Func::with_emit_log`, in the order they were emitted
+    pub fn emitted(&self, store: &Store<F>) -> Option<Vec<Ptr<F>>> {
+        let (input_log, mut log) = (self.input.last()?, *self.output.last()?);
+        let mut emitted = vec![];
+        while &log != input_log {
+            if log.tag() != &Tag::Expr(Cons) {
+                return None;
+            }
+            let (value, rest) = store.fetch_2_ptrs(log.get_index2()?)?;
+            emitted.push(*value);
+            log = *rest;
+        }
+        emitted.reverse();
+        Some(emitted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bellpepper_core::test_cs::TestConstraintSystem;
+    use blstrs::Scalar as Fr;
+
+    use super::*;
+    use crate::func;
+    use crate::lem::interpreter::Preimages;
+    use crate::tag::ExprTag::Nil;
+
+    #[test]
+    fn emitted_values_are_logged() {
+        let shout = func!(shout(x): 1 => {
+            emit(x);
+            return (x)
+        });
+        let lem = func!(twice(x, y): 1 => {
+            emit(x);
+            match y.tag {
+                Expr::Num => {
+                    let (z) = shout(y);
+                    return (z)
+                }
+            };
+            let (z) = shout(x);
+            return (z)
+        })
+        .with_emit_log()
+        .unwrap();
+        assert_eq!(lem.input_params.len(), 3);
+        assert_eq!(lem.output_size, 2);
+
+        let store = &mut Store::<Fr>::default();
+        let log = Ptr::null(Tag::Expr(Nil));
+        let (one, two) = (Ptr::num(Fr::from(1)), Ptr::num(Fr::from(2)));
+        let (frame, _) = lem
+            .call(vec![one, two, log], store, Preimages::new_from_func(&lem))
+            .unwrap();
+        assert_eq!(frame.emitted(store), Some(vec![one, two]));
+
+        // the log of a frame is where the next one starts
+        let (next_frame, _) = lem
+            .call(
+                vec![two, log, frame.output[1]],
+                store,
+                Preimages::new_from_func(&lem),
+            )
+            .unwrap();
+        assert_eq!(next_frame.emitted(store), Some(vec![two, two]));
+
+        for frame in [frame, next_frame] {
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            lem.synthesize(&mut cs, store, &frame).unwrap();
+            assert!(cs.is_satisfied());
+            assert_eq!(lem.num_constraints(store), cs.num_constraints());
+        }
+        lem.assert_num_constraints(store);
+    }
+}
@@ -1,18 +1,491 @@
+use anyhow::{bail, Result};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::coprocessor::Coprocessor;
+use crate::eval::lang::Lang;
+use crate::field::LurkField;
 use crate::func;
+use crate::tag::ContTag;
+
+use super::{
+    checkpoint::EvalCheckpoint,
+    interpreter::{Budget, Frame, FrameStream, Halt},
+    pointers::Ptr,
+    ratio::{make_ratio, ratio_add, ratio_div, ratio_lt, ratio_mul, ratio_sub},
+    set::{set_insert, set_member, set_union},
+    store::Store,
+    Func, Tag,
+};
+
+/// The capabilities of Lurk's step function. Each configuration builds its own
+/// `Func`, and thus its own circuit, so proofs must carry the configuration
+/// they were made with: two provers that disagree on it produce proofs that
+/// don't verify against each other's parameters.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct EvalConfig {
+    /// Whether `commit`, `comm`, `hide`, `open` and `secret` are available.
+    /// When they aren't, evaluating them is an error.
+    pub commitments: bool,
+    /// The coprocessors the step function dispatches to, by symbol path
+    pub coprocessors: Vec<String>,
+    /// Whether arithmetic and comparisons between a field element and a
+    /// `u64` are errors, rather than carried out in the field
+    pub strict_arithmetic: bool,
+    /// Whether the step function counts the iterations of an evaluation. When
+    /// set, it takes and returns one more pointer, after all others: the
+    /// number of iterations so far, which every step that starts with neither
+    /// the terminal nor the error continuation increments. Padding frames
+    /// thus leave it alone, and the iteration count is part of the output of a
+    /// proven evaluation.
+    pub metering: bool,
+    /// Whether the ratio builtins `ratio`, `ratio+`, `ratio-`, `ratio*`,
+    /// `ratio/` and `ratio<` are available. They normalize their results with
+    /// the `RatioHint` coprocessor, which must then be registered in the store
+    /// under `RATIO_HINT`.
+    #[serde(default)]
+    pub ratios: bool,
+    /// Whether the set builtins `set-member`, `set-insert` and `set-union` are
+    /// available, and the depth of the sets they support, below 64. `nil`
+    /// stands for the empty set. They compute keys with the `SetKey`
+    /// coprocessor, which must then be registered in the store under `SET_KEY`,
+    /// and `set-union` costs twice as much for each level of depth.
+    #[serde(default)]
+    pub set_depth: Option<usize>,
+}
+
+impl Default for EvalConfig {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+impl EvalConfig {
+    /// Lurk as evaluated by the REPL: commitments, lax arithmetic, no
+    /// coprocessors and no metering
+    pub fn standard() -> Self {
+        Self {
+            commitments: true,
+            coprocessors: vec![],
+            strict_arithmetic: false,
+            metering: false,
+            ratios: false,
+            set_depth: None,
+        }
+    }
+
+    /// A configuration for programs that only compute: no commitments and
+    /// strict arithmetic
+    pub fn pure() -> Self {
+        Self {
+            commitments: false,
+            strict_arithmetic: true,
+            ..Self::standard()
+        }
+    }
+
+    /// The standard configuration, with the coprocessors of `lang`
+    pub fn for_lang<F: LurkField, C: Coprocessor<F>>(lang: &Lang<F, C>) -> Self {
+        let mut coprocessors: Vec<_> = lang
+            .coprocessors()
+            .keys()
+            .map(|sym| sym.path().join("-"))
+            .collect();
+        coprocessors.sort();
+        Self {
+            coprocessors,
+            ..Self::standard()
+        }
+    }
+
+    /// A string identifying the configuration, in the style of `Lang::key`,
+    /// ending with the start of the digest of the step function it builds,
+    /// so that configurations building different circuits have different keys.
+    /// Configurations LEM can't build a step function for yet, those with
+    /// coprocessors, are identified by their flags alone.
+    pub fn key(&self) -> String {
+        let mut key = self.flags();
+        if let Ok(digest) = self.fingerprint() {
+            key += &format!("-{}", &digest[..16]);
+        }
+        key
+    }
+
+    /// The capabilities of the configuration, as a string
+    pub fn flags(&self) -> String {
+        let mut key = String::new();
+        key += if self.commitments { "comm" } else { "nocomm" };
+        key += if self.strict_arithmetic {
+            "-strict"
+        } else {
+            "-lax"
+        };
+        key += if self.metering { "-meter" } else { "-nometer" };
+        if self.ratios {
+            key += "-ratio";
+        }
+        if let Some(set_depth) = self.set_depth {
+            key += &format!("-sets-{set_depth}");
+        }
+        key += "-coproc-";
+        if self.coprocessors.is_empty() {
+            key += "none"
+        } else {
+            let mut coprocessors = self.coprocessors.clone();
+            coprocessors.sort();
+            key += &coprocessors.join("-")
+        }
+        key
+    }
 
-use super::Func;
+    /// The digest of the step function the configuration builds, which
+    /// determines the shape of its circuit. It's computed once per
+    /// configuration.
+    pub fn fingerprint(&self) -> Result<String> {
+        static FINGERPRINTS: Lazy<Mutex<HashMap<EvalConfig, String>>> = Lazy::new(Default::default);
+        if let Some(digest) = FINGERPRINTS.lock().unwrap().get(self) {
+            return Ok(digest.clone());
+        }
+        let digest = eval_step_with(self)?.digest()?;
+        FINGERPRINTS
+            .lock()
+            .unwrap()
+            .insert(self.clone(), digest.clone());
+        Ok(digest)
+    }
+}
+
+impl std::fmt::Display for EvalConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.key())
+    }
+}
 
 /// Lurk's step function
-#[allow(dead_code)]
 pub(crate) fn eval_step() -> Func {
-    let reduce = reduce();
-    let apply_cont = apply_cont();
+    eval_step_with(&EvalConfig::standard()).expect("the standard configuration is supported")
+}
+
+/// Evaluates `expr` in `env` with Lurk's step function, within `budget`.
+/// Returns the frames and the last output, whose continuation is the terminal
+/// or the error one if the evaluation ended, or the `OutOfGas` one, along with
+/// the expression and environment it would go on from, if it ran out of gas
+pub fn evaluate_within<F: LurkField>(
+    expr: Ptr<F>,
+    env: Ptr<F>,
+    store: &mut Store<F>,
+    budget: Budget,
+) -> Result<(Vec<Frame<F>>, Vec<Ptr<F>>, Halt)> {
+    let step = eval_step();
+    let terminal = Ptr::null(Tag::Cont(ContTag::Terminal));
+    let error = Ptr::null(Tag::Cont(ContTag::Error));
+    let stop_cond = |output: &[Ptr<F>]| output[2] == terminal || output[2] == error;
+    let input = vec![expr, env, Ptr::null(Tag::Cont(ContTag::Outermost))];
+    let (frames, _, halt) = step.call_until_within(input.clone(), store, stop_cond, budget)?;
+    let mut output = frames.last().map_or(input, |frame| frame.output.clone());
+    if let Halt::OutOfGas(_) = halt {
+        output[2] = Ptr::null(Tag::Cont(ContTag::OutOfGas));
+    }
+    Ok((frames, output, halt))
+}
+
+/// Evaluates `expr` in `env` with Lurk's step function, yielding its frames one
+/// at a time, along with their paths, so that evaluations of millions of
+/// iterations can be proven without holding all of their frames in memory at
+/// once. The store still holds everything the evaluation interns, and can be
+/// read through `FrameStream::store` between frames. The last frame is the one
+/// whose output has the terminal or the error continuation.
+pub fn evaluate_stream<F: LurkField>(
+    expr: Ptr<F>,
+    env: Ptr<F>,
+    store: &mut Store<F>,
+) -> FrameStream<'_, F, impl Fn(&[Ptr<F>]) -> bool> {
+    let terminal = Ptr::null(Tag::Cont(ContTag::Terminal));
+    let error = Ptr::null(Tag::Cont(ContTag::Error));
+    let stop_cond = move |output: &[Ptr<F>]| output[2] == terminal || output[2] == error;
+    let input = vec![expr, env, Ptr::null(Tag::Cont(ContTag::Outermost))];
+    FrameStream::new(Cow::Owned(eval_step()), input, store, stop_cond)
+}
+
+/// Like `evaluate_stream`, but goes on with the frames after `checkpoint`,
+/// taken from a stream of an evaluation
+pub fn resume_evaluation<'a, F: LurkField>(
+    checkpoint: &EvalCheckpoint<F>,
+    store: &'a mut Store<F>,
+) -> Result<FrameStream<'a, F, impl Fn(&[Ptr<F>]) -> bool>> {
+    let terminal = Ptr::null(Tag::Cont(ContTag::Terminal));
+    let error = Ptr::null(Tag::Cont(ContTag::Error));
+    let stop_cond = move |output: &[Ptr<F>]| output[2] == terminal || output[2] == error;
+    FrameStream::resume(Cow::Owned(eval_step()), checkpoint, store, stop_cond)
+}
+
+/// Lurk's step function with the capabilities of `config`
+pub(crate) fn eval_step_with(config: &EvalConfig) -> Result<Func> {
+    if !config.coprocessors.is_empty() {
+        bail!("LEM doesn't support coprocessors yet");
+    }
+    let guard = if config.commitments {
+        allow_all()
+    } else {
+        forbid_commitments()
+    };
+    if matches!(config.set_depth, Some(depth) if depth >= 64) {
+        bail!("Sets are at most 63 levels deep");
+    }
+    let reduce = reduce(config.ratios, config.set_depth.is_some());
+    let mut apply_cont = apply_cont(config.strict_arithmetic);
+    if config.ratios {
+        apply_cont = apply_builtin_first(apply_ratio_op(), apply_cont);
+    }
+    if let Some(depth) = config.set_depth {
+        apply_cont = apply_builtin_first(apply_set_op(depth), apply_cont);
+    }
     let make_thunk = make_thunk();
+    let meter = meter();
+
+    let step = if config.metering {
+        func!(step(expr, env, cont, iterations): 4 => {
+            let (iterations) = meter(cont, iterations);
+            let (expr, env, cont) = guard(expr, env, cont);
+            let (expr, env, cont, ctrl) = reduce(expr, env, cont);
+            let (expr, env, cont, ctrl) = apply_cont(expr, env, cont, ctrl);
+            let (expr, env, cont, _ctrl) = make_thunk(expr, env, cont, ctrl);
+            return (expr, env, cont, iterations)
+        })
+    } else {
+        func!(step(expr, env, cont): 3 => {
+            let (expr, env, cont) = guard(expr, env, cont);
+            let (expr, env, cont, ctrl) = reduce(expr, env, cont);
+            let (expr, env, cont, ctrl) = apply_cont(expr, env, cont, ctrl);
+            let (expr, env, cont, _ctrl) = make_thunk(expr, env, cont, ctrl);
+            return (expr, env, cont)
+        })
+    };
+    Ok(step)
+}
+
+/// Counts one more iteration, unless `cont` shows the evaluation has ended
+fn meter() -> Func {
+    func!(meter(cont, iterations): 1 => {
+        match cont.tag {
+            Cont::Terminal | Cont::Error => {
+                return (iterations)
+            }
+        };
+        let one = Num(1);
+        let iterations = add(iterations, one);
+        return (iterations)
+    })
+}
 
-    func!(step(expr, env, cont): 3 => {
-        let (expr, env, cont, ctrl) = reduce(expr, env, cont);
-        let (expr, env, cont, ctrl) = apply_cont(expr, env, cont, ctrl);
-        let (expr, env, cont, _ctrl) = make_thunk(expr, env, cont, ctrl);
+/// Applies the continuations of the builtin `apply_builtin` handles before
+/// passing them on to `apply_other_cont`
+fn apply_builtin_first(apply_builtin: Func, apply_other_cont: Func) -> Func {
+    func!(apply_cont(result, env, cont, ctrl): 4 => {
+        let (result, env, cont, ctrl) = apply_builtin(result, env, cont, ctrl);
+        let (result, env, cont, ctrl) = apply_other_cont(result, env, cont, ctrl);
+        return (result, env, cont, ctrl)
+    })
+}
+
+/// Applies the ratio builtins, leaving the other continuations to
+/// `apply_cont`. Arguments of the wrong type, a zero denominator or divisor and
+/// a negative difference go to the error continuation, but results that don't
+/// fit in 64 bits make the step fail, as they have no witness.
+fn apply_ratio_op() -> Func {
+    let (make_ratio, ratio_add, ratio_sub) = (make_ratio(), ratio_add(), ratio_sub());
+    let (ratio_mul, ratio_div, ratio_lt) = (ratio_mul(), ratio_div(), ratio_lt());
+    func!(apply_ratio_op(result, env, cont, ctrl): 4 => {
+        let makethunk: Ctrl::MakeThunk;
+        let errctrl: Ctrl::Error;
+        let err: Cont::Error;
+        let t = Symbol("t");
+        let zero = Num(0);
+        match ctrl.tag {
+            Ctrl::ApplyContinuation => {
+                match cont.tag {
+                    Cont::Binop2 => {
+                        let (operator, evaled_arg, continuation) = unhash3(cont);
+                        match operator.val {
+                            Symbol("ratio") => {
+                                match evaled_arg.tag {
+                                    Expr::U64 => {
+                                        match result.tag {
+                                            Expr::U64 => {
+                                                let den_is_zero = eq_val(result, zero);
+                                                match den_is_zero.val {
+                                                    Num(0) => {
+                                                        let (ratio) = make_ratio(evaled_arg, result);
+                                                        return (ratio, env, continuation, makethunk)
+                                                    }
+                                                };
+                                                return (result, env, err, errctrl)
+                                            }
+                                        };
+                                        return (result, env, err, errctrl)
+                                    }
+                                };
+                                return (result, env, err, errctrl)
+                            }
+                            Symbol("ratio+")
+                            | Symbol("ratio-")
+                            | Symbol("ratio*")
+                            | Symbol("ratio/")
+                            | Symbol("ratio<") => {
+                                match evaled_arg.tag {
+                                    Expr::Ratio => {
+                                        match result.tag {
+                                            Expr::Ratio => {
+                                                match operator.val {
+                                                    Symbol("ratio+") => {
+                                                        let (ratio) = ratio_add(evaled_arg, result);
+                                                        return (ratio, env, continuation, makethunk)
+                                                    }
+                                                    Symbol("ratio-") => {
+                                                        let (lt) = ratio_lt(evaled_arg, result);
+                                                        if lt == t {
+                                                            return (result, env, err, errctrl)
+                                                        }
+                                                        let (ratio) = ratio_sub(evaled_arg, result);
+                                                        return (ratio, env, continuation, makethunk)
+                                                    }
+                                                    Symbol("ratio*") => {
+                                                        let (ratio) = ratio_mul(evaled_arg, result);
+                                                        return (ratio, env, continuation, makethunk)
+                                                    }
+                                                    Symbol("ratio/") => {
+                                                        let (num, _den) = unhash2(result);
+                                                        let num_is_zero = eq_val(num, zero);
+                                                        match num_is_zero.val {
+                                                            Num(1) => {
+                                                                return (result, env, err, errctrl)
+                                                            }
+                                                        };
+                                                        let (ratio) = ratio_div(evaled_arg, result);
+                                                        return (ratio, env, continuation, makethunk)
+                                                    }
+                                                    Symbol("ratio<") => {
+                                                        let (lt) = ratio_lt(evaled_arg, result);
+                                                        return (lt, env, continuation, makethunk)
+                                                    }
+                                                }
+                                            }
+                                        };
+                                        return (result, env, err, errctrl)
+                                    }
+                                };
+                                return (result, env, err, errctrl)
+                            }
+                        };
+                        return (result, env, cont, ctrl)
+                    }
+                };
+                return (result, env, cont, ctrl)
+            }
+        };
+        return (result, env, cont, ctrl)
+    })
+}
+
+/// Applies the set builtins, leaving the other continuations to `apply_cont`.
+/// Arguments that should be sets but are neither sets nor `nil` go to the
+/// error continuation, but sets deeper than `depth` make the step fail, as
+/// they have no witness.
+fn apply_set_op(depth: usize) -> Func {
+    let (set_member, set_insert) = (set_member(depth), set_insert(depth));
+    let set_union = set_union(depth);
+    let to_set = func!(to_set(x): 1 => {
+        match x.tag {
+            Expr::Nil => {
+                let empty: Expr::Set;
+                return (empty)
+            }
+        };
+        return (x)
+    });
+    func!(apply_set_op(result, env, cont, ctrl): 4 => {
+        let makethunk: Ctrl::MakeThunk;
+        let errctrl: Ctrl::Error;
+        let err: Cont::Error;
+        match ctrl.tag {
+            Ctrl::ApplyContinuation => {
+                match cont.tag {
+                    Cont::Binop2 => {
+                        let (operator, evaled_arg, continuation) = unhash3(cont);
+                        match operator.val {
+                            Symbol("set-member") | Symbol("set-insert") | Symbol("set-union") => {
+                                match evaled_arg.tag {
+                                    Expr::Nil | Expr::Set => {
+                                        let (set) = to_set(evaled_arg);
+                                        match operator.val {
+                                            Symbol("set-member") => {
+                                                let (member) = set_member(set, result);
+                                                return (member, env, continuation, makethunk)
+                                            }
+                                            Symbol("set-insert") => {
+                                                let (set) = set_insert(set, result);
+                                                return (set, env, continuation, makethunk)
+                                            }
+                                            Symbol("set-union") => {
+                                                match result.tag {
+                                                    Expr::Nil | Expr::Set => {
+                                                        let (other) = to_set(result);
+                                                        let (set) = set_union(set, other);
+                                                        return (set, env, continuation, makethunk)
+                                                    }
+                                                };
+                                                return (result, env, err, errctrl)
+                                            }
+                                        }
+                                    }
+                                };
+                                return (result, env, err, errctrl)
+                            }
+                        };
+                        return (result, env, cont, ctrl)
+                    }
+                };
+                return (result, env, cont, ctrl)
+            }
+        };
+        return (result, env, cont, ctrl)
+    })
+}
+
+fn allow_all() -> Func {
+    func!(allow_all(expr, env, cont): 3 => {
+        return (expr, env, cont)
+    })
+}
+
+/// Sends applications of the commitment operators to the error continuation
+/// before they are reduced
+fn forbid_commitments() -> Func {
+    func!(forbid_commitments(expr, env, cont): 3 => {
+        let err: Cont::Error;
+        match cont.tag {
+            Cont::Terminal | Cont::Error => {
+                return (expr, env, cont)
+            }
+        };
+        match expr.tag {
+            Expr::Cons => {
+                let (head, _rest) = unhash2(expr);
+                match head.val {
+                    Symbol("commit")
+                    | Symbol("comm")
+                    | Symbol("hide")
+                    | Symbol("open")
+                    | Symbol("secret") => {
+                        return (expr, env, err)
+                    }
+                };
+                return (expr, env, cont)
+            }
+        };
         return (expr, env, cont)
     })
 }
@@ -41,7 +514,7 @@ fn safe_uncons() -> Func {
     })
 }
 
-fn reduce() -> Func {
+fn reduce(ratios: bool, sets: bool) -> Func {
     // Auxiliary functions
     let safe_uncons = safe_uncons();
     let env_to_use = func!(env_to_use(smaller_env, smaller_rec_env): 1 => {
@@ -129,12 +602,51 @@ fn reduce() -> Func {
             | Symbol("<")
             | Symbol(">")
             | Symbol("<=")
-            | Symbol(">=") => {
+            | Symbol(">=")
+            | Symbol("checked-div")
+            | Symbol("checked-u64-add")
+            | Symbol("checked-u64-sub")
+            | Symbol("checked-u64-mul") => {
                 return (t)
             }
         };
         return (nil)
     });
+    let is_binop = if ratios {
+        let is_other_binop = is_binop;
+        func!(is_binop(head): 1 => {
+            let t = Symbol("t");
+            match head.val {
+                Symbol("ratio")
+                | Symbol("ratio+")
+                | Symbol("ratio-")
+                | Symbol("ratio*")
+                | Symbol("ratio/")
+                | Symbol("ratio<") => {
+                    return (t)
+                }
+            };
+            let (op) = is_other_binop(head);
+            return (op)
+        })
+    } else {
+        is_binop
+    };
+    let is_binop = if sets {
+        let is_other_binop = is_binop;
+        func!(is_binop(head): 1 => {
+            let t = Symbol("t");
+            match head.val {
+                Symbol("set-member") | Symbol("set-insert") | Symbol("set-union") => {
+                    return (t)
+                }
+            };
+            let (op) = is_other_binop(head);
+            return (op)
+        })
+    } else {
+        is_binop
+    };
     let is_potentially_fun = func!(is_potentially_fun(head): 1 => {
         let t = Symbol("t");
         let nil = Symbol("nil");
@@ -418,7 +930,7 @@ fn reduce() -> Func {
     })
 }
 
-fn apply_cont() -> Func {
+fn apply_cont(strict_arithmetic: bool) -> Func {
     let safe_uncons = safe_uncons();
     let make_tail_continuation = func!(make_tail_continuation(env, continuation): 1 => {
         match continuation.tag {
@@ -486,6 +998,35 @@ fn apply_cont() -> Func {
         };
         return (other)
     });
+    // Strict arithmetic only accepts arguments of the same numeric type
+    let args_num_type = if strict_arithmetic {
+        func!(args_num_type(arg1, arg2): 1 => {
+            let other = Num(0);
+            match arg1.tag {
+                Expr::Num => {
+                    match arg2.tag {
+                        Expr::Num => {
+                            let ret = Num(1);
+                            return (ret)
+                        }
+                    };
+                    return (other)
+                }
+                Expr::U64 => {
+                    match arg2.tag {
+                        Expr::U64 => {
+                            let ret = Num(2);
+                            return (ret)
+                        }
+                    };
+                    return (other)
+                }
+            };
+            return (other)
+        })
+    } else {
+        args_num_type
+    };
     func!(apply_cont(result, env, cont, ctrl): 4 => {
         // Useful constants
         let ret: Ctrl::Return;
@@ -846,6 +1387,113 @@ fn apply_cont() -> Func {
                                 };
                                 return (result, env, err, errctrl)
                             }
+                            Symbol("checked-div") => {
+                                // Error codes: 0 for non-numeric arguments, 1 for a zero divisor
+                                let ok_sym = Symbol("ok");
+                                let err_sym = Symbol("err");
+                                match args_num_type.val {
+                                    Num(0) => {
+                                        let res: Expr::Cons = hash2(err_sym, zero);
+                                        return (res, env, continuation, makethunk)
+                                    }
+                                };
+                                let divisor_is_zero = eq_val(result, zero);
+                                match divisor_is_zero.val {
+                                    Num(1) => {
+                                        let code = Num(1);
+                                        let res: Expr::Cons = hash2(err_sym, code);
+                                        return (res, env, continuation, makethunk)
+                                    }
+                                };
+                                match args_num_type.val {
+                                    Num(1) => {
+                                        let val = div(evaled_arg, result);
+                                        let res: Expr::Cons = hash2(ok_sym, val);
+                                        return (res, env, continuation, makethunk)
+                                    }
+                                    Num(2) => {
+                                        let (div, _rem) = div_rem64(evaled_arg, result);
+                                        let div = cast(div, Expr::U64);
+                                        let res: Expr::Cons = hash2(ok_sym, div);
+                                        return (res, env, continuation, makethunk)
+                                    }
+                                }
+                            }
+                            Symbol("checked-u64-add") => {
+                                // Error codes: 0 for non-u64 arguments, 2 for an overflow
+                                let ok_sym = Symbol("ok");
+                                let err_sym = Symbol("err");
+                                match args_num_type.val {
+                                    Num(2) => {
+                                        let val = add(evaled_arg, result);
+                                        let not_overflow = lt(val, size_u64);
+                                        match not_overflow.val {
+                                            Num(0) => {
+                                                let code = Num(2);
+                                                let res: Expr::Cons = hash2(err_sym, code);
+                                                return (res, env, continuation, makethunk)
+                                            }
+                                            Num(1) => {
+                                                let val = cast(val, Expr::U64);
+                                                let res: Expr::Cons = hash2(ok_sym, val);
+                                                return (res, env, continuation, makethunk)
+                                            }
+                                        }
+                                    }
+                                };
+                                let res: Expr::Cons = hash2(err_sym, zero);
+                                return (res, env, continuation, makethunk)
+                            }
+                            Symbol("checked-u64-sub") => {
+                                // Error codes: 0 for non-u64 arguments, 2 for an underflow
+                                let ok_sym = Symbol("ok");
+                                let err_sym = Symbol("err");
+                                match args_num_type.val {
+                                    Num(2) => {
+                                        let val = sub(evaled_arg, result);
+                                        let is_neg = lt(val, zero);
+                                        match is_neg.val {
+                                            Num(0) => {
+                                                let val = cast(val, Expr::U64);
+                                                let res: Expr::Cons = hash2(ok_sym, val);
+                                                return (res, env, continuation, makethunk)
+                                            }
+                                            Num(1) => {
+                                                let code = Num(2);
+                                                let res: Expr::Cons = hash2(err_sym, code);
+                                                return (res, env, continuation, makethunk)
+                                            }
+                                        }
+                                    }
+                                };
+                                let res: Expr::Cons = hash2(err_sym, zero);
+                                return (res, env, continuation, makethunk)
+                            }
+                            Symbol("checked-u64-mul") => {
+                                // Error codes: 0 for non-u64 arguments, 2 for an overflow
+                                let ok_sym = Symbol("ok");
+                                let err_sym = Symbol("err");
+                                match args_num_type.val {
+                                    Num(2) => {
+                                        let val = mul(evaled_arg, result);
+                                        let not_overflow = lt(val, size_u64);
+                                        match not_overflow.val {
+                                            Num(0) => {
+                                                let code = Num(2);
+                                                let res: Expr::Cons = hash2(err_sym, code);
+                                                return (res, env, continuation, makethunk)
+                                            }
+                                            Num(1) => {
+                                                let val = cast(val, Expr::U64);
+                                                let res: Expr::Cons = hash2(ok_sym, val);
+                                                return (res, env, continuation, makethunk)
+                                            }
+                                        }
+                                    }
+                                };
+                                let res: Expr::Cons = hash2(err_sym, zero);
+                                return (res, env, continuation, makethunk)
+                            }
                             Symbol("=") => {
                                 match args_num_type.val {
                                     Num(0) => {
@@ -964,21 +1612,29 @@ fn make_thunk() -> Func {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::lem::{pointers::Ptr, slot::SlotsCounter, store::Store, Tag};
+    use crate::lem::ratio::{RatioHint, RATIO_HINT};
+    use crate::lem::slot::SlotsCounter;
+    use crate::lem::{SetKey, SET_KEY};
     use crate::state::{lurk_sym, State};
     use crate::tag::ContTag::*;
+    use bellpepper::util_cs::witness_cs::WitnessCS;
     use bellpepper_core::{test_cs::TestConstraintSystem, Comparable};
     use blstrs::Scalar as Fr;
+    use std::sync::Arc;
 
     const NUM_INPUTS: usize = 1;
-    const NUM_AUX: usize = 9885;
-    const NUM_CONSTRAINTS: usize = 12178;
+    const NUM_AUX: usize = 9359;
+    const NUM_CONSTRAINTS: usize = 11856;
     const NUM_SLOTS: SlotsCounter = SlotsCounter {
         hash2: 16,
         hash3: 4,
         hash4: 2,
+        hash6: 0,
+        hash8: 0,
         commitment: 1,
         less_than: 1,
+        bit_decomp: 1,
+        range: 0,
     };
 
     fn test_eval_and_constrain_aux(store: &mut Store<Fr>, pairs: Vec<(Ptr<Fr>, Ptr<Fr>)>) {
@@ -987,6 +1643,8 @@ mod tests {
         assert_eq!(eval_step.slot, NUM_SLOTS);
 
         let computed_num_constraints = eval_step.num_constraints::<Fr>(store);
+        let witness_generator = eval_step.witness_generator::<Fr>(store).unwrap();
+        assert_eq!(witness_generator.num_aux(), NUM_AUX);
 
         let mut all_paths = vec![];
 
@@ -1015,6 +1673,17 @@ mod tests {
                 let num_constraints = cs.num_constraints();
                 assert_eq!(computed_num_constraints, num_constraints);
                 assert_eq!(num_constraints, NUM_CONSTRAINTS);
+
+                // The witness pass assigns the same values, and can stand in
+                // for the full synthesis of witness generators
+                let witness = witness_generator.generate(store, frame).unwrap();
+                assert_eq!(witness.scalar_inputs(), cs.scalar_inputs());
+                assert_eq!(witness.scalar_aux(), cs.scalar_aux());
+                let mut wcs = WitnessCS::new();
+                eval_step
+                    .synthesize_with_witness(&mut wcs, store, frame, Some(&witness))
+                    .unwrap();
+                assert_eq!(wcs.scalar_aux(), witness.scalar_aux());
                 // TODO: assert uniformity with `Delta` from bellperson
             }
             all_paths.extend(paths);
@@ -1075,6 +1744,18 @@ mod tests {
                 (sum (build 10)))",
         );
         let fold_res = read("55");
+        let checked_div = read("(checked-div 70u64 8u64)");
+        let checked_div_res = read("(ok . 8u64)");
+        let checked_div_zero = read("(checked-div 7 0)");
+        let checked_div_zero_res = read("(err . 1)");
+        let checked_add = read("(checked-u64-add 18446744073709551615u64 1u64)");
+        let checked_add_res = read("(err . 2)");
+        let checked_sub = read("(checked-u64-sub 3u64 5u64)");
+        let checked_sub_res = read("(err . 2)");
+        let checked_mul = read("(checked-u64-mul 3u64 5u64)");
+        let checked_mul_res = read("(ok . 15u64)");
+        let checked_type = read("(checked-u64-add 1 2u64)");
+        let checked_type_res = read("(err . 0)");
         vec![
             (div, div_res),
             (rem, rem_res),
@@ -1096,9 +1777,57 @@ mod tests {
             (lam0, lam0_res),
             (lam, lam_res),
             (fold, fold_res),
+            (checked_div, checked_div_res),
+            (checked_div_zero, checked_div_zero_res),
+            (checked_add, checked_add_res),
+            (checked_sub, checked_sub_res),
+            (checked_mul, checked_mul_res),
+            (checked_type, checked_type_res),
         ]
     }
 
+    #[test]
+    fn test_measured_constraints() {
+        let store = &mut Store::<Fr>::default();
+        let eval_step = eval_step();
+        eval_step.assert_num_constraints(store);
+        assert_eq!(
+            eval_step.measured_constraints(store).unwrap(),
+            NUM_CONSTRAINTS
+        );
+    }
+
+    #[test]
+    fn test_merged_returns() {
+        let store = &mut Store::<Fr>::default();
+        let eval_step = eval_step();
+        let merged = eval_step.clone().with_merged_returns();
+        merged.assert_num_constraints(store);
+        let num_constraints = eval_step.num_constraints(store);
+        let merged_num_constraints = merged.num_constraints(store);
+        assert!(
+            merged_num_constraints < num_constraints,
+            "merging returns costs {merged_num_constraints} constraints, against {num_constraints}"
+        );
+
+        let terminal = Ptr::null(Tag::Cont(Terminal));
+        let error = Ptr::null(Tag::Cont(Error));
+        let stop_cond = |output: &[Ptr<Fr>]| output[2] == terminal || output[2] == error;
+        let nil = store.intern_symbol(&lurk_sym("nil"));
+        let outermost = Ptr::null(Tag::Cont(Outermost));
+        for (expr_in, _) in expr_in_expr_out_pairs(store) {
+            let input = vec![expr_in, nil, outermost];
+            let (frames, _) = merged.call_until(input, store, stop_cond).unwrap();
+            store.hydrate_z_cache();
+            for frame in &frames {
+                let mut cs = TestConstraintSystem::<Fr>::new();
+                merged.synthesize(&mut cs, store, frame).unwrap();
+                assert!(cs.is_satisfied());
+                assert_eq!(cs.num_constraints(), merged_num_constraints);
+            }
+        }
+    }
+
     #[test]
     fn test_pairs() {
         let mut store = Store::default();
@@ -1106,4 +1835,376 @@ mod tests {
         store.hydrate_z_cache();
         test_eval_and_constrain_aux(&mut store, pairs);
     }
+
+    #[test]
+    fn test_eval_config() {
+        assert_eq!(
+            eval_step_with(&EvalConfig::standard()).unwrap(),
+            eval_step()
+        );
+        let with_coprocessor = EvalConfig {
+            coprocessors: vec!["sha256".into()],
+            ..EvalConfig::standard()
+        };
+        assert!(eval_step_with(&with_coprocessor).is_err());
+        assert_eq!(with_coprocessor.key(), with_coprocessor.flags());
+        assert!(with_coprocessor.key().ends_with("-coproc-sha256"));
+        assert_ne!(EvalConfig::standard().key(), EvalConfig::pure().key());
+
+        let pure = eval_step_with(&EvalConfig::pure()).unwrap();
+        let store = &mut Store::<Fr>::default();
+        let state = State::init_lurk_state().rccell();
+        let outermost = Ptr::null(Tag::Cont(Outermost));
+        let terminal = Ptr::null(Tag::Cont(Terminal));
+        let error = Ptr::null(Tag::Cont(Error));
+        let nil = store.intern_symbol(&lurk_sym("nil"));
+        let stop_cond = |output: &[Ptr<Fr>]| output[2] == terminal || output[2] == error;
+        for (code, cont_out) in [
+            ("(+ 1 2)", terminal),
+            ("(+ 1 2u64)", error),
+            ("(< 1u64 2)", error),
+            ("(commit 1)", error),
+            ("(let ((x (hide 1 2))) x)", error),
+            ("'(commit 1)", terminal),
+        ] {
+            let expr = store.read(state.clone(), code).unwrap();
+            let input = vec![expr, nil, outermost];
+            let (frames, _) = pure.call_until(input, store, stop_cond).unwrap();
+            assert_eq!(frames.last().unwrap().output[2], cont_out, "{code}");
+            store.hydrate_z_cache();
+            for frame in frames.iter() {
+                let mut cs = TestConstraintSystem::<Fr>::new();
+                pure.synthesize(&mut cs, store, frame).unwrap();
+                assert!(cs.is_satisfied());
+            }
+        }
+    }
+
+    #[test]
+    fn test_metering() {
+        let config = EvalConfig {
+            metering: true,
+            ..EvalConfig::standard()
+        };
+        let standard = EvalConfig::standard();
+        assert_ne!(
+            config.fingerprint().unwrap(),
+            standard.fingerprint().unwrap()
+        );
+        assert_ne!(config.key(), standard.key());
+        assert!(standard
+            .key()
+            .ends_with(&standard.fingerprint().unwrap()[..16]));
+        let step = eval_step_with(&config).unwrap();
+        step.assert_num_constraints(&mut Store::<Fr>::default());
+
+        let store = &mut Store::<Fr>::default();
+        let state = State::init_lurk_state().rccell();
+        let outermost = Ptr::null(Tag::Cont(Outermost));
+        let terminal = Ptr::null(Tag::Cont(Terminal));
+        let error = Ptr::null(Tag::Cont(Error));
+        let nil = store.intern_symbol(&lurk_sym("nil"));
+        let stop_cond = |output: &[Ptr<Fr>]| output[2] == terminal || output[2] == error;
+        let expr = store.read(state, "(let ((x 1)) (+ x 2))").unwrap();
+        let input = vec![expr, nil, outermost, Ptr::num(Fr::from(0))];
+        let (frames, _) = step.call_until(input, store, stop_cond).unwrap();
+        let output = &frames.last().unwrap().output;
+        assert_eq!(output[2], terminal);
+        let iterations = frames.len() as u64;
+        assert_eq!(output[3], Ptr::num(Fr::from(iterations)));
+
+        // a padding frame leaves the count alone
+        let (padding, _) = step.call_until(output.clone(), store, |_| true).unwrap();
+        assert_eq!(padding[0].output[3], Ptr::num(Fr::from(iterations)));
+
+        store.hydrate_z_cache();
+        for frame in frames.iter().chain(&padding) {
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            step.synthesize(&mut cs, store, frame).unwrap();
+            assert!(cs.is_satisfied());
+        }
+    }
+
+    #[test]
+    fn test_ratios() {
+        let config = EvalConfig {
+            ratios: true,
+            ..EvalConfig::standard()
+        };
+        assert_ne!(config.key(), EvalConfig::standard().key());
+        let step = eval_step_with(&config).unwrap();
+        let store = &mut Store::<Fr>::default();
+        store.register_coproc(RATIO_HINT, Arc::new(RatioHint));
+        step.assert_num_constraints(store);
+
+        let state = State::init_lurk_state().rccell();
+        let outermost = Ptr::null(Tag::Cont(Outermost));
+        let terminal = Ptr::null(Tag::Cont(Terminal));
+        let error = Ptr::null(Tag::Cont(Error));
+        let nil = store.intern_symbol(&lurk_sym("nil"));
+        let t = store.intern_symbol(&lurk_sym("t"));
+        let stop_cond = |output: &[Ptr<Fr>]| output[2] == terminal || output[2] == error;
+        let half = "(ratio 3u64 6u64)";
+        let third = "(ratio 1u64 3u64)";
+        for (code, expr_out) in [
+            (half.to_string(), Some(store.intern_ratio(1, 2).unwrap())),
+            (
+                format!("(ratio+ {half} {third})"),
+                Some(store.intern_ratio(5, 6).unwrap()),
+            ),
+            (
+                format!("(ratio- {half} {third})"),
+                Some(store.intern_ratio(1, 6).unwrap()),
+            ),
+            (
+                format!("(ratio* {half} {third})"),
+                Some(store.intern_ratio(1, 6).unwrap()),
+            ),
+            (
+                format!("(ratio/ {half} {third})"),
+                Some(store.intern_ratio(3, 2).unwrap()),
+            ),
+            (format!("(ratio< {third} {half})"), Some(t)),
+            (format!("(ratio< {half} {third})"), Some(nil)),
+            (format!("(ratio- {third} {half})"), None),
+            (format!("(ratio/ {half} (ratio 0u64 1u64))"), None),
+            ("(ratio 1u64 0u64)".to_string(), None),
+            ("(ratio 1 2u64)".to_string(), None),
+            (format!("(ratio+ {half} 1u64)"), None),
+        ] {
+            let expr = store.read(state.clone(), &code).unwrap();
+            let input = vec![expr, nil, outermost];
+            let (frames, _) = step.call_until(input, store, stop_cond).unwrap();
+            let output = &frames.last().unwrap().output;
+            match expr_out {
+                Some(expr_out) => {
+                    assert_eq!(output[0], expr_out, "{code}");
+                    assert_eq!(output[2], terminal, "{code}");
+                }
+                None => assert_eq!(output[2], error, "{code}"),
+            }
+            store.hydrate_z_cache();
+            for frame in frames.iter() {
+                let mut cs = TestConstraintSystem::<Fr>::new();
+                step.synthesize(&mut cs, store, frame).unwrap();
+                assert!(cs.is_satisfied(), "{code}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_sets() {
+        let config = EvalConfig {
+            set_depth: Some(2),
+            ..EvalConfig::standard()
+        };
+        assert_ne!(config.key(), EvalConfig::standard().key());
+        let step = eval_step_with(&config).unwrap();
+        let store = &mut Store::<Fr>::default();
+        store.register_coproc(SET_KEY, Arc::new(SetKey));
+        step.assert_num_constraints(store);
+
+        // two numbers whose keys differ in their lowest bit, so that their set
+        // is one level deep
+        let first = store.set_key(&Ptr::num(Fr::from(1))).unwrap()[0];
+        let other = (2u64..)
+            .find(|n| store.set_key(&Ptr::num(Fr::from(*n))).unwrap()[0] != first)
+            .unwrap();
+        let (one, other_ptr) = (Ptr::num(Fr::from(1)), Ptr::num(Fr::from(other)));
+        let both = store.intern_set(&[one, other_ptr]).unwrap();
+        let only_one = store.intern_set(&[one]).unwrap();
+        let empty = store.intern_set(&[]).unwrap();
+
+        let state = State::init_lurk_state().rccell();
+        let outermost = Ptr::null(Tag::Cont(Outermost));
+        let terminal = Ptr::null(Tag::Cont(Terminal));
+        let error = Ptr::null(Tag::Cont(Error));
+        let nil = store.intern_symbol(&lurk_sym("nil"));
+        let t = store.intern_symbol(&lurk_sym("t"));
+        let stop_cond = |output: &[Ptr<Fr>]| output[2] == terminal || output[2] == error;
+        let with_one = "(set-insert nil 1)";
+        let with_other = format!("(set-insert nil {other})");
+        for (code, expr_out) in [
+            (with_one.to_string(), Some(only_one)),
+            (format!("(set-insert {with_one} {other})"), Some(both)),
+            (format!("(set-insert {with_one} 1)"), Some(only_one)),
+            (format!("(set-union {with_one} {with_other})"), Some(both)),
+            (format!("(set-union {with_other} {with_one})"), Some(both)),
+            ("(set-union nil nil)".to_string(), Some(empty)),
+            (format!("(set-member {with_one} 1)"), Some(t)),
+            (format!("(set-member {with_one} {other})"), Some(nil)),
+            ("(set-member nil 1)".to_string(), Some(nil)),
+            ("(set-insert 1 1)".to_string(), None),
+            (format!("(set-union {with_one} 1)"), None),
+        ] {
+            let expr = store.read(state.clone(), &code).unwrap();
+            let input = vec![expr, nil, outermost];
+            let (frames, _) = step.call_until(input, store, stop_cond).unwrap();
+            let output = &frames.last().unwrap().output;
+            match expr_out {
+                Some(expr_out) => {
+                    assert_eq!(output[0], expr_out, "{code}");
+                    assert_eq!(output[2], terminal, "{code}");
+                }
+                None => assert_eq!(output[2], error, "{code}"),
+            }
+            store.hydrate_z_cache();
+            for frame in frames.iter() {
+                let mut cs = TestConstraintSystem::<Fr>::new();
+                step.synthesize(&mut cs, store, frame).unwrap();
+                assert!(cs.is_satisfied(), "{code}");
+            }
+        }
+    }
+
+    #[test]
+    fn evaluation_runs_out_of_gas() {
+        let store = &mut Store::<Fr>::default();
+        let state = State::init_lurk_state().rccell();
+        let nil = store.intern_symbol(&lurk_sym("nil"));
+        let out_of_gas = Ptr::null(Tag::Cont(OutOfGas));
+
+        let looping = "(letrec ((loop (lambda (x) (loop x)))) (loop 1))";
+        let looping = store.read(state.clone(), looping).unwrap();
+        let (frames, output, halt) =
+            evaluate_within(looping, nil, store, Budget::steps(50)).unwrap();
+        assert_eq!(frames.len(), 50);
+        assert_eq!(output[2], out_of_gas);
+        assert!(matches!(halt, Halt::OutOfGas(halt) if halt.steps == 50));
+
+        // running out of gas is deterministic
+        let budget = Budget {
+            steps: 1000,
+            gas: 5000,
+        };
+        let (frames, _, halt) = evaluate_within(looping, nil, store, budget).unwrap();
+        let Halt::OutOfGas(halted) = halt else {
+            panic!("{halt:?}")
+        };
+        assert_eq!(halted.steps, frames.len());
+        assert!(halted.gas <= budget.gas);
+        assert_eq!(
+            evaluate_within(looping, nil, store, budget).unwrap().2,
+            halt
+        );
+
+        let expr = store.read(state.clone(), "(+ 1 2)").unwrap();
+        let (frames, output, halt) =
+            evaluate_within(expr, nil, store, Budget::unlimited()).unwrap();
+        assert_eq!(output[0], store.read(state, "3").unwrap());
+        assert_eq!(output[2], Ptr::null(Tag::Cont(Terminal)));
+        let Halt::Stopped { gas } = halt else {
+            panic!("{halt:?}")
+        };
+        let budget = Budget {
+            steps: frames.len(),
+            gas,
+        };
+        let (_, _, halt) = evaluate_within(expr, nil, store, budget).unwrap();
+        assert_eq!(halt, Halt::Stopped { gas });
+        let budget = Budget {
+            gas: gas - 1,
+            ..budget
+        };
+        let (frames, output, _) = evaluate_within(expr, nil, store, budget).unwrap();
+        assert_eq!(output[..2], frames.last().unwrap().output[..2]);
+        assert_eq!(output[2], out_of_gas);
+    }
+
+    #[test]
+    fn frames_are_streamed() {
+        let store = &mut Store::<Fr>::default();
+        let state = State::init_lurk_state().rccell();
+        let nil = store.intern_symbol(&lurk_sym("nil"));
+        let expr = "(letrec ((sum (lambda (n) (if (= n 0) 0 (+ n (sum (- n 1))))))) (sum 5))";
+        let expr = store.read(state.clone(), expr).unwrap();
+        let (frames, output, _) = evaluate_within(expr, nil, store, Budget::unlimited()).unwrap();
+
+        assert_eq!(output[0], store.read(state, "15").unwrap());
+
+        // streamed frames can be proven one at a time
+        let step = eval_step();
+        let mut streamed = 0;
+        let mut stream = evaluate_stream(expr, nil, store);
+        while let Some(frame) = stream.next() {
+            let (frame, _) = frame.unwrap();
+            assert_eq!(frame.input, frames[streamed].input);
+            assert_eq!(frame.output, frames[streamed].output);
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            step.synthesize(&mut cs, stream.store(), &frame).unwrap();
+            assert!(cs.is_satisfied());
+            streamed += 1;
+        }
+        assert_eq!(streamed, frames.len());
+
+        // streams stop on errors and on the stop condition
+        let input = vec![nil, nil, Ptr::null(Tag::Cont(Outermost))];
+        let mut stream = step.stream_until(input, store, |_| true);
+        assert!(stream.next().unwrap().is_ok());
+        assert!(stream.next().is_none());
+        let num = func!(num(x): 1 => {
+            match x.tag {
+                Expr::Num => {
+                    return (x)
+                }
+            }
+        });
+        let mut stream = num.stream_until(vec![nil], store, |_| false);
+        assert!(stream.next().unwrap().is_err());
+        assert!(stream.next().is_none());
+    }
+
+    #[test]
+    fn evaluations_are_resumed_from_checkpoints() {
+        let store = &mut Store::<Fr>::default();
+        let state = State::init_lurk_state().rccell();
+        let nil = store.intern_symbol(&lurk_sym("nil"));
+        let expr = "(let ((c (commit 40)))
+                      (letrec ((sum (lambda (n) (if (= n 0) (open c) (+ n (sum (- n 1)))))))
+                        (sum 2)))";
+        let expr = store.read(state, expr).unwrap();
+        let (frames, output, _) = evaluate_within(expr, nil, store, Budget::unlimited()).unwrap();
+        assert_eq!(output[0], Ptr::num(Fr::from(43)));
+
+        let mut stream = evaluate_stream(expr, nil, store);
+        for _ in 0..10 {
+            stream.next().unwrap().unwrap();
+        }
+        let checkpoint = stream.checkpoint().unwrap().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("eval.checkpoint");
+        checkpoint.write(&path).unwrap();
+
+        // the commitment opened at the end isn't in the new store until the
+        // checkpoint restores it
+        let store = &mut Store::<Fr>::default();
+        let checkpoint = EvalCheckpoint::read(&path).unwrap();
+        assert_eq!(checkpoint.frames_done, 10);
+        let step = eval_step();
+        let mut stream = resume_evaluation(&checkpoint, store).unwrap();
+        let mut last = None;
+        while let Some(frame) = stream.next() {
+            let (frame, _) = frame.unwrap();
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            step.synthesize(&mut cs, stream.store(), &frame).unwrap();
+            assert!(cs.is_satisfied());
+            last = Some(frame);
+        }
+        assert_eq!(stream.frames_done(), frames.len());
+        assert!(stream.checkpoint().unwrap().is_none());
+        assert_eq!(last.unwrap().output[0], Ptr::num(Fr::from(43)));
+
+        // checkpoints only resume runs of the function they were taken on,
+        // even if another one has its name
+        let num = func!(num(x): 1 => {
+            return (x)
+        });
+        assert!(num.resume_stream(&checkpoint, store, |_| true).is_err());
+        let impostor = func!(step(expr, env, cont): 3 => {
+            return (expr, env, cont)
+        });
+        assert!(impostor
+            .resume_stream(&checkpoint, store, |_| true)
+            .is_err());
+    }
 }
@@ -0,0 +1,99 @@
+//! Foreign addresses for LEM.
+//!
+//! The reader reads `#scheme:id` as a foreign address, such as a content ID or
+//! a URL, which the `Store` interns as an opaque commitment: a `Comm` pointer
+//! whose hash commits to the address but that can't be opened, since the store
+//! doesn't know what it commits to. Programs can pass such addresses around
+//! and compare them, and their proofs commit to the addresses they used.
+//!
+//! An application resolves the addresses of a scheme off-chain by registering
+//! a `ForeignResolver` for it with `Store::register_resolver`. The resolver
+//! reads an address as a digest, which is the hash of its commitment, and may
+//! fetch what it commits to so programs can open it. The store only adds a
+//! fetched commitment if it hashes to the digest, so a resolver can't make an
+//! address commit to anything but what its identifier names.
+
+use anyhow::Result;
+use std::fmt::Debug;
+
+use crate::field::LurkField;
+
+use super::{pointers::Ptr, store::Store};
+
+/// Resolves the foreign addresses of a scheme
+pub trait ForeignResolver<F: LurkField>: Debug + Send + Sync {
+    /// The hash of the commitment `#scheme:id` is read as, which only depends
+    /// on `id`
+    fn digest(&self, id: &str) -> Result<F>;
+
+    /// The secret and the payload of the commitment `#scheme:id`, interned in
+    /// `store`, or `None` if it's left opaque
+    fn fetch(&self, store: &mut Store<F>, id: &str) -> Result<Option<(F, Ptr<F>)>>;
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::bail;
+    use blstrs::Scalar as Fr;
+    use ff::Field;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::field::FWrap;
+    use crate::state::State;
+
+    /// Resolves addresses by looking up their digest and content in a
+    /// directory, as if they were content IDs
+    #[derive(Debug)]
+    struct Directory(HashMap<String, (Fr, String)>);
+
+    impl ForeignResolver<Fr> for Directory {
+        fn digest(&self, id: &str) -> Result<Fr> {
+            match self.0.get(id) {
+                Some((digest, _)) => Ok(*digest),
+                None => bail!("Unknown id {id}"),
+            }
+        }
+
+        fn fetch(&self, store: &mut Store<Fr>, id: &str) -> Result<Option<(Fr, Ptr<Fr>)>> {
+            Ok(self
+                .0
+                .get(id)
+                .map(|(_, content)| (Fr::ZERO, store.intern_string(content))))
+        }
+    }
+
+    #[test]
+    fn foreign_addresses_are_opaque_until_resolved() {
+        let store = &mut Store::<Fr>::default();
+        let state = State::init_lurk_state().rccell();
+        let cid = store.read(state.clone(), "#cid:abc").unwrap();
+        let address = store.intern_string("cid:abc");
+        let Ptr::Leaf(_, hash) = cid else {
+            panic!("Foreign addresses are leaves")
+        };
+        assert_eq!(cid, Ptr::comm(store.hash_comm(Fr::ZERO, &address).unwrap()));
+        assert!(!store.comms.contains_key(&FWrap(hash)));
+
+        // the store of the non-LEM evaluator agrees on the hash
+        let old_store = &mut crate::store::Store::<Fr>::default();
+        let old_cid = old_store.intern_foreign("cid", "abc");
+        assert_eq!(old_store.hash_expr(&old_cid).unwrap().value(), &hash);
+
+        let content = store.intern_string("ABC");
+        let digest = store.hash_comm(Fr::ZERO, &content).unwrap();
+        let directory = HashMap::from([
+            ("abc".to_string(), (digest, "ABC".to_string())),
+            ("lie".to_string(), (digest, "XYZ".to_string())),
+        ]);
+        store.register_resolver("cid", Arc::new(Directory(directory)));
+        let cid = store.read(state.clone(), "#cid:abc").unwrap();
+        assert_eq!(cid, Ptr::comm(digest));
+        let (_, content) = store.comms[&FWrap(digest)];
+        assert_eq!(store.fetch_string(&content).unwrap(), "ABC");
+
+        // content that doesn't hash to the digest is rejected
+        assert!(store.read(state, "#cid:lie").is_err());
+    }
+}
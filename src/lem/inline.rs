@@ -0,0 +1,260 @@
+//! Inlining of calls to small functions.
+//!
+//! Besides the constraints of the function it calls, a call takes constraints
+//! of its own: synthesis allocates the outputs of the call and every return of
+//! the function equates them to the values it returns, with two constraints
+//! per output (more when returns are merged). A function without branches
+//! returns once, so a call to it can be replaced by its operations, reading the
+//! arguments for its parameters, if the caller then reads the returned
+//! variables for the outputs of the call. Variables are bound once per
+//! function, the functions called included, so no renaming is needed other
+//! than that.
+//!
+//! `Func::inline_calls` does so for the functions with at most a given number
+//! of operations. The inlined operations take the slots the call took, but on
+//! the caller's path, so a function that shares slots can share them with the
+//! inlined operations as well. Slot counting, interpretation and synthesis all
+//! follow the inlined function, so `Func::num_constraints` stays exact.
+
+use std::collections::HashMap;
+
+use super::{Block, Ctrl, Func, Op, Var};
+
+/// The calls to a function that `Func::inline_calls` inlined
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InlineReport {
+    pub func: String,
+    pub calls: usize,
+    /// The constraints equating the outputs of the calls to the returned
+    /// values, which inlining saves when returns aren't merged
+    pub saved_constraints: usize,
+}
+
+impl Func {
+    /// Returns an equivalent function in which the calls to functions without
+    /// branches and with `max_ops` operations at most are inlined, along with
+    /// a report for each function inlined. Like `Func::minimize_slots`, it
+    /// changes the shape of the circuit, so it's up to the caller to apply it.
+    pub fn inline_calls(&self, max_ops: usize) -> (Func, Vec<InlineReport>) {
+        let mut reports = vec![];
+        let func = self.inline_calls_with(max_ops, &mut reports);
+        (func, reports)
+    }
+
+    fn inline_calls_with(&self, max_ops: usize, reports: &mut Vec<InlineReport>) -> Func {
+        let body =
+            self.body
+                .clone()
+                .inline_calls(max_ops, self.share_slots, &HashMap::new(), reports);
+        Func {
+            slot: body.count_slots_sharing(self.share_slots),
+            body,
+            ..self.clone()
+        }
+    }
+
+    /// Whether calls to the function can be inlined into a function that
+    /// shares slots if `share_slots` is true
+    fn is_inlinable(&self, max_ops: usize, share_slots: bool) -> bool {
+        matches!(self.body.ctrl, Ctrl::Return(..))
+            && self.body.ops.len() <= max_ops
+            // the operations would take more slots if they stopped sharing them
+            && (share_slots || !self.share_slots)
+    }
+}
+
+/// Replaces the variables in `renames` by the ones they're mapped to
+fn rename(var: &mut Var, renames: &HashMap<Var, Var>) {
+    if let Some(renamed) = renames.get(var) {
+        *var = renamed.clone();
+    }
+}
+
+impl Block {
+    /// Inlines the calls of the block, and of the functions it calls, after
+    /// renaming the variables of `renames` that the block reads
+    fn inline_calls(
+        self,
+        max_ops: usize,
+        share_slots: bool,
+        renames: &HashMap<Var, Var>,
+        reports: &mut Vec<InlineReport>,
+    ) -> Block {
+        let Block { ops, mut ctrl } = self;
+        let mut renames = renames.clone();
+        let mut inlined = Vec::with_capacity(ops.len());
+        for mut op in ops {
+            for src in op.sources_mut() {
+                rename(src, &renames);
+            }
+            let Op::Call(out, func, inp) = op else {
+                inlined.push(op);
+                continue;
+            };
+            // the callee's own calls first, so that its size is final
+            let func = func.inline_calls_with(max_ops, reports);
+            if !func.is_inlinable(max_ops, share_slots) {
+                inlined.push(Op::Call(out, Box::new(func), inp));
+                continue;
+            }
+            let Ctrl::Return(rets) = &func.body.ctrl else {
+                unreachable!("inlined functions have no branches")
+            };
+            let params = func.input_params.iter().cloned().zip(inp).collect();
+            for mut op in func.body.ops.iter().cloned() {
+                for src in op.sources_mut() {
+                    rename(src, &params);
+                }
+                inlined.push(op);
+            }
+            for (out, ret) in out.into_iter().zip(rets) {
+                let mut ret = ret.clone();
+                rename(&mut ret, &params);
+                renames.insert(out, ret);
+            }
+            match reports.iter_mut().find(|report| report.func == func.name) {
+                Some(report) => {
+                    report.calls += 1;
+                    report.saved_constraints += 2 * func.output_size;
+                }
+                None => reports.push(InlineReport {
+                    func: func.name.clone(),
+                    calls: 1,
+                    saved_constraints: 2 * func.output_size,
+                }),
+            }
+        }
+        match &mut ctrl {
+            Ctrl::MatchTag(var, ..) | Ctrl::MatchVal(var, ..) | Ctrl::MatchSymbol(var, ..) => {
+                rename(var, &renames)
+            }
+            Ctrl::IfEq(x, y, ..) => {
+                rename(x, &renames);
+                rename(y, &renames);
+            }
+            Ctrl::Return(vars) => vars.iter_mut().for_each(|var| rename(var, &renames)),
+        }
+        for branch in ctrl.branches_mut() {
+            let block = std::mem::replace(
+                branch,
+                Block {
+                    ops: vec![],
+                    ctrl: Ctrl::Return(vec![]),
+                },
+            );
+            *branch = block.inline_calls(max_ops, share_slots, &renames, reports);
+        }
+        Block { ops: inlined, ctrl }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        func,
+        lem::{eval::eval_step, interpreter::Preimages, pointers::Ptr, store::Store, Tag},
+        state::{lurk_sym, State},
+        tag::ContTag::{Error, Outermost, Terminal},
+    };
+    use bellpepper_core::test_cs::TestConstraintSystem;
+    use blstrs::Scalar as Fr;
+
+    #[test]
+    fn calls_to_small_functions_are_inlined() {
+        let pair = func!(pair(a, b): 1 => {
+            let x: Expr::Cons = hash2(a, b);
+            return (x)
+        });
+        let first = func!(first(x): 1 => {
+            let (a, _b) = unhash2(x);
+            return (a)
+        });
+        let lem = func!(outer(a, b): 2 => {
+            let (x) = pair(a, b);
+            let (y) = first(x);
+            match y.tag {
+                Expr::Num => {
+                    return (x, y)
+                }
+            };
+            return (y, x)
+        });
+        assert_eq!(lem.inline_calls(0).1, []);
+        let (inlined, reports) = lem.inline_calls(1);
+        assert_eq!(reports.len(), 2);
+        assert!(reports.iter().all(|report| report.saved_constraints == 2));
+        assert!(!inlined.body.ops.iter().any(|op| matches!(op, Op::Call(..))));
+        assert_eq!(inlined.slot, lem.slot);
+
+        let store = &mut Store::<Fr>::default();
+        let args = vec![Ptr::num(Fr::from(1)), Ptr::num(Fr::from(2))];
+        let (frame, _) = lem
+            .call(args.clone(), store, Preimages::new_from_func(&lem))
+            .unwrap();
+        let (inlined_frame, _) = inlined
+            .call(args.clone(), store, Preimages::new_from_func(&inlined))
+            .unwrap();
+        assert_eq!(frame.output, inlined_frame.output);
+        store.hydrate_z_cache();
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        inlined.synthesize(&mut cs, store, &inlined_frame).unwrap();
+        assert!(cs.is_satisfied());
+        assert_eq!(
+            lem.num_constraints(store) - inlined.num_constraints(store),
+            4
+        );
+        inlined.assert_num_constraints(store);
+
+        // the unhash shares the slot of the hash once both are in `outer`
+        let shared = lem.with_shared_slots();
+        let (inlined, _) = shared.inline_calls(1);
+        assert_eq!(shared.slot.hash2, 2);
+        assert_eq!(inlined.slot.hash2, 1);
+        let (frame, _) = inlined
+            .call(args, store, Preimages::new_from_func(&inlined))
+            .unwrap();
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        inlined.synthesize(&mut cs, store, &frame).unwrap();
+        assert!(cs.is_satisfied());
+        inlined.assert_num_constraints(store);
+    }
+
+    #[test]
+    fn calls_of_the_step_function_are_inlined() {
+        let store = &mut Store::<Fr>::default();
+        let step = eval_step();
+        let (inlined, reports) = step.inline_calls(0);
+        // `allow_all` only returns its arguments
+        assert!(reports.iter().any(|report| report.func == "allow_all"));
+        let saved: usize = reports.iter().map(|report| report.saved_constraints).sum();
+        assert_eq!(
+            step.num_constraints(store) - inlined.num_constraints(store),
+            saved
+        );
+        inlined.assert_num_constraints(store);
+
+        let state = State::init_lurk_state().rccell();
+        let nil = store.intern_symbol(&lurk_sym("nil"));
+        let outermost = Ptr::null(Tag::Cont(Outermost));
+        let terminal = Ptr::null(Tag::Cont(Terminal));
+        let error = Ptr::null(Tag::Cont(Error));
+        let stop_cond = |output: &[Ptr<Fr>]| output[2] == terminal || output[2] == error;
+        let expr = store
+            .read(state, "(let ((f (lambda (x) (+ x 1)))) (f 2))")
+            .unwrap();
+        let input = vec![expr, nil, outermost];
+        let (frames, _) = step.call_until(input.clone(), store, stop_cond).unwrap();
+        let (inlined_frames, _) = inlined.call_until(input, store, stop_cond).unwrap();
+        assert_eq!(
+            frames.last().unwrap().output,
+            inlined_frames.last().unwrap().output
+        );
+        store.hydrate_z_cache();
+        for frame in &inlined_frames {
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            inlined.synthesize(&mut cs, store, frame).unwrap();
+            assert!(cs.is_satisfied());
+        }
+    }
+}
@@ -1,33 +1,48 @@
 use crate::field::{FWrap, LurkField};
 use crate::num::Num;
 use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::VecDeque;
 
 use super::{
-    path::Path, pointers::Ptr, store::Store, var_map::VarMap, Block, Ctrl, Func, Lit, Op, Tag,
+    checkpoint::EvalCheckpoint, path::Path, pointers::Ptr, slot::SlotMemo, store::Store,
+    var_map::VarMap, Block, Ctrl, Func, Lit, Op, Tag, HASH_ARITIES,
 };
 
 use crate::tag::ExprTag::*;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum PreimageData<F: LurkField> {
     PtrVec(Vec<Ptr<F>>),
     FPtr(F, Ptr<F>),
     FPair(F, F),
+    F(F),
 }
 
-#[derive(Clone, Debug, Default)]
-/// `Preimages` hold the non-deterministic advices for hashes and `Func` calls.
-/// The hash preimages must have the same shape as the allocated slots for the
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+/// `Preimages` hold the non-deterministic advices for hashes, `Func` calls and
+/// coprocessors.
+/// The hash preimages must follow the order of the allocated slots for the
 /// `Func`, and the `None` values are used to fill the unused slots, which are
-/// later filled by dummy values.
+/// later filled by dummy values. Unused slots at the end can be left out
+/// altogether (see `Preimages::prune`).
 pub struct Preimages<F: LurkField> {
     pub hash2: Vec<Option<PreimageData<F>>>,
     pub hash3: Vec<Option<PreimageData<F>>>,
     pub hash4: Vec<Option<PreimageData<F>>>,
+    pub hash6: Vec<Option<PreimageData<F>>>,
+    pub hash8: Vec<Option<PreimageData<F>>>,
     pub commitment: Vec<Option<PreimageData<F>>>,
     pub less_than: Vec<Option<PreimageData<F>>>,
+    pub bit_decomp: Vec<Option<PreimageData<F>>>,
+    pub range: Vec<Option<PreimageData<F>>>,
     pub call_outputs: VecDeque<Vec<Ptr<F>>>,
+    pub coproc_outputs: VecDeque<Vec<Ptr<F>>>,
+    /// The values emitted so far, which are only recorded when tracing (see
+    /// `Func::trace_until`)
+    #[serde(skip)]
+    pub emitted: Option<Vec<Ptr<F>>>,
 }
 
 impl<F: LurkField> Preimages<F> {
@@ -36,16 +51,90 @@ impl<F: LurkField> Preimages<F> {
         let hash2 = Vec::with_capacity(slot.hash2);
         let hash3 = Vec::with_capacity(slot.hash3);
         let hash4 = Vec::with_capacity(slot.hash4);
+        let hash6 = Vec::with_capacity(slot.hash6);
+        let hash8 = Vec::with_capacity(slot.hash8);
         let commitment = Vec::with_capacity(slot.commitment);
         let less_than = Vec::with_capacity(slot.less_than);
+        let bit_decomp = Vec::with_capacity(slot.bit_decomp);
+        let range = Vec::with_capacity(slot.range);
         let call_outputs = VecDeque::new();
+        let coproc_outputs = VecDeque::new();
         Preimages {
             hash2,
             hash3,
             hash4,
+            hash6,
+            hash8,
             commitment,
             less_than,
+            bit_decomp,
+            range,
             call_outputs,
+            coproc_outputs,
+            emitted: None,
+        }
+    }
+
+    /// Preimages with every slot of `func` left unused
+    pub fn blank(func: &Func) -> Preimages<F> {
+        let slot = func.slot;
+        Preimages {
+            hash2: vec![None; slot.hash2],
+            hash3: vec![None; slot.hash3],
+            hash4: vec![None; slot.hash4],
+            hash6: vec![None; slot.hash6],
+            hash8: vec![None; slot.hash8],
+            commitment: vec![None; slot.commitment],
+            less_than: vec![None; slot.less_than],
+            bit_decomp: vec![None; slot.bit_decomp],
+            range: vec![None; slot.range],
+            call_outputs: VecDeque::new(),
+            coproc_outputs: VecDeque::new(),
+            emitted: None,
+        }
+    }
+
+    /// Drops the unused slots that aren't followed by any used slot of the
+    /// same type. Frames on cheap paths thus don't carry a `None` for every
+    /// slot they skip.
+    pub fn prune(&mut self) {
+        fn prune_slots<F: LurkField>(slots: &mut Vec<Option<PreimageData<F>>>) {
+            while let Some(None) = slots.last() {
+                slots.pop();
+            }
+        }
+        prune_slots(&mut self.hash2);
+        prune_slots(&mut self.hash3);
+        prune_slots(&mut self.hash4);
+        prune_slots(&mut self.hash6);
+        prune_slots(&mut self.hash8);
+        prune_slots(&mut self.commitment);
+        prune_slots(&mut self.less_than);
+        prune_slots(&mut self.bit_decomp);
+        prune_slots(&mut self.range);
+    }
+
+    /// The preimages for the slots of the hashes of `arity`, which must be one
+    /// of `HASH_ARITIES`
+    pub fn hash(&self, arity: usize) -> &[Option<PreimageData<F>>] {
+        match arity {
+            2 => &self.hash2,
+            3 => &self.hash3,
+            4 => &self.hash4,
+            6 => &self.hash6,
+            8 => &self.hash8,
+            _ => panic!("Unsupported hash arity {arity}"),
+        }
+    }
+
+    pub fn hash_mut(&mut self, arity: usize) -> &mut Vec<Option<PreimageData<F>>> {
+        match arity {
+            2 => &mut self.hash2,
+            3 => &mut self.hash3,
+            4 => &mut self.hash4,
+            6 => &mut self.hash6,
+            8 => &mut self.hash8,
+            _ => panic!("Unsupported hash arity {arity}"),
         }
     }
 }
@@ -55,13 +144,169 @@ impl<F: LurkField> Preimages<F> {
 /// running one iteration as a HashMap of variables to pointers.
 ///
 /// This information is used to generate the witness.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Frame<F: LurkField> {
     pub input: Vec<Ptr<F>>,
     pub output: Vec<Ptr<F>>,
     pub preimages: Preimages<F>,
 }
 
+impl<F: LurkField> Frame<F> {
+    /// A frame that doesn't come from interpreting `func`, with dummy inputs
+    /// and outputs and every slot unused. It's only good for synthesizing the
+    /// shape of the circuit, since the witness it gives rise to is
+    /// unsatisfiable.
+    pub fn blank(func: &Func) -> Frame<F> {
+        let dummy = Ptr::null(Tag::Expr(Nil));
+        Frame {
+            input: vec![dummy; func.input_params.len()],
+            output: vec![dummy; func.output_size],
+            preimages: Preimages::blank(func),
+        }
+    }
+}
+
+/// The resources a run of a function over several frames may use: `steps`
+/// frames at most, whose gas, as measured by `Func::gas`, adds up to `gas` at
+/// most
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Budget {
+    pub steps: usize,
+    pub gas: u64,
+}
+
+impl Budget {
+    /// A budget that doesn't limit the run
+    pub fn unlimited() -> Self {
+        Self {
+            steps: usize::MAX,
+            gas: u64::MAX,
+        }
+    }
+
+    /// A budget of `steps` frames, whatever their gas
+    pub fn steps(steps: usize) -> Self {
+        Self {
+            steps,
+            ..Self::unlimited()
+        }
+    }
+}
+
+/// A run ran out of its budget after `steps` frames of `gas` gas in all,
+/// since the next frame would have exceeded it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutOfGas {
+    pub steps: usize,
+    pub gas: u64,
+}
+
+impl std::fmt::Display for OutOfGas {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Out of gas after {} steps and {} gas",
+            self.steps, self.gas
+        )
+    }
+}
+
+/// How a run with a budget ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Halt {
+    /// The stop condition held on the output of the last frame, and the frames
+    /// took `gas` gas in all
+    Stopped {
+        gas: u64,
+    },
+    OutOfGas(OutOfGas),
+}
+
+/// The frames of a function called on the outputs of its previous call, as
+/// `Func::call_until` makes them, but computed one at a time as the iterator
+/// is advanced, so they needn't all be held in memory at once. It ends after
+/// the frame whose output satisfies the stop condition, or after an error.
+pub struct FrameStream<'a, F: LurkField, Stop> {
+    func: Cow<'a, Func>,
+    store: &'a mut Store<F>,
+    args: Option<Vec<Ptr<F>>>,
+    stop_cond: Stop,
+    frames_done: usize,
+}
+
+impl<'a, F: LurkField, Stop: Fn(&[Ptr<F>]) -> bool> FrameStream<'a, F, Stop> {
+    pub(crate) fn new(
+        func: Cow<'a, Func>,
+        args: Vec<Ptr<F>>,
+        store: &'a mut Store<F>,
+        stop_cond: Stop,
+    ) -> Self {
+        assert_eq!(func.input_params.len(), func.output_size);
+        assert_eq!(args.len(), func.input_params.len());
+        Self {
+            func,
+            store,
+            args: Some(args),
+            stop_cond,
+            frames_done: 0,
+        }
+    }
+
+    /// Goes on with the frames after `checkpoint`, after restoring its part of
+    /// the store in `store`
+    pub(crate) fn resume(
+        func: Cow<'a, Func>,
+        checkpoint: &EvalCheckpoint<F>,
+        store: &'a mut Store<F>,
+        stop_cond: Stop,
+    ) -> Result<Self> {
+        checkpoint.check_func(&func)?;
+        let args = checkpoint.restore(store)?;
+        let mut stream = Self::new(func, args, store, stop_cond);
+        stream.frames_done = checkpoint.frames_done;
+        Ok(stream)
+    }
+
+    /// The number of frames computed so far, including those before the
+    /// checkpoint the stream was resumed from
+    pub fn frames_done(&self) -> usize {
+        self.frames_done
+    }
+
+    /// The checkpoint to resume the stream from, before its next frame, or
+    /// `None` if it ended
+    pub fn checkpoint(&self) -> Result<Option<EvalCheckpoint<F>>> {
+        self.args
+            .as_ref()
+            .map(|args| EvalCheckpoint::new(&self.func, self.frames_done, args, self.store))
+            .transpose()
+    }
+
+    /// The store the frames are interned in
+    pub fn store(&self) -> &Store<F> {
+        self.store
+    }
+}
+
+impl<F: LurkField, Stop: Fn(&[Ptr<F>]) -> bool> Iterator for FrameStream<'_, F, Stop> {
+    type Item = Result<(Frame<F>, Path)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let args = self.args.take()?;
+        let preimages = Preimages::new_from_func(&self.func);
+        let (mut frame, path) = match self.func.call(args, self.store, preimages) {
+            Ok(res) => res,
+            Err(e) => return Some(Err(e)),
+        };
+        frame.preimages.prune();
+        self.frames_done += 1;
+        if !(self.stop_cond)(&frame.output) {
+            self.args = Some(frame.output.clone());
+        }
+        Some(Ok((frame, path)))
+    }
+}
+
 impl Block {
     /// Interprets a LEM while i) modifying a `Store`, ii) binding `Var`s to
     /// `Ptr`s and iii) collecting the preimages from visited slots (more on this
@@ -73,6 +318,7 @@ impl Block {
         mut bindings: VarMap<Ptr<F>>,
         mut preimages: Preimages<F>,
         mut path: Path,
+        mut memo: SlotMemo,
     ) -> Result<(Frame<F>, Path)> {
         for op in &self.ops {
             match op {
@@ -196,6 +442,7 @@ impl Block {
                     assert!(*n <= 64);
                     let a = bindings.get(a)?;
                     let c = if let Ptr::Leaf(_, f) = a {
+                        preimages.bit_decomp.push(Some(PreimageData::F(*f)));
                         let b = if *n < 64 { (1 << *n) - 1 } else { u64::MAX };
                         Ptr::Leaf(Tag::Expr(Num), F::from_u64(f.to_u64_unchecked() & b))
                     } else {
@@ -203,6 +450,42 @@ impl Block {
                     };
                     bindings.insert(tgt.clone(), c);
                 }
+                Op::AssertRange(a, n) => {
+                    assert!(*n <= 64);
+                    let Ptr::Leaf(_, f) = bindings.get(a)? else {
+                        bail!("`AssertRange` only works on leaves")
+                    };
+                    if f.to_u64().map_or(true, |f| *n < 64 && f >> *n != 0) {
+                        bail!("{} doesn't fit in {n} bits", f.hex_digits())
+                    }
+                    preimages.range.push(Some(PreimageData::F(*f)));
+                }
+                Op::AssertU32(a) | Op::AssertChar(a) => {
+                    let (name, n) = match op {
+                        Op::AssertU32(_) => ("AssertU32", 32),
+                        _ => ("AssertChar", 21),
+                    };
+                    let Ptr::Leaf(_, f) = bindings.get(a)? else {
+                        bail!("`{name}` only works on leaves")
+                    };
+                    if f.to_u64().map_or(true, |f| f >> n != 0) {
+                        bail!("{} doesn't fit in {n} bits", f.hex_digits())
+                    }
+                }
+                Op::And(tgt, a, b) | Op::Or(tgt, a, b) | Op::Xor(tgt, a, b) => {
+                    let a = bindings.get(a)?;
+                    let b = bindings.get(b)?;
+                    let c = if let (Ptr::Leaf(_, f), Ptr::Leaf(_, g)) = (a, b) {
+                        preimages.bit_decomp.push(Some(PreimageData::F(*f)));
+                        preimages.bit_decomp.push(Some(PreimageData::F(*g)));
+                        let f = f.to_u64_unchecked();
+                        let g = g.to_u64_unchecked();
+                        Ptr::Leaf(Tag::Expr(Num), F::from_u64(op.bitwise_u64(f, g)))
+                    } else {
+                        bail!("Bitwise operations only work on leaves")
+                    };
+                    bindings.insert(tgt.clone(), c);
+                }
                 Op::DivRem64(tgt, a, b) => {
                     let a = bindings.get(a)?;
                     let b = bindings.get(b)?;
@@ -221,88 +504,54 @@ impl Block {
                     bindings.insert(tgt[0].clone(), c1);
                     bindings.insert(tgt[1].clone(), c2);
                 }
+                Op::AddI64(tgt, a, b)
+                | Op::SubI64(tgt, a, b)
+                | Op::MulI64(tgt, a, b)
+                | Op::LtI64(tgt, a, b) => {
+                    let a = bindings.get(a)?;
+                    let b = bindings.get(b)?;
+                    let (Ptr::Leaf(_, f), Ptr::Leaf(_, g)) = (a, b) else {
+                        bail!("Signed operations only work on leaves")
+                    };
+                    let (Some(f), Some(g)) = (f.to_u64(), g.to_u64()) else {
+                        bail!("Signed operations only work on 64 bits")
+                    };
+                    let c = Ptr::Leaf(Tag::Expr(Num), F::from_u64(op.signed_i64(f, g)));
+                    bindings.insert(tgt.clone(), c);
+                }
                 Op::Emit(a) => {
                     let a = bindings.get(a)?;
+                    if let Some(emitted) = &mut preimages.emitted {
+                        emitted.push(*a);
+                    }
                     println!("{}", a.dbg_display(store))
                 }
-                Op::Hash2(img, tag, preimg) => {
+                Op::Hash(img, tag, preimg) => {
                     let preimg_ptrs = bindings.get_many_cloned(preimg)?;
-                    let tgt_ptr = store.intern_2_ptrs(*tag, preimg_ptrs[0], preimg_ptrs[1]);
+                    let tgt_ptr = store.intern_ptrs(*tag, &preimg_ptrs)?;
                     bindings.insert(img.clone(), tgt_ptr);
-                    preimages
-                        .hash2
-                        .push(Some(PreimageData::PtrVec(preimg_ptrs)));
-                }
-                Op::Hash3(img, tag, preimg) => {
-                    let preimg_ptrs = bindings.get_many_cloned(preimg)?;
-                    let tgt_ptr =
-                        store.intern_3_ptrs(*tag, preimg_ptrs[0], preimg_ptrs[1], preimg_ptrs[2]);
-                    bindings.insert(img.clone(), tgt_ptr);
-                    preimages
-                        .hash3
-                        .push(Some(PreimageData::PtrVec(preimg_ptrs)));
-                }
-                Op::Hash4(img, tag, preimg) => {
-                    let preimg_ptrs = bindings.get_many_cloned(preimg)?;
-                    let tgt_ptr = store.intern_4_ptrs(
-                        *tag,
-                        preimg_ptrs[0],
-                        preimg_ptrs[1],
-                        preimg_ptrs[2],
-                        preimg_ptrs[3],
-                    );
-                    bindings.insert(img.clone(), tgt_ptr);
-                    preimages
-                        .hash4
-                        .push(Some(PreimageData::PtrVec(preimg_ptrs)));
-                }
-                Op::Unhash2(preimg, img) => {
-                    let img_ptr = bindings.get(img)?;
-                    let Some(idx) = img_ptr.get_index2() else {
-                        bail!("{img} isn't a Tree2 pointer");
-                    };
-                    let Some((a, b)) = store.fetch_2_ptrs(idx) else {
-                        bail!("Couldn't fetch {img}'s children")
-                    };
-                    let preimg_ptrs = [*a, *b];
-                    for (var, ptr) in preimg.iter().zip(preimg_ptrs.iter()) {
-                        bindings.insert(var.clone(), *ptr);
+                    let slots = preimages.hash_mut(preimg.len());
+                    let (_, shared) = memo.hash_slot(op, || slots.len());
+                    if !shared {
+                        slots.push(Some(PreimageData::PtrVec(preimg_ptrs)));
                     }
-                    preimages
-                        .hash2
-                        .push(Some(PreimageData::PtrVec(preimg_ptrs.to_vec())));
                 }
-                Op::Unhash3(preimg, img) => {
+                Op::Unhash(preimg, img) => {
                     let img_ptr = bindings.get(img)?;
-                    let Some(idx) = img_ptr.get_index3() else {
-                        bail!("{img} isn't a Tree3 pointer");
-                    };
-                    let Some((a, b, c)) = store.fetch_3_ptrs(idx) else {
-                        bail!("Couldn't fetch {img}'s children")
-                    };
-                    let preimg_ptrs = [*a, *b, *c];
-                    for (var, ptr) in preimg.iter().zip(preimg_ptrs.iter()) {
-                        bindings.insert(var.clone(), *ptr);
+                    if img_ptr.arity() != preimg.len() {
+                        bail!("{img} isn't a Tree{} pointer", preimg.len());
                     }
-                    preimages
-                        .hash3
-                        .push(Some(PreimageData::PtrVec(preimg_ptrs.to_vec())));
-                }
-                Op::Unhash4(preimg, img) => {
-                    let img_ptr = bindings.get(img)?;
-                    let Some(idx) = img_ptr.get_index4() else {
-                        bail!("{img} isn't a Tree4 pointer");
-                    };
-                    let Some((a, b, c, d)) = store.fetch_4_ptrs(idx) else {
+                    let Some(preimg_ptrs) = store.fetch_ptrs(img_ptr) else {
                         bail!("Couldn't fetch {img}'s children")
                     };
-                    let preimg_ptrs = [*a, *b, *c, *d];
                     for (var, ptr) in preimg.iter().zip(preimg_ptrs.iter()) {
                         bindings.insert(var.clone(), *ptr);
                     }
-                    preimages
-                        .hash4
-                        .push(Some(PreimageData::PtrVec(preimg_ptrs.to_vec())));
+                    let slots = preimages.hash_mut(preimg.len());
+                    let (_, shared) = memo.hash_slot(op, || slots.len());
+                    if !shared {
+                        slots.push(Some(PreimageData::PtrVec(preimg_ptrs)));
+                    }
                 }
                 Op::Hide(tgt, sec, src) => {
                     let src_ptr = bindings.get(src)?;
@@ -334,6 +583,25 @@ impl Block {
                         .commitment
                         .push(Some(PreimageData::FPtr(*secret, *ptr)))
                 }
+                Op::Coproc(name, out, inp) => {
+                    let coproc = store.coproc(name)?;
+                    if inp.len() != coproc.arity() || out.len() != coproc.output_size() {
+                        bail!(
+                            "Coprocessor {name} takes {} arguments and returns {} values",
+                            coproc.arity(),
+                            coproc.output_size()
+                        )
+                    }
+                    let inp_ptrs = bindings.get_many_cloned(inp)?;
+                    let out_ptrs = coproc.evaluate(store, &inp_ptrs)?;
+                    if out_ptrs.len() != out.len() {
+                        bail!("Coprocessor {name} returned {} values", out_ptrs.len())
+                    }
+                    for (var, ptr) in out.iter().zip(out_ptrs.iter()) {
+                        bindings.insert(var.clone(), *ptr);
+                    }
+                    preimages.coproc_outputs.push_back(out_ptrs);
+                }
             }
         }
         match &self.ctrl {
@@ -343,12 +611,12 @@ impl Block {
                 match cases.get(tag) {
                     Some(block) => {
                         path.push_tag_inplace(tag);
-                        block.run(input, store, bindings, preimages, path)
+                        block.run(input, store, bindings, preimages, path, memo)
                     }
                     None => {
                         path.push_default_inplace();
                         match def {
-                            Some(def) => def.run(input, store, bindings, preimages, path),
+                            Some(def) => def.run(input, store, bindings, preimages, path, memo),
                             None => bail!("No match for tag {}", tag),
                         }
                     }
@@ -361,33 +629,55 @@ impl Block {
                     // of the cases, which are all interned
                     path.push_default_inplace();
                     match def {
-                        Some(def) => return def.run(input, store, bindings, preimages, path),
+                        Some(def) => return def.run(input, store, bindings, preimages, path, memo),
                         None => bail!("No match for literal"),
                     }
                 };
                 match cases.get(&lit) {
                     Some(block) => {
                         path.push_lit_inplace(&lit);
-                        block.run(input, store, bindings, preimages, path)
+                        block.run(input, store, bindings, preimages, path, memo)
                     }
                     None => {
                         path.push_default_inplace();
                         match def {
-                            Some(def) => def.run(input, store, bindings, preimages, path),
+                            Some(def) => def.run(input, store, bindings, preimages, path, memo),
                             None => bail!("No match for literal {:?}", lit),
                         }
                     }
                 }
             }
+            Ctrl::MatchSymbol(match_var, cases, def) => {
+                let ptr = bindings.get(match_var)?;
+                if ptr.tag() != &Tag::Expr(Sym) {
+                    bail!("`MatchSymbol` only works on symbols, not on {}", ptr.tag())
+                }
+                let Some(sym) = store.fetch_symbol(ptr) else {
+                    bail!("Couldn't fetch the symbol matched by `MatchSymbol`")
+                };
+                match cases.get(&sym) {
+                    Some(block) => {
+                        path.push_lit_inplace(&Lit::Symbol(sym));
+                        block.run(input, store, bindings, preimages, path, memo)
+                    }
+                    None => {
+                        path.push_default_inplace();
+                        match def {
+                            Some(def) => def.run(input, store, bindings, preimages, path, memo),
+                            None => bail!("No match for symbol {sym}"),
+                        }
+                    }
+                }
+            }
             Ctrl::IfEq(x, y, eq_block, else_block) => {
                 let x = bindings.get(x)?;
                 let y = bindings.get(y)?;
                 let b = x == y;
                 path.push_bool_inplace(b);
                 if b {
-                    eq_block.run(input, store, bindings, preimages, path)
+                    eq_block.run(input, store, bindings, preimages, path, memo)
                 } else {
-                    else_block.run(input, store, bindings, preimages, path)
+                    else_block.run(input, store, bindings, preimages, path, memo)
                 }
             }
             Ctrl::Return(output_vars) => {
@@ -422,31 +712,29 @@ impl Func {
 
         // We must fill any unused slots with `None` values so we save
         // the initial size of preimages, which might not be zero
-        let hash2_init = preimages.hash2.len();
-        let hash3_init = preimages.hash3.len();
-        let hash4_init = preimages.hash4.len();
+        let hash_init = HASH_ARITIES.map(|arity| preimages.hash(arity).len());
         let commitment_init = preimages.commitment.len();
         let less_than_init = preimages.less_than.len();
+        let bit_decomp_init = preimages.bit_decomp.len();
+        let range_init = preimages.range.len();
 
+        let memo = SlotMemo::new(self.share_slots);
         let mut res = self
             .body
-            .run(args, store, bindings, preimages, Path::default())?;
+            .run(args, store, bindings, preimages, Path::default(), memo)?;
         let preimages = &mut res.0.preimages;
 
-        let hash2_used = preimages.hash2.len() - hash2_init;
-        let hash3_used = preimages.hash3.len() - hash3_init;
-        let hash4_used = preimages.hash4.len() - hash4_init;
         let commitment_used = preimages.commitment.len() - commitment_init;
         let less_than_used = preimages.less_than.len() - less_than_init;
+        let bit_decomp_used = preimages.bit_decomp.len() - bit_decomp_init;
+        let range_used = preimages.range.len() - range_init;
 
-        for _ in hash2_used..self.slot.hash2 {
-            preimages.hash2.push(None);
-        }
-        for _ in hash3_used..self.slot.hash3 {
-            preimages.hash3.push(None);
-        }
-        for _ in hash4_used..self.slot.hash4 {
-            preimages.hash4.push(None);
+        for (arity, init) in HASH_ARITIES.into_iter().zip(hash_init) {
+            let slots = preimages.hash_mut(arity);
+            let used = slots.len() - init;
+            for _ in used..self.slot.hash(arity) {
+                slots.push(None);
+            }
         }
         for _ in commitment_used..self.slot.commitment {
             preimages.commitment.push(None);
@@ -454,6 +742,12 @@ impl Func {
         for _ in less_than_used..self.slot.less_than {
             preimages.less_than.push(None);
         }
+        for _ in bit_decomp_used..self.slot.bit_decomp {
+            preimages.bit_decomp.push(None);
+        }
+        for _ in range_used..self.slot.range {
+            preimages.range.push(None);
+        }
 
         Ok(res)
     }
@@ -462,10 +756,48 @@ impl Func {
     /// iteration as the input of the next one.
     pub fn call_until<F: LurkField, Stop: Fn(&[Ptr<F>]) -> bool>(
         &self,
-        mut args: Vec<Ptr<F>>,
+        args: Vec<Ptr<F>>,
         store: &mut Store<F>,
         stop_cond: Stop,
     ) -> Result<(Vec<Frame<F>>, Vec<Path>)> {
+        let (frames, paths, _) =
+            self.call_until_within(args, store, stop_cond, Budget::unlimited())?;
+        Ok((frames, paths))
+    }
+
+    /// Like `call_until`, but the frames are computed lazily, as the returned
+    /// `FrameStream` is iterated over
+    pub fn stream_until<'a, F: LurkField, Stop: Fn(&[Ptr<F>]) -> bool>(
+        &'a self,
+        args: Vec<Ptr<F>>,
+        store: &'a mut Store<F>,
+        stop_cond: Stop,
+    ) -> FrameStream<'a, F, Stop> {
+        FrameStream::new(Cow::Borrowed(self), args, store, stop_cond)
+    }
+
+    /// Like `stream_until`, but goes on with the frames after `checkpoint`,
+    /// taken from a stream of the same function
+    pub fn resume_stream<'a, F: LurkField, Stop: Fn(&[Ptr<F>]) -> bool>(
+        &'a self,
+        checkpoint: &EvalCheckpoint<F>,
+        store: &'a mut Store<F>,
+        stop_cond: Stop,
+    ) -> Result<FrameStream<'a, F, Stop>> {
+        FrameStream::resume(Cow::Borrowed(self), checkpoint, store, stop_cond)
+    }
+
+    /// Like `call_until`, but stops before the frame that would exceed `budget`
+    /// instead of looping forever on inputs that never satisfy the stop
+    /// condition. The run is deterministic, so it ends the same way whenever
+    /// it's repeated with the same budget.
+    pub fn call_until_within<F: LurkField, Stop: Fn(&[Ptr<F>]) -> bool>(
+        &self,
+        mut args: Vec<Ptr<F>>,
+        store: &mut Store<F>,
+        stop_cond: Stop,
+        budget: Budget,
+    ) -> Result<(Vec<Frame<F>>, Vec<Path>, Halt)> {
         if self.input_params.len() != self.output_size {
             assert_eq!(self.input_params.len(), self.output_size)
         }
@@ -476,14 +808,28 @@ impl Func {
         // Initial path vector and frames
         let mut frames = vec![];
         let mut paths = vec![];
+        let mut gas = 0u64;
 
         loop {
+            let out_of_gas = Halt::OutOfGas(OutOfGas {
+                steps: frames.len(),
+                gas,
+            });
+            if frames.len() >= budget.steps {
+                return Ok((frames, paths, out_of_gas));
+            }
             let preimages = Preimages::new_from_func(self);
-            let (frame, path) = self.call(args, store, preimages)?;
+            let (mut frame, path) = self.call(args, store, preimages)?;
+            let frame_gas = self.gas(&path)?;
+            if gas.saturating_add(frame_gas) > budget.gas {
+                return Ok((frames, paths, out_of_gas));
+            }
+            gas += frame_gas;
+            frame.preimages.prune();
             if stop_cond(&frame.output) {
                 frames.push(frame);
                 paths.push(path);
-                break;
+                return Ok((frames, paths, Halt::Stopped { gas }));
             }
             // Should frames take borrowed vectors instead, as to avoid cloning?
             // Using AVec is a possibility, but to create a dynamic AVec, currently,
@@ -493,6 +839,5 @@ impl Func {
             frames.push(frame);
             paths.push(path);
         }
-        Ok((frames, paths))
     }
 }
@@ -0,0 +1,221 @@
+//! Liveness of LEM variables.
+//!
+//! LEM functions are in SSA form and their blocks form a tree, so a variable
+//! bound in a block can only be read further down that block, in its control
+//! or in the blocks nested in it.
+//!
+//! The variables that are bound but never read are reported by
+//! `Func::dead_bindings`. The constraints and allocations spent on them are
+//! pure waste, which `Func::optimize` removes whenever it's sound.
+
+use std::collections::HashSet;
+
+use super::{Block, Ctrl, Func, Op, Var};
+
+impl Ctrl {
+    /// The variables read by the control or by the blocks it leads to, that
+    /// are bound outside of them
+    fn free_vars(&self) -> HashSet<Var> {
+        match self {
+            Ctrl::Return(vars) => vars.iter().cloned().collect(),
+            Ctrl::MatchTag(var, cases, def) => {
+                let mut free = HashSet::from([var.clone()]);
+                for block in cases.values().chain(def.as_deref()) {
+                    free.extend(block.free_vars());
+                }
+                free
+            }
+            Ctrl::MatchVal(var, cases, def) => {
+                let mut free = HashSet::from([var.clone()]);
+                for block in cases.values().chain(def.as_deref()) {
+                    free.extend(block.free_vars());
+                }
+                free
+            }
+            Ctrl::MatchSymbol(var, cases, def) => {
+                let mut free = HashSet::from([var.clone()]);
+                for block in cases.values().chain(def.as_deref()) {
+                    free.extend(block.free_vars());
+                }
+                free
+            }
+            Ctrl::IfEq(x, y, eq_block, else_block) => {
+                let mut free = HashSet::from([x.clone(), y.clone()]);
+                free.extend(eq_block.free_vars());
+                free.extend(else_block.free_vars());
+                free
+            }
+        }
+    }
+}
+
+impl Block {
+    /// The variables read in the block that are bound outside of it
+    pub(crate) fn free_vars(&self) -> HashSet<Var> {
+        let mut free = self.ctrl.free_vars();
+        for op in self.ops.iter().rev() {
+            for tgt in op.targets() {
+                free.remove(tgt);
+            }
+            free.extend(op.sources().into_iter().cloned());
+        }
+        free
+    }
+
+    /// For each operation of the block, the variables bound in the block that
+    /// are dead once it's done: those it reads for the last time and the
+    /// targets that are never read
+    pub(crate) fn deaths(&self) -> Vec<Vec<Var>> {
+        let bound = self
+            .ops
+            .iter()
+            .flat_map(Op::targets)
+            .collect::<HashSet<_>>();
+        let mut live = self.ctrl.free_vars();
+        let mut deaths = Vec::with_capacity(self.ops.len());
+        for op in self.ops.iter().rev() {
+            let mut dying = vec![];
+            for var in op.targets() {
+                if !live.remove(var) {
+                    dying.push(var.clone());
+                }
+            }
+            for var in op.sources() {
+                if bound.contains(var) && live.insert(var.clone()) {
+                    dying.push(var.clone());
+                }
+            }
+            deaths.push(dying);
+        }
+        deaths.reverse();
+        deaths
+    }
+
+    fn dead_bindings(&self, dead: &mut Vec<Var>) {
+        for (op, dying) in self.ops.iter().zip(self.deaths()) {
+            let targets = op.targets();
+            dead.extend(dying.into_iter().filter(|var| targets.contains(&var)));
+            if let Op::Call(_, func, _) = op {
+                func.body.dead_bindings(dead);
+            }
+        }
+        match &self.ctrl {
+            Ctrl::Return(..) => (),
+            Ctrl::MatchTag(_, cases, def) => {
+                for block in cases.values().chain(def.as_deref()) {
+                    block.dead_bindings(dead);
+                }
+            }
+            Ctrl::MatchVal(_, cases, def) => {
+                for block in cases.values().chain(def.as_deref()) {
+                    block.dead_bindings(dead);
+                }
+            }
+            Ctrl::MatchSymbol(_, cases, def) => {
+                for block in cases.values().chain(def.as_deref()) {
+                    block.dead_bindings(dead);
+                }
+            }
+            Ctrl::IfEq(_, _, eq_block, else_block) => {
+                eq_block.dead_bindings(dead);
+                else_block.dead_bindings(dead);
+            }
+        }
+    }
+}
+
+impl Func {
+    /// The variables bound by the function's operations that are never read,
+    /// in the order they are bound
+    pub fn dead_bindings(&self) -> Vec<Var> {
+        let mut dead = vec![];
+        self.body.dead_bindings(&mut dead);
+        dead
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bellpepper_core::test_cs::TestConstraintSystem;
+    use blstrs::Scalar as Fr;
+
+    use crate::{
+        block,
+        field::LurkField,
+        func,
+        lem::{interpreter::Preimages, pointers::Ptr, store::Store},
+        var,
+    };
+
+    #[test]
+    fn deaths_follow_last_reads() {
+        let block = block!({
+            let one: Expr::Num;
+            let a = add(x, one);
+            let b = add(a, a);
+            let _c = add(b, x);
+            match b.tag {
+                Expr::Num => {
+                    return (b);
+                }
+            }
+        });
+        let deaths = block.deaths();
+        assert!(deaths[0].is_empty());
+        // `one` is bound in the block, unlike `x`
+        assert_eq!(deaths[1], vec![var!(one)]);
+        assert_eq!(deaths[2], vec![var!(a)]);
+        // `b` is still read by the match
+        assert_eq!(deaths[3], vec![var!(_c)]);
+        assert_eq!(block.free_vars(), HashSet::from([var!(x)]));
+    }
+
+    #[test]
+    fn dead_bindings_are_reported() {
+        let lem = func!(f(x): 1 => {
+            let _y = add(x, x);
+            let z: Expr::Num;
+            match x.tag {
+                Expr::Num => {
+                    let _w = mul(x, z);
+                    return (x);
+                }
+            }
+        });
+        let dead = lem.dead_bindings();
+        assert_eq!(dead.len(), 2);
+        assert!(dead[0].name().starts_with("_y#"));
+        assert!(dead[1].name().starts_with("_w#"));
+        assert!(lem.optimize().dead_bindings().is_empty());
+    }
+
+    #[test]
+    fn dropping_dead_bindings_saves_constraints() {
+        let lem = func!(f(x): 1 => {
+            let _y = mul(x, x);
+            let _z = lt(x, x);
+            let _h: Expr::Cons = hash2(x, x);
+            return (x);
+        });
+        let opt = lem.optimize();
+        assert!(opt.body.ops.is_empty());
+
+        let store = &mut Store::<Fr>::default();
+        let x = Ptr::num(Fr::from_u64(3));
+        let measure = |func: &Func, store: &mut Store<Fr>| {
+            let (frame, _) = func
+                .call(vec![x], store, Preimages::new_from_func(func))
+                .unwrap();
+            store.hydrate_z_cache();
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            func.synthesize(&mut cs, store, &frame).unwrap();
+            assert!(cs.is_satisfied());
+            (cs.num_constraints(), cs.aux().len())
+        };
+        let (constraints, aux) = measure(&lem, store);
+        let (opt_constraints, opt_aux) = measure(&opt, store);
+        assert!(opt_constraints < constraints);
+        assert!(opt_aux < aux);
+    }
+}
@@ -32,6 +32,11 @@ macro_rules! tag {
     ( Expr::$tag:ident ) => {
         $crate::lem::Tag::Expr($crate::tag::ExprTag::$tag)
     };
+    ( Cont::User($index:expr) ) => {
+        $crate::lem::Tag::UserCont(
+            $crate::tag::UserContTag::new($index).expect("user continuation tag out of range"),
+        )
+    };
     ( Cont::$tag:ident ) => {
         $crate::lem::Tag::Cont($crate::tag::ContTag::$tag)
     };
@@ -114,6 +119,27 @@ macro_rules! op {
             $b,
         )
     };
+    ( let $tgt:ident = and($a:ident, $b:ident) ) => {
+        $crate::lem::Op::And(
+            $crate::var!($tgt),
+            $crate::var!($a),
+            $crate::var!($b),
+        )
+    };
+    ( let $tgt:ident = or($a:ident, $b:ident) ) => {
+        $crate::lem::Op::Or(
+            $crate::var!($tgt),
+            $crate::var!($a),
+            $crate::var!($b),
+        )
+    };
+    ( let $tgt:ident = xor($a:ident, $b:ident) ) => {
+        $crate::lem::Op::Xor(
+            $crate::var!($tgt),
+            $crate::var!($a),
+            $crate::var!($b),
+        )
+    };
     ( let ($tgt1:ident, $tgt2:ident) = div_rem64($a:ident, $b:ident) ) => {
         $crate::lem::Op::DivRem64(
             $crate::vars!($tgt1, $tgt2),
@@ -121,42 +147,108 @@ macro_rules! op {
             $crate::var!($b),
         )
     };
+    ( let $tgt:ident = add_i64($a:ident, $b:ident) ) => {
+        $crate::lem::Op::AddI64(
+            $crate::var!($tgt),
+            $crate::var!($a),
+            $crate::var!($b),
+        )
+    };
+    ( let $tgt:ident = sub_i64($a:ident, $b:ident) ) => {
+        $crate::lem::Op::SubI64(
+            $crate::var!($tgt),
+            $crate::var!($a),
+            $crate::var!($b),
+        )
+    };
+    ( let $tgt:ident = mul_i64($a:ident, $b:ident) ) => {
+        $crate::lem::Op::MulI64(
+            $crate::var!($tgt),
+            $crate::var!($a),
+            $crate::var!($b),
+        )
+    };
+    ( let $tgt:ident = lt_i64($a:ident, $b:ident) ) => {
+        $crate::lem::Op::LtI64(
+            $crate::var!($tgt),
+            $crate::var!($a),
+            $crate::var!($b),
+        )
+    };
     ( emit($v:ident) ) => {
         $crate::lem::Op::Emit($crate::var!($v))
     };
+    ( assert_range($v:ident, $n:literal) ) => {
+        $crate::lem::Op::AssertRange($crate::var!($v), $n)
+    };
+    ( assert_u32($v:ident) ) => {
+        $crate::lem::Op::AssertU32($crate::var!($v))
+    };
+    ( assert_char($v:ident) ) => {
+        $crate::lem::Op::AssertChar($crate::var!($v))
+    };
     ( let $tgt:ident : $kind:ident::$tag:ident = hash2($src1:ident, $src2:ident) ) => {
-        $crate::lem::Op::Hash2(
+        $crate::lem::Op::Hash(
             $crate::var!($tgt),
             $crate::tag!($kind::$tag),
-            $crate::vars!($src1, $src2),
+            $crate::vars!($src1, $src2).to_vec(),
         )
     };
     ( let $tgt:ident : $kind:ident::$tag:ident = hash3($src1:ident, $src2:ident, $src3:ident) ) => {
-        $crate::lem::Op::Hash3(
+        $crate::lem::Op::Hash(
             $crate::var!($tgt),
             $crate::tag!($kind::$tag),
-            $crate::vars!($src1, $src2, $src3),
+            $crate::vars!($src1, $src2, $src3).to_vec(),
         )
     };
     ( let $tgt:ident : $kind:ident::$tag:ident = hash4($src1:ident, $src2:ident, $src3:ident, $src4:ident) ) => {
-        $crate::lem::Op::Hash4(
+        $crate::lem::Op::Hash(
             $crate::var!($tgt),
             $crate::tag!($kind::$tag),
-            $crate::vars!($src1, $src2, $src3, $src4),
+            $crate::vars!($src1, $src2, $src3, $src4).to_vec(),
+        )
+    };
+    ( let $tgt:ident : $kind:ident::$tag:ident = hash6($src1:ident, $src2:ident, $src3:ident, $src4:ident, $src5:ident, $src6:ident) ) => {
+        $crate::lem::Op::Hash(
+            $crate::var!($tgt),
+            $crate::tag!($kind::$tag),
+            $crate::vars!($src1, $src2, $src3, $src4, $src5, $src6).to_vec(),
+        )
+    };
+    ( let $tgt:ident : $kind:ident::$tag:ident = hash8($src1:ident, $src2:ident, $src3:ident, $src4:ident, $src5:ident, $src6:ident, $src7:ident, $src8:ident) ) => {
+        $crate::lem::Op::Hash(
+            $crate::var!($tgt),
+            $crate::tag!($kind::$tag),
+            $crate::vars!($src1, $src2, $src3, $src4, $src5, $src6, $src7, $src8).to_vec(),
         )
     };
     ( let ($tgt1:ident, $tgt2:ident) = unhash2($src:ident) ) => {
-        $crate::lem::Op::Unhash2(
-            $crate::vars!($tgt1, $tgt2),
+        $crate::lem::Op::Unhash(
+            $crate::vars!($tgt1, $tgt2).to_vec(),
             $crate::var!($src),
         )
     };
     ( let ($tgt1:ident, $tgt2:ident, $tgt3:ident) = unhash3($src:ident) ) => {
-        $crate::lem::Op::Unhash3($crate::vars!($tgt1, $tgt2, $tgt3), $crate::var!($src))
+        $crate::lem::Op::Unhash(
+            $crate::vars!($tgt1, $tgt2, $tgt3).to_vec(),
+            $crate::var!($src),
+        )
     };
     ( let ($tgt1:ident, $tgt2:ident, $tgt3:ident, $tgt4:ident) = unhash4($src:ident) ) => {
-        $crate::lem::Op::Unhash4(
-            $crate::vars!($tgt1, $tgt2, $tgt3, $tgt4),
+        $crate::lem::Op::Unhash(
+            $crate::vars!($tgt1, $tgt2, $tgt3, $tgt4).to_vec(),
+            $crate::var!($src),
+        )
+    };
+    ( let ($tgt1:ident, $tgt2:ident, $tgt3:ident, $tgt4:ident, $tgt5:ident, $tgt6:ident) = unhash6($src:ident) ) => {
+        $crate::lem::Op::Unhash(
+            $crate::vars!($tgt1, $tgt2, $tgt3, $tgt4, $tgt5, $tgt6).to_vec(),
+            $crate::var!($src),
+        )
+    };
+    ( let ($tgt1:ident, $tgt2:ident, $tgt3:ident, $tgt4:ident, $tgt5:ident, $tgt6:ident, $tgt7:ident, $tgt8:ident) = unhash8($src:ident) ) => {
+        $crate::lem::Op::Unhash(
+            $crate::vars!($tgt1, $tgt2, $tgt3, $tgt4, $tgt5, $tgt6, $tgt7, $tgt8).to_vec(),
             $crate::var!($src),
         )
     };
@@ -166,6 +258,20 @@ macro_rules! op {
     ( let ($sec:ident, $src:ident) = open($hash:ident) ) => {
         $crate::lem::Op::Open($crate::var!($sec), $crate::var!($src), $crate::var!($hash))
     };
+    ( let ($($tgt:ident),*) = coproc $name:ident($($arg:ident),*) ) => {
+        {
+            let out = vec!($($crate::var!($tgt)),*);
+            let inp = vec!($($crate::var!($arg)),*);
+            $crate::lem::Op::Coproc(stringify!($name).into(), out, inp)
+        }
+    };
+    ( let ($($tgt:ident),*) = call_dyn $table:ident[$idx:ident]($($arg:ident),*) ) => {
+        {
+            let out = vec!($($crate::var!($tgt)),*);
+            let inp = vec!($($crate::var!($arg)),*);
+            $crate::lem::Op::CallDyn(out, $crate::var!($idx), &$table, inp)
+        }
+    };
     ( let ($($tgt:ident),*) = $func:ident($($arg:ident),*) ) => {
         {
             let out = vec!($($crate::var!($tgt)),*);
@@ -224,6 +330,29 @@ macro_rules! ctrl {
             $crate::lem::Ctrl::MatchVal($crate::var!($sii), cases, default)
         }
     };
+    ( match $sii:ident.symbol { $( $sym:literal $(| $other_sym:literal)* => $case_ops:tt )* } $(; $($def:tt)*)? ) => {
+        {
+            let mut cases = indexmap::IndexMap::new();
+            $(
+                if cases.insert(
+                    $crate::state::lurk_sym(&$sym),
+                    $crate::block!( $case_ops ),
+                ).is_some() {
+                    panic!("Repeated symbol on `match`");
+                };
+                $(
+                    if cases.insert(
+                        $crate::state::lurk_sym(&$other_sym),
+                        $crate::block!( $case_ops ),
+                    ).is_some() {
+                        panic!("Repeated symbol on `match`");
+                    };
+                )*
+            )*
+            let default = None $( .or (Some(Box::new($crate::block!( @seq {}, $($def)* )))) )?;
+            $crate::lem::Ctrl::MatchSymbol($crate::var!($sii), cases, default)
+        }
+    };
     ( if $x:ident == $y:ident { $($true_block:tt)+ } $($false_block:tt)+ ) => {
         {
             let x = $crate::var!($x);
@@ -358,6 +487,36 @@ macro_rules! block {
             $($tail)*
         )
     };
+    (@seq {$($limbs:expr)*}, let $tgt:ident = and($a:ident, $b:ident) ; $($tail:tt)*) => {
+        $crate::block! (
+            @seq
+            {
+                $($limbs)*
+                $crate::op!(let $tgt = and($a, $b))
+            },
+            $($tail)*
+        )
+    };
+    (@seq {$($limbs:expr)*}, let $tgt:ident = or($a:ident, $b:ident) ; $($tail:tt)*) => {
+        $crate::block! (
+            @seq
+            {
+                $($limbs)*
+                $crate::op!(let $tgt = or($a, $b))
+            },
+            $($tail)*
+        )
+    };
+    (@seq {$($limbs:expr)*}, let $tgt:ident = xor($a:ident, $b:ident) ; $($tail:tt)*) => {
+        $crate::block! (
+            @seq
+            {
+                $($limbs)*
+                $crate::op!(let $tgt = xor($a, $b))
+            },
+            $($tail)*
+        )
+    };
     (@seq {$($limbs:expr)*},  let ($tgt1:ident, $tgt2:ident) = div_rem64($a:ident, $b:ident) ; $($tail:tt)*) => {
         $crate::block! (
             @seq
@@ -368,6 +527,46 @@ macro_rules! block {
             $($tail)*
         )
     };
+    (@seq {$($limbs:expr)*}, let $tgt:ident = add_i64($a:ident, $b:ident) ; $($tail:tt)*) => {
+        $crate::block! (
+            @seq
+            {
+                $($limbs)*
+                $crate::op!(let $tgt = add_i64($a, $b))
+            },
+            $($tail)*
+        )
+    };
+    (@seq {$($limbs:expr)*}, let $tgt:ident = sub_i64($a:ident, $b:ident) ; $($tail:tt)*) => {
+        $crate::block! (
+            @seq
+            {
+                $($limbs)*
+                $crate::op!(let $tgt = sub_i64($a, $b))
+            },
+            $($tail)*
+        )
+    };
+    (@seq {$($limbs:expr)*}, let $tgt:ident = mul_i64($a:ident, $b:ident) ; $($tail:tt)*) => {
+        $crate::block! (
+            @seq
+            {
+                $($limbs)*
+                $crate::op!(let $tgt = mul_i64($a, $b))
+            },
+            $($tail)*
+        )
+    };
+    (@seq {$($limbs:expr)*}, let $tgt:ident = lt_i64($a:ident, $b:ident) ; $($tail:tt)*) => {
+        $crate::block! (
+            @seq
+            {
+                $($limbs)*
+                $crate::op!(let $tgt = lt_i64($a, $b))
+            },
+            $($tail)*
+        )
+    };
     (@seq {$($limbs:expr)*}, emit($v:ident) ; $($tail:tt)*) => {
         $crate::block! (
             @seq
@@ -378,6 +577,36 @@ macro_rules! block {
             $($tail)*
         )
     };
+    (@seq {$($limbs:expr)*}, assert_range($v:ident, $n:literal) ; $($tail:tt)*) => {
+        $crate::block! (
+            @seq
+            {
+                $($limbs)*
+                $crate::op!(assert_range($v, $n))
+            },
+            $($tail)*
+        )
+    };
+    (@seq {$($limbs:expr)*}, assert_u32($v:ident) ; $($tail:tt)*) => {
+        $crate::block! (
+            @seq
+            {
+                $($limbs)*
+                $crate::op!(assert_u32($v))
+            },
+            $($tail)*
+        )
+    };
+    (@seq {$($limbs:expr)*}, assert_char($v:ident) ; $($tail:tt)*) => {
+        $crate::block! (
+            @seq
+            {
+                $($limbs)*
+                $crate::op!(assert_char($v))
+            },
+            $($tail)*
+        )
+    };
     (@seq {$($limbs:expr)*}, let $tgt:ident = Num($sym:literal) ; $($tail:tt)*) => {
         $crate::block! (
             @seq
@@ -438,6 +667,26 @@ macro_rules! block {
             $($tail)*
         )
     };
+    (@seq {$($limbs:expr)*}, let $tgt:ident : $kind:ident::$tag:ident = hash6($src1:ident, $src2:ident, $src3:ident, $src4:ident, $src5:ident, $src6:ident) ; $($tail:tt)*) => {
+        $crate::block! (
+            @seq
+            {
+                $($limbs)*
+                $crate::op!(let $tgt: $kind::$tag = hash6($src1, $src2, $src3, $src4, $src5, $src6))
+            },
+            $($tail)*
+        )
+    };
+    (@seq {$($limbs:expr)*}, let $tgt:ident : $kind:ident::$tag:ident = hash8($src1:ident, $src2:ident, $src3:ident, $src4:ident, $src5:ident, $src6:ident, $src7:ident, $src8:ident) ; $($tail:tt)*) => {
+        $crate::block! (
+            @seq
+            {
+                $($limbs)*
+                $crate::op!(let $tgt: $kind::$tag = hash8($src1, $src2, $src3, $src4, $src5, $src6, $src7, $src8))
+            },
+            $($tail)*
+        )
+    };
     (@seq {$($limbs:expr)*}, let ($tgt1:ident, $tgt2:ident) = unhash2($src:ident) ; $($tail:tt)*) => {
         $crate::block! (
             @seq
@@ -468,6 +717,26 @@ macro_rules! block {
             $($tail)*
         )
     };
+    (@seq {$($limbs:expr)*}, let ($tgt1:ident, $tgt2:ident, $tgt3:ident, $tgt4:ident, $tgt5:ident, $tgt6:ident) = unhash6($src:ident) ; $($tail:tt)*) => {
+        $crate::block! (
+            @seq
+            {
+                $($limbs)*
+                $crate::op!(let ($tgt1, $tgt2, $tgt3, $tgt4, $tgt5, $tgt6) = unhash6($src) )
+            },
+            $($tail)*
+        )
+    };
+    (@seq {$($limbs:expr)*}, let ($tgt1:ident, $tgt2:ident, $tgt3:ident, $tgt4:ident, $tgt5:ident, $tgt6:ident, $tgt7:ident, $tgt8:ident) = unhash8($src:ident) ; $($tail:tt)*) => {
+        $crate::block! (
+            @seq
+            {
+                $($limbs)*
+                $crate::op!(let ($tgt1, $tgt2, $tgt3, $tgt4, $tgt5, $tgt6, $tgt7, $tgt8) = unhash8($src) )
+            },
+            $($tail)*
+        )
+    };
     (@seq {$($limbs:expr)*}, let $tgt:ident = hide($sec:ident, $src:ident) ; $($tail:tt)*) => {
         $crate::block! (
             @seq
@@ -488,6 +757,26 @@ macro_rules! block {
             $($tail)*
         )
     };
+    (@seq {$($limbs:expr)*}, let ($($tgt:ident),*) = coproc $name:ident($($arg:ident),*) ; $($tail:tt)*) => {
+        $crate::block! (
+            @seq
+            {
+                $($limbs)*
+                $crate::op!(let ($($tgt),*) = coproc $name($($arg),*))
+            },
+            $($tail)*
+        )
+    };
+    (@seq {$($limbs:expr)*}, let ($($tgt:ident),*) = call_dyn $table:ident[$idx:ident]($($arg:ident),*) ; $($tail:tt)*) => {
+        $crate::block! (
+            @seq
+            {
+                $($limbs)*
+                $crate::op!(let ($($tgt),*) = call_dyn $table[$idx]($($arg),*))
+            },
+            $($tail)*
+        )
+    };
     (@seq {$($limbs:expr)*}, let ($($tgt:ident),*) = $func:ident($($arg:ident),*) ; $($tail:tt)*) => {
         $crate::block! (
             @seq
@@ -517,6 +806,15 @@ macro_rules! block {
             $crate::ctrl!( match $sii.val { $( $cnstr($val) $(| $other_cnstr($other_val))* => $case_ops )* } $(; $($def)*)? )
         )
     };
+    (@seq {$($limbs:expr)*}, match $sii:ident.symbol { $( $sym:literal $(| $other_sym:literal)* => $case_ops:tt )* } $(; $($def:tt)*)?) => {
+        $crate::block! (
+            @end
+            {
+                $($limbs)*
+            },
+            $crate::ctrl!( match $sii.symbol { $( $sym $(| $other_sym)* => $case_ops )* } $(; $($def)*)? )
+        )
+    };
     (@seq {$($limbs:expr)*}, if $x:ident == $y:ident { $($true_block:tt)+ } $($false_block:tt)+ ) => {
         $crate::block! (
             @end
@@ -561,11 +859,16 @@ macro_rules! block {
 #[macro_export]
 macro_rules! func {
     ($name:ident($( $in:ident ),*): $size:expr => $lem:tt) => {
-        $crate::lem::Func::new(
+        $crate::lem::Func::new_at(
             stringify!($name).into(),
             vec![$($crate::var!($in)),*],
             $size,
             $crate::block!($lem),
+            Some($crate::lem::Span {
+                file: file!(),
+                line: line!(),
+                column: column!(),
+            }),
         ).unwrap()
     };
 }
@@ -614,6 +917,7 @@ mod tests {
             ),
             Op::Hide(mptr("bar"), mptr("baz"), mptr("bazz")),
             Op::Open(mptr("bar"), mptr("baz"), mptr("bazz")),
+            Op::Coproc("sq".into(), vec![mptr("bar")], vec![mptr("baz")]),
         ];
         let lemops_macro = vec![
             op!(let foo: Expr::Num),
@@ -625,9 +929,10 @@ mod tests {
             op!(let (foo, goo, moo, noo) = unhash4(aaa)),
             op!(let bar = hide(baz, bazz)),
             op!(let (bar, baz) = open(bazz)),
+            op!(let (bar) = coproc sq(baz)),
         ];
 
-        for i in 0..9 {
+        for i in 0..10 {
             assert!(lemops[i] == lemops_macro[i]);
         }
 
@@ -646,6 +951,7 @@ mod tests {
             let (foo, goo, moo, noo) = unhash4(aaa);
             let bar = hide(baz, bazz);
             let (bar, baz) = open(bazz);
+            let (bar) = coproc sq(baz);
             return (bar, baz, bazz);
         });
 
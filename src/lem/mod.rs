@@ -21,7 +21,7 @@
 //! operations `Op` followed by a control `Ctrl` statement.
 //!
 //! Operations are much like `let` statements in functional languages. For
-//! example, a `Op::Hash2(x, t, ys)` is to be understood as `let x = hash2(ys)`.
+//! example, a `Op::Hash(x, t, [y, z])` is to be understood as `let x = hash2(y, z)`.
 //! If a second operation binds its result to the same variable as a previous
 //! operation, we shadow the previous value. There is no mutation, thus the
 //! language is referentially transparent.
@@ -49,7 +49,9 @@
 //! Here are some simple transformations and static checks of correctness we
 //! want to perform on a LEM function before interpreting and synthesizing it
 //!
-//! 1. All variables must be bound, no variable can be used before being bound
+//! 1. All variables must be bound, no variable can be used before being bound,
+//!    and no statement can bind the same variable twice. These are checked on
+//!    the code as written, see `Block::validate`
 //! 2. All returns within a block must be of the same size and equal to the
 //!    function's output size
 //! 3. Function calls must have the correct number of arguments and must bind
@@ -59,51 +61,108 @@
 //! 6. We also check for variables that are not used. If intended they should
 //!    be prefixed by "_"
 
+mod checkpoint;
 mod circuit;
+mod coproc;
+mod cross_check;
+mod diff;
+mod dispatch;
+mod emit;
 mod eval;
+mod foreign;
+mod inline;
 mod interpreter;
+mod liveness;
 mod macros;
+mod optimize;
+mod parallel;
+pub mod parser;
 mod path;
 mod pointers;
+mod ratio;
+mod replay;
+mod serialization;
+mod set;
 mod slot;
 mod store;
+mod testing;
+mod trace;
+mod types;
+mod validate;
 mod var_map;
 
 use crate::field::LurkField;
 use crate::symbol::Symbol;
-use crate::tag::{ContTag, ExprTag, Tag as TagTrait};
+use crate::tag::{ContTag, ExprTag, Tag as TagTrait, UserContTag};
 use anyhow::{bail, Result};
 use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
-use self::{pointers::Ptr, slot::SlotsCounter, store::Store, var_map::VarMap};
+use self::{
+    pointers::{Ptr, ZPtr},
+    slot::SlotsCounter,
+    store::Store,
+    var_map::VarMap,
+};
+
+pub use checkpoint::EvalCheckpoint;
+pub use circuit::{ConstantsReport, GlobalAllocator, WitnessGenerator};
+pub use coproc::{Coproc, CoprocCS, POSEIDON_PAD_CONSTRAINTS};
+pub use cross_check::{compare_backends, cross_check, Comparison, Divergence, Outcome, Run};
+pub use diff::{diff, DiffLine, FuncDiff};
+pub use dispatch::FuncTable;
+pub use eval::{evaluate_stream, evaluate_within, resume_evaluation, EvalConfig};
+pub use foreign::ForeignResolver;
+pub use inline::InlineReport;
+pub use interpreter::{Budget, FrameStream, Halt, OutOfGas};
+pub use parallel::{evaluate_parallel, Evaluation};
+pub use ratio::{
+    make_ratio, ratio_add, ratio_div, ratio_lt, ratio_mul, ratio_sub, RatioHint, RATIO_HINT,
+};
+pub use replay::FrameData;
+pub use serialization::{FUNC_FORMAT_VERSION, FUNC_MAGIC};
+pub use set::{set_insert, set_member, set_union, SetKey, SET_KEY};
+pub use slot::SlotsReport;
+pub use store::StoreStats;
+pub use testing::assert_circuit_agrees;
+#[cfg(not(target_arch = "wasm32"))]
+pub use testing::{arb_func, arb_nums};
+pub use trace::{FrameTrace, Trace};
+pub use types::{PtrType, Shape};
+pub use validate::{Branch, Diagnostic, Issue, Location, Span, Stmt};
 
 pub type AString = Arc<str>;
 
 /// A `Func` is a LEM function. It consist of input params, output size and a
 /// function body, which is a `Block`
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Func {
     name: String,
     input_params: Vec<Var>,
     output_size: usize,
     body: Block,
     slot: SlotsCounter,
+    merge_returns: bool,
+    share_slots: bool,
 }
 
 /// LEM variables
-#[derive(Debug, PartialEq, Clone, Eq, Hash)]
+#[derive(Debug, PartialEq, Clone, Eq, Hash, Serialize, Deserialize)]
 pub struct Var(AString);
 
 /// LEM tags
-#[derive(Copy, Debug, PartialEq, Clone, Eq, Hash)]
+#[derive(Copy, Debug, PartialEq, Clone, Eq, Hash, Serialize, Deserialize)]
 pub enum Tag {
     Expr(ExprTag),
     Cont(ContTag),
     Ctrl(CtrlTag),
+    /// The continuations alternate evaluators add, which are matched along
+    /// with those of `Cont`
+    UserCont(UserContTag),
 }
 
-#[derive(Copy, Debug, PartialEq, Clone, Eq, Hash)]
+#[derive(Copy, Debug, PartialEq, Clone, Eq, Hash, Serialize, Deserialize)]
 pub enum CtrlTag {
     Return,
     MakeThunk,
@@ -119,6 +178,7 @@ impl Tag {
             Expr(tag) => tag.to_field(),
             Cont(tag) => tag.to_field(),
             Ctrl(tag) => tag.to_field(),
+            UserCont(tag) => tag.to_field(),
         }
     }
 }
@@ -148,12 +208,13 @@ impl std::fmt::Display for Tag {
             Expr(tag) => write!(f, "expr.{}", tag),
             Cont(tag) => write!(f, "cont.{}", tag),
             Ctrl(tag) => write!(f, "ctrl.{}", tag),
+            UserCont(tag) => write!(f, "cont.{}", tag),
         }
     }
 }
 
 /// LEM literals
-#[derive(Debug, PartialEq, Clone, Eq, Hash)]
+#[derive(Debug, PartialEq, Clone, Eq, Hash, Serialize, Deserialize)]
 pub enum Lit {
     // TODO maybe it should be a LurkField instead of u128
     Num(u128),
@@ -169,6 +230,18 @@ impl Lit {
             Self::Num(num) => Ptr::num(F::from_u128(*num)),
         }
     }
+    /// The `ZPtr` that `to_ptr` followed by `Store::hash_ptr` gives, without
+    /// interning anything, so hashing literals doesn't need `&mut Store`
+    pub fn to_z_ptr<F: LurkField>(&self, store: &Store<F>) -> ZPtr<F> {
+        match self {
+            Self::Symbol(s) => store.hash_symbol(s),
+            Self::String(s) => store.hash_string(s),
+            Self::Num(num) => ZPtr {
+                tag: Tag::Expr(ExprTag::Num),
+                hash: F::from_u128(*num),
+            },
+        }
+    }
     pub fn from_ptr<F: LurkField>(ptr: &Ptr<F>, store: &Store<F>) -> Option<Self> {
         use ExprTag::*;
         use Tag::*;
@@ -180,7 +253,7 @@ impl Lit {
                 }
                 _ => unreachable!(),
             },
-            Expr(Str) => store.fetch_string(ptr).cloned().map(Lit::String),
+            Expr(Str) => store.fetch_string(ptr).map(|s| Lit::String(s.into())),
             Expr(Sym) => store.fetch_symbol(ptr).map(Lit::Symbol),
             _ => None,
         }
@@ -202,7 +275,7 @@ impl Var {
 
 /// A block is a sequence of operations followed by a control. Each block
 /// delimits their variables' scope.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Block {
     ops: Vec<Op>,
     ctrl: Ctrl,
@@ -210,15 +283,21 @@ pub struct Block {
 
 /// The basic control nodes for LEM logical paths.
 #[non_exhaustive]
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Ctrl {
     /// `MatchTag(x, cases)` performs a match on the tag of `x`, choosing the
     /// appropriate `Block` among the ones provided in `cases`
     MatchTag(Var, IndexMap<Tag, Block>, Option<Box<Block>>),
-    /// `MatchSymbol(x, cases, def)` checks whether `x` matches some symbol among
-    /// the ones provided in `cases`. If so, run the corresponding `Block`. Run
-    /// `def` otherwise
+    /// `MatchVal(x, cases, def)` checks whether the value of `x` matches some
+    /// literal among the ones provided in `cases`. If so, run the corresponding
+    /// `Block`. Run `def` otherwise. Only the hashes are compared, so `x`'s tag
+    /// should be matched first
     MatchVal(Var, IndexMap<Lit, Block>, Option<Box<Block>>),
+    /// `MatchSymbol(x, cases, def)` checks whether the symbol `x` is one of the
+    /// symbols provided in `cases`. If so, run the corresponding `Block`. Run
+    /// `def` otherwise. Unlike `MatchVal`, it also constrains `x` to be a
+    /// symbol, so its tag doesn't have to be matched first
+    MatchSymbol(Var, IndexMap<Symbol, Block>, Option<Box<Block>>),
     /// `IfEq(x, y, eq_block, else_block)` runs `eq_block` if `x == y`, and
     /// otherwise runs `else_block`
     IfEq(Var, Var, Box<Block>, Box<Block>),
@@ -227,7 +306,7 @@ pub enum Ctrl {
 }
 
 /// The atomic operations of LEMs.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Op {
     /// `Call(ys, f, xs)` binds `ys` to the results of `f` applied to `xs`
     Call(Vec<Var>, Box<Func>, Vec<Var>),
@@ -253,28 +332,135 @@ pub enum Op {
     Lt(Var, Var, Var),
     /// `Trunc(y, a, n)` binds `y` to `a` truncated to `n` bits, up to 64 bits
     Trunc(Var, Var, u32),
+    /// `AssertRange(a, n)` fails unless `a` fits in `n` bits, up to 64 bits
+    AssertRange(Var, u32),
+    /// `AssertU32(a)` fails unless `a` fits in 32 bits. Unlike `AssertRange`,
+    /// it takes no slot
+    AssertU32(Var),
+    /// `AssertChar(a)` fails unless `a` fits in the 21 bits of a char
+    AssertChar(Var),
+    /// `And(y, a, b)` binds `y` to the bitwise and of the lowest 64 bits of `a` and `b`
+    And(Var, Var, Var),
+    /// `Or(y, a, b)` binds `y` to the bitwise or of the lowest 64 bits of `a` and `b`
+    Or(Var, Var, Var),
+    /// `Xor(y, a, b)` binds `y` to the bitwise xor of the lowest 64 bits of `a` and `b`
+    Xor(Var, Var, Var),
     /// `DivRem64(ys, a, b)` binds `ys` to `(a / b, a % b)` as if they were u64
     DivRem64([Var; 2], Var, Var),
-    /// `Emit(v)` simply prints out the value of `v` when interpreting the code
+    /// `AddI64(y, a, b)` binds `y` to the sum of the i64s whose two's
+    /// complements are `a` and `b`, wrapping around. Fails unless both fit in 64 bits
+    AddI64(Var, Var, Var),
+    /// `SubI64(y, a, b)` binds `y` to the difference of two i64s, like `AddI64`
+    SubI64(Var, Var, Var),
+    /// `MulI64(y, a, b)` binds `y` to the product of two i64s, like `AddI64`
+    MulI64(Var, Var, Var),
+    /// `LtI64(y, a, b)` binds `y` to `1` if `a < b` as i64s, or to `0` otherwise.
+    /// Fails unless both fit in 64 bits
+    LtI64(Var, Var, Var),
+    /// `Emit(v)` simply prints out the value of `v` when interpreting the code.
+    /// `Func::with_emit_log` makes the circuit log it as well
     Emit(Var),
-    /// `Hash2(x, t, ys)` binds `x` to a `Ptr` with tag `t` and 2 children `ys`
-    Hash2(Var, Tag, [Var; 2]),
-    /// `Hash3(x, t, ys)` binds `x` to a `Ptr` with tag `t` and 3 children `ys`
-    Hash3(Var, Tag, [Var; 3]),
-    /// `Hash4(x, t, ys)` binds `x` to a `Ptr` with tag `t` and 4 children `ys`
-    Hash4(Var, Tag, [Var; 4]),
-    /// `Unhash2([a, b], x)` binds `a` and `b` to the 2 children of `x`
-    Unhash2([Var; 2], Var),
-    /// `Unhash3([a, b, c], x)` binds `a`, `b` and `c` to the 3 children of `x`
-    Unhash3([Var; 3], Var),
-    /// `Unhash4([a, b, c, d], x)` binds `a`, `b`, `c` and `d` to the 4 children of `x`
-    Unhash4([Var; 4], Var),
+    /// `Hash(x, t, ys)` binds `x` to a `Ptr` with tag `t` and children `ys`,
+    /// as many as one of `HASH_ARITIES`
+    Hash(Var, Tag, Vec<Var>),
+    /// `Unhash(ys, x)` binds `ys` to the children of `x`, which must have as
+    /// many children as there are `ys`
+    Unhash(Vec<Var>, Var),
     /// `Hide(x, s, p)` binds `x` to a (comm) `Ptr` resulting from hiding the
     /// payload `p` with (num) secret `s`
     Hide(Var, Var, Var),
     /// `Open(s, p, h)` binds `s` and `p` to the secret and payload (respectively)
     /// of the commitment that resulted on (num or comm) `h`
     Open(Var, Var, Var),
+    /// `Coproc(name, ys, xs)` binds `ys` to the outputs of the coprocessor
+    /// registered in the store as `name` when applied to `xs`
+    Coproc(AString, Vec<Var>, Vec<Var>),
+}
+
+/// The numbers of children `Op::Hash` and `Op::Unhash` support. Each arity
+/// has slots of its own
+pub const HASH_ARITIES: [usize; 5] = [2, 3, 4, 6, 8];
+
+/// Shims for the operations that were once specific to an arity
+#[allow(non_snake_case)]
+impl Op {
+    #[inline]
+    pub fn Hash2(img: Var, tag: Tag, preimg: [Var; 2]) -> Op {
+        Op::Hash(img, tag, preimg.into())
+    }
+
+    #[inline]
+    pub fn Hash3(img: Var, tag: Tag, preimg: [Var; 3]) -> Op {
+        Op::Hash(img, tag, preimg.into())
+    }
+
+    #[inline]
+    pub fn Hash4(img: Var, tag: Tag, preimg: [Var; 4]) -> Op {
+        Op::Hash(img, tag, preimg.into())
+    }
+
+    #[inline]
+    pub fn Hash6(img: Var, tag: Tag, preimg: [Var; 6]) -> Op {
+        Op::Hash(img, tag, preimg.into())
+    }
+
+    #[inline]
+    pub fn Hash8(img: Var, tag: Tag, preimg: [Var; 8]) -> Op {
+        Op::Hash(img, tag, preimg.into())
+    }
+
+    #[inline]
+    pub fn Unhash2(preimg: [Var; 2], img: Var) -> Op {
+        Op::Unhash(preimg.into(), img)
+    }
+
+    #[inline]
+    pub fn Unhash3(preimg: [Var; 3], img: Var) -> Op {
+        Op::Unhash(preimg.into(), img)
+    }
+
+    #[inline]
+    pub fn Unhash4(preimg: [Var; 4], img: Var) -> Op {
+        Op::Unhash(preimg.into(), img)
+    }
+
+    #[inline]
+    pub fn Unhash6(preimg: [Var; 6], img: Var) -> Op {
+        Op::Unhash(preimg.into(), img)
+    }
+
+    #[inline]
+    pub fn Unhash8(preimg: [Var; 8], img: Var) -> Op {
+        Op::Unhash(preimg.into(), img)
+    }
+}
+
+impl Op {
+    /// Applies a bitwise operation (`And`, `Or` or `Xor`) to two u64s, so the
+    /// interpreter and the circuit agree on what they compute
+    pub(crate) fn bitwise_u64(&self, a: u64, b: u64) -> u64 {
+        match self {
+            Op::And(..) => a & b,
+            Op::Or(..) => a | b,
+            Op::Xor(..) => a ^ b,
+            _ => unreachable!("not a bitwise operation"),
+        }
+    }
+
+    /// Applies a signed operation (`AddI64`, `SubI64`, `MulI64` or `LtI64`) to
+    /// the two's complements of two i64s, returning the two's complement of
+    /// the result
+    pub(crate) fn signed_i64(&self, a: u64, b: u64) -> u64 {
+        let (a, b) = (a as i64, b as i64);
+        let c = match self {
+            Op::AddI64(..) => a.wrapping_add(b),
+            Op::SubI64(..) => a.wrapping_sub(b),
+            Op::MulI64(..) => a.wrapping_mul(b),
+            Op::LtI64(..) => i64::from(a < b),
+            _ => unreachable!("not a signed operation"),
+        };
+        c as u64
+    }
 }
 
 impl Func {
@@ -285,6 +471,28 @@ impl Func {
         output_size: usize,
         body: Block,
     ) -> Result<Func> {
+        Self::new_at(name, input_params, output_size, body, None)
+    }
+
+    /// Like `new`, but reports the issues found by `Block::validate` at `span`
+    pub fn new_at(
+        name: String,
+        input_params: Vec<Var>,
+        output_size: usize,
+        body: Block,
+        span: Option<Span>,
+    ) -> Result<Func> {
+        let (errors, shadowings): (Vec<_>, Vec<_>) = body
+            .validate(&input_params, span)
+            .into_iter()
+            .partition(Diagnostic::is_error);
+        for shadowing in shadowings {
+            tracing::debug!("In function `{name}`, {shadowing}");
+        }
+        if !errors.is_empty() {
+            let errors = errors.iter().map(ToString::to_string).collect::<Vec<_>>();
+            bail!("Invalid function `{name}`:\n{}", errors.join("\n"))
+        }
         let slot = body.count_slots();
         let func = Func {
             slot,
@@ -292,12 +500,25 @@ impl Func {
             input_params,
             output_size,
             body,
+            merge_returns: false,
+            share_slots: false,
         }
         .deconflict(&mut VarMap::new(), &mut 0)?;
         func.check()?;
         Ok(func)
     }
 
+    /// Makes synthesis constrain the outputs once for all the returns of the
+    /// function, and of the functions it calls, rather than once per return.
+    /// Returns of the same variable or constant then share their constraints,
+    /// which pays off for functions with many returns, like Lurk's step
+    /// function. It changes the shape of the circuit, and thus the public
+    /// parameters, so it's opt-in.
+    pub fn with_merged_returns(mut self) -> Self {
+        self.merge_returns = true;
+        self
+    }
+
     /// Performs the static checks described in LEM's docstring.
     pub fn check(&self) -> Result<()> {
         use std::collections::{HashMap, HashSet};
@@ -360,7 +581,14 @@ impl Func {
                     | Op::Sub(tgt, a, b)
                     | Op::Mul(tgt, a, b)
                     | Op::Div(tgt, a, b)
-                    | Op::Lt(tgt, a, b) => {
+                    | Op::Lt(tgt, a, b)
+                    | Op::And(tgt, a, b)
+                    | Op::Or(tgt, a, b)
+                    | Op::Xor(tgt, a, b)
+                    | Op::AddI64(tgt, a, b)
+                    | Op::SubI64(tgt, a, b)
+                    | Op::MulI64(tgt, a, b)
+                    | Op::LtI64(tgt, a, b) => {
                         is_bound(a, map)?;
                         is_bound(b, map)?;
                         is_unique(tgt, map);
@@ -372,6 +600,15 @@ impl Func {
                         is_bound(a, map)?;
                         is_unique(tgt, map);
                     }
+                    Op::AssertRange(a, n) => {
+                        if *n > 64 {
+                            bail!("Cannot yet check ranges over 64 bits")
+                        }
+                        is_bound(a, map)?;
+                    }
+                    Op::AssertU32(a) | Op::AssertChar(a) => {
+                        is_bound(a, map)?;
+                    }
                     Op::DivRem64(tgt, a, b) => {
                         is_bound(a, map)?;
                         is_bound(b, map)?;
@@ -380,27 +617,17 @@ impl Func {
                     Op::Emit(a) => {
                         is_bound(a, map)?;
                     }
-                    Op::Hash2(img, _tag, preimg) => {
-                        preimg.iter().try_for_each(|arg| is_bound(arg, map))?;
-                        is_unique(img, map);
-                    }
-                    Op::Hash3(img, _tag, preimg) => {
-                        preimg.iter().try_for_each(|arg| is_bound(arg, map))?;
-                        is_unique(img, map);
-                    }
-                    Op::Hash4(img, _tag, preimg) => {
+                    Op::Hash(img, _tag, preimg) => {
+                        if !HASH_ARITIES.contains(&preimg.len()) {
+                            bail!("Cannot hash {} children", preimg.len())
+                        }
                         preimg.iter().try_for_each(|arg| is_bound(arg, map))?;
                         is_unique(img, map);
                     }
-                    Op::Unhash2(preimg, img) => {
-                        is_bound(img, map)?;
-                        preimg.iter().for_each(|var| is_unique(var, map))
-                    }
-                    Op::Unhash3(preimg, img) => {
-                        is_bound(img, map)?;
-                        preimg.iter().for_each(|var| is_unique(var, map))
-                    }
-                    Op::Unhash4(preimg, img) => {
+                    Op::Unhash(preimg, img) => {
+                        if !HASH_ARITIES.contains(&preimg.len()) {
+                            bail!("Cannot unhash {} children", preimg.len())
+                        }
                         is_bound(img, map)?;
                         preimg.iter().for_each(|var| is_unique(var, map))
                     }
@@ -414,6 +641,10 @@ impl Func {
                         is_unique(tgt_secret, map);
                         is_unique(tgt_ptr, map);
                     }
+                    Op::Coproc(_name, out, inp) => {
+                        inp.iter().try_for_each(|arg| is_bound(arg, map))?;
+                        out.iter().for_each(|var| is_unique(var, map));
+                    }
                 }
             }
             match &block.ctrl {
@@ -434,7 +665,7 @@ impl Func {
                     for (tag, block) in cases {
                         let tag_kind = match tag {
                             Tag::Expr(..) => 0,
-                            Tag::Cont(..) => 1,
+                            Tag::Cont(..) | Tag::UserCont(..) => 1,
                             Tag::Ctrl(..) => 4,
                         };
                         if let Some(kind) = kind {
@@ -481,6 +712,17 @@ impl Func {
                         None => (),
                     }
                 }
+                Ctrl::MatchSymbol(var, cases, def) => {
+                    is_bound(var, map)?;
+                    // `IndexMap` keys are unique, so there can't be repeated cases
+                    for block in cases.values() {
+                        recurse(block, return_size, map)?;
+                    }
+                    match def {
+                        Some(def) => recurse(def, return_size, map)?,
+                        None => (),
+                    }
+                }
                 Ctrl::IfEq(x, y, eq_block, else_block) => {
                     is_bound(x, map)?;
                     is_bound(y, map)?;
@@ -627,45 +869,73 @@ impl Block {
                     let tgt = insert_one(map, uniq, &tgt);
                     ops.push(Op::Trunc(tgt, a, b))
                 }
+                Op::AssertRange(a, n) => {
+                    let a = map.get_cloned(&a)?;
+                    ops.push(Op::AssertRange(a, n))
+                }
+                Op::AssertU32(a) => ops.push(Op::AssertU32(map.get_cloned(&a)?)),
+                Op::AssertChar(a) => ops.push(Op::AssertChar(map.get_cloned(&a)?)),
+                Op::And(tgt, a, b) => {
+                    let a = map.get_cloned(&a)?;
+                    let b = map.get_cloned(&b)?;
+                    let tgt = insert_one(map, uniq, &tgt);
+                    ops.push(Op::And(tgt, a, b))
+                }
+                Op::Or(tgt, a, b) => {
+                    let a = map.get_cloned(&a)?;
+                    let b = map.get_cloned(&b)?;
+                    let tgt = insert_one(map, uniq, &tgt);
+                    ops.push(Op::Or(tgt, a, b))
+                }
+                Op::Xor(tgt, a, b) => {
+                    let a = map.get_cloned(&a)?;
+                    let b = map.get_cloned(&b)?;
+                    let tgt = insert_one(map, uniq, &tgt);
+                    ops.push(Op::Xor(tgt, a, b))
+                }
                 Op::DivRem64(tgt, a, b) => {
                     let a = map.get_cloned(&a)?;
                     let b = map.get_cloned(&b)?;
                     let tgt = insert_many(map, uniq, &tgt);
                     ops.push(Op::DivRem64(tgt.try_into().unwrap(), a, b))
                 }
-                Op::Emit(a) => {
+                Op::AddI64(tgt, a, b) => {
                     let a = map.get_cloned(&a)?;
-                    ops.push(Op::Emit(a))
+                    let b = map.get_cloned(&b)?;
+                    let tgt = insert_one(map, uniq, &tgt);
+                    ops.push(Op::AddI64(tgt, a, b))
                 }
-                Op::Hash2(img, tag, preimg) => {
-                    let preimg = map.get_many_cloned(&preimg)?.try_into().unwrap();
-                    let img = insert_one(map, uniq, &img);
-                    ops.push(Op::Hash2(img, tag, preimg))
+                Op::SubI64(tgt, a, b) => {
+                    let a = map.get_cloned(&a)?;
+                    let b = map.get_cloned(&b)?;
+                    let tgt = insert_one(map, uniq, &tgt);
+                    ops.push(Op::SubI64(tgt, a, b))
                 }
-                Op::Hash3(img, tag, preimg) => {
-                    let preimg = map.get_many_cloned(&preimg)?.try_into().unwrap();
-                    let img = insert_one(map, uniq, &img);
-                    ops.push(Op::Hash3(img, tag, preimg))
+                Op::MulI64(tgt, a, b) => {
+                    let a = map.get_cloned(&a)?;
+                    let b = map.get_cloned(&b)?;
+                    let tgt = insert_one(map, uniq, &tgt);
+                    ops.push(Op::MulI64(tgt, a, b))
                 }
-                Op::Hash4(img, tag, preimg) => {
-                    let preimg = map.get_many_cloned(&preimg)?.try_into().unwrap();
-                    let img = insert_one(map, uniq, &img);
-                    ops.push(Op::Hash4(img, tag, preimg))
+                Op::LtI64(tgt, a, b) => {
+                    let a = map.get_cloned(&a)?;
+                    let b = map.get_cloned(&b)?;
+                    let tgt = insert_one(map, uniq, &tgt);
+                    ops.push(Op::LtI64(tgt, a, b))
                 }
-                Op::Unhash2(preimg, img) => {
-                    let img = map.get_cloned(&img)?;
-                    let preimg = insert_many(map, uniq, &preimg);
-                    ops.push(Op::Unhash2(preimg.try_into().unwrap(), img))
+                Op::Emit(a) => {
+                    let a = map.get_cloned(&a)?;
+                    ops.push(Op::Emit(a))
                 }
-                Op::Unhash3(preimg, img) => {
-                    let img = map.get_cloned(&img)?;
-                    let preimg = insert_many(map, uniq, &preimg);
-                    ops.push(Op::Unhash3(preimg.try_into().unwrap(), img))
+                Op::Hash(img, tag, preimg) => {
+                    let preimg = map.get_many_cloned(&preimg)?;
+                    let img = insert_one(map, uniq, &img);
+                    ops.push(Op::Hash(img, tag, preimg))
                 }
-                Op::Unhash4(preimg, img) => {
+                Op::Unhash(preimg, img) => {
                     let img = map.get_cloned(&img)?;
                     let preimg = insert_many(map, uniq, &preimg);
-                    ops.push(Op::Unhash4(preimg.try_into().unwrap(), img))
+                    ops.push(Op::Unhash(preimg, img))
                 }
                 Op::Hide(tgt, sec, pay) => {
                     let sec = map.get_cloned(&sec)?;
@@ -679,6 +949,11 @@ impl Block {
                     let pay = insert_one(map, uniq, &pay);
                     ops.push(Op::Open(sec, pay, comm_or_num))
                 }
+                Op::Coproc(name, out, inp) => {
+                    let inp = map.get_many_cloned(&inp)?;
+                    let out = insert_many(map, uniq, &out);
+                    ops.push(Op::Coproc(name, out, inp))
+                }
             }
         }
         let ctrl = match self.ctrl {
@@ -708,6 +983,19 @@ impl Block {
                 };
                 Ctrl::MatchVal(var, IndexMap::from_iter(new_cases), new_def)
             }
+            Ctrl::MatchSymbol(var, cases, def) => {
+                let var = map.get_cloned(&var)?;
+                let mut new_cases = Vec::with_capacity(cases.len());
+                for (sym, case) in cases {
+                    let new_case = case.deconflict(&mut map.clone(), uniq)?;
+                    new_cases.push((sym, new_case));
+                }
+                let new_def = match def {
+                    Some(def) => Some(Box::new(def.deconflict(map, uniq)?)),
+                    None => None,
+                };
+                Ctrl::MatchSymbol(var, IndexMap::from_iter(new_cases), new_def)
+            }
             Ctrl::IfEq(x, y, eq_block, else_block) => {
                 let x = map.get_cloned(&x)?;
                 let y = map.get_cloned(&y)?;
@@ -731,12 +1019,18 @@ impl Var {
 #[cfg(test)]
 mod tests {
     use super::slot::SlotsCounter;
-    use super::{store::Store, *};
+    use super::{
+        circuit::SlotWitnesses,
+        interpreter::{PreimageData, Preimages},
+        store::Store,
+        *,
+    };
+    use crate::circuit::gadgets::data::hash_poseidon;
     use crate::state::lurk_sym;
     use crate::{func, lem::pointers::Ptr};
-    use bellpepper::util_cs::Comparable;
+    use bellpepper::util_cs::{witness_cs::WitnessCS, Comparable};
     use bellpepper_core::test_cs::TestConstraintSystem;
-    use bellpepper_core::Delta;
+    use bellpepper_core::{num::AllocatedNum, ConstraintSystem, Delta};
     use blstrs::Scalar as Fr;
 
     /// Helper function for testing circuit synthesis.
@@ -757,6 +1051,11 @@ mod tests {
         assert_eq!(func.slot, expected_num_slots);
 
         let computed_num_constraints = func.num_constraints::<Fr>(store);
+        func.assert_num_constraints(store);
+
+        let mut cs_blank = TestConstraintSystem::<Fr>::new();
+        func.synthesize_blank(&mut cs_blank, store).unwrap();
+        assert_eq!(computed_num_constraints, cs_blank.num_constraints());
 
         let mut cs_prev = None;
         for input in inputs.into_iter() {
@@ -777,6 +1076,11 @@ mod tests {
                 cs_prev = Some(cs);
             }
         }
+
+        // Blank synthesis gives the same circuit as real frames do
+        if let Some(cs_prev) = cs_prev {
+            assert_eq!(cs_blank.delta(&cs_prev, true), Delta::Equal);
+        }
     }
 
     #[test]
@@ -854,7 +1158,11 @@ mod tests {
         });
 
         let inputs = vec![Ptr::num(Fr::from_u64(42))];
-        synthesize_test_helper(&func, inputs, SlotsCounter::new((2, 0, 0, 0, 0)));
+        synthesize_test_helper(
+            &func,
+            inputs,
+            SlotsCounter::new((2, 0, 0, 0, 0, 0, 0, 0, 0)),
+        );
     }
 
     #[test]
@@ -915,7 +1223,7 @@ mod tests {
         });
 
         let inputs = vec![Ptr::num(Fr::from_u64(42)), Ptr::char('c')];
-        synthesize_test_helper(&lem, inputs, SlotsCounter::new((2, 2, 2, 0, 0)));
+        synthesize_test_helper(&lem, inputs, SlotsCounter::new((2, 2, 2, 0, 0, 0, 0, 0, 0)));
     }
 
     #[test]
@@ -949,7 +1257,7 @@ mod tests {
         });
 
         let inputs = vec![Ptr::num(Fr::from_u64(42)), Ptr::char('c')];
-        synthesize_test_helper(&lem, inputs, SlotsCounter::new((3, 3, 3, 0, 0)));
+        synthesize_test_helper(&lem, inputs, SlotsCounter::new((3, 3, 3, 0, 0, 0, 0, 0, 0)));
     }
 
     #[test]
@@ -996,6 +1304,382 @@ mod tests {
         });
 
         let inputs = vec![Ptr::num(Fr::from_u64(42)), Ptr::char('c')];
-        synthesize_test_helper(&lem, inputs, SlotsCounter::new((4, 4, 4, 0, 0)));
+        synthesize_test_helper(&lem, inputs, SlotsCounter::new((4, 4, 4, 0, 0, 0, 0, 0, 0)));
+    }
+
+    #[test]
+    fn test_wide_hash_slots() {
+        let lem = func!(foo(expr_in, env_in, cont_in): 3 => {
+            let x: Cont::Let = hash6(expr_in, env_in, cont_in, expr_in, env_in, cont_in);
+            let (a, b, c, _d, _e, _f) = unhash6(x);
+            let t: Cont::Terminal;
+            match expr_in.tag {
+                Expr::Num => {
+                    let y: Cont::Binop = hash8(a, b, c, a, b, c, a, b);
+                    let (_y1, _y2, _y3, _y4, _y5, _y6, _y7, y8) = unhash8(y);
+                    return (y8, x, t);
+                }
+                Expr::Char => {
+                    return (a, c, t);
+                }
+            }
+        });
+
+        let inputs = vec![Ptr::num(Fr::from_u64(42)), Ptr::char('c')];
+        synthesize_test_helper(&lem, inputs, SlotsCounter::new((0, 0, 0, 2, 2, 0, 0, 0, 0)));
+    }
+
+    #[test]
+    fn hashes_of_unsupported_arities_are_rejected() {
+        let a = Var("a".into());
+        let x = Var("_x".into());
+        let body = |op| Block {
+            ops: vec![op],
+            ctrl: Ctrl::Return(vec![a.clone()]),
+        };
+        let hash5 = Op::Hash(x.clone(), Tag::Expr(ExprTag::Cons), vec![a.clone(); 5]);
+        assert!(Func::new("f".into(), vec![a.clone()], 1, body(hash5)).is_err());
+        let unhash5 = Op::Unhash(vec![x.clone(); 5], a.clone());
+        assert!(Func::new("f".into(), vec![a.clone()], 1, body(unhash5)).is_err());
+
+        let hash6 = Op::Hash(x, Tag::Expr(ExprTag::Cons), vec![a.clone(); 6]);
+        let lem = Func::new("f".into(), vec![a.clone()], 1, body(hash6)).unwrap();
+        assert_eq!(lem.slot, SlotsCounter::new((0, 0, 0, 1, 0, 0, 0, 0, 0)));
+        assert_eq!(lem.slot.hash(6), 1);
+    }
+
+    #[test]
+    fn unused_trailing_slots_are_pruned() {
+        let lem = func!(foo(expr_in, env_in, cont_in): 3 => {
+            let x: Expr::Cons = hash2(expr_in, env_in);
+            let t: Cont::Terminal;
+            match expr_in.tag {
+                Expr::Num => {
+                    let y: Expr::Cons = hash2(x, x);
+                    let _z: Expr::Cons = hash3(y, env_in, cont_in);
+                    return (y, env_in, t);
+                }
+                Expr::Char => {
+                    return (x, env_in, t);
+                }
+            }
+        });
+        let store = &mut Store::default();
+        let nil = store.intern_symbol(&lurk_sym("nil"));
+        let outermost = Ptr::null(Tag::Cont(crate::tag::ContTag::Outermost));
+        let (frames, _) = lem
+            .call_until(vec![Ptr::char('c'), nil, outermost], store, |_| true)
+            .unwrap();
+        let preimages = &frames[0].preimages;
+        assert_eq!(preimages.hash2.len(), 1);
+        assert!(preimages.hash3.is_empty());
+        let blank = Preimages::<Fr>::blank(&lem);
+        assert_eq!((blank.hash2.len(), blank.hash3.len()), (2, 1));
+
+        // The pruned slots are still allocated, with dummy values
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        lem.synthesize(&mut cs, store, &frames[0]).unwrap();
+        assert!(cs.is_satisfied());
+        assert_eq!(lem.num_constraints::<Fr>(store), cs.num_constraints());
+    }
+
+    #[test]
+    fn match_symbols() {
+        let lem = func!(foo(x): 1 => {
+            let t: Expr::Num;
+            let f: Expr::Nil;
+            match x.symbol {
+                "car" | "cdr" => {
+                    return (t);
+                }
+            };
+            return (f);
+        });
+        // the textual syntax is the macro's
+        let parsed: Func = lem.to_string().parse().unwrap();
+        assert_eq!(parsed.to_string(), lem.to_string());
+
+        let store = &mut Store::default();
+        for (sym, matches) in [("car", true), ("cdr", true), ("cons", false)] {
+            let x = store.intern_symbol(&lurk_sym(sym));
+            let (frame, _) = lem
+                .call(vec![x], store, Preimages::new_from_func(&lem))
+                .unwrap();
+            assert_eq!(frame.output[0].tag() == &Tag::Expr(ExprTag::Num), matches);
+
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            lem.synthesize(&mut cs, store, &frame).unwrap();
+            assert!(cs.is_satisfied());
+            assert_eq!(lem.num_constraints::<Fr>(store), cs.num_constraints());
+        }
+
+        // only symbols can be matched, even if their hash is the one of a case
+        let car = store.intern_symbol(&lurk_sym("car"));
+        let car_hash = store.hash_ptr(&car).unwrap().hash;
+        assert!(lem
+            .call(
+                vec![Ptr::num(car_hash)],
+                store,
+                Preimages::new_from_func(&lem)
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn bitwise_ops_on_lowest_64_bits() {
+        let lem = func!(bitwise(a, b): 3 => {
+            let x = and(a, b);
+            let y = or(a, b);
+            let z = xor(a, b);
+            return (x, y, z);
+        });
+        let store = &mut Store::default();
+        // `a` doesn't fit in 64 bits and only its lowest 64 bits, `0b1100`, are used
+        let a = Ptr::num(Fr::from_u64(u64::MAX) + Fr::from_u64(13));
+        let b = Ptr::num(Fr::from_u64(0b1010));
+        let (frame, _) = lem
+            .call(vec![a, b], store, Preimages::new_from_func(&lem))
+            .unwrap();
+        let expected = [0b1000, 0b1110, 0b0110].map(|n| Ptr::num(Fr::from_u64(n)));
+        assert_eq!(frame.output, expected);
+
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        lem.synthesize(&mut cs, store, &frame).unwrap();
+        assert!(cs.is_satisfied());
+        assert_eq!(lem.num_constraints::<Fr>(store), cs.num_constraints());
+        lem.assert_num_constraints(store);
+    }
+
+    #[test]
+    fn signed_ops_wrap_around() {
+        let lem = func!(signed(a, b): 4 => {
+            let w = add_i64(a, b);
+            let x = sub_i64(a, b);
+            let y = mul_i64(a, b);
+            let z = lt_i64(a, b);
+            return (w, x, y, z);
+        });
+        let store = &mut Store::default();
+        let num = |n: i64| Ptr::num(Fr::from_u64(n as u64));
+        for (a, b) in [
+            (-3, 5),
+            (i64::MAX, 1),
+            (i64::MIN, 1),
+            (7, -7),
+            (i64::MIN, i64::MAX),
+        ] {
+            let (frame, _) = lem
+                .call(vec![num(a), num(b)], store, Preimages::new_from_func(&lem))
+                .unwrap();
+            let expected = [
+                a.wrapping_add(b),
+                a.wrapping_sub(b),
+                a.wrapping_mul(b),
+                i64::from(a < b),
+            ];
+            assert_eq!(frame.output, expected.map(num));
+
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            lem.synthesize(&mut cs, store, &frame).unwrap();
+            assert!(cs.is_satisfied());
+            assert_eq!(lem.num_constraints::<Fr>(store), cs.num_constraints());
+        }
+        lem.assert_num_constraints(store);
+
+        // unlike bitwise operations, signed ones reject operands over 64 bits
+        let big = Ptr::num(Fr::from_u64(u64::MAX) + Fr::from_u64(1));
+        let one = Ptr::num(Fr::from_u64(1));
+        assert!(lem
+            .call(vec![big, one], store, Preimages::new_from_func(&lem))
+            .is_err());
+    }
+
+    #[test]
+    fn range_checks() {
+        let lem = func!(range(a): 1 => {
+            assert_range(a, 8);
+            assert_range(a, 64);
+            return (a);
+        });
+        assert_eq!(lem.slot, SlotsCounter::new((0, 0, 0, 0, 0, 0, 0, 0, 2)));
+        let store = &mut Store::default();
+        let (frame, _) = lem
+            .call(
+                vec![Ptr::num(Fr::from_u64(255))],
+                store,
+                Preimages::new_from_func(&lem),
+            )
+            .unwrap();
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        lem.synthesize(&mut cs, store, &frame).unwrap();
+        assert!(cs.is_satisfied());
+        assert_eq!(lem.num_constraints::<Fr>(store), cs.num_constraints());
+        lem.assert_num_constraints(store);
+
+        let out_of_range = Ptr::num(Fr::from_u64(256));
+        assert!(lem
+            .call(vec![out_of_range], store, Preimages::new_from_func(&lem))
+            .is_err());
+
+        // A witness claiming otherwise doesn't satisfy the circuit
+        let mut forged = frame;
+        forged.input[0] = out_of_range;
+        forged.output[0] = out_of_range;
+        forged.preimages.range = vec![Some(PreimageData::F(Fr::from_u64(256))); 2];
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        lem.synthesize(&mut cs, store, &forged).unwrap();
+        assert!(!cs.is_satisfied());
+    }
+
+    #[test]
+    fn u32_and_char_checks() {
+        let lem = func!(check(a, b): 2 => {
+            assert_u32(a);
+            assert_char(b);
+            return (a, b);
+        });
+        assert_eq!(lem.slot, SlotsCounter::default());
+        let store = &mut Store::default();
+        let big = Ptr::num(Fr::from_u64(0x200000));
+        let (frame, _) = lem
+            .call(
+                vec![big, Ptr::char('λ')],
+                store,
+                Preimages::new_from_func(&lem),
+            )
+            .unwrap();
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        lem.synthesize(&mut cs, store, &frame).unwrap();
+        assert!(cs.is_satisfied());
+        assert_eq!(lem.num_constraints::<Fr>(store), cs.num_constraints());
+        lem.assert_num_constraints(store);
+
+        assert!(lem
+            .call(vec![big, big], store, Preimages::new_from_func(&lem))
+            .is_err());
+        let too_big = Ptr::num(Fr::from_u64(1 << 32));
+        assert!(lem
+            .call(
+                vec![too_big, Ptr::char('λ')],
+                store,
+                Preimages::new_from_func(&lem)
+            )
+            .is_err());
+
+        // A witness claiming otherwise doesn't satisfy the circuit
+        let mut forged = frame;
+        forged.input[1] = big;
+        forged.output[1] = big;
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        lem.synthesize(&mut cs, store, &forged).unwrap();
+        assert!(!cs.is_satisfied());
+    }
+
+    #[test]
+    fn constants_are_shared_across_frames() {
+        let lem = func!(step(x): 1 => {
+            let zero: Expr::Num;
+            let y = add(x, zero);
+            let z = mul(y, x);
+            return (z);
+        });
+        let store = &mut Store::default();
+        let mut frames = Vec::new();
+        let mut x = Ptr::num(Fr::from_u64(3));
+        for _ in 0..3 {
+            let (frame, _) = lem
+                .call(vec![x], store, Preimages::new_from_func(&lem))
+                .unwrap();
+            x = frame.output[0];
+            frames.push(frame);
+        }
+
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        let mut global_allocator = GlobalAllocator::default();
+        lem.synthesize_frames(&mut cs, store, &frames, &mut global_allocator)
+            .unwrap();
+        assert!(cs.is_satisfied());
+        // the tag of numbers and zero
+        assert_eq!(global_allocator.len(), 2);
+        let report = global_allocator.report();
+        assert_eq!(report.allocated(), 2);
+        assert!(report.savings() > 0);
+        assert!(report.constants.windows(2).all(|w| w[0].1 >= w[1].1));
+        let num_constraints = lem.num_constraints_frames(store, frames.len());
+        assert_eq!(cs.num_constraints(), num_constraints);
+        assert!(num_constraints < frames.len() * lem.num_constraints(store));
+    }
+
+    #[test]
+    fn literals_are_hashed_without_interning() {
+        let store = &mut Store::<Fr>::default();
+        for lit in [
+            Lit::Num(42),
+            Lit::String("".into()),
+            Lit::String("abc".into()),
+            Lit::Symbol(Symbol::sym(&["lurk", "car"])),
+            Lit::Symbol(Symbol::key(&["foo"])),
+            Lit::Symbol(lurk_sym("nil")),
+        ] {
+            let z_ptr = lit.to_z_ptr(store);
+            let ptr = lit.to_ptr(store);
+            assert_eq!(z_ptr, store.hash_ptr(&ptr).unwrap(), "{lit:?}");
+        }
+    }
+
+    #[test]
+    fn witnesses_are_generated_in_parallel() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Store<Fr>>();
+
+        let lem = func!(step(x): 1 => {
+            let y = String("frame");
+            let z: Expr::Cons = hash2(x, y);
+            return (z);
+        });
+        let store = &mut Store::default();
+        let mut frames = Vec::new();
+        let mut x = Ptr::num(Fr::from_u64(3));
+        for _ in 0..4 {
+            let (frame, _) = lem
+                .call(vec![x], store, Preimages::new_from_func(&lem))
+                .unwrap();
+            x = frame.output[0];
+            frames.push(frame);
+        }
+
+        let store = &*store;
+        let witnesses = lem.generate_witnesses(store, &frames).unwrap();
+        let generator = lem.witness_generator(store).unwrap();
+        for (frame, witness) in frames.iter().zip(&witnesses) {
+            let expected = generator.generate(store, frame).unwrap();
+            assert_eq!(witness.aux_slice(), expected.aux_slice());
+            assert_eq!(witness.aux_slice().len(), generator.num_aux());
+        }
+    }
+
+    #[test]
+    fn slot_witnesses_are_cached_across_frames() {
+        let store = Store::<Fr>::default();
+        let slot_witnesses = SlotWitnesses::default();
+        let values: Vec<_> = (0..4).map(Fr::from).collect();
+        let mut cs = WitnessCS::<Fr>::new();
+        let preimg = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let cs = cs.namespace(|| format!("preimg {i}"));
+                AllocatedNum::alloc(cs, || Ok(*value)).unwrap()
+            })
+            .collect();
+        let constants = store.poseidon_cache.constants.c4();
+        let img = hash_poseidon(&mut cs, preimg, constants).unwrap();
+
+        let (aux, cached_img) = slot_witnesses.get(&store, &values).into_owned();
+        assert_eq!(cached_img, img.get_value().unwrap());
+        assert_eq!(cs.aux_slice()[4..], [aux, vec![cached_img]].concat());
+        let cached = slot_witnesses.get(&store, &values);
+        assert!(matches!(cached, std::borrow::Cow::Borrowed(_)));
+        assert_eq!(slot_witnesses.len(), 1);
     }
 }
@@ -0,0 +1,402 @@
+//! Simplifications of LEM functions that cut the constraints they cost.
+//!
+//! `Func::optimize` first prunes the match arms that can't be taken because the
+//! tag or value of the matched variable is known statically, inlining the arm
+//! that is taken, and then removes the operations none of whose targets are
+//! read. Operations that are constrained to fail on some inputs (`Div`,
+//! `DivRem64`, the signed 64-bit operations, the `AssertRange`, `AssertU32`
+//! and `AssertChar` assertions, `Unhash`, `Open` and coprocessor calls) are
+//! kept even then, since removing them would make the function accept inputs
+//! it used to reject, and so are `Emit`s and calls to functions that have any
+//! of those.
+
+use std::collections::{HashMap, HashSet};
+
+use super::{Block, Ctrl, Func, Lit, Op, Tag, Var};
+use crate::tag::ExprTag;
+
+/// What is known about the variables of a path
+#[derive(Clone, Default)]
+struct Known {
+    tags: HashMap<Var, Tag>,
+    lits: HashMap<Var, Lit>,
+}
+
+impl Known {
+    fn learn(&mut self, op: &Op) {
+        use crate::tag::ExprTag::{Comm, Num, Str};
+        match op {
+            Op::Null(tgt, tag) | Op::Cast(tgt, tag, _) | Op::Hash(tgt, tag, _) => {
+                self.tags.insert(tgt.clone(), *tag);
+            }
+            Op::Lit(tgt, lit) => {
+                match lit {
+                    Lit::Num(..) => self.tags.insert(tgt.clone(), Tag::Expr(Num)),
+                    Lit::String(..) => self.tags.insert(tgt.clone(), Tag::Expr(Str)),
+                    // `nil` doesn't have the symbol tag
+                    Lit::Symbol(..) => None,
+                };
+                self.lits.insert(tgt.clone(), lit.clone());
+            }
+            Op::Hide(tgt, ..) => {
+                self.tags.insert(tgt.clone(), Tag::Expr(Comm));
+            }
+            _ => (),
+        }
+    }
+}
+
+impl Op {
+    /// The variables the operation binds
+    pub(crate) fn targets(&self) -> Vec<&Var> {
+        match self {
+            Op::Call(tgts, ..) | Op::Coproc(_, tgts, _) | Op::Unhash(tgts, _) => {
+                tgts.iter().collect()
+            }
+            Op::Null(tgt, _)
+            | Op::Lit(tgt, _)
+            | Op::Cast(tgt, ..)
+            | Op::EqTag(tgt, ..)
+            | Op::EqVal(tgt, ..)
+            | Op::Add(tgt, ..)
+            | Op::Sub(tgt, ..)
+            | Op::Mul(tgt, ..)
+            | Op::Div(tgt, ..)
+            | Op::Lt(tgt, ..)
+            | Op::Trunc(tgt, ..)
+            | Op::And(tgt, ..)
+            | Op::Or(tgt, ..)
+            | Op::Xor(tgt, ..)
+            | Op::AddI64(tgt, ..)
+            | Op::SubI64(tgt, ..)
+            | Op::MulI64(tgt, ..)
+            | Op::LtI64(tgt, ..)
+            | Op::Hash(tgt, ..)
+            | Op::Hide(tgt, ..) => vec![tgt],
+            Op::DivRem64(tgts, ..) => tgts.iter().collect(),
+            Op::Open(secret, payload, _) => vec![secret, payload],
+            Op::Emit(_) | Op::AssertRange(..) | Op::AssertU32(_) | Op::AssertChar(_) => vec![],
+        }
+    }
+
+    /// The variables the operation reads
+    pub(crate) fn sources(&self) -> Vec<&Var> {
+        match self {
+            Op::Call(_, _, srcs) | Op::Coproc(_, _, srcs) | Op::Hash(_, _, srcs) => {
+                srcs.iter().collect()
+            }
+            Op::Null(..) | Op::Lit(..) => vec![],
+            Op::Cast(_, _, src)
+            | Op::Trunc(_, src, _)
+            | Op::AssertRange(src, _)
+            | Op::AssertU32(src)
+            | Op::AssertChar(src)
+            | Op::Emit(src)
+            | Op::Unhash(_, src)
+            | Op::Open(_, _, src) => vec![src],
+            Op::EqTag(_, a, b)
+            | Op::EqVal(_, a, b)
+            | Op::Add(_, a, b)
+            | Op::Sub(_, a, b)
+            | Op::Mul(_, a, b)
+            | Op::Div(_, a, b)
+            | Op::Lt(_, a, b)
+            | Op::And(_, a, b)
+            | Op::Or(_, a, b)
+            | Op::Xor(_, a, b)
+            | Op::DivRem64(_, a, b)
+            | Op::AddI64(_, a, b)
+            | Op::SubI64(_, a, b)
+            | Op::MulI64(_, a, b)
+            | Op::LtI64(_, a, b)
+            | Op::Hide(_, a, b) => vec![a, b],
+        }
+    }
+
+    /// The variables the operation reads, to be renamed
+    pub(crate) fn sources_mut(&mut self) -> Vec<&mut Var> {
+        match self {
+            Op::Call(_, _, srcs) | Op::Coproc(_, _, srcs) | Op::Hash(_, _, srcs) => {
+                srcs.iter_mut().collect()
+            }
+            Op::Null(..) | Op::Lit(..) => vec![],
+            Op::Cast(_, _, src)
+            | Op::Trunc(_, src, _)
+            | Op::AssertRange(src, _)
+            | Op::AssertU32(src)
+            | Op::AssertChar(src)
+            | Op::Emit(src)
+            | Op::Unhash(_, src)
+            | Op::Open(_, _, src) => vec![src],
+            Op::EqTag(_, a, b)
+            | Op::EqVal(_, a, b)
+            | Op::Add(_, a, b)
+            | Op::Sub(_, a, b)
+            | Op::Mul(_, a, b)
+            | Op::Div(_, a, b)
+            | Op::Lt(_, a, b)
+            | Op::And(_, a, b)
+            | Op::Or(_, a, b)
+            | Op::Xor(_, a, b)
+            | Op::DivRem64(_, a, b)
+            | Op::AddI64(_, a, b)
+            | Op::SubI64(_, a, b)
+            | Op::MulI64(_, a, b)
+            | Op::LtI64(_, a, b)
+            | Op::Hide(_, a, b) => vec![a, b],
+        }
+    }
+
+    /// Whether removing the operation can change more than the values of its
+    /// targets
+    pub(crate) fn has_effects(&self) -> bool {
+        match self {
+            Op::Div(..)
+            | Op::DivRem64(..)
+            | Op::AddI64(..)
+            | Op::SubI64(..)
+            | Op::MulI64(..)
+            | Op::LtI64(..)
+            | Op::Unhash(..)
+            | Op::Open(..)
+            | Op::Emit(..)
+            | Op::AssertRange(..)
+            | Op::AssertU32(..)
+            | Op::AssertChar(..)
+            | Op::Coproc(..) => true,
+            Op::Call(_, func, _) => func.body.has_effects(),
+            _ => false,
+        }
+    }
+}
+
+impl Block {
+    fn has_effects(&self) -> bool {
+        self.ops.iter().any(Op::has_effects)
+            || match &self.ctrl {
+                Ctrl::Return(..) => false,
+                // it fails on pointers that aren't symbols
+                Ctrl::MatchSymbol(..) => true,
+                Ctrl::MatchTag(_, cases, def) => {
+                    cases.values().chain(def.as_deref()).any(Block::has_effects)
+                }
+                Ctrl::MatchVal(_, cases, def) => {
+                    cases.values().chain(def.as_deref()).any(Block::has_effects)
+                }
+                Ctrl::IfEq(_, _, eq_block, else_block) => {
+                    eq_block.has_effects() || else_block.has_effects()
+                }
+            }
+    }
+
+    /// Replaces the controls whose outcome is known by the block they lead to
+    fn prune(self, mut known: Known) -> Block {
+        let mut ops = Vec::with_capacity(self.ops.len());
+        for op in self.ops {
+            let op = match op {
+                Op::Call(tgts, func, srcs) => Op::Call(tgts, Box::new(func.optimize()), srcs),
+                op => op,
+            };
+            known.learn(&op);
+            ops.push(op);
+        }
+        let taken = match &self.ctrl {
+            Ctrl::MatchTag(var, cases, def) => known
+                .tags
+                .get(var)
+                .and_then(|tag| cases.get(tag).or(def.as_deref())),
+            Ctrl::MatchVal(var, cases, def) => known
+                .lits
+                .get(var)
+                .and_then(|lit| cases.get(lit).or(def.as_deref())),
+            // it can only be skipped if it can't fail
+            Ctrl::MatchSymbol(var, cases, def)
+                if known.tags.get(var) == Some(&Tag::Expr(ExprTag::Sym)) =>
+            {
+                match known.lits.get(var) {
+                    Some(Lit::Symbol(sym)) => cases.get(sym).or(def.as_deref()),
+                    _ => None,
+                }
+            }
+            Ctrl::IfEq(x, y, eq_block, _) if x == y => Some(eq_block.as_ref()),
+            _ => None,
+        };
+        if let Some(taken) = taken {
+            let taken = taken.clone().prune(known);
+            ops.extend(taken.ops);
+            return Block {
+                ops,
+                ctrl: taken.ctrl,
+            };
+        }
+        let ctrl = match self.ctrl {
+            Ctrl::MatchTag(var, cases, def) => {
+                let cases = cases
+                    .into_iter()
+                    .map(|(tag, block)| {
+                        let mut known = known.clone();
+                        known.tags.insert(var.clone(), tag);
+                        (tag, block.prune(known))
+                    })
+                    .collect();
+                let def = def.map(|def| Box::new(def.prune(known)));
+                Ctrl::MatchTag(var, cases, def)
+            }
+            Ctrl::MatchVal(var, cases, def) => {
+                let cases = cases
+                    .into_iter()
+                    .map(|(lit, block)| {
+                        let mut known = known.clone();
+                        known.lits.insert(var.clone(), lit.clone());
+                        (lit, block.prune(known))
+                    })
+                    .collect();
+                let def = def.map(|def| Box::new(def.prune(known)));
+                Ctrl::MatchVal(var, cases, def)
+            }
+            Ctrl::MatchSymbol(var, cases, def) => {
+                known.tags.insert(var.clone(), Tag::Expr(ExprTag::Sym));
+                let cases = cases
+                    .into_iter()
+                    .map(|(sym, block)| {
+                        let mut known = known.clone();
+                        known.lits.insert(var.clone(), Lit::Symbol(sym.clone()));
+                        (sym, block.prune(known))
+                    })
+                    .collect();
+                let def = def.map(|def| Box::new(def.prune(known)));
+                Ctrl::MatchSymbol(var, cases, def)
+            }
+            Ctrl::IfEq(x, y, eq_block, else_block) => Ctrl::IfEq(
+                x,
+                y,
+                Box::new(eq_block.prune(known.clone())),
+                Box::new(else_block.prune(known)),
+            ),
+            ctrl @ Ctrl::Return(..) => ctrl,
+        };
+        Block { ops, ctrl }
+    }
+
+    /// Removes the operations whose targets aren't read, adding the variables
+    /// the block reads to `live`. Variables are only bound once, so a single
+    /// set serves all paths.
+    fn eliminate_dead_ops(self, live: &mut HashSet<Var>) -> Block {
+        let ctrl = match self.ctrl {
+            Ctrl::Return(vars) => {
+                live.extend(vars.iter().cloned());
+                Ctrl::Return(vars)
+            }
+            Ctrl::MatchTag(var, cases, def) => {
+                let cases = cases
+                    .into_iter()
+                    .map(|(tag, block)| (tag, block.eliminate_dead_ops(live)))
+                    .collect();
+                let def = def.map(|def| Box::new(def.eliminate_dead_ops(live)));
+                live.insert(var.clone());
+                Ctrl::MatchTag(var, cases, def)
+            }
+            Ctrl::MatchVal(var, cases, def) => {
+                let cases = cases
+                    .into_iter()
+                    .map(|(lit, block)| (lit, block.eliminate_dead_ops(live)))
+                    .collect();
+                let def = def.map(|def| Box::new(def.eliminate_dead_ops(live)));
+                live.insert(var.clone());
+                Ctrl::MatchVal(var, cases, def)
+            }
+            Ctrl::MatchSymbol(var, cases, def) => {
+                let cases = cases
+                    .into_iter()
+                    .map(|(sym, block)| (sym, block.eliminate_dead_ops(live)))
+                    .collect();
+                let def = def.map(|def| Box::new(def.eliminate_dead_ops(live)));
+                live.insert(var.clone());
+                Ctrl::MatchSymbol(var, cases, def)
+            }
+            Ctrl::IfEq(x, y, eq_block, else_block) => {
+                let eq_block = Box::new(eq_block.eliminate_dead_ops(live));
+                let else_block = Box::new(else_block.eliminate_dead_ops(live));
+                live.insert(x.clone());
+                live.insert(y.clone());
+                Ctrl::IfEq(x, y, eq_block, else_block)
+            }
+        };
+        let mut ops = Vec::with_capacity(self.ops.len());
+        for op in self.ops.into_iter().rev() {
+            if op.has_effects() || op.targets().into_iter().any(|tgt| live.contains(tgt)) {
+                live.extend(op.sources().into_iter().cloned());
+                ops.push(op);
+            }
+        }
+        ops.reverse();
+        Block { ops, ctrl }
+    }
+}
+
+impl Func {
+    /// Returns an equivalent function without the match arms that can't be
+    /// taken and without the effect-free operations whose results aren't used
+    /// (see `Op::has_effects` for the ones that are always kept), with its
+    /// slots counted anew. Since it changes the shape of the circuit, and
+    /// thus the public parameters, it's up to the caller to apply it.
+    pub fn optimize(&self) -> Func {
+        let body = self
+            .body
+            .clone()
+            .prune(Known::default())
+            .eliminate_dead_ops(&mut HashSet::new());
+        // Some variables may not be used anymore, so `Func::new` would reject
+        // the function
+        Func {
+            slot: body.count_slots_sharing(self.share_slots),
+            body,
+            ..self.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::LurkField;
+    use crate::func;
+    use crate::lem::{interpreter::Preimages, pointers::Ptr, slot::SlotsCounter, store::Store};
+    use crate::tag::ExprTag::Cons;
+    use blstrs::Scalar as Fr;
+
+    #[test]
+    fn dead_code_is_eliminated() {
+        let lem = func!(f(x): 1 => {
+            let t: Expr::Num;
+            let unused = add(x, x);
+            let h: Expr::Cons = hash2(unused, x);
+            let _e: Expr::Cons = hash3(x, x, h);
+            let (_a, _b) = unhash2(x);
+            match t.tag {
+                Expr::Num => {
+                    return (x);
+                }
+                Expr::Char => {
+                    let y: Expr::Cons = hash4(x, x, x, x);
+                    return (y);
+                }
+            }
+        });
+        let opt = lem.optimize();
+        // Only the unhash, which fails on some inputs, is left
+        assert_eq!(opt.slot, SlotsCounter::new((1, 0, 0, 0, 0, 0, 0, 0, 0)));
+        assert_eq!(opt.body.ops.len(), 1);
+        assert!(matches!(opt.body.ctrl, Ctrl::Return(..)));
+
+        let store = &mut Store::<Fr>::default();
+        let (one, two) = (Ptr::num(Fr::from_u64(1)), Ptr::num(Fr::from_u64(2)));
+        let x = store.intern_2_ptrs(Tag::Expr(Cons), one, two);
+        let (frame, _) = opt
+            .call(vec![x], store, Preimages::new_from_func(&opt))
+            .unwrap();
+        assert_eq!(frame.output, vec![x]);
+        assert!(opt.num_constraints(store) < lem.num_constraints(store));
+        opt.assert_num_constraints(store);
+    }
+}
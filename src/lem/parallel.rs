@@ -0,0 +1,199 @@
+//! Evaluating independent expressions in parallel.
+//!
+//! The frames of a single evaluation can't be computed out of order: each one
+//! takes the continuation left by the previous one, and that continuation
+//! holds the values of the sub-expressions evaluated so far. Expressions that
+//! don't depend on each other, such as the applications of a pure function to
+//! each element of a list, can be evaluated at the same time, though.
+//! `evaluate_parallel` evaluates each of them on its own thread, in a fork of
+//! the store, and then interns the frames of each evaluation back into the
+//! store, in the order the expressions were given. Evaluation is
+//! deterministic, so the frames are the ones `evaluate_within` would have
+//! computed on the store, and each evaluation is proven on its own, as it
+//! would have been.
+//!
+//! The parallelism is across evaluations only. The sub-expressions of a single
+//! evaluation, like the arguments of a call, are still evaluated one after the
+//! other, as the frames of a provable trace must be, so mapping a function over
+//! a list in parallel means evaluating each application as an expression of
+//! its own.
+
+use anyhow::Result;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+use crate::field::{FWrap, LurkField};
+use crate::tag::ExprTag::Comm;
+
+use super::{
+    checkpoint::EvalCheckpoint,
+    eval::{eval_step, evaluate_within},
+    interpreter::{Budget, Frame, Halt},
+    pointers::Ptr,
+    replay::{globalize, intern_tuples, localize, map_frame},
+    store::Store,
+    Tag,
+};
+
+/// The frames of an evaluation, its last output and how it halted, as
+/// returned by `evaluate_within`
+pub type Evaluation<F> = (Vec<Frame<F>>, Vec<Ptr<F>>, Halt);
+
+/// An evaluation made in a fork of the store, whose pointers index `tuples`
+/// rather than the fork, so that it can be interned in another store
+struct Branch<F: LurkField> {
+    /// The children of the pointers reachable from the evaluation, each one
+    /// after the pointers among its children
+    tuples: Vec<Vec<Ptr<F>>>,
+    /// The hash, secret and payload of each commitment the evaluation refers
+    /// to, directly or through the payloads of other commitments
+    comms: Vec<(F, F, Ptr<F>)>,
+    frames: Vec<Frame<F>>,
+    output: Vec<Ptr<F>>,
+    halt: Halt,
+}
+
+impl<F: LurkField> Branch<F> {
+    fn new(evaluation: Evaluation<F>, store: &Store<F>) -> Result<Self> {
+        let (frames, output, halt) = evaluation;
+        let mut tuples = vec![];
+        let mut indices = HashMap::new();
+        let mut referenced = vec![];
+        let mut local = |ptr: &Ptr<F>| {
+            referenced.push(*ptr);
+            localize(ptr, store, &mut tuples, &mut indices)
+        };
+        let frames = frames
+            .iter()
+            .map(|frame| map_frame(frame, &mut local))
+            .collect::<Result<_>>()?;
+        let output = output.iter().map(&mut local).collect::<Result<_>>()?;
+
+        // only the commitments the evaluation refers to are exported, rather
+        // than all those the fork made. They're leaves, so the payloads they
+        // open to have to be looked for among the pointers found so far, and
+        // their own children, as `EvalCheckpoint::new` does.
+        let mut comms = vec![];
+        let mut seen = HashSet::new();
+        let mut pending = referenced;
+        let mut scanned = 0;
+        loop {
+            pending.extend(tuples[scanned..].iter().flatten());
+            scanned = tuples.len();
+            let Some(ptr) = pending.pop() else {
+                break;
+            };
+            let Ptr::Leaf(Tag::Expr(Comm), hash) = ptr else {
+                continue;
+            };
+            if !seen.insert(FWrap(hash)) {
+                continue;
+            }
+            if let Some((secret, payload)) = store.comms.get(&FWrap(hash)) {
+                let payload = localize(payload, store, &mut tuples, &mut indices)?;
+                comms.push((hash, *secret, payload));
+                pending.push(payload);
+            }
+        }
+        Ok(Self {
+            tuples,
+            comms,
+            frames,
+            output,
+            halt,
+        })
+    }
+
+    /// Interns the evaluation in `store` and returns it, pointing there
+    fn merge(self, store: &mut Store<F>) -> Result<Evaluation<F>> {
+        let interned = intern_tuples(&self.tuples, store)?;
+        let mut global = |ptr: &Ptr<F>| globalize(ptr, &interned);
+        for (hash, secret, payload) in &self.comms {
+            let payload = global(payload)?;
+            store.comms.insert(FWrap(*hash), (*secret, payload));
+        }
+        let frames = self
+            .frames
+            .iter()
+            .map(|frame| map_frame(frame, &mut global))
+            .collect::<Result<_>>()?;
+        let output = self.output.iter().map(&mut global).collect::<Result<_>>()?;
+        Ok((frames, output, self.halt))
+    }
+}
+
+/// Evaluates each of `exprs` in `env` within `budget`, in parallel, and
+/// returns their evaluations, interned in `store`, in the order of `exprs`
+pub fn evaluate_parallel<F: LurkField>(
+    exprs: &[Ptr<F>],
+    env: Ptr<F>,
+    store: &mut Store<F>,
+    budget: Budget,
+) -> Result<Vec<Evaluation<F>>> {
+    let step = eval_step();
+    let digest = step.digest()?;
+    let inputs = exprs
+        .iter()
+        .map(|expr| {
+            Ok((
+                EvalCheckpoint::with_digest(&step.name, digest.clone(), 0, &[*expr, env], store)?,
+                store.fork(),
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let branches = inputs
+        .into_par_iter()
+        .map(|(checkpoint, mut fork)| {
+            let args = checkpoint.restore(&mut fork)?;
+            let evaluation = evaluate_within(args[0], args[1], &mut fork, budget)?;
+            Branch::new(evaluation, &fork)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    // merging in order makes the store's indices independent of scheduling
+    branches
+        .into_iter()
+        .map(|branch| branch.merge(store))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{lurk_sym, State};
+    use blstrs::Scalar as Fr;
+
+    #[test]
+    fn independent_expressions_are_evaluated_in_parallel() {
+        let store = &mut Store::<Fr>::default();
+        let state = State::init_lurk_state().rccell();
+        let nil = store.intern_symbol(&lurk_sym("nil"));
+        let exprs = [
+            "(let ((square (lambda (x) (* x x)))) (square 3))",
+            "(let ((square (lambda (x) (* x x)))) (square 4))",
+            "(open (commit 5))",
+            "(car 1)",
+        ]
+        .map(|src| store.read(state.clone(), src).unwrap());
+
+        let evaluations = evaluate_parallel(&exprs, nil, store, Budget::unlimited()).unwrap();
+        assert_eq!(evaluations.len(), exprs.len());
+        // only the commitment to 5 was made
+        assert_eq!(store.comms.len(), 1);
+        let outputs: Vec<_> = evaluations.iter().map(|(_, output, _)| output[0]).collect();
+        let expected = [9, 16, 5].map(|n| Ptr::num(Fr::from(n)));
+        assert_eq!(outputs[..3], expected);
+
+        // the frames are the ones a sequential evaluation computes
+        for (expr, (frames, output, halt)) in exprs.iter().zip(&evaluations) {
+            let (expected, expected_output, expected_halt) =
+                evaluate_within(*expr, nil, store, Budget::unlimited()).unwrap();
+            assert_eq!(frames.len(), expected.len());
+            for (frame, expected) in frames.iter().zip(&expected) {
+                assert_eq!(frame.input, expected.input);
+                assert_eq!(frame.output, expected.output);
+            }
+            assert_eq!(output, &expected_output);
+            assert_eq!(halt, &expected_halt);
+        }
+    }
+}
@@ -0,0 +1,1075 @@
+//! A textual syntax for LEM, so functions can be kept in `.lem` files, diffed
+//! and loaded at runtime.
+//!
+//! The syntax is the one of the `func!` macro. A source is a sequence of
+//! functions, each of which may call the ones defined before it:
+//!
+//! ```text
+//! // doubles numbers
+//! double(x): 1 => {
+//!     match x.tag {
+//!         Expr::Num => {
+//!             let y = add(x, x);
+//!             return (y);
+//!         }
+//!     };
+//!     let nil = Symbol("nil");
+//!     return (nil);
+//! }
+//!
+//! main(x): 1 => {
+//!     let (y) = double(x);
+//!     return (y);
+//! }
+//! ```
+//!
+//! Functions are printed back in the same syntax by their `Display`
+//! implementation, preceded by the functions they call. Since parsing goes
+//! through `Func::new`, variables are renamed on every round trip.
+
+use anyhow::{anyhow, bail, Context, Result};
+use indexmap::IndexMap;
+use std::{collections::HashMap, fmt, hash::Hash, iter::Peekable, str::Chars, str::FromStr};
+
+use super::{Block, Ctrl, CtrlTag, Func, Lit, Op, Tag, Var, HASH_ARITIES};
+use crate::{
+    state::lurk_sym,
+    symbol::Symbol,
+    tag::{ContTag, ExprTag, UserContTag},
+};
+
+/// The operations binding several variables, whose names can't be taken by
+/// functions
+const MULTI_TARGET_OPS: [&str; 8] = [
+    "div_rem64",
+    "unhash2",
+    "unhash3",
+    "unhash4",
+    "unhash6",
+    "unhash8",
+    "open",
+    "coproc",
+];
+
+const PUNCTUATION: [&str; 10] = ["(", ")", "{", "}", ",", ";", ":", "=", "|", "."];
+
+#[derive(Clone, Copy, Debug)]
+struct Pos {
+    line: usize,
+    column: usize,
+}
+
+impl fmt::Display for Pos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Num(u128),
+    Str(String),
+    Punct(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ident(ident) => write!(f, "`{ident}`"),
+            Self::Num(num) => write!(f, "`{num}`"),
+            Self::Str(string) => write!(f, "{string:?}"),
+            Self::Punct(punct) => write!(f, "`{punct}`"),
+        }
+    }
+}
+
+struct Lexer<'a> {
+    chars: Peekable<Chars<'a>>,
+    pos: Pos,
+}
+
+impl<'a> Lexer<'a> {
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.pos.line += 1;
+            self.pos.column = 1;
+        } else {
+            self.pos.column += 1;
+        }
+        Some(c)
+    }
+
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> String {
+        let mut taken = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if !pred(c) {
+                break;
+            }
+            taken.push(c);
+            self.bump();
+        }
+        taken
+    }
+
+    /// Reads the rest of a string whose opening quote started at `start`
+    fn string(&mut self, start: Pos) -> Result<String> {
+        let mut string = String::new();
+        loop {
+            let c = match self.bump() {
+                None => bail!("Unterminated string at {start}"),
+                Some('"') => return Ok(string),
+                Some('\\') => match self.bump() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    Some('0') => '\0',
+                    Some(c @ ('\\' | '"' | '\'')) => c,
+                    Some('u') if self.bump() == Some('{') => {
+                        let hex = self.take_while(|c| c != '}');
+                        self.bump();
+                        u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| anyhow!("Invalid unicode escape in string at {start}"))?
+                    }
+                    _ => bail!("Invalid escape in string at {start}"),
+                },
+                Some(c) => c,
+            };
+            string.push(c);
+        }
+    }
+
+    /// Returns the tokens with their positions, along with the position of the
+    /// end of the source
+    fn tokenize(mut self) -> Result<(Vec<(Token, Pos)>, Pos)> {
+        let mut tokens = vec![];
+        while let Some(&c) = self.chars.peek() {
+            let pos = self.pos;
+            let token = if c.is_whitespace() {
+                self.bump();
+                continue;
+            } else if c == '/' {
+                self.bump();
+                if self.bump() != Some('/') {
+                    bail!("Unexpected `/` at {pos}")
+                }
+                self.take_while(|c| c != '\n');
+                continue;
+            } else if c.is_ascii_alphabetic() || c == '_' {
+                Token::Ident(self.take_while(|c| c.is_ascii_alphanumeric() || c == '_' || c == '#'))
+            } else if c.is_ascii_digit() {
+                let digits = self.take_while(|c| c.is_ascii_digit());
+                let num = digits
+                    .parse()
+                    .with_context(|| format!("Invalid number at {pos}"))?;
+                Token::Num(num)
+            } else if c == '"' {
+                self.bump();
+                Token::Str(self.string(pos)?)
+            } else {
+                self.bump();
+                let punct = match (c, self.chars.peek()) {
+                    (':', Some(':')) => Some("::"),
+                    ('=', Some('=')) => Some("=="),
+                    ('!', Some('=')) => Some("!="),
+                    ('=', Some('>')) => Some("=>"),
+                    _ => None,
+                };
+                match punct {
+                    Some(punct) => {
+                        self.bump();
+                        Token::Punct(punct)
+                    }
+                    None => match PUNCTUATION.iter().find(|p| p.starts_with(c)) {
+                        Some(punct) => Token::Punct(*punct),
+                        None => bail!("Unexpected `{c}` at {pos}"),
+                    },
+                }
+            };
+            tokens.push((token, pos));
+        }
+        Ok((tokens, self.pos))
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, Pos)>,
+    end: Pos,
+    idx: usize,
+    /// The functions parsed so far, which can be called
+    funcs: HashMap<String, Func>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.idx).map(|(token, _)| token)
+    }
+
+    fn pos(&self) -> Pos {
+        self.tokens.get(self.idx).map_or(self.end, |(_, pos)| *pos)
+    }
+
+    fn unexpected<T>(&self, expected: &str) -> Result<T> {
+        match self.tokens.get(self.idx) {
+            Some((token, pos)) => bail!("Expected {expected} at {pos}, found {token}"),
+            None => bail!("Expected {expected}, found the end of the source"),
+        }
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Punct(p)) if *p == punct) {
+            self.idx += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<()> {
+        if !self.eat(punct) {
+            return self.unexpected(&format!("`{punct}`"));
+        }
+        Ok(())
+    }
+
+    fn ident(&mut self) -> Result<String> {
+        match self.peek() {
+            Some(Token::Ident(ident)) => {
+                let ident = ident.clone();
+                self.idx += 1;
+                Ok(ident)
+            }
+            _ => self.unexpected("an identifier"),
+        }
+    }
+
+    fn num(&mut self) -> Result<u128> {
+        match self.peek() {
+            Some(Token::Num(num)) => {
+                let num = *num;
+                self.idx += 1;
+                Ok(num)
+            }
+            _ => self.unexpected("a number"),
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        match self.peek() {
+            Some(Token::Str(string)) => {
+                let string = string.clone();
+                self.idx += 1;
+                Ok(string)
+            }
+            _ => self.unexpected("a string"),
+        }
+    }
+
+    fn var(&mut self) -> Result<Var> {
+        Ok(Var(self.ident()?.into()))
+    }
+
+    /// Parses `(x, y, ...)`
+    fn vars(&mut self) -> Result<Vec<Var>> {
+        self.expect("(")?;
+        let mut vars = vec![];
+        if self.eat(")") {
+            return Ok(vars);
+        }
+        loop {
+            vars.push(self.var()?);
+            if self.eat(")") {
+                return Ok(vars);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    /// Parses exactly `N` variables between parentheses
+    fn args<const N: usize>(&mut self) -> Result<[Var; N]> {
+        let pos = self.pos();
+        exactly(self.vars()?, pos)
+    }
+
+    fn tag(&mut self) -> Result<Tag> {
+        let pos = self.pos();
+        let kind = self.ident()?;
+        self.expect("::")?;
+        let name = self.ident()?;
+        let is_named = |tag: &dyn fmt::Debug| format!("{tag:?}") == name;
+        let tag = match kind.as_str() {
+            "Expr" => (0u16..)
+                .map_while(|n| ExprTag::try_from(n).ok())
+                .find(|tag| is_named(tag))
+                .map(Tag::Expr),
+            "Cont" if name == "User" => {
+                self.expect("(")?;
+                let index = self.num()?;
+                self.expect(")")?;
+                let index = u16::try_from(index).ok();
+                index.and_then(UserContTag::new).map(Tag::UserCont)
+            }
+            "Cont" => (ContTag::Outermost as u16..)
+                .map_while(|n| ContTag::try_from(n).ok())
+                .find(|tag| is_named(tag))
+                .map(Tag::Cont),
+            "Ctrl" => [
+                CtrlTag::Return,
+                CtrlTag::MakeThunk,
+                CtrlTag::ApplyContinuation,
+                CtrlTag::Error,
+            ]
+            .into_iter()
+            .find(|tag| is_named(tag))
+            .map(Tag::Ctrl),
+            _ => None,
+        };
+        tag.ok_or_else(|| anyhow!("Unknown tag {kind}::{name} at {pos}"))
+    }
+
+    fn lit(&mut self) -> Result<Lit> {
+        let pos = self.pos();
+        let lit = match self.ident()?.as_str() {
+            "Num" => {
+                self.expect("(")?;
+                Lit::Num(self.num()?)
+            }
+            "String" => {
+                self.expect("(")?;
+                Lit::String(self.string()?)
+            }
+            "Symbol" => {
+                self.expect("(")?;
+                Lit::Symbol(self.symbol()?)
+            }
+            other => bail!("Expected `Num`, `String` or `Symbol` at {pos}, found `{other}`"),
+        };
+        self.expect(")")?;
+        Ok(lit)
+    }
+
+    /// A symbol, written as a string holding its name if it's in the `lurk`
+    /// package or as the string of its full path otherwise
+    fn symbol(&mut self) -> Result<Symbol> {
+        let pos = self.pos();
+        let name = self.string()?;
+        if name.starts_with(['.', ':', '~']) {
+            Symbol::from_str_impl(&name).ok_or_else(|| anyhow!("Invalid symbol {name:?} at {pos}"))
+        } else {
+            Ok(lurk_sym(&name))
+        }
+    }
+
+    fn func(&mut self) -> Result<Func> {
+        let pos = self.pos();
+        let name = self.ident()?;
+        if MULTI_TARGET_OPS.contains(&name.as_str()) {
+            bail!("Function at {pos} can't be named `{name}`")
+        }
+        let input_params = self.vars()?;
+        self.expect(":")?;
+        let output_size = self.num()? as usize;
+        self.expect("=>")?;
+        let body = self.block()?;
+        let func = Func::new(name.clone(), input_params, output_size, body)
+            .with_context(|| format!("Invalid function `{name}` at {pos}"))?;
+        self.funcs.insert(name, func.clone());
+        Ok(func)
+    }
+
+    fn block(&mut self) -> Result<Block> {
+        self.expect("{")?;
+        let block = self.seq()?;
+        self.expect("}")?;
+        Ok(block)
+    }
+
+    /// Parses operations up to and including a control
+    fn seq(&mut self) -> Result<Block> {
+        let mut ops = vec![];
+        loop {
+            let pos = self.pos();
+            let ctrl = match self.ident()?.as_str() {
+                "let" => {
+                    ops.push(self.binding()?);
+                    continue;
+                }
+                "emit" => {
+                    let [var] = self.args()?;
+                    self.expect(";")?;
+                    ops.push(Op::Emit(var));
+                    continue;
+                }
+                "assert_range" => {
+                    self.expect("(")?;
+                    let var = self.var()?;
+                    self.expect(",")?;
+                    let bits = u32::try_from(self.num()?)
+                        .with_context(|| format!("Too many bits at {pos}"))?;
+                    self.expect(")")?;
+                    self.expect(";")?;
+                    ops.push(Op::AssertRange(var, bits));
+                    continue;
+                }
+                "assert_u32" => {
+                    let [var] = self.args()?;
+                    self.expect(";")?;
+                    ops.push(Op::AssertU32(var));
+                    continue;
+                }
+                "assert_char" => {
+                    let [var] = self.args()?;
+                    self.expect(";")?;
+                    ops.push(Op::AssertChar(var));
+                    continue;
+                }
+                "return" => {
+                    let vars = self.vars()?;
+                    self.eat(";");
+                    Ctrl::Return(vars)
+                }
+                "match" => {
+                    let var = self.var()?;
+                    self.expect(".")?;
+                    match self.ident()?.as_str() {
+                        "tag" => {
+                            let cases = self.cases(Self::tag)?;
+                            Ctrl::MatchTag(var, cases, self.default()?)
+                        }
+                        "val" => {
+                            let cases = self.cases(Self::lit)?;
+                            Ctrl::MatchVal(var, cases, self.default()?)
+                        }
+                        "symbol" => {
+                            let cases = self.cases(Self::symbol)?;
+                            Ctrl::MatchSymbol(var, cases, self.default()?)
+                        }
+                        other => {
+                            bail!("Expected `tag`, `val` or `symbol` at {pos}, found `{other}`")
+                        }
+                    }
+                }
+                "if" => {
+                    let x = self.var()?;
+                    let eq = if self.eat("==") {
+                        true
+                    } else if self.eat("!=") {
+                        false
+                    } else {
+                        return self.unexpected("`==` or `!=`");
+                    };
+                    let y = self.var()?;
+                    let then_block = Box::new(self.block()?);
+                    let else_block = Box::new(self.seq()?);
+                    if eq {
+                        Ctrl::IfEq(x, y, then_block, else_block)
+                    } else {
+                        Ctrl::IfEq(x, y, else_block, then_block)
+                    }
+                }
+                other => bail!("Expected an operation or a control at {pos}, found `{other}`"),
+            };
+            return Ok(Block { ops, ctrl });
+        }
+    }
+
+    fn cases<K: Eq + Hash>(
+        &mut self,
+        key: fn(&mut Self) -> Result<K>,
+    ) -> Result<IndexMap<K, Block>> {
+        self.expect("{")?;
+        let mut cases = IndexMap::new();
+        while !self.eat("}") {
+            let pos = self.pos();
+            let mut keys = vec![key(self)?];
+            while self.eat("|") {
+                keys.push(key(self)?);
+            }
+            self.expect("=>")?;
+            let block = self.block()?;
+            for key in keys {
+                if cases.insert(key, block.clone()).is_some() {
+                    bail!("Repeated case at {pos}")
+                }
+            }
+        }
+        Ok(cases)
+    }
+
+    /// The default case of a match, made of what follows it after a `;`
+    fn default(&mut self) -> Result<Option<Box<Block>>> {
+        if self.eat(";") {
+            Ok(Some(Box::new(self.seq()?)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Parses what follows a `let`
+    fn binding(&mut self) -> Result<Op> {
+        let pos = self.pos();
+        let op = if self.peek() == Some(&Token::Punct("(")) {
+            let tgts = self.vars()?;
+            self.expect("=")?;
+            let name = self.ident()?;
+            match name.as_str() {
+                "div_rem64" => {
+                    let [a, b] = self.args()?;
+                    Op::DivRem64(exactly(tgts, pos)?, a, b)
+                }
+                "open" => {
+                    let [secret, payload] = exactly(tgts, pos)?;
+                    Op::Open(secret, payload, self.var_arg()?)
+                }
+                "coproc" => {
+                    let coproc = self.ident()?;
+                    Op::Coproc(coproc.into(), tgts, self.vars()?)
+                }
+                _ => {
+                    if let Some(arity) = hash_arity(&name, "unhash") {
+                        Op::Unhash(exactly_n(tgts, arity, pos)?, self.var_arg()?)
+                    } else {
+                        let Some(func) = self.funcs.get(&name) else {
+                            bail!("Unknown function `{name}` at {pos}")
+                        };
+                        let func = Box::new(func.clone());
+                        Op::Call(tgts, func, self.vars()?)
+                    }
+                }
+            }
+        } else {
+            let tgt = self.var()?;
+            if self.eat(":") {
+                let tag = self.tag()?;
+                if self.eat("=") {
+                    let name = self.ident()?;
+                    let Some(arity) = hash_arity(&name, "hash") else {
+                        bail!("Expected a hash at {pos}, found `{name}`")
+                    };
+                    let args_pos = self.pos();
+                    Op::Hash(tgt, tag, exactly_n(self.vars()?, arity, args_pos)?)
+                } else {
+                    Op::Null(tgt, tag)
+                }
+            } else {
+                self.expect("=")?;
+                let name_pos = self.pos();
+                match self.ident()?.as_str() {
+                    "Num" | "String" | "Symbol" => {
+                        self.idx -= 1;
+                        Op::Lit(tgt, self.lit()?)
+                    }
+                    "cast" => {
+                        self.expect("(")?;
+                        let src = self.var()?;
+                        self.expect(",")?;
+                        let tag = self.tag()?;
+                        self.expect(")")?;
+                        Op::Cast(tgt, tag, src)
+                    }
+                    "truncate" => {
+                        self.expect("(")?;
+                        let src = self.var()?;
+                        self.expect(",")?;
+                        let bits = u32::try_from(self.num()?)
+                            .with_context(|| format!("Too many bits at {pos}"))?;
+                        self.expect(")")?;
+                        Op::Trunc(tgt, src, bits)
+                    }
+                    "eq_tag" => {
+                        let [a, b] = self.args()?;
+                        Op::EqTag(tgt, a, b)
+                    }
+                    "eq_val" => {
+                        let [a, b] = self.args()?;
+                        Op::EqVal(tgt, a, b)
+                    }
+                    "add" => {
+                        let [a, b] = self.args()?;
+                        Op::Add(tgt, a, b)
+                    }
+                    "sub" => {
+                        let [a, b] = self.args()?;
+                        Op::Sub(tgt, a, b)
+                    }
+                    "mul" => {
+                        let [a, b] = self.args()?;
+                        Op::Mul(tgt, a, b)
+                    }
+                    "div" => {
+                        let [a, b] = self.args()?;
+                        Op::Div(tgt, a, b)
+                    }
+                    "lt" => {
+                        let [a, b] = self.args()?;
+                        Op::Lt(tgt, a, b)
+                    }
+                    "and" => {
+                        let [a, b] = self.args()?;
+                        Op::And(tgt, a, b)
+                    }
+                    "or" => {
+                        let [a, b] = self.args()?;
+                        Op::Or(tgt, a, b)
+                    }
+                    "xor" => {
+                        let [a, b] = self.args()?;
+                        Op::Xor(tgt, a, b)
+                    }
+                    "add_i64" => {
+                        let [a, b] = self.args()?;
+                        Op::AddI64(tgt, a, b)
+                    }
+                    "sub_i64" => {
+                        let [a, b] = self.args()?;
+                        Op::SubI64(tgt, a, b)
+                    }
+                    "mul_i64" => {
+                        let [a, b] = self.args()?;
+                        Op::MulI64(tgt, a, b)
+                    }
+                    "lt_i64" => {
+                        let [a, b] = self.args()?;
+                        Op::LtI64(tgt, a, b)
+                    }
+                    "hide" => {
+                        let [secret, payload] = self.args()?;
+                        Op::Hide(tgt, secret, payload)
+                    }
+                    other => bail!("Unknown operation `{other}` at {name_pos}"),
+                }
+            }
+        };
+        self.expect(";")?;
+        Ok(op)
+    }
+
+    fn var_arg(&mut self) -> Result<Var> {
+        let [var] = self.args()?;
+        Ok(var)
+    }
+}
+
+fn exactly<const N: usize>(vars: Vec<Var>, pos: Pos) -> Result<[Var; N]> {
+    let len = vars.len();
+    vars.try_into()
+        .map_err(|_| anyhow!("Expected {N} variables at {pos}, found {len}"))
+}
+
+fn exactly_n(vars: Vec<Var>, n: usize, pos: Pos) -> Result<Vec<Var>> {
+    if vars.len() != n {
+        bail!("Expected {n} variables at {pos}, found {}", vars.len())
+    }
+    Ok(vars)
+}
+
+/// The arity of the hashing operation `name`, which is `prefix` followed by
+/// one of `HASH_ARITIES`
+fn hash_arity(name: &str, prefix: &str) -> Option<usize> {
+    let suffix = name.strip_prefix(prefix)?;
+    HASH_ARITIES
+        .into_iter()
+        .find(|arity| arity.to_string() == suffix)
+}
+
+/// Parses the functions of a LEM source, in order
+pub fn parse(src: &str) -> Result<Vec<Func>> {
+    let lexer = Lexer {
+        chars: src.chars().peekable(),
+        pos: Pos { line: 1, column: 1 },
+    };
+    let (tokens, end) = lexer.tokenize()?;
+    let mut parser = Parser {
+        tokens,
+        end,
+        idx: 0,
+        funcs: HashMap::new(),
+    };
+    let mut funcs = vec![];
+    while parser.peek().is_some() {
+        funcs.push(parser.func()?);
+    }
+    Ok(funcs)
+}
+
+impl FromStr for Func {
+    type Err = anyhow::Error;
+
+    /// Parses a LEM source, returning its last function
+    fn from_str(src: &str) -> Result<Self> {
+        match parse(src)?.pop() {
+            Some(func) => Ok(func),
+            None => bail!("No function to parse"),
+        }
+    }
+}
+
+struct TagSyntax<'a>(&'a Tag);
+
+impl fmt::Display for TagSyntax<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Tag::Expr(tag) => write!(f, "Expr::{tag:?}"),
+            Tag::Cont(tag) => write!(f, "Cont::{tag:?}"),
+            Tag::Ctrl(tag) => write!(f, "Ctrl::{tag:?}"),
+            Tag::UserCont(tag) => write!(f, "Cont::User({})", tag.index()),
+        }
+    }
+}
+
+struct LitSyntax<'a>(&'a Lit);
+
+impl fmt::Display for LitSyntax<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Lit::Num(num) => write!(f, "Num({num})"),
+            Lit::String(string) => write!(f, "String({string:?})"),
+            Lit::Symbol(symbol) => write!(f, "Symbol({})", SymbolSyntax(symbol)),
+        }
+    }
+}
+
+struct SymbolSyntax<'a>(&'a Symbol);
+
+impl fmt::Display for SymbolSyntax<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.path().last() {
+            Some(name) if *self.0 == lurk_sym(name) && !name.starts_with(['.', ':', '~']) => {
+                write!(f, "{name:?}")
+            }
+            _ => write!(f, "{:?}", self.0.fmt_to_string()),
+        }
+    }
+}
+
+struct Vars<'a>(&'a [Var]);
+
+impl fmt::Display for Vars<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(")?;
+        for (i, var) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{var}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Call(tgts, func, srcs) => {
+                write!(f, "let {} = {}{};", Vars(tgts), func.name, Vars(srcs))
+            }
+            Op::Null(tgt, tag) => write!(f, "let {tgt}: {};", TagSyntax(tag)),
+            Op::Lit(tgt, lit) => write!(f, "let {tgt} = {};", LitSyntax(lit)),
+            Op::Cast(tgt, tag, src) => write!(f, "let {tgt} = cast({src}, {});", TagSyntax(tag)),
+            Op::EqTag(tgt, a, b) => write!(f, "let {tgt} = eq_tag({a}, {b});"),
+            Op::EqVal(tgt, a, b) => write!(f, "let {tgt} = eq_val({a}, {b});"),
+            Op::Add(tgt, a, b) => write!(f, "let {tgt} = add({a}, {b});"),
+            Op::Sub(tgt, a, b) => write!(f, "let {tgt} = sub({a}, {b});"),
+            Op::Mul(tgt, a, b) => write!(f, "let {tgt} = mul({a}, {b});"),
+            Op::Div(tgt, a, b) => write!(f, "let {tgt} = div({a}, {b});"),
+            Op::Lt(tgt, a, b) => write!(f, "let {tgt} = lt({a}, {b});"),
+            Op::Trunc(tgt, a, n) => write!(f, "let {tgt} = truncate({a}, {n});"),
+            Op::And(tgt, a, b) => write!(f, "let {tgt} = and({a}, {b});"),
+            Op::Or(tgt, a, b) => write!(f, "let {tgt} = or({a}, {b});"),
+            Op::Xor(tgt, a, b) => write!(f, "let {tgt} = xor({a}, {b});"),
+            Op::DivRem64(tgts, a, b) => write!(f, "let {} = div_rem64({a}, {b});", Vars(tgts)),
+            Op::AddI64(tgt, a, b) => write!(f, "let {tgt} = add_i64({a}, {b});"),
+            Op::SubI64(tgt, a, b) => write!(f, "let {tgt} = sub_i64({a}, {b});"),
+            Op::MulI64(tgt, a, b) => write!(f, "let {tgt} = mul_i64({a}, {b});"),
+            Op::LtI64(tgt, a, b) => write!(f, "let {tgt} = lt_i64({a}, {b});"),
+            Op::AssertRange(var, n) => write!(f, "assert_range({var}, {n});"),
+            Op::AssertU32(var) => write!(f, "assert_u32({var});"),
+            Op::AssertChar(var) => write!(f, "assert_char({var});"),
+            Op::Emit(var) => write!(f, "emit({var});"),
+            Op::Hash(img, tag, preimg) => {
+                let (tag, arity) = (TagSyntax(tag), preimg.len());
+                write!(f, "let {img}: {tag} = hash{arity}{};", Vars(preimg))
+            }
+            Op::Unhash(preimg, img) => {
+                write!(f, "let {} = unhash{}({img});", Vars(preimg), preimg.len())
+            }
+            Op::Hide(tgt, secret, payload) => write!(f, "let {tgt} = hide({secret}, {payload});"),
+            Op::Open(secret, payload, comm) => {
+                write!(f, "let ({secret}, {payload}) = open({comm});")
+            }
+            Op::Coproc(name, tgts, srcs) => {
+                write!(f, "let {} = coproc {name}{};", Vars(tgts), Vars(srcs))
+            }
+        }
+    }
+}
+
+impl Block {
+    /// Writes the operations and the control of the block, indented by
+    /// `indent` spaces
+    fn write_indented(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        let pad = " ".repeat(indent);
+        for op in &self.ops {
+            writeln!(f, "{pad}{op}")?;
+        }
+        match &self.ctrl {
+            Ctrl::Return(vars) => writeln!(f, "{pad}return {};", Vars(vars)),
+            Ctrl::MatchTag(var, cases, def) => {
+                writeln!(f, "{pad}match {var}.tag {{")?;
+                for (tag, block) in cases {
+                    writeln!(f, "{pad}    {} => {{", TagSyntax(tag))?;
+                    block.write_indented(f, indent + 8)?;
+                    writeln!(f, "{pad}    }}")?;
+                }
+                Self::write_default(f, def, indent)
+            }
+            Ctrl::MatchVal(var, cases, def) => {
+                writeln!(f, "{pad}match {var}.val {{")?;
+                for (lit, block) in cases {
+                    writeln!(f, "{pad}    {} => {{", LitSyntax(lit))?;
+                    block.write_indented(f, indent + 8)?;
+                    writeln!(f, "{pad}    }}")?;
+                }
+                Self::write_default(f, def, indent)
+            }
+            Ctrl::MatchSymbol(var, cases, def) => {
+                writeln!(f, "{pad}match {var}.symbol {{")?;
+                for (sym, block) in cases {
+                    writeln!(f, "{pad}    {} => {{", SymbolSyntax(sym))?;
+                    block.write_indented(f, indent + 8)?;
+                    writeln!(f, "{pad}    }}")?;
+                }
+                Self::write_default(f, def, indent)
+            }
+            Ctrl::IfEq(x, y, eq_block, else_block) => {
+                writeln!(f, "{pad}if {x} == {y} {{")?;
+                eq_block.write_indented(f, indent + 4)?;
+                writeln!(f, "{pad}}}")?;
+                else_block.write_indented(f, indent)
+            }
+        }
+    }
+
+    /// Closes a match, followed by its default block if there's one
+    fn write_default(
+        f: &mut fmt::Formatter<'_>,
+        def: &Option<Box<Block>>,
+        indent: usize,
+    ) -> fmt::Result {
+        let pad = " ".repeat(indent);
+        match def {
+            Some(def) => {
+                writeln!(f, "{pad}}};")?;
+                def.write_indented(f, indent)
+            }
+            None => writeln!(f, "{pad}}}"),
+        }
+    }
+
+    /// Collects the functions called in the block, callees first
+    fn callees<'a>(&'a self, funcs: &mut IndexMap<&'a str, &'a Func>) {
+        for op in &self.ops {
+            if let Op::Call(_, func, _) = op {
+                func.body.callees(funcs);
+                funcs.entry(func.name.as_str()).or_insert(&**func);
+            }
+        }
+        match &self.ctrl {
+            Ctrl::Return(..) => (),
+            Ctrl::MatchTag(_, cases, def) => cases
+                .values()
+                .chain(def.as_deref())
+                .for_each(|b| b.callees(funcs)),
+            Ctrl::MatchVal(_, cases, def) => cases
+                .values()
+                .chain(def.as_deref())
+                .for_each(|b| b.callees(funcs)),
+            Ctrl::MatchSymbol(_, cases, def) => cases
+                .values()
+                .chain(def.as_deref())
+                .for_each(|b| b.callees(funcs)),
+            Ctrl::IfEq(_, _, eq_block, else_block) => {
+                eq_block.callees(funcs);
+                else_block.callees(funcs);
+            }
+        }
+    }
+}
+
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{{")?;
+        self.write_indented(f, 4)?;
+        write!(f, "}}")
+    }
+}
+
+impl fmt::Display for Func {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut callees = IndexMap::new();
+        self.body.callees(&mut callees);
+        let funcs = callees.values().copied().chain(std::iter::once(self));
+        for (i, func) in funcs.enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            writeln!(
+                f,
+                "{}{}: {} => {}",
+                func.name,
+                Vars(&func.input_params),
+                func.output_size,
+                func.body
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::LurkField;
+    use crate::func;
+    use crate::lem::{interpreter::Preimages, pointers::Ptr, store::Store};
+    use bellpepper_core::test_cs::TestConstraintSystem;
+    use blstrs::Scalar as Fr;
+
+    const SRC: &str = r#"
+        // doubles numbers
+        double(x): 1 => {
+            match x.tag {
+                Expr::Num => {
+                    let y = add(x, x);
+                    return (y);
+                }
+            };
+            let nil = Symbol("nil");
+            return (nil);
+        }
+
+        main(a, b): 2 => {
+            let (c) = double(a);
+            let s = String("a \"quoted\"\n string");
+            let t = truncate(c, 8);
+            let h: Expr::Cons = hash2(s, t);
+            match b.val {
+                Num(0) | Num(1) => {
+                    let k = cast(h, Cont::Outermost);
+                    return (h, k);
+                }
+            };
+            if a != b {
+                return (a, b);
+            }
+            return (c, c);
+        }
+    "#;
+
+    #[test]
+    fn parses_like_the_macro() {
+        let double = func!(double(x): 1 => {
+            match x.tag {
+                Expr::Num => {
+                    let y = add(x, x);
+                    return (y);
+                }
+            };
+            let nil = Symbol("nil");
+            return (nil);
+        });
+        let main = func!(main(a, b): 2 => {
+            let (c) = double(a);
+            let s = String("a \"quoted\"\n string");
+            let t = truncate(c, 8);
+            let h: Expr::Cons = hash2(s, t);
+            match b.val {
+                Num(0) | Num(1) => {
+                    let k = cast(h, Cont::Outermost);
+                    return (h, k);
+                }
+            };
+            if a != b {
+                return (a, b);
+            }
+            return (c, c);
+        });
+        assert_eq!(parse(SRC).unwrap(), vec![double, main]);
+    }
+
+    #[test]
+    fn printed_functions_are_parsed_back() {
+        let main: Func = SRC.parse().unwrap();
+        let printed = main.to_string();
+        assert!(printed.starts_with("double(x#"));
+        let reparsed: Func = printed.parse().unwrap();
+        assert_eq!(reparsed.slot, main.slot);
+
+        let store = &mut Store::<Fr>::default();
+        for (a, b) in [(3, 3), (3, 4), (2, 1)] {
+            let input = vec![Ptr::num(Fr::from_u64(a)), Ptr::num(Fr::from_u64(b))];
+            let (frame, _) = main
+                .call(input.clone(), store, Preimages::new_from_func(&main))
+                .unwrap();
+            let (reframe, _) = reparsed
+                .call(input, store, Preimages::new_from_func(&reparsed))
+                .unwrap();
+            assert_eq!(frame.output, reframe.output);
+        }
+    }
+
+    #[test]
+    fn user_continuations_are_parsed_and_synthesized() {
+        let step: Func = "step(k): 1 => {
+            match k.tag {
+                Cont::Outermost => {
+                    let lazy = cast(k, Cont::User(0));
+                    return (lazy);
+                }
+                Cont::User(0) => {
+                    let done = cast(k, Cont::Terminal);
+                    return (done);
+                }
+            };
+            return (k);
+        }"
+        .parse()
+        .unwrap();
+        let printed = step.to_string();
+        assert!(printed.contains("Cont::User(0)"));
+        let reparsed: Func = printed.parse().unwrap();
+        assert_eq!(reparsed.slot, step.slot);
+        let src = "f(k): 1 => {\n    let x = cast(k, Cont::User(4096));\n    return (x);\n}";
+        assert!(parse(src).is_err());
+
+        let store = &mut Store::<Fr>::default();
+        let lazy = crate::tag!(Cont::User(0));
+        let mut k = Ptr::null(Tag::Cont(ContTag::Outermost));
+        for expected in [lazy, Tag::Cont(ContTag::Terminal)] {
+            let (frame, _) = step
+                .call(vec![k], store, Preimages::new_from_func(&step))
+                .unwrap();
+            k = frame.output[0];
+            assert_eq!(*k.tag(), expected);
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            step.synthesize(&mut cs, store, &frame).unwrap();
+            assert!(cs.is_satisfied());
+        }
+        step.assert_num_constraints(store);
+    }
+
+    #[test]
+    fn errors_point_at_the_source() {
+        let err = parse("f(x): 1 => {\n    let y = frob(x);\n    return (y);\n}")
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "Unknown operation `frob` at 2:13");
+        let err = parse("f(x): 1 => {\n    let (y) = g(x);\n    return (y);\n}")
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "Unknown function `g` at 2:9");
+    }
+}
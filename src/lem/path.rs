@@ -1,3 +1,4 @@
+use anyhow::{bail, Result};
 use std::collections::HashSet;
 
 use super::{Block, Ctrl, Func, Lit, Op, Tag};
@@ -102,9 +103,56 @@ impl Func {
     pub fn assert_all_paths_taken(&self, paths: &[Path]) {
         assert_eq!(Path::num_paths_taken(paths), self.num_paths());
     }
+
+    /// The gas of the frame that took `path`: a unit for each operation and
+    /// control statement it interpreted, including those of the functions it
+    /// called
+    pub fn gas(&self, path: &Path) -> Result<u64> {
+        let mut nodes = path.0.iter();
+        let gas = self.body.gas(&mut nodes)?;
+        if nodes.next().is_some() {
+            bail!("Path {path} is longer than the paths of `{}`", self.name)
+        }
+        Ok(gas)
+    }
 }
 
 impl Block {
+    fn gas<'a>(&self, nodes: &mut impl Iterator<Item = &'a PathNode>) -> Result<u64> {
+        let mut gas = 1 + self.ops.len() as u64;
+        for op in &self.ops {
+            if let Op::Call(_, func, _) = op {
+                gas += func.body.gas(nodes)?
+            }
+        }
+        if let Ctrl::Return(..) = self.ctrl {
+            return Ok(gas);
+        }
+        let block = match (&self.ctrl, nodes.next()) {
+            (Ctrl::MatchTag(_, cases, _), Some(PathNode::Tag(tag))) => cases.get(tag),
+            (Ctrl::MatchVal(_, cases, _), Some(PathNode::Lit(lit))) => cases.get(lit),
+            (Ctrl::MatchSymbol(_, cases, _), Some(PathNode::Lit(Lit::Symbol(sym)))) => {
+                cases.get(sym)
+            }
+            (
+                Ctrl::MatchTag(_, _, def)
+                | Ctrl::MatchVal(_, _, def)
+                | Ctrl::MatchSymbol(_, _, def),
+                Some(PathNode::Default),
+            ) => def.as_deref(),
+            (Ctrl::IfEq(_, _, eq_block, else_block), Some(PathNode::Bool(b))) => Some(if *b {
+                eq_block.as_ref()
+            } else {
+                else_block.as_ref()
+            }),
+            _ => None,
+        };
+        let Some(block) = block else {
+            bail!("Path doesn't follow the branches of the function")
+        };
+        Ok(gas + block.gas(nodes)?)
+    }
+
     fn num_paths(&self) -> usize {
         let mut num_paths = 1;
         for op in &self.ops {
@@ -125,6 +173,12 @@ impl Block {
                     .values()
                     .fold(init, |acc, block| acc + block.num_paths())
             }
+            Ctrl::MatchSymbol(_, cases, def) => {
+                let init = def.as_ref().map_or(0, |def| def.num_paths());
+                cases
+                    .values()
+                    .fold(init, |acc, block| acc + block.num_paths())
+            }
             Ctrl::IfEq(_, _, eq_block, else_block) => eq_block.num_paths() + else_block.num_paths(),
             Ctrl::Return(..) => 1,
         };
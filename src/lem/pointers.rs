@@ -1,3 +1,5 @@
+use serde::{Deserialize, Serialize};
+
 use crate::{field::*, tag::ContTag::Dummy, tag::ExprTag::*};
 
 use super::Tag;
@@ -13,12 +15,40 @@ use super::Tag;
 /// children a pointer has. However, LEMs require extra flexibility because LEM
 /// hashing operations can plug any tag to the resulting pointer. Thus, the
 /// number of children have to be made explicit as the `Ptr` enum.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Ptr<F: LurkField> {
     Leaf(Tag, F),
-    Tuple2(Tag, usize),
-    Tuple3(Tag, usize),
-    Tuple4(Tag, usize),
+    Tuple2(Tag, PtrIndex),
+    Tuple3(Tag, PtrIndex),
+    Tuple4(Tag, PtrIndex),
+    Tuple6(Tag, PtrIndex),
+    Tuple8(Tag, PtrIndex),
+}
+
+/// The index of the children of a `Ptr` among the store's tuples of its arity.
+/// With the `compact-ptr` feature, it's a `u32`, as in a `CompactPtr`, so that
+/// every pointer of the store can be compacted, which limits the store to 2^32
+/// tuples of each arity. A `Ptr` is as big as a leaf either way, so holding
+/// many pointers in little memory still takes `CompactPtr`s. Stores saved with
+/// and without the feature can't be opened by the other build.
+#[cfg(feature = "compact-ptr")]
+pub type PtrIndex = u32;
+#[cfg(not(feature = "compact-ptr"))]
+pub type PtrIndex = usize;
+
+/// `idx` as a `PtrIndex`. Panics if the store has outgrown `compact-ptr`'s
+/// indices.
+#[inline]
+#[allow(clippy::useless_conversion)]
+pub(crate) fn ptr_index(idx: usize) -> PtrIndex {
+    PtrIndex::try_from(idx).expect("the store holds more tuples than `compact-ptr` can index")
+}
+
+#[inline]
+#[allow(clippy::unnecessary_cast)]
+pub(crate) fn to_usize(idx: PtrIndex) -> usize {
+    // `u32` indices fit in a `usize` on the platforms Lurk supports
+    idx as usize
 }
 
 impl<F: LurkField> std::hash::Hash for Ptr<F> {
@@ -28,6 +58,8 @@ impl<F: LurkField> std::hash::Hash for Ptr<F> {
             Ptr::Tuple2(tag, x) => (1, tag, x).hash(state),
             Ptr::Tuple3(tag, x) => (2, tag, x).hash(state),
             Ptr::Tuple4(tag, x) => (3, tag, x).hash(state),
+            Ptr::Tuple6(tag, x) => (4, tag, x).hash(state),
+            Ptr::Tuple8(tag, x) => (5, tag, x).hash(state),
         }
     }
 }
@@ -35,9 +67,12 @@ impl<F: LurkField> std::hash::Hash for Ptr<F> {
 impl<F: LurkField> Ptr<F> {
     pub fn tag(&self) -> &Tag {
         match self {
-            Ptr::Leaf(tag, _) | Ptr::Tuple2(tag, _) | Ptr::Tuple3(tag, _) | Ptr::Tuple4(tag, _) => {
-                tag
-            }
+            Ptr::Leaf(tag, _)
+            | Ptr::Tuple2(tag, _)
+            | Ptr::Tuple3(tag, _)
+            | Ptr::Tuple4(tag, _)
+            | Ptr::Tuple6(tag, _)
+            | Ptr::Tuple8(tag, _) => tag,
         }
     }
 
@@ -68,13 +103,56 @@ impl<F: LurkField> Ptr<F> {
             Ptr::Tuple2(_, x) => Ptr::Tuple2(tag, *x),
             Ptr::Tuple3(_, x) => Ptr::Tuple3(tag, *x),
             Ptr::Tuple4(_, x) => Ptr::Tuple4(tag, *x),
+            Ptr::Tuple6(_, x) => Ptr::Tuple6(tag, *x),
+            Ptr::Tuple8(_, x) => Ptr::Tuple8(tag, *x),
+        }
+    }
+
+    /// The number of children of the pointer, which is 0 for leaves
+    #[inline]
+    pub fn arity(&self) -> usize {
+        match self {
+            Ptr::Leaf(..) => 0,
+            Ptr::Tuple2(..) => 2,
+            Ptr::Tuple3(..) => 3,
+            Ptr::Tuple4(..) => 4,
+            Ptr::Tuple6(..) => 6,
+            Ptr::Tuple8(..) => 8,
+        }
+    }
+
+    /// The index of the children of the pointer, which is `None` for leaves
+    #[inline]
+    pub fn index(&self) -> Option<usize> {
+        match self {
+            Ptr::Leaf(..) => None,
+            Ptr::Tuple2(_, x)
+            | Ptr::Tuple3(_, x)
+            | Ptr::Tuple4(_, x)
+            | Ptr::Tuple6(_, x)
+            | Ptr::Tuple8(_, x) => Some(to_usize(*x)),
+        }
+    }
+
+    /// The pointer with the same tag and arity, but with the children at
+    /// `idx`. Leaves are left as they are
+    #[inline]
+    pub fn with_index(&self, idx: usize) -> Self {
+        let idx = ptr_index(idx);
+        match self {
+            Ptr::Leaf(..) => *self,
+            Ptr::Tuple2(tag, _) => Ptr::Tuple2(*tag, idx),
+            Ptr::Tuple3(tag, _) => Ptr::Tuple3(*tag, idx),
+            Ptr::Tuple4(tag, _) => Ptr::Tuple4(*tag, idx),
+            Ptr::Tuple6(tag, _) => Ptr::Tuple6(*tag, idx),
+            Ptr::Tuple8(tag, _) => Ptr::Tuple8(*tag, idx),
         }
     }
 
     #[inline]
     pub fn get_index2(&self) -> Option<usize> {
         match self {
-            Ptr::Tuple2(_, x) => Some(*x),
+            Ptr::Tuple2(_, x) => Some(to_usize(*x)),
             _ => None,
         }
     }
@@ -82,7 +160,7 @@ impl<F: LurkField> Ptr<F> {
     #[inline]
     pub fn get_index3(&self) -> Option<usize> {
         match self {
-            Ptr::Tuple3(_, x) => Some(*x),
+            Ptr::Tuple3(_, x) => Some(to_usize(*x)),
             _ => None,
         }
     }
@@ -90,10 +168,86 @@ impl<F: LurkField> Ptr<F> {
     #[inline]
     pub fn get_index4(&self) -> Option<usize> {
         match self {
-            Ptr::Tuple4(_, x) => Some(*x),
+            Ptr::Tuple4(_, x) => Some(to_usize(*x)),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn get_index6(&self) -> Option<usize> {
+        match self {
+            Ptr::Tuple6(_, x) => Some(to_usize(*x)),
             _ => None,
         }
     }
+
+    #[inline]
+    pub fn get_index8(&self) -> Option<usize> {
+        match self {
+            Ptr::Tuple8(_, x) => Some(to_usize(*x)),
+            _ => None,
+        }
+    }
+}
+
+/// A `Ptr` in a fraction of its size, for holding many of them, such as the
+/// inputs and outputs of the frames of huge evaluations. A `Ptr` is as big as
+/// a leaf with a field element, whereas a `CompactPtr` holds the values of
+/// leaves that fit in a `u32` (chars, small numbers, tags...) inline and only
+/// refers to bigger ones by their indices in a table of the store, as it
+/// refers to the children of other pointers. `Store::compact` and
+/// `Store::expand` convert between the two, so APIs that take `Ptr`s keep
+/// working.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CompactPtr {
+    tag: Tag,
+    /// The number of children of the pointer, which is 0 for leaves, or
+    /// `INLINE_LEAF` for leaves whose value is `index` itself
+    arity: u8,
+    /// The index of the value of a leaf among the store's leaves, or of the
+    /// children of another pointer among the store's tuples of its arity
+    index: u32,
+}
+
+const INLINE_LEAF: u8 = u8::MAX;
+
+impl CompactPtr {
+    #[inline]
+    pub(crate) fn new(tag: Tag, arity: u8, index: u32) -> Self {
+        Self { tag, arity, index }
+    }
+
+    #[inline]
+    pub(crate) fn inline_leaf(tag: Tag, value: u32) -> Self {
+        Self::new(tag, INLINE_LEAF, value)
+    }
+
+    /// The value of a leaf that's held inline
+    #[inline]
+    pub(crate) fn inline_value(&self) -> Option<u32> {
+        (self.arity == INLINE_LEAF).then_some(self.index)
+    }
+
+    #[inline]
+    pub fn tag(&self) -> &Tag {
+        &self.tag
+    }
+
+    /// The number of children of the pointer, which is 0 for leaves
+    #[inline]
+    pub fn arity(&self) -> usize {
+        if self.arity == INLINE_LEAF {
+            0
+        } else {
+            self.arity.into()
+        }
+    }
+
+    #[inline]
+    pub(crate) fn index(&self) -> usize {
+        // `u32` indices fit in a `usize` on the platforms Lurk supports
+        self.index as usize
+    }
 }
 
 /// A `ZPtr` is the result of "hydrating" a `Ptr`. This process is better
@@ -120,6 +274,8 @@ pub(crate) enum ZChildren<F: LurkField> {
     Tuple2(ZPtr<F>, ZPtr<F>),
     Tuple3(ZPtr<F>, ZPtr<F>, ZPtr<F>),
     Tuple4(ZPtr<F>, ZPtr<F>, ZPtr<F>, ZPtr<F>),
+    Tuple6([ZPtr<F>; 6]),
+    Tuple8([ZPtr<F>; 8]),
 }
 
 impl<F: LurkField> ZPtr<F> {
@@ -0,0 +1,319 @@
+//! Exact rational arithmetic for LEM.
+//!
+//! A ratio is a pointer tagged `Expr::Ratio` with two `U64` children, its
+//! numerator and its denominator. Ratios are always in lowest terms, with a
+//! nonzero denominator, so equal ratios are equal pointers and `eq` compares
+//! them. The functions of this module make ratios and add, subtract, multiply,
+//! divide and compare them, normalizing their results in the circuit as well.
+//! Lurk's step function exposes them as the `ratio`, `ratio+`, `ratio-`,
+//! `ratio*`, `ratio/` and `ratio<` builtins when `EvalConfig::ratios` is set.
+//!
+//! Normalizing `n/d` takes a witness from the `RatioHint` coprocessor, which
+//! must be registered in the store under `RATIO_HINT`: the numerator `n'` and
+//! denominator `d'` in lowest terms, along with Bézout coefficients `a` and `b`.
+//! The circuit checks that `d` isn't zero, that all four fit in 64 bits, that
+//! `n * d' = d * n'` and that `a * n' - b * d'` is `1` or `-1`, which proves
+//! `n'` and `d'` coprime. None of these products wraps around the field, so
+//! the equations hold over the integers too. A result that's negative, or that
+//! doesn't fit in 64 bits, has no witness, so the operations fail on it.
+
+use anyhow::{anyhow, bail, Result};
+use bellpepper_core::{boolean::Boolean, SynthesisError};
+use num_bigint::{BigInt, Sign};
+use num_integer::{ExtendedGcd, Integer};
+
+use crate::circuit::gadgets::pointer::AllocatedPtr;
+use crate::field::LurkField;
+use crate::func;
+use crate::tag::ExprTag::{Ratio, U64};
+
+use super::{
+    coproc::{Coproc, CoprocCS},
+    pointers::Ptr,
+    store::Store,
+    Func, Tag,
+};
+
+/// The name the functions of this module call `RatioHint` by
+pub const RATIO_HINT: &str = "ratio_hint";
+
+/// Computes the witness for normalizing a ratio. It takes the numerator and
+/// the denominator as numbers and returns the numerator and the denominator in
+/// lowest terms, followed by the Bézout coefficients. It doesn't constrain
+/// them, which is up to the functions calling it.
+#[derive(Debug)]
+pub struct RatioHint;
+
+fn to_int<F: LurkField>(f: &F) -> BigInt {
+    BigInt::from_bytes_le(Sign::Plus, &f.to_bytes())
+}
+
+fn to_u64_ptr<F: LurkField>(x: &BigInt) -> Result<Ptr<F>> {
+    let x = u64::try_from(x).map_err(|_| anyhow!("Ratio term {x} doesn't fit in 64 bits"))?;
+    Ok(Ptr::Leaf(Tag::Expr(U64), F::from_u64(x)))
+}
+
+impl<F: LurkField> Coproc<F> for RatioHint {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn output_size(&self) -> usize {
+        4
+    }
+
+    fn evaluate(&self, _store: &mut Store<F>, args: &[Ptr<F>]) -> Result<Vec<Ptr<F>>> {
+        let (Ptr::Leaf(_, n), Ptr::Leaf(_, d)) = (&args[0], &args[1]) else {
+            bail!("Ratios are made of numbers")
+        };
+        let (n, d) = (to_int(n), to_int(d));
+        if d == BigInt::from(0) {
+            bail!("Ratio with a zero denominator")
+        }
+        let gcd = n.gcd(&d);
+        let (n, d) = (n / &gcd, d / &gcd);
+        // x * n + y * d = 1, where x and y don't have the same sign
+        let ExtendedGcd { x, y, .. } = n.extended_gcd(&d);
+        let (a, b) = (x.magnitude().clone(), y.magnitude().clone());
+        [n, d, a.into(), b.into()].iter().map(to_u64_ptr).collect()
+    }
+
+    fn synthesize(
+        &self,
+        _cs: &mut dyn CoprocCS<F>,
+        _store: &Store<F>,
+        _not_dummy: &Boolean,
+        _inputs: &[AllocatedPtr<F>],
+        _outputs: &[AllocatedPtr<F>],
+    ) -> Result<(), SynthesisError> {
+        Ok(())
+    }
+
+    fn num_constraints(&self) -> usize {
+        0
+    }
+}
+
+impl<F: LurkField> Store<F> {
+    /// Interns the ratio `num/den`, in lowest terms
+    pub fn intern_ratio(&mut self, num: u64, den: u64) -> Result<Ptr<F>> {
+        if den == 0 {
+            bail!("Ratio with a zero denominator")
+        }
+        let gcd = num.gcd(&den);
+        let num = Ptr::Leaf(Tag::Expr(U64), F::from_u64(num / gcd));
+        let den = Ptr::Leaf(Tag::Expr(U64), F::from_u64(den / gcd));
+        Ok(self.intern_2_ptrs(Tag::Expr(Ratio), num, den))
+    }
+
+    /// The numerator and the denominator of the ratio `ptr`
+    pub fn fetch_ratio(&self, ptr: &Ptr<F>) -> Option<(u64, u64)> {
+        if ptr.tag() != &Tag::Expr(Ratio) {
+            return None;
+        }
+        let (Ptr::Leaf(_, num), Ptr::Leaf(_, den)) = self.fetch_2_ptrs(ptr.get_index2()?)? else {
+            return None;
+        };
+        Some((num.to_u64()?, den.to_u64()?))
+    }
+}
+
+/// Reduces `n/d` to lowest terms, checking the witness of `RatioHint`
+fn normalize() -> Func {
+    func!(normalize(n, d): 1 => {
+        let one = Num(1);
+        let d_inv = div(one, d);
+        let unit = mul(d, d_inv);
+        let (n_norm, d_norm, a, b) = coproc ratio_hint(n, d);
+        assert_range(n_norm, 64);
+        assert_range(d_norm, 64);
+        assert_range(a, 64);
+        assert_range(b, 64);
+        let lhs = mul(n, d_norm);
+        let rhs = mul(d, n_norm);
+        let same = sub(lhs, rhs);
+        let an = mul(a, n_norm);
+        let bd = mul(b, d_norm);
+        let bezout = sub(an, bd);
+        let bezout = mul(bezout, bezout);
+        match unit.val {
+            Num(1) => {
+                match same.val {
+                    Num(0) => {
+                        match bezout.val {
+                            Num(1) => {
+                                let n_norm = cast(n_norm, Expr::U64);
+                                let d_norm = cast(d_norm, Expr::U64);
+                                let ratio: Expr::Ratio = hash2(n_norm, d_norm);
+                                return (ratio)
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// The numerators and denominators of two ratios
+fn unpack() -> Func {
+    func!(unpack(x, y): 4 => {
+        match x.tag {
+            Expr::Ratio => {
+                match y.tag {
+                    Expr::Ratio => {
+                        let (x_num, x_den) = unhash2(x);
+                        let (y_num, y_den) = unhash2(y);
+                        return (x_num, x_den, y_num, y_den)
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Makes the ratio of two `u64`s, which fails if the second one is zero
+pub fn make_ratio() -> Func {
+    let normalize = normalize();
+    func!(make_ratio(n, d): 1 => {
+        match n.tag {
+            Expr::U64 => {
+                match d.tag {
+                    Expr::U64 => {
+                        let (ratio) = normalize(n, d);
+                        return (ratio)
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Adds two ratios
+pub fn ratio_add() -> Func {
+    let (unpack, normalize) = (unpack(), normalize());
+    func!(ratio_add(x, y): 1 => {
+        let (x_num, x_den, y_num, y_den) = unpack(x, y);
+        let lhs = mul(x_num, y_den);
+        let rhs = mul(y_num, x_den);
+        let num = add(lhs, rhs);
+        let den = mul(x_den, y_den);
+        let (ratio) = normalize(num, den);
+        return (ratio)
+    })
+}
+
+/// Subtracts the second ratio from the first one, which fails if the result
+/// is negative
+pub fn ratio_sub() -> Func {
+    let (unpack, normalize) = (unpack(), normalize());
+    func!(ratio_sub(x, y): 1 => {
+        let (x_num, x_den, y_num, y_den) = unpack(x, y);
+        let lhs = mul(x_num, y_den);
+        let rhs = mul(y_num, x_den);
+        let num = sub(lhs, rhs);
+        let den = mul(x_den, y_den);
+        let (ratio) = normalize(num, den);
+        return (ratio)
+    })
+}
+
+/// Multiplies two ratios
+pub fn ratio_mul() -> Func {
+    let (unpack, normalize) = (unpack(), normalize());
+    func!(ratio_mul(x, y): 1 => {
+        let (x_num, x_den, y_num, y_den) = unpack(x, y);
+        let num = mul(x_num, y_num);
+        let den = mul(x_den, y_den);
+        let (ratio) = normalize(num, den);
+        return (ratio)
+    })
+}
+
+/// Divides the first ratio by the second one, which fails if it's zero
+pub fn ratio_div() -> Func {
+    let (unpack, normalize) = (unpack(), normalize());
+    func!(ratio_div(x, y): 1 => {
+        let (x_num, x_den, y_num, y_den) = unpack(x, y);
+        let num = mul(x_num, y_den);
+        let den = mul(x_den, y_num);
+        let (ratio) = normalize(num, den);
+        return (ratio)
+    })
+}
+
+/// Returns `t` if the first ratio is less than the second one, and `nil`
+/// otherwise
+pub fn ratio_lt() -> Func {
+    let unpack = unpack();
+    func!(ratio_lt(x, y): 1 => {
+        let (x_num, x_den, y_num, y_den) = unpack(x, y);
+        let lhs = mul(x_num, y_den);
+        let rhs = mul(y_num, x_den);
+        let lt = lt(lhs, rhs);
+        match lt.val {
+            Num(1) => {
+                let t = Symbol("t");
+                return (t)
+            }
+        };
+        let nil = Symbol("nil");
+        let nil = cast(nil, Expr::Nil);
+        return (nil)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bellpepper_core::test_cs::TestConstraintSystem;
+    use blstrs::Scalar as Fr;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::lem::interpreter::Preimages;
+    use crate::state::lurk_sym;
+
+    /// Calls `func`, checks the circuit is satisfied and returns the output
+    fn run(func: &Func, args: Vec<Ptr<Fr>>, store: &mut Store<Fr>) -> Result<Ptr<Fr>> {
+        let (frame, _) = func.call(args, store, Preimages::new_from_func(func))?;
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        func.synthesize(&mut cs, store, &frame)?;
+        assert!(cs.is_satisfied());
+        assert_eq!(func.num_constraints(store), cs.num_constraints());
+        Ok(frame.output[0])
+    }
+
+    #[test]
+    fn ratios_are_normalized() {
+        let store = &mut Store::<Fr>::default();
+        store.register_coproc(RATIO_HINT, Arc::new(RatioHint));
+        let u64 = |n: u64| Ptr::Leaf(Tag::Expr(U64), Fr::from(n));
+        let half = store.intern_ratio(1, 2).unwrap();
+        let third = store.intern_ratio(1, 3).unwrap();
+
+        let ratio = run(&make_ratio(), vec![u64(6), u64(4)], store).unwrap();
+        assert_eq!(store.fetch_ratio(&ratio), Some((3, 2)));
+        assert_eq!(ratio, store.intern_ratio(3, 2).unwrap());
+        let zero = run(&make_ratio(), vec![u64(0), u64(7)], store).unwrap();
+        assert_eq!(store.fetch_ratio(&zero), Some((0, 1)));
+        assert!(run(&make_ratio(), vec![u64(1), u64(0)], store).is_err());
+
+        for (func, expected) in [
+            (ratio_add(), (5, 6)),
+            (ratio_sub(), (1, 6)),
+            (ratio_mul(), (1, 6)),
+            (ratio_div(), (3, 2)),
+        ] {
+            let ratio = run(&func, vec![half, third], store).unwrap();
+            assert_eq!(store.fetch_ratio(&ratio), Some(expected));
+        }
+        assert!(run(&ratio_sub(), vec![third, half], store).is_err());
+        assert!(run(&ratio_div(), vec![half, zero], store).is_err());
+
+        let t = store.intern_symbol(&lurk_sym("t"));
+        let nil = store.intern_symbol(&lurk_sym("nil"));
+        assert_eq!(run(&ratio_lt(), vec![third, half], store).unwrap(), t);
+        assert_eq!(run(&ratio_lt(), vec![half, third], store).unwrap(), nil);
+        assert_eq!(run(&ratio_lt(), vec![half, half], store).unwrap(), nil);
+    }
+}
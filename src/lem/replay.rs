@@ -0,0 +1,232 @@
+//! Replaying single frames, to find out whether they're provable.
+//!
+//! Proving a long evaluation fails as a whole when any of its frames gives
+//! rise to an unsatisfiable witness. `FrameData` carries a frame along with the
+//! part of the store its pointers refer to, so the frame can be saved and then
+//! replayed on its own with `Func::replay`, which reports the namespace of the
+//! first unsatisfied constraint. Replaying the frames of a trace one by one
+//! tells exactly which of them breaks proving.
+
+use anyhow::{anyhow, Result};
+use bellpepper_core::test_cs::TestConstraintSystem;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::field::LurkField;
+use crate::tag::ExprTag::Nil;
+
+use super::{
+    interpreter::{Frame, PreimageData, Preimages},
+    pointers::Ptr,
+    store::Store,
+    Func, Tag,
+};
+
+/// A frame whose pointers index `tuples` rather than a store
+#[derive(Serialize, Deserialize)]
+pub struct FrameData<F: LurkField> {
+    /// The children of the pointers reachable from the frame, each one after
+    /// the pointers among its children
+    tuples: Vec<Vec<Ptr<F>>>,
+    frame: Frame<F>,
+}
+
+pub(super) type PtrMap<'a, F> = dyn FnMut(&Ptr<F>) -> Result<Ptr<F>> + 'a;
+
+fn map_ptrs<F: LurkField>(ptrs: &[Ptr<F>], f: &mut PtrMap<'_, F>) -> Result<Vec<Ptr<F>>> {
+    ptrs.iter().map(f).collect()
+}
+
+fn map_slots<F: LurkField>(
+    slots: &[Option<PreimageData<F>>],
+    f: &mut PtrMap<'_, F>,
+) -> Result<Vec<Option<PreimageData<F>>>> {
+    slots
+        .iter()
+        .map(|slot| match slot {
+            Some(PreimageData::PtrVec(ptrs)) => Ok(Some(PreimageData::PtrVec(map_ptrs(ptrs, f)?))),
+            Some(PreimageData::FPtr(x, ptr)) => Ok(Some(PreimageData::FPtr(*x, f(ptr)?))),
+            slot => Ok(slot.clone()),
+        })
+        .collect()
+}
+
+/// The frame with every pointer replaced by its image through `f`
+pub(super) fn map_frame<F: LurkField>(frame: &Frame<F>, f: &mut PtrMap<'_, F>) -> Result<Frame<F>> {
+    let p = &frame.preimages;
+    let preimages = Preimages {
+        hash2: map_slots(&p.hash2, f)?,
+        hash3: map_slots(&p.hash3, f)?,
+        hash4: map_slots(&p.hash4, f)?,
+        hash6: map_slots(&p.hash6, f)?,
+        hash8: map_slots(&p.hash8, f)?,
+        commitment: map_slots(&p.commitment, f)?,
+        less_than: map_slots(&p.less_than, f)?,
+        bit_decomp: map_slots(&p.bit_decomp, f)?,
+        range: map_slots(&p.range, f)?,
+        call_outputs: p
+            .call_outputs
+            .iter()
+            .map(|ptrs| map_ptrs(ptrs, f))
+            .collect::<Result<_>>()?,
+        coproc_outputs: p
+            .coproc_outputs
+            .iter()
+            .map(|ptrs| map_ptrs(ptrs, f))
+            .collect::<Result<_>>()?,
+        emitted: None,
+    };
+    Ok(Frame {
+        input: map_ptrs(&frame.input, f)?,
+        output: map_ptrs(&frame.output, f)?,
+        preimages,
+    })
+}
+
+/// Adds the children of `ptr` to `tuples`, along with their own children, and
+/// returns `ptr` indexing them there
+pub(super) fn localize<F: LurkField>(
+    ptr: &Ptr<F>,
+    store: &Store<F>,
+    tuples: &mut Vec<Vec<Ptr<F>>>,
+    indices: &mut HashMap<Ptr<F>, usize>,
+) -> Result<Ptr<F>> {
+    if ptr.index().is_none() {
+        return Ok(*ptr);
+    }
+    // the tag of a pointer doesn't matter to its children
+    let key = ptr.cast(Tag::Expr(Nil));
+    if let Some(idx) = indices.get(&key) {
+        return Ok(ptr.with_index(*idx));
+    }
+    let children = store
+        .fetch_ptrs(ptr)
+        .ok_or_else(|| anyhow!("Pointer {ptr:?} not found in the store"))?
+        .iter()
+        .map(|child| localize(child, store, tuples, indices))
+        .collect::<Result<Vec<_>>>()?;
+    let idx = tuples.len();
+    tuples.push(children);
+    indices.insert(key, idx);
+    Ok(ptr.with_index(idx))
+}
+
+/// The pointer `ptr`, made by `localize`, pointing to the tuples `interned`
+/// in a store instead
+pub(super) fn globalize<F: LurkField>(ptr: &Ptr<F>, interned: &[Ptr<F>]) -> Result<Ptr<F>> {
+    match ptr.index() {
+        None => Ok(*ptr),
+        Some(idx) => match interned.get(idx) {
+            Some(tuple) if tuple.arity() == ptr.arity() => Ok(tuple.cast(*ptr.tag())),
+            _ => Err(anyhow!("Invalid tuple index {idx} for {ptr:?}")),
+        },
+    }
+}
+
+/// Interns the tuples made by `localize` in `store`, in order
+pub(super) fn intern_tuples<F: LurkField>(
+    tuples: &[Vec<Ptr<F>>],
+    store: &mut Store<F>,
+) -> Result<Vec<Ptr<F>>> {
+    let mut interned: Vec<Ptr<F>> = Vec::with_capacity(tuples.len());
+    for children in tuples {
+        let children = children
+            .iter()
+            .map(|child| globalize(child, &interned))
+            .collect::<Result<Vec<_>>>()?;
+        interned.push(store.intern_ptrs(Tag::Expr(Nil), &children)?);
+    }
+    Ok(interned)
+}
+
+impl<F: LurkField> FrameData<F> {
+    pub fn new(frame: &Frame<F>, store: &Store<F>) -> Result<Self> {
+        let mut tuples = vec![];
+        let mut indices = HashMap::new();
+        let frame = map_frame(frame, &mut |ptr| {
+            localize(ptr, store, &mut tuples, &mut indices)
+        })?;
+        Ok(Self { tuples, frame })
+    }
+
+    /// Interns the pointers of the frame in `store` and returns the frame
+    /// pointing to them
+    pub fn into_frame(self, store: &mut Store<F>) -> Result<Frame<F>> {
+        let interned = intern_tuples(&self.tuples, store)?;
+        map_frame(&self.frame, &mut |ptr| globalize(ptr, &interned))
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>>
+    where
+        F: Serialize,
+    {
+        Ok(bincode::serialize(self)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self>
+    where
+        F: DeserializeOwned,
+    {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+impl Func {
+    /// Synthesizes `frame` alone in a `TestConstraintSystem` and returns the
+    /// namespace of the first unsatisfied constraint, or `None` if the frame is
+    /// provable
+    pub fn replay<F: LurkField>(
+        &self,
+        store: &mut Store<F>,
+        frame: &Frame<F>,
+    ) -> Result<Option<String>> {
+        store.hydrate_z_cache();
+        let mut cs = TestConstraintSystem::<F>::new();
+        self.synthesize(&mut cs, store, frame)?;
+        Ok(cs.which_is_unsatisfied().map(String::from))
+    }
+
+    /// Like `replay`, for a frame serialized with `FrameData::to_bytes`
+    pub fn replay_bytes<F: LurkField + DeserializeOwned>(
+        &self,
+        bytes: &[u8],
+    ) -> Result<Option<String>> {
+        let store = &mut Store::default();
+        let frame = FrameData::<F>::from_bytes(bytes)?.into_frame(store)?;
+        self.replay(store, &frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lem::eval::eval_step;
+    use crate::state::{lurk_sym, State};
+    use crate::tag::ContTag::{Outermost, Terminal};
+    use blstrs::Scalar as Fr;
+
+    #[test]
+    fn frames_replay_on_their_own() {
+        let func = eval_step();
+        let store = &mut Store::<Fr>::default();
+        let state = State::init_lurk_state().rccell();
+        let expr = store.read(state, "(car (cons 1 2))").unwrap();
+        let nil = store.intern_symbol(&lurk_sym("nil"));
+        let terminal = Ptr::null(Tag::Cont(Terminal));
+        let input = vec![expr, nil, Ptr::null(Tag::Cont(Outermost))];
+        let (frames, _) = func
+            .call_until(input, store, |output| output[2] == terminal)
+            .unwrap();
+        assert!(frames.len() > 1);
+
+        for frame in &frames {
+            let bytes = FrameData::new(frame, store).unwrap().to_bytes().unwrap();
+            assert_eq!(func.replay_bytes::<Fr>(&bytes).unwrap(), None);
+        }
+
+        let mut forged = frames[0].clone();
+        forged.output[0] = Ptr::num(Fr::from(42));
+        let bytes = FrameData::new(&forged, store).unwrap().to_bytes().unwrap();
+        assert!(func.replay_bytes::<Fr>(&bytes).unwrap().is_some());
+    }
+}
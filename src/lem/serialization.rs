@@ -0,0 +1,75 @@
+//! Versioned serialization of LEM functions.
+//!
+//! A serialized `Func` is [`FUNC_MAGIC`], followed by the version of the
+//! format and the bincode encoding of the function, as produced by `Func::new`.
+//! Any change to the serialized types must come with a new version. Reading a
+//! function checks it again, since its bytes may not come from `Func::new`.
+//!
+//! The digest of a function identifies it, so the step function a proof was
+//! made with can be checked against the one a verifier expects.
+
+use anyhow::{bail, Result};
+use sha2::{Digest, Sha256};
+
+use super::Func;
+
+/// The bytes serialized functions start with, followed by the version byte
+pub const FUNC_MAGIC: [u8; 3] = *b"LEM";
+
+/// The version of the format functions are serialized with
+pub const FUNC_FORMAT_VERSION: u8 = 3;
+
+impl Func {
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = FUNC_MAGIC.to_vec();
+        bytes.push(FUNC_FORMAT_VERSION);
+        bincode::serialize_into(&mut bytes, self)?;
+        Ok(bytes)
+    }
+
+    /// Deserializes a function serialized with `to_bytes`, making sure it
+    /// passes the static checks and that its slots are those of its body
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let Some(bytes) = bytes.strip_prefix(FUNC_MAGIC.as_slice()) else {
+            bail!("Not a serialized LEM function")
+        };
+        let bytes = match bytes.split_first() {
+            Some((&FUNC_FORMAT_VERSION, bytes)) => bytes,
+            Some((version, _)) => bail!(
+                "Unsupported LEM function format version {version}, the latest known is {}",
+                FUNC_FORMAT_VERSION
+            ),
+            None => bail!("Missing LEM function format version"),
+        };
+        let func: Func = bincode::deserialize(bytes)?;
+        func.check()?;
+        if func.slot != func.body.count_slots_sharing(func.share_slots) {
+            bail!("The slots of function `{}` don't match its body", func.name)
+        }
+        Ok(func)
+    }
+
+    /// The hex-encoded SHA-256 of the serialized function
+    pub fn digest(&self) -> Result<String> {
+        Ok(hex::encode(Sha256::digest(self.to_bytes()?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lem::eval::eval_step;
+
+    #[test]
+    fn step_function_roundtrips() {
+        let func = eval_step();
+        let bytes = func.to_bytes().unwrap();
+        assert_eq!(Func::from_bytes(&bytes).unwrap(), func);
+        assert_eq!(func.digest().unwrap(), eval_step().digest().unwrap());
+
+        let mut unknown = bytes.clone();
+        unknown[FUNC_MAGIC.len()] = FUNC_FORMAT_VERSION + 1;
+        assert!(Func::from_bytes(&unknown).is_err());
+        assert!(Func::from_bytes(&bytes[FUNC_MAGIC.len()..]).is_err());
+    }
+}
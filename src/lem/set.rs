@@ -0,0 +1,716 @@
+//! Sets for LEM.
+//!
+//! A set is a pointer tagged `Expr::Set`: the root of a binary trie whose
+//! leaves are the elements of the set. The key of an element is the Poseidon
+//! hash of its tag, its hash and zero, so that elements of different tags get
+//! different keys, and the `i`-th bit of the key picks the child a path takes
+//! at depth `i`. A subtrie of no element is the null `Expr::Set` pointer, a
+//! subtrie of one element is a leaf and a subtrie of more elements is a branch,
+//! so a set has a single trie, whatever order its elements were inserted in,
+//! and equal sets are equal pointers.
+//!
+//! Leaves and branches are `Expr::Set` pointers with two children: the element
+//! and the null `Expr::Nil` pointer for leaves, the two subtries for branches.
+//!
+//! `set_member` and `set_insert` follow the path of an element down the trie,
+//! so they cost as much as the depth of the trie, rather than as the size of
+//! the set. Since LEM functions can't loop, they're made for a maximum depth,
+//! and fail on deeper tries. The keys of the elements are computed by the
+//! `SetKey` coprocessor, which must be registered under `SET_KEY`. The
+//! circuit only reads the bits of the keys up to that depth, below 64, while
+//! the `Store` reads all of them.
+//!
+//! `set_union` merges two tries level by level, inserting the element of a
+//! leaf into the other subtrie when it meets one. It calls itself twice for
+//! each branch, so it costs twice as much for each level of depth, and is only
+//! practical for shallow sets. Programs that prove the union of larger sets
+//! insert the elements of one set into the other instead.
+//!
+//! With `EvalConfig::set_depth`, Lurk's step function exposes these functions
+//! as the `set-member`, `set-insert` and `set-union` builtins.
+
+use anyhow::{bail, Result};
+use bellpepper_core::{boolean::Boolean, LinearCombination, SynthesisError};
+
+use crate::circuit::gadgets::pointer::AllocatedPtr;
+use crate::field::LurkField;
+use crate::func;
+use crate::tag::ExprTag::{Nil, Set};
+
+use super::{
+    coproc::{Coproc, CoprocCS, POSEIDON_PAD_CONSTRAINTS},
+    pointers::Ptr,
+    store::Store,
+    Func, Tag,
+};
+
+/// The name the functions of this module call `SetKey` by
+pub const SET_KEY: &str = "set_key";
+
+/// Computes the key of an element of a set: the Poseidon hash of its tag, its
+/// hash and zero
+#[derive(Debug)]
+pub struct SetKey;
+
+impl<F: LurkField> Coproc<F> for SetKey {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn output_size(&self) -> usize {
+        1
+    }
+
+    fn evaluate(&self, store: &mut Store<F>, args: &[Ptr<F>]) -> Result<Vec<Ptr<F>>> {
+        Ok(vec![Ptr::num(store.set_key_field(&args[0])?)])
+    }
+
+    fn synthesize(
+        &self,
+        cs: &mut dyn CoprocCS<F>,
+        store: &Store<F>,
+        not_dummy: &Boolean,
+        inputs: &[AllocatedPtr<F>],
+        outputs: &[AllocatedPtr<F>],
+    ) -> Result<(), SynthesisError> {
+        let key = cs.poseidon_hash_pad(
+            "set key hash".into(),
+            [inputs[0].tag().clone(), inputs[0].hash().clone()],
+            store,
+        )?;
+        // not_dummy * (key - output) = 0
+        let one = cs.one_var();
+        cs.enforce_lc(
+            "set key".into(),
+            not_dummy.lc(one, F::ONE),
+            LinearCombination::zero() + key.get_variable() - outputs[0].hash().get_variable(),
+            LinearCombination::zero(),
+        );
+        Ok(())
+    }
+
+    fn num_constraints(&self) -> usize {
+        POSEIDON_PAD_CONSTRAINTS + 1
+    }
+}
+
+/// The nodes of the trie of a set
+enum Node<F: LurkField> {
+    Empty,
+    Leaf(Ptr<F>),
+    Branch(Ptr<F>, Ptr<F>),
+}
+
+impl<F: LurkField> Store<F> {
+    fn set_node(&self, node: &Ptr<F>) -> Result<Node<F>> {
+        if node.tag() != &Tag::Expr(Set) {
+            bail!("{} isn't a set", node.dbg_display(self))
+        }
+        if node == &Ptr::null(Tag::Expr(Set)) {
+            return Ok(Node::Empty);
+        }
+        let Some((a, b)) = node.get_index2().and_then(|idx| self.fetch_2_ptrs(idx)) else {
+            bail!("Set node not found")
+        };
+        match b.tag() {
+            Tag::Expr(Nil) => Ok(Node::Leaf(*a)),
+            Tag::Expr(Set) => Ok(Node::Branch(*a, *b)),
+            _ => bail!("Malformed set node"),
+        }
+    }
+
+    /// The key of `x`, as `SetKey` computes it
+    fn set_key_field(&self, x: &Ptr<F>) -> Result<F> {
+        let z_ptr = self.hash_ptr(x)?;
+        Ok(self
+            .poseidon_cache
+            .hash3(&[z_ptr.tag.to_field(), z_ptr.hash, F::ZERO]))
+    }
+
+    /// The bits of the key of `x`, lowest first
+    pub(crate) fn set_key(&self, x: &Ptr<F>) -> Result<Vec<bool>> {
+        Ok(self.set_key_field(x)?.to_le_bits().into_iter().collect())
+    }
+
+    /// Interns the set of `elems`
+    pub fn intern_set(&mut self, elems: &[Ptr<F>]) -> Result<Ptr<F>> {
+        let empty = Ptr::null(Tag::Expr(Set));
+        elems
+            .iter()
+            .try_fold(empty, |set, x| self.set_insert(set, *x))
+    }
+
+    /// The elements of `set`, in the order of their keys' bits
+    pub fn fetch_set(&self, set: &Ptr<F>) -> Result<Vec<Ptr<F>>> {
+        match self.set_node(set)? {
+            Node::Empty => Ok(vec![]),
+            Node::Leaf(elem) => Ok(vec![elem]),
+            Node::Branch(left, right) => {
+                Ok([self.fetch_set(&left)?, self.fetch_set(&right)?].concat())
+            }
+        }
+    }
+
+    /// Whether `x` is an element of `set`
+    pub fn set_member(&self, set: &Ptr<F>, x: &Ptr<F>) -> Result<bool> {
+        let key = self.set_key(x)?;
+        let mut node = *set;
+        for bit in key {
+            match self.set_node(&node)? {
+                Node::Empty => return Ok(false),
+                Node::Leaf(elem) => return Ok(self.hash_ptr(&elem)? == self.hash_ptr(x)?),
+                Node::Branch(left, right) => {
+                    node = if bit { right } else { left };
+                }
+            }
+        }
+        bail!("Set deeper than the keys of its elements")
+    }
+
+    /// The set of the elements of `set` and `x`
+    pub fn set_insert(&mut self, set: Ptr<F>, x: Ptr<F>) -> Result<Ptr<F>> {
+        let key = self.set_key(&x)?;
+        let leaf = self.intern_2_ptrs(Tag::Expr(Set), x, Ptr::null(Tag::Expr(Nil)));
+        self.insert_leaf(set, leaf, &key, 0)
+    }
+
+    fn insert_leaf(
+        &mut self,
+        node: Ptr<F>,
+        leaf: Ptr<F>,
+        key: &[bool],
+        depth: usize,
+    ) -> Result<Ptr<F>> {
+        match self.set_node(&node)? {
+            Node::Empty => Ok(leaf),
+            Node::Leaf(_) if self.hash_ptr(&node)? == self.hash_ptr(&leaf)? => Ok(node),
+            Node::Leaf(elem) => {
+                let elem_key = self.set_key(&elem)?;
+                self.split(node, leaf, &elem_key, key, depth)
+            }
+            Node::Branch(left, right) => {
+                let (left, right) = match key.get(depth) {
+                    None => bail!("Set deeper than the keys of its elements"),
+                    Some(false) => (self.insert_leaf(left, leaf, key, depth + 1)?, right),
+                    Some(true) => (left, self.insert_leaf(right, leaf, key, depth + 1)?),
+                };
+                Ok(self.intern_2_ptrs(Tag::Expr(Set), left, right))
+            }
+        }
+    }
+
+    /// The subtrie of the leaves `old` and `new`, at `depth`
+    fn split(
+        &mut self,
+        old: Ptr<F>,
+        new: Ptr<F>,
+        old_key: &[bool],
+        new_key: &[bool],
+        depth: usize,
+    ) -> Result<Ptr<F>> {
+        let (Some(old_bit), Some(new_bit)) = (old_key.get(depth), new_key.get(depth)) else {
+            bail!("Distinct elements of a set have the same key")
+        };
+        let empty = Ptr::null(Tag::Expr(Set));
+        let (left, right) = if old_bit == new_bit {
+            let child = self.split(old, new, old_key, new_key, depth + 1)?;
+            if *new_bit {
+                (empty, child)
+            } else {
+                (child, empty)
+            }
+        } else if *new_bit {
+            (old, new)
+        } else {
+            (new, old)
+        };
+        Ok(self.intern_2_ptrs(Tag::Expr(Set), left, right))
+    }
+
+    /// The set of the elements of `a` and `b`
+    pub fn set_union(&mut self, a: Ptr<F>, b: &Ptr<F>) -> Result<Ptr<F>> {
+        let elems = self.fetch_set(b)?;
+        elems
+            .into_iter()
+            .try_fold(a, |set, x| self.set_insert(set, x))
+    }
+}
+
+/// Chains `depth` functions made by `level`, each calling the next one for
+/// the next level of the trie, and `end` for the deepest level
+fn chain(depth: usize, end: Func, level: fn(Func) -> Func) -> Func {
+    (0..depth).fold(end, |next, _| level(next))
+}
+
+/// Follows the path of `key` from `node` to the empty subtrie or the leaf it
+/// ends at, which it returns along with 2 to the power of its depth, times
+/// `pow`
+fn find_level(next: Func) -> Func {
+    func!(set_find(node, key, pow): 2 => {
+        let empty: Expr::Set;
+        if node == empty {
+            return (node, pow)
+        }
+        let (left, right) = unhash2(node);
+        match right.tag {
+            Expr::Nil => {
+                return (node, pow)
+            }
+            Expr::Set => {
+                let two = Num(2);
+                let (key, bit) = div_rem64(key, two);
+                let pow = mul(pow, two);
+                match bit.val {
+                    Num(0) => {
+                        let (end, pow) = next(left, key, pow);
+                        return (end, pow)
+                    }
+                    Num(1) => {
+                        let (end, pow) = next(right, key, pow);
+                        return (end, pow)
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// The deepest level of `find_level`, which fails on branches
+fn find_end() -> Func {
+    func!(set_find(node, key, pow): 2 => {
+        let empty: Expr::Set;
+        if node == empty {
+            return (node, pow)
+        }
+        let (_elem, marker) = unhash2(node);
+        match marker.tag {
+            Expr::Nil => {
+                return (node, pow)
+            }
+        }
+    })
+}
+
+/// Replaces the subtrie the path of `key` ends at with `new`, rehashing the
+/// branches above it
+fn replace_level(next: Func) -> Func {
+    func!(set_replace(node, key, new): 1 => {
+        let empty: Expr::Set;
+        if node == empty {
+            return (new)
+        }
+        let (left, right) = unhash2(node);
+        match right.tag {
+            Expr::Nil => {
+                return (new)
+            }
+            Expr::Set => {
+                let two = Num(2);
+                let (key, bit) = div_rem64(key, two);
+                match bit.val {
+                    Num(0) => {
+                        let (left) = next(left, key, new);
+                        let node: Expr::Set = hash2(left, right);
+                        return (node)
+                    }
+                    Num(1) => {
+                        let (right) = next(right, key, new);
+                        let node: Expr::Set = hash2(left, right);
+                        return (node)
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// The deepest level of `replace_level`, which fails on branches
+fn replace_end() -> Func {
+    func!(set_replace(node, key, new): 1 => {
+        let empty: Expr::Set;
+        if node == empty {
+            return (new)
+        }
+        let (_elem, marker) = unhash2(node);
+        match marker.tag {
+            Expr::Nil => {
+                return (new)
+            }
+        }
+    })
+}
+
+/// The subtrie of the leaves `old` and `new`, whose keys are shifted to the
+/// depth of the subtrie
+fn split_level(next: Func) -> Func {
+    func!(set_split(old, new, old_key, new_key): 1 => {
+        let two = Num(2);
+        let (old_key, old_bit) = div_rem64(old_key, two);
+        let (new_key, new_bit) = div_rem64(new_key, two);
+        let same = eq_val(old_bit, new_bit);
+        match same.val {
+            Num(1) => {
+                let (child) = next(old, new, old_key, new_key);
+                let empty: Expr::Set;
+                match new_bit.val {
+                    Num(0) => {
+                        let node: Expr::Set = hash2(child, empty);
+                        return (node)
+                    }
+                    Num(1) => {
+                        let node: Expr::Set = hash2(empty, child);
+                        return (node)
+                    }
+                }
+            }
+            Num(0) => {
+                match new_bit.val {
+                    Num(0) => {
+                        let node: Expr::Set = hash2(new, old);
+                        return (node)
+                    }
+                    Num(1) => {
+                        let node: Expr::Set = hash2(old, new);
+                        return (node)
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// The deepest level of `split_level`, which fails if the keys agree on the
+/// bit of this level too
+fn split_end() -> Func {
+    func!(set_split(old, new, old_key, new_key): 1 => {
+        let two = Num(2);
+        let (_old_key, old_bit) = div_rem64(old_key, two);
+        let (_new_key, new_bit) = div_rem64(new_key, two);
+        let same = eq_val(old_bit, new_bit);
+        match same.val {
+            Num(0) => {
+                match new_bit.val {
+                    Num(0) => {
+                        let node: Expr::Set = hash2(new, old);
+                        return (node)
+                    }
+                    Num(1) => {
+                        let node: Expr::Set = hash2(old, new);
+                        return (node)
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Returns `t` if the second argument is an element of the set, and `nil`
+/// otherwise. It fails on sets deeper than `depth`, which must be less than 64.
+pub fn set_member(depth: usize) -> Func {
+    assert!(depth < 64, "Sets are at most 63 levels deep");
+    let find = chain(depth, find_end(), find_level);
+    func!(set_member(set, x): 1 => {
+        match set.tag {
+            Expr::Set => {
+                let (key) = coproc set_key(x);
+                let key = truncate(key, 64);
+                let one = Num(1);
+                let (end, _pow) = find(set, key, one);
+                let nil = Symbol("nil");
+                let nil = cast(nil, Expr::Nil);
+                let empty: Expr::Set;
+                if end == empty {
+                    return (nil)
+                }
+                let (elem, _marker) = unhash2(end);
+                if elem == x {
+                    let t = Symbol("t");
+                    return (t)
+                }
+                return (nil)
+            }
+        }
+    })
+}
+
+/// Inserts `x` into the subtrie `node`, whose depth is the logarithm of `pow`.
+/// It fails on subtries deeper than `depth`.
+fn insert_at(depth: usize) -> Func {
+    let find = chain(depth, find_end(), find_level);
+    let replace = chain(depth, replace_end(), replace_level);
+    let split = chain(depth, split_end(), split_level);
+    func!(set_insert_at(node, x, pow): 1 => {
+        let (key) = coproc set_key(x);
+        let key = truncate(key, 64);
+        // the key from the depth of `node` on
+        let (key, _path) = div_rem64(key, pow);
+        let one = Num(1);
+        let (end, end_pow) = find(node, key, one);
+        let marker: Expr::Nil;
+        let leaf: Expr::Set = hash2(x, marker);
+        let empty: Expr::Set;
+        if end == empty {
+            let (node) = replace(node, key, leaf);
+            return (node)
+        }
+        if end == leaf {
+            return (node)
+        }
+        let (elem, _marker) = unhash2(end);
+        let (elem_key) = coproc set_key(elem);
+        let elem_key = truncate(elem_key, 64);
+        // the keys of the elements from the depth of `end` on
+        let elem_pow = mul(pow, end_pow);
+        let (elem_key_rest, _elem_path) = div_rem64(elem_key, elem_pow);
+        let (key_rest, _path) = div_rem64(key, end_pow);
+        let (branch) = split(end, leaf, elem_key_rest, key_rest);
+        let (node) = replace(node, key, branch);
+        return (node)
+    })
+}
+
+/// Inserts the second argument into the set. It fails on sets deeper than
+/// `depth`, which must be less than 64.
+pub fn set_insert(depth: usize) -> Func {
+    assert!(depth < 64, "Sets are at most 63 levels deep");
+    let insert_at = insert_at(depth);
+    func!(set_insert(set, x): 1 => {
+        match set.tag {
+            Expr::Set => {
+                let one = Num(1);
+                let (set) = insert_at(set, x, one);
+                return (set)
+            }
+        }
+    })
+}
+
+/// Merges the subtries `a` and `b`, whose depth is the logarithm of `pow`,
+/// calling `next` on the children of two branches and `insert_at` to insert
+/// the element of a leaf into the other subtrie
+fn union_level(next: Func, insert_at: Func) -> Func {
+    func!(set_union(a, b, pow): 1 => {
+        let empty: Expr::Set;
+        if a == empty {
+            return (b)
+        }
+        if b == empty {
+            return (a)
+        }
+        let (b_left, b_right) = unhash2(b);
+        match b_right.tag {
+            Expr::Nil => {
+                let (node) = insert_at(a, b_left, pow);
+                return (node)
+            }
+        };
+        let (a_left, a_right) = unhash2(a);
+        match a_right.tag {
+            Expr::Nil => {
+                let (node) = insert_at(b, a_left, pow);
+                return (node)
+            }
+        };
+        let two = Num(2);
+        let pow = mul(pow, two);
+        let (left) = next(a_left, b_left, pow);
+        let (right) = next(a_right, b_right, pow);
+        let node: Expr::Set = hash2(left, right);
+        return (node)
+    })
+}
+
+/// The deepest level of `union_level`, which fails on two branches
+fn union_end(insert_at: Func) -> Func {
+    func!(set_union(a, b, pow): 1 => {
+        let empty: Expr::Set;
+        if a == empty {
+            return (b)
+        }
+        if b == empty {
+            return (a)
+        }
+        let (b_left, b_right) = unhash2(b);
+        match b_right.tag {
+            Expr::Nil => {
+                let (node) = insert_at(a, b_left, pow);
+                return (node)
+            }
+        };
+        let (a_left, a_right) = unhash2(a);
+        match a_right.tag {
+            Expr::Nil => {
+                let (node) = insert_at(b, a_left, pow);
+                return (node)
+            }
+        }
+    })
+}
+
+/// The union of two sets. It fails on sets deeper than `depth`, which must be
+/// less than 64, and costs twice as much for each level of depth.
+pub fn set_union(depth: usize) -> Func {
+    assert!(depth < 64, "Sets are at most 63 levels deep");
+    // the level at depth `depth - i - 1` inserts into subtries `i + 1` deep
+    let union = (0..depth).fold(union_end(insert_at(0)), |next, i| {
+        union_level(next, insert_at(i + 1))
+    });
+    func!(set_union(a, b): 1 => {
+        match a.tag {
+            Expr::Set => {
+                match b.tag {
+                    Expr::Set => {
+                        let one = Num(1);
+                        let (set) = union(a, b, one);
+                        return (set)
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bellpepper_core::test_cs::TestConstraintSystem;
+    use blstrs::Scalar as Fr;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::lem::interpreter::Preimages;
+    use crate::state::{lurk_sym, State};
+
+    const DEPTH: usize = 16;
+
+    /// Calls `func`, checks the circuit is satisfied and returns the output
+    fn run(func: &Func, args: Vec<Ptr<Fr>>, store: &mut Store<Fr>) -> Result<Ptr<Fr>> {
+        let (frame, _) = func.call(args, store, Preimages::new_from_func(func))?;
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        func.synthesize(&mut cs, store, &frame)?;
+        assert!(cs.is_satisfied());
+        assert_eq!(func.num_constraints(store), cs.num_constraints());
+        Ok(frame.output[0])
+    }
+
+    #[test]
+    fn sets_are_canonical_tries() {
+        let store = &mut Store::<Fr>::default();
+        store.register_coproc(SET_KEY, Arc::new(SetKey));
+        let state = State::init_lurk_state().rccell();
+        let elems = ["1", "1u64", "'a'", "\"abc\"", "(1 . 2)", "nil", "t"]
+            .map(|source| store.read(state.clone(), source).unwrap());
+        let set = store.intern_set(&elems).unwrap();
+        let mut reversed = elems;
+        reversed.reverse();
+        assert_eq!(set, store.intern_set(&reversed).unwrap());
+        assert_eq!(store.fetch_set(&set).unwrap().len(), elems.len());
+
+        let four = store.read(state, "4").unwrap();
+        let (t, nil) = (elems[6], elems[5]);
+        let member = set_member(DEPTH);
+        for x in elems {
+            assert!(store.set_member(&set, &x).unwrap());
+            assert_eq!(run(&member, vec![set, x], store).unwrap(), t);
+        }
+        assert!(!store.set_member(&set, &four).unwrap());
+        assert_eq!(run(&member, vec![set, four], store).unwrap(), nil);
+
+        // the circuit builds the same tries as the store
+        let insert = set_insert(DEPTH);
+        let mut partial = store.intern_set(&[]).unwrap();
+        for x in elems {
+            partial = run(&insert, vec![partial, x], store).unwrap();
+        }
+        assert_eq!(partial, set);
+        assert_eq!(run(&insert, vec![set, elems[0]], store).unwrap(), set);
+        let with_four = run(&insert, vec![set, four], store).unwrap();
+        assert_eq!(run(&member, vec![with_four, four], store).unwrap(), t);
+
+        let odd = store.intern_set(&[elems[0], four]).unwrap();
+        let union = store.set_union(set, &odd).unwrap();
+        assert_eq!(union, with_four);
+        assert_eq!(nil, store.intern_symbol(&lurk_sym("nil")));
+    }
+
+    #[test]
+    fn keys_depend_on_tags() {
+        let store = &mut Store::<Fr>::default();
+        store.register_coproc(SET_KEY, Arc::new(SetKey));
+        let state = State::init_lurk_state().rccell();
+        // the sums of their hashes and tags are equal
+        let elems =
+            ["100", "'a'", "10", "5u64"].map(|source| store.read(state.clone(), source).unwrap());
+        let keys = elems.map(|x| store.set_key(&x).unwrap());
+        assert_ne!(keys[0], keys[1]);
+        assert_ne!(keys[2], keys[3]);
+
+        let set = store.intern_set(&elems).unwrap();
+        assert_eq!(store.fetch_set(&set).unwrap().len(), elems.len());
+        let t = store.intern_symbol(&lurk_sym("t"));
+        let insert = set_insert(DEPTH);
+        let member = set_member(DEPTH);
+        let mut partial = store.intern_set(&[]).unwrap();
+        for x in elems {
+            partial = run(&insert, vec![partial, x], store).unwrap();
+            assert_eq!(run(&member, vec![partial, x], store).unwrap(), t);
+        }
+        assert_eq!(partial, set);
+    }
+
+    /// The depth of the trie of `set`
+    fn depth(store: &Store<Fr>, set: &Ptr<Fr>) -> usize {
+        match store.set_node(set).unwrap() {
+            Node::Branch(left, right) => 1 + depth(store, &left).max(depth(store, &right)),
+            Node::Empty | Node::Leaf(_) => 0,
+        }
+    }
+
+    #[test]
+    fn keys_colliding_deep_in_the_trie() {
+        let store = &mut Store::<Fr>::default();
+        store.register_coproc(SET_KEY, Arc::new(SetKey));
+        // elements whose keys agree on their lowest 3 bits, so that their
+        // leaves split at depth 3 or deeper
+        let low_bits = |store: &Store<Fr>, x: &Ptr<Fr>| store.set_key(x).unwrap()[..3].to_vec();
+        let nums: Vec<_> = (0..1000u64).map(|n| Ptr::num(Fr::from(n))).collect();
+        let first = low_bits(store, &nums[0]);
+        let elems: Vec<_> = nums
+            .into_iter()
+            .filter(|x| low_bits(store, x) == first)
+            .take(4)
+            .collect();
+        assert_eq!(elems.len(), 4);
+        let set = store.intern_set(&elems).unwrap();
+        assert!(depth(store, &set) >= 4);
+
+        let t = store.intern_symbol(&lurk_sym("t"));
+        let insert = set_insert(DEPTH);
+        let member = set_member(DEPTH);
+        let mut partial = store.intern_set(&[]).unwrap();
+        for x in &elems {
+            partial = run(&insert, vec![partial, *x], store).unwrap();
+            assert_eq!(run(&member, vec![partial, *x], store).unwrap(), t);
+        }
+        assert_eq!(partial, set);
+    }
+
+    #[test]
+    fn unions_in_the_circuit() {
+        let store = &mut Store::<Fr>::default();
+        store.register_coproc(SET_KEY, Arc::new(SetKey));
+        let state = State::init_lurk_state().rccell();
+        let [one, two, a] =
+            ["1", "2", "'a'"].map(|source| store.read(state.clone(), source).unwrap());
+        let left = store.intern_set(&[one, two]).unwrap();
+        let right = store.intern_set(&[two, a]).unwrap();
+        let both = store.intern_set(&[one, two, a]).unwrap();
+        let empty = store.intern_set(&[]).unwrap();
+
+        let union = set_union(depth(store, &both));
+        assert_eq!(run(&union, vec![left, right], store).unwrap(), both);
+        assert_eq!(run(&union, vec![right, left], store).unwrap(), both);
+        assert_eq!(run(&union, vec![left, empty], store).unwrap(), left);
+        assert_eq!(run(&union, vec![empty, right], store).unwrap(), right);
+        assert_eq!(run(&union, vec![both, left], store).unwrap(), both);
+    }
+}
@@ -102,47 +102,136 @@
 //! *STEP 3* respectively. STEP 1 should be performed once per function. Then
 //! STEP 2 will need as many iterations as it takes to evaluate the Lurk
 //! expression and so will STEP 3.
+//!
+//! #### Minimizing slots
+//!
+//! Branches only share the slots of the operations inside of them. So an
+//! operation that comes before a match, but whose result is only read by one
+//! of its arms, takes a slot of its own:
+//!
+//! ```text
+//! let x: Cons = hash2(a, b);
+//! match_tag c {
+//!     Num => { return (x, x, x); },
+//!     Char => {
+//!         let m: Cons = hash2(b, a);
+//!         let n: Cons = hash2(c, a);
+//!         return (m, m, n);
+//!     }
+//! }
+//! ```
+//!
+//! The function above needs three hash2 slots, while it only needs two if `x`
+//! is computed inside of the `Num` arm. `Func::minimize_slots` moves such
+//! operations down into the arms that read them, as long as they don't fail
+//! on any input, and reports the slots it saves.
+//!
+//! #### Sharing slots
+//!
+//! A path may also hash the same preimage twice, as when a cons is built and
+//! then deconstructed right away:
+//!
+//! ```text
+//! let x: Cons = hash2(a, b);
+//! let (c, d) = unhash2(x);
+//! ```
+//!
+//! The `Unhash` needs no slot of its own, since the slot of the `Hash` already
+//! holds the preimage of `x`. With `Func::with_shared_slots`, interpretation,
+//! slot counting and synthesis follow each path with a `SlotMemo`, which
+//! records the slot holding each preimage, by the variables of the preimage
+//! and of the image. An operation whose preimage or image is already in a slot
+//! shares it: it takes no new slot, its targets are bound to the slot's image
+//! or preimage, and it adds no constraints, because the operation that took the
+//! slot already constrained it on a path that includes the current one.
+//! Variables are only bound once per function, so equal variables mean equal
+//! values.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 
-use super::{Block, Ctrl, Op};
+use super::{Block, Ctrl, Func, Op, Var};
 
-#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SlotsCounter {
     pub hash2: usize,
     pub hash3: usize,
     pub hash4: usize,
+    pub hash6: usize,
+    pub hash8: usize,
     pub commitment: usize,
     pub less_than: usize,
+    pub bit_decomp: usize,
+    pub range: usize,
 }
 
 impl SlotsCounter {
     /// This interface is mostly for testing
     #[inline]
-    pub fn new(num_slots: (usize, usize, usize, usize, usize)) -> Self {
+    pub fn new(
+        num_slots: (
+            usize,
+            usize,
+            usize,
+            usize,
+            usize,
+            usize,
+            usize,
+            usize,
+            usize,
+        ),
+    ) -> Self {
         Self {
             hash2: num_slots.0,
             hash3: num_slots.1,
             hash4: num_slots.2,
-            commitment: num_slots.3,
-            less_than: num_slots.4,
+            hash6: num_slots.3,
+            hash8: num_slots.4,
+            commitment: num_slots.5,
+            less_than: num_slots.6,
+            bit_decomp: num_slots.7,
+            range: num_slots.8,
         }
     }
 
+    /// The slots of a single hash of `arity`
     #[inline]
-    pub fn consume_hash2(&mut self) -> usize {
-        self.hash2 += 1;
-        self.hash2 - 1
+    pub fn for_hash(arity: usize) -> Self {
+        let mut slot = Self::default();
+        *slot.hash_mut(arity) = 1;
+        slot
     }
 
+    /// The number of slots for hashes of `arity`, which must be one of
+    /// `HASH_ARITIES`
     #[inline]
-    pub fn consume_hash3(&mut self) -> usize {
-        self.hash3 += 1;
-        self.hash3 - 1
+    pub fn hash(&self, arity: usize) -> usize {
+        match arity {
+            2 => self.hash2,
+            3 => self.hash3,
+            4 => self.hash4,
+            6 => self.hash6,
+            8 => self.hash8,
+            _ => panic!("Unsupported hash arity {arity}"),
+        }
+    }
+
+    fn hash_mut(&mut self, arity: usize) -> &mut usize {
+        match arity {
+            2 => &mut self.hash2,
+            3 => &mut self.hash3,
+            4 => &mut self.hash4,
+            6 => &mut self.hash6,
+            8 => &mut self.hash8,
+            _ => panic!("Unsupported hash arity {arity}"),
+        }
     }
 
     #[inline]
-    pub fn consume_hash4(&mut self) -> usize {
-        self.hash4 += 1;
-        self.hash4 - 1
+    pub fn consume_hash(&mut self, arity: usize) -> usize {
+        let slot = self.hash_mut(arity);
+        *slot += 1;
+        *slot - 1
     }
 
     #[inline]
@@ -157,6 +246,18 @@ impl SlotsCounter {
         self.less_than - 1
     }
 
+    #[inline]
+    pub fn consume_bit_decomp(&mut self) -> usize {
+        self.bit_decomp += 1;
+        self.bit_decomp - 1
+    }
+
+    #[inline]
+    pub fn consume_range(&mut self) -> usize {
+        self.range += 1;
+        self.range - 1
+    }
+
     #[inline]
     pub fn max(&self, other: Self) -> Self {
         use std::cmp::max;
@@ -164,81 +265,345 @@ impl SlotsCounter {
             hash2: max(self.hash2, other.hash2),
             hash3: max(self.hash3, other.hash3),
             hash4: max(self.hash4, other.hash4),
+            hash6: max(self.hash6, other.hash6),
+            hash8: max(self.hash8, other.hash8),
             commitment: max(self.commitment, other.commitment),
             less_than: max(self.less_than, other.less_than),
+            bit_decomp: max(self.bit_decomp, other.bit_decomp),
+            range: max(self.range, other.range),
         }
     }
 
+    /// The number of slots of all types
+    #[inline]
+    pub fn total(&self) -> usize {
+        self.hash2
+            + self.hash3
+            + self.hash4
+            + self.hash6
+            + self.hash8
+            + self.commitment
+            + self.less_than
+            + self.bit_decomp
+            + self.range
+    }
+
     #[inline]
     pub fn add(&self, other: Self) -> Self {
         Self {
             hash2: self.hash2 + other.hash2,
             hash3: self.hash3 + other.hash3,
             hash4: self.hash4 + other.hash4,
+            hash6: self.hash6 + other.hash6,
+            hash8: self.hash8 + other.hash8,
             commitment: self.commitment + other.commitment,
             less_than: self.less_than + other.less_than,
+            bit_decomp: self.bit_decomp + other.bit_decomp,
+            range: self.range + other.range,
         }
     }
 }
 
+impl Op {
+    /// The slots the operation takes
+    fn count_slots(&self) -> SlotsCounter {
+        match self {
+            Op::Hash(_, _, preimg) | Op::Unhash(preimg, _) => SlotsCounter::for_hash(preimg.len()),
+            Op::Hide(..) | Op::Open(..) => SlotsCounter::new((0, 0, 0, 0, 0, 1, 0, 0, 0)),
+            Op::Lt(..) => SlotsCounter::new((0, 0, 0, 0, 0, 0, 1, 0, 0)),
+            Op::Trunc(..) => SlotsCounter::new((0, 0, 0, 0, 0, 0, 0, 1, 0)),
+            Op::And(..) | Op::Or(..) | Op::Xor(..) => {
+                SlotsCounter::new((0, 0, 0, 0, 0, 0, 0, 2, 0))
+            }
+            Op::AssertRange(..) => SlotsCounter::new((0, 0, 0, 0, 0, 0, 0, 0, 1)),
+            Op::Call(_, func, _) => func.slot,
+            _ => SlotsCounter::default(),
+        }
+    }
+}
+
+/// The hash slots taken so far on a path of a function, by the variables of
+/// the preimages they hold and of their images
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SlotMemo {
+    share: bool,
+    preimgs: HashMap<Vec<Var>, usize>,
+    imgs: HashMap<(Var, usize), usize>,
+}
+
+impl SlotMemo {
+    /// A memo that only shares slots if `share` is true
+    #[inline]
+    pub(crate) fn new(share: bool) -> Self {
+        Self {
+            share,
+            ..Self::default()
+        }
+    }
+
+    /// The hash slot of `op`, which must be a `Hash` or an `Unhash`, along
+    /// with whether it's shared. It's the slot an earlier operation of the
+    /// path took for the same preimage or image, if any, or otherwise the
+    /// slot `take` takes
+    pub(crate) fn hash_slot(&mut self, op: &Op, take: impl FnOnce() -> usize) -> (usize, bool) {
+        let (Op::Hash(img, _, preimg) | Op::Unhash(preimg, img)) = op else {
+            panic!("Only `Hash` and `Unhash` take hash slots")
+        };
+        if !self.share {
+            return (take(), false);
+        }
+        let img = (img.clone(), preimg.len());
+        let shared = match op {
+            Op::Hash(..) => self.preimgs.get(preimg),
+            _ => self.imgs.get(&img),
+        }
+        .copied();
+        let slot = shared.unwrap_or_else(take);
+        self.preimgs.insert(preimg.clone(), slot);
+        self.imgs.insert(img, slot);
+        (slot, shared.is_some())
+    }
+}
+
 impl Block {
     pub fn count_slots(&self) -> SlotsCounter {
-        let ops_slots = self.ops.iter().fold(SlotsCounter::default(), |acc, op| {
-            let val = match op {
-                Op::Hash2(..) | Op::Unhash2(..) => SlotsCounter::new((1, 0, 0, 0, 0)),
-                Op::Hash3(..) | Op::Unhash3(..) => SlotsCounter::new((0, 1, 0, 0, 0)),
-                Op::Hash4(..) | Op::Unhash4(..) => SlotsCounter::new((0, 0, 1, 0, 0)),
-                Op::Hide(..) | Op::Open(..) => SlotsCounter::new((0, 0, 0, 1, 0)),
-                Op::Lt(..) => SlotsCounter::new((0, 0, 0, 0, 1)),
-                Op::Call(_, func, _) => func.slot,
-                _ => SlotsCounter::default(),
-            };
-            acc.add(val)
-        });
-        let ctrl_slots = match &self.ctrl {
-            Ctrl::MatchTag(_, cases, def) => {
-                let init = def
-                    .as_ref()
-                    .map_or(SlotsCounter::default(), |def| def.count_slots());
-                cases
-                    .values()
-                    .fold(init, |acc, block| acc.max(block.count_slots()))
+        self.count_slots_sharing(false)
+    }
+
+    /// Like `count_slots`, but with the slots shared as explained above if
+    /// `share` is true
+    pub(crate) fn count_slots_sharing(&self, share: bool) -> SlotsCounter {
+        self.count_slots_from(SlotsCounter::default(), SlotMemo::new(share))
+    }
+
+    /// The slots taken by the end of the longest path of the block, when
+    /// `next_slot` were taken before it
+    fn count_slots_from(&self, mut next_slot: SlotsCounter, mut memo: SlotMemo) -> SlotsCounter {
+        for op in &self.ops {
+            match op {
+                Op::Hash(_, _, preimg) | Op::Unhash(preimg, _) => {
+                    memo.hash_slot(op, || next_slot.consume_hash(preimg.len()));
+                }
+                op => next_slot = next_slot.add(op.count_slots()),
             }
-            Ctrl::MatchVal(_, cases, def) => {
-                let init = def
-                    .as_ref()
-                    .map_or(SlotsCounter::default(), |def| def.count_slots());
-                cases
-                    .values()
-                    .fold(init, |acc, block| acc.max(block.count_slots()))
+        }
+        let branches: Vec<&Block> = match &self.ctrl {
+            Ctrl::MatchTag(_, cases, def) => cases.values().chain(def.as_deref()).collect(),
+            Ctrl::MatchVal(_, cases, def) => cases.values().chain(def.as_deref()).collect(),
+            Ctrl::MatchSymbol(_, cases, def) => cases.values().chain(def.as_deref()).collect(),
+            Ctrl::IfEq(_, _, eq_block, else_block) => vec![&**eq_block, &**else_block],
+            Ctrl::Return(..) => vec![],
+        };
+        branches.into_iter().fold(next_slot, |acc, block| {
+            acc.max(block.count_slots_from(next_slot, memo.clone()))
+        })
+    }
+
+    /// Makes the functions called by the block share slots
+    fn share_slots(&mut self) {
+        for op in &mut self.ops {
+            if let Op::Call(_, func, _) = op {
+                func.set_shared_slots();
             }
-            Ctrl::IfEq(_, _, eq_block, else_block) => {
-                let eq_slots = eq_block.count_slots();
-                eq_slots.max(else_block.count_slots())
+        }
+        for branch in self.ctrl.branches_mut() {
+            branch.share_slots();
+        }
+    }
+}
+
+impl Ctrl {
+    /// The variables read by the control itself, not by its branches
+    fn own_vars(&self) -> Vec<&Var> {
+        match self {
+            Ctrl::MatchTag(var, ..) | Ctrl::MatchVal(var, ..) | Ctrl::MatchSymbol(var, ..) => {
+                vec![var]
+            }
+            Ctrl::IfEq(x, y, ..) => vec![x, y],
+            Ctrl::Return(vars) => vars.iter().collect(),
+        }
+    }
+
+    pub(crate) fn branches_mut(&mut self) -> Vec<&mut Block> {
+        match self {
+            Ctrl::MatchTag(_, cases, def) => cases.values_mut().chain(def.as_deref_mut()).collect(),
+            Ctrl::MatchVal(_, cases, def) => cases.values_mut().chain(def.as_deref_mut()).collect(),
+            Ctrl::MatchSymbol(_, cases, def) => {
+                cases.values_mut().chain(def.as_deref_mut()).collect()
+            }
+            Ctrl::IfEq(_, _, eq_block, else_block) => vec![&mut **eq_block, &mut **else_block],
+            Ctrl::Return(..) => vec![],
+        }
+    }
+}
+
+impl Block {
+    /// Moves the operations that take slots into the only branch that reads
+    /// their targets, as long as they don't fail on any input, then does the
+    /// same for the branches and the functions called
+    fn minimize_slots(self, reports: &mut Vec<SlotsReport>) -> Block {
+        let Block { ops, mut ctrl } = self;
+        // The variables read by the operations that stay and by the control
+        let mut reads: HashSet<Var> = ctrl.own_vars().into_iter().cloned().collect();
+        let mut branches = ctrl.branches_mut();
+        let mut branch_reads = branches
+            .iter()
+            .map(|branch| branch.free_vars())
+            .collect::<Vec<_>>();
+        let mut sunk = vec![vec![]; branches.len()];
+        let mut kept = Vec::with_capacity(ops.len());
+        // Going backwards, every read of an operation's targets is known by
+        // the time it's visited
+        for op in ops.into_iter().rev() {
+            let targets = op.targets();
+            let readers = (0..branches.len())
+                .filter(|i| targets.iter().any(|tgt| branch_reads[*i].contains(*tgt)))
+                .collect::<Vec<_>>();
+            let sinkable = !op.has_effects()
+                && op.count_slots() != SlotsCounter::default()
+                && !targets.iter().any(|tgt| reads.contains(*tgt));
+            match readers[..] {
+                [i] if sinkable => {
+                    branch_reads[i].extend(op.sources().into_iter().cloned());
+                    sunk[i].push(op);
+                }
+                _ => {
+                    reads.extend(op.sources().into_iter().cloned());
+                    kept.push(op);
+                }
             }
-            Ctrl::Return(..) => SlotsCounter::default(),
+        }
+        for (branch, mut sunk) in branches.iter_mut().zip(sunk) {
+            sunk.reverse();
+            sunk.append(&mut branch.ops);
+            branch.ops = sunk;
+            let block = std::mem::replace(
+                &mut **branch,
+                Block {
+                    ops: vec![],
+                    ctrl: Ctrl::Return(vec![]),
+                },
+            );
+            **branch = block.minimize_slots(reports);
+        }
+        drop(branches);
+        kept.reverse();
+        let ops = kept
+            .into_iter()
+            .map(|op| match op {
+                Op::Call(tgts, func, srcs) => {
+                    Op::Call(tgts, Box::new(func.minimize_slots_with(reports)), srcs)
+                }
+                op => op,
+            })
+            .collect();
+        Block { ops, ctrl }
+    }
+}
+
+/// The slots a function takes before and after `Func::minimize_slots`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SlotsReport {
+    pub func: String,
+    pub before: SlotsCounter,
+    pub after: SlotsCounter,
+}
+
+impl SlotsReport {
+    /// The number of slots saved, of all types
+    #[inline]
+    pub fn savings(&self) -> usize {
+        self.before.total() - self.after.total()
+    }
+}
+
+impl std::fmt::Display for SlotsReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} slots, down from {} (hash2 {}, hash3 {}, hash4 {}, hash6 {}, hash8 {}, \
+            commitment {}, less_than {}, bit_decomp {}, range {})",
+            self.func,
+            self.after.total(),
+            self.before.total(),
+            self.after.hash2,
+            self.after.hash3,
+            self.after.hash4,
+            self.after.hash6,
+            self.after.hash8,
+            self.after.commitment,
+            self.after.less_than,
+            self.after.bit_decomp,
+            self.after.range,
+        )
+    }
+}
+
+impl Func {
+    /// Makes the operations of a path that hash a preimage an earlier one
+    /// already hashed, or unhash an image it already unhashed, share its slot,
+    /// as explained above. The functions it calls share slots too. It changes
+    /// the shape of the circuit, and thus the public parameters, so it's opt-in.
+    pub fn with_shared_slots(mut self) -> Self {
+        self.set_shared_slots();
+        self
+    }
+
+    fn set_shared_slots(&mut self) {
+        self.body.share_slots();
+        self.share_slots = true;
+        self.slot = self.body.count_slots_sharing(true);
+    }
+
+    /// Returns an equivalent function whose operations are moved into the
+    /// branches that read them, so they can share slots with the other
+    /// branches, along with a report of the savings for this function,
+    /// followed by one for each function it calls. Like `Func::optimize`, it changes the shape of
+    /// the circuit, so it's up to the caller to apply it.
+    pub fn minimize_slots(&self) -> (Func, Vec<SlotsReport>) {
+        let mut reports = vec![];
+        let func = self.minimize_slots_with(&mut reports);
+        // the function itself is pushed after its callees, but reported first
+        reports.rotate_right(1);
+        (func, reports)
+    }
+
+    fn minimize_slots_with(&self, reports: &mut Vec<SlotsReport>) -> Func {
+        let body = self.body.clone().minimize_slots(reports);
+        let func = Func {
+            slot: body.count_slots_sharing(self.share_slots),
+            body,
+            ..self.clone()
         };
-        ops_slots.add(ctrl_slots)
+        if !reports.iter().any(|report| report.func == self.name) {
+            reports.push(SlotsReport {
+                func: self.name.clone(),
+                before: self.slot,
+                after: func.slot,
+            });
+        }
+        func
     }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub(crate) enum SlotType {
-    Hash2,
-    Hash3,
-    Hash4,
+    /// The slots of the hashes with as many children as the arity
+    Hash(usize),
     Commitment,
     LessThan,
+    BitDecomp,
+    Range,
 }
 
 impl SlotType {
     pub(crate) fn preimg_size(&self) -> usize {
         match self {
-            Self::Hash2 => 4,
-            Self::Hash3 => 6,
-            Self::Hash4 => 8,
+            Self::Hash(arity) => 2 * arity,
             Self::Commitment => 3,
             Self::LessThan => 2,
+            Self::BitDecomp => 1,
+            Self::Range => 1,
         }
     }
 }
@@ -246,11 +611,11 @@ impl SlotType {
 impl std::fmt::Display for SlotType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Hash2 => write!(f, "Hash2"),
-            Self::Hash3 => write!(f, "Hash3"),
-            Self::Hash4 => write!(f, "Hash4"),
+            Self::Hash(arity) => write!(f, "Hash{arity}"),
             Self::Commitment => write!(f, "Commitment"),
             Self::LessThan => write!(f, "LessThan"),
+            Self::BitDecomp => write!(f, "BitDecomp"),
+            Self::Range => write!(f, "Range"),
         }
     }
 }
@@ -267,3 +632,105 @@ impl std::fmt::Display for Slot {
         write!(f, "Slot({}, {})", self.idx, self.typ)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::LurkField;
+    use crate::func;
+    use crate::lem::{interpreter::Preimages, pointers::Ptr, store::Store, Tag};
+    use crate::tag::ExprTag::Char;
+    use bellpepper_core::test_cs::TestConstraintSystem;
+    use blstrs::Scalar as Fr;
+
+    #[test]
+    fn ops_read_by_one_branch_are_moved_into_it() {
+        let inner = func!(inner(a, b): 1 => {
+            let x: Expr::Cons = hash2(a, b);
+            let y: Expr::Cons = hash2(b, a);
+            match a.tag {
+                Expr::Num => {
+                    return (x);
+                }
+            };
+            return (y);
+        });
+        let lem = func!(outer(a, b, c): 3 => {
+            let x: Expr::Cons = hash2(a, b);
+            // read by both arms
+            let shared: Expr::Cons = hash3(a, b, c);
+            let (z) = inner(a, b);
+            match c.tag {
+                Expr::Num => {
+                    return (x, shared, z);
+                }
+                Expr::Char => {
+                    let m: Expr::Cons = hash2(b, a);
+                    let n: Expr::Cons = hash2(c, a);
+                    return (m, n, shared);
+                }
+            }
+        });
+        assert_eq!(lem.slot, SlotsCounter::new((5, 1, 0, 0, 0, 0, 0, 0, 0)));
+
+        let (min, reports) = lem.minimize_slots();
+        // only `shared` is left before the match
+        assert_eq!(min.slot, SlotsCounter::new((2, 1, 0, 0, 0, 0, 0, 0, 0)));
+        assert_eq!(min.body.ops.len(), 1);
+        let [outer_report, inner_report] = &reports[..] else {
+            panic!("expected two reports, got {reports:?}")
+        };
+        assert_eq!(outer_report.func, "outer");
+        assert_eq!(outer_report.savings(), 3);
+        assert_eq!(inner_report.func, "inner");
+        assert_eq!(inner_report.savings(), 1);
+
+        let store = &mut Store::<Fr>::default();
+        let (one, two) = (Ptr::num(Fr::from_u64(1)), Ptr::num(Fr::from_u64(2)));
+        for c in [
+            Ptr::num(Fr::from_u64(3)),
+            Ptr::Leaf(Tag::Expr(Char), Fr::from_u64(97)),
+        ] {
+            let (frame, _) = lem
+                .call(vec![one, two, c], store, Preimages::new_from_func(&lem))
+                .unwrap();
+            let (min_frame, _) = min
+                .call(vec![one, two, c], store, Preimages::new_from_func(&min))
+                .unwrap();
+            assert_eq!(frame.output, min_frame.output);
+        }
+        assert!(min.num_constraints(store) < lem.num_constraints(store));
+        min.assert_num_constraints(store);
+    }
+
+    #[test]
+    fn identical_preimages_share_a_slot() {
+        let lem = func!(rebuild(a, b): 2 => {
+            let x: Expr::Cons = hash2(a, b);
+            let (c, d) = unhash2(x);
+            let y: Expr::Cons = hash2(c, d);
+            return (x, y)
+        });
+        assert_eq!(lem.slot.hash2, 3);
+        let shared = lem.clone().with_shared_slots();
+        assert_eq!(shared.slot.hash2, 1);
+
+        let store = &mut Store::<Fr>::default();
+        let args = vec![Ptr::num(Fr::from_u64(1)), Ptr::num(Fr::from_u64(2))];
+        let (frame, _) = lem
+            .call(args.clone(), store, Preimages::new_from_func(&lem))
+            .unwrap();
+        let (shared_frame, _) = shared
+            .call(args, store, Preimages::new_from_func(&shared))
+            .unwrap();
+        assert_eq!(frame.output, shared_frame.output);
+        assert_eq!(shared_frame.output[0], shared_frame.output[1]);
+
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        shared.synthesize(&mut cs, store, &shared_frame).unwrap();
+        assert!(cs.is_satisfied());
+        assert_eq!(shared.num_constraints(store), cs.num_constraints());
+        assert!(shared.num_constraints(store) < lem.num_constraints(store));
+        shared.assert_num_constraints(store);
+    }
+}
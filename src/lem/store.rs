@@ -1,32 +1,65 @@
 use rayon::prelude::*;
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    mem::size_of,
+    path::Path,
+    rc::Rc,
+    sync::Arc,
+};
 
 use crate::{
     field::{FWrap, LurkField},
-    hash::PoseidonCache,
+    hash::{PoseidonCache, PoseidonCacheStats},
+    hasher::{DashMap, HashMap, IndexSet},
     lem::Tag,
     state::{lurk_sym, State},
     symbol::Symbol,
     syntax::Syntax,
-    tag::ExprTag::*,
+    tag::{ContTagRegistry, ExprTag::*},
     uint::UInt,
 };
-use anyhow::{bail, Result};
-use dashmap::DashMap;
-use indexmap::IndexSet;
+use anyhow::{anyhow, bail, Result};
+
+use super::{
+    coproc::Coproc,
+    foreign::ForeignResolver,
+    pointers::{ptr_index, to_usize, CompactPtr, Ptr, ZChildren, ZPtr},
+    AString,
+};
 
-use super::pointers::{Ptr, ZChildren, ZPtr};
+/// What a `Store` holds, as returned by `Store::stats`
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoreStats {
+    /// The number of tuples of 2, 3, 4, 6 and 8 pointers
+    pub tuples: [usize; 5],
+    /// The number of pointers with children, or with cached hashes, by tag
+    pub tags: HashMap<Tag, usize>,
+    /// The number of interned strings
+    pub strings: usize,
+    /// The number of interned symbol paths, along with their prefixes
+    pub symbols: usize,
+    /// The number of pointers whose hashes are cached
+    pub hashes: usize,
+    pub comms: usize,
+    pub poseidon: PoseidonCacheStats,
+    /// An estimate of the bytes taken by the data the store holds, not
+    /// counting the spare capacity of its collections
+    pub memory: usize,
+}
 
 /// The `Store` is a crucial part of Lurk's implementation and tries to be a
 /// vesatile data structure for many parts of Lurk's data pipeline.
 ///
 /// It holds Lurk data structured as trees of `Ptr`s (or `ZPtr`s). When a `Ptr`
-/// has children`, we store them in the `IndexSet`s available: `tuple2`, `tuple3`
-/// or `tuple4`. These data structures speed up LEM interpretation because lookups
-/// by indices are fast.
+/// has children`, we store them in the `IndexSet`s available: `tuple2`, `tuple3`,
+/// `tuple4`, `tuple6` or `tuple8`. These data structures speed up LEM
+/// interpretation because lookups by indices are fast.
 ///
 /// The `Store` also provides an infra to speed up interning strings and symbols.
-/// This data is saved in `str_tails_cache` and `sym_tails_cache`, which are better
+/// This data is saved in `ptr_str_cache` and `ptr_sym_cache`, which are better
 /// explained in `intern_string` and `intern_symbol_path` respectively.
 ///
 /// There's also a process that we call "hydration", in which we use Poseidon
@@ -34,32 +67,124 @@ use super::pointers::{Ptr, ZChildren, ZPtr};
 /// are necessary when we want to create Lurk proofs because the circuit consumes
 /// elements of the `LurkField`, not (unstable) indices of `IndexSet`s.
 ///
-/// Lastly, we have a `HashMap` to hold committed data, which can be retrieved by
-/// the resulting commitment hash.
+/// We have a `HashMap` to hold committed data, which can be retrieved by the
+/// resulting commitment hash.
+///
+/// Lastly, the `Store` holds the coprocessors that `Op::Coproc` refers to by
+/// name, the resolvers of the foreign addresses it reads, by scheme, and the
+/// continuation tags alternate evaluators register.
 #[derive(Default, Debug)]
 pub struct Store<F: LurkField> {
     tuple2: IndexSet<(Ptr<F>, Ptr<F>)>,
     tuple3: IndexSet<(Ptr<F>, Ptr<F>, Ptr<F>)>,
     tuple4: IndexSet<(Ptr<F>, Ptr<F>, Ptr<F>, Ptr<F>)>,
+    tuple6: IndexSet<[Ptr<F>; 6]>,
+    tuple8: IndexSet<[Ptr<F>; 8]>,
+    /// The values of the leaves of `CompactPtr`s that don't fit in a `u32`
+    leaves: IndexSet<FWrap<F>>,
 
-    str_cache: HashMap<String, Ptr<F>>,
-    ptr_str_cache: HashMap<Ptr<F>, String>,
+    str_cache: HashMap<Arc<str>, Ptr<F>>,
+    /// Every suffix of the interned strings, as the text it was interned with
+    ptr_str_cache: HashMap<Ptr<F>, StrSuffix>,
     sym_cache: HashMap<Vec<String>, Ptr<F>>,
     ptr_sym_cache: HashMap<Ptr<F>, Vec<String>>,
 
     pub poseidon_cache: PoseidonCache<F>,
     dehydrated: Vec<Ptr<F>>,
-    z_cache: DashMap<Ptr<F>, ZPtr<F>, ahash::RandomState>,
-    z_dag: DashMap<ZPtr<F>, ZChildren<F>, ahash::RandomState>,
+    z_cache: DashMap<Ptr<F>, ZPtr<F>>,
+    z_dag: DashMap<ZPtr<F>, ZChildren<F>>,
+    /// The inverse of `z_cache`
+    z_index: DashMap<ZPtr<F>, Ptr<F>>,
 
     pub comms: HashMap<FWrap<F>, (F, Ptr<F>)>, // hash -> (secret, src)
+
+    coprocs: HashMap<AString, Arc<dyn Coproc<F>>>,
+    resolvers: HashMap<AString, Arc<dyn ForeignResolver<F>>>,
+    cont_tags: ContTagRegistry,
 }
 
 impl<F: LurkField> Store<F> {
+    /// An empty store with the coprocessors, the foreign resolvers and the
+    /// continuation tags of this one
+    pub fn fork(&self) -> Self {
+        Self {
+            coprocs: self.coprocs.clone(),
+            resolvers: self.resolvers.clone(),
+            cont_tags: self.cont_tags.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Registers `coproc` under `name`, replacing the coprocessor that was
+    /// registered under it, if any
+    pub fn register_coproc(&mut self, name: &str, coproc: Arc<dyn Coproc<F>>) {
+        self.coprocs.insert(name.into(), coproc);
+    }
+
+    /// Retrieves the coprocessor registered under `name`
+    pub fn coproc(&self, name: &str) -> Result<Arc<dyn Coproc<F>>> {
+        match self.coprocs.get(name) {
+            Some(coproc) => Ok(coproc.clone()),
+            None => bail!("Coprocessor {name} not registered"),
+        }
+    }
+
+    /// Registers `resolver` for the foreign addresses of `scheme`, replacing
+    /// the resolver that was registered for it, if any
+    pub fn register_resolver(&mut self, scheme: &str, resolver: Arc<dyn ForeignResolver<F>>) {
+        self.resolvers.insert(scheme.into(), resolver);
+    }
+
+    /// The tag of the continuations registered as `name`, registering them
+    /// under the next free tag of `USER_CONT_TAGS` if they aren't yet
+    pub fn register_cont_tag(&mut self, name: &str) -> Result<Tag> {
+        Ok(Tag::UserCont(self.cont_tags.register(name)?))
+    }
+
+    /// Retrieves the tag of the continuations registered as `name`
+    pub fn cont_tag(&self, name: &str) -> Result<Tag> {
+        match self.cont_tags.get(name) {
+            Some(tag) => Ok(Tag::UserCont(tag)),
+            None => bail!("Continuation {name} not registered"),
+        }
+    }
+
+    /// The hash of the commitment to `payload` with `secret`
+    pub fn hash_comm(&self, secret: F, payload: &Ptr<F>) -> Result<F> {
+        let z_ptr = self.hash_ptr(payload)?;
+        let preimage = [secret, z_ptr.tag.to_field(), z_ptr.hash];
+        Ok(self.poseidon_cache.hash3(&preimage))
+    }
+
+    /// Interns the foreign address `#scheme:id` as an opaque commitment, whose
+    /// hash is the digest the resolver registered for `scheme` reads `id` as.
+    /// If the resolver fetches what it commits to, the commitment is added to
+    /// the store once its hash is checked against the digest. Without a
+    /// resolver, it's the hash of the commitment to the string `"scheme:id"`.
+    pub fn intern_foreign(&mut self, scheme: &str, id: &str) -> Result<Ptr<F>> {
+        let hash = match self.resolvers.get(scheme).cloned() {
+            Some(resolver) => {
+                let hash = resolver.digest(id)?;
+                if let Some((secret, payload)) = resolver.fetch(self, id)? {
+                    if self.hash_comm(secret, &payload)? != hash {
+                        bail!("Payload of #{scheme}:{id} doesn't match its digest");
+                    }
+                    self.comms.insert(FWrap(hash), (secret, payload));
+                }
+                hash
+            }
+            None => {
+                let payload = self.intern_string(&format!("{scheme}:{id}"));
+                self.hash_comm(F::NON_HIDING_COMMITMENT_SECRET, &payload)?
+            }
+        };
+        Ok(Ptr::comm(hash))
+    }
+
     /// Creates a `Ptr` that's a parent of two children
     pub fn intern_2_ptrs(&mut self, tag: Tag, a: Ptr<F>, b: Ptr<F>) -> Ptr<F> {
         let (idx, inserted) = self.tuple2.insert_full((a, b));
-        let ptr = Ptr::Tuple2(tag, idx);
+        let ptr = Ptr::Tuple2(tag, ptr_index(idx));
         if inserted {
             // this is for `hydrate_z_cache`
             self.dehydrated.push(ptr);
@@ -72,13 +197,13 @@ impl<F: LurkField> Store<F> {
     /// `Store` (TODO).
     #[inline]
     pub fn intern_2_ptrs_not_dehydrated(&mut self, tag: Tag, a: Ptr<F>, b: Ptr<F>) -> Ptr<F> {
-        Ptr::Tuple2(tag, self.tuple2.insert_full((a, b)).0)
+        Ptr::Tuple2(tag, ptr_index(self.tuple2.insert_full((a, b)).0))
     }
 
     /// Creates a `Ptr` that's a parent of three children
     pub fn intern_3_ptrs(&mut self, tag: Tag, a: Ptr<F>, b: Ptr<F>, c: Ptr<F>) -> Ptr<F> {
         let (idx, inserted) = self.tuple3.insert_full((a, b, c));
-        let ptr = Ptr::Tuple3(tag, idx);
+        let ptr = Ptr::Tuple3(tag, ptr_index(idx));
         if inserted {
             // this is for `hydrate_z_cache`
             self.dehydrated.push(ptr);
@@ -97,7 +222,7 @@ impl<F: LurkField> Store<F> {
         b: Ptr<F>,
         c: Ptr<F>,
     ) -> Ptr<F> {
-        Ptr::Tuple3(tag, self.tuple3.insert_full((a, b, c)).0)
+        Ptr::Tuple3(tag, ptr_index(self.tuple3.insert_full((a, b, c)).0))
     }
 
     /// Creates a `Ptr` that's a parent of four children
@@ -110,7 +235,7 @@ impl<F: LurkField> Store<F> {
         d: Ptr<F>,
     ) -> Ptr<F> {
         let (idx, inserted) = self.tuple4.insert_full((a, b, c, d));
-        let ptr = Ptr::Tuple4(tag, idx);
+        let ptr = Ptr::Tuple4(tag, ptr_index(idx));
         if inserted {
             // this is for `hydrate_z_cache`
             self.dehydrated.push(ptr);
@@ -130,7 +255,96 @@ impl<F: LurkField> Store<F> {
         c: Ptr<F>,
         d: Ptr<F>,
     ) -> Ptr<F> {
-        Ptr::Tuple4(tag, self.tuple4.insert_full((a, b, c, d)).0)
+        Ptr::Tuple4(tag, ptr_index(self.tuple4.insert_full((a, b, c, d)).0))
+    }
+
+    /// Creates a `Ptr` that's a parent of six children
+    pub fn intern_6_ptrs(&mut self, tag: Tag, ptrs: [Ptr<F>; 6]) -> Ptr<F> {
+        let (idx, inserted) = self.tuple6.insert_full(ptrs);
+        let ptr = Ptr::Tuple6(tag, ptr_index(idx));
+        if inserted {
+            // this is for `hydrate_z_cache`
+            self.dehydrated.push(ptr);
+        }
+        ptr
+    }
+
+    /// Creates a `Ptr` that's a parent of eight children
+    pub fn intern_8_ptrs(&mut self, tag: Tag, ptrs: [Ptr<F>; 8]) -> Ptr<F> {
+        let (idx, inserted) = self.tuple8.insert_full(ptrs);
+        let ptr = Ptr::Tuple8(tag, ptr_index(idx));
+        if inserted {
+            // this is for `hydrate_z_cache`
+            self.dehydrated.push(ptr);
+        }
+        ptr
+    }
+
+    /// Creates a `Ptr` that's a parent of `ptrs`, as long as their number is
+    /// one of `HASH_ARITIES`
+    pub fn intern_ptrs(&mut self, tag: Tag, ptrs: &[Ptr<F>]) -> Result<Ptr<F>> {
+        match ptrs {
+            [a, b] => Ok(self.intern_2_ptrs(tag, *a, *b)),
+            [a, b, c] => Ok(self.intern_3_ptrs(tag, *a, *b, *c)),
+            [a, b, c, d] => Ok(self.intern_4_ptrs(tag, *a, *b, *c, *d)),
+            _ if ptrs.len() == 6 => Ok(self.intern_6_ptrs(tag, ptrs.try_into()?)),
+            _ if ptrs.len() == 8 => Ok(self.intern_8_ptrs(tag, ptrs.try_into()?)),
+            _ => bail!("Can't intern a parent of {} pointers", ptrs.len()),
+        }
+    }
+
+    /// The children of `ptr`, or `None` if it's a leaf or it's not in the store
+    pub fn fetch_ptrs(&self, ptr: &Ptr<F>) -> Option<Vec<Ptr<F>>> {
+        match ptr {
+            Ptr::Leaf(..) => None,
+            Ptr::Tuple2(_, idx) => self.fetch_2_ptrs(to_usize(*idx)).map(|(a, b)| vec![*a, *b]),
+            Ptr::Tuple3(_, idx) => self
+                .fetch_3_ptrs(to_usize(*idx))
+                .map(|(a, b, c)| vec![*a, *b, *c]),
+            Ptr::Tuple4(_, idx) => self
+                .fetch_4_ptrs(to_usize(*idx))
+                .map(|(a, b, c, d)| vec![*a, *b, *c, *d]),
+            Ptr::Tuple6(_, idx) => self.fetch_6_ptrs(to_usize(*idx)).map(|ptrs| ptrs.to_vec()),
+            Ptr::Tuple8(_, idx) => self.fetch_8_ptrs(to_usize(*idx)).map(|ptrs| ptrs.to_vec()),
+        }
+    }
+
+    /// The compact form of `ptr`, for which the value of a leaf is held inline
+    /// if it fits in a `u32` and is interned among the store's leaves
+    /// otherwise. Fails if the index of `ptr` doesn't fit in a `u32`
+    pub fn compact(&mut self, ptr: &Ptr<F>) -> Result<CompactPtr> {
+        let index = match ptr {
+            Ptr::Leaf(tag, f) => match f.to_u32() {
+                Some(value) => return Ok(CompactPtr::inline_leaf(*tag, value)),
+                None => self.leaves.insert_full(FWrap(*f)).0,
+            },
+            _ => ptr.index().expect("only leaves have no index"),
+        };
+        let Ok(index) = u32::try_from(index) else {
+            bail!("Can't compact a pointer with index {index}")
+        };
+        let arity = u8::try_from(ptr.arity()).expect("arities fit in a byte");
+        Ok(CompactPtr::new(*ptr.tag(), arity, index))
+    }
+
+    /// The pointer whose compact form is `ptr`
+    pub fn expand(&self, ptr: &CompactPtr) -> Result<Ptr<F>> {
+        let (tag, idx) = (*ptr.tag(), ptr.index());
+        if let Some(value) = ptr.inline_value() {
+            return Ok(Ptr::Leaf(tag, F::from_u64(value.into())));
+        }
+        match ptr.arity() {
+            0 => match self.leaves.get_index(idx) {
+                Some(FWrap(f)) => Ok(Ptr::Leaf(tag, *f)),
+                None => bail!("Leaf {idx} not found"),
+            },
+            2 => Ok(Ptr::Tuple2(tag, ptr_index(idx))),
+            3 => Ok(Ptr::Tuple3(tag, ptr_index(idx))),
+            4 => Ok(Ptr::Tuple4(tag, ptr_index(idx))),
+            6 => Ok(Ptr::Tuple6(tag, ptr_index(idx))),
+            8 => Ok(Ptr::Tuple8(tag, ptr_index(idx))),
+            arity => bail!("Invalid arity {arity}"),
+        }
     }
 
     #[inline]
@@ -148,32 +362,123 @@ impl<F: LurkField> Store<F> {
         self.tuple4.get_index(idx)
     }
 
-    /// Interns a string recursively
+    #[inline]
+    pub fn fetch_6_ptrs(&self, idx: usize) -> Option<&[Ptr<F>; 6]> {
+        self.tuple6.get_index(idx)
+    }
+
+    #[inline]
+    pub fn fetch_8_ptrs(&self, idx: usize) -> Option<&[Ptr<F>; 8]> {
+        self.tuple8.get_index(idx)
+    }
+
+    /// Interns a string as the chain of its characters, from the last one to
+    /// the first. Its suffixes are cached as well, as offsets in its text, so
+    /// that they take memory linear in the string's length.
     pub fn intern_string(&mut self, s: &str) -> Ptr<F> {
-        if s.is_empty() {
-            let ptr = Ptr::null(Tag::Expr(Str));
-            self.ptr_str_cache.insert(ptr, "".into());
-            return ptr;
+        match self.str_cache.get(s) {
+            Some(ptr) => *ptr,
+            None => self.intern_text(s.into()),
         }
+    }
 
-        match self.str_cache.get(s) {
-            Some(ptr_cache) => *ptr_cache,
-            None => {
-                let tail = &s.chars().skip(1).collect::<String>();
-                let tail_ptr = self.intern_string(tail);
-                let head = s.chars().next().unwrap();
-                let s_ptr = self.intern_2_ptrs(Tag::Expr(Str), Ptr::char(head), tail_ptr);
-                self.str_cache.insert(s.into(), s_ptr);
-                self.ptr_str_cache.insert(s_ptr, s.into());
-                s_ptr
+    fn intern_text(&mut self, text: Arc<str>) -> Ptr<F> {
+        let tag = Tag::Expr(Str);
+        let mut ptr = Ptr::null(tag);
+        let mut suffix = StrSuffix {
+            text: text.clone(),
+            start: text.len(),
+        };
+        self.ptr_str_cache
+            .entry(ptr)
+            .or_insert_with(|| suffix.clone());
+        for (start, c) in text.char_indices().rev() {
+            ptr = self.intern_2_ptrs(tag, Ptr::char(c), ptr);
+            suffix.start = start;
+            self.ptr_str_cache
+                .entry(ptr)
+                .or_insert_with(|| suffix.clone());
+        }
+        self.str_cache.insert(text, ptr);
+        ptr
+    }
+
+    /// Interns the UTF-8 text read from `reader` as a string, for inputs too
+    /// large to be read beforehand, like documents to commit to. The text is
+    /// decoded as it's read, but its chain can only be built once its last
+    /// character is known.
+    pub fn intern_string_reader(&mut self, mut reader: impl Read) -> Result<Ptr<F>> {
+        let mut text = String::new();
+        let mut buf = vec![0; 1 << 16];
+        // the bytes of a character split by the end of the last read
+        let mut split = 0;
+        loop {
+            let read = match reader.read(&mut buf[split..]) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let filled = split + read;
+            let valid = match std::str::from_utf8(&buf[..filled]) {
+                Ok(chunk) => chunk.len(),
+                Err(e) if e.error_len().is_some() => bail!("Invalid UTF-8: {e}"),
+                Err(e) => e.valid_up_to(),
+            };
+            text.push_str(std::str::from_utf8(&buf[..valid]).expect("valid prefix"));
+            buf.copy_within(valid..filled, 0);
+            split = filled - valid;
+        }
+        if split > 0 {
+            bail!("Invalid UTF-8: the text ends within a character")
+        }
+        Ok(match self.str_cache.get(text.as_str()) {
+            Some(ptr) => *ptr,
+            None => self.intern_text(text.into()),
+        })
+    }
+
+    /// Interns a byte vector as the chain of its bytes, as `U64`s tagged
+    /// `Bytes`, from the last one to the first, which `fetch_bytes` reads back
+    pub fn intern_bytes(&mut self, bytes: &[u8]) -> Ptr<F> {
+        let tag = Tag::Expr(Bytes);
+        bytes.iter().rev().fold(Ptr::null(tag), |tail, byte| {
+            let head = Ptr::Leaf(Tag::Expr(U64), F::from_u64((*byte).into()));
+            self.intern_2_ptrs(tag, head, tail)
+        })
+    }
+
+    /// Interns the bytes read from `reader` as `intern_bytes` does
+    pub fn intern_bytes_reader(&mut self, mut reader: impl Read) -> Result<Ptr<F>> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        Ok(self.intern_bytes(&bytes))
+    }
+
+    /// The bytes of a byte vector, walking the chain of its bytes
+    pub fn fetch_bytes(&self, ptr: &Ptr<F>) -> Option<Vec<u8>> {
+        let mut bytes = vec![];
+        let mut ptr = *ptr;
+        loop {
+            match ptr {
+                Ptr::Leaf(Tag::Expr(Bytes), _) => return Some(bytes),
+                Ptr::Tuple2(Tag::Expr(Bytes), idx) => {
+                    let (head, tail) = self.fetch_2_ptrs(to_usize(idx))?;
+                    let Ptr::Leaf(Tag::Expr(U64), byte) = head else {
+                        return None;
+                    };
+                    bytes.push(u8::try_from(byte.to_u64()?).ok()?);
+                    ptr = *tail;
+                }
+                _ => return None,
             }
         }
     }
 
     #[inline]
-    pub fn fetch_string(&self, ptr: &Ptr<F>) -> Option<&String> {
+    pub fn fetch_string(&self, ptr: &Ptr<F>) -> Option<&str> {
         match ptr.tag() {
-            Tag::Expr(Str) => self.ptr_str_cache.get(ptr),
+            Tag::Expr(Str) => self.ptr_str_cache.get(ptr).map(StrSuffix::as_str),
             _ => None,
         }
     }
@@ -228,34 +533,53 @@ impl<F: LurkField> Store<F> {
         }
     }
 
+    /// Interns `syn`, keeping the lists it's nested in on the heap so that
+    /// deeply nested syntax doesn't overflow the stack
     pub fn intern_syntax(&mut self, syn: Syntax<F>) -> Result<Ptr<F>> {
-        match syn {
-            Syntax::Num(_, x) => Ok(Ptr::Leaf(Tag::Expr(Num), x.into_scalar())),
-            Syntax::UInt(_, UInt::U64(x)) => Ok(Ptr::Leaf(Tag::Expr(U64), x.into())),
-            Syntax::Char(_, x) => Ok(Ptr::Leaf(Tag::Expr(Char), (x as u64).into())),
-            Syntax::Symbol(_, symbol) => Ok(self.intern_symbol(&symbol)),
-            Syntax::String(_, x) => Ok(self.intern_string(&x)),
-            Syntax::Quote(pos, x) => {
-                let xs = vec![Syntax::Symbol(pos, lurk_sym("quote").into()), *x];
-                self.intern_syntax(Syntax::List(pos, xs))
-            }
-            Syntax::List(_, xs) => {
-                let mut cdr = self.intern_symbol(&lurk_sym("nil"));
-                for x in xs.into_iter().rev() {
-                    let car = self.intern_syntax(x)?;
-                    cdr = self.intern_2_ptrs(Tag::Expr(Cons), car, cdr);
+        enum Task<F: LurkField> {
+            Intern(Syntax<F>),
+            /// Conses the last interned pointer onto the one before it
+            Cons,
+        }
+        let mut tasks = vec![Task::Intern(syn)];
+        let mut ptrs = vec![];
+        while let Some(task) = tasks.pop() {
+            let syn = match task {
+                Task::Intern(syn) => syn,
+                Task::Cons => {
+                    let car = ptrs.pop().expect("car was interned");
+                    let cdr = ptrs.pop().expect("cdr was interned");
+                    ptrs.push(self.intern_2_ptrs(Tag::Expr(Cons), car, cdr));
+                    continue;
                 }
-                Ok(cdr)
-            }
-            Syntax::Improper(_, xs, end) => {
-                let mut cdr = self.intern_syntax(*end)?;
-                for x in xs.into_iter().rev() {
-                    let car = self.intern_syntax(x)?;
-                    cdr = self.intern_2_ptrs(Tag::Expr(Cons), car, cdr);
+            };
+            // the elements of lists are interned from last to first
+            match syn {
+                Syntax::Num(_, x) => ptrs.push(Ptr::Leaf(Tag::Expr(Num), x.into_scalar())),
+                Syntax::UInt(_, UInt::U64(x)) => ptrs.push(Ptr::Leaf(Tag::Expr(U64), x.into())),
+                Syntax::Char(_, x) => ptrs.push(Ptr::Leaf(Tag::Expr(Char), (x as u64).into())),
+                Syntax::Symbol(_, symbol) => ptrs.push(self.intern_symbol(&symbol)),
+                Syntax::String(_, x) => ptrs.push(self.intern_string(&x)),
+                Syntax::Foreign(_, scheme, id) => ptrs.push(self.intern_foreign(&scheme, &id)?),
+                Syntax::Quote(pos, x) => {
+                    let xs = vec![Syntax::Symbol(pos, lurk_sym("quote").into()), *x];
+                    tasks.push(Task::Intern(Syntax::List(pos, xs)));
+                }
+                Syntax::List(_, xs) => {
+                    ptrs.push(self.intern_symbol(&lurk_sym("nil")));
+                    for x in xs {
+                        tasks.extend([Task::Cons, Task::Intern(x)]);
+                    }
+                }
+                Syntax::Improper(_, xs, end) => {
+                    for x in xs {
+                        tasks.extend([Task::Cons, Task::Intern(x)]);
+                    }
+                    tasks.push(Task::Intern(*end));
                 }
-                Ok(cdr)
             }
         }
+        Ok(ptrs.pop().expect("syntax was interned"))
     }
 
     pub fn read(&mut self, state: Rc<RefCell<State>>, input: &str) -> Result<Ptr<F>> {
@@ -289,7 +613,7 @@ impl<F: LurkField> Store<F> {
             Ptr::Tuple2(tag, idx) => match self.z_cache.get(ptr) {
                 Some(z_ptr) => Ok(*z_ptr),
                 None => {
-                    let Some((a, b)) = self.tuple2.get_index(*idx) else {
+                    let Some((a, b)) = self.tuple2.get_index(to_usize(*idx)) else {
                         bail!("Index {idx} not found on tuple2")
                     };
                     let a = self.hash_ptr(a)?;
@@ -305,13 +629,14 @@ impl<F: LurkField> Store<F> {
                     };
                     self.z_dag.insert(z_ptr, ZChildren::Tuple2(a, b));
                     self.z_cache.insert(*ptr, z_ptr);
+                    self.z_index.insert(z_ptr, *ptr);
                     Ok(z_ptr)
                 }
             },
             Ptr::Tuple3(tag, idx) => match self.z_cache.get(ptr) {
                 Some(z_ptr) => Ok(*z_ptr),
                 None => {
-                    let Some((a, b, c)) = self.tuple3.get_index(*idx) else {
+                    let Some((a, b, c)) = self.tuple3.get_index(to_usize(*idx)) else {
                         bail!("Index {idx} not found on tuple3")
                     };
                     let a = self.hash_ptr(a)?;
@@ -330,13 +655,14 @@ impl<F: LurkField> Store<F> {
                     };
                     self.z_dag.insert(z_ptr, ZChildren::Tuple3(a, b, c));
                     self.z_cache.insert(*ptr, z_ptr);
+                    self.z_index.insert(z_ptr, *ptr);
                     Ok(z_ptr)
                 }
             },
             Ptr::Tuple4(tag, idx) => match self.z_cache.get(ptr) {
                 Some(z_ptr) => Ok(*z_ptr),
                 None => {
-                    let Some((a, b, c, d)) = self.tuple4.get_index(*idx) else {
+                    let Some((a, b, c, d)) = self.tuple4.get_index(to_usize(*idx)) else {
                         bail!("Index {idx} not found on tuple4")
                     };
                     let a = self.hash_ptr(a)?;
@@ -358,9 +684,153 @@ impl<F: LurkField> Store<F> {
                     };
                     self.z_dag.insert(z_ptr, ZChildren::Tuple4(a, b, c, d));
                     self.z_cache.insert(*ptr, z_ptr);
+                    self.z_index.insert(z_ptr, *ptr);
                     Ok(z_ptr)
                 }
             },
+            Ptr::Tuple6(tag, idx) => match self.z_cache.get(ptr) {
+                Some(z_ptr) => Ok(*z_ptr),
+                None => {
+                    let Some(ptrs) = self.tuple6.get_index(to_usize(*idx)) else {
+                        bail!("Index {idx} not found on tuple6")
+                    };
+                    let children = self.hash_children(ptrs)?;
+                    let z_ptr = ZPtr {
+                        tag: *tag,
+                        hash: self.poseidon_cache.hash12(&preimage(&children)),
+                    };
+                    self.z_dag.insert(z_ptr, ZChildren::Tuple6(children));
+                    self.z_cache.insert(*ptr, z_ptr);
+                    self.z_index.insert(z_ptr, *ptr);
+                    Ok(z_ptr)
+                }
+            },
+            Ptr::Tuple8(tag, idx) => match self.z_cache.get(ptr) {
+                Some(z_ptr) => Ok(*z_ptr),
+                None => {
+                    let Some(ptrs) = self.tuple8.get_index(to_usize(*idx)) else {
+                        bail!("Index {idx} not found on tuple8")
+                    };
+                    let children = self.hash_children(ptrs)?;
+                    let z_ptr = ZPtr {
+                        tag: *tag,
+                        hash: self.poseidon_cache.hash16(&preimage(&children)),
+                    };
+                    self.z_dag.insert(z_ptr, ZChildren::Tuple8(children));
+                    self.z_cache.insert(*ptr, z_ptr);
+                    self.z_index.insert(z_ptr, *ptr);
+                    Ok(z_ptr)
+                }
+            },
+        }
+    }
+
+    fn hash_children<const N: usize>(&self, ptrs: &[Ptr<F>; N]) -> Result<[ZPtr<F>; N]> {
+        let mut children = [ZPtr::dummy(); N];
+        for (child, ptr) in children.iter_mut().zip(ptrs) {
+            *child = self.hash_ptr(ptr)?;
+        }
+        Ok(children)
+    }
+
+    /// The pointer whose hash is `z_ptr`, if it has been hashed, found without
+    /// hashing anything. Leaves are their own hashes, so they aren't indexed.
+    pub fn fetch_z_ptr(&self, z_ptr: &ZPtr<F>) -> Option<Ptr<F>> {
+        self.z_index.get(z_ptr).map(|ptr| *ptr)
+    }
+
+    /// The `ZPtr`s of `ptrs`. The pointers reachable from them that haven't
+    /// been hashed yet are hashed by height, from the leaves up: pointers of
+    /// the same height don't depend on each other, so each height is hashed in
+    /// parallel, and `hash_ptr` only finds cached children.
+    pub fn hash_ptrs(&self, ptrs: &[Ptr<F>]) -> Result<Vec<ZPtr<F>>> {
+        let mut heights: HashMap<Ptr<F>, usize> = HashMap::default();
+        let mut levels: Vec<Vec<Ptr<F>>> = vec![];
+        // a depth-first traversal that places each pointer after its children
+        let mut stack: Vec<_> = ptrs.iter().map(|ptr| (*ptr, false)).collect();
+        while let Some((ptr, visited)) = stack.pop() {
+            if ptr.index().is_none()
+                || heights.contains_key(&ptr)
+                || self.z_cache.contains_key(&ptr)
+            {
+                continue;
+            }
+            let Some(children) = self.fetch_ptrs(&ptr) else {
+                bail!("Pointer {ptr:?} not found in the store")
+            };
+            if visited {
+                let height = children
+                    .iter()
+                    .filter_map(|child| heights.get(child))
+                    .max()
+                    .map_or(0, |height| height + 1);
+                heights.insert(ptr, height);
+                if levels.len() <= height {
+                    levels.resize(height + 1, vec![]);
+                }
+                levels[height].push(ptr);
+            } else {
+                stack.push((ptr, true));
+                stack.extend(children.into_iter().map(|child| (child, false)));
+            }
+        }
+        for level in &levels {
+            level
+                .par_iter()
+                .try_for_each(|ptr| self.hash_ptr(ptr).map(|_| ()))?;
+        }
+        ptrs.iter().map(|ptr| self.hash_ptr(ptr)).collect()
+    }
+
+    /// The `ZPtr` of the string `s`, as `intern_string` followed by `hash_ptr`
+    /// gives, computed without interning it
+    pub fn hash_string(&self, s: &str) -> ZPtr<F> {
+        let tag = Tag::Expr(Str);
+        s.chars()
+            .rev()
+            .fold(ZPtr { tag, hash: F::ZERO }, |tail, head| ZPtr {
+                tag,
+                hash: self.poseidon_cache.hash4(&[
+                    Tag::Expr(Char).to_field(),
+                    F::from_char(head),
+                    tag.to_field(),
+                    tail.hash,
+                ]),
+            })
+    }
+
+    /// The `ZPtr` of the symbol `sym`, as `intern_symbol` followed by
+    /// `hash_ptr` gives, computed without interning it
+    pub fn hash_symbol(&self, sym: &Symbol) -> ZPtr<F> {
+        let path_tag = Tag::Expr(Sym);
+        let path = sym.path().iter().rev().fold(
+            ZPtr {
+                tag: path_tag,
+                hash: F::ZERO,
+            },
+            |tail, head| {
+                let head = self.hash_string(head);
+                ZPtr {
+                    tag: path_tag,
+                    hash: self.poseidon_cache.hash4(&[
+                        head.tag.to_field(),
+                        head.hash,
+                        path_tag.to_field(),
+                        tail.hash,
+                    ]),
+                }
+            },
+        );
+        let tag = if sym == &lurk_sym("nil") {
+            Tag::Expr(Nil)
+        } else if sym.is_keyword() {
+            Tag::Expr(Key)
+        } else {
+            path_tag
+        };
+        ZPtr {
+            tag,
+            hash: path.hash,
         }
     }
 
@@ -372,6 +842,237 @@ impl<F: LurkField> Store<F> {
         });
         self.dehydrated = Vec::new();
     }
+
+    /// What the store holds, to monitor the memory used by long-lived stores
+    pub fn stats(&self) -> StoreStats {
+        let mut tags = HashMap::default();
+        let hashed = self.z_cache.iter().map(|entry| *entry.key());
+        let dehydrated = self
+            .dehydrated
+            .iter()
+            .filter(|ptr| !self.z_cache.contains_key(*ptr));
+        for ptr in hashed.chain(dehydrated.copied()) {
+            *tags.entry(*ptr.tag()).or_insert(0) += 1;
+        }
+        let tuples = [
+            self.tuple2.len(),
+            self.tuple3.len(),
+            self.tuple4.len(),
+            self.tuple6.len(),
+            self.tuple8.len(),
+        ];
+        let poseidon = self.poseidon_cache.stats();
+
+        // the indices of `IndexSet`s and the hashes they keep for each entry
+        let index = 2 * size_of::<usize>();
+        let ptr = size_of::<Ptr<F>>();
+        let tuples_memory: usize = tuples
+            .iter()
+            .zip([2, 3, 4, 6, 8])
+            .map(|(len, arity)| len * (arity * ptr + index))
+            .sum();
+        // strings are held once, and shared by the pointers of their suffixes,
+        // whereas both caches of symbols hold each of them and its pointer
+        let chars: usize = self.str_cache.keys().map(|s| s.len()).sum();
+        let strings_memory = chars
+            + self.str_cache.len() * (size_of::<Arc<str>>() + ptr)
+            + self.ptr_str_cache.len() * (ptr + size_of::<StrSuffix>());
+        let limbs = self.sym_cache.keys().flatten();
+        let limbs_memory: usize = limbs.map(|limb| limb.len() + size_of::<String>()).sum();
+        let paths_memory = self.sym_cache.len() * (size_of::<Vec<String>>() + ptr);
+        let symbols_memory = 2 * (limbs_memory + paths_memory);
+        let hashes_memory =
+            (self.z_cache.len() + self.z_index.len()) * (ptr + size_of::<ZPtr<F>>());
+        let memory = tuples_memory
+            + strings_memory
+            + symbols_memory
+            + hashes_memory
+            + self.leaves.len() * (size_of::<F>() + index)
+            + self.dehydrated.len() * ptr
+            + self.comms.len() * (2 * size_of::<F>() + ptr)
+            + poseidon.memory;
+
+        StoreStats {
+            tuples,
+            tags,
+            strings: self.str_cache.len(),
+            symbols: self.sym_cache.len(),
+            hashes: self.z_cache.len(),
+            comms: self.comms.len(),
+            poseidon,
+            memory,
+        }
+    }
+
+    /// Writes the data interned in the store and the hashes cached for it to
+    /// `path`, to be read back with `load` after a restart. The file is replaced
+    /// only once it's completely written. Coprocessors, foreign resolvers and
+    /// continuation tags aren't saved.
+    pub fn save(&self, path: &Path) -> Result<()>
+    where
+        F: Serialize,
+    {
+        let snapshot = Snapshot {
+            tuple2: self.tuple2.iter().cloned().collect(),
+            tuple3: self.tuple3.iter().cloned().collect(),
+            tuple4: self.tuple4.iter().cloned().collect(),
+            tuple6: self.tuple6.iter().cloned().collect(),
+            tuple8: self.tuple8.iter().cloned().collect(),
+            leaves: self.leaves.iter().map(|f| f.0).collect(),
+            // suffixes are cached again as their strings are interned
+            strings: self
+                .str_cache
+                .iter()
+                .map(|(s, p)| (*p, s.to_string()))
+                .collect(),
+            symbols: self
+                .ptr_sym_cache
+                .iter()
+                .map(|(p, s)| (*p, s.clone()))
+                .collect(),
+            dehydrated: self.dehydrated.clone(),
+            hashes: self
+                .z_cache
+                .iter()
+                .map(|entry| (*entry.key(), entry.tag, entry.hash))
+                .collect(),
+            comms: self
+                .comms
+                .iter()
+                .map(|(hash, (secret, payload))| (hash.0, *secret, *payload))
+                .collect(),
+        };
+        let tmp = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        writer.write_all(&STORE_MAGIC)?;
+        writer.write_all(&[STORE_FORMAT_VERSION])?;
+        bincode::serialize_into(&mut writer, &snapshot)?;
+        writer
+            .into_inner()
+            .map_err(|e| anyhow!("Couldn't write store: {e}"))?
+            .sync_all()?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// Loads a store written by `save`. The whole file is read and
+    /// deserialized up front, which takes time in proportion to its size, but
+    /// the hashes it caches are taken as they are, so pointers that were hashed
+    /// before saving aren't hashed again. The lengths the file claims are
+    /// bounded by its size, so a corrupt file fails to load rather than
+    /// exhausting memory.
+    pub fn load(path: &Path) -> Result<Self>
+    where
+        F: DeserializeOwned,
+    {
+        use bincode::Options;
+
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+        let mut header = [0; STORE_MAGIC.len() + 1];
+        if reader.read_exact(&mut header).is_err() || !header.starts_with(&STORE_MAGIC) {
+            bail!("{} isn't a saved LEM store", path.display())
+        }
+        let version = header[STORE_MAGIC.len()];
+        if version != STORE_FORMAT_VERSION {
+            bail!(
+                "Unsupported LEM store format version {version}, the latest known is {}",
+                STORE_FORMAT_VERSION
+            )
+        }
+        // the options of `bincode::serialize_into`, which `save` uses
+        let snapshot: Snapshot<F> = bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .allow_trailing_bytes()
+            .with_limit(len.saturating_sub(header.len() as u64))
+            .deserialize_from(reader)?;
+        let z_cache: DashMap<_, _> = snapshot
+            .hashes
+            .into_iter()
+            .map(|(ptr, tag, hash)| (ptr, ZPtr { tag, hash }))
+            .collect();
+        let mut store = Self {
+            tuple2: snapshot.tuple2.into_iter().collect(),
+            tuple3: snapshot.tuple3.into_iter().collect(),
+            tuple4: snapshot.tuple4.into_iter().collect(),
+            tuple6: snapshot.tuple6.into_iter().collect(),
+            tuple8: snapshot.tuple8.into_iter().collect(),
+            leaves: snapshot.leaves.into_iter().map(FWrap).collect(),
+            dehydrated: snapshot.dehydrated,
+            z_index: z_cache
+                .iter()
+                .map(|entry| (*entry.value(), *entry.key()))
+                .collect(),
+            z_cache,
+            comms: snapshot
+                .comms
+                .into_iter()
+                .map(|(hash, secret, payload)| (FWrap(hash), (secret, payload)))
+                .collect(),
+            ..Default::default()
+        };
+        for (ptr, s) in snapshot.strings {
+            if store.intern_string(&s) != ptr {
+                bail!("String {s:?} doesn't match its pointer");
+            }
+        }
+        for (ptr, sym_path) in snapshot.symbols {
+            store.sym_cache.insert(sym_path.clone(), ptr);
+            store.ptr_sym_cache.insert(ptr, sym_path);
+        }
+        Ok(store)
+    }
+}
+
+/// A suffix of an interned string, which shares the string's text
+#[derive(Clone, Debug)]
+struct StrSuffix {
+    text: Arc<str>,
+    start: usize,
+}
+
+impl StrSuffix {
+    #[inline]
+    fn as_str(&self) -> &str {
+        &self.text[self.start..]
+    }
+}
+
+/// The bytes saved stores start with, followed by the version byte
+const STORE_MAGIC: [u8; 4] = *b"LEMS";
+
+/// The version of the format stores are saved with
+const STORE_FORMAT_VERSION: u8 = 2;
+
+/// The contents of a saved store. Tuples are kept in the order they were
+/// interned in, so pointers keep their indices.
+#[derive(Serialize, Deserialize)]
+struct Snapshot<F: LurkField> {
+    tuple2: Vec<(Ptr<F>, Ptr<F>)>,
+    tuple3: Vec<(Ptr<F>, Ptr<F>, Ptr<F>)>,
+    tuple4: Vec<(Ptr<F>, Ptr<F>, Ptr<F>, Ptr<F>)>,
+    tuple6: Vec<[Ptr<F>; 6]>,
+    tuple8: Vec<[Ptr<F>; 8]>,
+    leaves: Vec<F>,
+    strings: Vec<(Ptr<F>, String)>,
+    symbols: Vec<(Ptr<F>, Vec<String>)>,
+    dehydrated: Vec<Ptr<F>>,
+    /// The tag and hash each hashed pointer was found to have
+    hashes: Vec<(Ptr<F>, Tag, F)>,
+    /// The hash, secret and payload of each commitment
+    comms: Vec<(F, F, Ptr<F>)>,
+}
+
+/// The tags and hashes of `children`, in order, as they're hashed together.
+/// `M` must be twice `N`.
+fn preimage<F: LurkField, const N: usize, const M: usize>(children: &[ZPtr<F>; N]) -> [F; M] {
+    let mut preimage = [F::ZERO; M];
+    for (i, child) in children.iter().enumerate() {
+        preimage[2 * i] = child.tag.to_field();
+        preimage[2 * i + 1] = child.hash;
+    }
+    preimage
 }
 
 impl<F: LurkField> Ptr<F> {
@@ -391,7 +1092,7 @@ impl<F: LurkField> Ptr<F> {
                 }
             }
             Ptr::Tuple2(tag, x) => {
-                let (p1, p2) = store.fetch_2_ptrs(x).unwrap();
+                let (p1, p2) = store.fetch_2_ptrs(to_usize(x)).unwrap();
                 format!(
                     "({} {} {})",
                     tag,
@@ -400,7 +1101,7 @@ impl<F: LurkField> Ptr<F> {
                 )
             }
             Ptr::Tuple3(tag, x) => {
-                let (p1, p2, p3) = store.fetch_3_ptrs(x).unwrap();
+                let (p1, p2, p3) = store.fetch_3_ptrs(to_usize(x)).unwrap();
                 format!(
                     "({} {} {} {})",
                     tag,
@@ -410,7 +1111,7 @@ impl<F: LurkField> Ptr<F> {
                 )
             }
             Ptr::Tuple4(tag, x) => {
-                let (p1, p2, p3, p4) = store.fetch_4_ptrs(x).unwrap();
+                let (p1, p2, p3, p4) = store.fetch_4_ptrs(to_usize(x)).unwrap();
                 format!(
                     "({} {} {} {} {})",
                     tag,
@@ -420,6 +1121,224 @@ impl<F: LurkField> Ptr<F> {
                     (*p4).dbg_display(store)
                 )
             }
+            Ptr::Tuple6(tag, x) => {
+                let ptrs = store.fetch_6_ptrs(to_usize(x)).unwrap();
+                let ptrs = ptrs.iter().map(|ptr| ptr.dbg_display(store));
+                format!("({} {})", tag, ptrs.collect::<Vec<_>>().join(" "))
+            }
+            Ptr::Tuple8(tag, x) => {
+                let ptrs = store.fetch_8_ptrs(to_usize(x)).unwrap();
+                let ptrs = ptrs.iter().map(|ptr| ptr.dbg_display(store));
+                format!("({} {})", tag, ptrs.collect::<Vec<_>>().join(" "))
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blstrs::Scalar as Fr;
+
+    #[test]
+    fn saved_stores_are_loaded_with_their_hashes() {
+        let store = &mut Store::<Fr>::default();
+        let state = State::init_lurk_state().rccell();
+        let expr = store
+            .read(state, "(let ((s \"hello\")) (cons s (commit 1)))")
+            .unwrap();
+        let secret = Fr::from(7);
+        let hash = store.hash_comm(secret, &expr).unwrap();
+        store.comms.insert(FWrap(hash), (secret, expr));
+        store.hydrate_z_cache();
+        let z_expr = store.hash_ptr(&expr).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.lems");
+        store.save(&path).unwrap();
+        let mut loaded = Store::<Fr>::load(&path).unwrap();
+        assert!(loaded.z_cache.contains_key(&expr));
+        assert_eq!(loaded.hash_ptr(&expr).unwrap(), z_expr);
+        assert_eq!(loaded.fetch_z_ptr(&z_expr), Some(expr));
+        assert_eq!(loaded.fetch_ptrs(&expr), store.fetch_ptrs(&expr));
+        // interned strings are found rather than interned again
+        let tuples = loaded.tuple2.len();
+        let s = loaded.intern_string("hello");
+        assert_eq!(loaded.tuple2.len(), tuples);
+        assert_eq!(s, store.intern_string("hello"));
+        assert_eq!(loaded.fetch_string(&s).unwrap(), "hello");
+        assert_eq!(loaded.comms.get(&FWrap(hash)), Some(&(secret, expr)));
+
+        fs::write(&path, b"LEMS").unwrap();
+        assert!(Store::<Fr>::load(&path).is_err());
+        // a length beyond the size of the file isn't allocated
+        let mut huge = STORE_MAGIC.to_vec();
+        huge.push(STORE_FORMAT_VERSION);
+        huge.extend(u64::MAX.to_le_bytes());
+        fs::write(&path, huge).unwrap();
+        assert!(Store::<Fr>::load(&path).is_err());
+    }
+
+    #[test]
+    fn pointers_are_hashed_in_batches() {
+        let sources = [
+            "(a (b c) (b c) . d)",
+            "(b c)",
+            "\"string\"",
+            "42",
+            "(a (b c))",
+        ];
+        let state = State::init_lurk_state().rccell();
+        let batched = &mut Store::<Fr>::default();
+        let exprs = sources.map(|src| batched.read(state.clone(), src).unwrap());
+        let z_ptrs = batched.hash_ptrs(&exprs).unwrap();
+
+        // the same pointers, hashed one at a time in a store that read the same
+        let store = &mut Store::<Fr>::default();
+        for (src, (expr, z_ptr)) in sources.iter().zip(exprs.iter().zip(z_ptrs)) {
+            assert_eq!(store.read(state.clone(), src).unwrap(), *expr);
+            assert_eq!(store.hash_ptr(expr).unwrap(), z_ptr);
+        }
+        assert!(batched.hash_ptrs(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn hashed_pointers_are_found_by_their_hashes() {
+        let store = &mut Store::<Fr>::default();
+        let state = State::init_lurk_state().rccell();
+        let expr = store.read(state, "(a (b . c) \"d\")").unwrap();
+        let z_expr = store.hash_ptr(&expr).unwrap();
+        assert_eq!(store.fetch_z_ptr(&z_expr), Some(expr));
+        let children = store.fetch_ptrs(&expr).unwrap();
+        for child in children.iter().filter(|child| child.index().is_some()) {
+            let z_child = store.hash_ptr(child).unwrap();
+            assert_eq!(store.fetch_z_ptr(&z_child), Some(*child));
+        }
+        assert_eq!(store.fetch_z_ptr(&ZPtr::dummy()), None);
+    }
+
+    #[test]
+    fn pointers_are_compacted_and_expanded() {
+        assert!(size_of::<CompactPtr>() < size_of::<Ptr<Fr>>());
+        let store = &mut Store::<Fr>::default();
+        let state = State::init_lurk_state().rccell();
+        let expr = store.read(state, "(1 #\\a \"b\" (c . 1))").unwrap();
+        let mut ptrs = vec![expr];
+        ptrs.extend(store.fetch_ptrs(&expr).unwrap());
+        ptrs.push(Ptr::num(Fr::from(1)));
+        ptrs.push(Ptr::num(Fr::from(u64::MAX)));
+        let compact: Vec<_> = ptrs.iter().map(|ptr| store.compact(ptr).unwrap()).collect();
+        for (ptr, compact) in ptrs.iter().zip(&compact) {
+            assert_eq!(compact.tag(), ptr.tag());
+            assert_eq!(compact.arity(), ptr.arity());
+            assert_eq!(&store.expand(compact).unwrap(), ptr);
+        }
+        // equal leaves are equal, and only the big one takes a table entry
+        assert_eq!(compact[1], compact[3]);
+        assert_eq!(store.leaves.len(), 1);
+        assert_eq!(store.compact(&ptrs[4]).unwrap(), compact[4]);
+        assert_eq!(store.leaves.len(), 1);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("compact.lems");
+        store.save(&path).unwrap();
+        let loaded = Store::<Fr>::load(&path).unwrap();
+        assert_eq!(loaded.expand(&compact[0]).unwrap(), expr);
+        assert_eq!(loaded.expand(&compact[4]).unwrap(), ptrs[4]);
+        // small leaves need no store, but big ones need theirs
+        let empty = Store::<Fr>::default();
+        assert_eq!(empty.expand(&compact[3]).unwrap(), ptrs[3]);
+        assert!(empty.expand(&compact[4]).is_err());
+    }
+
+    #[test]
+    fn stores_report_what_they_hold() {
+        let store = &mut Store::<Fr>::default();
+        let state = State::init_lurk_state().rccell();
+        store.read(state, "(a \"b\" a)").unwrap();
+        let stats = store.stats();
+        assert_eq!(stats.tags[&Tag::Expr(Cons)], 3);
+        assert_eq!(stats.hashes, 0);
+        assert!(stats.strings > 0 && stats.symbols > 0 && stats.memory > 0);
+
+        // hashing doesn't change what's interned
+        store.hydrate_z_cache();
+        let hydrated = store.stats();
+        assert_eq!(hydrated.tags[&Tag::Expr(Cons)], 3);
+        assert_eq!(hydrated.tuples, stats.tuples);
+        assert!(hydrated.hashes > 0 && hydrated.poseidon.misses > 0);
+        assert!(hydrated.memory > stats.memory);
+
+        let preimage = [Fr::from(1), Fr::from(2), Fr::from(3)];
+        store.poseidon_cache.hash3(&preimage);
+        store.poseidon_cache.hash3(&preimage);
+        let poseidon = store.poseidon_cache.stats();
+        assert_eq!(poseidon.lookups, hydrated.poseidon.lookups + 2);
+        assert_eq!(poseidon.misses, hydrated.poseidon.misses + 1);
+        assert!(poseidon.hit_rate().is_some());
+        assert_eq!(PoseidonCacheStats::default().hit_rate(), None);
+        let racy = PoseidonCacheStats {
+            lookups: 1,
+            misses: 2,
+            ..Default::default()
+        };
+        assert_eq!(racy.hit_rate(), Some(0.0));
+
+        // the lookups made from rayon's threads are all counted
+        let cache = &store.poseidon_cache;
+        (0..100u64).into_par_iter().for_each(|i| {
+            cache.hash3(&[Fr::from(i % 10), Fr::from(4), Fr::from(5)]);
+        });
+        let parallel = cache.stats();
+        assert_eq!(parallel.lookups, poseidon.lookups + 100);
+        assert_eq!(parallel.misses, poseidon.misses + 10);
+    }
+
+    #[test]
+    fn long_strings_and_bytes_are_interned() {
+        let store = &mut Store::<Fr>::default();
+        // longer than the reader's buffer, whose end splits a `€`
+        let text = "abc€".repeat(1 << 14);
+        let ptr = store.intern_string_reader(text.as_bytes()).unwrap();
+        assert_eq!(ptr, store.intern_string(&text));
+        assert_eq!(store.fetch_string(&ptr).unwrap(), text);
+        assert_eq!(store.stats().strings, 1);
+        assert_eq!(store.hash_ptrs(&[ptr]).unwrap(), [store.hash_string(&text)]);
+        // a suffix, as `cdr` would give
+        let (_, tail) = store.fetch_2_ptrs(ptr.get_index2().unwrap()).unwrap();
+        assert_eq!(store.fetch_string(tail).unwrap(), &text[1..]);
+        assert!(store.intern_string_reader(&[0xff, 0xfe][..]).is_err());
+        assert!(store.intern_string_reader(&"€".as_bytes()[..2]).is_err());
+
+        let bytes: Vec<u8> = (0..=255).cycle().take(1 << 16).collect();
+        let ptr = store.intern_bytes_reader(&bytes[..]).unwrap();
+        assert_eq!(ptr, store.intern_bytes(&bytes));
+        assert_eq!(ptr.tag(), &Tag::Expr(Bytes));
+        assert_eq!(store.fetch_bytes(&ptr).unwrap(), bytes);
+        let (_, tail) = store.fetch_2_ptrs(ptr.get_index2().unwrap()).unwrap();
+        assert_eq!(store.fetch_bytes(tail).unwrap(), bytes[1..]);
+        let empty = store.intern_bytes(&[]);
+        assert_eq!(store.fetch_bytes(&empty).unwrap(), []);
+        // byte vectors and strings don't mix
+        let abc = store.intern_bytes(b"abc");
+        assert_eq!(store.fetch_string(&abc), None);
+        let abc = store.intern_string("abc");
+        assert_eq!(store.fetch_bytes(&abc), None);
+    }
+
+    #[test]
+    fn user_continuations_are_interned_and_hashed() {
+        let store = &mut Store::<Fr>::default();
+        let lazy = store.register_cont_tag("lazy").unwrap();
+        assert_eq!(store.register_cont_tag("lazy").unwrap(), lazy);
+        assert_ne!(store.register_cont_tag("metered").unwrap(), lazy);
+        assert!(store.cont_tag("debug").is_err());
+
+        let outermost = Ptr::null(Tag::Cont(crate::tag::ContTag::Outermost));
+        let k = store.intern_2_ptrs(lazy, Ptr::num(Fr::from(1)), outermost);
+        let z_k = store.hash_ptr(&k).unwrap();
+        assert_eq!(z_k.tag, lazy);
+        assert_eq!(z_k.tag.to_field::<Fr>(), Fr::from(0b0001_1000_0000_0000));
+        assert_eq!(store.fork().cont_tag("lazy").unwrap(), lazy);
+    }
+}
@@ -0,0 +1,266 @@
+//! Differential testing of LEM functions against their circuits.
+//!
+//! Whatever the interpreter computes, the circuit of a function must accept:
+//! every frame the interpreter produces has to satisfy the constraints
+//! synthesized for it, with as many constraints as `Func::num_constraints`
+//! says and the same shape as the blank circuit public parameters are made
+//! from. `assert_circuit_agrees` checks that on the frames of any number of
+//! inputs, which is how authors of custom step functions and coprocessors
+//! should test them.
+//!
+//! `arb_func` generates random functions, straight-line arithmetic and hashing
+//! split by `if`s on equalities, over numeric inputs generated by `arb_nums`,
+//! to check the interpreter and the circuit agree on what LEM itself does.
+
+use bellpepper::util_cs::Comparable;
+use bellpepper_core::{test_cs::TestConstraintSystem, Delta};
+#[cfg(not(target_arch = "wasm32"))]
+use proptest::prelude::*;
+
+use crate::field::LurkField;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::tag::ExprTag::{Cons, Fun};
+
+use super::{interpreter::Preimages, pointers::Ptr, store::Store, Func};
+#[cfg(not(target_arch = "wasm32"))]
+use super::{Block, Ctrl, Lit, Op, Tag, Var};
+
+/// Interprets `func` on each of `inputs` and asserts that the circuit of every
+/// resulting frame is satisfied, has the number of constraints computed by
+/// `Func::num_constraints` and the shape of the blank circuit. Inputs the
+/// interpreter rejects have no frame, so they're skipped. Returns the number
+/// of frames checked.
+///
+/// Each input gives a single frame: to check a run of a step function, pass
+/// the outputs of its frames as the next inputs.
+pub fn assert_circuit_agrees<F: LurkField>(
+    func: &Func,
+    inputs: &[Vec<Ptr<F>>],
+    store: &mut Store<F>,
+) -> usize {
+    let num_constraints = func.num_constraints(store);
+    let mut cs_blank = TestConstraintSystem::<F>::new();
+    func.synthesize_blank(&mut cs_blank, store)
+        .expect("blank synthesis failed");
+    assert_eq!(
+        cs_blank.num_constraints(),
+        num_constraints,
+        "`{}` has a blank circuit of another size than computed",
+        func.name
+    );
+
+    let mut checked = 0;
+    for input in inputs {
+        let preimages = Preimages::new_from_func(func);
+        let Ok((frame, path)) = func.call(input.clone(), store, preimages) else {
+            continue;
+        };
+        let mut cs = TestConstraintSystem::<F>::new();
+        if let Err(e) = func.synthesize(&mut cs, store, &frame) {
+            panic!("`{}` failed to synthesize on path {path}: {e}", func.name)
+        }
+        if !cs.is_satisfied() {
+            let input = input
+                .iter()
+                .map(|ptr| ptr.dbg_display(store))
+                .collect::<Vec<_>>();
+            panic!(
+                "`{}` isn't satisfied on input [{}], path {path}: {}",
+                func.name,
+                input.join(", "),
+                cs.which_is_unsatisfied().unwrap_or_default()
+            )
+        }
+        assert_eq!(
+            cs.num_constraints(),
+            num_constraints,
+            "`{}` has another number of constraints on path {path}",
+            func.name
+        );
+        assert_eq!(
+            cs.delta(&cs_blank, true),
+            Delta::Equal,
+            "`{}` has another shape on path {path} than blank",
+            func.name
+        );
+        checked += 1;
+    }
+    checked
+}
+
+/// One step of a function generated by `arb_func`. The operands are indices
+/// into the variables bound so far, taken modulo their number.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Debug)]
+struct Recipe {
+    ops: Vec<(u8, [usize; 2])>,
+    branch: Option<([usize; 2], Box<Recipe>, Box<Recipe>)>,
+    rets: Vec<usize>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn arb_recipe(max_ops: usize, output_size: usize, depth: u32) -> BoxedStrategy<Recipe> {
+    let ops = || prop::collection::vec((0..9u8, any::<[usize; 2]>()), 0..=max_ops);
+    let rets = || prop::collection::vec(any::<usize>(), output_size);
+    let leaf = (ops(), rets()).prop_map(|(ops, rets)| Recipe {
+        ops,
+        branch: None,
+        rets,
+    });
+    if depth == 0 {
+        return leaf.boxed();
+    }
+    let inner = arb_recipe(max_ops, output_size, depth - 1);
+    let branch =
+        (ops(), any::<[usize; 2]>(), inner.clone(), inner).prop_map(|(ops, operands, eq, neq)| {
+            Recipe {
+                ops,
+                branch: Some((operands, Box::new(eq), Box::new(neq))),
+                rets: vec![],
+            }
+        });
+    prop_oneof![leaf, branch].boxed()
+}
+
+/// The variables in scope, along with whether they're bound to numbers
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone)]
+struct Scope {
+    vars: Vec<(Var, bool)>,
+    fresh: usize,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Scope {
+    fn bind(&mut self, num: bool) -> Var {
+        // the `_` keeps the variables that end up unused from being rejected
+        let var = Var(format!("_v{}", self.fresh).into());
+        self.fresh += 1;
+        self.vars.push((var.clone(), num));
+        var
+    }
+
+    fn any(&self, i: usize) -> Var {
+        self.vars[i % self.vars.len()].0.clone()
+    }
+
+    fn num(&self, i: usize) -> Var {
+        let nums = self.vars.iter().filter(|(_, num)| *num).collect::<Vec<_>>();
+        nums[i % nums.len()].0.clone()
+    }
+
+    fn build(&mut self, recipe: &Recipe) -> Block {
+        let mut ops = vec![];
+        for (kind, [a, b]) in &recipe.ops {
+            let op = match kind {
+                0 => Op::Add(self.bind(true), self.num(*a), self.num(*b)),
+                1 => Op::Sub(self.bind(true), self.num(*a), self.num(*b)),
+                2 => Op::Mul(self.bind(true), self.num(*a), self.num(*b)),
+                3 => Op::Lt(self.bind(true), self.num(*a), self.num(*b)),
+                4 => Op::Lit(self.bind(true), Lit::Num((*a % 4) as u128)),
+                5 => Op::EqVal(self.bind(true), self.any(*a), self.any(*b)),
+                6 => Op::EqTag(self.bind(true), self.any(*a), self.any(*b)),
+                7 => {
+                    let preimg = vec![self.any(*a), self.any(*b)];
+                    Op::Hash(self.bind(false), Tag::Expr(Cons), preimg)
+                }
+                _ => {
+                    let preimg = vec![self.any(*a), self.any(*b), self.any(a ^ b)];
+                    Op::Hash(self.bind(false), Tag::Expr(Fun), preimg)
+                }
+            };
+            ops.push(op);
+        }
+        let ctrl = match &recipe.branch {
+            None => Ctrl::Return(recipe.rets.iter().map(|i| self.any(*i)).collect()),
+            Some(([a, b], eq, neq)) => {
+                let (x, y) = (self.any(*a), self.any(*b));
+                // both branches see the variables bound so far, and bind
+                // variables of their own
+                let mut eq_scope = self.clone();
+                let eq = eq_scope.build(eq);
+                self.fresh = eq_scope.fresh;
+                let neq = self.clone().build(neq);
+                Ctrl::IfEq(x, y, Box::new(eq), Box::new(neq))
+            }
+        };
+        Block { ops, ctrl }
+    }
+}
+
+/// Random valid functions of `num_inputs` numeric inputs, which must be at
+/// least one, and `output_size` outputs, with up to `max_ops` operations per
+/// block and `if`s nested up to `depth` times. They never fail on numeric
+/// inputs.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn arb_func(
+    num_inputs: usize,
+    output_size: usize,
+    max_ops: usize,
+    depth: u32,
+) -> BoxedStrategy<Func> {
+    assert!(num_inputs > 0, "random functions need a numeric input");
+    arb_recipe(max_ops, output_size, depth)
+        .prop_map(move |recipe| {
+            let mut scope = Scope {
+                vars: vec![],
+                fresh: 0,
+            };
+            let input_params = (0..num_inputs).map(|_| scope.bind(true)).collect();
+            let body = scope.build(&recipe);
+            Func::new("arb".into(), input_params, output_size, body)
+                .expect("random functions are valid")
+        })
+        .boxed()
+}
+
+/// Random inputs of `n` numbers for the functions of `arb_func`, small ones
+/// often enough for their `if`s to take either branch
+#[cfg(not(target_arch = "wasm32"))]
+pub fn arb_nums<F: LurkField>(n: usize) -> BoxedStrategy<Vec<Ptr<F>>> {
+    let num = prop_oneof![0..4u64, any::<u64>()].prop_map(|n| Ptr::num(F::from_u64(n)));
+    prop::collection::vec(num, n).boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use blstrs::Scalar as Fr;
+
+    use super::*;
+    use crate::func;
+    use crate::tag::ExprTag::Nil;
+
+    #[test]
+    fn frames_are_checked() {
+        let func = func!(double(n): 1 => {
+            match n.tag {
+                Expr::Num => {
+                    let m = add(n, n);
+                    let _pair: Expr::Cons = hash2(n, m);
+                    return (m)
+                }
+            }
+        });
+        let store = &mut Store::<Fr>::default();
+        let nil = Ptr::null(Tag::Expr(Nil));
+        let inputs = [
+            vec![Ptr::num(Fr::from(3))],
+            vec![nil],
+            vec![Ptr::num(Fr::from(0))],
+        ];
+        // `nil` isn't a number, so the interpreter rejects it
+        assert_eq!(assert_circuit_agrees(&func, &inputs, store), 2);
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+        #[test]
+        fn random_funcs_agree_with_their_circuits(
+            func in arb_func(2, 2, 6, 2),
+            inputs in prop::collection::vec(arb_nums::<Fr>(2), 1..4),
+        ) {
+            let store = &mut Store::default();
+            prop_assert_eq!(assert_circuit_agrees(&func, &inputs, store), inputs.len());
+        }
+    }
+}
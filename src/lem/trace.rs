@@ -0,0 +1,218 @@
+//! Traces of LEM runs, for debugging.
+//!
+//! When the circuit of a function disagrees with the interpreter, the first
+//! thing to know is what the interpreter did on the frame at fault: which
+//! branches it took, which slots it filled and what it emitted. `trace_until`
+//! runs a function over several frames, as `call_until` does, and records that
+//! for every frame in a `Trace`, which `Trace::render` prints. If a frame fails
+//! to interpret, the trace ends with its error instead.
+
+use std::fmt::Write;
+
+use crate::field::LurkField;
+
+use super::{
+    interpreter::{PreimageData, Preimages},
+    path::Path,
+    pointers::Ptr,
+    slot::SlotsCounter,
+    store::Store,
+    Func,
+};
+
+/// What the interpreter did on one frame
+#[derive(Clone, Debug)]
+pub struct FrameTrace<F: LurkField> {
+    pub input: Vec<Ptr<F>>,
+    pub output: Vec<Ptr<F>>,
+    /// The branches taken, in the order they were taken
+    pub path: Path,
+    /// The slots of each kind that were filled
+    pub slots: SlotsCounter,
+    /// The values emitted, in the order they were emitted
+    pub emitted: Vec<Ptr<F>>,
+}
+
+/// The frames of a run of a function, as recorded by `Func::trace_until`
+#[derive(Clone, Debug)]
+pub struct Trace<F: LurkField> {
+    /// The name of the function
+    pub func: String,
+    /// The slots of each kind the function has
+    pub slots: SlotsCounter,
+    pub frames: Vec<FrameTrace<F>>,
+    /// The error of the frame that failed to interpret, if one did
+    pub error: Option<String>,
+}
+
+fn filled<F: LurkField>(slots: &[Option<PreimageData<F>>]) -> usize {
+    slots.iter().filter(|slot| slot.is_some()).count()
+}
+
+fn slots_filled<F: LurkField>(preimages: &Preimages<F>) -> SlotsCounter {
+    SlotsCounter {
+        hash2: filled(&preimages.hash2),
+        hash3: filled(&preimages.hash3),
+        hash4: filled(&preimages.hash4),
+        hash6: filled(&preimages.hash6),
+        hash8: filled(&preimages.hash8),
+        commitment: filled(&preimages.commitment),
+        less_than: filled(&preimages.less_than),
+        bit_decomp: filled(&preimages.bit_decomp),
+        range: filled(&preimages.range),
+    }
+}
+
+/// The slot kinds along with their number of slots in `slots`
+fn slot_kinds(slots: &SlotsCounter) -> [(&'static str, usize); 9] {
+    [
+        ("hash2", slots.hash2),
+        ("hash3", slots.hash3),
+        ("hash4", slots.hash4),
+        ("hash6", slots.hash6),
+        ("hash8", slots.hash8),
+        ("commitment", slots.commitment),
+        ("less_than", slots.less_than),
+        ("bit_decomp", slots.bit_decomp),
+        ("range", slots.range),
+    ]
+}
+
+fn display_ptrs<F: LurkField>(ptrs: &[Ptr<F>], store: &Store<F>) -> String {
+    let ptrs = ptrs
+        .iter()
+        .map(|ptr| ptr.dbg_display(store))
+        .collect::<Vec<_>>();
+    format!("[{}]", ptrs.join(", "))
+}
+
+impl<F: LurkField> Trace<F> {
+    /// The trace as text, one block per frame, with the pointers displayed as
+    /// found in `store`
+    pub fn render(&self, store: &Store<F>) -> String {
+        let mut out = String::new();
+        let frames = self.frames.len();
+        writeln!(out, "trace of `{}`, {frames} frames", self.func).unwrap();
+        for (i, frame) in self.frames.iter().enumerate() {
+            writeln!(out, "frame {i}:").unwrap();
+            writeln!(out, "  input:   {}", display_ptrs(&frame.input, store)).unwrap();
+            writeln!(out, "  output:  {}", display_ptrs(&frame.output, store)).unwrap();
+            writeln!(out, "  path:    {}", frame.path).unwrap();
+            let slots = slot_kinds(&frame.slots)
+                .into_iter()
+                .zip(slot_kinds(&self.slots))
+                .filter(|(_, (_, total))| *total > 0)
+                .map(|((kind, used), (_, total))| format!("{kind} {used}/{total}"))
+                .collect::<Vec<_>>();
+            writeln!(out, "  slots:   {}", slots.join(", ")).unwrap();
+            if !frame.emitted.is_empty() {
+                writeln!(out, "  emitted: {}", display_ptrs(&frame.emitted, store)).unwrap();
+            }
+        }
+        if let Some(error) = &self.error {
+            writeln!(out, "frame {frames} failed: {error}").unwrap();
+        }
+        out
+    }
+}
+
+impl Func {
+    /// Calls the function as `call_until` does, for `limit` frames at most,
+    /// recording a trace of each frame. An error interpreting a frame ends the
+    /// trace, which records it.
+    pub fn trace_until<F: LurkField, Stop: Fn(&[Ptr<F>]) -> bool>(
+        &self,
+        mut args: Vec<Ptr<F>>,
+        store: &mut Store<F>,
+        stop_cond: Stop,
+        limit: usize,
+    ) -> Trace<F> {
+        let mut trace = Trace {
+            func: self.name.clone(),
+            slots: self.slot,
+            frames: vec![],
+            error: None,
+        };
+        while trace.frames.len() < limit {
+            let mut preimages = Preimages::new_from_func(self);
+            preimages.emitted = Some(vec![]);
+            let (frame, path) = match self.call(args, store, preimages) {
+                Ok(res) => res,
+                Err(e) => {
+                    trace.error = Some(e.to_string());
+                    break;
+                }
+            };
+            let stop = stop_cond(&frame.output);
+            args = frame.output.clone();
+            trace.frames.push(FrameTrace {
+                input: frame.input,
+                output: frame.output,
+                path,
+                slots: slots_filled(&frame.preimages),
+                emitted: frame.preimages.emitted.unwrap_or_default(),
+            });
+            if stop {
+                break;
+            }
+        }
+        trace
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use blstrs::Scalar as Fr;
+
+    use super::*;
+    use crate::func;
+    use crate::lem::Tag;
+    use crate::tag::ExprTag::Nil;
+
+    #[test]
+    fn frames_are_traced() {
+        let countdown = func!(countdown(n): 1 => {
+            emit(n);
+            match n.tag {
+                Expr::Num => {
+                    match n.val {
+                        Num(0) => {
+                            return (n)
+                        }
+                    };
+                    let one = Num(1);
+                    let m = sub(n, one);
+                    let _pair: Expr::Cons = hash2(n, m);
+                    return (m)
+                }
+            }
+        });
+        let store = &mut Store::<Fr>::default();
+        let zero = Ptr::num(Fr::from(0));
+        let two = Ptr::num(Fr::from(2));
+        let trace = countdown.trace_until(vec![two], store, |out| out[0] == zero, 10);
+        assert_eq!(trace.frames.len(), 3);
+        assert_eq!(trace.error, None);
+        let emitted: Vec<_> = trace
+            .frames
+            .iter()
+            .map(|frame| frame.emitted.clone())
+            .collect();
+        assert_eq!(emitted, [[two], [Ptr::num(Fr::from(1))], [zero]]);
+        assert_eq!(trace.frames[0].slots.hash2, 1);
+        assert_eq!(trace.frames[2].slots.hash2, 0);
+        assert_ne!(trace.frames[0].path, trace.frames[2].path);
+
+        let rendered = trace.render(store);
+        assert!(rendered.starts_with("trace of `countdown`, 3 frames"));
+        assert!(rendered.contains("slots:   hash2 1/1"));
+        assert!(rendered.contains("slots:   hash2 0/1"));
+
+        // the trace ends on the frame that can't be interpreted
+        let nil = Ptr::null(Tag::Expr(Nil));
+        let trace = countdown.trace_until(vec![nil], store, |_| false, 10);
+        assert!(trace.frames.is_empty());
+        assert!(trace.error.is_some());
+        assert!(trace.render(store).contains("frame 0 failed"));
+    }
+}
@@ -0,0 +1,449 @@
+//! Static inference of the pointers LEM variables can hold.
+//!
+//! `Func::check_types` follows the tags set by `Null`, `Lit`, `Cast` and the
+//! hashes, and narrowed by `MatchTag`, along with the shapes of the pointers
+//! (leaves or tuples). A function is rejected when an operation can never
+//! get the kind of pointer it works on, like unhashing a number or doing
+//! arithmetic on a cons, or when arities don't match. Such functions would
+//! otherwise only fail when interpreted or synthesized, far from the cause.
+
+use anyhow::{bail, Result};
+use std::collections::{HashMap, HashSet};
+
+use super::{Block, Ctrl, Func, Lit, Op, Tag, Var};
+use crate::tag::ExprTag::{Comm, Num, Str, Sym};
+
+/// The shapes of LEM pointers
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Shape {
+    Leaf,
+    Tuple2,
+    Tuple3,
+    Tuple4,
+    Tuple6,
+    Tuple8,
+}
+
+impl Shape {
+    const ALL: [Shape; 6] = [
+        Shape::Leaf,
+        Shape::Tuple2,
+        Shape::Tuple3,
+        Shape::Tuple4,
+        Shape::Tuple6,
+        Shape::Tuple8,
+    ];
+
+    /// The shape of the pointers with `arity` children, if there's one
+    pub fn tuple(arity: usize) -> Option<Shape> {
+        match arity {
+            2 => Some(Shape::Tuple2),
+            3 => Some(Shape::Tuple3),
+            4 => Some(Shape::Tuple4),
+            6 => Some(Shape::Tuple6),
+            8 => Some(Shape::Tuple8),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Shape {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Leaf => write!(f, "leaf"),
+            Self::Tuple2 => write!(f, "Tuple2"),
+            Self::Tuple3 => write!(f, "Tuple3"),
+            Self::Tuple4 => write!(f, "Tuple4"),
+            Self::Tuple6 => write!(f, "Tuple6"),
+            Self::Tuple8 => write!(f, "Tuple8"),
+        }
+    }
+}
+
+/// The pointers a variable can hold: their possible shapes and, if known,
+/// their possible tags
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PtrType {
+    pub shapes: HashSet<Shape>,
+    pub tags: Option<HashSet<Tag>>,
+}
+
+impl PtrType {
+    /// Any pointer at all
+    pub fn any() -> Self {
+        Self {
+            shapes: Shape::ALL.into(),
+            tags: None,
+        }
+    }
+
+    fn new(shape: Shape, tag: Tag) -> Self {
+        Self {
+            shapes: [shape].into(),
+            tags: Some([tag].into()),
+        }
+    }
+
+    fn num() -> Self {
+        Self::new(Shape::Leaf, Tag::Expr(Num))
+    }
+
+    /// Whether the variable can hold a pointer with `shape` and, if given, `tag`
+    pub fn admits(&self, shape: Shape, tag: Option<Tag>) -> bool {
+        self.shapes.contains(&shape)
+            && match (&self.tags, tag) {
+                (Some(tags), Some(tag)) => tags.contains(&tag),
+                _ => true,
+            }
+    }
+
+    fn union(&mut self, other: &Self) {
+        self.shapes.extend(&other.shapes);
+        self.tags = match (self.tags.take(), &other.tags) {
+            (Some(mut tags), Some(other)) => {
+                tags.extend(other);
+                Some(tags)
+            }
+            _ => None,
+        };
+    }
+}
+
+/// The types found so far and the types of the returns of the function
+/// being checked
+struct Inference {
+    types: HashMap<Var, PtrType>,
+    returns: Option<Vec<PtrType>>,
+}
+
+impl Inference {
+    fn get(&self, var: &Var) -> Result<&PtrType> {
+        match self.types.get(var) {
+            Some(typ) => Ok(typ),
+            None => bail!("Variable {var} is unbound"),
+        }
+    }
+
+    fn bind(&mut self, var: &Var, typ: PtrType) {
+        self.types.insert(var.clone(), typ);
+    }
+
+    fn expect(&self, var: &Var, shape: Shape, tag: Option<Tag>, op: &Op) -> Result<()> {
+        if !self.get(var)?.admits(shape, tag) {
+            match tag {
+                Some(tag) => bail!("{var} can never be a {shape} with tag {tag}, as {op:?} needs"),
+                None => bail!("{var} can never be a {shape}, as {op:?} needs"),
+            }
+        }
+        Ok(())
+    }
+
+    fn block(&mut self, block: &Block, output_size: usize) -> Result<()> {
+        for op in &block.ops {
+            self.op(op)?;
+        }
+        match &block.ctrl {
+            Ctrl::Return(vars) => {
+                if vars.len() != output_size {
+                    bail!(
+                        "Returning {} values from a function of output size {output_size}",
+                        vars.len()
+                    )
+                }
+                let types = vars
+                    .iter()
+                    .map(|var| self.get(var).cloned())
+                    .collect::<Result<Vec<_>>>()?;
+                match &mut self.returns {
+                    Some(returns) => returns
+                        .iter_mut()
+                        .zip(&types)
+                        .for_each(|(ret, typ)| ret.union(typ)),
+                    None => self.returns = Some(types),
+                }
+            }
+            Ctrl::MatchTag(var, cases, def) => {
+                let typ = self.get(var)?.clone();
+                for (tag, block) in cases {
+                    // Arms that can't be taken aren't checked
+                    if typ.tags.as_ref().map_or(true, |tags| tags.contains(tag)) {
+                        self.bind(
+                            var,
+                            PtrType {
+                                shapes: typ.shapes.clone(),
+                                tags: Some([*tag].into()),
+                            },
+                        );
+                        self.block(block, output_size)?;
+                    }
+                }
+                let tags = typ.tags.clone().map(|mut tags| {
+                    tags.retain(|tag| !cases.contains_key(tag));
+                    tags
+                });
+                if let Some(def) = def {
+                    if tags.as_ref().map_or(true, |tags| !tags.is_empty()) {
+                        self.bind(
+                            var,
+                            PtrType {
+                                shapes: typ.shapes.clone(),
+                                tags,
+                            },
+                        );
+                        self.block(def, output_size)?;
+                    }
+                }
+                self.bind(var, typ);
+            }
+            Ctrl::MatchVal(var, cases, def) => {
+                self.get(var)?;
+                for block in cases.values().chain(def.as_deref()) {
+                    self.block(block, output_size)?;
+                }
+            }
+            Ctrl::MatchSymbol(var, cases, def) => {
+                let typ = self.get(var)?.clone();
+                let sym = Tag::Expr(Sym);
+                if typ.tags.as_ref().map_or(false, |tags| !tags.contains(&sym)) {
+                    bail!("{var} can never be a symbol, as the match on its symbols needs")
+                }
+                self.bind(
+                    var,
+                    PtrType {
+                        shapes: typ.shapes.clone(),
+                        tags: Some([sym].into()),
+                    },
+                );
+                for block in cases.values().chain(def.as_deref()) {
+                    self.block(block, output_size)?;
+                }
+                self.bind(var, typ);
+            }
+            Ctrl::IfEq(x, y, eq_block, else_block) => {
+                self.get(x)?;
+                self.get(y)?;
+                self.block(eq_block, output_size)?;
+                self.block(else_block, output_size)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn op(&mut self, op: &Op) -> Result<()> {
+        use Shape::*;
+        match op {
+            Op::Call(tgts, func, srcs) => {
+                if srcs.len() != func.input_params.len() || tgts.len() != func.output_size {
+                    bail!(
+                        "Calling {} with {} arguments and {} targets instead of {} and {}",
+                        func.name,
+                        srcs.len(),
+                        tgts.len(),
+                        func.input_params.len(),
+                        func.output_size
+                    )
+                }
+                let inputs = srcs
+                    .iter()
+                    .map(|src| self.get(src).cloned())
+                    .collect::<Result<Vec<_>>>()?;
+                let (_, returns) = func.infer(inputs)?;
+                for (tgt, typ) in tgts.iter().zip(returns) {
+                    self.bind(tgt, typ);
+                }
+            }
+            Op::Null(tgt, tag) => self.bind(tgt, PtrType::new(Leaf, *tag)),
+            Op::Lit(tgt, lit) => {
+                let typ = match lit {
+                    Lit::Num(..) => PtrType::num(),
+                    Lit::String(..) => PtrType {
+                        tags: Some([Tag::Expr(Str)].into()),
+                        ..PtrType::any()
+                    },
+                    // `nil` doesn't have the symbol tag
+                    Lit::Symbol(..) => PtrType::any(),
+                };
+                self.bind(tgt, typ)
+            }
+            Op::Cast(tgt, tag, src) => {
+                let typ = PtrType {
+                    shapes: self.get(src)?.shapes.clone(),
+                    tags: Some([*tag].into()),
+                };
+                self.bind(tgt, typ)
+            }
+            Op::EqTag(tgt, a, b) | Op::EqVal(tgt, a, b) => {
+                self.get(a)?;
+                self.get(b)?;
+                self.bind(tgt, PtrType::num())
+            }
+            Op::Add(tgt, a, b)
+            | Op::Sub(tgt, a, b)
+            | Op::Mul(tgt, a, b)
+            | Op::Div(tgt, a, b)
+            | Op::Lt(tgt, a, b)
+            | Op::And(tgt, a, b)
+            | Op::Or(tgt, a, b)
+            | Op::Xor(tgt, a, b)
+            | Op::AddI64(tgt, a, b)
+            | Op::SubI64(tgt, a, b)
+            | Op::MulI64(tgt, a, b)
+            | Op::LtI64(tgt, a, b) => {
+                self.expect(a, Leaf, None, op)?;
+                self.expect(b, Leaf, None, op)?;
+                self.bind(tgt, PtrType::num())
+            }
+            Op::Trunc(tgt, a, _) => {
+                self.expect(a, Leaf, None, op)?;
+                self.bind(tgt, PtrType::num())
+            }
+            Op::AssertRange(a, _) | Op::AssertU32(a) | Op::AssertChar(a) => {
+                self.expect(a, Leaf, None, op)?;
+            }
+            Op::DivRem64(tgts, a, b) => {
+                self.expect(a, Leaf, None, op)?;
+                self.expect(b, Leaf, None, op)?;
+                tgts.iter().for_each(|tgt| self.bind(tgt, PtrType::num()))
+            }
+            Op::Emit(a) => {
+                self.get(a)?;
+            }
+            Op::Hash(img, tag, preimg) => {
+                let Some(shape) = Shape::tuple(preimg.len()) else {
+                    bail!("There are no pointers with {} children", preimg.len())
+                };
+                preimg
+                    .iter()
+                    .try_for_each(|var| self.get(var).map(|_| ()))?;
+                self.bind(img, PtrType::new(shape, *tag))
+            }
+            Op::Unhash(preimg, img) => {
+                let Some(shape) = Shape::tuple(preimg.len()) else {
+                    bail!("There are no pointers with {} children", preimg.len())
+                };
+                self.expect(img, shape, None, op)?;
+                preimg.iter().for_each(|var| self.bind(var, PtrType::any()))
+            }
+            Op::Hide(tgt, sec, src) => {
+                self.expect(sec, Leaf, Some(Tag::Expr(Num)), op)?;
+                self.get(src)?;
+                self.bind(tgt, PtrType::new(Leaf, Tag::Expr(Comm)))
+            }
+            Op::Open(tgt_secret, tgt_ptr, comm) => {
+                self.expect(comm, Leaf, Some(Tag::Expr(Comm)), op)?;
+                self.bind(tgt_secret, PtrType::num());
+                self.bind(tgt_ptr, PtrType::any())
+            }
+            Op::Coproc(_, tgts, srcs) => {
+                for src in srcs {
+                    self.get(src)?;
+                }
+                for tgt in tgts {
+                    self.bind(tgt, PtrType::any())
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Func {
+    /// Infers the types of the function's variables given the types of its
+    /// inputs, along with the types of its outputs
+    fn infer(&self, inputs: Vec<PtrType>) -> Result<(HashMap<Var, PtrType>, Vec<PtrType>)> {
+        let mut inference = Inference {
+            types: HashMap::new(),
+            returns: None,
+        };
+        for (param, typ) in self.input_params.iter().zip(inputs) {
+            inference.bind(param, typ);
+        }
+        inference.block(&self.body, self.output_size)?;
+        let returns = inference
+            .returns
+            .unwrap_or_else(|| vec![PtrType::any(); self.output_size]);
+        Ok((inference.types, returns))
+    }
+
+    /// Infers the pointers each variable of the function can hold, assuming
+    /// nothing about the inputs. Errors if some operation can never get the
+    /// kind of pointer it works on or if some arities don't match.
+    pub fn infer_types(&self) -> Result<HashMap<Var, PtrType>> {
+        let inputs = vec![PtrType::any(); self.input_params.len()];
+        Ok(self.infer(inputs)?.0)
+    }
+
+    /// Performs the checks of `infer_types`
+    pub fn check_types(&self) -> Result<()> {
+        self.infer_types().map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::func;
+
+    #[test]
+    fn tags_are_inferred() {
+        let lem = func!(f(x): 2 => {
+            let nil: Expr::Nil;
+            let c: Expr::Cons = hash2(x, nil);
+            let f = cast(c, Expr::Fun);
+            match x.tag {
+                Expr::Num => {
+                    let y = add(x, x);
+                    return (y, f);
+                }
+                Expr::Cons => {
+                    let (a, _b) = unhash2(x);
+                    return (a, c);
+                }
+            }
+        });
+        let types = lem.infer_types().unwrap();
+        let f = types
+            .iter()
+            .find(|(var, _)| var.name().starts_with("f#"))
+            .unwrap();
+        assert_eq!(
+            f.1,
+            &PtrType::new(Shape::Tuple2, Tag::Expr(crate::tag::ExprTag::Fun))
+        );
+    }
+
+    #[test]
+    fn impossible_pointers_are_rejected() {
+        let unhashed_num = func!(f(x): 1 => {
+            let n: Expr::Num;
+            let (a, _b) = unhash2(n);
+            return (a);
+        });
+        assert!(unhashed_num.check_types().is_err());
+
+        let added_cons = func!(f(x): 1 => {
+            let c: Expr::Cons = hash2(x, x);
+            let y = add(c, x);
+            return (y);
+        });
+        assert!(added_cons.check_types().is_err());
+
+        let hidden_char = func!(f(x): 1 => {
+            let c = cast(x, Expr::Char);
+            let z = hide(c, x);
+            return (z);
+        });
+        assert!(hidden_char.check_types().is_err());
+
+        // `x` is a character in the arm
+        let narrowed = func!(f(x): 1 => {
+            match x.tag {
+                Expr::Char => {
+                    let z = hide(x, x);
+                    return (z);
+                }
+            }
+        });
+        assert!(narrowed.check_types().is_err());
+    }
+}
@@ -0,0 +1,298 @@
+//! Static validation of the variables of LEM code.
+//!
+//! `deconflict` renames variables as it goes, so it stops at the first
+//! unbound variable it finds and can't tell where it was read. This pass runs
+//! on the code as it was written instead, and reports every unbound read,
+//! every statement that binds the same variable twice and every binding that
+//! shadows a variable already in scope, pointing at the statement at fault.
+//!
+//! Shadowing is allowed in LEM, so only the first two are errors. `func!`
+//! records where each function was written, which is added to the reports.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use super::{Block, Ctrl, Lit, Tag, Var};
+
+/// Where a LEM function was written
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub file: &'static str,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// A branch of a control, leading to one of its blocks
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Branch {
+    Tag(Tag),
+    Val(Lit),
+    Default,
+    Eq,
+    NotEq,
+}
+
+impl fmt::Display for Branch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tag(tag) => write!(f, "case {tag}"),
+            Self::Val(lit) => write!(f, "case {lit:?}"),
+            Self::Default => write!(f, "default case"),
+            Self::Eq => write!(f, "eq branch"),
+            Self::NotEq => write!(f, "else branch"),
+        }
+    }
+}
+
+/// A statement of a function
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stmt {
+    /// The function's input parameters
+    Params,
+    /// The operation of a block with this index
+    Op(usize),
+    /// The control of a block
+    Ctrl,
+}
+
+/// A statement along with the branches leading to its block
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Location {
+    pub branches: Vec<Branch>,
+    pub stmt: Stmt,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for branch in &self.branches {
+            write!(f, "{branch} > ")?;
+        }
+        match self.stmt {
+            Stmt::Params => write!(f, "input params"),
+            Stmt::Op(idx) => write!(f, "op {idx}"),
+            Stmt::Ctrl => write!(f, "control"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Issue {
+    /// The variable is read but isn't bound
+    Unbound(Var),
+    /// The variable is bound more than once by the same statement
+    DoubleBind(Var),
+    /// The variable is bound while already in scope
+    Shadowed(Var),
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unbound(var) => write!(f, "variable {var} is unbound"),
+            Self::DoubleBind(var) => write!(f, "variable {var} is bound more than once"),
+            Self::Shadowed(var) => write!(f, "variable {var} shadows a previous binding"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub span: Option<Span>,
+    pub location: Location,
+    pub issue: Issue,
+}
+
+impl Diagnostic {
+    #[inline]
+    pub fn is_error(&self) -> bool {
+        !matches!(self.issue, Issue::Shadowed(..))
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(span) = self.span {
+            write!(f, "{span}: ")?;
+        }
+        write!(f, "{}: {}", self.location, self.issue)
+    }
+}
+
+struct Validator {
+    span: Option<Span>,
+    branches: Vec<Branch>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Validator {
+    fn report(&mut self, stmt: Stmt, issue: Issue) {
+        self.diagnostics.push(Diagnostic {
+            span: self.span,
+            location: Location {
+                branches: self.branches.clone(),
+                stmt,
+            },
+            issue,
+        })
+    }
+
+    fn read<'a>(
+        &mut self,
+        scope: &HashSet<Var>,
+        stmt: Stmt,
+        vars: impl IntoIterator<Item = &'a Var>,
+    ) {
+        for var in vars {
+            if !scope.contains(var) {
+                self.report(stmt, Issue::Unbound(var.clone()))
+            }
+        }
+    }
+
+    /// Binds the variables bound by one statement
+    fn bind<'a>(
+        &mut self,
+        scope: &mut HashSet<Var>,
+        stmt: Stmt,
+        vars: impl IntoIterator<Item = &'a Var>,
+    ) {
+        let mut bound = HashSet::new();
+        for var in vars {
+            if !bound.insert(var) {
+                self.report(stmt, Issue::DoubleBind(var.clone()))
+            } else if !scope.insert(var.clone()) {
+                self.report(stmt, Issue::Shadowed(var.clone()))
+            }
+        }
+    }
+
+    fn branch(&mut self, branch: Branch, block: &Block, scope: &HashSet<Var>) {
+        self.branches.push(branch);
+        self.block(block, scope.clone());
+        self.branches.pop();
+    }
+
+    fn block(&mut self, block: &Block, mut scope: HashSet<Var>) {
+        for (idx, op) in block.ops.iter().enumerate() {
+            // a called function is a scope of its own, already validated
+            self.read(&scope, Stmt::Op(idx), op.sources());
+            self.bind(&mut scope, Stmt::Op(idx), op.targets());
+        }
+        match &block.ctrl {
+            Ctrl::Return(vars) => self.read(&scope, Stmt::Ctrl, vars),
+            Ctrl::MatchTag(var, cases, def) => {
+                self.read(&scope, Stmt::Ctrl, [var]);
+                for (tag, block) in cases {
+                    self.branch(Branch::Tag(*tag), block, &scope);
+                }
+                if let Some(def) = def {
+                    self.branch(Branch::Default, def, &scope);
+                }
+            }
+            Ctrl::MatchVal(var, cases, def) => {
+                self.read(&scope, Stmt::Ctrl, [var]);
+                for (lit, block) in cases {
+                    self.branch(Branch::Val(lit.clone()), block, &scope);
+                }
+                if let Some(def) = def {
+                    self.branch(Branch::Default, def, &scope);
+                }
+            }
+            Ctrl::MatchSymbol(var, cases, def) => {
+                self.read(&scope, Stmt::Ctrl, [var]);
+                for (sym, block) in cases {
+                    self.branch(Branch::Val(Lit::Symbol(sym.clone())), block, &scope);
+                }
+                if let Some(def) = def {
+                    self.branch(Branch::Default, def, &scope);
+                }
+            }
+            Ctrl::IfEq(x, y, eq_block, else_block) => {
+                self.read(&scope, Stmt::Ctrl, [x, y]);
+                self.branch(Branch::Eq, eq_block, &scope);
+                self.branch(Branch::NotEq, else_block, &scope);
+            }
+        }
+    }
+}
+
+impl Block {
+    /// Reports the issues with the variables of a function with this body and
+    /// the given input params, in the order they appear in the code
+    pub fn validate(&self, input_params: &[Var], span: Option<Span>) -> Vec<Diagnostic> {
+        let mut validator = Validator {
+            span,
+            branches: vec![],
+            diagnostics: vec![],
+        };
+        let mut scope = HashSet::new();
+        validator.bind(&mut scope, Stmt::Params, input_params);
+        validator.block(self, scope);
+        validator.diagnostics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lem::Func;
+    use crate::{block, var};
+
+    #[test]
+    fn reports_unbound_double_bound_and_shadowed_vars() {
+        let body = block!({
+            let (a, a) = unhash2(x);
+            match x.tag {
+                Expr::Num => {
+                    let y = add(a, b);
+                    return (y);
+                }
+            };
+            let x: Expr::Nil;
+            return (x);
+        });
+        let span = Span {
+            file: "f.rs",
+            line: 1,
+            column: 2,
+        };
+        let diagnostics = body.validate(&[var!(x)], Some(span));
+        let issues = diagnostics
+            .iter()
+            .map(|d| (d.location.to_string(), d.issue.clone(), d.is_error()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            issues,
+            vec![
+                ("op 0".into(), Issue::DoubleBind(var!(a)), true),
+                (
+                    "case expr.num# > op 0".into(),
+                    Issue::Unbound(var!(b)),
+                    true
+                ),
+                (
+                    "default case > op 0".into(),
+                    Issue::Shadowed(var!(x)),
+                    false
+                ),
+            ]
+        );
+        assert_eq!(
+            diagnostics[1].to_string(),
+            "f.rs:1:2: case expr.num# > op 0: variable b is unbound"
+        );
+    }
+
+    #[test]
+    fn invalid_functions_are_rejected() {
+        let body = block!({ return (y) });
+        let err = Func::new("foo".into(), vec![var!(x)], 1, body).unwrap_err();
+        assert!(err.to_string().contains("control: variable y is unbound"));
+    }
+}
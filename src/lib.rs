@@ -2,6 +2,10 @@
 #[macro_use]
 extern crate alloc;
 
+pub mod api;
+pub mod artifacts;
+pub mod batch;
+pub mod builder;
 pub mod cache_map;
 pub mod circuit;
 pub mod cli;
@@ -14,14 +18,17 @@ pub mod expr;
 pub mod field;
 pub mod hash;
 pub mod hash_witness;
+pub mod hasher;
 pub mod lem;
 mod num;
 pub mod package;
 pub mod parser;
+pub mod pattern;
 pub mod proof;
 pub mod ptr;
 pub mod public_parameters;
 pub mod repl;
+pub mod secret;
 pub mod state;
 pub mod store;
 pub mod symbol;
@@ -2,6 +2,7 @@ use std::cell::RefCell;
 use std::rc::Rc;
 
 use crate::field::LurkField;
+use crate::parser::dialect::ReaderDialect;
 use crate::ptr::Ptr;
 use crate::state::State;
 use crate::store::Store;
@@ -10,6 +11,7 @@ use nom::Parser;
 use thiserror;
 
 pub mod base;
+pub mod dialect;
 pub mod error;
 pub mod position;
 pub mod string;
@@ -48,6 +50,18 @@ impl<F: LurkField> Store<F> {
         }
     }
 
+    pub fn read_with_dialect(
+        &mut self,
+        dialect: ReaderDialect,
+        input: &str,
+    ) -> Result<Ptr<F>, Error> {
+        let mut state = State::init_lurk_state();
+        state
+            .set_dialect(dialect)
+            .map_err(|e| Error::Syntax(format!("{e}")))?;
+        self.read_with_state(state.rccell(), input)
+    }
+
     pub fn read_with_state(
         &mut self,
         state: Rc<RefCell<State>>,
@@ -0,0 +1,82 @@
+use serde::{Deserialize, Serialize};
+
+/// How the reader treats the case of unescaped symbol limbs. Limbs written
+/// between pipes (`|Foo|`) are always read verbatim.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CaseFolding {
+    /// Symbols are read exactly as written
+    #[default]
+    Preserve,
+    /// Symbols are lowercased, so `LAMBDA` and `lambda` read the same
+    Lower,
+}
+
+impl CaseFolding {
+    #[inline]
+    pub fn fold(&self, limb: String) -> String {
+        match self {
+            Self::Preserve => limb,
+            Self::Lower => limb.to_lowercase(),
+        }
+    }
+}
+
+/// How backslashes are interpreted inside string literals
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum StringEscapes {
+    /// `\n`, `\u{00AC}`, escaped whitespace and so on
+    #[default]
+    Standard,
+    /// Backslashes are ordinary characters and strings end at the first `"`
+    Raw,
+}
+
+/// Settings that affect how source text is turned into pointers. The same
+/// bytes read under different dialects can produce different data, so the
+/// dialect is recorded next to anything that depends on what was read.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ReaderDialect {
+    pub case_folding: CaseFolding,
+    /// Path of the package that relative symbols resolve against when reading
+    /// starts, e.g. `["lurk", "user"]`. `None` keeps the state's own package.
+    pub default_package: Option<Vec<String>>,
+    pub string_escapes: StringEscapes,
+}
+
+impl ReaderDialect {
+    #[inline]
+    pub fn case_folding(mut self, case_folding: CaseFolding) -> Self {
+        self.case_folding = case_folding;
+        self
+    }
+
+    #[inline]
+    pub fn default_package(mut self, path: Vec<String>) -> Self {
+        self.default_package = Some(path);
+        self
+    }
+
+    #[inline]
+    pub fn string_escapes(mut self, string_escapes: StringEscapes) -> Self {
+        self.string_escapes = string_escapes;
+        self
+    }
+}
+
+impl std::fmt::Display for ReaderDialect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let case = match self.case_folding {
+            CaseFolding::Preserve => "preserve",
+            CaseFolding::Lower => "lower",
+        };
+        let escapes = match self.string_escapes {
+            StringEscapes::Standard => "standard",
+            StringEscapes::Raw => "raw",
+        };
+        write!(f, "case: {case}, escapes: {escapes}")?;
+        if let Some(path) = &self.default_package {
+            write!(f, ", package: .{}", path.join("."))?;
+        }
+        Ok(())
+    }
+}
@@ -6,7 +6,7 @@
 use crate::field::LurkField;
 use nom::{
     branch::alt,
-    bytes::complete::{is_not, take_while_m_n},
+    bytes::complete::{is_not, take_till, take_while_m_n},
     character::complete::{char, multispace1, one_of},
     combinator::{map, value, verify},
     multi::fold_many0,
@@ -198,6 +198,23 @@ pub fn parse_string<'a, F: LurkField>(
         )(from)
     }
 }
+
+/// Parse a string in which backslashes carry no meaning. It ends at the first
+/// occurrence of the delimiter.
+pub fn parse_raw_string<'a, F: LurkField>(
+    delim: char,
+) -> impl Fn(Span<'a>) -> ParseResult<'a, F, String> {
+    move |from: Span<'a>| {
+        delimited(
+            char(delim),
+            map(take_till(|c| c == delim), |s: Span<'a>| {
+                (*s.fragment()).to_string()
+            }),
+            char(delim),
+        )(from)
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use blstrs::Scalar as Fr;
@@ -231,6 +248,17 @@ pub mod tests {
         }
     }
 
+    #[test]
+    fn unit_parse_raw_string() {
+        test_parse(parse_raw_string('"'), "\"\"", Some(String::from("")));
+        test_parse(
+            parse_raw_string('"'),
+            "\"fo\\no\"",
+            Some(String::from("fo\\no")),
+        );
+        test_parse(parse_raw_string('"'), "\"foo", None);
+    }
+
     #[test]
     fn unit_parse_string() {
         test_parse(parse_string('"'), "\"foo\"", Some(String::from("foo")));
@@ -2,9 +2,9 @@ use std::{cell::RefCell, rc::Rc};
 
 use nom::{
     branch::alt,
-    bytes::complete::{tag, take_till},
-    character::complete::{anychar, char, multispace0, multispace1, none_of},
-    combinator::{opt, peek, success, value},
+    bytes::complete::{tag, take_till, take_till1, take_while},
+    character::complete::{anychar, char, multispace0, multispace1, none_of, satisfy},
+    combinator::{map, opt, peek, recognize, success, value},
     error::context,
     multi::{many0, many_till, separated_list1},
     sequence::{delimited, preceded, terminated},
@@ -17,6 +17,7 @@ use crate::{
     package::SymbolRef,
     parser::{
         base,
+        dialect::{CaseFolding, StringEscapes},
         error::{ParseError, ParseErrorKind},
         position::Pos,
         string, ParseResult, Span,
@@ -46,10 +47,14 @@ pub fn parse_space1<F: LurkField>(i: Span<'_>) -> ParseResult<'_, F, Vec<Span<'_
 
 pub fn parse_symbol_limb<F: LurkField>(
     escape: &'static str,
+    folding: CaseFolding,
 ) -> impl Fn(Span<'_>) -> ParseResult<'_, F, String> {
     move |from: Span<'_>| {
         let (i, s) = alt((
-            string::parse_string_inner1(symbol::SYM_SEPARATOR, false, escape),
+            map(
+                string::parse_string_inner1(symbol::SYM_SEPARATOR, false, escape),
+                |s| folding.fold(s),
+            ),
             delimited(
                 tag("|"),
                 string::parse_string_inner1('|', true, "|"),
@@ -63,10 +68,13 @@ pub fn parse_symbol_limb<F: LurkField>(
 
 pub fn parse_symbol_limb_raw<F: LurkField>(
     escape: &'static str,
+    folding: CaseFolding,
 ) -> impl Fn(Span<'_>) -> ParseResult<'_, F, String> {
     move |from: Span<'_>| {
         let (i, s) = alt((
-            string::parse_string_inner1(' ', false, escape),
+            map(string::parse_string_inner1(' ', false, escape), |s| {
+                folding.fold(s)
+            }),
             delimited(
                 tag("|"),
                 string::parse_string_inner1('|', true, "|"),
@@ -78,11 +86,13 @@ pub fn parse_symbol_limb_raw<F: LurkField>(
     }
 }
 
-pub fn parse_symbol_limbs<F: LurkField>() -> impl Fn(Span<'_>) -> ParseResult<'_, F, Vec<String>> {
+pub fn parse_symbol_limbs<F: LurkField>(
+    folding: CaseFolding,
+) -> impl Fn(Span<'_>) -> ParseResult<'_, F, Vec<String>> {
     move |from: Span<'_>| {
         let (i, path) = separated_list1(
             char(symbol::SYM_SEPARATOR),
-            parse_symbol_limb(symbol::ESCAPE_CHARS),
+            parse_symbol_limb(symbol::ESCAPE_CHARS, folding),
         )(from)?;
         let (upto, _) = opt(tag("."))(i)?;
         Ok((upto, path))
@@ -123,7 +133,8 @@ pub fn parse_absolute_symbol<F: LurkField>(
             value(false, char(symbol::SYM_MARKER)),
             value(true, char(symbol::KEYWORD_MARKER)),
         ))(from)?;
-        let (upto, path) = parse_symbol_limbs()(i)?;
+        let folding = state.borrow().dialect().case_folding;
+        let (upto, path) = parse_symbol_limbs(folding)(i)?;
         intern_path(
             state.clone(),
             upto,
@@ -140,7 +151,8 @@ pub fn parse_relative_symbol<F: LurkField>(
 ) -> impl Fn(Span<'_>) -> ParseResult<'_, F, SymbolRef> {
     move |from: Span<'_>| {
         let (i, _) = peek(none_of(",~#(){}[]1234567890."))(from)?;
-        let (upto, path) = parse_symbol_limbs()(i)?;
+        let folding = state.borrow().dialect().case_folding;
+        let (upto, path) = parse_symbol_limbs(folding)(i)?;
         intern_path(state.clone(), upto, &path, None, create_unknown_packages)
     }
 }
@@ -151,7 +163,8 @@ pub fn parse_raw_symbol<F: LurkField>(
 ) -> impl Fn(Span<'_>) -> ParseResult<'_, F, SymbolRef> {
     move |from: Span<'_>| {
         let (i, _) = tag("~(")(from)?;
-        let (i, mut path) = many0(preceded(parse_space, parse_symbol_limb_raw("|()")))(i)?;
+        let folding = state.borrow().dialect().case_folding;
+        let (i, mut path) = many0(preceded(parse_space, parse_symbol_limb_raw("|()", folding)))(i)?;
         let (upto, _) = many_till(parse_space, tag(")"))(i)?;
         path.reverse();
         intern_path(
@@ -170,7 +183,8 @@ pub fn parse_raw_keyword<F: LurkField>(
 ) -> impl Fn(Span<'_>) -> ParseResult<'_, F, SymbolRef> {
     move |from: Span<'_>| {
         let (i, _) = tag("~:(")(from)?;
-        let (i, mut path) = many0(preceded(parse_space, parse_symbol_limb_raw("|()")))(i)?;
+        let folding = state.borrow().dialect().case_folding;
+        let (i, mut path) = many0(preceded(parse_space, parse_symbol_limb_raw("|()", folding)))(i)?;
         let (upto, _) = many_till(parse_space, tag(")"))(i)?;
         path.reverse();
         intern_path(
@@ -287,9 +301,14 @@ pub fn parse_num<F: LurkField>() -> impl Fn(Span<'_>) -> ParseResult<'_, F, Synt
     }
 }
 
-pub fn parse_string<F: LurkField>() -> impl Fn(Span<'_>) -> ParseResult<'_, F, Syntax<F>> {
+pub fn parse_string<F: LurkField>(
+    escapes: StringEscapes,
+) -> impl Fn(Span<'_>) -> ParseResult<'_, F, Syntax<F>> {
     move |from: Span<'_>| {
-        let (upto, s) = string::parse_string('"')(from)?;
+        let (upto, s) = match escapes {
+            StringEscapes::Standard => string::parse_string('"')(from)?,
+            StringEscapes::Raw => string::parse_raw_string('"')(from)?,
+        };
         let pos = Pos::from_upto(from, upto);
         Ok((upto, Syntax::String(pos, s)))
     }
@@ -305,6 +324,25 @@ pub fn parse_hash_char<F: LurkField>() -> impl Fn(Span<'_>) -> ParseResult<'_, F
     }
 }
 
+// hash syntax for foreign addresses, whose schemes are those of URIs
+pub fn parse_foreign<F: LurkField>() -> impl Fn(Span<'_>) -> ParseResult<'_, F, Syntax<F>> {
+    |from: Span<'_>| {
+        let (i, _) = tag("#")(from)?;
+        let (i, scheme) = recognize(preceded(
+            satisfy(|c| c.is_ascii_alphabetic()),
+            take_while(|c: char| c.is_ascii_alphanumeric() || "+-.".contains(c)),
+        ))(i)?;
+        let (i, _) = char(':')(i)?;
+        let (upto, id) = take_till1(|c: char| c.is_whitespace() || c == '(' || c == ')')(i)?;
+        let pos = Pos::from_upto(from, upto);
+        let scheme = scheme.fragment().to_ascii_lowercase();
+        Ok((
+            upto,
+            Syntax::Foreign(pos, scheme, id.fragment().to_string()),
+        ))
+    }
+}
+
 pub fn parse_char<F: LurkField>() -> impl Fn(Span<'_>) -> ParseResult<'_, F, Syntax<F>> {
     move |from: Span<'_>| {
         let (i, _) = tag("'")(from)?;
@@ -327,51 +365,8 @@ pub fn parse_list<F: LurkField>(
     create_unknown_packages: bool,
 ) -> impl Fn(Span<'_>) -> ParseResult<'_, F, Syntax<F>> {
     move |from: Span<'_>| {
-        let (i, _) = tag("(")(from)?;
-        let (i, xs) = if meta {
-            // parse the head symbol in the meta package
-            let saved_package = state.borrow().get_current_package_name().clone();
-            state
-                .borrow_mut()
-                .set_current_package(meta_package_symbol().into())
-                .expect("meta package is available");
-            let (i, h) = preceded(
-                parse_space,
-                parse_symbol(state.clone(), create_unknown_packages),
-            )(i)?;
-            // then recover the previous package
-            state
-                .borrow_mut()
-                .set_current_package(saved_package)
-                .expect("previous package is available");
-            let (i, t) = many0(preceded(
-                parse_space,
-                parse_syntax(state.clone(), false, create_unknown_packages),
-            ))(i)?;
-            let mut xs = vec![h];
-            xs.extend(t);
-            (i, xs)
-        } else {
-            many0(preceded(
-                parse_space,
-                parse_syntax(state.clone(), false, create_unknown_packages),
-            ))(i)?
-        };
-        let (i, end) = opt(preceded(
-            preceded(parse_space, tag(".")),
-            preceded(
-                parse_space,
-                parse_syntax(state.clone(), false, create_unknown_packages),
-            ),
-        ))(i)?;
-        let (i, _) = parse_space(i)?;
-        let (upto, _) = tag(")")(i)?;
-        let pos = Pos::from_upto(from, upto);
-        if let Some(end) = end {
-            Ok((upto, Syntax::Improper(pos, xs, Box::new(end))))
-        } else {
-            Ok((upto, Syntax::List(pos, xs)))
-        }
+        let (_, _) = peek(tag("("))(from)?;
+        parse_syntax(state.clone(), meta, create_unknown_packages)(from)
     }
 }
 
@@ -384,40 +379,162 @@ pub fn parse_quote<F: LurkField>(
         if let Some(c) = c {
             Ok((i, c))
         } else {
-            let (i, _) = tag("'")(from)?;
-            let (upto, s) = parse_syntax(state.clone(), false, create_unknown_packages)(i)?;
-            let pos = Pos::from_upto(from, upto);
-            Ok((upto, Syntax::Quote(pos, Box::new(s))))
+            let (_, _) = peek(tag("'"))(from)?;
+            parse_syntax(state.clone(), false, create_unknown_packages)(from)
         }
     }
 }
 
-// top-level syntax parser
-pub fn parse_syntax<F: LurkField>(
+/// How an expression starts
+enum Start<F: LurkField> {
+    /// With an expression that has no subexpressions
+    Atom(Syntax<F>),
+    /// With the opening parenthesis of a list
+    List,
+    /// With the quote of a quoted expression
+    Quote,
+}
+
+fn parse_start<F: LurkField>(
     state: Rc<RefCell<State>>,
-    meta: bool,
-    // this parameter triggers a less strict mode for testing purposes
     create_unknown_packages: bool,
-) -> impl Fn(Span<'_>) -> ParseResult<'_, F, Syntax<F>> {
+) -> impl Fn(Span<'_>) -> ParseResult<'_, F, Start<F>> {
     move |from: Span<'_>| {
+        let escapes = state.borrow().dialect().string_escapes;
         alt((
+            context("list", map(tag("("), |_| Start::List)),
+            map(parse_uint(), Start::Atom),
+            map(parse_num(), Start::Atom),
             context(
-                "list",
-                parse_list(state.clone(), meta, create_unknown_packages),
+                "symbol",
+                map(
+                    parse_symbol(state.clone(), create_unknown_packages),
+                    Start::Atom,
+                ),
             ),
-            parse_uint(),
-            parse_num(),
+            map(parse_string(escapes), Start::Atom),
             context(
-                "symbol",
-                parse_symbol(state.clone(), create_unknown_packages),
+                "quote",
+                alt((
+                    map(parse_char(), Start::Atom),
+                    map(tag("'"), |_| Start::Quote),
+                )),
             ),
-            parse_string(),
-            context("quote", parse_quote(state.clone(), create_unknown_packages)),
-            parse_hash_char(),
+            map(parse_hash_char(), Start::Atom),
+            map(parse_foreign(), Start::Atom),
         ))(from)
     }
 }
 
+/// An expression whose subexpressions are being read, along with where it
+/// starts
+enum Open<'a, F: LurkField> {
+    /// A list, with the elements read so far
+    List(Span<'a>, Vec<Syntax<F>>),
+    /// An improper list, whose last cdr is being read
+    Improper(Span<'a>, Vec<Syntax<F>>),
+    /// A quote, whose expression is being read
+    Quote(Span<'a>),
+}
+
+// top-level syntax parser
+//
+// The lists and quotes an expression is nested in are kept on the heap rather
+// than on the stack, so that reading deeply nested data can't overflow it
+pub fn parse_syntax<F: LurkField>(
+    state: Rc<RefCell<State>>,
+    meta: bool,
+    // this parameter triggers a less strict mode for testing purposes
+    create_unknown_packages: bool,
+) -> impl Fn(Span<'_>) -> ParseResult<'_, F, Syntax<F>> {
+    move |from: Span<'_>| {
+        let parse_start = parse_start(state.clone(), create_unknown_packages);
+        let mut open: Vec<Open<'_, F>> = vec![];
+        let mut i = from;
+        loop {
+            // read an expression, or the end of the list it would be in
+            let in_list = matches!(open.last(), Some(Open::List(..)));
+            let (start, _) = if in_list {
+                parse_space(i)?
+            } else {
+                (i, vec![])
+            };
+            let (mut upto, mut syntax) = match parse_start(start) {
+                Ok((upto, Start::Atom(syntax))) => (upto, syntax),
+                Ok((upto, Start::List)) => {
+                    let mut xs = vec![];
+                    i = upto;
+                    if meta && open.is_empty() {
+                        // parse the head symbol in the meta package
+                        let saved_package = state.borrow().get_current_package_name().clone();
+                        state
+                            .borrow_mut()
+                            .set_current_package(meta_package_symbol().into())
+                            .expect("meta package is available");
+                        let (j, h) = preceded(
+                            parse_space,
+                            parse_symbol(state.clone(), create_unknown_packages),
+                        )(i)?;
+                        // then recover the previous package
+                        state
+                            .borrow_mut()
+                            .set_current_package(saved_package)
+                            .expect("previous package is available");
+                        xs.push(h);
+                        i = j;
+                    }
+                    open.push(Open::List(start, xs));
+                    continue;
+                }
+                Ok((upto, Start::Quote)) => {
+                    open.push(Open::Quote(start));
+                    i = upto;
+                    continue;
+                }
+                Err(nom::Err::Error(_)) if in_list => {
+                    let Some(Open::List(list_from, xs)) = open.pop() else {
+                        unreachable!("the expression was read in a list")
+                    };
+                    let dot: ParseResult<'_, F, Span<'_>> = preceded(parse_space, tag("."))(i);
+                    if let Ok((j, _)) = dot {
+                        let (j, _) = parse_space(j)?;
+                        open.push(Open::Improper(list_from, xs));
+                        i = j;
+                        continue;
+                    }
+                    let (j, _) = parse_space(i)?;
+                    let (upto, _) = tag(")")(j)?;
+                    (upto, Syntax::List(Pos::from_upto(list_from, upto), xs))
+                }
+                Err(e) => return Err(e),
+            };
+            // put the expression in the ones it's nested in, closing those it ends
+            loop {
+                match open.pop() {
+                    None => return Ok((upto, syntax)),
+                    Some(Open::Quote(quote_from)) => {
+                        let pos = Pos::from_upto(quote_from, upto);
+                        syntax = Syntax::Quote(pos, Box::new(syntax));
+                    }
+                    Some(Open::List(list_from, mut xs)) => {
+                        xs.push(syntax);
+                        open.push(Open::List(list_from, xs));
+                        break;
+                    }
+                    Some(Open::Improper(list_from, xs)) => {
+                        let (j, _) = parse_space(upto)?;
+                        let (j, _) = tag(")")(j)?;
+                        let pos = Pos::from_upto(list_from, j);
+                        syntax = Syntax::Improper(pos, xs, Box::new(syntax));
+                        upto = j;
+                    }
+                }
+            }
+            i = upto;
+        }
+    }
+}
+
 pub fn parse_maybe_meta<F: LurkField>(
     state: Rc<RefCell<State>>,
     create_unknown_packages: bool,
@@ -442,7 +559,7 @@ pub mod tests {
     use proptest::prelude::*;
 
     use super::*;
-    use crate::{char, keyword, list, num, str, symbol, uint};
+    use crate::{char, keyword, list, num, parser::dialect::ReaderDialect, str, symbol, uint};
 
     fn test<'a, P, R>(mut p: P, i: &'a str, expected: Option<R>) -> bool
     where
@@ -463,14 +580,34 @@ pub mod tests {
 
     #[test]
     fn unit_parse_string() {
-        assert!(test(parse_string(), "\"foo\"", Some(str!("foo"))));
-        assert!(test(parse_string(), "\"fo\\no\"", Some(str!("fo\no"))));
+        let escapes = StringEscapes::Standard;
+        assert!(test(parse_string(escapes), "\"foo\"", Some(str!("foo"))));
         assert!(test(
-            parse_string(),
+            parse_string(escapes),
+            "\"fo\\no\"",
+            Some(str!("fo\no"))
+        ));
+        assert!(test(
+            parse_string(escapes),
             "\"fo\\u{00}o\"",
             Some(str!("fo\u{00}o"))
         ));
-        assert!(test(parse_string(), "\"foo\\   \"", Some(str!("foo"))));
+        assert!(test(
+            parse_string(escapes),
+            "\"foo\\   \"",
+            Some(str!("foo"))
+        ));
+        let escapes = StringEscapes::Raw;
+        assert!(test(
+            parse_string(escapes),
+            "\"fo\\no\"",
+            Some(str!("fo\\no"))
+        ));
+        assert!(test(
+            parse_string(escapes),
+            "\"foo\\   \"",
+            Some(str!("foo\\   "))
+        ));
     }
 
     #[test]
@@ -602,6 +739,36 @@ pub mod tests {
         ));
     }
 
+    #[test]
+    fn unit_parse_symbol_case_folding() {
+        let state_ = State::default().rccell();
+        state_
+            .borrow_mut()
+            .set_dialect(ReaderDialect::default().case_folding(CaseFolding::Lower))
+            .unwrap();
+        let state = || state_.clone();
+        assert!(test(
+            parse_symbol(state(), true),
+            "FOO.Bar",
+            Some(symbol!(["foo", "bar"]))
+        ));
+        assert!(test(
+            parse_symbol(state(), true),
+            "|FOO|.Bar",
+            Some(symbol!(["FOO", "bar"]))
+        ));
+        assert!(test(
+            parse_symbol(state(), true),
+            ":Foo",
+            Some(keyword!(["foo"]))
+        ));
+        assert!(test(
+            parse_symbol(state(), true),
+            "~(Foo |Bar|)",
+            Some(symbol!(["Bar", "foo"]))
+        ));
+    }
+
     #[test]
     fn unit_parse_keyword() {
         let state_ = State::default().rccell();
@@ -798,6 +965,31 @@ pub mod tests {
         ));
     }
 
+    #[test]
+    fn unit_parse_foreign() {
+        let state_ = State::default().rccell();
+        let state = || state_.clone();
+        let foreign = |scheme: &str, id: &str| Syntax::Foreign(Pos::No, scheme.into(), id.into());
+        assert!(test(
+            parse_foreign(),
+            "#cid:bafy42",
+            Some(foreign("cid", "bafy42"))
+        ));
+        assert!(test(
+            parse_foreign(),
+            "#HTTPS://lurk-lang.org/x?y=1",
+            Some(foreign("https", "//lurk-lang.org/x?y=1"))
+        ));
+        assert!(test(parse_foreign(), "#cid:", None));
+        assert!(test(parse_foreign(), "#1cid:bafy42", None));
+        assert!(test(
+            parse_syntax(state(), false, false),
+            "(#cid:bafy42)",
+            Some(list!([foreign("cid", "bafy42")]))
+        ));
+        assert_eq!("#cid:bafy42", foreign("cid", "bafy42").to_string());
+    }
+
     #[test]
     fn unit_parse_quote() {
         let state_ = State::default().rccell();
@@ -1004,6 +1196,33 @@ pub mod tests {
         assert!(test(parse_syntax(state(), false, true), "#", None));
     }
 
+    #[test]
+    fn deep_and_long_lists_are_parsed() {
+        let depth = 10_000;
+        let text = format!("{}'x{}", "(".repeat(depth), ")".repeat(depth));
+        let (rest, mut syntax) =
+            parse_syntax::<Scalar>(State::default().rccell(), false, true)(Span::new(&text))
+                .expect("valid parse");
+        assert!(rest.is_empty());
+        for _ in 0..depth {
+            let Syntax::List(_, mut xs) = syntax else {
+                panic!("expected a list")
+            };
+            assert_eq!(xs.len(), 1);
+            syntax = xs.pop().unwrap();
+        }
+        assert!(matches!(syntax, Syntax::Quote(..)));
+
+        let text = format!("({} . 1)", "a ".repeat(100_000));
+        let (_, syntax) =
+            parse_syntax::<Scalar>(State::default().rccell(), false, true)(Span::new(&text))
+                .expect("valid parse");
+        let Syntax::Improper(_, xs, _) = syntax else {
+            panic!("expected an improper list")
+        };
+        assert_eq!(xs.len(), 100_000);
+    }
+
     #[test]
     fn test_minus_zero_symbol() {
         let x: Syntax<Scalar> = symbol!(["-0"]);
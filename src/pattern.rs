@@ -0,0 +1,322 @@
+//! Destructuring Lurk values into Rust values.
+//!
+//! Consumers of evaluation results usually expect a value of a fixed shape,
+//! such as a list of a number and a string, and would otherwise walk it with
+//! `car_cdr` and check each tag by hand. A type implementing `FromLurk` can be
+//! read from any pointer of a store, failing with a `MatchError` that says
+//! what was expected where and what was found instead. Lists of fixed shape
+//! are read into tuples, and the `ptr_match!` macro reads the first elements
+//! of a longer list into a tuple along with the rest of it:
+//!
+//! ```
+//! use lurk::{ptr::Ptr, ptr_match, store::Store};
+//! use pasta_curves::pallas::Scalar as Fr;
+//!
+//! let store = &mut Store::<Fr>::default();
+//! let ptr = store.read("(42u64 \"answer\" 1 2)").unwrap();
+//! let (n, name, rest) = ptr_match!(store, &ptr, [u64, String; ..]).unwrap();
+//! assert_eq!((n, name.as_str()), (42, "answer"));
+//! let rest: Vec<u64> = ptr_match!(store, &rest, Vec<u64>).unwrap();
+//! assert_eq!(rest, [1, 2]);
+//! ```
+
+use thiserror::Error;
+
+use crate::field::LurkField;
+use crate::num::Num;
+use crate::ptr::Ptr;
+use crate::state::{initial_lurk_state, lurk_sym};
+use crate::store::Store;
+use crate::symbol::Symbol;
+use crate::tag::ExprTag;
+use crate::uint::UInt;
+use crate::writer::Write;
+
+/// A value that isn't of the expected shape
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("{}expected {expected}, got {got}", location(.path))]
+pub struct MatchError {
+    /// The indices of the list elements leading to the value, outermost first
+    pub path: Vec<usize>,
+    pub expected: String,
+    /// The value found, as printed by Lurk
+    pub got: String,
+}
+
+fn location(path: &[usize]) -> String {
+    if path.is_empty() {
+        return String::new();
+    }
+    let path = path.iter().map(ToString::to_string).collect::<Vec<_>>();
+    format!("at element {}: ", path.join("."))
+}
+
+impl MatchError {
+    pub fn new<F: LurkField>(store: &Store<F>, ptr: &Ptr<F>, expected: impl Into<String>) -> Self {
+        Self {
+            path: vec![],
+            expected: expected.into(),
+            got: ptr.fmt_to_string(store, initial_lurk_state()),
+        }
+    }
+
+    /// The error, for a value found as the `index`th element of a list
+    fn at(mut self, index: usize) -> Self {
+        self.path.insert(0, index);
+        self
+    }
+}
+
+/// A Rust value that can be read from a Lurk value
+pub trait FromLurk<F: LurkField>: Sized {
+    fn from_lurk(store: &Store<F>, ptr: &Ptr<F>) -> Result<Self, MatchError>;
+}
+
+/// A tuple that can be read from the first elements of a Lurk list, along
+/// with the rest of the list
+pub trait ListPrefix<F: LurkField> {
+    /// The elements of the tuple followed by the rest of the list
+    type Output;
+
+    fn read_prefix(store: &Store<F>, ptr: &Ptr<F>) -> Result<Self::Output, MatchError>;
+}
+
+impl<F: LurkField> FromLurk<F> for Ptr<F> {
+    fn from_lurk(_store: &Store<F>, ptr: &Ptr<F>) -> Result<Self, MatchError> {
+        Ok(*ptr)
+    }
+}
+
+impl<F: LurkField> FromLurk<F> for Num<F> {
+    fn from_lurk(store: &Store<F>, ptr: &Ptr<F>) -> Result<Self, MatchError> {
+        match ptr.tag {
+            ExprTag::Num => store.fetch_num(ptr).cloned(),
+            _ => None,
+        }
+        .ok_or_else(|| MatchError::new(store, ptr, "a number"))
+    }
+}
+
+impl<F: LurkField> FromLurk<F> for u64 {
+    /// Reads `u64`s as well as the numbers that fit in 64 bits
+    fn from_lurk(store: &Store<F>, ptr: &Ptr<F>) -> Result<Self, MatchError> {
+        match ptr.tag {
+            ExprTag::U64 => store.fetch_uint(ptr).map(|UInt::U64(n)| n),
+            ExprTag::Num => match store.fetch_num(ptr) {
+                Some(Num::U64(n)) => Some(*n),
+                Some(Num::Scalar(f)) => f.to_u64(),
+                None => None,
+            },
+            _ => None,
+        }
+        .ok_or_else(|| MatchError::new(store, ptr, "a number of 64 bits"))
+    }
+}
+
+impl<F: LurkField> FromLurk<F> for String {
+    fn from_lurk(store: &Store<F>, ptr: &Ptr<F>) -> Result<Self, MatchError> {
+        match ptr.tag {
+            ExprTag::Str => store.fetch_string(ptr),
+            _ => None,
+        }
+        .ok_or_else(|| MatchError::new(store, ptr, "a string"))
+    }
+}
+
+impl<F: LurkField> FromLurk<F> for char {
+    fn from_lurk(store: &Store<F>, ptr: &Ptr<F>) -> Result<Self, MatchError> {
+        match ptr.tag {
+            ExprTag::Char => store.fetch_char(ptr),
+            _ => None,
+        }
+        .ok_or_else(|| MatchError::new(store, ptr, "a character"))
+    }
+}
+
+impl<F: LurkField> FromLurk<F> for Symbol {
+    fn from_lurk(store: &Store<F>, ptr: &Ptr<F>) -> Result<Self, MatchError> {
+        match ptr.tag {
+            ExprTag::Sym | ExprTag::Key | ExprTag::Nil => store.fetch_symbol(ptr),
+            _ => None,
+        }
+        .ok_or_else(|| MatchError::new(store, ptr, "a symbol"))
+    }
+}
+
+impl<F: LurkField> FromLurk<F> for bool {
+    /// Reads `nil` as `false` and `t` as `true`
+    fn from_lurk(store: &Store<F>, ptr: &Ptr<F>) -> Result<Self, MatchError> {
+        match ptr.tag {
+            ExprTag::Nil => Ok(false),
+            ExprTag::Sym if store.fetch_symbol(ptr) == Some(lurk_sym("t")) => Ok(true),
+            _ => Err(MatchError::new(store, ptr, "t or nil")),
+        }
+    }
+}
+
+impl<F: LurkField, T: FromLurk<F>> FromLurk<F> for Vec<T> {
+    /// Reads proper lists of any length
+    fn from_lurk(store: &Store<F>, ptr: &Ptr<F>) -> Result<Self, MatchError> {
+        let mut elements = Elements::new(store, ptr, "a list");
+        let mut vec = vec![];
+        while elements.list.tag == ExprTag::Cons {
+            vec.push(elements.read()?);
+        }
+        elements.end()?;
+        Ok(vec)
+    }
+}
+
+/// The elements of a list, read one at a time
+struct Elements<'a, F: LurkField> {
+    store: &'a Store<F>,
+    whole: Ptr<F>,
+    list: Ptr<F>,
+    index: usize,
+    expected: String,
+}
+
+impl<'a, F: LurkField> Elements<'a, F> {
+    /// Starts reading the list `ptr`, which the errors say was expected to be
+    /// `expected`
+    fn new(store: &'a Store<F>, ptr: &Ptr<F>, expected: impl Into<String>) -> Self {
+        Self {
+            store,
+            whole: *ptr,
+            list: *ptr,
+            index: 0,
+            expected: expected.into(),
+        }
+    }
+
+    /// Reads the next element
+    fn read<T: FromLurk<F>>(&mut self) -> Result<T, MatchError> {
+        if self.list.tag != ExprTag::Cons {
+            return Err(MatchError::new(self.store, &self.whole, &self.expected));
+        }
+        let (car, cdr) = self
+            .store
+            .car_cdr(&self.list)
+            .map_err(|_| MatchError::new(self.store, &self.whole, &self.expected))?;
+        let element = T::from_lurk(self.store, &car).map_err(|e| e.at(self.index))?;
+        self.index += 1;
+        self.list = cdr;
+        Ok(element)
+    }
+
+    /// Checks that there are no elements left
+    fn end(self) -> Result<(), MatchError> {
+        if self.list.tag != ExprTag::Nil {
+            return Err(MatchError::new(self.store, &self.whole, self.expected));
+        }
+        Ok(())
+    }
+
+    /// The rest of the list, which may be improper
+    fn rest(self) -> Ptr<F> {
+        self.list
+    }
+}
+
+macro_rules! impl_from_lurk_for_tuple {
+    ($len:literal: $($t:ident),+) => {
+        impl<F: LurkField, $($t: FromLurk<F>),+> FromLurk<F> for ($($t,)+) {
+            /// Reads lists of as many elements as the tuple has
+            fn from_lurk(store: &Store<F>, ptr: &Ptr<F>) -> Result<Self, MatchError> {
+                let expected = concat!("a list of ", $len, " elements");
+                let mut elements = Elements::new(store, ptr, expected);
+                let tuple = ($(elements.read::<$t>()?,)+);
+                elements.end()?;
+                Ok(tuple)
+            }
+        }
+
+        impl<F: LurkField, $($t: FromLurk<F>),+> ListPrefix<F> for ($($t,)+) {
+            type Output = ($($t,)+ Ptr<F>);
+
+            fn read_prefix(store: &Store<F>, ptr: &Ptr<F>) -> Result<Self::Output, MatchError> {
+                let expected = concat!("a list of at least ", $len, " elements");
+                let mut elements = Elements::new(store, ptr, expected);
+                Ok(($(elements.read::<$t>()?,)+ elements.rest()))
+            }
+        }
+    };
+}
+
+impl_from_lurk_for_tuple!(1: A);
+impl_from_lurk_for_tuple!(2: A, B);
+impl_from_lurk_for_tuple!(3: A, B, C);
+impl_from_lurk_for_tuple!(4: A, B, C, D);
+impl_from_lurk_for_tuple!(5: A, B, C, D, E);
+impl_from_lurk_for_tuple!(6: A, B, C, D, E, G);
+
+/// Reads a Lurk value of a store into Rust values, returning a `MatchError`
+/// if it isn't of the expected shape:
+///
+/// - `ptr_match!(store, ptr, T)` reads a `T: FromLurk`
+/// - `ptr_match!(store, ptr, [A, B])` reads a list of exactly two elements
+///   into an `(A, B)`, lists of up to six elements being supported
+/// - `ptr_match!(store, ptr, [A, B; ..])` reads a list of at least two
+///   elements into an `(A, B, Ptr)`, the pointer being the rest of the list,
+///   so `[A; ..]` reads a cons into its car and cdr
+#[macro_export]
+macro_rules! ptr_match {
+    ($store:expr, $ptr:expr, [$($t:ty),+ ; ..]) => {
+        <($($t,)+) as $crate::pattern::ListPrefix<_>>::read_prefix($store, $ptr)
+    };
+    ($store:expr, $ptr:expr, [$($t:ty),+ $(,)?]) => {
+        <($($t,)+) as $crate::pattern::FromLurk<_>>::from_lurk($store, $ptr)
+    };
+    ($store:expr, $ptr:expr, $t:ty) => {
+        <$t as $crate::pattern::FromLurk<_>>::from_lurk($store, $ptr)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use blstrs::Scalar as Fr;
+
+    use super::*;
+    use crate::state::user_sym;
+
+    #[test]
+    fn values_are_destructured() {
+        let store = &mut Store::<Fr>::default();
+        let ptr = store.read("(1 \"two\" #\\3 (4u64 t) . nil)").unwrap();
+        let (one, two, three, (four, t)) =
+            ptr_match!(store, &ptr, [Num<Fr>, String, char, (u64, bool)]).unwrap();
+        assert_eq!(one.into_scalar(), Fr::from(1));
+        assert_eq!((two.as_str(), three, four, t), ("two", '3', 4, true));
+
+        let (one, rest) = ptr_match!(store, &ptr, [u64; ..]).unwrap();
+        assert_eq!(one, 1);
+        assert_eq!(
+            ptr_match!(store, &rest, [Ptr<Fr>; ..]).unwrap().1.tag,
+            ExprTag::Cons
+        );
+
+        let nums = store.read("(1 2 3)").unwrap();
+        assert_eq!(ptr_match!(store, &nums, Vec<u64>).unwrap(), [1, 2, 3]);
+        let sym = store.read("foo").unwrap();
+        assert_eq!(ptr_match!(store, &sym, Symbol).unwrap(), user_sym("foo"));
+    }
+
+    #[test]
+    fn mismatches_are_located() {
+        let store = &mut Store::<Fr>::default();
+        let ptr = store.read("(1 (2 \"three\"))").unwrap();
+        let err = ptr_match!(store, &ptr, [u64, (u64, u64)]).unwrap_err();
+        assert_eq!(err.path, [1, 1]);
+        assert_eq!(
+            err.to_string(),
+            "at element 1.1: expected a number of 64 bits, got \"three\""
+        );
+
+        let err = ptr_match!(store, &ptr, [u64]).unwrap_err();
+        assert!(err.path.is_empty());
+        assert_eq!(err.expected, "a list of 1 elements");
+
+        let err = ptr_match!(store, &ptr, [u64, u64, u64; ..]).unwrap_err();
+        assert_eq!(err.expected, "a list of at least 3 elements");
+    }
+}
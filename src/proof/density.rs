@@ -0,0 +1,202 @@
+//! What each folding step of a Nova proof costs the prover.
+//!
+//! Every step folds the same circuit, the step circuit of `reduction_count`
+//! frames augmented with Nova's verifier circuit, so the work of a step follows
+//! from the shape of that circuit: the size of its witness, its number of
+//! constraints and the number of non-zero entries of its A, B and C matrices,
+//! which the prover commits to and multiplies by. A larger reduction count
+//! means fewer, larger steps, and a `StepReport` tells how much larger.
+
+use bellpepper_core::{ConstraintSystem, Index, LinearCombination, SynthesisError, Variable};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::field::LurkField;
+
+/// The shape of a folding step, as computed by `NovaProver::step_report`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StepReport {
+    /// The number of frames reduced by each step
+    pub reduction_count: usize,
+    /// The number of variables of the step circuit's witness
+    pub witness_size: usize,
+    /// The number of constraints of the step circuit
+    pub constraints: usize,
+    /// The number of non-zero entries of the step circuit's A, B and C matrices
+    pub nonzero_entries: [usize; 3],
+    /// The number of constraints of the circuit Nova folds, which augments the
+    /// step circuit with its verifier circuit
+    pub folded_constraints: usize,
+    /// The number of variables of the circuit Nova folds
+    pub folded_variables: usize,
+}
+
+impl StepReport {
+    /// The number of non-zero entries of all three matrices
+    pub fn nonzero(&self) -> usize {
+        self.nonzero_entries.iter().sum()
+    }
+
+    /// The number of constraints of the step circuit per frame it reduces
+    pub fn constraints_per_reduction(&self) -> usize {
+        self.constraints / self.reduction_count.max(1)
+    }
+}
+
+impl fmt::Display for StepReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c] = self.nonzero_entries;
+        writeln!(f, "step of {} reductions:", self.reduction_count)?;
+        writeln!(f, "  witness size:     {}", self.witness_size)?;
+        writeln!(
+            f,
+            "  constraints:      {} ({} per reduction)",
+            self.constraints,
+            self.constraints_per_reduction()
+        )?;
+        writeln!(
+            f,
+            "  non-zero entries: {} (A: {a}, B: {b}, C: {c})",
+            self.nonzero()
+        )?;
+        write!(
+            f,
+            "  folded circuit:   {} constraints, {} variables",
+            self.folded_constraints, self.folded_variables
+        )
+    }
+}
+
+/// How far proving has got, reported after each folding step
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProveProgress {
+    /// The number of steps folded so far
+    pub steps_done: usize,
+    /// The number of steps of the proof
+    pub num_steps: usize,
+    /// The shape of each step
+    pub step: StepReport,
+}
+
+impl ProveProgress {
+    /// The number of constraints folded so far
+    pub fn constraints_done(&self) -> usize {
+        self.steps_done * self.step.folded_constraints
+    }
+}
+
+/// A constraint system that counts variables, constraints and the non-zero
+/// entries of the constraints' linear combinations
+#[derive(Default)]
+pub(crate) struct DensityCounter {
+    inputs: usize,
+    aux: usize,
+    constraints: usize,
+    nonzero_entries: [usize; 3],
+}
+
+impl DensityCounter {
+    pub(crate) fn report(
+        &self,
+        reduction_count: usize,
+        folded_constraints: usize,
+        folded_variables: usize,
+    ) -> StepReport {
+        StepReport {
+            reduction_count,
+            witness_size: self.aux,
+            constraints: self.constraints,
+            nonzero_entries: self.nonzero_entries,
+            folded_constraints,
+            folded_variables,
+        }
+    }
+}
+
+fn nonzero<F: LurkField>(lc: &LinearCombination<F>) -> usize {
+    lc.iter().filter(|(_, coeff)| **coeff != F::ZERO).count()
+}
+
+impl<F: LurkField> ConstraintSystem<F> for DensityCounter {
+    type Root = Self;
+
+    fn alloc<V, A, AR>(&mut self, _annotation: A, _f: V) -> Result<Variable, SynthesisError>
+    where
+        V: FnOnce() -> Result<F, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.aux += 1;
+        Ok(Variable::new_unchecked(Index::Aux(self.aux - 1)))
+    }
+
+    fn alloc_input<V, A, AR>(&mut self, _annotation: A, _f: V) -> Result<Variable, SynthesisError>
+    where
+        V: FnOnce() -> Result<F, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        // the first input is the constant one
+        self.inputs += 1;
+        Ok(Variable::new_unchecked(Index::Input(self.inputs)))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, _annotation: A, a: LA, b: LB, c: LC)
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+        LA: FnOnce(LinearCombination<F>) -> LinearCombination<F>,
+        LB: FnOnce(LinearCombination<F>) -> LinearCombination<F>,
+        LC: FnOnce(LinearCombination<F>) -> LinearCombination<F>,
+    {
+        self.constraints += 1;
+        self.nonzero_entries[0] += nonzero(&a(LinearCombination::zero()));
+        self.nonzero_entries[1] += nonzero(&b(LinearCombination::zero()));
+        self.nonzero_entries[2] += nonzero(&c(LinearCombination::zero()));
+    }
+
+    fn push_namespace<NR, N>(&mut self, _name_fn: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn pop_namespace(&mut self) {}
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bellpepper_core::num::AllocatedNum;
+    use blstrs::Scalar as Fr;
+
+    use super::*;
+
+    #[test]
+    fn nonzero_entries_are_counted() {
+        let mut cs = DensityCounter::default();
+        let x = AllocatedNum::<Fr>::alloc(cs.namespace(|| "x"), || Ok(Fr::from(3))).unwrap();
+        let y = x.square(cs.namespace(|| "y")).unwrap();
+        ConstraintSystem::<Fr>::enforce(
+            &mut cs,
+            || "x * (x + y) = x + y",
+            |lc| lc + x.get_variable(),
+            |lc| lc + x.get_variable() + y.get_variable(),
+            |lc| lc + x.get_variable() + y.get_variable(),
+        );
+
+        let report = cs.report(2, 10, 20);
+        assert_eq!(report.witness_size, 2);
+        assert_eq!(report.constraints, 2);
+        assert_eq!(report.constraints_per_reduction(), 1);
+        assert_eq!(report.nonzero_entries, [2, 3, 3]);
+        assert_eq!(report.nonzero(), 8);
+        assert!(report
+            .to_string()
+            .contains("non-zero entries: 8 (A: 2, B: 3, C: 3)"));
+    }
+}
@@ -5,10 +5,16 @@
 //! has two instantiations:
 //! - the Groth16/SnarkPack proving system, implemented in the `groth16` module
 //! - the Nova proving system, implemented in the `nova` module.
+/// The cost of the folding steps of Nova proofs.
+pub mod density;
 /// An adapter to a Groth16 proving system implementation.
 pub mod groth16;
 /// An adapter to a Nova proving system implementation.
 pub mod nova;
+/// Nova proofs split into segments with different reduction counts.
+pub mod segmented;
+/// Keeps the prover under a memory limit.
+pub mod watchdog;
 
 use crate::circuit::MultiFrame;
 use crate::coprocessor::Coprocessor;
@@ -1,4 +1,8 @@
 #![allow(non_snake_case)]
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Mutex;
 
 use abomonation::Abomonation;
@@ -20,6 +24,7 @@ use nova::{
 use pasta_curves::{pallas, vesta};
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::sync::Arc;
 
 use crate::circuit::{
@@ -33,11 +38,17 @@ use crate::config::CONFIG;
 
 use crate::coprocessor::Coprocessor;
 use crate::error::ProofError;
-use crate::eval::{lang::Lang, Evaluator, Frame, Witness, IO};
+use crate::eval::{empty_emit_log, lang::Lang, Evaluator, Frame, Witness, IO};
 use crate::field::LurkField;
-use crate::proof::{Prover, PublicParameters};
+use crate::proof::{
+    density::{DensityCounter, ProveProgress, StepReport},
+    watchdog::{MemoryWatchdog, Pressure},
+    Prover, PublicParameters,
+};
 use crate::ptr::Ptr;
-use crate::store::Store;
+use crate::store::{self, Store};
+use crate::tag::Tag;
+use crate::z_ptr::ZExprPtr;
 
 /// This trait defines most of the requirements for programming generically over the supported Nova curve cycles
 /// (currently Pallas/Vesta and BN254/Grumpkin). It being pegged on the `LurkField` trait encodes that we do
@@ -125,6 +136,41 @@ pub type C2<F> = TrivialTestCircuit<<G2<F> as Group>::Scalar>;
 /// Type alias for Nova Public Parameters with the curve cycle types defined above.
 pub type NovaPublicParams<'a, F, C> = nova::PublicParams<G1<F>, G2<F>, C1<'a, F, C>, C2<F>>;
 
+/// The steps of a recursive proof folded before the prover reached its memory
+/// limit, to be resumed with `Proof::resume_recursively`
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct Checkpoint<'a, F: CurveCycleEquipped, C: Coprocessor<F>>
+where
+    <<G1<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+    <<G2<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+{
+    /// The number of steps folded into `snark`
+    pub steps_done: usize,
+    /// The recursive SNARK of those steps
+    pub snark: Box<RecursiveSNARK<G1<F>, G2<F>, C1<'a, F, C>, C2<F>>>,
+}
+
+impl<'a, F: CurveCycleEquipped, C: Coprocessor<F>> Checkpoint<'a, F, C>
+where
+    <<G1<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+    <<G2<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+{
+    /// Writes the checkpoint to a file
+    pub fn write(&self, path: &Path) -> Result<(), ProofError> {
+        let file = File::create(path).map_err(|e| ProofError::Checkpoint(e.to_string()))?;
+        bincode::serialize_into(BufWriter::new(file), self)
+            .map_err(|e| ProofError::Checkpoint(e.to_string()))
+    }
+
+    /// Reads a checkpoint from a file written by `write`
+    pub fn read(path: &Path) -> Result<Self, ProofError> {
+        let file = File::open(path).map_err(|e| ProofError::Checkpoint(e.to_string()))?;
+        bincode::deserialize_from(BufReader::new(file))
+            .map_err(|e| ProofError::Checkpoint(e.to_string()))
+    }
+}
+
 /// A struct that contains public parameters for the Nova proving system.
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(bound = "")]
@@ -228,6 +274,19 @@ where
 {
 }
 
+impl<'a, F: CurveCycleEquipped, C: Coprocessor<F>> PublicParams<'a, F, C>
+where
+    <<G1<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+    <<G2<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+{
+    /// The hex-encoded SHA-256 of the verifier key, which tells the parameters
+    /// proofs are verified with apart
+    pub fn vk_digest(&self) -> String {
+        let vk = bincode::serialize(&self.vk).expect("verifier keys can be serialized");
+        hex::encode(Sha256::digest(vk))
+    }
+}
+
 impl<'a, F: CurveCycleEquipped, C: Coprocessor<F> + 'a> Prover<'a, '_, F, C> for NovaProver<F, C>
 where
     <<G1<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
@@ -272,6 +331,21 @@ where
         Ok(frames)
     }
 
+    /// The shape of the folding steps of proofs made with `pp`: that of the
+    /// step circuit, measured by synthesizing a blank one, and that of the
+    /// circuit Nova folds
+    pub fn step_report(&self, pp: &PublicParams<'_, F, C>) -> Result<StepReport, SynthesisError> {
+        let (circuit, _) = C1::circuits(self.reduction_count, Arc::new(self.lang.clone()));
+        let mut cs = DensityCounter::default();
+        let z = (0..circuit.arity())
+            .map(|i| AllocatedNum::alloc(cs.namespace(|| format!("z{i}")), || Ok(F::ZERO)))
+            .collect::<Result<Vec<_>, _>>()?;
+        circuit.synthesize(&mut cs, &z)?;
+        let (folded_constraints, _) = pp.pp.num_constraints();
+        let (folded_variables, _) = pp.pp.num_variables();
+        Ok(cs.report(self.reduction_count, folded_constraints, folded_variables))
+    }
+
     /// Proves the computation given the public parameters, frames, and store.
     pub fn prove<'a>(
         &'a self,
@@ -280,13 +354,56 @@ where
         store: &'a mut Store<F>,
         lang: Arc<Lang<F, C>>,
     ) -> Result<(Proof<'_, F, C>, Vec<F>, Vec<F>, usize), ProofError> {
-        let z0 = frames[0].input.to_vector(store)?;
-        let zi = frames.last().unwrap().output.to_vector(store)?;
+        self.prove_reporting(pp, frames, store, lang, &mut |_, _| ())
+    }
+
+    /// Like `prove`, but calls `on_progress` after each folding step
+    pub fn prove_with_progress<'a>(
+        &'a self,
+        pp: &'a PublicParams<'_, F, C>,
+        frames: &[Frame<IO<F>, Witness<F>, C>],
+        store: &'a mut Store<F>,
+        lang: Arc<Lang<F, C>>,
+        mut on_progress: impl FnMut(ProveProgress),
+    ) -> Result<(Proof<'_, F, C>, Vec<F>, Vec<F>, usize), ProofError> {
+        let step = self.step_report(pp)?;
+        let mut on_step = |steps_done, num_steps| {
+            on_progress(ProveProgress {
+                steps_done,
+                num_steps,
+                step,
+            })
+        };
+        self.prove_reporting(pp, frames, store, lang, &mut on_step)
+    }
+
+    fn prove_reporting<'a>(
+        &'a self,
+        pp: &'a PublicParams<'_, F, C>,
+        frames: &[Frame<IO<F>, Witness<F>, C>],
+        store: &'a mut Store<F>,
+        lang: Arc<Lang<F, C>>,
+        on_step: &mut dyn FnMut(usize, usize),
+    ) -> Result<(Proof<'_, F, C>, Vec<F>, Vec<F>, usize), ProofError> {
+        // a broken chain would otherwise only show up as an unsatisfied circuit
+        #[cfg(debug_assertions)]
+        if let Err(e) = Frame::check_io_chain(frames) {
+            panic!("inconsistent frames: {e}");
+        }
+        let (z0, zi) = public_io(frames, empty_emit_log(store), store)?;
         let circuits = MultiFrame::from_frames(self.reduction_count(), frames, store, lang.clone());
 
         let num_steps = circuits.len();
-        let proof =
-            Proof::prove_recursively(pp, store, &circuits, self.reduction_count, z0.clone(), lang)?;
+        let proof = Proof::prove_recursively_from(
+            pp,
+            store,
+            &circuits,
+            self.reduction_count,
+            z0.clone(),
+            lang,
+            None,
+            &mut |steps_done| on_step(steps_done, num_steps),
+        )?;
 
         Ok((proof, z0, zi, num_steps))
     }
@@ -306,16 +423,44 @@ where
     }
 }
 
+/// The public IO of a proof of `frames`, which start with the emit log `log`:
+/// the input and the output of the frames, each followed by its emit log
+pub fn public_io<F: LurkField, C: Coprocessor<F>>(
+    frames: &[Frame<IO<F>, Witness<F>, C>],
+    log: ZExprPtr<F>,
+    store: &Store<F>,
+) -> Result<(Vec<F>, Vec<F>), store::Error> {
+    let mut z0 = frames[0].input.to_vector(store)?;
+    z0.extend([log.tag().to_field(), *log.value()]);
+    let log = frames
+        .iter()
+        .try_fold(log, |log, frame| frame.output.extend_emit_log(log, store))?;
+    let mut zi = frames.last().unwrap().output.to_vector(store)?;
+    zi.extend([log.tag().to_field(), *log.value()]);
+    Ok((z0, zi))
+}
+
 impl<'a, F: LurkField, C: Coprocessor<F>> MultiFrame<'a, F, C> {
+    /// The emit log after the frames, computed out of the circuit
+    pub(crate) fn output_emit_log(&self) -> Option<ZExprPtr<F>> {
+        let store = self.store?;
+        self.frames
+            .as_ref()?
+            .iter()
+            .try_fold(self.emit_log?, |log, frame| {
+                frame.output?.extend_emit_log(log, store).ok()
+            })
+    }
+
     fn compute_witness(&self, s: &Store<F>) -> WitnessCS<F> {
         let mut wcs = WitnessCS::new();
 
         let input = self.input.unwrap();
 
-        use crate::tag::Tag;
         let expr = s.hash_expr(&input.expr).unwrap();
         let env = s.hash_expr(&input.env).unwrap();
         let cont = s.hash_cont(&input.cont).unwrap();
+        let log = self.emit_log.expect("emit log missing");
 
         let z_scalar = vec![
             expr.tag().to_field(),
@@ -324,6 +469,8 @@ impl<'a, F: LurkField, C: Coprocessor<F>> MultiFrame<'a, F, C> {
             *env.value(),
             cont.tag().to_field(),
             *cont.value(),
+            log.tag().to_field(),
+            *log.value(),
         ];
 
         let mut bogus_cs = WitnessCS::<F>::new();
@@ -340,7 +487,7 @@ impl<'a, F: LurkField, C: Coprocessor<F>> MultiFrame<'a, F, C> {
 
 impl<'a, F: LurkField, C: Coprocessor<F>> StepCircuit<F> for MultiFrame<'a, F, C> {
     fn arity(&self) -> usize {
-        6
+        8
     }
 
     #[tracing::instrument(skip_all, name = "<MultiFrame as StepCircuit>::synthesize")]
@@ -357,13 +504,19 @@ impl<'a, F: LurkField, C: Coprocessor<F>> StepCircuit<F> for MultiFrame<'a, F, C
         if cs.is_witness_generator() {
             if let Some(w) = &self.cached_witness {
                 let aux = w.aux_slice();
-                let end = aux.len() - 6;
                 let inputs = &w.inputs_slice()[1..];
 
                 cs.extend_aux(aux);
                 cs.extend_inputs(inputs);
 
-                let scalars = &aux[end..];
+                let output = self.output.ok_or(SynthesisError::AssignmentMissing)?;
+                let log = self
+                    .output_emit_log()
+                    .ok_or(SynthesisError::AssignmentMissing)?;
+                let mut scalars = output
+                    .to_vector(self.get_store())
+                    .map_err(|_| SynthesisError::AssignmentMissing)?;
+                scalars.extend([log.tag().to_field(), *log.value()]);
 
                 let allocated = {
                     let mut bogus_cs = WitnessCS::new();
@@ -380,15 +533,18 @@ impl<'a, F: LurkField, C: Coprocessor<F>> StepCircuit<F> for MultiFrame<'a, F, C
         let input_expr = AllocatedPtr::by_index(0, z);
         let input_env = AllocatedPtr::by_index(1, z);
         let input_cont = AllocatedContPtr::by_index(2, z);
+        let input_log = Some(AllocatedPtr::by_index(3, z));
 
         let count = self.count;
 
-        let (new_expr, new_env, new_cont) = match self.frames.as_ref() {
+        let (new_expr, new_env, new_cont, new_log) = match self.frames.as_ref() {
             Some(frames) => {
                 let s = self.store.expect("store missing");
                 let g = GlobalAllocations::new(&mut cs.namespace(|| "global_allocations"), s)?;
 
-                self.synthesize_frames(cs, s, input_expr, input_env, input_cont, frames, &g)
+                self.synthesize_frames(
+                    cs, s, input_expr, input_env, input_cont, input_log, frames, &g,
+                )
             }
             None => {
                 assert!(self.store.is_none());
@@ -398,9 +554,12 @@ impl<'a, F: LurkField, C: Coprocessor<F>> StepCircuit<F> for MultiFrame<'a, F, C
 
                 let g = GlobalAllocations::new(&mut cs.namespace(|| "global_allocations"), &s)?;
 
-                self.synthesize_frames(cs, &s, input_expr, input_env, input_cont, &frames, &g)
+                self.synthesize_frames(
+                    cs, &s, input_expr, input_env, input_cont, input_log, &frames, &g,
+                )
             }
         };
+        let new_log = new_log.expect("emit log missing");
 
         Ok(vec![
             new_expr.tag().clone(),
@@ -409,6 +568,8 @@ impl<'a, F: LurkField, C: Coprocessor<F>> StepCircuit<F> for MultiFrame<'a, F, C
             new_env.hash().clone(),
             new_cont.tag().clone(),
             new_cont.hash().clone(),
+            new_log.tag().clone(),
+            new_log.hash().clone(),
         ])
     }
 }
@@ -419,7 +580,10 @@ where
     <<G2<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
 {
     /// Proves the computation recursively, generating a recursive SNARK proof.
-    #[tracing::instrument(skip_all, name = "Proof::prove_recursively")]
+    ///
+    /// If a memory limit is configured, proving backs off as memory gets close
+    /// to it and stops with `ProofError::MemoryLimit` when it's reached. See
+    /// [`crate::proof::watchdog`].
     pub fn prove_recursively(
         pp: &'a PublicParams<'_, F, C>,
         store: &'a Store<F>,
@@ -427,6 +591,55 @@ where
         num_iters_per_step: usize,
         z0: Vec<F>,
         lang: Arc<Lang<F, C>>,
+    ) -> Result<Self, ProofError> {
+        Self::prove_recursively_from(
+            pp,
+            store,
+            circuits,
+            num_iters_per_step,
+            z0,
+            lang,
+            None,
+            &mut |_| (),
+        )
+    }
+
+    /// Resumes proving recursively from a checkpoint saved at the memory
+    /// limit. `circuits` are all the circuits of the computation, including
+    /// the ones already folded into the checkpoint.
+    pub fn resume_recursively(
+        pp: &'a PublicParams<'_, F, C>,
+        store: &'a Store<F>,
+        circuits: &[C1<'a, F, C>],
+        num_iters_per_step: usize,
+        z0: Vec<F>,
+        lang: Arc<Lang<F, C>>,
+        checkpoint: Checkpoint<'a, F, C>,
+    ) -> Result<Self, ProofError> {
+        Self::prove_recursively_from(
+            pp,
+            store,
+            circuits,
+            num_iters_per_step,
+            z0,
+            lang,
+            Some(checkpoint),
+            &mut |_| (),
+        )
+    }
+
+    /// Calls `on_step` with the number of steps done after folding each one
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument(skip_all, name = "Proof::prove_recursively")]
+    fn prove_recursively_from(
+        pp: &'a PublicParams<'_, F, C>,
+        store: &'a Store<F>,
+        circuits: &[C1<'a, F, C>],
+        num_iters_per_step: usize,
+        z0: Vec<F>,
+        lang: Arc<Lang<F, C>>,
+        checkpoint: Option<Checkpoint<'a, F, C>>,
+        on_step: &mut dyn FnMut(usize),
     ) -> Result<Self, ProofError> {
         assert!(!circuits.is_empty());
         assert_eq!(circuits[0].arity(), z0.len());
@@ -445,8 +658,23 @@ where
 
         tracing::debug!("circuits.len: {}", circuits.len());
 
-        // produce a recursive SNARK
-        let mut recursive_snark: Option<RecursiveSNARK<G1<F>, G2<F>, C1<'a, F, C>, C2<F>>> = None;
+        // produce a recursive SNARK, or carry on with the checkpoint's
+        let (steps_done, mut recursive_snark) = match checkpoint {
+            Some(Checkpoint { steps_done, snark }) => {
+                if steps_done >= circuits.len() {
+                    return Err(ProofError::Checkpoint(format!(
+                        "checkpoint is after {steps_done} steps, but there are only {}",
+                        circuits.len()
+                    )));
+                }
+                (steps_done, Some(*snark))
+            }
+            None => (0, None),
+        };
+        let circuits = &circuits[steps_done..];
+
+        let watchdog = MemoryWatchdog::from_config();
+        let pressure = || watchdog.map_or(Pressure::Normal, |w| w.pressure());
 
         // the shadowing here is voluntary
         let recursive_snark = if CONFIG.parallelism.recursive_steps.is_parallel() {
@@ -454,12 +682,18 @@ where
                 .iter()
                 .map(|c| Mutex::new(c.clone()))
                 .collect::<Vec<_>>();
+            let stop = AtomicBool::new(false);
 
             crossbeam::thread::scope(|s| {
                 s.spawn(|_| {
                     // Skip the very first circuit's witness, so `prove_step` can begin immediately.
                     // That circuit's witness will not be cached and will just be computed on-demand.
+                    // The witnesses cached ahead are where the memory goes, so under pressure the
+                    // steps are left to compute theirs on demand too.
                     cc.par_iter().skip(1).for_each(|mf| {
+                        if stop.load(Ordering::Relaxed) || pressure() > Pressure::Normal {
+                            return;
+                        }
                         let witness = {
                             let mf1 = mf.lock().unwrap();
                             mf1.compute_witness(store)
@@ -470,8 +704,12 @@ where
                     });
                 });
 
-                for circuit_primary in cc.iter() {
-                    let circuit_primary = circuit_primary.lock().unwrap();
+                for (i, circuit_primary) in cc.iter().enumerate() {
+                    if pressure() == Pressure::Critical {
+                        stop.store(true, Ordering::Relaxed);
+                        return Err(Self::suspend(steps_done + i, recursive_snark));
+                    }
+                    let mut circuit_primary = circuit_primary.lock().unwrap();
                     assert_eq!(
                         num_iters_per_step,
                         circuit_primary.frames.as_ref().unwrap().len()
@@ -495,13 +733,19 @@ where
                             z0_secondary.clone(),
                         )
                         .expect("failure to prove Nova step");
+                    // the step is folded, its witness won't be used again
+                    circuit_primary.cached_witness = None;
                     recursive_snark = Some(r_snark);
+                    on_step(steps_done + i + 1);
                 }
-                recursive_snark
+                Ok(recursive_snark)
             })
-            .unwrap()
+            .unwrap()?
         } else {
-            for circuit_primary in circuits.iter() {
+            for (i, circuit_primary) in circuits.iter().enumerate() {
+                if pressure() == Pressure::Critical {
+                    return Err(Self::suspend(steps_done + i, recursive_snark));
+                }
                 assert_eq!(
                     num_iters_per_step,
                     circuit_primary.frames.as_ref().unwrap().len()
@@ -511,10 +755,12 @@ where
                     use bellpepper_core::test_cs::TestConstraintSystem;
                     let mut cs = TestConstraintSystem::<<G1<F> as Group>::Scalar>::new();
 
-                    let zi = circuit_primary.frames.as_ref().unwrap()[0]
+                    let mut zi = circuit_primary.frames.as_ref().unwrap()[0]
                         .input
                         .unwrap()
                         .to_vector(store)?;
+                    let log = circuit_primary.emit_log.unwrap();
+                    zi.extend([log.tag().to_field(), *log.value()]);
                     let zi_allocated: Vec<_> = zi
                         .iter()
                         .enumerate()
@@ -547,6 +793,7 @@ where
                     )
                     .expect("failure to prove Nova step");
                 recursive_snark = Some(r_snark);
+                on_step(steps_done + i + 1);
             }
             recursive_snark
         };
@@ -554,6 +801,32 @@ where
         Ok(Self::Recursive(Box::new(recursive_snark.unwrap())))
     }
 
+    /// Stops proving at the memory limit, after saving the steps folded so far
+    /// if there is a checkpoint path to save them to
+    fn suspend(
+        steps_done: usize,
+        recursive_snark: Option<RecursiveSNARK<G1<F>, G2<F>, C1<'a, F, C>, C2<F>>>,
+    ) -> ProofError {
+        tracing::warn!("memory limit reached after {steps_done} steps");
+        let checkpoint = match (recursive_snark, &CONFIG.memory.checkpoint_path) {
+            (Some(snark), Some(path)) => {
+                let checkpoint = Checkpoint {
+                    steps_done,
+                    snark: Box::new(snark),
+                };
+                if let Err(e) = checkpoint.write(path) {
+                    return e;
+                }
+                Some(path.clone())
+            }
+            _ => None,
+        };
+        ProofError::MemoryLimit {
+            steps_done,
+            checkpoint,
+        }
+    }
+
     /// Compresses the proof using a (Spartan) Snark (finishing step)
     pub fn compress(self, pp: &'a PublicParams<'_, F, C>) -> Result<Self, ProofError> {
         match &self {
@@ -613,6 +886,7 @@ pub mod tests {
     use crate::proof::Provable;
     use crate::ptr::ContPtr;
     use crate::tag::{Op, Op1, Op2};
+    use crate::z_expr::ZExpr;
 
     use bellpepper::util_cs::witness_cs::WitnessCS;
     use bellpepper::util_cs::{metric_cs::MetricCS, Comparable};
@@ -1217,6 +1491,39 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn test_emit_log_in_step_io() {
+        let s = &mut Store::<Fr>::default();
+        let lang = Arc::new(Lang::<Fr, Coproc<Fr>>::new());
+        let expr = s.read("(begin (emit 1) (emit 2) 3)").unwrap();
+        let nova_prover = NovaProver::<Fr, Coproc<Fr>>::new(2, (*lang).clone());
+        let frames = nova_prover
+            .get_evaluation_frames(expr, empty_sym_env(s), s, 100, &lang)
+            .unwrap();
+        let (z0, zi) = public_io(&frames, empty_emit_log(s), s).unwrap();
+
+        let (one, two) = (s.num(1), s.num(2));
+        let log = [one, two].iter().fold(empty_emit_log(s), |log, value| {
+            ZExpr::Cons(s.hash_expr(value).unwrap(), log).z_ptr(&s.poseidon_cache)
+        });
+        assert_eq!(zi[6..], [log.tag().to_field(), *log.value()]);
+
+        let mut z = z0;
+        for circuit in MultiFrame::from_frames(2, &frames, s, lang) {
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            let z_allocated = z
+                .iter()
+                .enumerate()
+                .map(|(i, x)| AllocatedNum::alloc(cs.namespace(|| format!("z{i}")), || Ok(*x)))
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            let output = StepCircuit::synthesize(&circuit, &mut cs, &z_allocated).unwrap();
+            assert!(cs.is_satisfied());
+            z = output.iter().map(|x| x.get_value().unwrap()).collect();
+        }
+        assert_eq!(z, zi);
+    }
+
     #[test]
     #[ignore]
     fn test_prove_evaluate() {
@@ -3996,4 +4303,39 @@ pub mod tests {
             None,
         );
     }
+
+    #[test]
+    fn proving_reports_its_progress() {
+        let s = &mut Store::<Fr>::default();
+        let lang = Arc::new(Lang::<Fr, Coproc<Fr>>::new());
+        let prover = NovaProver::<Fr, Coproc<Fr>>::new(2, (*lang).clone());
+        let pp = public_params(2, lang.clone());
+        let step = prover.step_report(&pp).unwrap();
+        assert_eq!(step.reduction_count, 2);
+        assert!(step.constraints < step.folded_constraints);
+        assert!(step.witness_size < step.folded_variables);
+        assert!(step.nonzero() > step.constraints);
+
+        let expr = s.read("(+ 1 (+ 2 3))").unwrap();
+        let env = empty_sym_env(s);
+        let frames = prover
+            .get_evaluation_frames(expr, env, s, 100, &lang)
+            .unwrap();
+        let mut progress = vec![];
+        let (proof, z0, zi, num_steps) = prover
+            .prove_with_progress(&pp, &frames, s, lang.clone(), |p| progress.push(p))
+            .unwrap();
+        assert!(proof.verify(&pp, num_steps, &z0, &zi).unwrap());
+        assert_eq!(progress.len(), num_steps);
+        for (i, p) in progress.iter().enumerate() {
+            assert_eq!(
+                (p.steps_done, p.num_steps, p.step),
+                (i + 1, num_steps, step)
+            );
+        }
+        assert_eq!(
+            progress[num_steps - 1].constraints_done(),
+            num_steps * step.folded_constraints
+        );
+    }
 }
@@ -0,0 +1,331 @@
+//! Nova proofs whose frames are split into segments, each folded with its own
+//! reduction count. A long-running computation can then use a large `rc` for
+//! the bulk of its frames and a small one for the tail, instead of padding the
+//! tail up to a large `rc`. Each segment is an independent Nova proof, and the
+//! segments are tied together by requiring the output of one to be the input
+//! of the next.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+use abomonation::Abomonation;
+use nova::traits::Group;
+use serde::{Deserialize, Serialize};
+
+use crate::circuit::MultiFrame;
+use crate::coprocessor::Coprocessor;
+use crate::error::ProofError;
+use crate::eval::{empty_emit_log, lang::Lang, Frame, Witness, IO};
+use crate::proof::nova::{public_io, CurveCycleEquipped, Proof, PublicParams, G1, G2};
+use crate::store::Store;
+
+/// Public parameters for each of the reduction counts used by a `Schedule`
+pub type SegmentParams<F, C> = HashMap<usize, Arc<PublicParams<'static, F, C>>>;
+
+/// A run of Nova steps folding `rc` frames each
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Segment {
+    /// The reduction count of the segment
+    pub rc: usize,
+    /// The number of steps in the segment. Only the last segment of a
+    /// schedule leaves it open, taking all remaining frames.
+    pub steps: Option<usize>,
+}
+
+/// Describes how the frames of a computation are split into segments
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Schedule {
+    segments: Vec<Segment>,
+}
+
+impl Schedule {
+    /// Creates a schedule, checking that every segment but the last has a
+    /// step count and that no segment is empty
+    pub fn new(segments: Vec<Segment>) -> Result<Self, ProofError> {
+        let Some((last, init)) = segments.split_last() else {
+            return Err(ProofError::Schedule("no segments".into()));
+        };
+        if last.steps.is_some() {
+            return Err(ProofError::Schedule(
+                "the last segment must take the remaining frames".into(),
+            ));
+        }
+        for (i, segment) in segments.iter().enumerate() {
+            if segment.rc == 0 {
+                return Err(ProofError::Schedule(format!("segment {i} has rc 0")));
+            }
+        }
+        for (i, segment) in init.iter().enumerate() {
+            match segment.steps {
+                None => {
+                    return Err(ProofError::Schedule(format!(
+                        "segment {i} must have a step count"
+                    )))
+                }
+                Some(0) => return Err(ProofError::Schedule(format!("segment {i} has no steps"))),
+                Some(_) => (),
+            }
+        }
+        Ok(Self { segments })
+    }
+
+    /// A schedule with a single segment, equivalent to a regular Nova proof
+    pub fn uniform(rc: usize) -> Result<Self, ProofError> {
+        Self::new(vec![Segment { rc, steps: None }])
+    }
+
+    /// The segments of the schedule
+    #[inline]
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// The distinct reduction counts used by the schedule, in ascending order
+    pub fn reduction_counts(&self) -> Vec<usize> {
+        let mut rcs: Vec<_> = self.segments.iter().map(|s| s.rc).collect();
+        rcs.sort_unstable();
+        rcs.dedup();
+        rcs
+    }
+
+    /// Assigns ranges of `num_frames` frames to the segments, paired with
+    /// their reduction counts. Segments that get no frames are left out.
+    pub fn split(&self, num_frames: usize) -> Vec<(usize, Range<usize>)> {
+        let mut ranges = Vec::with_capacity(self.segments.len());
+        let mut start = 0;
+        for segment in &self.segments {
+            if start >= num_frames {
+                break;
+            }
+            let end = match segment.steps {
+                Some(steps) => num_frames.min(start + steps * segment.rc),
+                None => num_frames,
+            };
+            ranges.push((segment.rc, start..end));
+            start = end;
+        }
+        ranges
+    }
+}
+
+fn params_for<F: CurveCycleEquipped, C: Coprocessor<F>>(
+    params: &SegmentParams<F, C>,
+    rc: usize,
+) -> Result<&PublicParams<'static, F, C>, ProofError>
+where
+    <<G1<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+    <<G2<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+{
+    params
+        .get(&rc)
+        .map(AsRef::as_ref)
+        .ok_or_else(|| ProofError::Schedule(format!("missing public parameters for rc {rc}")))
+}
+
+/// The proof of one segment, along with what's needed to verify it
+#[derive(Serialize, Deserialize)]
+pub struct SegmentProof<'a, F: CurveCycleEquipped, C: Coprocessor<F>>
+where
+    <<G1<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+    <<G2<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+{
+    /// The reduction count the segment was proven with
+    pub rc: usize,
+    /// The number of Nova steps in the segment
+    pub num_steps: usize,
+    /// The public input of the segment's first step
+    pub z0: Vec<F>,
+    /// The public output of the segment's last step
+    pub zi: Vec<F>,
+    /// The Nova proof of the segment
+    pub proof: Proof<'a, F, C>,
+}
+
+/// A sequence of segment proofs that together prove a whole computation
+#[derive(Serialize, Deserialize)]
+pub struct SegmentedProof<'a, F: CurveCycleEquipped, C: Coprocessor<F>>
+where
+    <<G1<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+    <<G2<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+{
+    /// The segment proofs, in evaluation order
+    pub segments: Vec<SegmentProof<'a, F, C>>,
+}
+
+impl<'a, F: CurveCycleEquipped, C: Coprocessor<F>> SegmentedProof<'a, F, C>
+where
+    <<G1<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+    <<G2<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+{
+    /// Proves `frames` following `schedule`. `params` must hold parameters for
+    /// each of the schedule's reduction counts, and the scalar cache of `store`
+    /// must be hydrated, as done by `NovaProver::get_evaluation_frames`.
+    pub fn prove(
+        params: &'a SegmentParams<F, C>,
+        schedule: &Schedule,
+        frames: &[Frame<IO<F>, Witness<F>, C>],
+        store: &'a Store<F>,
+        lang: Arc<Lang<F, C>>,
+    ) -> Result<Self, ProofError> {
+        let ranges = schedule.split(frames.len());
+        let mut segments = Vec::with_capacity(ranges.len());
+        let mut log = empty_emit_log(store);
+        for (rc, range) in ranges {
+            let pp = params_for(params, rc)?;
+            let frames = &frames[range];
+            let (z0, zi) = public_io(frames, log, store)?;
+            let circuits = MultiFrame::from_frames_with_log(rc, frames, store, lang.clone(), log);
+            log = frames
+                .iter()
+                .try_fold(log, |log, frame| frame.output.extend_emit_log(log, store))?;
+            let num_steps = circuits.len();
+            let proof =
+                Proof::prove_recursively(pp, store, &circuits, rc, z0.clone(), lang.clone())?;
+            segments.push(SegmentProof {
+                rc,
+                num_steps,
+                z0,
+                zi,
+                proof,
+            });
+        }
+        Ok(Self { segments })
+    }
+
+    /// Compresses the proof of every segment
+    pub fn compress(self, params: &'a SegmentParams<F, C>) -> Result<Self, ProofError> {
+        let segments = self
+            .segments
+            .into_iter()
+            .map(|segment| {
+                let pp = params_for(params, segment.rc)?;
+                Ok(SegmentProof {
+                    proof: segment.proof.compress(pp)?,
+                    ..segment
+                })
+            })
+            .collect::<Result<_, ProofError>>()?;
+        Ok(Self { segments })
+    }
+
+    /// The reduction count and number of steps of each segment, which is the
+    /// schedule as it was actually applied to the frames
+    pub fn schedule(&self) -> Vec<(usize, usize)> {
+        self.segments.iter().map(|s| (s.rc, s.num_steps)).collect()
+    }
+
+    /// Verifies that the segments prove a computation from `z0` to `zi`
+    pub fn verify(
+        &self,
+        params: &SegmentParams<F, C>,
+        z0: &[F],
+        zi: &[F],
+    ) -> Result<bool, ProofError> {
+        let (Some(first), Some(last)) = (self.segments.first(), self.segments.last()) else {
+            return Ok(false);
+        };
+        if first.z0 != z0 || last.zi != zi {
+            return Ok(false);
+        }
+        if self
+            .segments
+            .windows(2)
+            .any(|pair| pair[0].zi != pair[1].z0)
+        {
+            return Ok(false);
+        }
+        for segment in &self.segments {
+            let pp = params_for(params, segment.rc)?;
+            if !segment
+                .proof
+                .verify(pp, segment.num_steps, &segment.z0, &segment.zi)?
+            {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pasta_curves::pallas::Scalar as Fr;
+
+    use super::*;
+    use crate::eval::{empty_sym_env, lang::Coproc, Evaluator};
+    use crate::proof::nova::public_params;
+
+    #[test]
+    fn schedule_validation() {
+        let open = |rc| Segment { rc, steps: None };
+        let fixed = |rc, steps| Segment {
+            rc,
+            steps: Some(steps),
+        };
+        assert!(Schedule::new(vec![]).is_err());
+        assert!(Schedule::new(vec![fixed(10, 2)]).is_err());
+        assert!(Schedule::new(vec![open(10), open(1)]).is_err());
+        assert!(Schedule::new(vec![fixed(10, 0), open(1)]).is_err());
+        assert!(Schedule::new(vec![fixed(0, 1), open(1)]).is_err());
+        assert!(Schedule::new(vec![fixed(10, 2), open(1)]).is_ok());
+    }
+
+    #[test]
+    fn schedule_split() {
+        let schedule = Schedule::new(vec![
+            Segment {
+                rc: 10,
+                steps: Some(2),
+            },
+            Segment {
+                rc: 5,
+                steps: Some(1),
+            },
+            Segment { rc: 1, steps: None },
+        ])
+        .unwrap();
+        assert_eq!(schedule.reduction_counts(), vec![1, 5, 10]);
+        assert_eq!(
+            schedule.split(33),
+            vec![(10, 0..20), (5, 20..25), (1, 25..33)]
+        );
+        assert_eq!(schedule.split(22), vec![(10, 0..20), (5, 20..22)]);
+        assert_eq!(schedule.split(7), vec![(10, 0..7)]);
+        assert!(schedule.split(0).is_empty());
+    }
+
+    #[test]
+    fn prove_segments() {
+        let store = &mut Store::<Fr>::default();
+        let lang = Arc::new(Lang::<Fr, Coproc<Fr>>::new());
+        let expr = store.read("(let ((x 1) (y 2)) (+ x y))").unwrap();
+        let env = empty_sym_env(store);
+        let frames = Evaluator::generate_frames(expr, env, store, 100, |_| false, &lang).unwrap();
+        store.hydrate_scalar_cache();
+
+        let schedule = Schedule::new(vec![
+            Segment {
+                rc: 2,
+                steps: Some(2),
+            },
+            Segment { rc: 1, steps: None },
+        ])
+        .unwrap();
+        let params: SegmentParams<_, _> = schedule
+            .reduction_counts()
+            .into_iter()
+            .map(|rc| (rc, Arc::new(public_params(rc, lang.clone()))))
+            .collect();
+
+        let (z0, zi) = public_io(&frames, empty_emit_log(store), store).unwrap();
+        let proof = SegmentedProof::prove(&params, &schedule, &frames, store, lang).unwrap();
+        assert_eq!(proof.schedule()[0], (2, 2));
+        assert_eq!(proof.schedule()[1].0, 1);
+        assert!(proof.verify(&params, &z0, &zi).unwrap());
+        assert!(!proof.verify(&params, &zi, &zi).unwrap());
+
+        let proof = proof.compress(&params).unwrap();
+        assert!(proof.verify(&params, &z0, &zi).unwrap());
+    }
+}
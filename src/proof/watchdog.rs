@@ -0,0 +1,112 @@
+//! Keeps the Nova prover under a memory limit, so that a long proof degrades
+//! or stops with its progress saved instead of getting OOM-killed hours in.
+//!
+//! The limit is set with `LURK_MEMORY_LIMIT` (see `config::MemoryConfig`) and
+//! compared to the resident memory of the process before each step. Past
+//! [`SOFT_LIMIT_PERCENT`] of it the prover stops computing witnesses ahead of
+//! the step being proved, which is where parallel proving spends its memory.
+//! At the limit it stops, saving the steps folded so far to
+//! `LURK_CHECKPOINT_PATH` if set, and `Proof::resume_recursively` picks up
+//! from there.
+
+use crate::config::CONFIG;
+
+/// The share of the limit, in percent, past which memory is under pressure
+pub const SOFT_LIMIT_PERCENT: usize = 80;
+
+/// How close the process is to its memory limit
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Pressure {
+    /// Below the soft limit
+    Normal,
+    /// Past the soft limit: the prover stops using memory it can do without
+    High,
+    /// At or past the limit: the prover stops
+    Critical,
+}
+
+/// Compares the resident memory of the process to a limit
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryWatchdog {
+    limit: usize,
+    probe: fn() -> Option<usize>,
+}
+
+impl MemoryWatchdog {
+    /// A watchdog for `limit` bytes of resident memory
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            probe: resident_memory,
+        }
+    }
+
+    /// The watchdog for the configured limit, if there is one
+    pub fn from_config() -> Option<Self> {
+        CONFIG.memory.limit.map(Self::new)
+    }
+
+    /// The memory limit, in bytes
+    #[inline]
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// The current pressure. Platforms where the resident memory can't be
+    /// read never report any.
+    pub fn pressure(&self) -> Pressure {
+        match (self.probe)() {
+            Some(used) if used >= self.limit => Pressure::Critical,
+            Some(used) if used * 100 >= self.limit * SOFT_LIMIT_PERCENT => Pressure::High,
+            _ => Pressure::Normal,
+        }
+    }
+}
+
+/// The resident memory of the process in bytes, read from `/proc/self/status`
+/// where it exists
+pub fn resident_memory() -> Option<usize> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kib: usize = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// Parses a size in bytes, with an optional `K`, `M` or `G` (binary) suffix
+pub fn parse_size(s: &str) -> Option<usize> {
+    let s = s.trim();
+    let (digits, shift) = match s.char_indices().last()? {
+        (i, 'k' | 'K') => (&s[..i], 10),
+        (i, 'm' | 'M') => (&s[..i], 20),
+        (i, 'g' | 'G') => (&s[..i], 30),
+        _ => (s, 0),
+    };
+    digits.trim().parse::<usize>().ok()?.checked_mul(1 << shift)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watchdog(probe: fn() -> Option<usize>) -> MemoryWatchdog {
+        MemoryWatchdog { limit: 1000, probe }
+    }
+
+    #[test]
+    fn pressure_follows_resident_memory() {
+        assert_eq!(watchdog(|| Some(100)).pressure(), Pressure::Normal);
+        assert_eq!(watchdog(|| Some(800)).pressure(), Pressure::High);
+        assert_eq!(watchdog(|| Some(1000)).pressure(), Pressure::Critical);
+        assert_eq!(watchdog(|| None).pressure(), Pressure::Normal);
+    }
+
+    #[test]
+    fn sizes_are_parsed() {
+        assert_eq!(parse_size("4096"), Some(4096));
+        assert_eq!(parse_size("16K"), Some(16 << 10));
+        assert_eq!(parse_size(" 3 m"), Some(3 << 20));
+        assert_eq!(parse_size("2G"), Some(2 << 30));
+        assert_eq!(parse_size("G"), None);
+        assert_eq!(parse_size("lots"), None);
+    }
+}
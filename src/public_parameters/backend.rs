@@ -0,0 +1,271 @@
+use std::fmt;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{BufWriter, Read};
+use std::sync::Arc;
+
+use abomonation::Abomonation;
+use camino::{Utf8Path, Utf8PathBuf};
+use nova::traits::Group;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::coprocessor::Coprocessor;
+use crate::eval::lang::Lang;
+use crate::proof::nova::{self, CurveCycleEquipped, PublicParams, G1, G2};
+use crate::public_parameters::error::Error;
+
+/// Records where a set of public parameters came from. It's persisted next to
+/// the cached parameters and compared against the requested backend whenever
+/// the cache is read, so parameters from one source are never silently used
+/// in place of another. Proofs record it too, so that they're only verified
+/// with parameters of the same provenance.
+///
+/// Imported material is identified by its digest alone: the same material
+/// read from two paths has the same provenance.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Provenance {
+    /// Produced locally by Nova's transparent setup
+    Generated,
+    /// Loaded from externally produced parameter material
+    Imported {
+        /// Path the material was read from
+        source: String,
+        /// Hex-encoded SHA-256 digest of the imported bytes
+        digest: String,
+    },
+    /// Produced by `SeededSetup`, for tests
+    Seeded { seed: u64 },
+}
+
+impl Provenance {
+    /// Suffix for disk cache keys, keeping imported parameters apart from the
+    /// generated ones (whose keys predate provenance tracking)
+    pub(crate) fn key_suffix(&self) -> String {
+        match self {
+            Self::Generated => String::new(),
+            Self::Imported { digest, .. } => format!("-imported-{}", &digest[..16]),
+            Self::Seeded { seed } => format!("-seeded-{seed}"),
+        }
+    }
+}
+
+impl PartialEq for Provenance {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Generated, Self::Generated) => true,
+            (Self::Imported { digest: a, .. }, Self::Imported { digest: b, .. }) => a == b,
+            (Self::Seeded { seed: a }, Self::Seeded { seed: b }) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Provenance {}
+
+impl Hash for Provenance {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Self::Generated => (),
+            Self::Imported { digest, .. } => digest.hash(state),
+            Self::Seeded { seed } => seed.hash(state),
+        }
+    }
+}
+
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Generated => write!(f, "generated"),
+            Self::Imported { source, digest } => write!(f, "imported from {source} ({digest})"),
+            Self::Seeded { seed } => write!(f, "seeded with {seed}"),
+        }
+    }
+}
+
+/// A source of public parameters for a given reduction count and `Lang`
+pub trait ParamsBackend<F: CurveCycleEquipped, C: Coprocessor<F>> {
+    /// The provenance that parameters produced by this backend carry
+    fn provenance(&self) -> Provenance;
+
+    /// Produces fresh parameters
+    fn produce(
+        &self,
+        rc: usize,
+        lang: Arc<Lang<F, C>>,
+    ) -> Result<PublicParams<'static, F, C>, Error>;
+}
+
+/// The default backend, running Nova's setup locally. Nova derives its
+/// commitment keys from fixed labels, so the result is deterministic for a
+/// given `rc` and `Lang`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NovaSetup;
+
+impl<F: CurveCycleEquipped, C: Coprocessor<F>> ParamsBackend<F, C> for NovaSetup
+where
+    <<G1<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+    <<G2<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+{
+    fn provenance(&self) -> Provenance {
+        Provenance::Generated
+    }
+
+    fn produce(
+        &self,
+        rc: usize,
+        lang: Arc<Lang<F, C>>,
+    ) -> Result<PublicParams<'static, F, C>, Error> {
+        Ok(nova::public_params(rc, lang))
+    }
+}
+
+/// A backend for tests, producing the same parameters as `NovaSetup` under a
+/// provenance of their own. Nova derives its commitment keys from fixed
+/// labels, so the parameters are deterministic and don't depend on the seed:
+/// it only keeps the parameters of one test apart from those of other tests
+/// and from the ones provers use, in the caches and in proofs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SeededSetup {
+    pub seed: u64,
+}
+
+impl<F: CurveCycleEquipped, C: Coprocessor<F>> ParamsBackend<F, C> for SeededSetup
+where
+    <<G1<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+    <<G2<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+{
+    fn provenance(&self) -> Provenance {
+        Provenance::Seeded { seed: self.seed }
+    }
+
+    fn produce(
+        &self,
+        rc: usize,
+        lang: Arc<Lang<F, C>>,
+    ) -> Result<PublicParams<'static, F, C>, Error> {
+        Ok(nova::public_params(rc, lang))
+    }
+}
+
+/// The circuit imported parameter material was built for, written ahead of
+/// the parameters themselves
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportHeader {
+    pub rc: usize,
+    pub lang_key: String,
+}
+
+impl ImportHeader {
+    pub fn new<F: CurveCycleEquipped, C: Coprocessor<F>>(rc: usize, lang: &Lang<F, C>) -> Self {
+        Self {
+            rc,
+            lang_key: lang.key(),
+        }
+    }
+}
+
+impl fmt::Display for ImportHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rc {} and lang {}", self.rc, self.lang_key)
+    }
+}
+
+/// Reuses existing parameter material, stored as a bincode-serialized
+/// `ImportHeader` followed by the bincode-serialized `PublicParams`, as
+/// `ImportedParams::export` writes them. The digest of the file is computed
+/// when the backend is created and becomes part of the provenance. Material
+/// built for another `rc` or `Lang` than the requested ones is rejected.
+#[derive(Clone, Debug)]
+pub struct ImportedParams {
+    path: Utf8PathBuf,
+    digest: String,
+}
+
+impl ImportedParams {
+    pub fn new(path: &Utf8Path) -> Result<Self, Error> {
+        let digest = hex::encode(Sha256::digest(read_bytes(path)?));
+        Ok(Self {
+            path: path.to_owned(),
+            digest,
+        })
+    }
+
+    #[inline]
+    pub fn digest(&self) -> &str {
+        &self.digest
+    }
+
+    pub fn provenance(&self) -> Provenance {
+        Provenance::Imported {
+            source: self.path.to_string(),
+            digest: self.digest.clone(),
+        }
+    }
+
+    /// Writes `pp`, produced for `rc` and `lang`, as parameter material to
+    /// import
+    pub fn export<F: CurveCycleEquipped, C: Coprocessor<F>>(
+        path: &Utf8Path,
+        rc: usize,
+        lang: &Lang<F, C>,
+        pp: &PublicParams<'_, F, C>,
+    ) -> Result<(), Error>
+    where
+        <<G1<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+        <<G2<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+    {
+        let mut out = BufWriter::new(File::create(path)?);
+        let to_error = |e| Error::CacheError(format!("Public params serialization error: {e}"));
+        bincode::serialize_into(&mut out, &ImportHeader::new(rc, lang)).map_err(to_error)?;
+        bincode::serialize_into(&mut out, pp).map_err(to_error)
+    }
+}
+
+fn read_bytes(path: &Utf8Path) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+impl<F: CurveCycleEquipped, C: Coprocessor<F>> ParamsBackend<F, C> for ImportedParams
+where
+    <<G1<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+    <<G2<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+{
+    fn provenance(&self) -> Provenance {
+        ImportedParams::provenance(self)
+    }
+
+    fn produce(
+        &self,
+        rc: usize,
+        lang: Arc<Lang<F, C>>,
+    ) -> Result<PublicParams<'static, F, C>, Error> {
+        let bytes = read_bytes(&self.path)?;
+        // the file may have changed since the backend was created
+        let digest = hex::encode(Sha256::digest(&bytes));
+        if digest != self.digest {
+            return Err(Error::ProvenanceMismatch {
+                expected: ImportedParams::provenance(self),
+                found: Provenance::Imported {
+                    source: self.path.to_string(),
+                    digest,
+                },
+            });
+        }
+        let to_error =
+            |e| Error::CacheError(format!("Imported public params deserialization error: {e}"));
+        let mut input = bytes.as_slice();
+        let header: ImportHeader = bincode::deserialize_from(&mut input).map_err(to_error)?;
+        let expected = ImportHeader::new(rc, &lang);
+        if header != expected {
+            return Err(Error::CircuitMismatch {
+                expected: expected.to_string(),
+                found: header.to_string(),
+            });
+        }
+        bincode::deserialize_from(input).map_err(to_error)
+    }
+}
@@ -6,9 +6,10 @@ use abomonation::{encode, Abomonation};
 use camino::{Utf8Path, Utf8PathBuf};
 use nova::traits::Group;
 
+use crate::artifacts::{ArtifactName, Artifacts};
 use crate::coprocessor::Coprocessor;
 use crate::proof::nova::{CurveCycleEquipped, PublicParams, G1, G2};
-use crate::public_parameters::error::Error;
+use crate::public_parameters::{backend::Provenance, error::Error};
 
 pub(crate) struct PublicParamDiskCache<F, C>
 where
@@ -34,6 +35,17 @@ where
         })
     }
 
+    /// The key the parameters named `name` are cached under. That's their
+    /// canonical name, unless they were cached before names were canonical
+    pub(crate) fn key(&self, name: &ArtifactName) -> String {
+        let path = Artifacts::new(self.dir.clone())
+            .with_legacy_names(|name| format!("public-params-{}", name.hash))
+            .resolve(name);
+        path.file_name()
+            .expect("artifact paths end with their names")
+            .to_owned()
+    }
+
     fn key_path(&self, key: &str) -> Utf8PathBuf {
         self.dir.join(Utf8PathBuf::from(key))
     }
@@ -67,4 +79,38 @@ where
         unsafe { encode(data, &mut file).expect("failed to encode") };
         Ok(())
     }
+
+    fn provenance_path(&self, key: &str) -> Utf8PathBuf {
+        self.dir
+            .join(Utf8PathBuf::from(format!("{key}.provenance.json")))
+    }
+
+    /// Returns the provenance recorded for `key`. Entries written before
+    /// provenance was tracked were always produced by Nova's setup.
+    pub(crate) fn get_provenance(&self, key: &str) -> Result<Provenance, Error> {
+        let path = self.provenance_path(key);
+        if !path.exists() {
+            return Ok(Provenance::Generated);
+        }
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    pub(crate) fn set_provenance(&self, key: &str, provenance: &Provenance) -> Result<(), Error> {
+        let writer = BufWriter::new(File::create(self.provenance_path(key))?);
+        Ok(serde_json::to_writer(writer, provenance)?)
+    }
+
+    /// Fails if the entry for `key` was recorded with a provenance other than
+    /// `expected`
+    pub(crate) fn check_provenance(&self, key: &str, expected: &Provenance) -> Result<(), Error> {
+        let found = self.get_provenance(key)?;
+        if &found != expected {
+            return Err(Error::ProvenanceMismatch {
+                expected: expected.clone(),
+                found,
+            });
+        }
+        Ok(())
+    }
 }
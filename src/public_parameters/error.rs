@@ -1,6 +1,8 @@
 use std::io;
 use thiserror::Error;
 
+use super::backend::Provenance;
+
 #[non_exhaustive]
 #[derive(Error, Debug)]
 pub enum Error {
@@ -10,4 +12,11 @@ pub enum Error {
     CacheError(String),
     #[error("JSON error: {0}")]
     JsonError(#[from] serde_json::Error),
+    #[error("Provenance mismatch: expected {expected}, found {found}")]
+    ProvenanceMismatch {
+        expected: Provenance,
+        found: Provenance,
+    },
+    #[error("Circuit mismatch: expected parameters for {expected}, found {found}")]
+    CircuitMismatch { expected: String, found: String },
 }
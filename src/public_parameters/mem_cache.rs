@@ -11,16 +11,20 @@ use tap::TapFallible;
 use tracing::{info, warn};
 
 use crate::{
+    artifacts::{ArtifactKind, ArtifactName},
     coprocessor::Coprocessor,
     eval::lang::Lang,
     proof::nova::{PublicParams, G1, G2},
 };
 use crate::{proof::nova::CurveCycleEquipped, public_parameters::error::Error};
 
-use super::disk_cache::PublicParamDiskCache;
+use super::{
+    backend::{ParamsBackend, Provenance},
+    disk_cache::PublicParamDiskCache,
+};
 
 type AnyMap = anymap::Map<dyn core::any::Any + Send + Sync>;
-type PublicParamMap<F, C> = HashMap<(usize, bool), Arc<PublicParams<'static, F, C>>>;
+type PublicParamMap<F, C> = HashMap<(usize, bool, Provenance), Arc<PublicParams<'static, F, C>>>;
 
 /// This is a global registry for Coproc-specific parameters.
 /// It is used to cache parameters for each Coproc, so that they are not
@@ -41,12 +45,12 @@ impl PublicParamMemCache {
     fn get_from_disk_cache_or_update_with<
         F: CurveCycleEquipped,
         C: Coprocessor<F> + 'static,
-        Fn: FnOnce(Arc<Lang<F, C>>) -> Arc<PublicParams<'static, F, C>>,
+        B: ParamsBackend<F, C> + ?Sized,
     >(
         &'static self,
         rc: usize,
         abomonated: bool,
-        default: Fn,
+        backend: &B,
         lang: Arc<Lang<F, C>>,
         disk_cache_path: &Utf8Path,
     ) -> Result<Arc<PublicParams<'static, F, C>>, Error>
@@ -59,13 +63,21 @@ impl PublicParamMemCache {
         // use the cached language key
         let lang_key = lang.key();
         let quick_suffix = if abomonated { "-abomonated" } else { "" };
+        let provenance = backend.provenance();
+        let provenance_suffix = provenance.key_suffix();
         // Sanity-check: we're about to use a lang-dependent disk cache, which should be specialized
         // for this lang/coprocessor.
-        let key = format!("public-params-rc-{rc}-coproc-{lang_key}{quick_suffix}");
+        let name = ArtifactName::new(
+            ArtifactKind::PublicParams,
+            F::FIELD,
+            format!("rc-{rc}-coproc-{lang_key}{provenance_suffix}{quick_suffix}"),
+        );
+        let key = disk_cache.key(&name);
         // read the file if it exists, otherwise initialize
         if abomonated {
             match disk_cache.get_raw_bytes(&key) {
                 Ok(mut bytes) => {
+                    disk_cache.check_provenance(&key, &provenance)?;
                     info!("loading abomonated {lang_key}");
                     let (pp, rest) =
                         unsafe { decode::<PublicParams<'_, F, C>>(&mut bytes).unwrap() };
@@ -74,13 +86,14 @@ impl PublicParamMemCache {
                 }
                 Err(Error::IOError(e)) => {
                     warn!("{e}");
-                    info!("Generating fresh public parameters");
-                    let pp = default(lang);
+                    info!("Producing fresh public parameters ({provenance})");
+                    let pp = Arc::new(backend.produce(rc, lang)?);
                     // maybe just directly write
                     disk_cache
                         .set_abomonated(&key, &*pp)
                         .tap_ok(|_| info!("writing public params to disk-cache: {}", lang_key))
                         .map_err(|e| Error::CacheError(format!("Disk write error: {e}")))?;
+                    disk_cache.set_provenance(&key, &provenance)?;
                     Ok(pp)
                 }
                 _ => unreachable!(),
@@ -88,14 +101,16 @@ impl PublicParamMemCache {
         } else {
             // read the file if it exists, otherwise initialize
             if let Ok(pp) = disk_cache.get(&key) {
+                disk_cache.check_provenance(&key, &provenance)?;
                 info!("loading abomonated {lang_key}");
                 Ok(Arc::new(pp))
             } else {
-                let pp = default(lang);
+                let pp = Arc::new(backend.produce(rc, lang)?);
                 disk_cache
                     .set(&key, &*pp)
                     .tap_ok(|_| info!("writing public params to disk-cache: {}", lang_key))
                     .map_err(|e| Error::CacheError(format!("Disk write error: {e}")))?;
+                disk_cache.set_provenance(&key, &provenance)?;
                 Ok(pp)
             }
         }
@@ -106,12 +121,12 @@ impl PublicParamMemCache {
     pub(crate) fn get_from_mem_cache_or_update_with<
         F: CurveCycleEquipped,
         C: Coprocessor<F> + 'static,
-        Fn: FnOnce(Arc<Lang<F, C>>) -> Arc<PublicParams<'static, F, C>>,
+        B: ParamsBackend<F, C> + ?Sized,
     >(
         &'static self,
         rc: usize,
         abomonated: bool,
-        default: Fn,
+        backend: &B,
         lang: Arc<Lang<F, C>>,
         disk_cache_path: &Utf8Path,
     ) -> Result<Arc<PublicParams<'static, F, C>>, Error>
@@ -127,13 +142,13 @@ impl PublicParamMemCache {
         let entry = mem_cache.entry::<PublicParamMap<F, C>>();
         // deduce the map and populate it if needed
         let param_entry = entry.or_insert_with(HashMap::new);
-        match param_entry.entry((rc, abomonated)) {
+        match param_entry.entry((rc, abomonated, backend.provenance())) {
             Entry::Occupied(o) => Ok(o.into_mut()),
             Entry::Vacant(v) => {
                 let val = self.get_from_disk_cache_or_update_with(
                     rc,
                     true,
-                    default,
+                    backend,
                     lang,
                     disk_cache_path,
                 )?;
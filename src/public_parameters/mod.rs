@@ -3,53 +3,96 @@ use abomonation::{decode, Abomonation};
 use camino::{Utf8Path, Utf8PathBuf};
 use std::sync::Arc;
 
+use crate::artifacts::{ArtifactKind, ArtifactName};
 use crate::coprocessor::Coprocessor;
 use crate::proof::nova::{CurveCycleEquipped, G1, G2};
 use crate::{
     eval::lang::Lang,
     proof::nova::{self, PublicParams},
+    proof::segmented::{Schedule, SegmentParams},
 };
 
+pub mod backend;
 mod disk_cache;
 pub mod error;
 mod mem_cache;
 
-use crate::public_parameters::error::Error;
+use crate::public_parameters::{
+    backend::{NovaSetup, ParamsBackend},
+    error::Error,
+};
 
-#[cfg(not(target_arch = "wasm32"))]
 pub fn public_params_default_dir() -> Utf8PathBuf {
-    let home = home::home_dir().unwrap();
-    Utf8PathBuf::from_path_buf(home.join(".lurk/public_params"))
-        .expect("path contains invalid Unicode")
+    crate::config::lurk_data_dir().join("public_params")
 }
 
-#[cfg(target_arch = "wasm32")]
-pub fn public_params_default_dir() -> Utf8PathBuf {
-    Utf8PathBuf::from(".lurk/public_params")
+pub fn public_params<F: CurveCycleEquipped, C: Coprocessor<F> + 'static>(
+    rc: usize,
+    abomonated: bool,
+    lang: Arc<Lang<F, C>>,
+    disk_cache_path: &Utf8Path,
+) -> Result<Arc<PublicParams<'static, F, C>>, Error>
+where
+    F::CK1: Sync + Send,
+    F::CK2: Sync + Send,
+    <<G1<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+    <<G2<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+{
+    public_params_with_backend(rc, abomonated, lang, disk_cache_path, &NovaSetup)
 }
 
-pub fn public_params<F: CurveCycleEquipped, C: Coprocessor<F> + 'static>(
+/// Like `public_params`, but missing parameters are produced by `backend`.
+/// Cached parameters are only reused if they were recorded with the same
+/// provenance as the one `backend` reports.
+pub fn public_params_with_backend<F, C, B>(
     rc: usize,
     abomonated: bool,
     lang: Arc<Lang<F, C>>,
     disk_cache_path: &Utf8Path,
+    backend: &B,
 ) -> Result<Arc<PublicParams<'static, F, C>>, Error>
 where
+    F: CurveCycleEquipped,
+    C: Coprocessor<F> + 'static,
+    B: ParamsBackend<F, C> + ?Sized,
     F::CK1: Sync + Send,
     F::CK2: Sync + Send,
     <<G1<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
     <<G2<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
 {
-    let f = |lang: Arc<Lang<F, C>>| Arc::new(nova::public_params(rc, lang));
     mem_cache::PUBLIC_PARAM_MEM_CACHE.get_from_mem_cache_or_update_with(
         rc,
         abomonated,
-        f,
+        backend,
         lang,
         disk_cache_path,
     )
 }
 
+/// Gets the public parameters for every reduction count in `schedule`, each
+/// going through the same caches as `public_params`
+pub fn segment_params<F: CurveCycleEquipped, C: Coprocessor<F> + 'static>(
+    schedule: &Schedule,
+    abomonated: bool,
+    lang: Arc<Lang<F, C>>,
+    disk_cache_path: &Utf8Path,
+) -> Result<SegmentParams<F, C>, Error>
+where
+    F::CK1: Sync + Send,
+    F::CK2: Sync + Send,
+    <<G1<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+    <<G2<F> as Group>::Scalar as ff::PrimeField>::Repr: Abomonation,
+{
+    schedule
+        .reduction_counts()
+        .into_iter()
+        .map(|rc| {
+            let pp = public_params(rc, abomonated, lang.clone(), disk_cache_path)?;
+            Ok((rc, pp))
+        })
+        .collect()
+}
+
 /// Attempts to extract abomonated public parameters.
 /// To avoid all copying overhead, we zerocopy all of the data within the file;
 /// this leads to extremely high performance, but restricts the lifetime of the data
@@ -72,7 +115,12 @@ where
     let lang_key = lang.key();
     // Sanity-check: we're about to use a lang-dependent disk cache, which should be specialized
     // for this lang/coprocessor.
-    let key = format!("public-params-rc-{rc}-coproc-{lang_key}-abomonated");
+    let name = ArtifactName::new(
+        ArtifactKind::PublicParams,
+        F::FIELD,
+        format!("rc-{rc}-coproc-{lang_key}-abomonated"),
+    );
+    let key = disk_cache.key(&name);
 
     match disk_cache.get_raw_bytes(&key) {
         Ok(mut bytes) => {
@@ -125,4 +173,83 @@ mod tests {
         // With disk cache, reads from tmpfile
         let _public_params = public_params(10, true, lang, &public_params_dir).unwrap();
     }
+
+    #[test]
+    fn provenance_is_checked() {
+        use crate::public_parameters::backend::Provenance;
+
+        let tmp_dir = Builder::new().prefix("tmp").tempdir().unwrap();
+        let dir = Utf8Path::from_path(tmp_dir.path()).unwrap();
+        let disk_cache = disk_cache::PublicParamDiskCache::<S1, Coproc<S1>>::new(dir).unwrap();
+
+        // entries without a record are assumed to be generated
+        assert_eq!(
+            disk_cache.get_provenance("key").unwrap(),
+            Provenance::Generated
+        );
+
+        let imported = Provenance::Imported {
+            source: "srs.bin".into(),
+            digest: "00".repeat(32),
+        };
+        disk_cache.set_provenance("key", &imported).unwrap();
+        assert!(disk_cache.check_provenance("key", &imported).is_ok());
+        assert!(matches!(
+            disk_cache.check_provenance("key", &Provenance::Generated),
+            Err(Error::ProvenanceMismatch { .. })
+        ));
+        // the same material read from elsewhere
+        let moved = Provenance::Imported {
+            source: "elsewhere/srs.bin".into(),
+            digest: "00".repeat(32),
+        };
+        assert!(disk_cache.check_provenance("key", &moved).is_ok());
+    }
+
+    #[test]
+    fn seeded_params_are_deterministic_and_kept_apart() {
+        use crate::public_parameters::backend::SeededSetup;
+
+        let tmp_dir = Builder::new().prefix("tmp").tempdir().unwrap();
+        let dir = Utf8Path::from_path(tmp_dir.path()).unwrap();
+        let lang: Arc<Lang<S1, Coproc<S1>>> = Arc::new(Lang::new());
+        let seeded = SeededSetup { seed: 7 };
+        let pp = seeded.produce(1, lang.clone()).unwrap();
+        assert_eq!(
+            pp.vk_digest(),
+            seeded.produce(1, lang.clone()).unwrap().vk_digest()
+        );
+
+        let cached = public_params_with_backend(1, true, lang.clone(), dir, &seeded).unwrap();
+        assert_eq!(cached.vk_digest(), pp.vk_digest());
+        let other_seed = SeededSetup { seed: 8 };
+        assert_ne!(
+            ParamsBackend::<S1, Coproc<S1>>::provenance(&seeded),
+            ParamsBackend::<S1, Coproc<S1>>::provenance(&other_seed)
+        );
+        assert_ne!(
+            ParamsBackend::<S1, Coproc<S1>>::provenance(&seeded).key_suffix(),
+            ParamsBackend::<S1, Coproc<S1>>::provenance(&NovaSetup).key_suffix()
+        );
+    }
+
+    #[test]
+    fn imported_params_are_checked_against_the_circuit() {
+        use crate::public_parameters::backend::ImportedParams;
+
+        let tmp_dir = Builder::new().prefix("tmp").tempdir().unwrap();
+        let path = Utf8Path::from_path(tmp_dir.path())
+            .unwrap()
+            .join("params.bin");
+
+        let lang: Arc<Lang<S1, Coproc<S1>>> = Arc::new(Lang::new());
+        let pp = NovaSetup.produce(10, lang.clone()).unwrap();
+        ImportedParams::export(&path, 10, &lang, &pp).unwrap();
+        let imported = ImportedParams::new(&path).unwrap();
+        assert!(imported.produce(10, lang.clone()).is_ok());
+        assert!(matches!(
+            imported.produce(20, lang),
+            Err(Error::CircuitMismatch { .. })
+        ));
+    }
 }
@@ -238,7 +238,7 @@ fn repl_aux<
     let received_z_store = z_store.is_some();
     let mut s = z_store
         .and_then(|z_store_path| fs::read(z_store_path).ok())
-        .and_then(|bytes| ZData::from_bytes(&bytes).ok())
+        .and_then(|bytes| ZData::from_versioned_bytes(&bytes).ok())
         .and_then(|zd| from_z_data(&zd).ok())
         .map(|z_store: ZStore<F>| ZStore::to_store(&z_store))
         .tap_none(|| {
@@ -0,0 +1,185 @@
+//! Secrets of hiding commitments.
+//!
+//! Whoever learns the secret of a hiding commitment can check guesses of its
+//! payload, so secrets are kept in a `Secret`, which can't leak by accident:
+//! its `Debug` output is redacted and it implements neither `Display` nor
+//! serde's traits, so it can't be printed, logged or written to a file without
+//! calling `expose` first, and it's overwritten with zero when dropped. Fields
+//! that must persist a secret opt into it with `#[serde(with = "exposed")]`.
+//! Secrets are compared in constant time, and the field operations of the
+//! hashes they're committed with don't branch on the values of field elements
+//! either.
+//!
+//! A store holds the secrets of the commitments it interns, in order to open
+//! them, so a `Secret` only protects the copies made outside of it. Openings
+//! return a `Secret`, and are logged on the `lurk::secret` target.
+
+use std::fmt;
+
+use rand_core::{CryptoRng, RngCore};
+use subtle::{Choice, ConstantTimeEq};
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+use crate::field::LurkField;
+
+/// The secret of a hiding commitment, zeroized when dropped
+pub struct Secret<F: LurkField>(F);
+
+impl<F: LurkField> Secret<F> {
+    /// Wraps `secret`, which the caller should no longer keep a copy of
+    pub fn new(secret: F) -> Self {
+        Self(secret)
+    }
+
+    /// A fresh secret drawn from `rng`, which must be cryptographically secure
+    pub fn random(rng: impl RngCore + CryptoRng) -> Self {
+        Self(F::random(rng))
+    }
+
+    /// The field element of the secret. Copies of it aren't zeroized, so they
+    /// should only be made to hash or store the secret.
+    pub fn expose(&self) -> &F {
+        &self.0
+    }
+}
+
+impl<F: LurkField> Clone for Secret<F> {
+    fn clone(&self) -> Self {
+        Self(self.0)
+    }
+}
+
+impl<F: LurkField> Zeroize for Secret<F> {
+    fn zeroize(&mut self) {
+        // SAFETY: `self.0` is a valid, aligned field element, and writing a
+        // `Copy` value over it drops nothing. The write is volatile so it isn't
+        // optimized away, even though the value is never read again.
+        unsafe { std::ptr::write_volatile(&mut self.0, F::ZERO) };
+        std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+impl<F: LurkField> Drop for Secret<F> {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl<F: LurkField> ZeroizeOnDrop for Secret<F> {}
+
+impl<F: LurkField> ConstantTimeEq for Secret<F> {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.ct_eq(&other.0)
+    }
+}
+
+impl<F: LurkField> PartialEq for Secret<F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl<F: LurkField> Eq for Secret<F> {}
+
+impl<F: LurkField> fmt::Debug for Secret<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(<redacted>)")
+    }
+}
+
+/// Serializes an optional secret as its field element, for the fields that
+/// must write it out, like those of the files holding a commitment's opening
+pub mod exposed {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::Secret;
+    use crate::field::LurkField;
+
+    pub fn serialize<F: LurkField + Serialize, S: Serializer>(
+        secret: &Option<Secret<F>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        secret.as_ref().map(Secret::expose).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, F: LurkField + Deserialize<'de>, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Secret<F>>, D::Error> {
+        Ok(Option::<F>::deserialize(deserializer)?.map(Secret::new))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use blstrs::Scalar as Fr;
+    use ff::Field;
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::store::Store;
+    use crate::Num;
+
+    fn zeroized_on_drop<T: ZeroizeOnDrop>(_: &T) {}
+
+    #[test]
+    fn secrets_are_zeroized() {
+        let mut secret = Secret::<Fr>::random(OsRng);
+        assert_ne!(*secret.expose(), Fr::ZERO);
+        zeroized_on_drop(&secret);
+
+        let copy = secret.clone();
+        assert!(secret == copy);
+        secret.zeroize();
+        assert_eq!(*secret.expose(), Fr::ZERO);
+        assert!(secret != copy);
+        assert_ne!(*copy.expose(), Fr::ZERO);
+    }
+
+    #[test]
+    fn hidden_secrets_open_commitments() {
+        let store = &mut Store::<Fr>::default();
+        let payload = store.num(42);
+        let secret = Secret::random(OsRng);
+        let comm = store.hide_secret(&secret, payload);
+        let (opened, opened_payload) = store.open(comm).unwrap();
+        assert!(opened == secret);
+        assert_eq!(opened_payload, payload);
+        assert_eq!(store.hidden(*secret.expose(), payload), Some(comm));
+        assert!(store.open_mut(comm).unwrap().0 == secret);
+        assert_eq!(
+            store.secret(comm),
+            store.get_num(Num::Scalar(*secret.expose()))
+        );
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct Opening {
+        #[serde(with = "exposed")]
+        secret: Option<Secret<Fr>>,
+    }
+
+    #[test]
+    fn secrets_are_redacted() {
+        let secret = Secret::<Fr>::random(OsRng);
+        let value = *secret.expose();
+        let debug = format!("{:?}", Some(secret.clone()));
+        assert_eq!(debug, "Some(Secret(<redacted>))");
+        for shown in [format!("{value:?}"), serde_json::to_string(&value).unwrap()] {
+            assert!(!debug.contains(shown.trim_matches('"')));
+        }
+
+        // Only fields opting into it write the secret out
+        let opening = Opening {
+            secret: Some(secret.clone()),
+        };
+        let json = serde_json::to_string(&opening).unwrap();
+        assert_eq!(
+            json,
+            format!(r#"{{"secret":{}}}"#, serde_json::to_string(&value).unwrap())
+        );
+        let read: Opening = serde_json::from_str(&json).unwrap();
+        assert!(read.secret == Some(secret));
+        let read: Opening = serde_json::from_str(r#"{"secret":null}"#).unwrap();
+        assert!(read.secret.is_none());
+    }
+}
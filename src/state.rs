@@ -7,7 +7,7 @@ use anyhow::{bail, Result};
 use once_cell::sync::OnceCell;
 use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
-use crate::Symbol;
+use crate::{parser::dialect::ReaderDialect, Symbol};
 
 use super::package::{Package, SymbolRef};
 
@@ -16,6 +16,7 @@ use super::package::{Package, SymbolRef};
 pub struct State {
     current_package: SymbolRef,
     symbol_packages: HashMap<SymbolRef, Package>,
+    dialect: ReaderDialect,
 }
 
 impl State {
@@ -33,6 +34,7 @@ impl State {
         Self {
             current_package,
             symbol_packages,
+            dialect: ReaderDialect::default(),
         }
     }
 
@@ -51,6 +53,22 @@ impl State {
         }
     }
 
+    /// Returns the reader dialect in use
+    #[inline]
+    pub const fn dialect(&self) -> &ReaderDialect {
+        &self.dialect
+    }
+
+    /// Sets the reader dialect, moving into its default package if it has one
+    pub fn set_dialect(&mut self, dialect: ReaderDialect) -> Result<()> {
+        if let Some(path) = &dialect.default_package {
+            let package_name = SymbolRef::new(Symbol::sym(path));
+            self.set_current_package(package_name)?;
+        }
+        self.dialect = dialect;
+        Ok(())
+    }
+
     /// Returns the name of the current package
     #[inline]
     pub const fn get_current_package_name(&self) -> &SymbolRef {
@@ -182,6 +200,7 @@ impl Default for State {
         Self {
             current_package: SymbolRef::new(Symbol::root_sym()),
             symbol_packages: Default::default(),
+            dialect: Default::default(),
         }
     }
 }
@@ -215,17 +234,22 @@ const LURK_PACKAGE_SYMBOL_NAME: &str = "lurk";
 const USER_PACKAGE_SYMBOL_NAME: &str = "user";
 const META_PACKAGE_SYMBOL_NAME: &str = "meta";
 
-const LURK_PACKAGE_SYMBOLS_NAMES: [&str; 36] = [
+const LURK_PACKAGE_SYMBOLS_NAMES: [&str; 51] = [
     "atom",
     "begin",
     "car",
     "cdr",
     "char",
+    "checked-div",
+    "checked-u64-add",
+    "checked-u64-mul",
+    "checked-u64-sub",
     "comm",
     "commit",
     "cons",
     "current-env",
     "emit",
+    "err",
     "eval",
     "eq",
     "hide",
@@ -235,10 +259,20 @@ const LURK_PACKAGE_SYMBOLS_NAMES: [&str; 36] = [
     "letrec",
     "nil",
     "num",
+    "ok",
     "u64",
     "open",
     "quote",
+    "ratio",
+    "ratio+",
+    "ratio-",
+    "ratio*",
+    "ratio/",
+    "ratio<",
     "secret",
+    "set-insert",
+    "set-member",
+    "set-union",
     "strcons",
     "t",
     "+",
@@ -254,7 +288,7 @@ const LURK_PACKAGE_SYMBOLS_NAMES: [&str; 36] = [
     "_",
 ];
 
-const META_PACKAGE_SYMBOLS_NAMES: [&str; 18] = [
+const META_PACKAGE_SYMBOLS_NAMES: [&str; 21] = [
     "def",
     "defrec",
     "load",
@@ -268,8 +302,11 @@ const META_PACKAGE_SYMBOLS_NAMES: [&str; 18] = [
     "open",
     "clear",
     "set-env",
+    "set-dialect",
     "prove",
     "verify",
+    "find-frames",
+    "compare-backends",
     "defpackage",
     "import",
     "in-package",
@@ -280,6 +317,7 @@ pub mod test {
     use super::{lurk_sym, State, LURK_PACKAGE_SYMBOLS_NAMES};
     use crate::{
         package::{Package, SymbolRef},
+        parser::dialect::{CaseFolding, ReaderDialect},
         Symbol,
     };
 
@@ -324,4 +362,23 @@ pub mod test {
             "my-other-symbol",
         );
     }
+
+    #[test]
+    fn test_dialect_default_package() {
+        let mut state = State::init_lurk_state();
+
+        let unknown = ReaderDialect::default().default_package(vec!["nope".into()]);
+        assert!(state.set_dialect(unknown).is_err());
+        assert_eq!(state.dialect(), &ReaderDialect::default());
+
+        let dialect = ReaderDialect::default()
+            .case_folding(CaseFolding::Lower)
+            .default_package(vec!["lurk".into()]);
+        state.set_dialect(dialect.clone()).unwrap();
+        assert_eq!(state.dialect(), &dialect);
+        assert_eq!(
+            state.get_current_package_name(),
+            &SymbolRef::new(Symbol::sym(&["lurk"]))
+        );
+    }
 }
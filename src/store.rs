@@ -1,5 +1,4 @@
 use rayon::prelude::*;
-use std::collections::HashMap;
 use std::fmt;
 use std::usize;
 use thiserror;
@@ -12,6 +11,7 @@ use crate::expr;
 use crate::expr::{Expression, Thunk};
 use crate::field::{FWrap, LurkField};
 use crate::ptr::{ContPtr, Ptr, RawPtr};
+use crate::secret::Secret;
 use crate::state::{lurk_sym, user_sym};
 use crate::symbol::Symbol;
 use crate::tag::{ContTag, ExprTag, Op1, Op2, Tag};
@@ -22,8 +22,7 @@ use crate::z_store::ZStore;
 use crate::{Num, UInt};
 
 use crate::hash::{HashConstants, InversePoseidonCache, PoseidonCache};
-
-type IndexSet<K> = indexmap::IndexSet<K, ahash::RandomState>;
+use crate::hasher::{HashMap, IndexSet};
 
 #[derive(Debug)]
 pub struct Store<F: LurkField> {
@@ -132,6 +131,37 @@ impl fmt::Display for Error {
     }
 }
 
+/// The numbers of expressions and continuations interned in a store before and
+/// after `Store::gc`. A fresh store interns some entries of its own, so a store
+/// that was nearly empty can have more entries after a collection than before.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GcStats {
+    pub exprs_before: usize,
+    pub exprs_after: usize,
+    pub conts_before: usize,
+    pub conts_after: usize,
+}
+
+impl GcStats {
+    /// The number of expressions and continuations dropped
+    pub fn reclaimed(&self) -> usize {
+        (self.exprs_before + self.conts_before).saturating_sub(self.exprs_after + self.conts_after)
+    }
+}
+
+impl fmt::Display for GcStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "reclaimed {} of {} expressions and {} of {} continuations",
+            self.exprs_before.saturating_sub(self.exprs_after),
+            self.exprs_before,
+            self.conts_before.saturating_sub(self.conts_after),
+            self.conts_before
+        )
+    }
+}
+
 #[macro_export]
 macro_rules! lurk_sym_ptr {
     ( $store:expr, $sym:ident ) => {{
@@ -180,11 +210,17 @@ impl<F: LurkField> Store<F> {
         self.intern_comm(secret, payload)
     }
 
+    /// Hides `payload` with `secret`, which the store keeps to open the
+    /// commitment
+    pub fn hide_secret(&mut self, secret: &Secret<F>, payload: Ptr<F>) -> Ptr<F> {
+        self.intern_comm(*secret.expose(), payload)
+    }
+
     pub fn commit(&mut self, payload: Ptr<F>) -> Ptr<F> {
         self.hide(F::NON_HIDING_COMMITMENT_SECRET, payload)
     }
 
-    pub fn open(&self, ptr: Ptr<F>) -> Option<(F, Ptr<F>)> {
+    pub fn open(&self, ptr: Ptr<F>) -> Option<(Secret<F>, Ptr<F>)> {
         let p = match ptr.tag {
             ExprTag::Comm => ptr,
             ExprTag::Num => {
@@ -199,11 +235,10 @@ impl<F: LurkField> Store<F> {
             _ => return None,
         };
 
-        self.fetch_comm(&p)
-            .map(|(secret, payload)| (secret.0, *payload))
+        self.opening(p)
     }
 
-    pub fn open_mut(&mut self, ptr: Ptr<F>) -> Result<(F, Ptr<F>), Error> {
+    pub fn open_mut(&mut self, ptr: Ptr<F>) -> Result<(Secret<F>, Ptr<F>), Error> {
         let p = match ptr.tag {
             ExprTag::Comm => ptr,
             ExprTag::Num => {
@@ -214,11 +249,17 @@ impl<F: LurkField> Store<F> {
             _ => return Err(Error("wrong type for commitment specifier".into())),
         };
 
-        if let Some((secret, payload)) = self.fetch_comm(&p) {
-            Ok((secret.0, *payload))
-        } else {
-            Err(Error("hidden value could not be opened".into()))
-        }
+        self.opening(p)
+            .ok_or_else(|| Error("hidden value could not be opened".into()))
+    }
+
+    /// The secret and payload of the commitment `comm`. Every secret that
+    /// leaves the store goes through here, and is logged on the `lurk::secret`
+    /// target, without its value, so openings can be audited.
+    fn opening(&self, comm: Ptr<F>) -> Option<(Secret<F>, Ptr<F>)> {
+        let (secret, payload) = self.fetch_comm(&comm)?;
+        tracing::debug!(target: "lurk::secret", "opening the commitment at {comm:?}");
+        Some((Secret::new(secret.0), *payload))
     }
 
     pub fn secret(&self, ptr: Ptr<F>) -> Option<Ptr<F>> {
@@ -227,8 +268,8 @@ impl<F: LurkField> Store<F> {
             _ => return None,
         };
 
-        self.fetch_comm(&p)
-            .and_then(|(secret, _payload)| self.get_num(Num::Scalar(secret.0)))
+        let (secret, _payload) = self.opening(p)?;
+        self.get_num(Num::Scalar(*secret.expose()))
     }
 
     pub fn secret_mut(&mut self, ptr: Ptr<F>) -> Result<Ptr<F>, Error> {
@@ -237,8 +278,8 @@ impl<F: LurkField> Store<F> {
             _ => return Err(Error("wrong type for commitment specifier".into())),
         };
 
-        if let Some((secret, _payload)) = self.fetch_comm(&p) {
-            let secret_element = Num::Scalar(secret.0);
+        if let Some((secret, _payload)) = self.opening(p) {
+            let secret_element = Num::Scalar(*secret.expose());
             let secret_num = self.intern_num(secret_element);
             Ok(secret_num)
         } else {
@@ -431,6 +472,17 @@ impl<F: LurkField> Store<F> {
         self.intern_opaque(ExprTag::Comm, hash)
     }
 
+    /// Interns the foreign address `#scheme:id` as an opaque commitment to the
+    /// string `"scheme:id"`, which an application resolving the address can
+    /// open. Custom resolvers are only supported by the LEM store.
+    pub fn intern_foreign(&mut self, scheme: &str, id: &str) -> Ptr<F> {
+        let payload = self.intern_string(&format!("{scheme}:{id}"));
+        let payload = self.hash_expr(&payload).expect("strings can be hashed");
+        let secret = F::NON_HIDING_COMMITMENT_SECRET;
+        let comm = ZExpr::Comm(secret, payload).z_ptr(&self.poseidon_cache);
+        self.intern_opaque_comm(*comm.value())
+    }
+
     /// Helper to allocate a list, instead of manually using `cons`.
     pub fn intern_list(&mut self, elts: &[Ptr<F>]) -> Ptr<F> {
         elts.iter()
@@ -773,6 +825,8 @@ impl<F: LurkField> Store<F> {
                 .map(|(car, cdr)| Expression::Str(car, cdr)),
             ExprTag::Char => self.fetch_char(ptr).map(Expression::Char),
             ExprTag::U64 => self.fetch_uint(ptr).map(Expression::UInt),
+            // only LEM has ratios, sets and byte vectors
+            ExprTag::Ratio | ExprTag::Set | ExprTag::Bytes => None,
         }
     }
 
@@ -892,6 +946,8 @@ impl<F: LurkField> Store<F> {
                 }),
             Dummy => Some(Continuation::Dummy),
             Terminal => Some(Continuation::Terminal),
+            // only LEM runs out of gas
+            OutOfGas => None,
             Emit => self
                 .emit_store
                 .get_index(ptr.raw.idx()?)
@@ -1002,8 +1058,14 @@ impl<F: LurkField> Store<F> {
             // TODO: should we try to dereference the opaque pointer?
             Ok((*z_ptr, None))
         } else {
-            // Store all children reachable from Ptr in ZStore
-            if z_store.is_some() {
+            // Store all children reachable from Ptr in ZStore, unless they
+            // already are, so shared subexpressions are only exported once
+            if let Some(z_store_ref) = z_store.as_ref() {
+                if let Some((z_ptr, z_expr)) = self.z_expr_ptr_cache.get(ptr) {
+                    if z_store_ref.expr_map.contains_key(z_ptr) {
+                        return Ok((*z_ptr, z_expr.clone()));
+                    }
+                }
                 let (z_ptr, z_expr) = get_z_expr_aux(z_store)?;
                 z_store
                     .as_mut()
@@ -1032,7 +1094,12 @@ impl<F: LurkField> Store<F> {
                 .ok_or(Error("get_z_cont unknown opaque ".into()))?;
             // TODO: should we try to dereference the opaque pointer?
             Ok((*z_ptr, None))
-        } else if let Some((z_ptr, z_cont)) = self.z_cont_ptr_cache.get(ptr) {
+        } else if let Some((z_ptr, z_cont)) = self.z_cont_ptr_cache.get(ptr).filter(|cached| {
+            // a cached continuation must still be exported with its children
+            z_store
+                .as_ref()
+                .map_or(true, |z_store| z_store.cont_map.contains_key(&cached.0))
+        }) {
             Ok((*z_ptr, z_cont.clone()))
         } else {
             let (z_ptr, z_cont) = match self.fetch_cont(ptr) {
@@ -1254,6 +1321,95 @@ impl<F: LurkField> Store<F> {
         Ok((store_opt.unwrap(), z_ptr))
     }
 
+    /// Exports the expressions reachable from `roots`, and only them, to a
+    /// `ZStore`, returning it along with the roots' `ZExprPtr`s. Shared
+    /// subexpressions are exported once. The secrets of the reachable
+    /// commitments are exported too, so the `ZStore` opens them.
+    pub fn export_reachable(
+        &self,
+        roots: &[Ptr<F>],
+    ) -> Result<(ZStore<F>, Vec<ZExprPtr<F>>), Error> {
+        let mut z_store = Some(ZStore::new());
+        let z_ptrs = roots
+            .iter()
+            .map(|root| Ok(self.get_z_expr(root, &mut z_store)?.0))
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok((z_store.unwrap(), z_ptrs))
+    }
+
+    /// Interns the expressions exported by `export_reachable`, returning the
+    /// roots' pointers, or `None` if `z_store` lacks some of them
+    pub fn import_reachable(
+        &mut self,
+        z_store: &ZStore<F>,
+        z_ptrs: &[ZExprPtr<F>],
+    ) -> Option<Vec<Ptr<F>>> {
+        z_ptrs
+            .iter()
+            .map(|z_ptr| self.intern_z_expr_ptr(z_ptr, z_store))
+            .collect()
+    }
+
+    /// Drops the expressions and continuations unreachable from `roots` and
+    /// compacts the store's indices, returning the pointers `roots` are now
+    /// interned at along with the numbers of entries before and after.
+    ///
+    /// The reachable DAG is marked by exporting it with `export_reachable` and
+    /// swept by interning it in a fresh store, which replaces this one. Every
+    /// pointer other than the returned ones is thus invalidated, and the
+    /// caches start over empty. Nothing catches the use of an invalidated
+    /// pointer: it refers to whatever is interned at its index afterwards, if
+    /// anything, so callers must only keep the pointers of `roots`, and re-read
+    /// or re-intern everything else.
+    ///
+    /// The exported DAG and the fresh store live alongside this store until
+    /// it's replaced, so a collection that keeps most of the store can take up
+    /// to about twice its memory at its peak.
+    pub fn gc(&mut self, roots: &[Ptr<F>]) -> Result<(Vec<Ptr<F>>, GcStats), Error> {
+        let (z_store, z_ptrs) = self.export_reachable(roots)?;
+        let mut store = Store::default();
+        let roots = store
+            .import_reachable(&z_store, &z_ptrs)
+            .ok_or_else(|| Error("gc couldn't intern the reachable expressions".into()))?;
+        let stats = GcStats {
+            exprs_before: self.expr_count(),
+            exprs_after: store.expr_count(),
+            conts_before: self.cont_count(),
+            conts_after: store.cont_count(),
+        };
+        *self = store;
+        Ok((roots, stats))
+    }
+
+    /// The number of expressions interned in the store, other than immediates
+    fn expr_count(&self) -> usize {
+        self.cons_store.len()
+            + self.comm_store.len()
+            + self.fun_store.len()
+            + self.sym_store.len()
+            + self.num_store.len()
+            + self.str_store.len()
+            + self.thunk_store.len()
+            + self.opaque_ptrs.len()
+    }
+
+    /// The number of continuations interned in the store, other than simple ones
+    fn cont_count(&self) -> usize {
+        self.call0_store.len()
+            + self.call_store.len()
+            + self.call2_store.len()
+            + self.tail_store.len()
+            + self.lookup_store.len()
+            + self.unop_store.len()
+            + self.binop_store.len()
+            + self.binop2_store.len()
+            + self.if_store.len()
+            + self.let_store.len()
+            + self.letrec_store.len()
+            + self.emit_store.len()
+            + self.opaque_cont_ptrs.len()
+    }
+
     pub fn to_z_expr(&self, ptr: &Ptr<F>) -> Option<ZExpr<F>> {
         self.get_z_expr(ptr, &mut None).ok()?.1
     }
@@ -1888,6 +2044,9 @@ pub mod test {
         assert_eq!(8, ExprTag::Comm as u64);
         assert_eq!(9, ExprTag::U64 as u64);
         assert_eq!(10, ExprTag::Key as u64);
+        assert_eq!(11, ExprTag::Ratio as u64);
+        assert_eq!(12, ExprTag::Set as u64);
+        assert_eq!(13, ExprTag::Bytes as u64);
     }
 
     #[test]
@@ -2438,4 +2597,61 @@ pub mod test {
 
         assert!(store.open(comm3).is_none());
     }
+
+    #[test]
+    fn reachable_z_store_roundtrip() {
+        let store = &mut Store::<S1>::default();
+        let shared = store.read("(1 2 3)").unwrap();
+        let comm = commit_and_open(store, shared);
+        let root = store.cons(shared, comm);
+        let root = store.cons(root, shared);
+        let unreachable = store.read("(4 5 6)").unwrap();
+
+        let (z_store, z_ptrs) = store.export_reachable(&[root]).unwrap();
+        let full_z_store = ZStore::to_z_store(store);
+        assert!(z_store.expr_map.len() < full_z_store.expr_map.len());
+        assert!(!z_store
+            .expr_map
+            .contains_key(&store.hash_expr(&unreachable).unwrap()));
+
+        let new_store = &mut Store::<S1>::default();
+        let roots = new_store.import_reachable(&z_store, &z_ptrs).unwrap();
+        assert_eq!(new_store.hash_expr(&roots[0]), Some(z_ptrs[0]));
+        let (pair, _) = new_store.car_cdr(&roots[0]).unwrap();
+        let (_, new_comm) = new_store.car_cdr(&pair).unwrap();
+        let (_, opened) = new_store.open(new_comm).unwrap();
+        assert_eq!(new_store.hash_expr(&opened), store.hash_expr(&shared));
+    }
+
+    #[test]
+    fn gc_keeps_reachable_expressions() {
+        let store = &mut Store::<S1>::default();
+        let kept = store.read("(lambda (x) (cons x \"kept\"))").unwrap();
+        let hash = store.hash_expr(&kept);
+        for i in 0..10 {
+            store
+                .read(&format!("(garbage {i} \"string {i}\")"))
+                .unwrap();
+        }
+
+        let (roots, stats) = store.gc(&[kept]).unwrap();
+        assert!(stats.exprs_after < stats.exprs_before);
+        assert!(stats.reclaimed() > 0);
+        assert_eq!(store.hash_expr(&roots[0]), hash);
+
+        // nothing is left to collect
+        let (_, stats) = store.gc(&roots).unwrap();
+        assert_eq!(stats.reclaimed(), 0);
+
+        let grown = GcStats {
+            exprs_before: 1,
+            exprs_after: 3,
+            conts_before: 2,
+            conts_after: 2,
+        };
+        assert_eq!(grown.reclaimed(), 0);
+        assert!(grown
+            .to_string()
+            .starts_with("reclaimed 0 of 1 expressions"));
+    }
 }
@@ -27,6 +27,8 @@ pub enum Syntax<F: LurkField> {
     String(Pos, String),
     // A character literal: #\A #\λ #\u03BB
     Char(Pos, char),
+    // A foreign address, a scheme and an identifier: #cid:bafybei...
+    Foreign(Pos, String, String),
     // A quoted expression: 'a, '(1 2)
     Quote(Pos, Box<Syntax<F>>),
     // A nil-terminated cons-list of expressions: (1 2 3)
@@ -79,6 +81,7 @@ impl<F: LurkField> fmt::Display for Syntax<F> {
                     write!(f, "'{}'", x.escape_default())
                 }
             }
+            Self::Foreign(_, scheme, id) => write!(f, "#{scheme}:{id}"),
             Self::Quote(_, x) => write!(f, "'{}", x),
             Self::List(_, xs) => {
                 let mut iter = xs.iter().peekable();
@@ -107,34 +110,53 @@ impl<F: LurkField> fmt::Display for Syntax<F> {
 }
 
 impl<F: LurkField> Store<F> {
+    /// Interns `syn`, keeping the lists it's nested in on the heap so that
+    /// deeply nested syntax doesn't overflow the stack
     pub fn intern_syntax(&mut self, syn: Syntax<F>) -> Ptr<F> {
-        match syn {
-            Syntax::Num(_, x) => self.intern_num(x),
-            Syntax::UInt(_, x) => self.intern_uint(x),
-            Syntax::Char(_, x) => self.intern_char(x),
-            Syntax::Symbol(_, symbol) => self.intern_symbol(&symbol),
-            Syntax::String(_, x) => self.intern_string(&x),
-            Syntax::Quote(pos, x) => {
-                let xs = vec![Syntax::Symbol(pos, lurk_sym("quote").into()), *x];
-                self.intern_syntax(Syntax::List(pos, xs))
-            }
-            Syntax::List(_, xs) => {
-                let mut cdr = lurk_sym_ptr!(self, nil);
-                for x in xs.into_iter().rev() {
-                    let car = self.intern_syntax(x);
-                    cdr = self.intern_cons(car, cdr);
+        enum Task<F: LurkField> {
+            Intern(Syntax<F>),
+            /// Conses the last interned pointer onto the one before it
+            Cons,
+        }
+        let mut tasks = vec![Task::Intern(syn)];
+        let mut ptrs = vec![];
+        while let Some(task) = tasks.pop() {
+            let syn = match task {
+                Task::Intern(syn) => syn,
+                Task::Cons => {
+                    let car = ptrs.pop().expect("car was interned");
+                    let cdr = ptrs.pop().expect("cdr was interned");
+                    ptrs.push(self.intern_cons(car, cdr));
+                    continue;
                 }
-                cdr
-            }
-            Syntax::Improper(_, xs, end) => {
-                let mut cdr = self.intern_syntax(*end);
-                for x in xs.into_iter().rev() {
-                    let car = self.intern_syntax(x);
-                    cdr = self.intern_cons(car, cdr);
+            };
+            // the elements of lists are interned from last to first
+            match syn {
+                Syntax::Num(_, x) => ptrs.push(self.intern_num(x)),
+                Syntax::UInt(_, x) => ptrs.push(self.intern_uint(x)),
+                Syntax::Char(_, x) => ptrs.push(self.intern_char(x)),
+                Syntax::Symbol(_, symbol) => ptrs.push(self.intern_symbol(&symbol)),
+                Syntax::String(_, x) => ptrs.push(self.intern_string(&x)),
+                Syntax::Foreign(_, scheme, id) => ptrs.push(self.intern_foreign(&scheme, &id)),
+                Syntax::Quote(pos, x) => {
+                    let xs = vec![Syntax::Symbol(pos, lurk_sym("quote").into()), *x];
+                    tasks.push(Task::Intern(Syntax::List(pos, xs)));
+                }
+                Syntax::List(_, xs) => {
+                    ptrs.push(lurk_sym_ptr!(self, nil));
+                    for x in xs {
+                        tasks.extend([Task::Cons, Task::Intern(x)]);
+                    }
+                }
+                Syntax::Improper(_, xs, end) => {
+                    for x in xs {
+                        tasks.extend([Task::Cons, Task::Intern(x)]);
+                    }
+                    tasks.push(Task::Intern(*end));
                 }
-                cdr
             }
         }
+        ptrs.pop().expect("syntax was interned")
     }
 
     /// Tries to fetch a syntactic list from an expression pointer, by looping over cons cells and
@@ -1,8 +1,9 @@
 use lurk_macros::TryFromRepr;
 #[cfg(not(target_arch = "wasm32"))]
 use proptest_derive::Arbitrary;
+use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
-use std::{convert::TryFrom, fmt};
+use std::{convert::TryFrom, fmt, ops::Range};
 
 use crate::field::LurkField;
 use crate::ptr::TypePredicates;
@@ -37,6 +38,9 @@ pub enum ExprTag {
     Comm,
     U64,
     Key,
+    Ratio,
+    Set,
+    Bytes,
 }
 
 impl From<ExprTag> for u16 {
@@ -65,6 +69,9 @@ impl fmt::Display for ExprTag {
             ExprTag::Char => write!(f, "char#"),
             ExprTag::Comm => write!(f, "comm#"),
             ExprTag::U64 => write!(f, "u64#"),
+            ExprTag::Ratio => write!(f, "ratio#"),
+            ExprTag::Set => write!(f, "set#"),
+            ExprTag::Bytes => write!(f, "bytes#"),
         }
     }
 }
@@ -83,7 +90,10 @@ impl TypePredicates for ExprTag {
             | Self::Char
             | Self::Comm
             | Self::U64
-            | Self::Key => true,
+            | Self::Key
+            | Self::Ratio
+            | Self::Set
+            | Self::Bytes => true,
         }
     }
 
@@ -132,6 +142,7 @@ pub enum ContTag {
     Dummy,
     Terminal,
     Emit,
+    OutOfGas,
 }
 
 impl From<ContTag> for u16 {
@@ -182,10 +193,115 @@ impl fmt::Display for ContTag {
             ContTag::Dummy => write!(f, "dummy#"),
             ContTag::Terminal => write!(f, "terminal#"),
             ContTag::Emit => write!(f, "emit#"),
+            ContTag::OutOfGas => write!(f, "out_of_gas#"),
         }
     }
 }
 
+/// The continuation tags reserved for alternate evaluators, like lazy, metered
+/// or debugging ones, which can thus add their own kinds of continuations
+/// without extending `ContTag`. Core Lurk never uses them.
+pub const USER_CONT_TAGS: Range<u16> = 0b0001_1000_0000_0000..0b0010_0000_0000_0000;
+
+/// A continuation tag of `USER_CONT_TAGS`, the `index`th of the range
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[serde(try_from = "u16", into = "u16")]
+pub struct UserContTag(u16);
+
+impl UserContTag {
+    /// The `index`th tag of `USER_CONT_TAGS`, if the range is that long
+    pub fn new(index: u16) -> Option<Self> {
+        let code = USER_CONT_TAGS.start.checked_add(index)?;
+        USER_CONT_TAGS.contains(&code).then_some(Self(code))
+    }
+
+    #[inline]
+    pub fn index(&self) -> u16 {
+        self.0 - USER_CONT_TAGS.start
+    }
+}
+
+impl From<UserContTag> for u16 {
+    fn from(val: UserContTag) -> Self {
+        val.0
+    }
+}
+
+impl From<UserContTag> for u64 {
+    fn from(val: UserContTag) -> Self {
+        val.0 as u64
+    }
+}
+
+impl TryFrom<u16> for UserContTag {
+    type Error = anyhow::Error;
+
+    fn try_from(code: u16) -> Result<Self, Self::Error> {
+        if USER_CONT_TAGS.contains(&code) {
+            Ok(Self(code))
+        } else {
+            Err(anyhow::anyhow!("{code:#x} isn't a user continuation tag"))
+        }
+    }
+}
+
+impl Tag for UserContTag {
+    fn from_field<F: LurkField>(f: &F) -> Option<Self> {
+        Self::try_from(f.to_u16()?).ok()
+    }
+
+    fn to_field<F: LurkField>(&self) -> F {
+        F::from(self.0 as u64)
+    }
+
+    fn to_field_bytes<F: LurkField>(&self) -> F::Repr {
+        let mut res = F::Repr::default();
+        res.as_mut()[..2].copy_from_slice(&self.0.to_le_bytes());
+        res
+    }
+}
+
+impl fmt::Display for UserContTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "user{}#", self.index())
+    }
+}
+
+/// Names for the tags of `USER_CONT_TAGS`, which are handed out in the order
+/// the names are registered. Evaluators that register the same names in the
+/// same order thus agree on their tags, and on the hashes of continuations.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ContTagRegistry {
+    names: Vec<String>,
+}
+
+impl ContTagRegistry {
+    /// The tag registered as `name`, registering it under the next free tag
+    /// if it isn't yet. Fails once every tag of the range is taken.
+    pub fn register(&mut self, name: &str) -> anyhow::Result<UserContTag> {
+        if let Some(tag) = self.get(name) {
+            return Ok(tag);
+        }
+        let tag = u16::try_from(self.names.len())
+            .ok()
+            .and_then(UserContTag::new)
+            .ok_or_else(|| anyhow::anyhow!("No user continuation tag left for {name}"))?;
+        self.names.push(name.into());
+        Ok(tag)
+    }
+
+    /// The tag registered as `name`, if any
+    pub fn get(&self, name: &str) -> Option<UserContTag> {
+        let index = self.names.iter().position(|n| n == name)?;
+        UserContTag::new(index as u16)
+    }
+
+    /// The name `tag` was registered as, if any
+    pub fn name(&self, tag: UserContTag) -> Option<&str> {
+        self.names.get(tag.index() as usize).map(String::as_str)
+    }
+}
+
 #[derive(
     Copy,
     Clone,
@@ -490,6 +606,38 @@ pub mod tests {
     }
     }
 
+    proptest! {
+    #[test]
+    fn prop_user_cont_tag_u16(index in 0..USER_CONT_TAGS.len() as u16) {
+        let x = UserContTag::new(index).expect("index in range");
+        let x_u16: u16 = x.into();
+        assert!(ContTag::try_from(x_u16).is_err());
+        assert_eq!(UserContTag::try_from(x_u16).expect("read UserContTag from u16"), x);
+        assert_eq!(x.index(), index);
+    }
+    }
+
+    #[test]
+    fn user_cont_tags_are_registered_in_order() {
+        assert_eq!(UserContTag::new(USER_CONT_TAGS.len() as u16), None);
+        assert!(UserContTag::try_from(ContTag::Outermost as u16).is_err());
+
+        let mut registry = ContTagRegistry::default();
+        let lazy = registry.register("lazy").unwrap();
+        let metered = registry.register("metered").unwrap();
+        assert_eq!((lazy.index(), metered.index()), (0, 1));
+        assert_eq!(registry.register("lazy").unwrap(), lazy);
+        assert_eq!(registry.get("metered"), Some(metered));
+        assert_eq!(registry.get("debug"), None);
+        assert_eq!(registry.name(metered), Some("metered"));
+        assert_eq!(lazy.to_string(), "user0#");
+
+        for i in 2..USER_CONT_TAGS.len() {
+            registry.register(&format!("tag{i}")).unwrap();
+        }
+        assert!(registry.register("one-too-many").is_err());
+    }
+
     proptest! {
     #[test]
     fn prop_op1_u16(x in any::<Op1>()) {
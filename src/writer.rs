@@ -10,17 +10,52 @@ use crate::symbol::Symbol;
 use crate::z_expr::ZExpr;
 use std::io;
 
+/// How much of long or deeply nested lists is printed. The elements left
+/// out are printed as `…`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Elision {
+    /// Lists nested in this many others are printed as `…`
+    pub max_depth: Option<usize>,
+    /// Lists print this many elements at most, followed by `…`
+    pub max_length: Option<usize>,
+}
+
 pub trait Write<F: LurkField> {
     fn fmt<W: io::Write>(&self, store: &Store<F>, state: &State, w: &mut W) -> io::Result<()>;
+    fn fmt_elided<W: io::Write>(
+        &self,
+        store: &Store<F>,
+        state: &State,
+        _elision: Elision,
+        w: &mut W,
+    ) -> io::Result<()> {
+        self.fmt(store, state, w)
+    }
     fn fmt_to_string(&self, store: &Store<F>, state: &State) -> String {
         let mut out = Vec::new();
         self.fmt(store, state, &mut out).expect("preallocated");
         String::from_utf8(out).expect("I know it")
     }
+    fn fmt_to_string_elided(&self, store: &Store<F>, state: &State, elision: Elision) -> String {
+        let mut out = Vec::new();
+        self.fmt_elided(store, state, elision, &mut out)
+            .expect("preallocated");
+        String::from_utf8(out).expect("I know it")
+    }
 }
 
 impl<F: LurkField> Write<F> for Ptr<F> {
     fn fmt<W: io::Write>(&self, store: &Store<F>, state: &State, w: &mut W) -> io::Result<()> {
+        self.fmt_elided(store, state, Elision::default(), w)
+    }
+
+    fn fmt_elided<W: io::Write>(
+        &self,
+        store: &Store<F>,
+        state: &State,
+        elision: Elision,
+        w: &mut W,
+    ) -> io::Result<()> {
         if self.is_opaque() {
             // This should never fail.
             write!(w, "<Opaque ")?;
@@ -33,7 +68,7 @@ impl<F: LurkField> Write<F> for Ptr<F> {
             }
             write!(w, ">")
         } else if let Some(expr) = store.fetch(self) {
-            expr.fmt(store, state, w)
+            expr.fmt_elided(store, state, elision, w)
         } else {
             Err(io::Error::new(
                 io::ErrorKind::Other,
@@ -62,9 +97,20 @@ fn write_symbol<W: io::Write>(w: &mut W, sym: Symbol, state: &State) -> io::Resu
 
 impl<F: LurkField> Write<F> for Expression<F> {
     fn fmt<W: io::Write>(&self, store: &Store<F>, state: &State, w: &mut W) -> io::Result<()> {
+        self.fmt_elided(store, state, Elision::default(), w)
+    }
+
+    fn fmt_elided<W: io::Write>(
+        &self,
+        store: &Store<F>,
+        state: &State,
+        elision: Elision,
+        w: &mut W,
+    ) -> io::Result<()> {
         use Expression::*;
 
         match self {
+            Cons(..) | Fun(..) => write_nested(self.clone(), store, state, elision, w),
             Nil => write!(w, "nil"),
             RootSym => write_symbol(w, Symbol::root_sym(), state),
             RootKey => write_symbol(w, Symbol::root_key(), state),
@@ -85,42 +131,14 @@ impl<F: LurkField> Write<F> for Expression<F> {
                 let tail = store.fetch_string(cdr).expect("missing string tail");
                 write!(w, "\"{head}{tail}\"")
             }
-            Fun(arg, body, _closed_env) => {
-                let is_zero_arg = *arg == lurk_sym_ptr!(store, dummy);
-                let arg = store.fetch(arg).unwrap();
-                write!(w, "<FUNCTION (")?;
-                if !is_zero_arg {
-                    arg.fmt(store, state, w)?;
-                }
-                write!(w, ") ")?;
-
-                //Assume body is a single-element cons, ignore the cdr
-                match store.fetch(body).unwrap() {
-                    Expression::Cons(expr, _) => {
-                        let expr = store.fetch(&expr).unwrap();
-                        expr.fmt(store, state, w)?;
-                    }
-                    Expression::Nil => {
-                        lurk_sym_ptr!(store, nil).fmt(store, state, w)?;
-                    }
-                    _ => {
-                        panic!("Function body was neither a Cons nor Nil");
-                    }
-                }
-                write!(w, ">")
-            }
             Num(n) => write!(w, "{n}"),
             Thunk(f) => {
                 write!(w, "Thunk{{ value: ")?;
-                f.value.fmt(store, state, w)?;
+                f.value.fmt_elided(store, state, elision, w)?;
                 write!(w, " => cont: ")?;
                 f.continuation.fmt(store, state, w)?;
                 write!(w, "}}")
             }
-            Cons(_, _) => {
-                write!(w, "(")?;
-                self.print_tail(store, state, w)
-            }
             Comm(secret, payload) => {
                 // This requires a run-time coercion.
                 // Consider implementing the equivalent of CL's #. reader macro to let this happen at read-time.
@@ -138,59 +156,82 @@ impl<F: LurkField> Write<F> for Expression<F> {
     }
 }
 
-impl<F: LurkField> Expression<F> {
-    fn print_tail<W: io::Write>(
-        &self,
-        store: &Store<F>,
-        state: &State,
-        w: &mut W,
-    ) -> io::Result<()> {
-        match self {
-            Expression::Nil => write!(w, ")"),
-            Expression::Cons(car, cdr) => {
-                let car = store.fetch(car);
-                let cdr = store.fetch(cdr);
-                let fmt_car = |store, w: &mut W| {
-                    if let Some(car) = car {
-                        car.fmt(store, state, w)
-                    } else {
-                        write!(w, "<Opaque>")
-                    }
-                };
-                let fmt_cdr = |store, w: &mut W| {
-                    if let Some(cdr) = &cdr {
-                        cdr.fmt(store, state, w)
-                    } else {
-                        write!(w, "<Opaque>")
-                    }
-                };
+/// A part of an expression that's left to print
+enum Piece<F: LurkField> {
+    /// An expression, nested in the given number of lists, or an opaque one
+    Expr(Option<Expression<F>>, usize),
+    /// The rest of a list nested in the given number of lists, after the
+    /// given number of elements
+    Tail(Expression<F>, usize, usize),
+    Text(&'static str),
+}
 
-                match cdr {
-                    Some(Expression::Nil) => {
-                        fmt_car(store, w)?;
-                        write!(w, ")")
+/// Prints lists and functions, keeping the pieces left to print on the heap
+/// so that deeply nested expressions don't overflow the stack
+fn write_nested<F: LurkField, W: io::Write>(
+    expr: Expression<F>,
+    store: &Store<F>,
+    state: &State,
+    elision: Elision,
+    w: &mut W,
+) -> io::Result<()> {
+    use Expression::*;
+
+    let mut pieces = vec![Piece::Expr(Some(expr), 0)];
+    while let Some(piece) = pieces.pop() {
+        match piece {
+            Piece::Text(text) => write!(w, "{text}")?,
+            Piece::Expr(None, _) => write!(w, "<Opaque>")?,
+            Piece::Expr(Some(expr @ Cons(..)), depth) => {
+                if elision.max_depth.is_some_and(|max| depth >= max) {
+                    write!(w, "…")?;
+                } else {
+                    write!(w, "(")?;
+                    pieces.push(Piece::Tail(expr, depth, 0));
+                }
+            }
+            Piece::Expr(Some(Fun(arg, body, _closed_env)), depth) => {
+                let is_zero_arg = arg == lurk_sym_ptr!(store, dummy);
+                write!(w, "<FUNCTION (")?;
+                pieces.push(Piece::Text(">"));
+                //Assume body is a single-element cons, ignore the cdr
+                match store.fetch(&body).unwrap() {
+                    Cons(expr, _) => pieces.push(Piece::Expr(store.fetch(&expr), depth)),
+                    Nil => pieces.push(Piece::Expr(store.fetch(&lurk_sym_ptr!(store, nil)), depth)),
+                    _ => {
+                        panic!("Function body was neither a Cons nor Nil");
                     }
-                    Some(Expression::Cons(_, _)) => {
-                        fmt_car(store, w)?;
-                        write!(w, " ")?;
-                        if let Some(cdr) = cdr {
-                            cdr.print_tail(store, state, w)
-                        } else {
-                            write!(w, "<Opaque Tail>")
-                        }
+                }
+                pieces.push(Piece::Text(") "));
+                if !is_zero_arg {
+                    pieces.push(Piece::Expr(store.fetch(&arg), depth));
+                }
+            }
+            Piece::Expr(Some(expr), _) => expr.fmt_elided(store, state, elision, w)?,
+            Piece::Tail(Nil, _, _) => write!(w, ")")?,
+            Piece::Tail(Cons(car, cdr), depth, printed) => {
+                if elision.max_length.is_some_and(|max| printed >= max) {
+                    write!(w, "…)")?;
+                    continue;
+                }
+                let car = Piece::Expr(store.fetch(&car), depth + 1);
+                match store.fetch(&cdr) {
+                    Some(Nil) => pieces.extend([Piece::Text(")"), car]),
+                    Some(cdr @ Cons(..)) => {
+                        let tail = Piece::Tail(cdr, depth, printed + 1);
+                        pieces.extend([tail, Piece::Text(" "), car]);
                     }
-                    Some(_) => {
-                        fmt_car(store, w)?;
-                        write!(w, " . ")?;
-                        fmt_cdr(store, w)?;
-                        write!(w, ")")
+                    Some(cdr) => {
+                        let cdr = Piece::Expr(Some(cdr), depth + 1);
+                        pieces.extend([Piece::Text(")"), cdr, Piece::Text(" . "), car]);
                     }
-                    None => write!(w, "<Opaque>"),
+                    None => write!(w, "<Opaque>")?,
                 }
             }
-            expr => expr.fmt(store, state, w),
+            Piece::Tail(expr, depth, _) => pieces.push(Piece::Expr(Some(expr), depth)),
         }
     }
+    Ok(())
 }
 
 impl<F: LurkField> Write<F> for Continuation<F> {
@@ -387,4 +428,36 @@ pub mod test {
         let foo_key_str = foo_key_ptr.fmt_to_string(&store, initial_lurk_state());
         assert_eq!(":foo", foo_key_str);
     }
+
+    #[test]
+    fn deep_and_long_lists_are_printed() {
+        let mut store = Store::<Fr>::default();
+        let state = initial_lurk_state();
+        let depth = 100_000;
+        let deep = format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        let long = format!("({}. 2)", "1 ".repeat(100_000));
+        for source in [deep, long] {
+            let expr = store.read(&source).unwrap();
+            assert_eq!(expr.fmt_to_string(&store, state), source);
+        }
+    }
+
+    #[test]
+    fn lists_are_elided() {
+        let mut store = Store::<Fr>::default();
+        let state = initial_lurk_state();
+        let expr = store.read("(1 (2 (3 4)) 5 . 6)").unwrap();
+        let elided = |max_depth, max_length| {
+            let elision = Elision {
+                max_depth,
+                max_length,
+            };
+            expr.fmt_to_string_elided(&store, state, elision)
+        };
+        assert_eq!(elided(None, None), "(1 (2 (3 4)) 5 . 6)");
+        assert_eq!(elided(Some(0), None), "…");
+        assert_eq!(elided(Some(1), None), "(1 … 5 . 6)");
+        assert_eq!(elided(Some(2), Some(1)), "(1 …)");
+        assert_eq!(elided(None, Some(2)), "(1 (2 (3 4)) …)");
+    }
 }
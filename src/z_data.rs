@@ -10,6 +10,15 @@
 //!
 //! This module also provides several traits that can be used to encode and decode Rust types into
 //! `ZData` values.
+//!
+//! # Versions
+//!
+//! Bytes meant to be stored, like serialized stores, should be written with
+//! [`ZData::to_versioned_bytes`], which prefixes the encoding with [`MAGIC`] and
+//! a [`Version`] byte, and read back with [`ZData::from_versioned_bytes`], which
+//! also reads every previous version. Any change to the encoding must come with
+//! a new version, keeping the decoders of the previous ones, and golden vectors
+//! for it in the tests below.
 
 use std::fmt::Display;
 
@@ -22,6 +31,9 @@ use nom::multi::count;
 use nom::Finish;
 use nom::IResult;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub mod chunked;
+pub mod ipld;
 pub mod serde;
 pub mod z_cont;
 pub mod z_expr;
@@ -30,6 +42,35 @@ pub mod z_store;
 
 pub use self::serde::{from_z_data, to_z_data};
 
+/// The bytes a versioned encoding starts with, followed by the version byte.
+///
+/// The unversioned encoding of a cell starts with a byte whose highest bit is
+/// set, so it can't be mistaken for this header. Since structs, maps and
+/// sequences are all encoded as cells, so is anything worth storing.
+pub const MAGIC: [u8; 2] = *b"ZD";
+
+/// The versions of the byte encoding of `ZData`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Version {
+    /// The original encoding, without header, still read for compatibility
+    V0 = 0,
+    /// `MAGIC` and the version byte, followed by the `V0` encoding
+    V1 = 1,
+}
+
+impl Version {
+    /// The version bytes are written with
+    pub const CURRENT: Self = Self::V1;
+
+    /// The version a version byte stands for, if any. `V0` has no version byte.
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            1 => Some(Self::V1),
+            _ => None,
+        }
+    }
+}
+
 /// `ZData` is a binary tree with two types of nodes: `Atom` and `Cell`.
 ///
 /// # Examples
@@ -173,6 +214,56 @@ impl ZData {
         }
     }
 
+    /// Serializes this `ZData` with the current version of the encoding
+    pub fn to_versioned_bytes(&self) -> Vec<u8> {
+        self.to_bytes_with_version(Version::CURRENT)
+    }
+
+    /// Serializes this `ZData` with an arbitrary version of the encoding, for
+    /// producing data readable by older versions of Lurk
+    pub fn to_bytes_with_version(&self, version: Version) -> Vec<u8> {
+        match version {
+            Version::V0 => self.to_bytes(),
+            Version::V1 => {
+                let mut res = MAGIC.to_vec();
+                res.push(version as u8);
+                res.extend(self.to_bytes());
+                res
+            }
+        }
+    }
+
+    /// The version of the encoding `i` was written with
+    ///
+    /// # Errors
+    ///
+    /// This function errors if `i` has a header with an unknown version
+    pub fn version_of(i: &[u8]) -> anyhow::Result<Version> {
+        match i {
+            [m0, m1, version, ..] if [*m0, *m1] == MAGIC => Version::from_byte(*version)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Unsupported ZData version {version}, the latest known is {}",
+                        Version::CURRENT as u8
+                    )
+                }),
+            _ => Ok(Version::V0),
+        }
+    }
+
+    /// Deserializes a `ZData` written with any known version of the encoding.
+    ///
+    /// # Errors
+    ///
+    /// This function errors if the version is unknown or if the bytes don't
+    /// correspond to a valid serialization in that version
+    pub fn from_versioned_bytes(i: &[u8]) -> anyhow::Result<Self> {
+        match Self::version_of(i)? {
+            Version::V0 => Self::from_bytes(i),
+            Version::V1 => Self::from_bytes(&i[MAGIC.len() + 1..]),
+        }
+    }
+
     #[inline]
     fn from_bytes_aux(i: &[u8]) -> IResult<&[u8], Self> {
         let (i, tag) = take(1u8)(i)?;
@@ -296,7 +387,56 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn golden_vectors() {
+        let zd = ZData::Cell(vec![ZData::Atom(vec![1]), ZData::Atom(vec![])]);
+        let v0 = vec![0b1100_0010, 0b0100_0001, 1, 0b0000_0000];
+        let v1 = vec![b'Z', b'D', 1, 0b1100_0010, 0b0100_0001, 1, 0b0000_0000];
+
+        assert_eq!(zd.to_bytes_with_version(Version::V0), v0);
+        assert_eq!(zd.to_bytes_with_version(Version::V1), v1);
+        assert_eq!(zd.to_versioned_bytes(), v1);
+        assert_eq!(ZData::version_of(&v0).unwrap(), Version::V0);
+        assert_eq!(ZData::version_of(&v1).unwrap(), Version::V1);
+        assert_eq!(ZData::from_versioned_bytes(&v0).unwrap(), zd);
+        assert_eq!(ZData::from_versioned_bytes(&v1).unwrap(), zd);
+
+        let mut unknown = v1;
+        unknown[2] = 0xff;
+        assert!(ZData::from_versioned_bytes(&unknown).is_err());
+    }
+
+    #[test]
+    fn z_store_reads_across_versions() {
+        use crate::{store::Store, z_store::ZStore};
+        use pasta_curves::pallas::Scalar as Fr;
+
+        let store = &mut Store::<Fr>::default();
+        let expr = store.read("(cons 'a \"b\")").unwrap();
+        store.hydrate_scalar_cache();
+        let (z_store, z_ptr) = ZStore::new_with_expr(store, &expr);
+        assert!(z_ptr.is_some());
+        let zd = to_z_data(&z_store).unwrap();
+        for version in [Version::V0, Version::V1] {
+            let bytes = zd.to_bytes_with_version(version);
+            let zd_read = ZData::from_versioned_bytes(&bytes).unwrap();
+            let z_store_read: ZStore<Fr> = from_z_data(&zd_read).unwrap();
+            assert_eq!(z_store_read, z_store);
+        }
+    }
+
     proptest! {
+        #[test]
+        fn prop_z_data_versions(x in any::<ZData>()) {
+            // V0 data is only recognized as such when it's a cell
+            let x = ZData::Cell(vec![x]);
+            for version in [Version::V0, Version::V1] {
+                let ser = x.to_bytes_with_version(version);
+                assert_eq!(ZData::version_of(&ser).unwrap(), version);
+                assert_eq!(x, ZData::from_versioned_bytes(&ser).expect("read ZData"));
+            }
+        }
+
         #[test]
         fn prop_z_data_bytes(x in any::<ZData>()) {
             let ser = x.to_bytes();
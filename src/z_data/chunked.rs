@@ -0,0 +1,379 @@
+//! A chunked, zstd-compressed file format for `ZStore`s.
+//!
+//! Stores of several gigabytes don't fit in memory as one `ZData`, so this
+//! format splits them into chunks of a bounded number of entries, each one a
+//! `ZStore` of its own, encoded as versioned `ZData` and compressed with zstd.
+//! A `ChunkedWriter` writes the chunks as entries come in, and a
+//! `ChunkedReader` decodes them one at a time, fetching single entries by
+//! decoding the chunk that holds them only.
+//!
+//! The file starts with `CHUNKED_MAGIC`, a version byte and the offset and
+//! length of the index, which follows the chunks and is compressed the same
+//! way. The index has the location of each chunk and the chunk of each entry.
+//! Offsets are relative to the start of the header, so a chunked store can be
+//! embedded in a larger file.
+//!
+//! Readers don't trust the lengths the file claims: buffers grow as bytes are
+//! read, and chunks and the index decompress to `MAX_DECOMPRESSED_LEN` bytes
+//! at most, which writers check too.
+
+use anyhow::{bail, Context, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io::{Read, Seek, SeekFrom, Write},
+};
+
+use crate::{
+    field::LurkField,
+    z_cont::ZCont,
+    z_data::{from_z_data, to_z_data, ZData},
+    z_expr::ZExpr,
+    z_ptr::{ZContPtr, ZExprPtr},
+    z_store::ZStore,
+};
+
+/// The bytes a chunked store starts with
+pub const CHUNKED_MAGIC: [u8; 4] = *b"ZSTC";
+const CHUNKED_VERSION: u8 = 1;
+/// The magic, the version and the offset and length of the index
+const HEADER_LEN: u64 = 4 + 1 + 8 + 8;
+const ZSTD_LEVEL: i32 = 3;
+/// The most bytes a chunk or the index decompresses to
+pub const MAX_DECOMPRESSED_LEN: u64 = 1 << 30;
+
+/// A number of entries per chunk that keeps chunks around a few megabytes
+pub const DEFAULT_CHUNK_LEN: usize = 1 << 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct ChunkLocation {
+    offset: u64,
+    len: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct ChunkIndex<F: LurkField> {
+    chunks: Vec<ChunkLocation>,
+    exprs: BTreeMap<ZExprPtr<F>, u32>,
+    conts: BTreeMap<ZContPtr<F>, u32>,
+}
+
+fn compress<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    let bytes = to_z_data(value)?.to_versioned_bytes();
+    if bytes.len() as u64 > MAX_DECOMPRESSED_LEN {
+        bail!("{} bytes are too many for a chunk or an index", bytes.len());
+    }
+    Ok(zstd::encode_all(&bytes[..], ZSTD_LEVEL)?)
+}
+
+fn decompress<T: DeserializeOwned>(compressed: &[u8]) -> Result<T> {
+    let mut bytes = vec![];
+    zstd::Decoder::new(compressed)?
+        .take(MAX_DECOMPRESSED_LEN + 1)
+        .read_to_end(&mut bytes)?;
+    if bytes.len() as u64 > MAX_DECOMPRESSED_LEN {
+        bail!("Decompresses to more than {MAX_DECOMPRESSED_LEN} bytes");
+    }
+    Ok(from_z_data(&ZData::from_versioned_bytes(&bytes)?)?)
+}
+
+/// Reads the `len` bytes at `offset` of `input`, growing the buffer as they
+/// arrive, so that a corrupted `len` doesn't allocate more than there is
+fn read_at<R: Read + Seek>(input: &mut R, offset: u64, len: u64) -> Result<Vec<u8>> {
+    input.seek(SeekFrom::Start(offset))?;
+    let mut bytes = vec![];
+    input.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        bail!("Expected {len} bytes, found {}", bytes.len());
+    }
+    Ok(bytes)
+}
+
+/// Writes the entries it's given to a chunked store as they come in, holding
+/// at most a chunk of them in memory, besides the index
+pub struct ChunkedWriter<F: LurkField, W: Write + Seek> {
+    out: W,
+    start: u64,
+    /// The end of the last chunk written, relative to `start`
+    end: u64,
+    chunk_len: usize,
+    pending: ZStore<F>,
+    index: ChunkIndex<F>,
+}
+
+impl<F: LurkField + Serialize, W: Write + Seek> ChunkedWriter<F, W> {
+    /// Starts a chunked store at the current position of `out`, with chunks of
+    /// `chunk_len` entries
+    pub fn new(mut out: W, chunk_len: usize) -> Result<Self> {
+        if chunk_len == 0 {
+            bail!("Chunks must hold at least one entry");
+        }
+        let start = out.stream_position()?;
+        out.write_all(&CHUNKED_MAGIC)?;
+        out.write_all(&[CHUNKED_VERSION])?;
+        // the offset and length of the index, written by `finish`
+        out.write_all(&[0; 16])?;
+        Ok(Self {
+            out,
+            start,
+            end: HEADER_LEN,
+            chunk_len,
+            pending: ZStore::new(),
+            index: ChunkIndex {
+                chunks: vec![],
+                exprs: BTreeMap::new(),
+                conts: BTreeMap::new(),
+            },
+        })
+    }
+
+    /// Adds an expression, or an opaque one if `expr` is `None`. Entries that
+    /// were already written are skipped.
+    pub fn insert_expr(&mut self, ptr: ZExprPtr<F>, expr: Option<ZExpr<F>>) -> Result<()> {
+        if !self.index.exprs.contains_key(&ptr) {
+            self.pending.expr_map.insert(ptr, expr);
+        }
+        self.flush_if_full()
+    }
+
+    /// Adds a continuation, or an opaque one if `cont` is `None`. Entries that
+    /// were already written are skipped.
+    pub fn insert_cont(&mut self, ptr: ZContPtr<F>, cont: Option<ZCont<F>>) -> Result<()> {
+        if !self.index.conts.contains_key(&ptr) {
+            self.pending.cont_map.insert(ptr, cont);
+        }
+        self.flush_if_full()
+    }
+
+    fn flush_if_full(&mut self) -> Result<()> {
+        if self.pending.expr_map.len() + self.pending.cont_map.len() >= self.chunk_len {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the pending entries as a chunk
+    fn flush(&mut self) -> Result<()> {
+        if self.pending.expr_map.is_empty() && self.pending.cont_map.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.pending, ZStore::new());
+        let bytes = compress(&chunk)?;
+        self.out.write_all(&bytes)?;
+        let n = u32::try_from(self.index.chunks.len()).context("Too many chunks")?;
+        self.index
+            .exprs
+            .extend(chunk.expr_map.into_keys().map(|ptr| (ptr, n)));
+        self.index
+            .conts
+            .extend(chunk.cont_map.into_keys().map(|ptr| (ptr, n)));
+        let len = bytes.len() as u64;
+        self.index.chunks.push(ChunkLocation {
+            offset: self.end,
+            len,
+        });
+        self.end += len;
+        Ok(())
+    }
+
+    /// Writes the last chunk and the index, returning the writer positioned
+    /// at the end of the chunked store
+    pub fn finish(mut self) -> Result<W> {
+        self.flush()?;
+        let index = compress(&self.index)?;
+        self.out.write_all(&index)?;
+        let end = self.out.stream_position()?;
+        self.out.seek(SeekFrom::Start(self.start + 5))?;
+        self.out.write_all(&self.end.to_le_bytes())?;
+        self.out.write_all(&(index.len() as u64).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(end))?;
+        Ok(self.out)
+    }
+}
+
+/// Reads a chunked store, decoding its chunks as they're needed
+pub struct ChunkedReader<F: LurkField, R: Read + Seek> {
+    input: R,
+    start: u64,
+    index: ChunkIndex<F>,
+    /// The last chunk decoded, which consecutive lookups often hit again
+    cached: Option<(u32, ZStore<F>)>,
+}
+
+impl<F: LurkField + DeserializeOwned, R: Read + Seek> ChunkedReader<F, R> {
+    /// Reads the header and the index of the chunked store at the current
+    /// position of `input`
+    pub fn open(mut input: R) -> Result<Self> {
+        let start = input.stream_position()?;
+        let mut header = [0; HEADER_LEN as usize];
+        input
+            .read_exact(&mut header)
+            .context("Missing chunked store header")?;
+        if header[..4] != CHUNKED_MAGIC {
+            bail!("Not a chunked store");
+        }
+        if header[4] != CHUNKED_VERSION {
+            bail!("Unknown chunked store version {}", header[4]);
+        }
+        let index_offset = u64::from_le_bytes(header[5..13].try_into()?);
+        let index_len = u64::from_le_bytes(header[13..].try_into()?);
+        if index_offset < HEADER_LEN {
+            bail!("Unfinished chunked store");
+        }
+        let bytes = read_at(&mut input, start + index_offset, index_len)
+            .context("Truncated chunk index")?;
+        let index: ChunkIndex<F> = decompress(&bytes).context("Corrupted chunk index")?;
+        Ok(Self {
+            input,
+            start,
+            index,
+            cached: None,
+        })
+    }
+
+    /// The number of chunks of the store
+    pub fn num_chunks(&self) -> usize {
+        self.index.chunks.len()
+    }
+
+    /// Decodes the chunk `n`
+    pub fn read_chunk(&mut self, n: usize) -> Result<ZStore<F>> {
+        let Some(location) = self.index.chunks.get(n) else {
+            bail!("There's no chunk {n}");
+        };
+        let offset = self.start + location.offset;
+        let bytes = read_at(&mut self.input, offset, location.len)
+            .with_context(|| format!("Truncated chunk {n}"))?;
+        decompress(&bytes).with_context(|| format!("Corrupted chunk {n}"))
+    }
+
+    /// The chunk `n`, decoded unless it was the last one decoded
+    fn chunk(&mut self, n: u32) -> Result<&ZStore<F>> {
+        if !matches!(&self.cached, Some((cached, _)) if *cached == n) {
+            let chunk = self.read_chunk(n as usize)?;
+            self.cached = Some((n, chunk));
+        }
+        Ok(&self.cached.as_ref().expect("just cached").1)
+    }
+
+    /// The expression at `ptr`, if the store has it and it's not opaque,
+    /// decoding only the chunk that holds it
+    pub fn get_expr(&mut self, ptr: &ZExprPtr<F>) -> Result<Option<ZExpr<F>>> {
+        if let Some(expr) = ZStore::immediate_z_expr(ptr) {
+            return Ok(Some(expr));
+        }
+        match self.index.exprs.get(ptr) {
+            Some(&n) => Ok(self.chunk(n)?.get_expr(ptr)),
+            None => Ok(None),
+        }
+    }
+
+    /// The continuation at `ptr`, if the store has it and it's not opaque,
+    /// decoding only the chunk that holds it
+    pub fn get_cont(&mut self, ptr: &ZContPtr<F>) -> Result<Option<ZCont<F>>> {
+        match self.index.conts.get(ptr) {
+            Some(&n) => Ok(self.chunk(n)?.get_cont(ptr)),
+            None => Ok(None),
+        }
+    }
+
+    /// Decodes the whole store
+    pub fn to_z_store(&mut self) -> Result<ZStore<F>> {
+        let mut z_store = ZStore::new();
+        for n in 0..self.num_chunks() {
+            let chunk = self.read_chunk(n)?;
+            z_store.expr_map.extend(chunk.expr_map);
+            z_store.cont_map.extend(chunk.cont_map);
+        }
+        Ok(z_store)
+    }
+}
+
+impl<F: LurkField + Serialize> ZStore<F> {
+    /// Writes the `ZStore` to `out` as a chunked store, with `chunk_len`
+    /// entries per chunk
+    pub fn write_chunked<W: Write + Seek>(&self, out: W, chunk_len: usize) -> Result<W> {
+        let mut writer = ChunkedWriter::new(out, chunk_len)?;
+        for (ptr, expr) in &self.expr_map {
+            writer.insert_expr(*ptr, expr.clone())?;
+        }
+        for (ptr, cont) in &self.cont_map {
+            writer.insert_cont(*ptr, cont.clone())?;
+        }
+        writer.finish()
+    }
+}
+
+impl<F: LurkField + DeserializeOwned> ZStore<F> {
+    /// Reads a whole chunked store from `input`
+    pub fn read_chunked<R: Read + Seek>(input: R) -> Result<Self> {
+        ChunkedReader::open(input)?.to_z_store()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::Store;
+    use pasta_curves::pallas::Scalar as Fr;
+    use std::io::Cursor;
+
+    #[test]
+    fn chunked_stores_are_read_back() {
+        let store = &mut Store::<Fr>::default();
+        for i in 0..20 {
+            store.read(&format!("(cons {i} \"entry {i}\")")).unwrap();
+        }
+        let mut z_store = ZStore::to_z_store(store);
+        let opaque = ZExprPtr::from_parts(crate::tag::ExprTag::Cons, Fr::from(42));
+        z_store.expr_map.insert(opaque, None);
+
+        let bytes = z_store.write_chunked(Cursor::new(vec![]), 7).unwrap();
+        let input = Cursor::new(bytes.into_inner());
+        let mut reader = ChunkedReader::<Fr, _>::open(input).unwrap();
+        let entries = z_store.expr_map.len() + z_store.cont_map.len();
+        assert_eq!(reader.num_chunks(), (entries + 6) / 7);
+        for ptr in z_store.expr_map.keys() {
+            assert_eq!(reader.get_expr(ptr).unwrap(), z_store.get_expr(ptr));
+        }
+        for ptr in z_store.cont_map.keys() {
+            assert_eq!(reader.get_cont(ptr).unwrap(), z_store.get_cont(ptr));
+        }
+        assert_eq!(reader.get_expr(&opaque).unwrap(), None);
+        let missing = ZExprPtr::from_parts(crate::tag::ExprTag::Cons, Fr::from(43));
+        assert_eq!(reader.get_expr(&missing).unwrap(), None);
+        assert_eq!(reader.to_z_store().unwrap(), z_store);
+    }
+
+    #[test]
+    fn chunked_stores_are_embedded_and_checked() {
+        let store = &mut Store::<Fr>::default();
+        store.read("(1 2 3)").unwrap();
+        let z_store = ZStore::to_z_store(store);
+        let mut out = Cursor::new(b"prefix".to_vec());
+        out.seek(SeekFrom::End(0)).unwrap();
+        let mut bytes = z_store.write_chunked(out, 2).unwrap().into_inner();
+        // trailing data is ignored
+        bytes.extend(b"suffix");
+
+        let mut embedded = Cursor::new(&bytes[..]);
+        embedded.seek(SeekFrom::Start(6)).unwrap();
+        assert_eq!(ZStore::<Fr>::read_chunked(embedded).unwrap(), z_store);
+        assert!(ZStore::<Fr>::read_chunked(Cursor::new(&bytes[..])).is_err());
+        let mut truncated = Cursor::new(&bytes[..bytes.len() - 7]);
+        truncated.seek(SeekFrom::Start(6)).unwrap();
+        assert!(ZStore::<Fr>::read_chunked(truncated).is_err());
+
+        // nor are stores claiming more bytes than there are
+        let mut huge = bytes.clone();
+        huge[6 + 13..6 + 21].copy_from_slice(&u64::MAX.to_le_bytes());
+        let mut huge = Cursor::new(&huge[..]);
+        huge.seek(SeekFrom::Start(6)).unwrap();
+        assert!(ZStore::<Fr>::read_chunked(huge).is_err());
+
+        // unfinished stores aren't read
+        let writer = ChunkedWriter::<Fr, _>::new(Cursor::new(vec![]), 2).unwrap();
+        let unfinished = writer.out.into_inner();
+        assert!(ZStore::<Fr>::read_chunked(Cursor::new(unfinished)).is_err());
+    }
+}
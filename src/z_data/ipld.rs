@@ -0,0 +1,344 @@
+//! Encoding of Lurk expressions as IPLD blocks, in canonical DAG-CBOR.
+//!
+//! Each expression of a `ZStore` reachable from a root becomes a block: a
+//! DAG-CBOR map with the expression's tag, its Lurk hash and its fields, in
+//! which the subexpressions the `ZStore` has are CID links to their own
+//! blocks. Shared subexpressions are thus encoded once, and the blocks can be
+//! published to IPFS, for instance as the CAR file `to_car` writes, and linked
+//! to from other systems by the CID of the root.
+//!
+//! Subexpressions the `ZStore` doesn't have, and continuations, are encoded
+//! inline as maps with their tag and hash. So are commitments: publishing
+//! their secrets and payloads would open them.
+//!
+//! CIDs are version 1, with the DAG-CBOR codec and SHA-256 multihashes, and
+//! are displayed in base32, as IPFS does.
+
+use anyhow::{anyhow, Result};
+use base32ct::{Base32Unpadded, Encoding};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    field::LurkField,
+    tag::Tag,
+    z_expr::ZExpr,
+    z_ptr::{ZExprPtr, ZPtr},
+    z_store::ZStore,
+};
+
+/// The multicodec of DAG-CBOR
+const DAG_CBOR: u8 = 0x71;
+/// The multihash code of SHA-256
+const SHA2_256: u8 = 0x12;
+/// The version byte, the codec and the multihash code and length, which are
+/// all below 0x80 and so take a byte each as varints, and the digest
+const CID_LEN: usize = 4 + 32;
+/// The CBOR tag of CIDs
+const CID_TAG: u64 = 42;
+
+/// A content identifier of a DAG-CBOR block
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Cid([u8; CID_LEN]);
+
+impl Cid {
+    /// The CID of the DAG-CBOR block `data`
+    pub fn dag_cbor(data: &[u8]) -> Self {
+        let mut bytes = [0; CID_LEN];
+        bytes[..4].copy_from_slice(&[1, DAG_CBOR, SHA2_256, 32]);
+        bytes[4..].copy_from_slice(&Sha256::digest(data));
+        Self(bytes)
+    }
+
+    /// The binary form of the CID
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Displays the CID in multibase base32, as in `bafy...`
+impl fmt::Display for Cid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "b{}", Base32Unpadded::encode_string(&self.0))
+    }
+}
+
+/// A DAG-CBOR block and its CID
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Block {
+    pub cid: Cid,
+    pub data: Vec<u8>,
+}
+
+/// The subset of the DAG-CBOR data model Lurk expressions are encoded with
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Cbor {
+    Uint(u64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Cbor>),
+    Map(Vec<(&'static str, Cbor)>),
+    Link(Cid),
+}
+
+fn write_head(major: u8, n: u64, out: &mut Vec<u8>) {
+    let major = major << 5;
+    match n {
+        0..=23 => out.push(major | n as u8),
+        24..=0xff => out.extend([major | 24, n as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend((n as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend((n as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend(n.to_be_bytes());
+        }
+    }
+}
+
+impl Cbor {
+    /// Writes the canonical encoding: the shortest heads, and map keys sorted
+    /// by length first and bytes next
+    fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            Self::Uint(n) => write_head(0, *n, out),
+            Self::Bytes(bytes) => {
+                write_head(2, bytes.len() as u64, out);
+                out.extend(bytes);
+            }
+            Self::Text(text) => {
+                write_head(3, text.len() as u64, out);
+                out.extend(text.as_bytes());
+            }
+            Self::Array(items) => {
+                write_head(4, items.len() as u64, out);
+                items.iter().for_each(|item| item.write(out));
+            }
+            Self::Map(entries) => {
+                let mut entries: Vec<_> = entries.iter().collect();
+                entries.sort_by_key(|(key, _)| (key.len(), *key));
+                write_head(5, entries.len() as u64, out);
+                for (key, value) in entries {
+                    Self::Text(key.to_string()).write(out);
+                    value.write(out);
+                }
+            }
+            Self::Link(cid) => {
+                write_head(6, CID_TAG, out);
+                // the multibase prefix of the identity encoding
+                write_head(2, CID_LEN as u64 + 1, out);
+                out.push(0);
+                out.extend(cid.as_bytes());
+            }
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![];
+        self.write(&mut out);
+        out
+    }
+}
+
+fn tag<E: Tag>(tag: E) -> Cbor {
+    let tag: u16 = tag.into();
+    Cbor::Uint(tag.into())
+}
+
+fn field<F: LurkField>(f: &F) -> Cbor {
+    Cbor::Bytes(f.to_repr().as_ref().to_vec())
+}
+
+/// A pointer encoded inline, without a link
+fn inline<E: Tag, F: LurkField>(ptr: &ZPtr<E, F>) -> Cbor {
+    Cbor::Map(vec![("tag", tag(ptr.tag())), ("hash", field(ptr.value()))])
+}
+
+/// The subexpressions of `expr` that get blocks of their own
+fn children<F: LurkField>(expr: &ZExpr<F>) -> Vec<ZExprPtr<F>> {
+    match expr {
+        ZExpr::Cons(x, y) | ZExpr::Sym(x, y) | ZExpr::Key(x, y) | ZExpr::Str(x, y) => {
+            vec![*x, *y]
+        }
+        ZExpr::Fun {
+            arg,
+            body,
+            closed_env,
+        } => vec![*arg, *body, *closed_env],
+        ZExpr::Thunk(x, _) => vec![*x],
+        _ => vec![],
+    }
+}
+
+/// The block of `expr`, given the CIDs of its subexpressions in `cids`
+fn node<F: LurkField>(
+    ptr: &ZExprPtr<F>,
+    expr: &ZExpr<F>,
+    cids: &HashMap<ZExprPtr<F>, Cid>,
+) -> Cbor {
+    let link = |ptr: &ZExprPtr<F>| match cids.get(ptr) {
+        Some(cid) => Cbor::Link(*cid),
+        None => inline(ptr),
+    };
+    let mut entries = vec![("tag", tag(ptr.tag())), ("hash", field(ptr.value()))];
+    match expr {
+        ZExpr::Cons(car, cdr) => entries.extend([("car", link(car)), ("cdr", link(cdr))]),
+        ZExpr::Sym(head, tail) | ZExpr::Key(head, tail) | ZExpr::Str(head, tail) => {
+            entries.extend([("head", link(head)), ("tail", link(tail))])
+        }
+        ZExpr::Fun {
+            arg,
+            body,
+            closed_env,
+        } => entries.extend([
+            ("arg", link(arg)),
+            ("body", link(body)),
+            ("closed_env", link(closed_env)),
+        ]),
+        ZExpr::Thunk(value, cont) => {
+            entries.extend([("value", link(value)), ("cont", inline(cont))])
+        }
+        ZExpr::Num(f) => entries.push(("value", field(f))),
+        ZExpr::Char(c) => entries.push(("value", Cbor::Text(c.to_string()))),
+        ZExpr::UInt(n) => entries.push(("value", Cbor::Uint(u64::from(*n)))),
+        ZExpr::Nil | ZExpr::Comm(..) | ZExpr::RootSym | ZExpr::RootKey | ZExpr::EmptyStr => (),
+    }
+    Cbor::Map(entries)
+}
+
+/// Encodes the expressions of `z_store` reachable from `root` as DAG-CBOR
+/// blocks, returning the CID of the root's block and the blocks, each after
+/// the blocks it links to
+pub fn encode_dag<F: LurkField>(
+    z_store: &ZStore<F>,
+    root: &ZExprPtr<F>,
+) -> Result<(Cid, Vec<Block>)> {
+    if z_store.get_expr(root).is_none() {
+        return Err(anyhow!("{root} isn't in the ZStore"));
+    }
+    let mut cids = HashMap::new();
+    let mut blocks = vec![];
+    // iterative, since lists and strings are as deep as they're long
+    let mut stack = vec![*root];
+    while let Some(ptr) = stack.last().copied() {
+        if cids.contains_key(&ptr) {
+            stack.pop();
+            continue;
+        }
+        let expr = z_store
+            .get_expr(&ptr)
+            .expect("only known expressions are pushed");
+        let pending: Vec<_> = children(&expr)
+            .into_iter()
+            .filter(|child| !cids.contains_key(child) && z_store.get_expr(child).is_some())
+            .collect();
+        if !pending.is_empty() {
+            stack.extend(pending);
+            continue;
+        }
+        stack.pop();
+        let data = node(&ptr, &expr, &cids).to_bytes();
+        let cid = Cid::dag_cbor(&data);
+        cids.insert(ptr, cid);
+        blocks.push(Block { cid, data });
+    }
+    Ok((cids[root], blocks))
+}
+
+fn write_varint(mut n: u64, out: &mut Vec<u8>) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+/// A CARv1 file with `blocks` under the root `root`, which IPFS imports
+pub fn to_car(root: &Cid, blocks: &[Block]) -> Vec<u8> {
+    let header = Cbor::Map(vec![
+        ("roots", Cbor::Array(vec![Cbor::Link(*root)])),
+        ("version", Cbor::Uint(1)),
+    ]);
+    let header_bytes = header.to_bytes();
+    let mut out = vec![];
+    write_varint(header_bytes.len() as u64, &mut out);
+    out.extend(header_bytes);
+    for block in blocks {
+        write_varint((CID_LEN + block.data.len()) as u64, &mut out);
+        out.extend(block.cid.as_bytes());
+        out.extend(&block.data);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::Store;
+    use pasta_curves::pallas::Scalar as Fr;
+
+    #[test]
+    fn cbor_is_canonical() {
+        let empty = Cbor::Map(vec![]).to_bytes();
+        assert_eq!(empty, [0xa0]);
+        assert_eq!(
+            Cid::dag_cbor(&empty).to_string(),
+            "bafyreigbtj4x7ip5legnfznufuopl4sg4knzc2cof6duas4b3q2fy6swua"
+        );
+        let map = Cbor::Map(vec![
+            ("b", Cbor::Uint(1)),
+            ("aa", Cbor::Uint(500)),
+            ("a", Cbor::Text("x".into())),
+        ]);
+        let bytes = [
+            0xa3, 0x61, b'a', 0x61, b'x', 0x61, b'b', 0x01, 0x62, b'a', b'a', 0x19, 0x01, 0xf4,
+        ];
+        assert_eq!(map.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn expressions_are_encoded_as_dags() {
+        let store = &mut Store::<Fr>::default();
+        let expr = store.read("(\"ab\" \"ab\" 1)").unwrap();
+        let secret = store.read("(secret data)").unwrap();
+        let comm = store.hide(Fr::from(7), secret);
+        let (z_store, z_ptrs) = store.export_reachable(&[expr, comm]).unwrap();
+
+        let (root, blocks) = encode_dag(&z_store, &z_ptrs[0]).unwrap();
+        assert_eq!(blocks.last().unwrap().cid, root);
+        assert!(blocks
+            .iter()
+            .all(|block| block.cid == Cid::dag_cbor(&block.data)));
+        let mut cids: Vec<_> = blocks.iter().map(|block| block.cid).collect();
+        cids.sort_by_key(|cid| cid.0);
+        cids.dedup();
+        // the shared string is encoded once
+        assert_eq!(cids.len(), blocks.len());
+        assert_eq!(
+            encode_dag(&z_store, &z_ptrs[0]).unwrap(),
+            (root, blocks.clone())
+        );
+
+        // the commitment alone is published
+        let (comm_root, comm_blocks) = encode_dag(&z_store, &z_ptrs[1]).unwrap();
+        assert_eq!(comm_blocks.len(), 1);
+        assert_ne!(comm_root, root);
+
+        let car = to_car(&root, &blocks);
+        let header_len = car[0] as usize;
+        let header = Cbor::Map(vec![
+            ("version", Cbor::Uint(1)),
+            ("roots", Cbor::Array(vec![Cbor::Link(root)])),
+        ]);
+        assert_eq!(car[1..=header_len], header.to_bytes());
+        assert!(car.ends_with(&blocks.last().unwrap().data));
+
+        let missing = ZExprPtr::from_parts(crate::tag::ExprTag::Cons, Fr::from(42));
+        assert!(encode_dag(&z_store, &missing).is_err());
+    }
+}
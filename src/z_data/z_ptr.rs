@@ -1,10 +1,11 @@
-use anyhow::anyhow;
+use anyhow::{anyhow, bail};
 use base32ct::{Base32Unpadded, Encoding};
 #[cfg(not(target_arch = "wasm32"))]
 use lurk_macros::serde_test;
 #[cfg(not(target_arch = "wasm32"))]
 use proptest_derive::Arbitrary;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::hash::Hash;
@@ -19,6 +20,16 @@ use crate::hash::IntoHashComponents;
 use crate::store::{self, Store};
 use crate::tag::{ContTag, ExprTag, Tag};
 
+/// The alphabet of base58btc, the one of Bitcoin addresses, which leaves out
+/// the characters that are easily mistaken for one another
+const BASE58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+/// The multibase prefix of base58btc
+const BASE58_PREFIX: char = 'z';
+/// The version of the base58 encoding. Leading the encoded bytes, it also
+/// keeps them from starting with zeros.
+const BASE58_VERSION: u8 = 1;
+const BASE58_CHECKSUM_LEN: usize = 4;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[cfg_attr(not(target_arch = "wasm32"), derive(Arbitrary))]
 #[cfg_attr(
@@ -44,11 +55,11 @@ pub struct ZPtr<E: Tag, F: LurkField>(
     pub F,
 );
 
+/// Displays the ZPtr as `ZPtr::to_base58` encodes it, which `ZPtr::from_base58`
+/// reads back
 impl<E: Tag + Display, F: LurkField> Display for ZPtr<E, F> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        let tag = self.0;
-        let trimmed_f = self.1.trimmed_hex_digits();
-        write!(f, "(ptr->{tag}, {trimmed_f})",)
+        write!(f, "{}", self.to_base58())
     }
 }
 
@@ -124,6 +135,49 @@ impl<E: Tag, F: LurkField> ZPtr<E, F> {
         let val = F::from_bytes(&val_bytes).ok_or_else(|| anyhow!("Failed to decode field"))?;
         Ok(Self::from_parts(tag, val))
     }
+
+    /// Converts the ZPtr to multibase base58btc: a `z` followed by the base58
+    /// encoding of a version byte, the tag, the value and a checksum, the first
+    /// bytes of the SHA-256 of the rest. Mistyped or truncated strings thus
+    /// fail to decode, instead of decoding to another ZPtr.
+    pub fn to_base58(&self) -> String {
+        let mut bytes = vec![BASE58_VERSION];
+        bytes.extend(self.0.into().to_le_bytes());
+        bytes.extend(self.1.to_repr().as_ref());
+        let checksum = Sha256::digest(&bytes);
+        bytes.extend(&checksum[..BASE58_CHECKSUM_LEN]);
+        format!("{BASE58_PREFIX}{}", base_x::encode(BASE58_ALPHABET, &bytes))
+    }
+
+    /// Converts a string encoded by `to_base58` to a ZPtr, checking its
+    /// checksum
+    pub fn from_base58(zptr: &str) -> Result<Self, anyhow::Error> {
+        let encoded = zptr
+            .strip_prefix(BASE58_PREFIX)
+            .ok_or_else(|| anyhow!("Missing the base58 prefix {BASE58_PREFIX}"))?;
+        let bytes = base_x::decode(BASE58_ALPHABET, encoded)
+            .map_err(|_| anyhow!("Failed to decode base58"))?;
+        if bytes.len() < BASE58_CHECKSUM_LEN {
+            bail!("Failed to decode base58: too short");
+        }
+        let (payload, checksum) = bytes.split_at(bytes.len() - BASE58_CHECKSUM_LEN);
+        if Sha256::digest(payload)[..BASE58_CHECKSUM_LEN] != *checksum {
+            bail!("Wrong base58 checksum: the string was mistyped or truncated");
+        }
+        let [version, tag_lo, tag_hi, val_bytes @ ..] = payload else {
+            bail!("Failed to decode base58: too short");
+        };
+        if *version != BASE58_VERSION {
+            bail!("Unknown base58 version {version}");
+        }
+        let tag = E::try_from(u16::from_le_bytes([*tag_lo, *tag_hi]))
+            .map_err(|_| anyhow!("Failed to decode tag"))?;
+        if val_bytes.len() != F::ZERO.to_repr().as_ref().len() {
+            bail!("Failed to decode field: wrong length");
+        }
+        let val = F::from_bytes(val_bytes).ok_or_else(|| anyhow!("Failed to decode field"))?;
+        Ok(Self::from_parts(tag, val))
+    }
 }
 
 /// Alias for an expression pointer
@@ -169,4 +223,31 @@ mod tests {
         let zptr = ZExprPtr::from_parts(ExprTag::Nil, Scalar::zero());
         assert_eq!(zptr, ZPtr::from_base32(&zptr.to_base32()).unwrap());
     }
+
+    proptest! {
+        #[test]
+        fn prop_base58_z_ptr(x in any::<ZExprPtr<Scalar>>(), k in any::<ZContPtr<Scalar>>()) {
+            assert_eq!(x, ZPtr::from_base58(&x.to_base58()).unwrap());
+            assert_eq!(k, ZPtr::from_base58(&k.to_string()).unwrap());
+        }
+    }
+
+    #[test]
+    fn base58_typos_are_detected() {
+        let zptr = ZExprPtr::from_parts(ExprTag::Nil, Scalar::zero());
+        let encoded = zptr.to_string();
+        assert!(encoded.starts_with('z'));
+        assert_eq!(zptr, ZPtr::from_base58(&encoded).unwrap());
+
+        let mut typo = encoded.clone().into_bytes();
+        typo[10] = if typo[10] == b'2' { b'3' } else { b'2' };
+        assert!(ZExprPtr::<Scalar>::from_base58(std::str::from_utf8(&typo).unwrap()).is_err());
+        let truncated = &encoded[..encoded.len() - 1];
+        assert!(ZExprPtr::<Scalar>::from_base58(truncated).is_err());
+        assert!(ZExprPtr::<Scalar>::from_base58(&encoded[1..]).is_err());
+        assert!(ZExprPtr::<Scalar>::from_base58("z0OIl").is_err());
+        // a continuation isn't an expression
+        let cont = ZContPtr::from_parts(ContTag::Outermost, Scalar::zero());
+        assert!(ZExprPtr::<Scalar>::from_base58(&cont.to_base58()).is_err());
+    }
 }
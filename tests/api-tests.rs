@@ -0,0 +1,183 @@
+//! Compile tests for `lurk::api`. Changing the signatures pinned here breaks
+//! downstream users, which the facade promises not to do within a release.
+
+use lurk::api::{
+    self, BatchRoot, CancellationToken, Claim, Coproc, EvaluateAsync, Evaluation, ExprTag,
+    InclusionProof, Lang, NovaProver, OutputPredicate, Proof, ProofError, ProveOptions, Ptr,
+    PublicParams, ReductionError, Secret, Store,
+};
+use pasta_curves::pallas::Scalar as S1;
+use rand::rngs::OsRng;
+use std::{
+    future::Future,
+    pin::pin,
+    sync::Arc,
+    task::{Context, Poll, Wake, Waker},
+};
+
+type Lng = Lang<S1, Coproc<S1>>;
+
+#[test]
+fn evaluation_and_commitment_signatures() {
+    let _: fn(&mut Store<S1>, Ptr<S1>, usize, &Lng) -> Result<Evaluation<S1>, ReductionError> =
+        api::evaluate;
+    let _: fn(&mut Store<S1>, Ptr<S1>, Option<&Secret<S1>>) -> Ptr<S1> = api::commit;
+    let _: fn(&Store<S1>, Ptr<S1>) -> Option<(Secret<S1>, Ptr<S1>)> = api::open;
+    let _: fn(
+        &mut Store<S1>,
+        &[Ptr<S1>],
+        &[Secret<S1>],
+    ) -> (Vec<Ptr<S1>>, BatchRoot<S1>, Vec<InclusionProof<S1>>) = api::commit_batch;
+}
+
+#[allow(dead_code)]
+fn async_evaluation_signature<'a>(
+    store: &'a mut Store<S1>,
+    expr: Ptr<S1>,
+    lang: &'a Lng,
+) -> impl Future<Output = Result<Evaluation<S1>, ReductionError>> + 'a {
+    let evaluation: EvaluateAsync<'a, S1, Coproc<S1>> =
+        api::evaluate_async(store, expr, 100, lang, 10, CancellationToken::new());
+    evaluation
+}
+
+/// Never called, since proving is slow. It's enough for it to compile.
+#[allow(dead_code)]
+fn proving_signatures(
+    prover: &NovaProver<S1, Coproc<S1>>,
+    pp: &PublicParams<'_, S1, Coproc<S1>>,
+    store: &mut Store<S1>,
+    expr: Ptr<S1>,
+    lang: Arc<Lng>,
+) -> Result<bool, ProofError> {
+    let (proof, claim): (Proof<'_, S1, Coproc<S1>>, Claim<S1>) =
+        api::prove(prover, pp, store, expr, 100, lang)?;
+    let Claim {
+        input: _,
+        output: _,
+        num_steps: _,
+        output_predicate: _,
+    } = &claim;
+    api::verify(&proof, pp, &claim)
+}
+
+/// Never called either
+#[allow(dead_code)]
+fn proving_with_options_signature(
+    prover: &NovaProver<S1, Coproc<S1>>,
+    pp: &PublicParams<'_, S1, Coproc<S1>>,
+    store: &mut Store<S1>,
+    expr: Ptr<S1>,
+    lang: Arc<Lng>,
+) -> Result<bool, ProofError> {
+    let options = ProveOptions {
+        output_predicate: Some(OutputPredicate::Returns(ExprTag::Num)),
+    };
+    let (proof, claim) = api::prove_with(prover, pp, store, expr, 100, lang, options)?;
+    api::verify(&proof, pp, &claim)
+}
+
+#[test]
+fn output_predicates_check_the_public_output() {
+    let store = &mut Store::<S1>::default();
+    let lang = Lng::new();
+    let expr = store.read("(+ 1 2)").unwrap();
+    let Evaluation { output, .. } = api::evaluate(store, expr, 100, &lang).unwrap();
+
+    // the public output of a proof of the evaluation
+    let mut public_output = vec![];
+    for z_ptr in [store.hash_expr(&output.expr), store.hash_expr(&output.env)] {
+        let (tag, hash) = z_ptr.unwrap().parts();
+        public_output.extend([tag, hash]);
+    }
+    let (tag, hash) = store.hash_cont(&output.cont).unwrap().parts();
+    public_output.extend([tag, hash]);
+
+    assert!(OutputPredicate::Terminal.holds(&public_output));
+    assert!(OutputPredicate::Returns(ExprTag::Num).holds(&public_output));
+    assert!(!OutputPredicate::Returns(ExprTag::Str).holds(&public_output));
+    assert!(!OutputPredicate::Terminal.holds(&public_output[..4]));
+}
+
+#[test]
+fn facade_evaluates_and_commits() {
+    let store = &mut Store::<S1>::default();
+    let lang = Lng::new();
+
+    let expr = store.read("(+ 1 2)").unwrap();
+    let Evaluation {
+        output,
+        iterations,
+        emitted,
+    } = api::evaluate(store, expr, 100, &lang).unwrap();
+    assert_eq!(output.expr, store.num(3));
+    assert_eq!(iterations, 3);
+    assert!(emitted.is_empty());
+
+    let payload = store.read("(1 . 2)").unwrap();
+    let secret = Secret::new(S1::from(42));
+    let comm = api::commit(store, payload, Some(&secret));
+    assert_eq!(api::open(store, comm), Some((secret, payload)));
+    assert_eq!(api::open(store, payload), None);
+}
+
+#[test]
+fn batches_of_commitments_are_proven_against_their_root() {
+    let store = &mut Store::<S1>::default();
+    let payloads: Vec<_> = ["1", "(1 . 2)", "\"abc\""]
+        .into_iter()
+        .map(|input| store.read(input).unwrap())
+        .collect();
+    let secrets: Vec<_> = (0..3).map(|_| Secret::random(OsRng)).collect();
+    let (comms, root, proofs) = api::commit_batch(store, &payloads, &secrets);
+    assert_eq!(root.len, 3);
+    let openings = comms.iter().zip(&payloads).zip(&secrets).zip(&proofs);
+    for (((comm, payload), secret), proof) in openings {
+        assert_eq!(api::open(store, *comm), Some((secret.clone(), *payload)));
+        let hash = *store.hash_expr(comm).unwrap().value();
+        assert!(root.verify(&store.poseidon_cache, hash, proof));
+    }
+    let other = *store.hash_expr(&payloads[0]).unwrap().value();
+    assert!(!root.verify(&store.poseidon_cache, other, &proofs[0]));
+}
+
+struct NoopWaker;
+
+impl Wake for NoopWaker {
+    fn wake(self: Arc<Self>) {}
+}
+
+/// Polls `future` until it's ready, returning how many times it was pending
+fn poll_to_completion<T>(future: impl Future<Output = T>) -> (T, usize) {
+    let waker = Waker::from(Arc::new(NoopWaker));
+    let cx = &mut Context::from_waker(&waker);
+    let mut future = pin!(future);
+    let mut pending = 0;
+    loop {
+        match future.as_mut().poll(cx) {
+            Poll::Ready(output) => return (output, pending),
+            Poll::Pending => pending += 1,
+        }
+    }
+}
+
+#[test]
+fn async_evaluation_yields_and_cancels() {
+    let store = &mut Store::<S1>::default();
+    let lang = Lng::new();
+    let expr = store.read("(+ 1 2)").unwrap();
+
+    let future = api::evaluate_async(store, expr, 100, &lang, 1, CancellationToken::new());
+    let (evaluation, pending) = poll_to_completion(future);
+    let evaluation = evaluation.unwrap();
+    assert_eq!(evaluation.output.expr, store.num(3));
+    assert_eq!(evaluation.iterations, 3);
+    // one step per poll
+    assert_eq!(pending, 3);
+
+    let cancellation = CancellationToken::new();
+    let future = api::evaluate_async(store, expr, 100, &lang, 1, cancellation.clone());
+    cancellation.cancel();
+    let (evaluation, _) = poll_to_completion(future);
+    assert!(matches!(evaluation, Err(ReductionError::Cancelled(0))));
+}
@@ -0,0 +1,181 @@
+//! Golden-file tests for the shape of the eval circuit.
+//!
+//! The blank circuit is synthesized into a constraint system that records, for
+//! every namespace in the order it is first used, how many variables it
+//! allocates and how many constraints it enforces. A digest of that sequence
+//! is compared to the files in `tests/golden`, so a change in the number of
+//! constraints, or in the order things are allocated in, fails these tests
+//! until the golden files are regenerated on purpose with
+//!
+//! ```text
+//! UPDATE_GOLDENS=1 cargo test --test circuit-golden-tests
+//! ```
+//!
+//! and committed along with the change. A missing golden file fails the tests
+//! too, rather than being written, so that they can't pass without checking
+//! anything.
+
+use bellpepper_core::{
+    Circuit, ConstraintSystem, Index, LinearCombination, SynthesisError, Variable,
+};
+use indexmap::IndexMap;
+use lurk::circuit::circuit_frame::MultiFrame;
+use lurk::eval::lang::{Coproc, Lang};
+use pasta_curves::pallas::Scalar as S1;
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[derive(Default)]
+struct NamespaceCounts {
+    inputs: usize,
+    aux: usize,
+    constraints: usize,
+}
+
+/// A constraint system that only records where variables and constraints are
+/// created
+struct RecordingCS {
+    path: Vec<String>,
+    namespaces: IndexMap<String, NamespaceCounts>,
+    inputs: usize,
+    aux: usize,
+    constraints: usize,
+}
+
+impl RecordingCS {
+    fn new() -> Self {
+        Self {
+            path: vec![],
+            namespaces: IndexMap::new(),
+            // the constant one
+            inputs: 1,
+            aux: 0,
+            constraints: 0,
+        }
+    }
+
+    fn current(&mut self) -> &mut NamespaceCounts {
+        self.namespaces.entry(self.path.join("/")).or_default()
+    }
+
+    fn summary(&self) -> String {
+        let mut hasher = Sha256::new();
+        for (path, counts) in &self.namespaces {
+            hasher.update(format!(
+                "{path}\t{}\t{}\t{}\n",
+                counts.inputs, counts.aux, counts.constraints
+            ));
+        }
+        format!(
+            "inputs {}\naux {}\nconstraints {}\nnamespaces {}\ndigest {}\n",
+            self.inputs,
+            self.aux,
+            self.constraints,
+            self.namespaces.len(),
+            hex::encode(hasher.finalize())
+        )
+    }
+}
+
+impl ConstraintSystem<S1> for RecordingCS {
+    type Root = Self;
+
+    fn alloc<F, A, AR>(&mut self, _annotation: A, _f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<S1, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.current().aux += 1;
+        self.aux += 1;
+        Ok(Variable::new_unchecked(Index::Aux(self.aux - 1)))
+    }
+
+    fn alloc_input<F, A, AR>(&mut self, _annotation: A, _f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<S1, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.current().inputs += 1;
+        self.inputs += 1;
+        Ok(Variable::new_unchecked(Index::Input(self.inputs - 1)))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, _annotation: A, _a: LA, _b: LB, _c: LC)
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+        LA: FnOnce(LinearCombination<S1>) -> LinearCombination<S1>,
+        LB: FnOnce(LinearCombination<S1>) -> LinearCombination<S1>,
+        LC: FnOnce(LinearCombination<S1>) -> LinearCombination<S1>,
+    {
+        self.current().constraints += 1;
+        self.constraints += 1;
+    }
+
+    fn push_namespace<NR, N>(&mut self, name_fn: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+        self.path.push(name_fn().into());
+    }
+
+    fn pop_namespace(&mut self) {
+        self.path.pop();
+    }
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+fn check_golden(name: &str, actual: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.txt"));
+    if std::env::var("UPDATE_GOLDENS").map_or(false, |v| v == "1") {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, actual).unwrap();
+        eprintln!("wrote {}", path.display());
+        return;
+    }
+    let expected = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+        panic!(
+            "can't read {}: {e}; generate it with UPDATE_GOLDENS=1",
+            path.display()
+        )
+    });
+    assert_eq!(
+        expected,
+        actual,
+        "the shape of the circuit changed; if that's intended, regenerate {} with \
+         UPDATE_GOLDENS=1",
+        path.display()
+    );
+}
+
+fn eval_circuit_summary(rc: usize) -> String {
+    let lang = Arc::new(Lang::<S1, Coproc<S1>>::new());
+    let mut cs = RecordingCS::new();
+    MultiFrame::blank(rc, lang).synthesize(&mut cs).unwrap();
+    cs.summary()
+}
+
+#[test]
+fn golden_eval_circuit_rc_1() {
+    check_golden("eval-circuit-rc-1", &eval_circuit_summary(1));
+}
+
+#[test]
+fn golden_eval_circuit_rc_10() {
+    check_golden("eval-circuit-rc-10", &eval_circuit_summary(10));
+}
+
+#[test]
+fn synthesis_order_is_stable() {
+    // the digest is only meaningful if synthesis is deterministic
+    assert_eq!(eval_circuit_summary(2), eval_circuit_summary(2));
+}
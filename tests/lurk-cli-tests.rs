@@ -81,9 +81,9 @@ fn test_config_file() {
 
     let config = lurk::cli::get_config(&Some(config_dir)).unwrap();
 
-    assert_eq!(config.get("public_params").unwrap(), &public_params_dir);
-    assert_eq!(config.get("proofs").unwrap(), &proofs_dir_env);
-    assert_eq!(config.get("commits").unwrap(), &commits_dir);
+    assert_eq!(config.public_params, Some(public_params_dir.into()));
+    assert_eq!(config.proofs, Some(proofs_dir_env.into()));
+    assert_eq!(config.commits, Some(commits_dir.into()));
 }
 
 // TODO: Use a snapshot test for the proof ID and/or test the REPL process